@@ -0,0 +1,1406 @@
+// Initially generated by `wit-bindgen` 0.16.0 from ../wasm-rpc/wit, and maintained by hand since. Regenerating it
+// loses the hand edits, which have to be reapplied:
+// - the types of the interface are replaced by the ones of golem-wasm-rpc, so the guests and the hosts share them
+// - the `prim-bytes` node (discriminant 22) is lowered from and lifted to `WitNode::PrimBytes`
+// - the embedded component type follows the changes of the WIT package, which the tests check against ../wasm-rpc/wit
+pub mod golem {
+  pub mod rpc {
+    
+    #[allow(clippy::all)]
+    pub mod types {
+      #[used]
+      #[doc(hidden)]
+      #[cfg(target_arch = "wasm32")]
+      static __FORCE_SECTION_REF: fn() = super::super::super::__link_section;
+      pub use golem_wasm_rpc::{NodeIndex, RpcError, Uri, WitNode, WitValue};
+      
+      #[derive(Debug)]
+      #[repr(transparent)]
+      pub struct WasmRpc{
+        handle: wit_bindgen::rt::Resource<WasmRpc>,
+      }
+      
+      impl WasmRpc{
+        #[doc(hidden)]
+        pub unsafe fn from_handle(handle: u32) -> Self {
+          Self {
+            handle: wit_bindgen::rt::Resource::from_handle(handle),
+          }
+        }
+        
+        #[doc(hidden)]
+        pub fn into_handle(self) -> u32 {
+          wit_bindgen::rt::Resource::into_handle(self.handle)
+        }
+        
+        #[doc(hidden)]
+        pub fn handle(&self) -> u32 {
+          wit_bindgen::rt::Resource::handle(&self.handle)
+        }
+      }
+      
+      
+      unsafe impl wit_bindgen::rt::WasmResource for WasmRpc{
+        #[inline]
+        unsafe fn drop(_handle: u32) {
+          #[cfg(not(target_arch = "wasm32"))]
+          unreachable!();
+          
+          #[cfg(target_arch = "wasm32")]
+          {
+            #[link(wasm_import_module = "golem:rpc/types@0.2.0")]
+            extern "C" {
+              #[link_name = "[resource-drop]wasm-rpc"]
+              fn drop(_: u32);
+            }
+            
+            drop(_handle);
+          }
+        }
+      }
+      
+      impl WasmRpc {
+        #[allow(unused_unsafe, clippy::all)]
+        pub fn new(location: &Uri,) -> Self{
+          
+          #[allow(unused_imports)]
+          use wit_bindgen::rt::{alloc, vec::Vec, string::String};
+          unsafe {
+            let Uri{ value:value0, } = location;
+            let vec1 = value0;
+            let ptr1 = vec1.as_ptr() as i32;
+            let len1 = vec1.len() as i32;
+            
+            #[cfg(target_arch = "wasm32")]
+            #[link(wasm_import_module = "golem:rpc/types@0.2.0")]
+            extern "C" {
+              #[link_name = "[constructor]wasm-rpc"]
+              fn wit_import(_: i32, _: i32, ) -> i32;
+            }
+            
+            #[cfg(not(target_arch = "wasm32"))]
+            fn wit_import(_: i32, _: i32, ) -> i32{ unreachable!() }
+            let ret = wit_import(ptr1, len1);
+            WasmRpc::from_handle(ret as u32)
+          }
+        }
+      }
+      impl WasmRpc {
+        #[allow(unused_unsafe, clippy::all)]
+        pub fn invoke_and_await(&self,function_name: &str,function_params: &[WitValue],) -> Result<WitValue,RpcError>{
+          
+          #[allow(unused_imports)]
+          use wit_bindgen::rt::{alloc, vec::Vec, string::String};
+          unsafe {
+            let mut cleanup_list = Vec::new();
+            
+            #[repr(align(4))]
+            struct RetArea([u8; 16]);
+            let mut ret_area = ::core::mem::MaybeUninit::<RetArea>::uninit();
+            let vec0 = function_name;
+            let ptr0 = vec0.as_ptr() as i32;
+            let len0 = vec0.len() as i32;
+            let vec12 = function_params;
+            let len12 = vec12.len() as i32;
+            let layout12 = alloc::Layout::from_size_align_unchecked(vec12.len() * 8, 4);
+            let result12 = if layout12.size() != 0
+            {
+              let ptr = alloc::alloc(layout12);
+              if ptr.is_null()
+              {
+                alloc::handle_alloc_error(layout12);
+              }
+              ptr
+            }else {{
+              ::core::ptr::null_mut()
+            }};
+            for (i, e) in vec12.into_iter().enumerate() {
+              let base = result12 as i32 + (i as i32) * 8;
+              {
+                let WitValue{ nodes:nodes1, } = e;
+                let vec11 = nodes1;
+                let len11 = vec11.len() as i32;
+                let layout11 = alloc::Layout::from_size_align_unchecked(vec11.len() * 24, 8);
+                let result11 = if layout11.size() != 0
+                {
+                  let ptr = alloc::alloc(layout11);
+                  if ptr.is_null()
+                  {
+                    alloc::handle_alloc_error(layout11);
+                  }
+                  ptr
+                }else {{
+                  ::core::ptr::null_mut()
+                }};
+                for (i, e) in vec11.into_iter().enumerate() {
+                  let base = result11 as i32 + (i as i32) * 24;
+                  {
+                    match e {
+                      WitNode::RecordValue(e) => {
+                        *((base + 0) as *mut u8) = (0i32) as u8;
+                        let vec2 = e;
+                        let ptr2 = vec2.as_ptr() as i32;
+                        let len2 = vec2.len() as i32;
+                        *((base + 12) as *mut i32) = len2;
+                        *((base + 8) as *mut i32) = ptr2;
+                      },
+                      WitNode::VariantValue(e) => {
+                        *((base + 0) as *mut u8) = (1i32) as u8;
+                        let (t3_0, t3_1, ) = e;
+                        *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(t3_0);
+                        match t3_1 {
+                          Some(e) => {
+                            *((base + 12) as *mut u8) = (1i32) as u8;
+                            *((base + 16) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                          },
+                          None => {
+                            {
+                              *((base + 12) as *mut u8) = (0i32) as u8;
+                            }
+                          },
+                        };},
+                        WitNode::EnumValue(e) => {
+                          *((base + 0) as *mut u8) = (2i32) as u8;
+                          *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                        },
+                        WitNode::FlagsValue(e) => {
+                          *((base + 0) as *mut u8) = (3i32) as u8;
+                          let vec4 = e;
+                          let len4 = vec4.len() as i32;
+                          let layout4 = alloc::Layout::from_size_align_unchecked(vec4.len() * 1, 1);
+                          let result4 = if layout4.size() != 0
+                          {
+                            let ptr = alloc::alloc(layout4);
+                            if ptr.is_null()
+                            {
+                              alloc::handle_alloc_error(layout4);
+                            }
+                            ptr
+                          }else {{
+                            ::core::ptr::null_mut()
+                          }};
+                          for (i, e) in vec4.into_iter().enumerate() {
+                            let base = result4 as i32 + (i as i32) * 1;
+                            {
+                              *((base + 0) as *mut u8) = (match e { true => 1, false => 0 }) as u8;
+                            }
+                          }
+                          *((base + 12) as *mut i32) = len4;
+                          *((base + 8) as *mut i32) = result4 as i32;
+                          cleanup_list.extend_from_slice(&[(result4, layout4),]);
+                        },
+                        WitNode::TupleValue(e) => {
+                          *((base + 0) as *mut u8) = (4i32) as u8;
+                          let vec5 = e;
+                          let ptr5 = vec5.as_ptr() as i32;
+                          let len5 = vec5.len() as i32;
+                          *((base + 12) as *mut i32) = len5;
+                          *((base + 8) as *mut i32) = ptr5;
+                        },
+                        WitNode::ListValue(e) => {
+                          *((base + 0) as *mut u8) = (5i32) as u8;
+                          let vec6 = e;
+                          let ptr6 = vec6.as_ptr() as i32;
+                          let len6 = vec6.len() as i32;
+                          *((base + 12) as *mut i32) = len6;
+                          *((base + 8) as *mut i32) = ptr6;
+                        },
+                        WitNode::OptionValue(e) => {
+                          *((base + 0) as *mut u8) = (6i32) as u8;
+                          match e {
+                            Some(e) => {
+                              *((base + 8) as *mut u8) = (1i32) as u8;
+                              *((base + 12) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                            },
+                            None => {
+                              {
+                                *((base + 8) as *mut u8) = (0i32) as u8;
+                              }
+                            },
+                          };},
+                          WitNode::ResultValue(e) => {
+                            *((base + 0) as *mut u8) = (7i32) as u8;
+                            match e {
+                              Ok(e) => { {
+                                *((base + 8) as *mut u8) = (0i32) as u8;
+                                match e {
+                                  Some(e) => {
+                                    *((base + 12) as *mut u8) = (1i32) as u8;
+                                    *((base + 16) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                  },
+                                  None => {
+                                    {
+                                      *((base + 12) as *mut u8) = (0i32) as u8;
+                                    }
+                                  },
+                                };} },
+                                Err(e) => { {
+                                  *((base + 8) as *mut u8) = (1i32) as u8;
+                                  match e {
+                                    Some(e) => {
+                                      *((base + 12) as *mut u8) = (1i32) as u8;
+                                      *((base + 16) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                    },
+                                    None => {
+                                      {
+                                        *((base + 12) as *mut u8) = (0i32) as u8;
+                                      }
+                                    },
+                                  };} },
+                                };},
+                                WitNode::PrimU8(e) => {
+                                  *((base + 0) as *mut u8) = (8i32) as u8;
+                                  *((base + 8) as *mut u8) = (wit_bindgen::rt::as_i32(e)) as u8;
+                                },
+                                WitNode::PrimU16(e) => {
+                                  *((base + 0) as *mut u8) = (9i32) as u8;
+                                  *((base + 8) as *mut u16) = (wit_bindgen::rt::as_i32(e)) as u16;
+                                },
+                                WitNode::PrimU32(e) => {
+                                  *((base + 0) as *mut u8) = (10i32) as u8;
+                                  *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                },
+                                WitNode::PrimU64(e) => {
+                                  *((base + 0) as *mut u8) = (11i32) as u8;
+                                  *((base + 8) as *mut i64) = wit_bindgen::rt::as_i64(e);
+                                },
+                                WitNode::PrimS8(e) => {
+                                  *((base + 0) as *mut u8) = (12i32) as u8;
+                                  *((base + 8) as *mut u8) = (wit_bindgen::rt::as_i32(e)) as u8;
+                                },
+                                WitNode::PrimS16(e) => {
+                                  *((base + 0) as *mut u8) = (13i32) as u8;
+                                  *((base + 8) as *mut u16) = (wit_bindgen::rt::as_i32(e)) as u16;
+                                },
+                                WitNode::PrimS32(e) => {
+                                  *((base + 0) as *mut u8) = (14i32) as u8;
+                                  *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                },
+                                WitNode::PrimS64(e) => {
+                                  *((base + 0) as *mut u8) = (15i32) as u8;
+                                  *((base + 8) as *mut i64) = wit_bindgen::rt::as_i64(e);
+                                },
+                                WitNode::PrimFloat32(e) => {
+                                  *((base + 0) as *mut u8) = (16i32) as u8;
+                                  *((base + 8) as *mut f32) = wit_bindgen::rt::as_f32(e);
+                                },
+                                WitNode::PrimFloat64(e) => {
+                                  *((base + 0) as *mut u8) = (17i32) as u8;
+                                  *((base + 8) as *mut f64) = wit_bindgen::rt::as_f64(e);
+                                },
+                                WitNode::PrimChar(e) => {
+                                  *((base + 0) as *mut u8) = (18i32) as u8;
+                                  *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                },
+                                WitNode::PrimBool(e) => {
+                                  *((base + 0) as *mut u8) = (19i32) as u8;
+                                  *((base + 8) as *mut u8) = (match e { true => 1, false => 0 }) as u8;
+                                },
+                                WitNode::PrimString(e) => {
+                                  *((base + 0) as *mut u8) = (20i32) as u8;
+                                  let vec7 = e;
+                                  let ptr7 = vec7.as_ptr() as i32;
+                                  let len7 = vec7.len() as i32;
+                                  *((base + 12) as *mut i32) = len7;
+                                  *((base + 8) as *mut i32) = ptr7;
+                                },
+                                WitNode::Handle(e) => {
+                                  *((base + 0) as *mut u8) = (21i32) as u8;
+                                  let (t8_0, t8_1, ) = e;
+                                  let Uri{ value:value9, } = t8_0;
+                                  let vec10 = value9;
+                                  let ptr10 = vec10.as_ptr() as i32;
+                                  let len10 = vec10.len() as i32;
+                                  *((base + 12) as *mut i32) = len10;
+                                  *((base + 8) as *mut i32) = ptr10;
+                                  *((base + 16) as *mut i64) = wit_bindgen::rt::as_i64(t8_1);
+                                },
+                                WitNode::PrimBytes(e) => {
+                                  *((base + 0) as *mut u8) = (22i32) as u8;
+                                  let vec93 = e;
+                                  let ptr93 = vec93.as_ptr() as i32;
+                                  let len93 = vec93.len() as i32;
+                                  *((base + 12) as *mut i32) = len93;
+                                  *((base + 8) as *mut i32) = ptr93;
+                                },
+                              }
+                            }
+                          }
+                          *((base + 4) as *mut i32) = len11;
+                          *((base + 0) as *mut i32) = result11 as i32;
+                          cleanup_list.extend_from_slice(&[(result11, layout11),]);
+                        }
+                      }
+                      let ptr13 = ret_area.as_mut_ptr() as i32;
+                      #[cfg(target_arch = "wasm32")]
+                      #[link(wasm_import_module = "golem:rpc/types@0.2.0")]
+                      extern "C" {
+                        #[link_name = "[method]wasm-rpc.invoke-and-await"]
+                        fn wit_import(_: i32, _: i32, _: i32, _: i32, _: i32, _: i32, );
+                      }
+                      
+                      #[cfg(not(target_arch = "wasm32"))]
+                      fn wit_import(_: i32, _: i32, _: i32, _: i32, _: i32, _: i32, ){ unreachable!() }
+                      wit_import((self).handle() as i32, ptr0, len0, result12 as i32, len12, ptr13);
+                      let l14 = i32::from(*((ptr13 + 0) as *const u8));
+                      if layout12.size() != 0 {
+                        alloc::dealloc(result12, layout12);
+                      }
+                      for (ptr, layout) in cleanup_list {
+                        
+                        if layout.size() != 0 {
+                          
+                          alloc::dealloc(ptr, layout);
+                          
+                        }
+                        
+                      }
+                      match l14 {
+                        0 => {
+                          let e = {
+                            let l15 = *((ptr13 + 4) as *const i32);
+                            let l16 = *((ptr13 + 8) as *const i32);
+                            let base62 = l15;
+                            let len62 = l16;
+                            let mut result62 = Vec::with_capacity(len62 as usize);
+                            for i in 0..len62 {
+                              let base = base62 + i * 24;
+                              let e62 = {
+                                let l17 = i32::from(*((base + 0) as *const u8));
+                                let v61 = match l17 {
+                                  0 => {
+                                    let e61 = {
+                                      let l18 = *((base + 8) as *const i32);
+                                      let l19 = *((base + 12) as *const i32);
+                                      let len20 = l19 as usize;
+                                      
+                                      Vec::from_raw_parts(l18 as *mut _, len20, len20)
+                                    };
+                                    WitNode::RecordValue(e61)
+                                  }
+                                  1 => {
+                                    let e61 = {
+                                      let l21 = *((base + 8) as *const i32);
+                                      let l22 = i32::from(*((base + 12) as *const u8));
+                                      
+                                      (l21 as u32, match l22 {
+                                        0 => None,
+                                        1 => {
+                                          let e = {
+                                            let l23 = *((base + 16) as *const i32);
+                                            
+                                            l23
+                                          };
+                                          Some(e)
+                                        }
+                                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                      })
+                                    };
+                                    WitNode::VariantValue(e61)
+                                  }
+                                  2 => {
+                                    let e61 = {
+                                      let l24 = *((base + 8) as *const i32);
+                                      
+                                      l24 as u32
+                                    };
+                                    WitNode::EnumValue(e61)
+                                  }
+                                  3 => {
+                                    let e61 = {
+                                      let l25 = *((base + 8) as *const i32);
+                                      let l26 = *((base + 12) as *const i32);
+                                      let base28 = l25;
+                                      let len28 = l26;
+                                      let mut result28 = Vec::with_capacity(len28 as usize);
+                                      for i in 0..len28 {
+                                        let base = base28 + i * 1;
+                                        let e28 = {
+                                          let l27 = i32::from(*((base + 0) as *const u8));
+                                          
+                                          wit_bindgen::rt::bool_lift(l27 as u8)
+                                        };
+                                        result28.push(e28);
+                                      }
+                                      wit_bindgen::rt::dealloc(base28, (len28 as usize) * 1, 1);
+                                      
+                                      result28
+                                    };
+                                    WitNode::FlagsValue(e61)
+                                  }
+                                  4 => {
+                                    let e61 = {
+                                      let l29 = *((base + 8) as *const i32);
+                                      let l30 = *((base + 12) as *const i32);
+                                      let len31 = l30 as usize;
+                                      
+                                      Vec::from_raw_parts(l29 as *mut _, len31, len31)
+                                    };
+                                    WitNode::TupleValue(e61)
+                                  }
+                                  5 => {
+                                    let e61 = {
+                                      let l32 = *((base + 8) as *const i32);
+                                      let l33 = *((base + 12) as *const i32);
+                                      let len34 = l33 as usize;
+                                      
+                                      Vec::from_raw_parts(l32 as *mut _, len34, len34)
+                                    };
+                                    WitNode::ListValue(e61)
+                                  }
+                                  6 => {
+                                    let e61 = {
+                                      let l35 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      match l35 {
+                                        0 => None,
+                                        1 => {
+                                          let e = {
+                                            let l36 = *((base + 12) as *const i32);
+                                            
+                                            l36
+                                          };
+                                          Some(e)
+                                        }
+                                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                      }
+                                    };
+                                    WitNode::OptionValue(e61)
+                                  }
+                                  7 => {
+                                    let e61 = {
+                                      let l37 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      match l37 {
+                                        0 => {
+                                          let e = {
+                                            let l38 = i32::from(*((base + 12) as *const u8));
+                                            
+                                            match l38 {
+                                              0 => None,
+                                              1 => {
+                                                let e = {
+                                                  let l39 = *((base + 16) as *const i32);
+                                                  
+                                                  l39
+                                                };
+                                                Some(e)
+                                              }
+                                              _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                            }
+                                          };
+                                          Ok(e)
+                                        }
+                                        1 => {
+                                          let e = {
+                                            let l40 = i32::from(*((base + 12) as *const u8));
+                                            
+                                            match l40 {
+                                              0 => None,
+                                              1 => {
+                                                let e = {
+                                                  let l41 = *((base + 16) as *const i32);
+                                                  
+                                                  l41
+                                                };
+                                                Some(e)
+                                              }
+                                              _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                            }
+                                          };
+                                          Err(e)
+                                        }
+                                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                      }
+                                    };
+                                    WitNode::ResultValue(e61)
+                                  }
+                                  8 => {
+                                    let e61 = {
+                                      let l42 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      l42 as u8
+                                    };
+                                    WitNode::PrimU8(e61)
+                                  }
+                                  9 => {
+                                    let e61 = {
+                                      let l43 = i32::from(*((base + 8) as *const u16));
+                                      
+                                      l43 as u16
+                                    };
+                                    WitNode::PrimU16(e61)
+                                  }
+                                  10 => {
+                                    let e61 = {
+                                      let l44 = *((base + 8) as *const i32);
+                                      
+                                      l44 as u32
+                                    };
+                                    WitNode::PrimU32(e61)
+                                  }
+                                  11 => {
+                                    let e61 = {
+                                      let l45 = *((base + 8) as *const i64);
+                                      
+                                      l45 as u64
+                                    };
+                                    WitNode::PrimU64(e61)
+                                  }
+                                  12 => {
+                                    let e61 = {
+                                      let l46 = i32::from(*((base + 8) as *const i8));
+                                      
+                                      l46 as i8
+                                    };
+                                    WitNode::PrimS8(e61)
+                                  }
+                                  13 => {
+                                    let e61 = {
+                                      let l47 = i32::from(*((base + 8) as *const i16));
+                                      
+                                      l47 as i16
+                                    };
+                                    WitNode::PrimS16(e61)
+                                  }
+                                  14 => {
+                                    let e61 = {
+                                      let l48 = *((base + 8) as *const i32);
+                                      
+                                      l48
+                                    };
+                                    WitNode::PrimS32(e61)
+                                  }
+                                  15 => {
+                                    let e61 = {
+                                      let l49 = *((base + 8) as *const i64);
+                                      
+                                      l49
+                                    };
+                                    WitNode::PrimS64(e61)
+                                  }
+                                  16 => {
+                                    let e61 = {
+                                      let l50 = *((base + 8) as *const f32);
+                                      
+                                      l50
+                                    };
+                                    WitNode::PrimFloat32(e61)
+                                  }
+                                  17 => {
+                                    let e61 = {
+                                      let l51 = *((base + 8) as *const f64);
+                                      
+                                      l51
+                                    };
+                                    WitNode::PrimFloat64(e61)
+                                  }
+                                  18 => {
+                                    let e61 = {
+                                      let l52 = *((base + 8) as *const i32);
+                                      
+                                      wit_bindgen::rt::char_lift(l52 as u32)
+                                    };
+                                    WitNode::PrimChar(e61)
+                                  }
+                                  19 => {
+                                    let e61 = {
+                                      let l53 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      wit_bindgen::rt::bool_lift(l53 as u8)
+                                    };
+                                    WitNode::PrimBool(e61)
+                                  }
+                                  20 => {
+                                    let e61 = {
+                                      let l54 = *((base + 8) as *const i32);
+                                      let l55 = *((base + 12) as *const i32);
+                                      let len56 = l55 as usize;
+                                      let bytes56 = Vec::from_raw_parts(l54 as *mut _, len56, len56);
+                                      
+                                      wit_bindgen::rt::string_lift(bytes56)
+                                    };
+                                    WitNode::PrimString(e61)
+                                  }
+                                  21 => {
+                                    let e61 = {
+                                      let l57 = *((base + 8) as *const i32);
+                                      let l58 = *((base + 12) as *const i32);
+                                      let len59 = l58 as usize;
+                                      let bytes59 = Vec::from_raw_parts(l57 as *mut _, len59, len59);
+                                      let l60 = *((base + 16) as *const i64);
+                                      
+                                      (Uri{
+                                        value: wit_bindgen::rt::string_lift(bytes59),
+                                      }, l60 as u64)
+                                    };
+                                    WitNode::Handle(e61)
+                                  }
+                                  n => {
+                                    debug_assert_eq!(n, 22, "invalid enum discriminant");
+                                    let e61 = {
+                                      let l90 = *((base + 8) as *const i32);
+                                      let l91 = *((base + 12) as *const i32);
+                                      let len92 = l91 as usize;
+                                      
+                                      Vec::from_raw_parts(l90 as *mut _, len92, len92)
+                                    };
+                                    WitNode::PrimBytes(e61)
+                                  }
+                                };
+                                
+                                v61
+                              };
+                              result62.push(e62);
+                            }
+                            wit_bindgen::rt::dealloc(base62, (len62 as usize) * 24, 8);
+                            
+                            WitValue{
+                              nodes: result62,
+                            }
+                          };
+                          Ok(e)
+                        }
+                        1 => {
+                          let e = {
+                            let l63 = i32::from(*((ptr13 + 4) as *const u8));
+                            let v76 = match l63 {
+                              0 => {
+                                let e76 = {
+                                  let l64 = *((ptr13 + 8) as *const i32);
+                                  let l65 = *((ptr13 + 12) as *const i32);
+                                  let len66 = l65 as usize;
+                                  let bytes66 = Vec::from_raw_parts(l64 as *mut _, len66, len66);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes66)
+                                };
+                                RpcError::ProtocolError(e76)
+                              }
+                              1 => {
+                                let e76 = {
+                                  let l67 = *((ptr13 + 8) as *const i32);
+                                  let l68 = *((ptr13 + 12) as *const i32);
+                                  let len69 = l68 as usize;
+                                  let bytes69 = Vec::from_raw_parts(l67 as *mut _, len69, len69);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes69)
+                                };
+                                RpcError::Denied(e76)
+                              }
+                              2 => {
+                                let e76 = {
+                                  let l70 = *((ptr13 + 8) as *const i32);
+                                  let l71 = *((ptr13 + 12) as *const i32);
+                                  let len72 = l71 as usize;
+                                  let bytes72 = Vec::from_raw_parts(l70 as *mut _, len72, len72);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes72)
+                                };
+                                RpcError::NotFound(e76)
+                              }
+                              n => {
+                                debug_assert_eq!(n, 3, "invalid enum discriminant");
+                                let e76 = {
+                                  let l73 = *((ptr13 + 8) as *const i32);
+                                  let l74 = *((ptr13 + 12) as *const i32);
+                                  let len75 = l74 as usize;
+                                  let bytes75 = Vec::from_raw_parts(l73 as *mut _, len75, len75);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes75)
+                                };
+                                RpcError::RemoteInternalError(e76)
+                              }
+                            };
+                            
+                            v76
+                          };
+                          Err(e)
+                        }
+                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                      }
+                    }
+                  }
+        #[allow(unused_unsafe, clippy::all)]
+        pub fn invoke_and_await_with_metadata(&self,function_name: &str,function_params: &[WitValue],metadata: &[(wit_bindgen::rt::string::String,wit_bindgen::rt::string::String,)],) -> Result<WitValue,RpcError>{
+          
+          #[allow(unused_imports)]
+          use wit_bindgen::rt::{alloc, vec::Vec, string::String};
+          unsafe {
+            let mut cleanup_list = Vec::new();
+            
+            #[repr(align(4))]
+            struct RetArea([u8; 16]);
+            let mut ret_area = ::core::mem::MaybeUninit::<RetArea>::uninit();
+            let vec0 = function_name;
+            let ptr0 = vec0.as_ptr() as i32;
+            let len0 = vec0.len() as i32;
+            let vec12 = function_params;
+            let len12 = vec12.len() as i32;
+            let layout12 = alloc::Layout::from_size_align_unchecked(vec12.len() * 8, 4);
+            let result12 = if layout12.size() != 0
+            {
+              let ptr = alloc::alloc(layout12);
+              if ptr.is_null()
+              {
+                alloc::handle_alloc_error(layout12);
+              }
+              ptr
+            }else {{
+              ::core::ptr::null_mut()
+            }};
+            for (i, e) in vec12.into_iter().enumerate() {
+              let base = result12 as i32 + (i as i32) * 8;
+              {
+                let WitValue{ nodes:nodes1, } = e;
+                let vec11 = nodes1;
+                let len11 = vec11.len() as i32;
+                let layout11 = alloc::Layout::from_size_align_unchecked(vec11.len() * 24, 8);
+                let result11 = if layout11.size() != 0
+                {
+                  let ptr = alloc::alloc(layout11);
+                  if ptr.is_null()
+                  {
+                    alloc::handle_alloc_error(layout11);
+                  }
+                  ptr
+                }else {{
+                  ::core::ptr::null_mut()
+                }};
+                for (i, e) in vec11.into_iter().enumerate() {
+                  let base = result11 as i32 + (i as i32) * 24;
+                  {
+                    match e {
+                      WitNode::RecordValue(e) => {
+                        *((base + 0) as *mut u8) = (0i32) as u8;
+                        let vec2 = e;
+                        let ptr2 = vec2.as_ptr() as i32;
+                        let len2 = vec2.len() as i32;
+                        *((base + 12) as *mut i32) = len2;
+                        *((base + 8) as *mut i32) = ptr2;
+                      },
+                      WitNode::VariantValue(e) => {
+                        *((base + 0) as *mut u8) = (1i32) as u8;
+                        let (t3_0, t3_1, ) = e;
+                        *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(t3_0);
+                        match t3_1 {
+                          Some(e) => {
+                            *((base + 12) as *mut u8) = (1i32) as u8;
+                            *((base + 16) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                          },
+                          None => {
+                            {
+                              *((base + 12) as *mut u8) = (0i32) as u8;
+                            }
+                          },
+                        };},
+                        WitNode::EnumValue(e) => {
+                          *((base + 0) as *mut u8) = (2i32) as u8;
+                          *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                        },
+                        WitNode::FlagsValue(e) => {
+                          *((base + 0) as *mut u8) = (3i32) as u8;
+                          let vec4 = e;
+                          let len4 = vec4.len() as i32;
+                          let layout4 = alloc::Layout::from_size_align_unchecked(vec4.len() * 1, 1);
+                          let result4 = if layout4.size() != 0
+                          {
+                            let ptr = alloc::alloc(layout4);
+                            if ptr.is_null()
+                            {
+                              alloc::handle_alloc_error(layout4);
+                            }
+                            ptr
+                          }else {{
+                            ::core::ptr::null_mut()
+                          }};
+                          for (i, e) in vec4.into_iter().enumerate() {
+                            let base = result4 as i32 + (i as i32) * 1;
+                            {
+                              *((base + 0) as *mut u8) = (match e { true => 1, false => 0 }) as u8;
+                            }
+                          }
+                          *((base + 12) as *mut i32) = len4;
+                          *((base + 8) as *mut i32) = result4 as i32;
+                          cleanup_list.extend_from_slice(&[(result4, layout4),]);
+                        },
+                        WitNode::TupleValue(e) => {
+                          *((base + 0) as *mut u8) = (4i32) as u8;
+                          let vec5 = e;
+                          let ptr5 = vec5.as_ptr() as i32;
+                          let len5 = vec5.len() as i32;
+                          *((base + 12) as *mut i32) = len5;
+                          *((base + 8) as *mut i32) = ptr5;
+                        },
+                        WitNode::ListValue(e) => {
+                          *((base + 0) as *mut u8) = (5i32) as u8;
+                          let vec6 = e;
+                          let ptr6 = vec6.as_ptr() as i32;
+                          let len6 = vec6.len() as i32;
+                          *((base + 12) as *mut i32) = len6;
+                          *((base + 8) as *mut i32) = ptr6;
+                        },
+                        WitNode::OptionValue(e) => {
+                          *((base + 0) as *mut u8) = (6i32) as u8;
+                          match e {
+                            Some(e) => {
+                              *((base + 8) as *mut u8) = (1i32) as u8;
+                              *((base + 12) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                            },
+                            None => {
+                              {
+                                *((base + 8) as *mut u8) = (0i32) as u8;
+                              }
+                            },
+                          };},
+                          WitNode::ResultValue(e) => {
+                            *((base + 0) as *mut u8) = (7i32) as u8;
+                            match e {
+                              Ok(e) => { {
+                                *((base + 8) as *mut u8) = (0i32) as u8;
+                                match e {
+                                  Some(e) => {
+                                    *((base + 12) as *mut u8) = (1i32) as u8;
+                                    *((base + 16) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                  },
+                                  None => {
+                                    {
+                                      *((base + 12) as *mut u8) = (0i32) as u8;
+                                    }
+                                  },
+                                };} },
+                                Err(e) => { {
+                                  *((base + 8) as *mut u8) = (1i32) as u8;
+                                  match e {
+                                    Some(e) => {
+                                      *((base + 12) as *mut u8) = (1i32) as u8;
+                                      *((base + 16) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                    },
+                                    None => {
+                                      {
+                                        *((base + 12) as *mut u8) = (0i32) as u8;
+                                      }
+                                    },
+                                  };} },
+                                };},
+                                WitNode::PrimU8(e) => {
+                                  *((base + 0) as *mut u8) = (8i32) as u8;
+                                  *((base + 8) as *mut u8) = (wit_bindgen::rt::as_i32(e)) as u8;
+                                },
+                                WitNode::PrimU16(e) => {
+                                  *((base + 0) as *mut u8) = (9i32) as u8;
+                                  *((base + 8) as *mut u16) = (wit_bindgen::rt::as_i32(e)) as u16;
+                                },
+                                WitNode::PrimU32(e) => {
+                                  *((base + 0) as *mut u8) = (10i32) as u8;
+                                  *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                },
+                                WitNode::PrimU64(e) => {
+                                  *((base + 0) as *mut u8) = (11i32) as u8;
+                                  *((base + 8) as *mut i64) = wit_bindgen::rt::as_i64(e);
+                                },
+                                WitNode::PrimS8(e) => {
+                                  *((base + 0) as *mut u8) = (12i32) as u8;
+                                  *((base + 8) as *mut u8) = (wit_bindgen::rt::as_i32(e)) as u8;
+                                },
+                                WitNode::PrimS16(e) => {
+                                  *((base + 0) as *mut u8) = (13i32) as u8;
+                                  *((base + 8) as *mut u16) = (wit_bindgen::rt::as_i32(e)) as u16;
+                                },
+                                WitNode::PrimS32(e) => {
+                                  *((base + 0) as *mut u8) = (14i32) as u8;
+                                  *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                },
+                                WitNode::PrimS64(e) => {
+                                  *((base + 0) as *mut u8) = (15i32) as u8;
+                                  *((base + 8) as *mut i64) = wit_bindgen::rt::as_i64(e);
+                                },
+                                WitNode::PrimFloat32(e) => {
+                                  *((base + 0) as *mut u8) = (16i32) as u8;
+                                  *((base + 8) as *mut f32) = wit_bindgen::rt::as_f32(e);
+                                },
+                                WitNode::PrimFloat64(e) => {
+                                  *((base + 0) as *mut u8) = (17i32) as u8;
+                                  *((base + 8) as *mut f64) = wit_bindgen::rt::as_f64(e);
+                                },
+                                WitNode::PrimChar(e) => {
+                                  *((base + 0) as *mut u8) = (18i32) as u8;
+                                  *((base + 8) as *mut i32) = wit_bindgen::rt::as_i32(e);
+                                },
+                                WitNode::PrimBool(e) => {
+                                  *((base + 0) as *mut u8) = (19i32) as u8;
+                                  *((base + 8) as *mut u8) = (match e { true => 1, false => 0 }) as u8;
+                                },
+                                WitNode::PrimString(e) => {
+                                  *((base + 0) as *mut u8) = (20i32) as u8;
+                                  let vec7 = e;
+                                  let ptr7 = vec7.as_ptr() as i32;
+                                  let len7 = vec7.len() as i32;
+                                  *((base + 12) as *mut i32) = len7;
+                                  *((base + 8) as *mut i32) = ptr7;
+                                },
+                                WitNode::Handle(e) => {
+                                  *((base + 0) as *mut u8) = (21i32) as u8;
+                                  let (t8_0, t8_1, ) = e;
+                                  let Uri{ value:value9, } = t8_0;
+                                  let vec10 = value9;
+                                  let ptr10 = vec10.as_ptr() as i32;
+                                  let len10 = vec10.len() as i32;
+                                  *((base + 12) as *mut i32) = len10;
+                                  *((base + 8) as *mut i32) = ptr10;
+                                  *((base + 16) as *mut i64) = wit_bindgen::rt::as_i64(t8_1);
+                                },
+                                WitNode::PrimBytes(e) => {
+                                  *((base + 0) as *mut u8) = (22i32) as u8;
+                                  let vec93 = e;
+                                  let ptr93 = vec93.as_ptr() as i32;
+                                  let len93 = vec93.len() as i32;
+                                  *((base + 12) as *mut i32) = len93;
+                                  *((base + 8) as *mut i32) = ptr93;
+                                },
+                              }
+                            }
+                          }
+                          *((base + 4) as *mut i32) = len11;
+                          *((base + 0) as *mut i32) = result11 as i32;
+                          cleanup_list.extend_from_slice(&[(result11, layout11),]);
+                        }
+                      }
+                      let vec103 = metadata;
+                      let len103 = vec103.len() as i32;
+                      let layout103 = alloc::Layout::from_size_align_unchecked(vec103.len() * 16, 4);
+                      let result103 = if layout103.size() != 0
+                      {
+                        let ptr = alloc::alloc(layout103);
+                        if ptr.is_null()
+                        {
+                          alloc::handle_alloc_error(layout103);
+                        }
+                        ptr
+                      }else {{
+                        ::core::ptr::null_mut()
+                      }};
+                      for (i, e) in vec103.into_iter().enumerate() {
+                        let base = result103 as i32 + (i as i32) * 16;
+                        {
+                          let (t100_0, t100_1, ) = e;
+                          let vec101 = t100_0;
+                          let ptr101 = vec101.as_ptr() as i32;
+                          let len101 = vec101.len() as i32;
+                          *((base + 4) as *mut i32) = len101;
+                          *((base + 0) as *mut i32) = ptr101;
+                          let vec102 = t100_1;
+                          let ptr102 = vec102.as_ptr() as i32;
+                          let len102 = vec102.len() as i32;
+                          *((base + 12) as *mut i32) = len102;
+                          *((base + 8) as *mut i32) = ptr102;
+                        }
+                      }
+                      let ptr13 = ret_area.as_mut_ptr() as i32;
+                      #[cfg(target_arch = "wasm32")]
+                      #[link(wasm_import_module = "golem:rpc/types@0.2.0")]
+                      extern "C" {
+                        #[link_name = "[method]wasm-rpc.invoke-and-await-with-metadata"]
+                        fn wit_import(_: i32, _: i32, _: i32, _: i32, _: i32, _: i32, _: i32, _: i32, );
+                      }
+                      
+                      #[cfg(not(target_arch = "wasm32"))]
+                      fn wit_import(_: i32, _: i32, _: i32, _: i32, _: i32, _: i32, _: i32, _: i32, ){ unreachable!() }
+                      wit_import((self).handle() as i32, ptr0, len0, result12 as i32, len12, result103 as i32, len103, ptr13);
+                      let l14 = i32::from(*((ptr13 + 0) as *const u8));
+                      if layout12.size() != 0 {
+                        alloc::dealloc(result12, layout12);
+                      }
+                      if layout103.size() != 0 {
+                        alloc::dealloc(result103, layout103);
+                      }
+                      for (ptr, layout) in cleanup_list {
+                        
+                        if layout.size() != 0 {
+                          
+                          alloc::dealloc(ptr, layout);
+                          
+                        }
+                        
+                      }
+                      match l14 {
+                        0 => {
+                          let e = {
+                            let l15 = *((ptr13 + 4) as *const i32);
+                            let l16 = *((ptr13 + 8) as *const i32);
+                            let base62 = l15;
+                            let len62 = l16;
+                            let mut result62 = Vec::with_capacity(len62 as usize);
+                            for i in 0..len62 {
+                              let base = base62 + i * 24;
+                              let e62 = {
+                                let l17 = i32::from(*((base + 0) as *const u8));
+                                let v61 = match l17 {
+                                  0 => {
+                                    let e61 = {
+                                      let l18 = *((base + 8) as *const i32);
+                                      let l19 = *((base + 12) as *const i32);
+                                      let len20 = l19 as usize;
+                                      
+                                      Vec::from_raw_parts(l18 as *mut _, len20, len20)
+                                    };
+                                    WitNode::RecordValue(e61)
+                                  }
+                                  1 => {
+                                    let e61 = {
+                                      let l21 = *((base + 8) as *const i32);
+                                      let l22 = i32::from(*((base + 12) as *const u8));
+                                      
+                                      (l21 as u32, match l22 {
+                                        0 => None,
+                                        1 => {
+                                          let e = {
+                                            let l23 = *((base + 16) as *const i32);
+                                            
+                                            l23
+                                          };
+                                          Some(e)
+                                        }
+                                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                      })
+                                    };
+                                    WitNode::VariantValue(e61)
+                                  }
+                                  2 => {
+                                    let e61 = {
+                                      let l24 = *((base + 8) as *const i32);
+                                      
+                                      l24 as u32
+                                    };
+                                    WitNode::EnumValue(e61)
+                                  }
+                                  3 => {
+                                    let e61 = {
+                                      let l25 = *((base + 8) as *const i32);
+                                      let l26 = *((base + 12) as *const i32);
+                                      let base28 = l25;
+                                      let len28 = l26;
+                                      let mut result28 = Vec::with_capacity(len28 as usize);
+                                      for i in 0..len28 {
+                                        let base = base28 + i * 1;
+                                        let e28 = {
+                                          let l27 = i32::from(*((base + 0) as *const u8));
+                                          
+                                          wit_bindgen::rt::bool_lift(l27 as u8)
+                                        };
+                                        result28.push(e28);
+                                      }
+                                      wit_bindgen::rt::dealloc(base28, (len28 as usize) * 1, 1);
+                                      
+                                      result28
+                                    };
+                                    WitNode::FlagsValue(e61)
+                                  }
+                                  4 => {
+                                    let e61 = {
+                                      let l29 = *((base + 8) as *const i32);
+                                      let l30 = *((base + 12) as *const i32);
+                                      let len31 = l30 as usize;
+                                      
+                                      Vec::from_raw_parts(l29 as *mut _, len31, len31)
+                                    };
+                                    WitNode::TupleValue(e61)
+                                  }
+                                  5 => {
+                                    let e61 = {
+                                      let l32 = *((base + 8) as *const i32);
+                                      let l33 = *((base + 12) as *const i32);
+                                      let len34 = l33 as usize;
+                                      
+                                      Vec::from_raw_parts(l32 as *mut _, len34, len34)
+                                    };
+                                    WitNode::ListValue(e61)
+                                  }
+                                  6 => {
+                                    let e61 = {
+                                      let l35 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      match l35 {
+                                        0 => None,
+                                        1 => {
+                                          let e = {
+                                            let l36 = *((base + 12) as *const i32);
+                                            
+                                            l36
+                                          };
+                                          Some(e)
+                                        }
+                                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                      }
+                                    };
+                                    WitNode::OptionValue(e61)
+                                  }
+                                  7 => {
+                                    let e61 = {
+                                      let l37 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      match l37 {
+                                        0 => {
+                                          let e = {
+                                            let l38 = i32::from(*((base + 12) as *const u8));
+                                            
+                                            match l38 {
+                                              0 => None,
+                                              1 => {
+                                                let e = {
+                                                  let l39 = *((base + 16) as *const i32);
+                                                  
+                                                  l39
+                                                };
+                                                Some(e)
+                                              }
+                                              _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                            }
+                                          };
+                                          Ok(e)
+                                        }
+                                        1 => {
+                                          let e = {
+                                            let l40 = i32::from(*((base + 12) as *const u8));
+                                            
+                                            match l40 {
+                                              0 => None,
+                                              1 => {
+                                                let e = {
+                                                  let l41 = *((base + 16) as *const i32);
+                                                  
+                                                  l41
+                                                };
+                                                Some(e)
+                                              }
+                                              _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                            }
+                                          };
+                                          Err(e)
+                                        }
+                                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                                      }
+                                    };
+                                    WitNode::ResultValue(e61)
+                                  }
+                                  8 => {
+                                    let e61 = {
+                                      let l42 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      l42 as u8
+                                    };
+                                    WitNode::PrimU8(e61)
+                                  }
+                                  9 => {
+                                    let e61 = {
+                                      let l43 = i32::from(*((base + 8) as *const u16));
+                                      
+                                      l43 as u16
+                                    };
+                                    WitNode::PrimU16(e61)
+                                  }
+                                  10 => {
+                                    let e61 = {
+                                      let l44 = *((base + 8) as *const i32);
+                                      
+                                      l44 as u32
+                                    };
+                                    WitNode::PrimU32(e61)
+                                  }
+                                  11 => {
+                                    let e61 = {
+                                      let l45 = *((base + 8) as *const i64);
+                                      
+                                      l45 as u64
+                                    };
+                                    WitNode::PrimU64(e61)
+                                  }
+                                  12 => {
+                                    let e61 = {
+                                      let l46 = i32::from(*((base + 8) as *const i8));
+                                      
+                                      l46 as i8
+                                    };
+                                    WitNode::PrimS8(e61)
+                                  }
+                                  13 => {
+                                    let e61 = {
+                                      let l47 = i32::from(*((base + 8) as *const i16));
+                                      
+                                      l47 as i16
+                                    };
+                                    WitNode::PrimS16(e61)
+                                  }
+                                  14 => {
+                                    let e61 = {
+                                      let l48 = *((base + 8) as *const i32);
+                                      
+                                      l48
+                                    };
+                                    WitNode::PrimS32(e61)
+                                  }
+                                  15 => {
+                                    let e61 = {
+                                      let l49 = *((base + 8) as *const i64);
+                                      
+                                      l49
+                                    };
+                                    WitNode::PrimS64(e61)
+                                  }
+                                  16 => {
+                                    let e61 = {
+                                      let l50 = *((base + 8) as *const f32);
+                                      
+                                      l50
+                                    };
+                                    WitNode::PrimFloat32(e61)
+                                  }
+                                  17 => {
+                                    let e61 = {
+                                      let l51 = *((base + 8) as *const f64);
+                                      
+                                      l51
+                                    };
+                                    WitNode::PrimFloat64(e61)
+                                  }
+                                  18 => {
+                                    let e61 = {
+                                      let l52 = *((base + 8) as *const i32);
+                                      
+                                      wit_bindgen::rt::char_lift(l52 as u32)
+                                    };
+                                    WitNode::PrimChar(e61)
+                                  }
+                                  19 => {
+                                    let e61 = {
+                                      let l53 = i32::from(*((base + 8) as *const u8));
+                                      
+                                      wit_bindgen::rt::bool_lift(l53 as u8)
+                                    };
+                                    WitNode::PrimBool(e61)
+                                  }
+                                  20 => {
+                                    let e61 = {
+                                      let l54 = *((base + 8) as *const i32);
+                                      let l55 = *((base + 12) as *const i32);
+                                      let len56 = l55 as usize;
+                                      let bytes56 = Vec::from_raw_parts(l54 as *mut _, len56, len56);
+                                      
+                                      wit_bindgen::rt::string_lift(bytes56)
+                                    };
+                                    WitNode::PrimString(e61)
+                                  }
+                                  21 => {
+                                    let e61 = {
+                                      let l57 = *((base + 8) as *const i32);
+                                      let l58 = *((base + 12) as *const i32);
+                                      let len59 = l58 as usize;
+                                      let bytes59 = Vec::from_raw_parts(l57 as *mut _, len59, len59);
+                                      let l60 = *((base + 16) as *const i64);
+                                      
+                                      (Uri{
+                                        value: wit_bindgen::rt::string_lift(bytes59),
+                                      }, l60 as u64)
+                                    };
+                                    WitNode::Handle(e61)
+                                  }
+                                  n => {
+                                    debug_assert_eq!(n, 22, "invalid enum discriminant");
+                                    let e61 = {
+                                      let l90 = *((base + 8) as *const i32);
+                                      let l91 = *((base + 12) as *const i32);
+                                      let len92 = l91 as usize;
+                                      
+                                      Vec::from_raw_parts(l90 as *mut _, len92, len92)
+                                    };
+                                    WitNode::PrimBytes(e61)
+                                  }
+                                };
+                                
+                                v61
+                              };
+                              result62.push(e62);
+                            }
+                            wit_bindgen::rt::dealloc(base62, (len62 as usize) * 24, 8);
+                            
+                            WitValue{
+                              nodes: result62,
+                            }
+                          };
+                          Ok(e)
+                        }
+                        1 => {
+                          let e = {
+                            let l63 = i32::from(*((ptr13 + 4) as *const u8));
+                            let v76 = match l63 {
+                              0 => {
+                                let e76 = {
+                                  let l64 = *((ptr13 + 8) as *const i32);
+                                  let l65 = *((ptr13 + 12) as *const i32);
+                                  let len66 = l65 as usize;
+                                  let bytes66 = Vec::from_raw_parts(l64 as *mut _, len66, len66);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes66)
+                                };
+                                RpcError::ProtocolError(e76)
+                              }
+                              1 => {
+                                let e76 = {
+                                  let l67 = *((ptr13 + 8) as *const i32);
+                                  let l68 = *((ptr13 + 12) as *const i32);
+                                  let len69 = l68 as usize;
+                                  let bytes69 = Vec::from_raw_parts(l67 as *mut _, len69, len69);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes69)
+                                };
+                                RpcError::Denied(e76)
+                              }
+                              2 => {
+                                let e76 = {
+                                  let l70 = *((ptr13 + 8) as *const i32);
+                                  let l71 = *((ptr13 + 12) as *const i32);
+                                  let len72 = l71 as usize;
+                                  let bytes72 = Vec::from_raw_parts(l70 as *mut _, len72, len72);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes72)
+                                };
+                                RpcError::NotFound(e76)
+                              }
+                              n => {
+                                debug_assert_eq!(n, 3, "invalid enum discriminant");
+                                let e76 = {
+                                  let l73 = *((ptr13 + 8) as *const i32);
+                                  let l74 = *((ptr13 + 12) as *const i32);
+                                  let len75 = l74 as usize;
+                                  let bytes75 = Vec::from_raw_parts(l73 as *mut _, len75, len75);
+                                  
+                                  wit_bindgen::rt::string_lift(bytes75)
+                                };
+                                RpcError::RemoteInternalError(e76)
+                              }
+                            };
+                            
+                            v76
+                          };
+                          Err(e)
+                        }
+                        _ => wit_bindgen::rt::invalid_enum_discriminant(),
+                      }
+                    }
+                  }
+                }
+                
+              }
+              
+            }
+          }
+          
+          #[cfg(any(target_arch = "wasm32", test))]
+          #[cfg_attr(target_arch = "wasm32", link_section = "component-type:wit-value")]
+          #[doc(hidden)]
+          pub static __WIT_BINDGEN_COMPONENT_TYPE: [u8; 1785] = [3, 0, 9, 119, 105, 116, 45, 118, 97, 108, 117, 101, 0, 97, 115, 109, 13, 0, 1, 0, 7, 163, 6, 1, 65, 2, 1, 66, 31, 1, 122, 4, 0, 10, 110, 111, 100, 101, 45, 105, 110, 100, 101, 120, 3, 0, 0, 1, 114, 1, 5, 118, 97, 108, 117, 101, 115, 4, 0, 3, 117, 114, 105, 3, 0, 2, 1, 112, 1, 1, 107, 1, 1, 111, 2, 121, 5, 1, 112, 127, 1, 106, 1, 5, 1, 5, 1, 111, 2, 3, 119, 1, 112, 125, 1, 113, 23, 12, 114, 101, 99, 111, 114, 100, 45, 118, 97, 108, 117, 101, 1, 4, 0, 13, 118, 97, 114, 105, 97, 110, 116, 45, 118, 97, 108, 117, 101, 1, 6, 0, 10, 101, 110, 117, 109, 45, 118, 97, 108, 117, 101, 1, 121, 0, 11, 102, 108, 97, 103, 115, 45, 118, 97, 108, 117, 101, 1, 7, 0, 11, 116, 117, 112, 108, 101, 45, 118, 97, 108, 117, 101, 1, 4, 0, 10, 108, 105, 115, 116, 45, 118, 97, 108, 117, 101, 1, 4, 0, 12, 111, 112, 116, 105, 111, 110, 45, 118, 97, 108, 117, 101, 1, 5, 0, 12, 114, 101, 115, 117, 108, 116, 45, 118, 97, 108, 117, 101, 1, 8, 0, 7, 112, 114, 105, 109, 45, 117, 56, 1, 125, 0, 8, 112, 114, 105, 109, 45, 117, 49, 54, 1, 123, 0, 8, 112, 114, 105, 109, 45, 117, 51, 50, 1, 121, 0, 8, 112, 114, 105, 109, 45, 117, 54, 52, 1, 119, 0, 7, 112, 114, 105, 109, 45, 115, 56, 1, 126, 0, 8, 112, 114, 105, 109, 45, 115, 49, 54, 1, 124, 0, 8, 112, 114, 105, 109, 45, 115, 51, 50, 1, 122, 0, 8, 112, 114, 105, 109, 45, 115, 54, 52, 1, 120, 0, 12, 112, 114, 105, 109, 45, 102, 108, 111, 97, 116, 51, 50, 1, 118, 0, 12, 112, 114, 105, 109, 45, 102, 108, 111, 97, 116, 54, 52, 1, 117, 0, 9, 112, 114, 105, 109, 45, 99, 104, 97, 114, 1, 116, 0, 9, 112, 114, 105, 109, 45, 98, 111, 111, 108, 1, 127, 0, 11, 112, 114, 105, 109, 45, 115, 116, 114, 105, 110, 103, 1, 115, 0, 6, 104, 97, 110, 100, 108, 101, 1, 9, 0, 10, 112, 114, 105, 109, 45, 98, 121, 116, 101, 115, 1, 10, 0, 4, 0, 8, 119, 105, 116, 45, 110, 111, 100, 101, 3, 0, 11, 1, 112, 12, 1, 114, 1, 5, 110, 111, 100, 101, 115, 13, 4, 0, 9, 119, 105, 116, 45, 118, 97, 108, 117, 101, 3, 0, 14, 1, 113, 4, 14, 112, 114, 111, 116, 111, 99, 111, 108, 45, 101, 114, 114, 111, 114, 1, 115, 0, 6, 100, 101, 110, 105, 101, 100, 1, 115, 0, 9, 110, 111, 116, 45, 102, 111, 117, 110, 100, 1, 115, 0, 21, 114, 101, 109, 111, 116, 101, 45, 105, 110, 116, 101, 114, 110, 97, 108, 45, 101, 114, 114, 111, 114, 1, 115, 0, 4, 0, 9, 114, 112, 99, 45, 101, 114, 114, 111, 114, 3, 0, 16, 4, 0, 8, 119, 97, 115, 109, 45, 114, 112, 99, 3, 1, 1, 105, 18, 1, 64, 1, 8, 108, 111, 99, 97, 116, 105, 111, 110, 3, 0, 19, 4, 0, 21, 91, 99, 111, 110, 115, 116, 114, 117, 99, 116, 111, 114, 93, 119, 97, 115, 109, 45, 114, 112, 99, 1, 20, 1, 104, 18, 1, 112, 15, 1, 106, 1, 15, 1, 17, 1, 64, 3, 4, 115, 101, 108, 102, 21, 13, 102, 117, 110, 99, 116, 105, 111, 110, 45, 110, 97, 109, 101, 115, 15, 102, 117, 110, 99, 116, 105, 111, 110, 45, 112, 97, 114, 97, 109, 115, 22, 0, 23, 4, 0, 33, 91, 109, 101, 116, 104, 111, 100, 93, 119, 97, 115, 109, 45, 114, 112, 99, 46, 105, 110, 118, 111, 107, 101, 45, 97, 110, 100, 45, 97, 119, 97, 105, 116, 1, 24, 1, 111, 2, 115, 115, 1, 112, 25, 1, 64, 4, 4, 115, 101, 108, 102, 21, 13, 102, 117, 110, 99, 116, 105, 111, 110, 45, 110, 97, 109, 101, 115, 15, 102, 117, 110, 99, 116, 105, 111, 110, 45, 112, 97, 114, 97, 109, 115, 22, 8, 109, 101, 116, 97, 100, 97, 116, 97, 26, 0, 23, 4, 0, 47, 91, 109, 101, 116, 104, 111, 100, 93, 119, 97, 115, 109, 45, 114, 112, 99, 46, 105, 110, 118, 111, 107, 101, 45, 97, 110, 100, 45, 97, 119, 97, 105, 116, 45, 119, 105, 116, 104, 45, 109, 101, 116, 97, 100, 97, 116, 97, 1, 27, 4, 1, 21, 103, 111, 108, 101, 109, 58, 114, 112, 99, 47, 116, 121, 112, 101, 115, 64, 48, 46, 50, 46, 48, 5, 0, 11, 11, 1, 0, 5, 116, 121, 112, 101, 115, 3, 0, 0, 7, 196, 6, 1, 65, 2, 1, 65, 2, 1, 66, 31, 1, 122, 4, 0, 10, 110, 111, 100, 101, 45, 105, 110, 100, 101, 120, 3, 0, 0, 1, 114, 1, 5, 118, 97, 108, 117, 101, 115, 4, 0, 3, 117, 114, 105, 3, 0, 2, 1, 112, 1, 1, 107, 1, 1, 111, 2, 121, 5, 1, 112, 127, 1, 106, 1, 5, 1, 5, 1, 111, 2, 3, 119, 1, 112, 125, 1, 113, 23, 12, 114, 101, 99, 111, 114, 100, 45, 118, 97, 108, 117, 101, 1, 4, 0, 13, 118, 97, 114, 105, 97, 110, 116, 45, 118, 97, 108, 117, 101, 1, 6, 0, 10, 101, 110, 117, 109, 45, 118, 97, 108, 117, 101, 1, 121, 0, 11, 102, 108, 97, 103, 115, 45, 118, 97, 108, 117, 101, 1, 7, 0, 11, 116, 117, 112, 108, 101, 45, 118, 97, 108, 117, 101, 1, 4, 0, 10, 108, 105, 115, 116, 45, 118, 97, 108, 117, 101, 1, 4, 0, 12, 111, 112, 116, 105, 111, 110, 45, 118, 97, 108, 117, 101, 1, 5, 0, 12, 114, 101, 115, 117, 108, 116, 45, 118, 97, 108, 117, 101, 1, 8, 0, 7, 112, 114, 105, 109, 45, 117, 56, 1, 125, 0, 8, 112, 114, 105, 109, 45, 117, 49, 54, 1, 123, 0, 8, 112, 114, 105, 109, 45, 117, 51, 50, 1, 121, 0, 8, 112, 114, 105, 109, 45, 117, 54, 52, 1, 119, 0, 7, 112, 114, 105, 109, 45, 115, 56, 1, 126, 0, 8, 112, 114, 105, 109, 45, 115, 49, 54, 1, 124, 0, 8, 112, 114, 105, 109, 45, 115, 51, 50, 1, 122, 0, 8, 112, 114, 105, 109, 45, 115, 54, 52, 1, 120, 0, 12, 112, 114, 105, 109, 45, 102, 108, 111, 97, 116, 51, 50, 1, 118, 0, 12, 112, 114, 105, 109, 45, 102, 108, 111, 97, 116, 54, 52, 1, 117, 0, 9, 112, 114, 105, 109, 45, 99, 104, 97, 114, 1, 116, 0, 9, 112, 114, 105, 109, 45, 98, 111, 111, 108, 1, 127, 0, 11, 112, 114, 105, 109, 45, 115, 116, 114, 105, 110, 103, 1, 115, 0, 6, 104, 97, 110, 100, 108, 101, 1, 9, 0, 10, 112, 114, 105, 109, 45, 98, 121, 116, 101, 115, 1, 10, 0, 4, 0, 8, 119, 105, 116, 45, 110, 111, 100, 101, 3, 0, 11, 1, 112, 12, 1, 114, 1, 5, 110, 111, 100, 101, 115, 13, 4, 0, 9, 119, 105, 116, 45, 118, 97, 108, 117, 101, 3, 0, 14, 1, 113, 4, 14, 112, 114, 111, 116, 111, 99, 111, 108, 45, 101, 114, 114, 111, 114, 1, 115, 0, 6, 100, 101, 110, 105, 101, 100, 1, 115, 0, 9, 110, 111, 116, 45, 102, 111, 117, 110, 100, 1, 115, 0, 21, 114, 101, 109, 111, 116, 101, 45, 105, 110, 116, 101, 114, 110, 97, 108, 45, 101, 114, 114, 111, 114, 1, 115, 0, 4, 0, 9, 114, 112, 99, 45, 101, 114, 114, 111, 114, 3, 0, 16, 4, 0, 8, 119, 97, 115, 109, 45, 114, 112, 99, 3, 1, 1, 105, 18, 1, 64, 1, 8, 108, 111, 99, 97, 116, 105, 111, 110, 3, 0, 19, 4, 0, 21, 91, 99, 111, 110, 115, 116, 114, 117, 99, 116, 111, 114, 93, 119, 97, 115, 109, 45, 114, 112, 99, 1, 20, 1, 104, 18, 1, 112, 15, 1, 106, 1, 15, 1, 17, 1, 64, 3, 4, 115, 101, 108, 102, 21, 13, 102, 117, 110, 99, 116, 105, 111, 110, 45, 110, 97, 109, 101, 115, 15, 102, 117, 110, 99, 116, 105, 111, 110, 45, 112, 97, 114, 97, 109, 115, 22, 0, 23, 4, 0, 33, 91, 109, 101, 116, 104, 111, 100, 93, 119, 97, 115, 109, 45, 114, 112, 99, 46, 105, 110, 118, 111, 107, 101, 45, 97, 110, 100, 45, 97, 119, 97, 105, 116, 1, 24, 1, 111, 2, 115, 115, 1, 112, 25, 1, 64, 4, 4, 115, 101, 108, 102, 21, 13, 102, 117, 110, 99, 116, 105, 111, 110, 45, 110, 97, 109, 101, 115, 15, 102, 117, 110, 99, 116, 105, 111, 110, 45, 112, 97, 114, 97, 109, 115, 22, 8, 109, 101, 116, 97, 100, 97, 116, 97, 26, 0, 23, 4, 0, 47, 91, 109, 101, 116, 104, 111, 100, 93, 119, 97, 115, 109, 45, 114, 112, 99, 46, 105, 110, 118, 111, 107, 101, 45, 97, 110, 100, 45, 97, 119, 97, 105, 116, 45, 119, 105, 116, 104, 45, 109, 101, 116, 97, 100, 97, 116, 97, 1, 27, 3, 1, 21, 103, 111, 108, 101, 109, 58, 114, 112, 99, 47, 116, 121, 112, 101, 115, 64, 48, 46, 50, 46, 48, 5, 0, 4, 1, 25, 103, 111, 108, 101, 109, 58, 114, 112, 99, 47, 119, 105, 116, 45, 118, 97, 108, 117, 101, 64, 48, 46, 50, 46, 48, 4, 0, 11, 15, 1, 0, 9, 119, 105, 116, 45, 118, 97, 108, 117, 101, 3, 2, 0, 0, 16, 12, 112, 97, 99, 107, 97, 103, 101, 45, 100, 111, 99, 115, 0, 123, 125, 0, 70, 9, 112, 114, 111, 100, 117, 99, 101, 114, 115, 1, 12, 112, 114, 111, 99, 101, 115, 115, 101, 100, 45, 98, 121, 2, 13, 119, 105, 116, 45, 99, 111, 109, 112, 111, 110, 101, 110, 116, 6, 48, 46, 49, 56, 46, 50, 16, 119, 105, 116, 45, 98, 105, 110, 100, 103, 101, 110, 45, 114, 117, 115, 116, 6, 48, 46, 49, 54, 46, 48];
+          
+          #[inline(never)]
+          #[doc(hidden)]
+          #[cfg(target_arch = "wasm32")]
+          pub fn __link_section() {}
+          
\ No newline at end of file
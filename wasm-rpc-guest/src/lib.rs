@@ -0,0 +1,82 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg_attr(not(test), no_std)]
+
+//! The guest side of Golem WASM RPC, the dependency of the generated stubs: `WasmRpc`, the bindings of the `wasm-rpc`
+//! resource, together with the value types of `golem-wasm-rpc` it passes.
+
+#[allow(unused)]
+#[rustfmt::skip]
+mod bindings;
+
+pub use bindings::golem::rpc::types::WasmRpc;
+pub use golem_wasm_rpc::*;
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::__WIT_BINDGEN_COMPONENT_TYPE;
+    use crate::{Uri, Value, WasmRpc, WitValue};
+    use std::path::Path;
+    use wit_component::{DecodedWasm, StringEncoding};
+    use wit_parser::Resolve;
+
+    #[test]
+    fn stubs_share_the_value_types_of_golem_wasm_rpc() {
+        let value = Value::Record(vec![Value::U64(1), Value::String("counter".to_string())]);
+        let wit_value: golem_wasm_rpc::WitValue = WitValue::from(value.clone());
+
+        assert_eq!(Value::from(wit_value), value);
+        assert_eq!(
+            Uri::worker("4b1c", "counter-1"),
+            golem_wasm_rpc::Uri {
+                value: "worker://4b1c/counter-1".to_string()
+            }
+        );
+        assert!(std::any::type_name::<WasmRpc>().starts_with("golem_wasm_rpc_guest::"));
+    }
+
+    #[test]
+    fn embedded_component_type_matches_the_wit_package() {
+        // The section starts with its format, the string encoding and the name of the world, followed by the
+        // encoded WIT package
+        let section = &__WIT_BINDGEN_COMPONENT_TYPE[..];
+        let name_len = section[2] as usize;
+        let world_name = std::str::from_utf8(&section[3..3 + name_len]).unwrap();
+        let (embedded, embedded_package) =
+            match wit_component::decode(&section[3 + name_len..]).unwrap() {
+                DecodedWasm::WitPackage(resolve, package) => (resolve, package),
+                DecodedWasm::Component(..) => panic!("The component type is not a WIT package"),
+            };
+
+        let mut source = Resolve::default();
+        let (source_package, _) = source
+            .push_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../wasm-rpc/wit"))
+            .unwrap();
+
+        assert_eq!(
+            embedded.packages[embedded_package].name,
+            source.packages[source_package].name
+        );
+        // Encoding the world of each package in the same way compares the types, regardless of their docs
+        let encode = |resolve: &Resolve, package| {
+            let world = resolve.packages[package].worlds[world_name];
+            wit_component::metadata::encode(resolve, world, StringEncoding::UTF8, None).unwrap()
+        };
+        assert_eq!(
+            encode(&embedded, embedded_package),
+            encode(&source, source_package)
+        );
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use golem_wasm_rpc::WorkerUri;
+use std::str::FromStr;
+
+/// The export of a component a function name of a wasm-rpc invocation refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    /// A function, a resource constructor or method, called with the parameters of the invocation
+    Function {
+        /// The exported interface, without its version, or `None` for the functions exported by the component directly
+        interface: Option<String>,
+        function: String,
+    },
+    /// Drops the resource instance whose handle is the only parameter of the invocation
+    Drop {
+        interface: Option<String>,
+        resource: String,
+    },
+}
+
+impl ExportTarget {
+    pub fn interface(&self) -> Option<&str> {
+        match self {
+            ExportTarget::Function { interface, .. } | ExportTarget::Drop { interface, .. } => {
+                interface.as_deref()
+            }
+        }
+    }
+}
+
+/// The exports a function name of a wasm-rpc invocation can refer to, in the order of preference.
+///
+/// Stubs invoke the functions of an interface like `rpc:counter/api/add`, and the resources of an interface like
+/// `rpc:counter/api/counter/add`, where `new` and `drop` stand for the constructor and the drop of the resource. Names
+/// like `rpc:counter/api.{add}` refer to a function of an exported interface as it is, and `{add}` to a function
+/// exported by the component directly.
+pub fn export_targets(function_name: &str) -> Vec<ExportTarget> {
+    let function = |interface: Option<String>, function: String| ExportTarget::Function {
+        interface,
+        function,
+    };
+    if let Some((interface, name)) = function_name.split_once(".{") {
+        return match name.strip_suffix('}') {
+            Some(name) if !interface.is_empty() && !name.is_empty() => {
+                vec![function(Some(interface.to_string()), name.to_string())]
+            }
+            _ => Vec::new(),
+        };
+    }
+    if let Some(name) = function_name.strip_prefix('{') {
+        return match name.strip_suffix('}') {
+            Some(name) if !name.is_empty() => vec![function(None, name.to_string())],
+            _ => Vec::new(),
+        };
+    }
+
+    let Some((package, path)) = function_name.split_once('/') else {
+        return Vec::new();
+    };
+    let segments = path.split('/').collect::<Vec<_>>();
+    if package.is_empty() || segments.iter().any(|segment| segment.is_empty()) {
+        return Vec::new();
+    }
+    let interface = |name: &str| Some(format!("{package}/{name}"));
+    match segments.as_slice() {
+        [name] => vec![function(None, name.to_string())],
+        [name, function_name] => vec![function(interface(name), function_name.to_string())],
+        [name, resource, "new"] => vec![function(
+            interface(name),
+            format!("[constructor]{resource}"),
+        )],
+        [name, resource, "drop"] => vec![ExportTarget::Drop {
+            interface: interface(name),
+            resource: resource.to_string(),
+        }],
+        [name, resource, method] => vec![
+            function(interface(name), format!("[method]{resource}.{method}")),
+            function(interface(name), format!("[static]{resource}.{method}")),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// An invocation read by the simulator, like `worker://counter/worker-1 rpc:counter/api.{add}(1, "visits")`. The
+/// function can also be part of the URI, and the parentheses can be omitted for functions without parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    /// The target worker, without the function
+    pub uri: WorkerUri,
+    pub function: String,
+    /// The arguments in WAVE, in the order of the function's parameters
+    pub args: Vec<String>,
+}
+
+impl FromStr for Invocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let line = s.trim();
+        let (target, args) = match line.find('(') {
+            Some(idx) => {
+                let args = line[idx + 1..].strip_suffix(')').ok_or_else(|| {
+                    format!("The arguments of {line} are not closed by a parenthesis")
+                })?;
+                (line[..idx].trim_end(), split_arguments(args)?)
+            }
+            None => (line, Vec::new()),
+        };
+        let (uri, function) = match target.split_once(char::is_whitespace) {
+            Some((uri, function)) => (uri, Some(function.trim())),
+            None => (target, None),
+        };
+        let uri: WorkerUri = uri
+            .parse()
+            .map_err(|err| format!("Invalid worker URI {uri}: {err}"))?;
+        let function = match (function, uri.function.as_deref()) {
+            (Some(function), _) | (None, Some(function)) => function.to_string(),
+            (None, None) => return Err(format!("The function to invoke is missing from {line}")),
+        };
+        Ok(Invocation {
+            uri: uri.worker(),
+            function,
+            args,
+        })
+    }
+}
+
+/// Splits the arguments of an invocation at the commas which are not nested in a value or quoted
+fn split_arguments(text: &str) -> Result<Vec<String>, String> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| format!("Unbalanced {c} in the arguments {text}"))?
+                }
+                ',' if depth == 0 => {
+                    args.push(argument(&current, text)?);
+                    current.clear();
+                    continue;
+                }
+                _ => {}
+            },
+        }
+        current.push(c);
+    }
+    if quote.is_some() || depth > 0 {
+        return Err(format!("Unterminated value in the arguments {text}"));
+    }
+    args.push(argument(&current, text)?);
+    Ok(args)
+}
+
+fn argument(arg: &str, text: &str) -> Result<String, String> {
+    match arg.trim() {
+        "" => Err(format!("Missing argument in {text}")),
+        arg => Ok(arg.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::call::{export_targets, ExportTarget, Invocation};
+
+    fn function(interface: Option<&str>, function: &str) -> ExportTarget {
+        ExportTarget::Function {
+            interface: interface.map(|interface| interface.to_string()),
+            function: function.to_string(),
+        }
+    }
+
+    #[test]
+    fn function_names_refer_to_exports() {
+        assert_eq!(
+            export_targets("rpc:counter/api/add"),
+            vec![function(Some("rpc:counter/api"), "add")]
+        );
+        assert_eq!(
+            export_targets("rpc:counter/get-all"),
+            vec![function(None, "get-all")]
+        );
+        assert_eq!(
+            export_targets("rpc:counter/api/counter/new"),
+            vec![function(Some("rpc:counter/api"), "[constructor]counter")]
+        );
+        assert_eq!(
+            export_targets("rpc:counter/api/counter/add"),
+            vec![
+                function(Some("rpc:counter/api"), "[method]counter.add"),
+                function(Some("rpc:counter/api"), "[static]counter.add"),
+            ]
+        );
+        assert_eq!(
+            export_targets("rpc:counter/api/counter/drop"),
+            vec![ExportTarget::Drop {
+                interface: Some("rpc:counter/api".to_string()),
+                resource: "counter".to_string(),
+            }]
+        );
+        assert_eq!(
+            export_targets("rpc:counter/api.{add}"),
+            vec![function(Some("rpc:counter/api"), "add")]
+        );
+        assert_eq!(
+            export_targets("rpc:counter/api.{[method]counter.add}"),
+            vec![function(Some("rpc:counter/api"), "[method]counter.add")]
+        );
+        assert_eq!(export_targets("{add}"), vec![function(None, "add")]);
+
+        for invalid in [
+            "add",
+            "rpc:counter/",
+            "rpc:counter/api.{add",
+            "{}",
+            "a/b/c/d/e",
+        ] {
+            assert_eq!(export_targets(invalid), vec![], "{invalid}");
+        }
+    }
+
+    #[test]
+    fn invocations_are_parsed_with_their_arguments() {
+        let invocation: Invocation =
+            r#"worker://counter/worker-1 rpc:counter/api.{add}({name: "a, (b)", value: 1}, [1, 2], 'x')"#
+                .parse()
+                .unwrap();
+        assert_eq!(invocation.uri.to_string(), "worker://counter/worker-1");
+        assert_eq!(invocation.function, "rpc:counter/api.{add}");
+        assert_eq!(
+            invocation.args,
+            vec![r#"{name: "a, (b)", value: 1}"#, "[1, 2]", "'x'"]
+        );
+
+        let invocation: Invocation = "worker://counter/worker-1/rpc:counter/api.{get}"
+            .parse()
+            .unwrap();
+        assert_eq!(invocation.uri.to_string(), "worker://counter/worker-1");
+        assert_eq!(invocation.function, "rpc:counter/api.{get}");
+        assert!(invocation.args.is_empty());
+
+        let invocation: Invocation =
+            r#"worker://counter/worker-1 rpc:counter/api.{add}("\")", ')')"#
+                .parse()
+                .unwrap();
+        assert_eq!(invocation.args, vec![r#""\")""#, "')'"]);
+
+        for invalid in [
+            "worker://counter/worker-1",
+            "worker://counter/worker-1 rpc:counter/api.{add}(1",
+            "worker://counter/worker-1 rpc:counter/api.{add}(1,)",
+            "worker://counter/worker-1 rpc:counter/api.{add}([1)",
+            "worker://counter/worker-1 rpc:counter/api.{add}(\"1)",
+            "counter/worker-1 rpc:counter/api.{add}(1)",
+        ] {
+            assert!(invalid.parse::<Invocation>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use clap::Args;
+use golem_wasm_rpc::chaos::{Chaos, Faults, Latency};
+use golem_wasm_rpc::WorkerUri;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The faults injected into the invocations of the workers, for exercising the retry and idempotency logic of the
+/// callers. Each option is given per target, as `<target>=<value>`, where the target is a worker URI, or
+/// `worker://<component-id>/*` for all the workers of a component. The invocations of the `wasm-rpc` resources are
+/// all awaited, so there are no oneway invocations to drop.
+#[derive(Args, Debug, Clone, Default)]
+pub struct FaultArgs {
+    /// The seed of the injected faults. The same invocations of a worker get the same faults for the same seed.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Delays the invocations of a target, like worker://counter/*=20ms, =10ms..50ms for a uniform distribution, or
+    /// =exp:20ms for an exponential distribution with the given mean
+    #[arg(long = "latency", value_name = "TARGET=LATENCY", value_parser = latency_option)]
+    pub latencies: Vec<(Target, Latency)>,
+    /// The ratio of the invocations of a target failing with a protocol error, between 0 and 1
+    #[arg(long = "error-rate", value_name = "TARGET=RATE", value_parser = rate_option)]
+    pub error_rates: Vec<(Target, f64)>,
+    /// The ratio of the invocations of a target which are delivered twice, between 0 and 1
+    #[arg(long = "duplicate-rate", value_name = "TARGET=RATE", value_parser = rate_option)]
+    pub duplicate_rates: Vec<(Target, f64)>,
+}
+
+impl FaultArgs {
+    pub fn chaos(&self) -> Chaos {
+        let mut faults = HashMap::new();
+        for (target, latency) in &self.latencies {
+            update(&mut faults, target, |faults| faults.with_latency(*latency));
+        }
+        for (target, rate) in &self.error_rates {
+            update(&mut faults, target, |faults| faults.with_error_rate(*rate));
+        }
+        for (target, rate) in &self.duplicate_rates {
+            update(&mut faults, target, |faults| {
+                faults.with_duplicate_rate(*rate)
+            });
+        }
+        faults.into_iter().fold(
+            Chaos::new(self.seed),
+            |chaos, (target, faults)| match target {
+                Target::Worker(uri) => chaos.worker(&uri, faults),
+                Target::Component(component_id) => chaos.component(component_id, faults),
+            },
+        )
+    }
+}
+
+fn update(faults: &mut HashMap<Target, Faults>, target: &Target, f: impl FnOnce(Faults) -> Faults) {
+    let entry = faults.entry(target.clone()).or_default();
+    *entry = f(std::mem::take(entry));
+}
+
+/// The target of the faults
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    Worker(WorkerUri),
+    /// All the workers of a component
+    Component(String),
+}
+
+fn target_option(arg: &str) -> Result<(Target, &str), String> {
+    let (target, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected <target>=<value>, got {arg}"))?;
+    let uri: WorkerUri = target
+        .parse()
+        .map_err(|err| format!("Invalid target {target}: {err}"))?;
+    let target = match (uri.worker_name.as_str(), &uri.function) {
+        ("*", None) => Target::Component(uri.component_id),
+        _ => Target::Worker(uri.worker()),
+    };
+    Ok((target, value))
+}
+
+fn latency_option(arg: &str) -> Result<(Target, Latency), String> {
+    let (target, value) = target_option(arg)?;
+    Ok((target, latency(value)?))
+}
+
+fn rate_option(arg: &str) -> Result<(Target, f64), String> {
+    let (target, value) = target_option(arg)?;
+    match value.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok((target, rate)),
+        _ => Err(format!("expected a rate between 0 and 1, got {value}")),
+    }
+}
+
+/// Parses a latency like `20ms`, `10ms..50ms` or `exp:20ms`
+fn latency(text: &str) -> Result<Latency, String> {
+    if let Some(mean) = text.strip_prefix("exp:") {
+        return Ok(Latency::Exponential {
+            mean: duration(mean)?,
+        });
+    }
+    match text.split_once("..") {
+        Some((min, max)) => {
+            let (min, max) = (duration(min)?, duration(max)?);
+            if min > max {
+                return Err(format!(
+                    "The lower bound of {text} is above the upper bound"
+                ));
+            }
+            Ok(Latency::Uniform { min, max })
+        }
+        None => Ok(Latency::Constant(duration(text)?)),
+    }
+}
+
+/// Parses a duration in milliseconds or seconds, like `20ms` or `1.5s`
+fn duration(text: &str) -> Result<Duration, String> {
+    let (value, unit) = match text.strip_suffix("ms") {
+        Some(value) => (value, 0.001),
+        None => (text.strip_suffix('s').unwrap_or(text), 1.0),
+    };
+    match value.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => Ok(Duration::from_secs_f64(value * unit)),
+        _ => Err(format!("expected a duration like 20ms or 1.5s, got {text}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::faults::{latency, latency_option, rate_option, Target};
+    use golem_wasm_rpc::chaos::Latency;
+    use golem_wasm_rpc::WorkerUri;
+    use std::time::Duration;
+
+    #[test]
+    fn fault_options_are_given_per_target() {
+        assert_eq!(
+            rate_option("worker://counter/worker-1=0.25").unwrap(),
+            (Target::Worker(WorkerUri::new("counter", "worker-1")), 0.25)
+        );
+        assert_eq!(
+            rate_option("worker://counter/*=1").unwrap(),
+            (Target::Component("counter".to_string()), 1.0)
+        );
+        assert_eq!(
+            latency_option("worker://counter/worker-1/rpc:counter/api.{add}=5ms").unwrap(),
+            (
+                Target::Worker(WorkerUri::new("counter", "worker-1")),
+                Latency::Constant(Duration::from_millis(5))
+            )
+        );
+
+        for invalid in [
+            "worker://counter/worker-1",
+            "worker://counter/worker-1=1.5",
+            "worker://counter/worker-1=-0.1",
+            "counter/worker-1=0.5",
+            "worker://counter=0.5",
+        ] {
+            assert!(rate_option(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn latencies_are_parsed_with_their_distribution() {
+        let ms = Duration::from_millis;
+        assert_eq!(latency("20ms").unwrap(), Latency::Constant(ms(20)));
+        assert_eq!(latency("1.5s").unwrap(), Latency::Constant(ms(1500)));
+        assert_eq!(
+            latency("10ms..50ms").unwrap(),
+            Latency::Uniform {
+                min: ms(10),
+                max: ms(50)
+            }
+        );
+        assert_eq!(
+            latency("exp:20ms").unwrap(),
+            Latency::Exponential { mean: ms(20) }
+        );
+
+        for invalid in ["", "fast", "50ms..10ms", "-5ms", "exp:", "10ms.."] {
+            assert!(latency(invalid).is_err(), "{invalid}");
+        }
+    }
+}
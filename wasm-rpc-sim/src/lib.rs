@@ -0,0 +1,26 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A local simulator of Golem workers calling each other through WASM RPC. It loads composed components, assigns
+//! their workers `worker://<component-id>/<worker-name>` URIs, and routes the invocations of their `wasm-rpc`
+//! resources between them in-process, without persisting anything.
+
+pub mod call;
+pub mod faults;
+pub mod simulator;
+pub mod worker;
+
+pub use call::Invocation;
+pub use faults::FaultArgs;
+pub use simulator::{Simulator, Trace};
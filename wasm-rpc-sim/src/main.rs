@@ -0,0 +1,105 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use clap::Parser;
+use golem_wasm_rpc::intercept::Interceptors;
+use golem_wasm_rpc_sim::{FaultArgs, Invocation, Simulator, Trace};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tokio::fs::File;
+use tokio::io::{stdin, AsyncBufRead, AsyncBufReadExt, BufReader};
+
+/// Runs composed components locally and routes the WASM RPC calls between their workers in-process, for testing
+/// worker-to-worker flows before deploying to Golem.
+///
+/// Reads invocations like `worker://counter/worker-1 rpc:counter/api.{add}(1)`, one per line, from the standard input
+/// or a script, and prints their results in WAVE. Workers are started on their first invocation and nothing is
+/// persisted.
+#[derive(Parser, Debug)]
+#[command(name = "wasm-rpc-sim", version)]
+struct Args {
+    /// A component to load, as <component-id>=<wasm file>. Its workers get the URIs worker://<component-id>/<worker-name>
+    #[arg(long = "component", value_name = "ID=WASM", required = true, value_parser = component)]
+    components: Vec<(String, PathBuf)>,
+    /// Reads the invocations from a file, stopping at the first failing one, instead of the standard input
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// Prints every invocation handled by the workers, including the calls between them, to the standard error
+    #[arg(long)]
+    trace: bool,
+    #[command(flatten)]
+    faults: FaultArgs,
+}
+
+fn component(arg: &str) -> Result<(String, PathBuf), String> {
+    match arg.split_once('=') {
+        Some((id, path)) if !id.is_empty() && !path.is_empty() => {
+            Ok((id.to_string(), PathBuf::from(path)))
+        }
+        _ => Err(format!("expected <component-id>=<wasm file>, got {arg}")),
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> anyhow::Result<ExitCode> {
+    let interceptors = if args.trace {
+        Interceptors::new().with(Trace)
+    } else {
+        Interceptors::new()
+    };
+    let simulator = Simulator::load(&args.components, interceptors, args.faults.chaos())?;
+
+    let input: Box<dyn AsyncBufRead + Unpin> = match &args.script {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path)
+                .await
+                .with_context(|| format!("Failed to open {path:?}"))?,
+        )),
+        None => Box::new(BufReader::new(stdin())),
+    };
+    let mut lines = input.lines();
+    let mut line_number = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let results = match line.parse::<Invocation>() {
+            Ok(invocation) => simulator.invoke(&invocation).await,
+            Err(err) => Err(anyhow::Error::msg(err)),
+        };
+        match results {
+            Ok(results) => results.iter().for_each(|result| println!("{result}")),
+            Err(err) => {
+                eprintln!("Line {line_number}: {err:#}");
+                if args.script.is_some() {
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,446 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::call::{export_targets, ExportTarget, Invocation};
+use crate::worker::{add_to_linker, Worker, WorkerCtx};
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use golem_wasm_ast::analysis::{AnalysedExport, AnalysedFunction};
+use golem_wasm_rpc::chaos::{Chaos, ChaosTransport};
+use golem_wasm_rpc::intercept::{IncomingCall, Interceptor, Interceptors};
+use golem_wasm_rpc::metadata::ComponentExports;
+use golem_wasm_rpc::wasmtime::define_imports_as_traps;
+use golem_wasm_rpc::{
+    parse_value, print_value, RpcError, RpcHandler, RpcRouter, Uri, Value, WasmRpcHost, WitValue,
+    WorkerUri,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Config, Engine};
+
+/// Runs the workers of a set of components in-process, routing the wasm-rpc invocations between them through an
+/// [RpcRouter]. Nothing is persisted: the workers are started on their first invocation, and live as long as the
+/// simulator.
+pub struct Simulator {
+    components: HashMap<String, Arc<SimulatedComponent>>,
+    host: WasmRpcHost,
+}
+
+impl Simulator {
+    /// Loads the components, given by their component IDs and WASM files. The workers of a component get the URIs
+    /// `worker://<component-id>/<worker-name>`, and every invocation they handle runs through the interceptors, after
+    /// the faults configured by the chaos were injected into it.
+    pub fn load(
+        components: &[(String, PathBuf)],
+        interceptors: Interceptors,
+        chaos: Chaos,
+    ) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.async_support(true).wasm_component_model(true);
+        let engine = Engine::new(&config)?;
+        let mut linker = Linker::new(&engine);
+        // The imports which are not simulated, like the Golem host API, trap when called. The simulated imports
+        // are added afterwards, replacing their trapping instances.
+        linker.allow_shadowing(true);
+
+        let mut compiled: Vec<(String, Component, ComponentExports)> = Vec::new();
+        for (id, path) in components {
+            if compiled.iter().any(|(other, _, _)| other == id) {
+                bail!("The component ID {id} is given more than once");
+            }
+            let bytes = fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+            let exports = ComponentExports::from_bytes(&bytes)
+                .map_err(|err| anyhow!("Failed to load {path:?}: {err}"))?;
+            let component = Component::new(&engine, &bytes)
+                .map_err(|err| anyhow!("Failed to compile {path:?}: {err:?}"))?;
+            define_imports_as_traps(&mut linker, &component, &bytes)?;
+            compiled.push((id.clone(), component, exports));
+        }
+        add_to_linker(&mut linker)?;
+
+        let router = RpcRouter::new().interceptors(interceptors);
+        let host = WasmRpcHost::builder(ChaosTransport::new(router.clone(), chaos)).build();
+        let linker = Arc::new(linker);
+        let components = compiled
+            .into_iter()
+            .map(|(id, component, exports)| {
+                let component = Arc::new(SimulatedComponent {
+                    id: id.clone(),
+                    engine: engine.clone(),
+                    component,
+                    linker: linker.clone(),
+                    exports,
+                    host: host.clone(),
+                    workers: Mutex::default(),
+                });
+                router.register_component(id.clone(), component.clone());
+                (id, component)
+            })
+            .collect();
+        Ok(Self { components, host })
+    }
+
+    /// Invokes a function of a worker the way its callers do, with the arguments in WAVE, and returns the results in
+    /// WAVE
+    pub async fn invoke(&self, invocation: &Invocation) -> anyhow::Result<Vec<String>> {
+        let Invocation {
+            uri,
+            function,
+            args,
+        } = invocation;
+        let component = self
+            .components
+            .get(&uri.component_id)
+            .ok_or_else(|| anyhow!("No component is loaded with the ID {}", uri.component_id))?;
+        let signature = component
+            .resolve(function)
+            .and_then(|target| component.signature(&target))
+            .ok_or_else(|| anyhow!("{function} is not exported by {}", component.id))?;
+
+        if args.len() != signature.params.len() {
+            bail!(
+                "{function} expects {} argument(s), got {}",
+                signature.params.len(),
+                args.len()
+            );
+        }
+        let mut params = Vec::new();
+        for (arg, param) in args.iter().zip(&signature.params) {
+            let value = parse_value(&param.typ, arg)
+                .map_err(|err| anyhow!("Invalid value for the {} parameter: {err}", param.name))?;
+            params.push(WitValue::from(value));
+        }
+
+        let result = self
+            .host
+            .invoke_and_await(&Uri::from(uri.clone()), function, params)
+            .await
+            .map_err(|err| anyhow!("Invoking {function} of {uri} failed: {err:?}"))?;
+        match Value::from(result) {
+            Value::Tuple(values) if values.len() == signature.results.len() => Ok(values
+                .into_iter()
+                .zip(&signature.results)
+                .map(|(value, result)| {
+                    // Handles have no WAVE representation
+                    print_value(value.clone(), &result.typ).unwrap_or_else(|_| format!("{value:?}"))
+                })
+                .collect()),
+            other => bail!("{function} of {uri} returned unexpected results: {other:?}"),
+        }
+    }
+}
+
+/// A component loaded by the simulator, starting its workers on their first invocation
+struct SimulatedComponent {
+    id: String,
+    engine: Engine,
+    component: Component,
+    linker: Arc<Linker<WorkerCtx>>,
+    exports: ComponentExports,
+    host: WasmRpcHost,
+    workers: Mutex<HashMap<String, Arc<Worker>>>,
+}
+
+impl SimulatedComponent {
+    /// The export a function name of an invocation refers to
+    fn resolve(&self, function_name: &str) -> Option<ExportTarget> {
+        export_targets(function_name)
+            .into_iter()
+            .find_map(|target| match target {
+                ExportTarget::Function {
+                    interface,
+                    function,
+                } => {
+                    let interface = self.interface_name(interface.as_deref())?;
+                    self.exports
+                        .interface_function(interface.as_deref(), &function)?;
+                    Some(ExportTarget::Function {
+                        interface,
+                        function,
+                    })
+                }
+                ExportTarget::Drop {
+                    interface,
+                    resource,
+                } => Some(ExportTarget::Drop {
+                    interface: self.interface_name(interface.as_deref())?,
+                    resource,
+                }),
+            })
+    }
+
+    /// The name an interface is exported by, which has a version if its package has one, unlike the name the stubs
+    /// refer to it by
+    fn interface_name(&self, interface: Option<&str>) -> Option<Option<String>> {
+        let Some(interface) = interface else {
+            return Some(None);
+        };
+        self.exports
+            .exports()
+            .iter()
+            .find_map(|export| match export {
+                AnalysedExport::Instance(instance)
+                    if instance.name == interface
+                        || instance
+                            .name
+                            .strip_prefix(interface)
+                            .is_some_and(|version| version.starts_with('@')) =>
+                {
+                    Some(Some(instance.name.clone()))
+                }
+                _ => None,
+            })
+    }
+
+    fn signature(&self, target: &ExportTarget) -> Option<&AnalysedFunction> {
+        match target {
+            ExportTarget::Function {
+                interface,
+                function,
+            } => self
+                .exports
+                .interface_function(interface.as_deref(), function),
+            ExportTarget::Drop { .. } => None,
+        }
+    }
+
+    /// The worker of the given name, started if this is its first invocation
+    async fn worker(&self, worker_name: &str) -> Result<Arc<Worker>, RpcError> {
+        let mut workers = self.workers.lock().await;
+        if let Some(worker) = workers.get(worker_name) {
+            return Ok(worker.clone());
+        }
+        let uri = WorkerUri::new(&self.id, worker_name);
+        let worker = Worker::start(
+            uri.clone(),
+            &self.engine,
+            &self.component,
+            &self.linker,
+            self.host.clone(),
+        )
+        .await
+        .map_err(|err| RpcError::RemoteInternalError(format!("Failed to start {uri}: {err:?}")))?;
+        let worker = Arc::new(worker);
+        workers.insert(worker_name.to_string(), worker.clone());
+        Ok(worker)
+    }
+}
+
+#[async_trait]
+impl RpcHandler for SimulatedComponent {
+    async fn handle(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+        let uri = call
+            .uri
+            .parse_worker()
+            .map_err(|err| RpcError::ProtocolError(err.to_string()))?;
+        let target = self.resolve(&call.function_name).ok_or_else(|| {
+            RpcError::NotFound(format!(
+                "{} is not exported by {}",
+                call.function_name, self.id
+            ))
+        })?;
+        let worker = self.worker(&uri.worker_name).await?;
+        worker
+            .invoke(&target, &call.function_name, call.function_params.clone())
+            .await
+    }
+}
+
+/// Prints the invocations handled by the workers to the standard error
+pub struct Trace;
+
+#[async_trait]
+impl Interceptor for Trace {
+    async fn before(&self, call: &IncomingCall) -> Result<(), RpcError> {
+        eprintln!("-> {} {}", call.uri, call.function_name);
+        Ok(())
+    }
+
+    async fn after(&self, call: &IncomingCall, _result: &WitValue) {
+        eprintln!("<- {} {}", call.uri, call.function_name);
+    }
+
+    async fn on_error(&self, call: &IncomingCall, error: &RpcError) {
+        eprintln!("<- {} {} failed: {error:?}", call.uri, call.function_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::call::Invocation;
+    use crate::simulator::Simulator;
+    use golem_wasm_rpc::chaos::Chaos;
+    use golem_wasm_rpc::intercept::Interceptors;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    /// Exports `get`, returning 42
+    const COUNTER: &str = r#"
+        (component
+            (core module $m
+                (func (export "get") (result i64) i64.const 42))
+            (core instance $i (instantiate $m))
+            (func (export "get") (result u64) (canon lift (core func $i "get")))
+        )
+    "#;
+
+    /// Exports `run`, calling `{get}` of `worker://counter/worker-1` through wasm-rpc and returning
+    /// the `prim-u64` node of its results
+    const CALLER: &str = r#"
+        (component
+            (import "golem:rpc/types@0.2.0" (instance $rpc
+                (type $uri' (record (field "value" string)))
+                (export $uri "uri" (type (eq $uri')))
+                (type $node' (variant
+                    (case "record-value" (list s32))
+                    (case "variant-value" (tuple u32 (option s32)))
+                    (case "enum-value" u32)
+                    (case "flags-value" (list bool))
+                    (case "tuple-value" (list s32))
+                    (case "list-value" (list s32))
+                    (case "option-value" (option s32))
+                    (case "result-value" (result (option s32) (error (option s32))))
+                    (case "prim-u8" u8)
+                    (case "prim-u16" u16)
+                    (case "prim-u32" u32)
+                    (case "prim-u64" u64)
+                    (case "prim-s8" s8)
+                    (case "prim-s16" s16)
+                    (case "prim-s32" s32)
+                    (case "prim-s64" s64)
+                    (case "prim-float32" float32)
+                    (case "prim-float64" float64)
+                    (case "prim-char" char)
+                    (case "prim-bool" bool)
+                    (case "prim-string" string)
+                    (case "handle" (tuple $uri u64))
+                    (case "prim-bytes" (list u8))))
+                (export $node "wit-node" (type (eq $node')))
+                (type $value' (record (field "nodes" (list $node))))
+                (export $value "wit-value" (type (eq $value')))
+                (type $error' (variant
+                    (case "protocol-error" string)
+                    (case "denied" string)
+                    (case "not-found" string)
+                    (case "remote-internal-error" string)))
+                (export $error "rpc-error" (type (eq $error')))
+                (export $wasm-rpc "wasm-rpc" (type (sub resource)))
+                (export "[constructor]wasm-rpc"
+                    (func (param "location" $uri) (result (own $wasm-rpc))))
+                (export "[method]wasm-rpc.invoke-and-await"
+                    (func (param "self" (borrow $wasm-rpc)) (param "function-name" string)
+                        (param "function-params" (list $value)) (result (result $value (error $error)))))
+            ))
+            (core module $libc
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const 1024))
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                    (local $ptr i32)
+                    (local.set $ptr
+                        (i32.and
+                            (i32.add (global.get $next) (i32.sub (local.get 2) (i32.const 1)))
+                            (i32.sub (i32.const 0) (local.get 2))))
+                    (global.set $next (i32.add (local.get $ptr) (local.get 3)))
+                    (local.get $ptr)))
+            (core instance $libc (instantiate $libc))
+            (core func $new (canon lower (func $rpc "[constructor]wasm-rpc")
+                (memory $libc "memory") (realloc (func $libc "realloc"))))
+            (core func $invoke (canon lower (func $rpc "[method]wasm-rpc.invoke-and-await")
+                (memory $libc "memory") (realloc (func $libc "realloc"))))
+            (core module $m
+                (import "libc" "memory" (memory 1))
+                (import "rpc" "new" (func $new (param i32 i32) (result i32)))
+                (import "rpc" "invoke" (func $invoke (param i32 i32 i32 i32 i32 i32)))
+                (data (i32.const 0) "worker://counter/worker-1")
+                (data (i32.const 32) "{get}")
+                (func (export "run") (result i64)
+                    (local $nodes i32)
+                    (call $invoke
+                        (call $new (i32.const 0) (i32.const 25))
+                        (i32.const 32) (i32.const 5) (i32.const 0) (i32.const 0) (i32.const 64))
+                    (if (i32.load8_u (i32.const 64)) (then unreachable))
+                    ;; The results are a tuple-value node, followed by the node of the result
+                    (local.set $nodes (i32.load (i32.const 68)))
+                    (if (i32.ne (i32.load8_u offset=24 (local.get $nodes)) (i32.const 11))
+                        (then unreachable))
+                    (i64.load offset=32 (local.get $nodes))))
+            (core instance $i (instantiate $m
+                (with "libc" (instance $libc))
+                (with "rpc" (instance (export "new" (func $new)) (export "invoke" (func $invoke))))))
+            (func (export "run") (result u64) (canon lift (core func $i "run")))
+        )
+    "#;
+
+    /// Exports `run`, calling the `ping` function of an import which is not simulated
+    const PINGER: &str = r#"
+        (component
+            (import "test:host/api" (instance $api
+                (export "ping" (func))
+            ))
+            (core func $ping (canon lower (func $api "ping")))
+            (core module $m
+                (import "api" "ping" (func $ping))
+                (func (export "run") (call $ping)))
+            (core instance $i (instantiate $m
+                (with "api" (instance (export "ping" (func $ping))))))
+            (func (export "run") (canon lift (core func $i "run")))
+        )
+    "#;
+
+    fn load(dir: &TempDir, components: &[(&str, &str)]) -> Simulator {
+        let components = components
+            .iter()
+            .map(|(id, wat)| {
+                let path = dir.path().join(format!("{id}.wasm"));
+                fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+                (id.to_string(), path)
+            })
+            .collect::<Vec<(String, PathBuf)>>();
+        Simulator::load(&components, Interceptors::new(), Chaos::new(0)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn calls_are_routed_between_workers() {
+        let dir = TempDir::new("wasm-rpc-sim").unwrap();
+        let simulator = load(&dir, &[("caller", CALLER), ("counter", COUNTER)]);
+        assert!(simulator.components["counter"]
+            .workers
+            .lock()
+            .await
+            .is_empty());
+
+        let invocation: Invocation = "worker://caller/worker-1 {run}".parse().unwrap();
+        assert_eq!(simulator.invoke(&invocation).await.unwrap(), vec!["42"]);
+        let workers = simulator.components["counter"].workers.lock().await;
+        assert_eq!(workers.keys().collect::<Vec<_>>(), vec!["worker-1"]);
+    }
+
+    #[tokio::test]
+    async fn unsimulated_imports_trap() {
+        let dir = TempDir::new("wasm-rpc-sim").unwrap();
+        let simulator = load(&dir, &[("pinger", PINGER)]);
+
+        let invocation: Invocation = "worker://pinger/worker-1 {run}".parse().unwrap();
+        let error = simulator.invoke(&invocation).await.unwrap_err();
+        assert!(
+            format!("{error:?}").contains("test:host/api.{ping} is not available"),
+            "{error:?}"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::call::ExportTarget;
+use async_trait::async_trait;
+use golem_wasm_rpc::wasmtime::{decode_param, encode_output, EncodingError, ResourceStore};
+use golem_wasm_rpc::{
+    Host, HostWasmRpc, RpcError, Uri, Value, WasmRpcEntry, WasmRpcHost, WitValue, WorkerUri,
+};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use wasmtime::component::{Component, Instance, Linker, Resource, ResourceAny, ResourceTable, Val};
+use wasmtime::{Engine, Store};
+use wasmtime_wasi::preview2::{WasiCtx, WasiCtxBuilder, WasiView};
+
+/// The state of a simulated worker's store: its WASI context, the targets of its `wasm-rpc` resources, and the
+/// resources it created for its callers
+pub struct WorkerCtx {
+    uri: Uri,
+    host: WasmRpcHost,
+    wasi: WasiCtx,
+    table: ResourceTable,
+    next_resource_id: u64,
+    resources: HashMap<u64, ResourceAny>,
+}
+
+impl WorkerCtx {
+    /// The target of a `wasm-rpc` resource of the worker
+    fn target(&self, resource: &Resource<WasmRpcEntry>) -> wasmtime::Result<Uri> {
+        let entry = self.table.get(resource)?;
+        entry
+            .payload
+            .downcast_ref::<Uri>()
+            .cloned()
+            .ok_or_else(|| wasmtime::Error::msg("The wasm-rpc resource has no target URI"))
+    }
+}
+
+impl WasiView for WorkerCtx {
+    fn table(&self) -> &ResourceTable {
+        &self.table
+    }
+
+    fn table_mut(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn ctx(&self) -> &WasiCtx {
+        &self.wasi
+    }
+
+    fn ctx_mut(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+impl ResourceStore for WorkerCtx {
+    fn self_uri(&self) -> Uri {
+        self.uri.clone()
+    }
+
+    fn add(&mut self, resource: ResourceAny) -> u64 {
+        let id = self.next_resource_id;
+        self.next_resource_id += 1;
+        self.resources.insert(id, resource);
+        id
+    }
+
+    fn get(&mut self, resource_id: u64) -> Option<ResourceAny> {
+        self.resources.remove(&resource_id)
+    }
+
+    fn borrow(&self, resource_id: u64) -> Option<ResourceAny> {
+        self.resources.get(&resource_id).cloned()
+    }
+}
+
+#[async_trait]
+impl HostWasmRpc for WorkerCtx {
+    async fn new(&mut self, location: Uri) -> wasmtime::Result<Resource<WasmRpcEntry>> {
+        Ok(self.table.push(WasmRpcEntry {
+            payload: Box::new(location),
+        })?)
+    }
+
+    async fn invoke_and_await(
+        &mut self,
+        self_: Resource<WasmRpcEntry>,
+        function_name: String,
+        function_params: Vec<WitValue>,
+    ) -> wasmtime::Result<Result<WitValue, RpcError>> {
+        let uri = self.target(&self_)?;
+        Ok(self
+            .host
+            .invoke_and_await(&uri, &function_name, function_params)
+            .await)
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &mut self,
+        self_: Resource<WasmRpcEntry>,
+        function_name: String,
+        function_params: Vec<WitValue>,
+        metadata: Vec<(String, String)>,
+    ) -> wasmtime::Result<Result<WitValue, RpcError>> {
+        let uri = self.target(&self_)?;
+        Ok(self
+            .host
+            .invoke_and_await_with_metadata(&uri, &function_name, function_params, &metadata)
+            .await)
+    }
+
+    fn drop(&mut self, rep: Resource<WasmRpcEntry>) -> wasmtime::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl Host for WorkerCtx {}
+
+/// Adds the imports the simulated workers are provided with, WASI and the `wasm-rpc` resource, to a linker
+pub fn add_to_linker(linker: &mut Linker<WorkerCtx>) -> wasmtime::Result<()> {
+    wasmtime_wasi::preview2::command::add_to_linker(linker)?;
+    golem_wasm_rpc::golem::rpc::types::add_to_linker(linker, |ctx: &mut WorkerCtx| ctx)
+}
+
+/// A worker of a simulated component: a component instance in its own store, kept in memory only. Like a Golem
+/// worker, it handles one invocation at a time.
+pub struct Worker {
+    uri: WorkerUri,
+    state: Mutex<(Store<WorkerCtx>, Instance)>,
+}
+
+impl Worker {
+    pub async fn start(
+        uri: WorkerUri,
+        engine: &Engine,
+        component: &Component,
+        linker: &Linker<WorkerCtx>,
+        host: WasmRpcHost,
+    ) -> wasmtime::Result<Self> {
+        let wasi = WasiCtxBuilder::new()
+            .inherit_stdout()
+            .inherit_stderr()
+            .args(&[&uri.worker_name])
+            .env("GOLEM_COMPONENT_ID", &uri.component_id)
+            .env("GOLEM_WORKER_NAME", &uri.worker_name)
+            .build();
+        let ctx = WorkerCtx {
+            uri: uri.clone().into(),
+            host,
+            wasi,
+            table: ResourceTable::new(),
+            next_resource_id: 0,
+            resources: HashMap::new(),
+        };
+        let mut store = Store::new(engine, ctx);
+        let instance = linker.instantiate_async(&mut store, component).await?;
+        Ok(Self {
+            uri,
+            state: Mutex::new((store, instance)),
+        })
+    }
+
+    /// Calls an export of the worker with the parameters of an invocation, returning its results as a tuple.
+    ///
+    /// An invocation reaching a worker which is still waiting for the results of an RPC call, like a worker calling
+    /// itself or a cycle of calls, fails instead of waiting forever.
+    pub async fn invoke(
+        &self,
+        target: &ExportTarget,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        let mut state = self.state.try_lock().map_err(|_| {
+            RpcError::ProtocolError(format!(
+                "{} is waiting for the results of an RPC call and cannot handle {function_name}",
+                self.uri
+            ))
+        })?;
+        let (store, instance) = &mut *state;
+
+        let (interface, function) = match target {
+            ExportTarget::Function {
+                interface,
+                function,
+            } => (interface.as_deref(), function.as_str()),
+            ExportTarget::Drop { .. } => return self.drop_resource(store, function_params).await,
+        };
+        let func = {
+            let mut exports = instance.exports(&mut *store);
+            match interface {
+                Some(interface) => exports
+                    .instance(interface)
+                    .and_then(|mut instance| instance.func(function)),
+                None => exports.root().func(function),
+            }
+        }
+        .ok_or_else(|| {
+            RpcError::NotFound(format!("{function_name} is not exported by {}", self.uri))
+        })?;
+
+        let param_types = func.params(&*store);
+        if param_types.len() != function_params.len() {
+            return Err(RpcError::ProtocolError(format!(
+                "{function_name} expects {} parameters, got {}",
+                param_types.len(),
+                function_params.len()
+            )));
+        }
+        let mut params = Vec::new();
+        for (param, typ) in function_params.into_iter().zip(param_types.iter()) {
+            let decoded =
+                decode_param(&Value::from(param), typ, store.data_mut()).map_err(encoding_error)?;
+            params.push(decoded.val);
+        }
+
+        let mut results = vec![Val::Bool(false); func.results(&*store).len()];
+        func.call_async(&mut *store, &params, &mut results)
+            .await
+            .map_err(|err| RpcError::RemoteInternalError(format!("{err:?}")))?;
+        func.post_return_async(&mut *store)
+            .await
+            .map_err(|err| RpcError::RemoteInternalError(format!("{err:?}")))?;
+
+        let mut values = Vec::new();
+        for result in &results {
+            values.push(encode_output(result, store.data_mut()).map_err(encoding_error)?);
+        }
+        Ok(Value::Tuple(values).into())
+    }
+
+    /// Drops a resource the worker created for a caller, given by its handle
+    async fn drop_resource(
+        &self,
+        store: &mut Store<WorkerCtx>,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        let resource = match function_params
+            .into_iter()
+            .map(Value::from)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [Value::Handle { uri, resource_id }] if *uri == store.data().uri => {
+                store.data_mut().get(*resource_id)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| {
+            RpcError::ProtocolError(format!(
+                "Dropping a resource requires the handle of a resource of {}",
+                self.uri
+            ))
+        })?;
+        resource
+            .resource_drop_async(&mut *store)
+            .await
+            .map_err(|err| RpcError::RemoteInternalError(format!("{err:?}")))?;
+        Ok(Value::Tuple(Vec::new()).into())
+    }
+}
+
+fn encoding_error(err: EncodingError) -> RpcError {
+    RpcError::ProtocolError(match err {
+        EncodingError::ParamTypeMismatch => "Parameter type mismatch".to_string(),
+        EncodingError::ValueMismatch { details } => format!("Value mismatch: {details}"),
+        EncodingError::Unknown { details } => details,
+    })
+}
@@ -0,0 +1,303 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Regenerates WASM RPC stub crates from `build.rs`, so the stub follows the WIT definition of the component it calls
+//! without running `wasm-rpc-stubgen generate` by hand.
+//!
+//! ```no_run
+//! // build.rs
+//! golem_wasm_rpc_stubgen_build::StubGenerator::new("../counter/wit", "../counter-stub")
+//!     .generate()
+//!     .expect("Failed to generate the counter stub");
+//! ```
+//!
+//! Relative paths are resolved against the directory of the crate running the build script. Only the files whose
+//! contents changed are written, so an up-to-date stub does not trigger rebuilds.
+
+use anyhow::{anyhow, Context};
+use golem_wasm_rpc_stubgen::api::{generate_stub, GenerateOptions, NoProgress};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+
+pub use golem_wasm_rpc_stubgen::api::{
+    DependencySource, GeneratedStub, StubCrateDependencies, StubPackageVersion,
+};
+
+/// Generates a stub crate for a component's WIT definition from a build script
+#[derive(Debug, Clone)]
+pub struct StubGenerator {
+    source_wit_root: PathBuf,
+    dest_crate_root: PathBuf,
+    package: Option<String>,
+    world: Option<String>,
+    stub_crate_version: String,
+    stub_package_version: StubPackageVersion,
+    dependencies: StubCrateDependencies,
+    integration_test: bool,
+    rerun_if_changed: bool,
+}
+
+impl StubGenerator {
+    pub fn new(source_wit_root: impl Into<PathBuf>, dest_crate_root: impl Into<PathBuf>) -> Self {
+        Self {
+            source_wit_root: source_wit_root.into(),
+            dest_crate_root: dest_crate_root.into(),
+            package: None,
+            world: None,
+            stub_crate_version: "0.0.1".to_string(),
+            stub_package_version: StubPackageVersion::Same,
+            dependencies: StubCrateDependencies::default(),
+            integration_test: false,
+            rerun_if_changed: true,
+        }
+    }
+
+    /// The package to generate the stub for, if the source WIT root has more than one
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// The world to generate the stub for, if the source package has more than one
+    pub fn world(mut self, world: impl Into<String>) -> Self {
+        self.world = Some(world.into());
+        self
+    }
+
+    pub fn stub_crate_version(mut self, version: impl Into<String>) -> Self {
+        self.stub_crate_version = version.into();
+        self
+    }
+
+    pub fn stub_package_version(mut self, version: StubPackageVersion) -> Self {
+        self.stub_package_version = version;
+        self
+    }
+
+    pub fn wasm_rpc_source(mut self, source: DependencySource) -> Self {
+        self.dependencies.wasm_rpc = source;
+        self
+    }
+
+    pub fn wit_bindgen_source(mut self, source: DependencySource) -> Self {
+        self.dependencies.wit_bindgen = source;
+        self
+    }
+
+    /// Whether to generate an integration test invoking the functions of the stub on the target component
+    pub fn integration_test(mut self, enabled: bool) -> Self {
+        self.integration_test = enabled;
+        self
+    }
+
+    /// Whether to emit `cargo:rerun-if-changed` for the source WIT files, enabled by default
+    pub fn rerun_if_changed(mut self, enabled: bool) -> Self {
+        self.rerun_if_changed = enabled;
+        self
+    }
+
+    /// Generates the stub crate, writing only the files which differ from the destination's current content
+    pub fn generate(&self) -> anyhow::Result<GeneratedStub> {
+        let source_wit_root = resolve_path(&self.source_wit_root);
+        let dest_crate_root = resolve_path(&self.dest_crate_root);
+
+        if self.rerun_if_changed {
+            println!("cargo:rerun-if-changed={}", source_wit_root.display());
+            for file in files_of(&source_wit_root)? {
+                println!("cargo:rerun-if-changed={}", file.display());
+            }
+        }
+
+        let staging = TempDir::new("wasm-rpc-stubgen-build")?;
+        stage_previous_stub(&dest_crate_root, staging.path())?;
+
+        let options = GenerateOptions {
+            package: self.package.clone(),
+            world: self.world.clone(),
+            stub_crate_version: self.stub_crate_version.clone(),
+            stub_package_version: self.stub_package_version.clone(),
+            dependencies: self.dependencies.clone(),
+            integration_test: self.integration_test,
+            ..GenerateOptions::new(source_wit_root, staging.path())
+        };
+        let generated = generate_stub(&options, &NoProgress).map_err(|err| anyhow!(err))?;
+
+        sync_dir(staging.path(), &dest_crate_root)?;
+
+        let relocate = |path: &Path| -> anyhow::Result<PathBuf> {
+            Ok(dest_crate_root.join(path.strip_prefix(staging.path())?))
+        };
+        Ok(GeneratedStub {
+            crate_root: dest_crate_root.clone(),
+            wit_root: relocate(&generated.wit_root)?,
+            stub_wit: relocate(&generated.stub_wit)?,
+            cargo_toml: relocate(&generated.cargo_toml)?,
+            rust_source: relocate(&generated.rust_source)?,
+            integration_test: generated
+                .integration_test
+                .as_deref()
+                .map(relocate)
+                .transpose()?,
+            changes: generated.changes,
+            // Only the staging directory is changed by the generation, the destination is updated by syncing it
+            backup: None,
+        })
+    }
+}
+
+/// Copies the Cargo.toml and the WIT files of the previously generated stub to the staging directory, so the
+/// generation merges into the existing manifest and reports the changes of the stub's interfaces
+fn stage_previous_stub(dest_crate_root: &Path, staging: &Path) -> anyhow::Result<()> {
+    let existing_cargo_toml = dest_crate_root.join("Cargo.toml");
+    if existing_cargo_toml.is_file() {
+        fs::copy(&existing_cargo_toml, staging.join("Cargo.toml"))
+            .context("Failed to stage the existing Cargo.toml")?;
+    }
+    for file in files_of(&dest_crate_root.join("wit"))? {
+        let target = staging.join(file.strip_prefix(dest_crate_root)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&file, &target).context(format!("Failed to stage {file:?}"))?;
+    }
+    Ok(())
+}
+
+/// Resolves relative paths against the directory of the crate being built
+fn resolve_path(path: &Path) -> PathBuf {
+    match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(manifest_dir) if path.is_relative() => PathBuf::from(manifest_dir).join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// All the files under a directory, recursively, in a stable order
+fn files_of(dir: &Path) -> anyhow::Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir).context(format!("Failed to read {dir:?}"))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                files.extend(files_of(&path)?);
+            } else {
+                files.insert(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Copies the changed files of the staged stub crate to the destination and removes the stale WIT files,
+/// returning the written and removed paths
+fn sync_dir(staging: &Path, dest: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+
+    let staged_files = files_of(staging)?;
+    for source in &staged_files {
+        let target = dest.join(source.strip_prefix(staging)?);
+        let content = fs::read(source)?;
+        if fs::read(&target).ok().as_ref() != Some(&content) {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, content).context(format!("Failed to write {target:?}"))?;
+            changed.push(target);
+        }
+    }
+
+    for existing in files_of(&dest.join("wit"))? {
+        if !staged_files.contains(&staging.join(existing.strip_prefix(dest)?)) {
+            fs::remove_file(&existing).context(format!("Failed to remove {existing:?}"))?;
+            changed.push(existing);
+        }
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{sync_dir, StubGenerator};
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn write_counter_wit(root: &TempDir, functions: &str) {
+        fs::write(
+            root.path().join("counter.wit"),
+            format!(
+                "package rpc:counter;\n\ninterface api {{\n{functions}}}\n\nworld counter {{\n  export api;\n}}\n"
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn only_changed_files_are_synced() {
+        let staging = TempDir::new("stubgen-build-test").unwrap();
+        let dest = TempDir::new("stubgen-build-test").unwrap();
+        fs::create_dir_all(staging.path().join("wit")).unwrap();
+        fs::write(staging.path().join("Cargo.toml"), "[package]").unwrap();
+        fs::write(staging.path().join("wit").join("_stub.wit"), "package a:b;").unwrap();
+        fs::create_dir_all(dest.path().join("wit").join("deps")).unwrap();
+        fs::write(dest.path().join("wit").join("deps").join("old.wit"), "").unwrap();
+
+        let changed = sync_dir(staging.path(), dest.path()).unwrap();
+        assert_eq!(changed.len(), 3);
+        assert!(!dest
+            .path()
+            .join("wit")
+            .join("deps")
+            .join("old.wit")
+            .exists());
+        assert_eq!(
+            fs::read_to_string(dest.path().join("wit").join("_stub.wit")).unwrap(),
+            "package a:b;"
+        );
+
+        fs::write(staging.path().join("Cargo.toml"), "[package]\nname = \"x\"").unwrap();
+        let changed = sync_dir(staging.path(), dest.path()).unwrap();
+        assert_eq!(changed, vec![dest.path().join("Cargo.toml")]);
+    }
+
+    #[test]
+    fn regenerated_stubs_report_their_changes_and_integration_test() {
+        let source = TempDir::new("stubgen-build-test").unwrap();
+        let dest = TempDir::new("stubgen-build-test").unwrap();
+        let generator = StubGenerator::new(source.path(), dest.path())
+            .integration_test(true)
+            .rerun_if_changed(false);
+
+        write_counter_wit(&source, "  get: func() -> u64;\n");
+        let generated = generator.generate().unwrap();
+        assert_eq!(
+            generated.integration_test,
+            Some(dest.path().join("tests").join("stub_integration.rs"))
+        );
+        assert!(generated.integration_test.unwrap().is_file());
+        assert!(generated.stub_wit.starts_with(dest.path()));
+        assert!(generated.changes.is_empty());
+        assert_eq!(generated.backup, None);
+
+        write_counter_wit(&source, "  get: func() -> u64;\n  inc: func(by: u64);\n");
+        let generated = generator.generate().unwrap();
+        let changes = generated
+            .changes
+            .iter()
+            .map(|change| (change.function.clone(), change.breaking))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![(Some("[method]api.inc".to_string()), false)]);
+    }
+}
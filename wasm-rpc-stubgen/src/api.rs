@@ -0,0 +1,2355 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Library interface of the stub generator, for build scripts and tools embedding it instead of running the CLI.
+//!
+//! The functions never print: steps are reported to a [Progress] implementation and failures are returned as [Error].
+//! The generate, compile and compose steps are recorded as `tracing` spans, to be timed by a subscriber.
+
+use crate::cargo::generate_cargo_toml;
+use crate::changes::{dir_copy_changes, file_change};
+use crate::compat::{incompatibilities, interface_changes, ComponentTypes, InterfaceChange};
+use crate::compilation::{cache_key, check_toolchain, compile};
+use crate::integration::generate_integration_test;
+use crate::journal::Journal;
+use crate::optimize::{
+    format_size, is_debug_section, is_wasm_opt_installed, optimize_wasm, strip_sections,
+};
+use crate::rust::generate_stub_source;
+use crate::sbom::{sbom_path, Bom};
+use crate::signing::sign;
+use crate::stub::{FunctionConvention, StubDefinition};
+use crate::wac;
+use crate::wit::{copy_wit_files, generate_stub_wit, previous_stub_interfaces, stub_interfaces};
+use cargo_component_core::lock::FileLock;
+use fs_extra::dir::CopyOptions;
+use golem_wasm_ast::analysis::{AnalysedExport, AnalysisContext, AnalysisFailure};
+use golem_wasm_ast::component::Component;
+use golem_wasm_ast::IgnoreAllButMetadata;
+use heck::ToSnakeCase;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempdir::TempDir;
+use tracing::{instrument, warn};
+use wasm_compose::config::{Config, Dependency};
+use wasmparser::{Chunk, CustomSectionReader, Parser, Payload};
+
+pub use crate::cargo::{DependencySource, SharedTypes, StubCrateDependencies};
+pub use crate::changes::{ChangeKind, FileChange};
+pub use crate::compilation::{ToolchainCheck, ToolchainReport};
+pub use crate::progress::{NoProgress, Progress, ProgressEvent};
+pub use crate::signing::{
+    CommandSigner, CommandVerifier, ComponentSignature, Ed25519Signer, Ed25519Verifier, Signer,
+    Verifier,
+};
+pub use crate::stub::StubPackageVersion;
+
+/// Options of [generate_stub]
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /// The root directory of the component's WIT definition to be called via RPC
+    pub source_wit_root: PathBuf,
+    /// The path to generate the stub crate to
+    pub dest_crate_root: PathBuf,
+    /// The package to generate the stub for, if the source WIT root has more than one
+    pub package: Option<String>,
+    /// The world to generate the stub for, if the source package has more than one
+    pub world: Option<String>,
+    pub stub_crate_version: String,
+    pub stub_package_version: StubPackageVersion,
+    pub dependencies: StubCrateDependencies,
+    /// Overwrite an existing Cargo.toml instead of merging into it
+    pub force_manifest: bool,
+    /// Generate an integration test invoking the functions of the stub on the target component
+    pub integration_test: bool,
+    /// Give the stubs a `set-metadata` method, setting the metadata passed with their invocations, like the tracing
+    /// context, auth tokens or tenancy
+    pub metadata: bool,
+    /// The root directory of the WIT definition of an older version of the component. The stubs get a
+    /// `set-target-version` method, and down-convert their calls to the older API when the target has its version.
+    pub older_wit_root: Option<PathBuf>,
+    /// How the stub functions surface the results of the target functions, by `<resource>.<function>` as named in
+    /// the stub WIT
+    pub conventions: BTreeMap<String, FunctionConvention>,
+    /// Keep the interfaces and functions marked `@unstable` in the source WIT in the stub
+    pub include_unstable: bool,
+    /// Fail instead of warning when regenerating the stub breaks the callers of the previously generated one
+    pub deny_breaking: bool,
+    /// Keep the backup of the files the generation changed, they are restored from it only if it fails
+    pub keep_backup: bool,
+}
+
+impl GenerateOptions {
+    pub fn new(source_wit_root: impl Into<PathBuf>, dest_crate_root: impl Into<PathBuf>) -> Self {
+        Self {
+            source_wit_root: source_wit_root.into(),
+            dest_crate_root: dest_crate_root.into(),
+            package: None,
+            world: None,
+            stub_crate_version: "0.0.1".to_string(),
+            stub_package_version: StubPackageVersion::Same,
+            dependencies: StubCrateDependencies::default(),
+            force_manifest: false,
+            integration_test: false,
+            metadata: false,
+            older_wit_root: None,
+            conventions: BTreeMap::new(),
+            include_unstable: false,
+            deny_breaking: false,
+            keep_backup: false,
+        }
+    }
+}
+
+/// Options of [build_stub]
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// The root directory of the component's WIT definition to be called via RPC
+    pub source_wit_root: PathBuf,
+    /// The stub WASM file to be generated
+    pub dest_wasm: PathBuf,
+    /// The directory to place the stub's WIT files in
+    pub dest_wit_root: PathBuf,
+    /// The package to generate the stub for, if the source WIT root has more than one
+    pub package: Option<String>,
+    /// The world to generate the stub for, if the source package has more than one
+    pub world: Option<String>,
+    pub stub_crate_version: String,
+    pub stub_package_version: StubPackageVersion,
+    pub dependencies: StubCrateDependencies,
+    /// Give the stubs a `set-metadata` method, setting the metadata passed with their invocations
+    pub metadata: bool,
+    /// The root directory of the WIT definition of an older version of the component, the stubs down-convert their
+    /// calls to
+    pub older_wit_root: Option<PathBuf>,
+    /// How the stub functions surface the results of the target functions, by `<resource>.<function>` as named in
+    /// the stub WIT
+    pub conventions: BTreeMap<String, FunctionConvention>,
+    /// Keep the interfaces and functions marked `@unstable` in the source WIT in the stub
+    pub include_unstable: bool,
+    /// The cargo target directory to compile the stub in. Builds of several stubs sharing one reuse the compiled
+    /// dependencies, by default each build compiles in its own.
+    pub target_dir: Option<PathBuf>,
+    /// The program rustc is run through when compiling the stub, like `sccache`
+    pub rustc_wrapper: Option<PathBuf>,
+    /// Write a CycloneDX software bill of materials next to the stub WASM, listing the hashes of the source WIT
+    /// files and the versions of the crates it was compiled from
+    pub sbom: bool,
+}
+
+impl BuildOptions {
+    pub fn new(
+        source_wit_root: impl Into<PathBuf>,
+        dest_wasm: impl Into<PathBuf>,
+        dest_wit_root: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            source_wit_root: source_wit_root.into(),
+            dest_wasm: dest_wasm.into(),
+            dest_wit_root: dest_wit_root.into(),
+            package: None,
+            world: None,
+            stub_crate_version: "0.0.1".to_string(),
+            stub_package_version: StubPackageVersion::Same,
+            dependencies: StubCrateDependencies::default(),
+            metadata: false,
+            older_wit_root: None,
+            conventions: BTreeMap::new(),
+            include_unstable: false,
+            target_dir: None,
+            rustc_wrapper: None,
+            sbom: false,
+        }
+    }
+}
+
+/// Options of [compose]
+#[derive(Debug, Clone)]
+pub struct ComposeOptions {
+    /// The component calling other components through stubs
+    pub source_wasm: PathBuf,
+    /// The compiled stubs to plug into the source component
+    pub stub_wasm: Vec<PathBuf>,
+    /// The composed component to be written
+    pub dest_wasm: PathBuf,
+    /// Compose even if a stub satisfies none of the imports of the source component, or an import of a stub package
+    /// is not satisfied by any of the stubs
+    pub allow_unused: bool,
+    /// Skip checking that the composed component has the exports of the source component, and no stub interfaces
+    /// among them
+    pub skip_verify: bool,
+    /// Remove the debug information, like DWARF sections, from the composed component and all the components and
+    /// modules it consists of
+    pub strip_debug: bool,
+    /// Remove all the custom sections from the composed component and all the components and modules it consists of
+    pub strip_custom_sections: bool,
+    /// Optimize the composed component with `wasm-opt`, passing it these arguments
+    pub wasm_opt_args: Option<Vec<String>>,
+    pub engine: ComposeEngine,
+    pub config: ComposeConfig,
+    /// Write a CycloneDX software bill of materials next to the composed component, listing the hashes of the
+    /// components it was composed of
+    pub sbom: bool,
+    /// Sign the composed component, embedding the signature in a custom section, so deployments can check that it
+    /// was not changed after the build
+    pub signer: Option<Arc<dyn Signer>>,
+}
+
+impl ComposeOptions {
+    pub fn new(
+        source_wasm: impl Into<PathBuf>,
+        stub_wasm: Vec<PathBuf>,
+        dest_wasm: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            source_wasm: source_wasm.into(),
+            stub_wasm,
+            dest_wasm: dest_wasm.into(),
+            allow_unused: false,
+            skip_verify: false,
+            strip_debug: false,
+            strip_custom_sections: false,
+            wasm_opt_args: None,
+            engine: ComposeEngine::default(),
+            config: ComposeConfig::default(),
+            sbom: false,
+            signer: None,
+        }
+    }
+}
+
+/// Options of [compose_chain]
+#[derive(Debug, Clone)]
+pub struct ComposeChainOptions {
+    /// The components of the pipeline, in order. The interfaces exported by each component satisfy the imports of
+    /// the next one.
+    pub wasm: Vec<PathBuf>,
+    /// The composed component to be written
+    pub dest_wasm: PathBuf,
+    pub engine: ComposeEngine,
+    /// Write a CycloneDX software bill of materials next to the composed component, see [ComposeOptions::sbom]
+    pub sbom: bool,
+}
+
+/// The library composing the components
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ComposeEngine {
+    /// wasm-compose, configured by the dependencies and the options of [ComposeConfig]
+    #[default]
+    WasmCompose,
+    /// The composition graph of wac, which supports the newer component model features. The dependencies, the
+    /// search paths and the flags of [ComposeConfig] are translated to it, but definitions and instantiations are not
+    /// supported.
+    Wac,
+}
+
+/// Options passed through to `wasm-compose`, for components depending on more than the stubs
+#[derive(Debug, Clone, Default)]
+pub struct ComposeConfig {
+    /// A `wasm-compose` configuration file, for example with instantiation arguments. The stubs are added to its
+    /// dependencies.
+    pub config_file: Option<PathBuf>,
+    /// Components defining additional exports of the composed component
+    pub definitions: Vec<PathBuf>,
+    /// Directories to look for the components of the imports not satisfied by the stubs, by their names
+    pub search_paths: Vec<PathBuf>,
+    /// Fail if an import cannot be satisfied, instead of leaving it as an import of the composed component, like the
+    /// host interfaces
+    pub disallow_imports: bool,
+    /// Import the dependencies as components instead of embedding them
+    pub import_components: bool,
+    pub skip_validation: bool,
+}
+
+/// The files of a generated stub crate
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedStub {
+    pub crate_root: PathBuf,
+    pub wit_root: PathBuf,
+    pub stub_wit: PathBuf,
+    pub cargo_toml: PathBuf,
+    pub rust_source: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integration_test: Option<PathBuf>,
+    /// The changes of the stub's interfaces since the previously generated stub WIT in the destination
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<InterfaceChange>,
+    /// The directory of the kept backup of the changed files, see [GenerateOptions::keep_backup]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+}
+
+/// The results of building a stub
+#[derive(Debug, Clone, Serialize)]
+pub struct BuiltStub {
+    pub wasm: PathBuf,
+    pub wit_root: PathBuf,
+    /// The key of the compiled stub, see [stub_cache_key]
+    pub cache_key: String,
+    /// The software bill of materials written next to the stub WASM, see [BuildOptions::sbom]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sbom: Option<PathBuf>,
+}
+
+/// The result of a composition
+#[derive(Debug, Clone, Serialize)]
+pub struct ComposedComponent {
+    pub wasm: PathBuf,
+    /// The SHA-256 hash of the composed component, in hexadecimal
+    pub sha256: String,
+    #[serde(flatten)]
+    pub stubs: StubMatches,
+    pub sizes: ComposedSizes,
+    /// The software bill of materials written next to the composed component, see [ComposeOptions::sbom]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sbom: Option<PathBuf>,
+    /// The signature embedded in the composed component, see [ComposeOptions::signer]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ComponentSignature>,
+}
+
+/// The size of the composed component in bytes, after each of the requested processing steps
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComposedSizes {
+    pub composed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stripped: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimized: Option<u64>,
+}
+
+impl Display for ComposedSizes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "size: {}", format_size(self.composed))?;
+        if let Some(stripped) = self.stripped {
+            write!(f, ", stripped: {}", format_size(stripped))?;
+        }
+        if let Some(optimized) = self.optimized {
+            write!(f, ", optimized: {}", format_size(optimized))?;
+        }
+        Ok(())
+    }
+}
+
+/// How the stubs match the imports of the component they are composed with
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StubMatches {
+    /// The imports satisfied by the stubs
+    pub dependencies: Vec<ComposedDependency>,
+    /// The stubs exporting none of the imports
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unused_stubs: Vec<PathBuf>,
+    /// The imports of stub packages which none of the stubs export
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unsatisfied_imports: Vec<String>,
+}
+
+impl StubMatches {
+    pub fn is_complete(&self) -> bool {
+        self.unused_stubs.is_empty() && self.unsatisfied_imports.is_empty()
+    }
+}
+
+impl Display for StubMatches {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut rows = vec![("Import".to_string(), "Stub".to_string())];
+        for dependency in &self.dependencies {
+            rows.push((
+                dependency.interface.clone(),
+                dependency.stub_wasm.to_string_lossy().to_string(),
+            ));
+        }
+        for import in &self.unsatisfied_imports {
+            rows.push((import.clone(), "(missing)".to_string()));
+        }
+        for stub_wasm in &self.unused_stubs {
+            rows.push((
+                "(unused)".to_string(),
+                stub_wasm.to_string_lossy().to_string(),
+            ));
+        }
+
+        let width = rows
+            .iter()
+            .map(|(import, _)| import.len())
+            .max()
+            .unwrap_or(0);
+        for (import, stub) in rows {
+            writeln!(f, "{import:<width$}  {stub}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of composing a pipeline of components
+#[derive(Debug, Clone, Serialize)]
+pub struct ComposedChain {
+    pub wasm: PathBuf,
+    /// The SHA-256 hash of the composed component, in hexadecimal
+    pub sha256: String,
+    pub links: Vec<ChainLink>,
+    /// The software bill of materials written next to the composed component, see [ComposeChainOptions::sbom]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sbom: Option<PathBuf>,
+}
+
+/// An interface exported by a component of a pipeline and imported by the next one
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainLink {
+    pub interface: String,
+    pub provider: PathBuf,
+    pub consumer: PathBuf,
+}
+
+/// An interface imported by the composed component, and the stub exporting it
+#[derive(Debug, Clone, Serialize)]
+pub struct ComposedDependency {
+    pub interface: String,
+    pub stub_wasm: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The source WIT root cannot be parsed or resolved
+    InvalidWit(String),
+    /// Writing the stub crate failed
+    Generation(String),
+    /// Tools required for building the stub are missing
+    MissingTools(ToolchainReport),
+    /// Compiling the generated stub crate failed
+    Compilation(String),
+    /// Composing the components failed
+    Composition(String),
+    /// Copying the results to their destination failed
+    Output(String),
+    /// Regenerating the stub breaks the callers of the previously generated one
+    BreakingChanges(Vec<InterfaceChange>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidWit(message) => write!(f, "Invalid source WIT: {message}"),
+            Error::Generation(message) => write!(f, "Failed to generate the stub: {message}"),
+            Error::MissingTools(report) => write!(
+                f,
+                "{report}Required tools are missing, run `wasm-rpc-stubgen check-toolchain --install` to fix them"
+            ),
+            Error::Compilation(message) => {
+                write!(f, "Failed to compile the generated stub: {message}")
+            }
+            Error::Composition(message) => write!(f, "Failed to compose the component: {message}"),
+            Error::Output(message) => write!(f, "Failed to write the results: {message}"),
+            Error::BreakingChanges(changes) => {
+                write!(f, "The regenerated stub has breaking changes:")?;
+                for change in changes.iter().filter(|change| change.breaking) {
+                    write!(f, "\n  {change}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A new temporary directory for one invocation, named after its purpose to tell apart the ones left behind by
+/// concurrent invocations
+fn staging_dir(purpose: &str) -> std::io::Result<TempDir> {
+    TempDir::new(&format!(
+        "wasm-rpc-stubgen-{purpose}-{}",
+        std::process::id()
+    ))
+}
+
+/// Writes an output file by renaming a fully written temporary file next to it, so concurrent invocations writing the
+/// same output never leave it partially written
+fn write_output(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path
+        .file_name()
+        .ok_or(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{path:?} is not a file path"),
+        ))?
+        .to_string_lossy();
+    let temp_path = path.with_file_name(format!(
+        ".{file_name}.{}-{}.tmp",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
+fn error(variant: fn(String) -> Error) -> impl Fn(anyhow::Error) -> Error {
+    move |err| variant(format!("{err:#}"))
+}
+
+/// Generates a Rust stub crate for the component defined by the source WIT root
+pub fn generate_stub(
+    options: &GenerateOptions,
+    progress: &dyn Progress,
+) -> Result<GeneratedStub, Error> {
+    let mut stub_def = stub_definition(
+        &options.source_wit_root,
+        &options.dest_crate_root,
+        &options.package,
+        &options.world,
+        &options.stub_crate_version,
+        &options.stub_package_version,
+        &options.dependencies,
+    )?;
+    stub_def.metadata = options.metadata;
+    stub_def.older_target = older_target(
+        &options.older_wit_root,
+        &stub_def,
+        &options.package,
+        &options.world,
+    )?;
+    if !options.include_unstable {
+        stub_def.exclude_unstable();
+    }
+    stub_def.conventions = options.conventions.clone();
+    stub_def
+        .check_conventions()
+        .map_err(error(Error::Generation))?;
+    let changes = stub_changes(&stub_def)?;
+    if changes.iter().any(|change| change.breaking) {
+        if options.deny_breaking {
+            return Err(Error::BreakingChanges(changes));
+        }
+        for change in changes.iter().filter(|change| change.breaking) {
+            warn!("Breaking change in the regenerated stub: {change}");
+        }
+    }
+
+    // Generation failing halfway restores the destination from the journal when it is dropped
+    let mut journal = Journal::new(&options.dest_crate_root).map_err(error(Error::Output))?;
+    let mut changed = vec![
+        stub_def.target_wit_root(),
+        stub_def.target_cargo_path(),
+        stub_def.target_rust_path(),
+    ];
+    if options.integration_test {
+        changed.push(stub_def.target_integration_test_path());
+    }
+    for path in changed {
+        journal.record(&path).map_err(error(Error::Output))?;
+    }
+    write_stub_crate(
+        &stub_def,
+        options.force_manifest,
+        options.integration_test,
+        progress,
+    )?;
+    let backup = journal.commit(options.keep_backup);
+
+    Ok(GeneratedStub {
+        crate_root: options.dest_crate_root.clone(),
+        wit_root: stub_def.target_wit_root(),
+        stub_wit: stub_def.target_wit_path(),
+        cargo_toml: stub_def.target_cargo_path(),
+        rust_source: stub_def.target_rust_path(),
+        integration_test: options
+            .integration_test
+            .then(|| stub_def.target_integration_test_path()),
+        changes,
+        backup,
+    })
+}
+
+/// The changes of the stub's interfaces since the stub WIT previously generated to its target, if there is one
+fn stub_changes(stub_def: &StubDefinition) -> Result<Vec<InterfaceChange>, Error> {
+    let previous = match previous_stub_interfaces(stub_def) {
+        Ok(Some(previous)) => previous,
+        Ok(None) => return Ok(Vec::new()),
+        Err(err) => {
+            warn!("Cannot compare with the previously generated stub: {err:#}");
+            return Ok(Vec::new());
+        }
+    };
+    let current = stub_interfaces(stub_def).map_err(error(Error::Generation))?;
+    Ok(interface_changes(&previous, &current))
+}
+
+/// The cargo target directory of a stub build: the shared one of the options, or a new one in the build's crate root
+fn stub_target_dir(options: &BuildOptions, crate_root: &Path) -> PathBuf {
+    match &options.target_dir {
+        Some(target_dir) => target_dir.clone(),
+        None => crate_root.join("target"),
+    }
+}
+
+/// Runs the compilation of a stub crate and reads the WASM file it produced. The builds of the same stub crate sharing
+/// a target directory write the same file, so it is locked from the start of the compilation until it is read.
+async fn compile_artifact(
+    target_dir: &Path,
+    crate_name: &str,
+    compilation: impl Future<Output = anyhow::Result<()>>,
+) -> Result<Vec<u8>, Error> {
+    let wasm_path = target_dir
+        .join("wasm32-wasi")
+        .join("release")
+        .join(format!("{}.wasm", crate_name.to_snake_case()));
+    let _lock = FileLock::open_rw(wasm_path.with_extension("wasm.lock"))
+        .map_err(error(Error::Compilation))?;
+    compilation.await.map_err(error(Error::Compilation))?;
+    fs::read(&wasm_path).map_err(|err| {
+        Error::Output(format!(
+            "Failed to read the compiled WASM file {wasm_path:?}: {err}"
+        ))
+    })
+}
+
+/// Generates and compiles a stub for the component defined by the source WIT root
+#[instrument(name = "build", skip_all, fields(source = ?options.source_wit_root))]
+pub async fn build_stub(
+    options: &BuildOptions,
+    progress: &dyn Progress,
+) -> Result<BuiltStub, Error> {
+    let target_root = staging_dir("build").map_err(|err| Error::Generation(err.to_string()))?;
+    let target_dir = stub_target_dir(options, target_root.path());
+
+    let stub_def = write_build_crate(options, target_root.path(), progress)?;
+    let cache_key = cache_key(target_root.path()).map_err(error(Error::Compilation))?;
+
+    let toolchain = check_toolchain();
+    if !toolchain.is_ok() {
+        return Err(Error::MissingTools(toolchain));
+    }
+
+    let crate_name = stub_def
+        .target_crate_name()
+        .map_err(error(Error::Compilation))?;
+    progress.on_event(&ProgressEvent::Compiling {
+        crate_root: target_root.path().to_path_buf(),
+    });
+    let wasm = compile_artifact(
+        &target_dir,
+        &crate_name,
+        compile(
+            target_root.path(),
+            &target_dir,
+            options.rustc_wrapper.as_deref(),
+        ),
+    )
+    .await?;
+
+    progress.on_event(&ProgressEvent::Writing {
+        description: "stub WASM".to_string(),
+        path: options.dest_wasm.clone(),
+    });
+    if let Some(parent) = options.dest_wasm.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            Error::Output(format!(
+                "Failed to create parent directory of the target WASM file: {err}"
+            ))
+        })?;
+    }
+    write_output(&options.dest_wasm, &wasm).map_err(|err| {
+        Error::Output(format!(
+            "Failed to copy the WASM file to the destination: {err}"
+        ))
+    })?;
+    let sbom = if options.sbom {
+        progress.on_event(&ProgressEvent::Writing {
+            description: "software bill of materials".to_string(),
+            path: sbom_path(&options.dest_wasm),
+        });
+        let bom = Bom::stub(
+            &options.dest_wasm,
+            &wasm,
+            &options.source_wit_root,
+            target_root.path(),
+            &crate_name,
+            &cache_key,
+        )
+        .map_err(error(Error::Output))?;
+        Some(
+            bom.write(&options.dest_wasm)
+                .map_err(error(Error::Output))?,
+        )
+    } else {
+        None
+    };
+
+    progress.on_event(&ProgressEvent::Writing {
+        description: "stub WIT".to_string(),
+        path: options.dest_wit_root.clone(),
+    });
+    fs::create_dir_all(&options.dest_wit_root).map_err(|err| {
+        Error::Output(format!(
+            "Failed to create the target WIT root directory: {err}"
+        ))
+    })?;
+    fs_extra::dir::copy(
+        target_root.path().join("wit"),
+        &options.dest_wit_root,
+        &CopyOptions::new().content_only(true).overwrite(true),
+    )
+    .map_err(|err| {
+        Error::Output(format!(
+            "Failed to copy the generated WIT files to the destination: {err}"
+        ))
+    })?;
+
+    Ok(BuiltStub {
+        wasm: options.dest_wasm.clone(),
+        wit_root: options.dest_wit_root.clone(),
+        cache_key,
+        sbom,
+    })
+}
+
+/// The key identifying the stub [build_stub] would compile with the same options, without compiling it: the hash of
+/// the generated stub crate and the Rust toolchain compiling it. The stubs built with the same key are the same, so
+/// CI can cache them by it.
+pub fn stub_cache_key(options: &BuildOptions) -> Result<String, Error> {
+    let staging = staging_dir("cache-key").map_err(|err| Error::Generation(err.to_string()))?;
+    write_build_crate(options, staging.path(), &NoProgress)?;
+    cache_key(staging.path()).map_err(error(Error::Compilation))
+}
+
+/// Generates the stub crate to be compiled by [build_stub] in the given directory
+fn write_build_crate(
+    options: &BuildOptions,
+    target_root: &Path,
+    progress: &dyn Progress,
+) -> Result<StubDefinition, Error> {
+    let mut stub_def = stub_definition(
+        &options.source_wit_root,
+        target_root,
+        &options.package,
+        &options.world,
+        &options.stub_crate_version,
+        &options.stub_package_version,
+        &options.dependencies,
+    )?;
+    stub_def.metadata = options.metadata;
+    stub_def.older_target = older_target(
+        &options.older_wit_root,
+        &stub_def,
+        &options.package,
+        &options.world,
+    )?;
+    if !options.include_unstable {
+        stub_def.exclude_unstable();
+    }
+    stub_def.conventions = options.conventions.clone();
+    stub_def
+        .check_conventions()
+        .map_err(error(Error::Generation))?;
+    write_stub_crate(&stub_def, true, false, progress)?;
+    Ok(stub_def)
+}
+
+/// Composes a component with the stubs it calls other components through
+pub fn compose(
+    options: &ComposeOptions,
+    progress: &dyn Progress,
+) -> Result<ComposedComponent, Error> {
+    let (result, stubs, sizes) = compose_in_memory(options, progress)?;
+    let (result, signature) = match &options.signer {
+        Some(signer) => {
+            let (signed, signature) =
+                sign(&result, signer.as_ref()).map_err(error(Error::Composition))?;
+            (signed, Some(signature))
+        }
+        None => (result, None),
+    };
+
+    progress.on_event(&ProgressEvent::Writing {
+        description: "composed component".to_string(),
+        path: options.dest_wasm.clone(),
+    });
+    write_output(&options.dest_wasm, &result)
+        .map_err(|err| Error::Output(format!("Failed to write the composed component: {err}")))?;
+
+    let sbom = if options.sbom {
+        let parts: Vec<PathBuf> = std::iter::once(options.source_wasm.clone())
+            .chain(options.stub_wasm.iter().cloned())
+            .collect();
+        Some(write_composition_sbom(
+            &options.dest_wasm,
+            &result,
+            &parts,
+            progress,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(ComposedComponent {
+        wasm: options.dest_wasm.clone(),
+        sha256: content_hash(&result),
+        stubs,
+        sizes,
+        sbom,
+        signature,
+    })
+}
+
+/// Composes the component without writing the result, returning the change [compose] would make
+pub fn plan_compose(options: &ComposeOptions) -> Result<Vec<FileChange>, Error> {
+    let (result, _, _) = compose_in_memory(options, &NoProgress)?;
+    Ok(file_change(&options.dest_wasm, &result)
+        .into_iter()
+        .collect())
+}
+
+/// Composes a pipeline of components, linking the imports of each component to the exports of the previous one.
+/// The composed component has the exports of the last component.
+pub fn compose_chain(
+    options: &ComposeChainOptions,
+    progress: &dyn Progress,
+) -> Result<ComposedChain, Error> {
+    let (result, links) = compose_chain_in_memory(options, progress)?;
+
+    progress.on_event(&ProgressEvent::Writing {
+        description: "composed component".to_string(),
+        path: options.dest_wasm.clone(),
+    });
+    write_output(&options.dest_wasm, &result)
+        .map_err(|err| Error::Output(format!("Failed to write the composed component: {err}")))?;
+
+    let sbom = if options.sbom {
+        Some(write_composition_sbom(
+            &options.dest_wasm,
+            &result,
+            &options.wasm,
+            progress,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(ComposedChain {
+        wasm: options.dest_wasm.clone(),
+        sha256: content_hash(&result),
+        links,
+        sbom,
+    })
+}
+
+fn write_composition_sbom(
+    dest_wasm: &Path,
+    composed: &[u8],
+    parts: &[PathBuf],
+    progress: &dyn Progress,
+) -> Result<PathBuf, Error> {
+    progress.on_event(&ProgressEvent::Writing {
+        description: "software bill of materials".to_string(),
+        path: sbom_path(dest_wasm),
+    });
+    Bom::composition(dest_wasm, composed, parts)
+        .and_then(|bom| bom.write(dest_wasm))
+        .map_err(error(Error::Output))
+}
+
+/// Signs a component in place, replacing its previous signature
+pub fn sign_component(wasm: &Path, signer: &dyn Signer) -> Result<ComponentSignature, Error> {
+    let content = fs::read(wasm)
+        .map_err(|err| Error::Composition(format!("Failed to read {wasm:?}: {err}")))?;
+    let (signed, signature) = sign(&content, signer).map_err(error(Error::Composition))?;
+    write_output(wasm, &signed)
+        .map_err(|err| Error::Output(format!("Failed to write the signed component: {err}")))?;
+    Ok(signature)
+}
+
+/// Composes the pipeline without writing the result, returning the change [compose_chain] would make
+pub fn plan_compose_chain(options: &ComposeChainOptions) -> Result<Vec<FileChange>, Error> {
+    let (result, _) = compose_chain_in_memory(options, &NoProgress)?;
+    Ok(file_change(&options.dest_wasm, &result)
+        .into_iter()
+        .collect())
+}
+
+/// Generates the stub into a temporary directory, returning the changes [generate_stub] would make to the
+/// destination
+pub fn plan_stub(options: &GenerateOptions) -> Result<Vec<FileChange>, Error> {
+    let staging = staging_dir("plan").map_err(|err| Error::Generation(err.to_string()))?;
+    let existing_cargo_toml = options.dest_crate_root.join("Cargo.toml");
+    if existing_cargo_toml.is_file() {
+        fs::copy(&existing_cargo_toml, staging.path().join("Cargo.toml")).map_err(|err| {
+            Error::Generation(format!("Failed to stage the existing Cargo.toml: {err}"))
+        })?;
+    }
+
+    let mut stub_def = stub_definition(
+        &options.source_wit_root,
+        staging.path(),
+        &options.package,
+        &options.world,
+        &options.stub_crate_version,
+        &options.stub_package_version,
+        &options.dependencies,
+    )?;
+    stub_def.metadata = options.metadata;
+    stub_def.older_target = older_target(
+        &options.older_wit_root,
+        &stub_def,
+        &options.package,
+        &options.world,
+    )?;
+    if !options.include_unstable {
+        stub_def.exclude_unstable();
+    }
+    stub_def.conventions = options.conventions.clone();
+    stub_def
+        .check_conventions()
+        .map_err(error(Error::Generation))?;
+    write_stub_crate(
+        &stub_def,
+        options.force_manifest,
+        options.integration_test,
+        &NoProgress,
+    )?;
+
+    let changes = dir_copy_changes(staging.path(), &options.dest_crate_root)
+        .map_err(error(Error::Generation))?;
+    Ok(changes
+        .into_iter()
+        .map(|change| {
+            if change.path == existing_cargo_toml
+                && change.kind == ChangeKind::Overwrite
+                && !options.force_manifest
+            {
+                FileChange {
+                    kind: ChangeKind::Modify,
+                    ..change
+                }
+            } else {
+                change
+            }
+        })
+        .collect())
+}
+
+#[instrument(name = "compose", skip_all, fields(source = ?options.source_wasm))]
+fn compose_in_memory(
+    options: &ComposeOptions,
+    progress: &dyn Progress,
+) -> Result<(Vec<u8>, StubMatches, ComposedSizes), Error> {
+    let mut config = wasm_compose_config(&options.config)?;
+    let source_imports = component_imports(&options.source_wasm)?;
+    let source_types = component_types(&options.source_wasm)?;
+    let mut stubs = StubMatches::default();
+    let mut incompatible = Vec::new();
+
+    for stub_wasm in &options.stub_wasm {
+        let stub_bytes = fs::read(stub_wasm)
+            .map_err(|err| Error::Composition(format!("Failed to read {stub_wasm:?}: {err}")))?;
+        let stub_exports = top_level_exports(&stub_bytes, stub_wasm)?;
+        let stub_types =
+            ComponentTypes::new(&stub_bytes, stub_wasm).map_err(error(Error::Composition))?;
+
+        let mut used = false;
+        for export in stub_exports {
+            if let AnalysedExport::Instance(instance) = export {
+                if source_imports.contains(&instance.name) {
+                    used = true;
+                    let issues = incompatibilities(
+                        &source_types
+                            .imported_interface(&instance.name)
+                            .map_err(error(Error::Composition))?,
+                        &stub_types
+                            .exported_interface(&instance.name)
+                            .map_err(error(Error::Composition))?,
+                    );
+                    if !issues.is_empty() {
+                        incompatible.push(format!(
+                            "{} ({stub_wasm:?}):\n{}",
+                            instance.name,
+                            issues.join("\n")
+                        ));
+                    }
+                    stubs.dependencies.push(ComposedDependency {
+                        interface: instance.name.clone(),
+                        stub_wasm: stub_wasm.clone(),
+                    });
+                }
+            }
+        }
+        if !used {
+            stubs.unused_stubs.push(stub_wasm.clone());
+        }
+    }
+    // A stub built from an older version of the target's WIT than the one the source component was built against
+    // would only fail later, with a less helpful message, or when called
+    if !incompatible.is_empty() {
+        return Err(Error::Composition(format!(
+            "The stubs are not compatible with the imports of {:?}, regenerate and rebuild them:\n{}",
+            options.source_wasm,
+            incompatible.join("\n")
+        )));
+    }
+    // The order of the dependencies determines the order of the instances in the result, so it must not depend on
+    // the order the stubs are listed in
+    stubs
+        .dependencies
+        .sort_by(|a, b| a.interface.cmp(&b.interface));
+    for dependency in &stubs.dependencies {
+        progress.on_event(&ProgressEvent::Composing {
+            interface: dependency.interface.clone(),
+            stub_wasm: dependency.stub_wasm.clone(),
+        });
+        config.dependencies.insert(
+            dependency.interface.clone(),
+            Dependency {
+                path: absolute_path(&dependency.stub_wasm)?,
+            },
+        );
+    }
+    stubs.unsatisfied_imports = source_imports
+        .into_iter()
+        .filter(|import| {
+            is_stub_import(import)
+                && !stubs
+                    .dependencies
+                    .iter()
+                    .any(|dependency| &dependency.interface == import)
+        })
+        .collect();
+
+    if !stubs.is_complete() {
+        if options.allow_unused {
+            warn!(
+                "The stubs do not match the imports of {:?}:\n{stubs}",
+                options.source_wasm
+            );
+        } else {
+            return Err(Error::Composition(format!(
+                "The stubs do not match the imports of {:?}, use `--allow-unused` to compose anyway:\n{stubs}",
+                options.source_wasm
+            )));
+        }
+    }
+
+    let result = compose_with(options.engine, &options.source_wasm, &config)?;
+    let mut result = strip_custom_sections(&result, UNSTABLE_CUSTOM_SECTIONS)?;
+    let mut sizes = ComposedSizes {
+        composed: result.len() as u64,
+        ..ComposedSizes::default()
+    };
+
+    if options.strip_debug || options.strip_custom_sections {
+        let strip_all = options.strip_custom_sections;
+        result = strip_sections(&result, &|name| strip_all || is_debug_section(name))
+            .map_err(error(Error::Composition))?;
+        sizes.stripped = Some(result.len() as u64);
+    }
+    if let Some(wasm_opt_args) = &options.wasm_opt_args {
+        if is_wasm_opt_installed() {
+            result = optimize_wasm(&result, wasm_opt_args).map_err(error(Error::Composition))?;
+            sizes.optimized = Some(result.len() as u64);
+        } else {
+            warn!("wasm-opt is not installed, skipping the optimization of the composed component. It can be installed from https://github.com/WebAssembly/binaryen");
+        }
+    }
+
+    if !options.skip_verify {
+        verify_composed(&options.source_wasm, &result, &stubs)?;
+    }
+    Ok((result, stubs, sizes))
+}
+
+#[instrument(name = "compose_chain", skip_all, fields(dest = ?options.dest_wasm))]
+fn compose_chain_in_memory(
+    options: &ComposeChainOptions,
+    progress: &dyn Progress,
+) -> Result<(Vec<u8>, Vec<ChainLink>), Error> {
+    if options.wasm.len() < 2 {
+        return Err(Error::Composition(
+            "At least two components are needed for a pipeline".to_string(),
+        ));
+    }
+    let first = &options.wasm[0];
+
+    let staging = staging_dir("compose").map_err(|err| Error::Composition(err.to_string()))?;
+    // Each component is composed with the result of composing the ones before it, which has the exports of the
+    // previous component
+    let mut composed_path = absolute_path(first)?;
+    let mut composed = fs::read(first)
+        .map_err(|err| Error::Composition(format!("Failed to read {first:?}: {err}")))?;
+    let mut links = Vec::new();
+
+    for (index, consumer) in options.wasm[1..].iter().enumerate() {
+        let provider = &options.wasm[index];
+        let provider_types =
+            ComponentTypes::new(&composed, provider).map_err(error(Error::Composition))?;
+        let consumer_imports = component_imports(consumer)?;
+        let consumer_types = component_types(consumer)?;
+
+        let interfaces =
+            linked_interfaces(top_level_exports(&composed, provider)?, &consumer_imports);
+        if interfaces.is_empty() {
+            return Err(Error::Composition(format!(
+                "{consumer:?} imports none of the interfaces exported by {provider:?}"
+            )));
+        }
+
+        let mut config = Config::default();
+        let mut incompatible = Vec::new();
+        for interface in interfaces {
+            let issues = incompatibilities(
+                &consumer_types
+                    .imported_interface(&interface)
+                    .map_err(error(Error::Composition))?,
+                &provider_types
+                    .exported_interface(&interface)
+                    .map_err(error(Error::Composition))?,
+            );
+            if !issues.is_empty() {
+                incompatible.push(format!("{interface}:\n{}", issues.join("\n")));
+            }
+            progress.on_event(&ProgressEvent::Composing {
+                interface: interface.clone(),
+                stub_wasm: provider.clone(),
+            });
+            config.dependencies.insert(
+                interface.clone(),
+                Dependency {
+                    path: composed_path.clone(),
+                },
+            );
+            links.push(ChainLink {
+                interface,
+                provider: provider.clone(),
+                consumer: consumer.clone(),
+            });
+        }
+        if !incompatible.is_empty() {
+            return Err(Error::Composition(format!(
+                "The exports of {provider:?} are not compatible with the imports of {consumer:?}:\n{}",
+                incompatible.join("\n")
+            )));
+        }
+
+        composed = compose_with(options.engine, consumer, &config)?;
+        composed_path = staging.path().join(format!("stage-{}.wasm", index + 1));
+        fs::write(&composed_path, &composed).map_err(|err| {
+            Error::Composition(format!("Failed to write {composed_path:?}: {err}"))
+        })?;
+    }
+
+    let result = strip_custom_sections(&composed, UNSTABLE_CUSTOM_SECTIONS)?;
+    Ok((result, links))
+}
+
+fn compose_with(
+    engine: ComposeEngine,
+    source_wasm: &Path,
+    config: &Config,
+) -> Result<Vec<u8>, Error> {
+    match engine {
+        ComposeEngine::WasmCompose => {
+            wasm_compose::composer::ComponentComposer::new(source_wasm, config).compose()
+        }
+        ComposeEngine::Wac => wac::compose(source_wasm, config),
+    }
+    .map_err(error(Error::Composition))
+}
+
+/// The interfaces exported by a component of a pipeline which the next one imports, sorted by name
+fn linked_interfaces(exports: Vec<AnalysedExport>, consumer_imports: &[String]) -> Vec<String> {
+    let mut interfaces = exports
+        .into_iter()
+        .filter_map(|export| match export {
+            AnalysedExport::Instance(instance) if consumer_imports.contains(&instance.name) => {
+                Some(instance.name)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    interfaces.sort();
+    interfaces
+}
+
+fn top_level_exports(bytes: &[u8], path: &Path) -> Result<Vec<AnalysedExport>, Error> {
+    let component = Component::<IgnoreAllButMetadata>::from_bytes(bytes)
+        .map_err(|err| Error::Composition(format!("Failed to parse {path:?}: {err}")))?;
+    let state = AnalysisContext::new(component);
+    state.get_top_level_exports().map_err(|err| match err {
+        AnalysisFailure::Failed(msg) => Error::Composition(msg),
+    })
+}
+
+fn export_name(export: &AnalysedExport) -> &str {
+    match export {
+        AnalysedExport::Function(function) => &function.name,
+        AnalysedExport::Instance(instance) => &instance.name,
+    }
+}
+
+/// Checks that composing kept all the exports of the source component, and did not make the stubs' interfaces
+/// exports of the result, which would only fail when the component is deployed
+fn verify_composed(source_wasm: &Path, composed: &[u8], stubs: &StubMatches) -> Result<(), Error> {
+    let source = fs::read(source_wasm)
+        .map_err(|err| Error::Composition(format!("Failed to read {source_wasm:?}: {err}")))?;
+    let source_exports = top_level_exports(&source, source_wasm)?;
+    let composed_exports = top_level_exports(composed, Path::new("the composed component"))?;
+
+    let issues = export_issues(
+        &source_exports.iter().map(export_name).collect::<Vec<_>>(),
+        &composed_exports.iter().map(export_name).collect::<Vec<_>>(),
+        stubs,
+    );
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Composition(format!(
+            "The composed component does not match {source_wasm:?}:\n{}",
+            issues.join("\n")
+        )))
+    }
+}
+
+fn export_issues(
+    source_exports: &[&str],
+    composed_exports: &[&str],
+    stubs: &StubMatches,
+) -> Vec<String> {
+    let mut issues = Vec::new();
+    for name in source_exports {
+        if !composed_exports.contains(name) {
+            issues.push(format!("  missing export: {name}"));
+        }
+    }
+    for name in composed_exports {
+        let is_stub_interface = is_stub_import(name)
+            || stubs
+                .dependencies
+                .iter()
+                .any(|dependency| dependency.interface == *name);
+        if is_stub_interface {
+            issues.push(format!("  stub interface exported: {name}"));
+        }
+    }
+    issues
+}
+
+/// Custom sections of the composed component which differ between builds of the same inputs, such as `producers`
+/// recording the versions of the tools the component was composed with
+const UNSTABLE_CUSTOM_SECTIONS: &[&str] = &["producers"];
+
+/// Removes the top level custom sections with the given names. The sections of the nested modules and components
+/// are kept, as they are part of the inputs.
+fn strip_custom_sections(component: &[u8], names: &[&str]) -> Result<Vec<u8>, Error> {
+    let parse_error = |err: wasmparser::BinaryReaderError| {
+        Error::Composition(format!("Failed to parse the composed component: {err}"))
+    };
+    let mut parser = Parser::new(0);
+    let mut result = Vec::with_capacity(component.len());
+    let mut offset = 0;
+    loop {
+        let (payload, consumed) = match parser
+            .parse(&component[offset..], true)
+            .map_err(parse_error)?
+        {
+            Chunk::Parsed { payload, consumed } => (payload, consumed),
+            Chunk::NeedMoreData(_) => {
+                return Err(Error::Composition(
+                    "Unexpected end of the composed component".to_string(),
+                ))
+            }
+        };
+        let section_len = match &payload {
+            Payload::ModuleSection { range, .. } | Payload::ComponentSection { range, .. } => {
+                consumed + range.len()
+            }
+            _ => consumed,
+        };
+        match payload {
+            Payload::End(_) => break,
+            Payload::CustomSection(section) if names.contains(&section.name()) => {}
+            _ => result.extend_from_slice(&component[offset..offset + section_len]),
+        }
+        offset += section_len;
+    }
+    Ok(result)
+}
+
+/// The SHA-256 hash of a file's content, in hexadecimal
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+fn component_types(path: &Path) -> Result<ComponentTypes, Error> {
+    let bytes = fs::read(path)
+        .map_err(|err| Error::Composition(format!("Failed to read {path:?}: {err}")))?;
+    ComponentTypes::new(&bytes, path).map_err(error(Error::Composition))
+}
+
+/// The names of the top level imports of a component
+fn component_imports(path: &Path) -> Result<Vec<String>, Error> {
+    let bytes = fs::read(path)
+        .map_err(|err| Error::Composition(format!("Failed to read {path:?}: {err}")))?;
+    top_level_imports(&bytes, |_| Ok(()))
+        .map_err(|err| Error::Composition(format!("Failed to parse {path:?}: {err:#}")))
+}
+
+/// The names of the top level imports of a component, passing its top level custom sections to `custom_section`
+pub(crate) fn top_level_imports(
+    bytes: &[u8],
+    mut custom_section: impl FnMut(&CustomSectionReader) -> anyhow::Result<()>,
+) -> anyhow::Result<Vec<String>> {
+    let mut parser = Parser::new(0);
+    let mut offset = 0;
+    let mut imports = Vec::new();
+    loop {
+        let payload = match parser.parse(&bytes[offset..], true)? {
+            Chunk::Parsed { payload, consumed } => {
+                offset += consumed;
+                payload
+            }
+            Chunk::NeedMoreData(_) => anyhow::bail!("Unexpected end of the component"),
+        };
+        match payload {
+            // The imports of nested modules and components are satisfied within the component, and their custom
+            // sections describe them
+            Payload::ModuleSection { range, .. } | Payload::ComponentSection { range, .. } => {
+                offset += range.len();
+            }
+            Payload::ComponentImportSection(reader) => {
+                for import in reader {
+                    imports.push(import?.name.0.to_string());
+                }
+            }
+            Payload::CustomSection(section) => custom_section(&section)?,
+            Payload::End(_) => break,
+            _ => {}
+        }
+    }
+    Ok(imports)
+}
+
+/// Whether an imported interface, like `rpc:counter-stub/stub-counter@0.0.1`, belongs to a generated stub package
+pub(crate) fn is_stub_import(name: &str) -> bool {
+    let package = name.split('/').next().unwrap_or_default();
+    let package = package.split('@').next().unwrap_or_default();
+    package.ends_with("-stub")
+}
+
+fn wasm_compose_config(options: &ComposeConfig) -> Result<Config, Error> {
+    let mut config = match &options.config_file {
+        Some(config_file) => Config::from_file(config_file).map_err(|err| {
+            Error::Composition(format!("Failed to read {config_file:?}: {err:#}"))
+        })?,
+        None => Config::default(),
+    };
+    // The paths of the configuration file are relative to its directory, so the other ones are made absolute
+    for definition in &options.definitions {
+        config.definitions.push(absolute_path(definition)?);
+    }
+    for search_path in &options.search_paths {
+        config.search_paths.push(absolute_path(search_path)?);
+    }
+    config.disallow_imports |= options.disallow_imports;
+    config.import_components |= options.import_components;
+    config.skip_validation |= options.skip_validation;
+    Ok(config)
+}
+
+fn absolute_path(path: &Path) -> Result<PathBuf, Error> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        let current_dir = std::env::current_dir().map_err(|err| {
+            Error::Composition(format!("Failed to get the current directory: {err}"))
+        })?;
+        Ok(current_dir.join(path))
+    }
+}
+
+fn stub_definition(
+    source_wit_root: &std::path::Path,
+    target_root: &std::path::Path,
+    package: &Option<String>,
+    world: &Option<String>,
+    stub_crate_version: &str,
+    stub_package_version: &StubPackageVersion,
+    dependencies: &StubCrateDependencies,
+) -> Result<StubDefinition, Error> {
+    StubDefinition::new(
+        source_wit_root,
+        target_root,
+        package,
+        world,
+        stub_crate_version,
+        stub_package_version,
+        dependencies,
+    )
+    .map_err(error(Error::InvalidWit))
+}
+
+/// The definition of the older version of the target, the stubs down-convert their calls to
+fn older_target(
+    older_wit_root: &Option<PathBuf>,
+    stub_def: &StubDefinition,
+    package: &Option<String>,
+    world: &Option<String>,
+) -> Result<Option<Box<StubDefinition>>, Error> {
+    let Some(older_wit_root) = older_wit_root else {
+        return Ok(None);
+    };
+    let older = stub_definition(
+        older_wit_root,
+        &stub_def.target_root,
+        package,
+        world,
+        &stub_def.stub_crate_version,
+        &StubPackageVersion::Same,
+        &stub_def.dependencies,
+    )?;
+    if older.root_package_name.version.is_none() {
+        return Err(Error::InvalidWit(format!(
+            "The package {} of the older WIT definition has no version",
+            older.root_package_name
+        )));
+    }
+    Ok(Some(Box::new(older)))
+}
+
+#[instrument(name = "generate", skip_all, fields(dest = ?stub_def.target_root))]
+fn write_stub_crate(
+    stub_def: &StubDefinition,
+    force_manifest: bool,
+    integration_test: bool,
+    progress: &dyn Progress,
+) -> Result<(), Error> {
+    let generation = |context: &'static str| {
+        move |err: anyhow::Error| Error::Generation(format!("{context}: {err:#}"))
+    };
+
+    generate_stub_wit(stub_def, progress)
+        .map_err(generation("Failed to generate the stub wit file"))?;
+    copy_wit_files(stub_def, progress)
+        .map_err(generation("Failed to copy the dependent wit files"))?;
+    stub_def
+        .verify_target_wits()
+        .map_err(error(Error::InvalidWit))?;
+    generate_cargo_toml(stub_def, force_manifest, integration_test, progress)
+        .map_err(generation("Failed to generate the Cargo.toml file"))?;
+    generate_stub_source(stub_def, progress)
+        .map_err(generation("Failed to generate the stub Rust source"))?;
+    if integration_test {
+        generate_integration_test(stub_def, progress)
+            .map_err(generation("Failed to generate the integration test"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{
+        compile_artifact, component_imports, content_hash, export_issues, generate_stub,
+        is_stub_import, linked_interfaces, plan_compose_chain, strip_custom_sections,
+        stub_cache_key, stub_target_dir, wasm_compose_config, write_output, BuildOptions,
+        ComposeChainOptions, ComposeConfig, ComposeEngine, Error, GenerateOptions, NoProgress,
+        ProgressEvent, StubMatches,
+    };
+    use crate::cargo::DependencySource;
+    use crate::stub::{FunctionConvention, RpcErrors};
+    use golem_wasm_ast::analysis::{AnalysedExport, AnalysedFunction, AnalysedInstance};
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use std::process::Command;
+    use tempdir::TempDir;
+
+    #[test]
+    fn generate_stub_reports_progress_and_outputs() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let events = RefCell::new(Vec::new());
+        let progress = |event: &ProgressEvent| events.borrow_mut().push(event.clone());
+
+        let generated =
+            generate_stub(&GenerateOptions::new("example", target.path()), &progress).unwrap();
+
+        assert!(generated.stub_wit.is_file());
+        assert!(generated.cargo_toml.is_file());
+        assert!(generated.rust_source.is_file());
+        let source = std::fs::read_to_string(&generated.rust_source).unwrap();
+        assert!(
+            source.contains(".bytes(&data)"),
+            "lists of bytes are not built as bytes"
+        );
+        assert!(
+            source.contains("list.bytes()"),
+            "lists of bytes are not read as bytes"
+        );
+        assert!(events.borrow().contains(&ProgressEvent::Generating {
+            description: "Cargo.toml".to_string(),
+            path: generated.cargo_toml.clone(),
+        }));
+    }
+
+    #[test]
+    fn integration_test_invokes_every_function_of_the_stub() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new("example", target.path());
+        options.integration_test = true;
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let test_path = generated.integration_test.unwrap();
+        let test = std::fs::read_to_string(test_path).unwrap();
+        syn::parse_file(&test).unwrap();
+        for function in [
+            "rpc_name: \"test:main/iface1/add\"",
+            "function: \"[constructor]cart\"",
+            "function: \"[method]cart.add-item\"",
+            "function: \"[static]cart.merge\"",
+            "interface: None",
+        ] {
+            assert!(test.contains(function), "{function} is not invoked");
+        }
+        let manifest: toml::Value =
+            toml::from_str(&std::fs::read_to_string(generated.cargo_toml).unwrap()).unwrap();
+        assert_eq!(
+            manifest["dev-dependencies"]["golem-wasm-rpc-guest"]["features"]
+                .as_array()
+                .unwrap()[0]
+                .as_str(),
+            Some("integration-test")
+        );
+    }
+
+    #[test]
+    fn generated_integration_test_compiles() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let guest = Path::new(env!("CARGO_MANIFEST_DIR")).join("../wasm-rpc-guest");
+        let mut options = GenerateOptions::new("example", target.path());
+        options.integration_test = true;
+        options.dependencies.wasm_rpc =
+            DependencySource::Path(guest.canonicalize().unwrap().to_string_lossy().to_string());
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        // The stub library itself only builds with cargo-component, so the test is compiled in a host crate with
+        // the dev-dependencies of the generated manifest
+        let manifest: toml::Value =
+            toml::from_str(&std::fs::read_to_string(generated.cargo_toml).unwrap()).unwrap();
+        let check = TempDir::new("stubgen-test").unwrap();
+        std::fs::write(check.path().join("lib.rs"), "").unwrap();
+        let test_path = generated.integration_test.unwrap();
+        let mut check_manifest: toml::Table = toml::from_str(&format!(
+            r#"
+            [package]
+            name = "integration-test-check"
+            version = "0.0.0"
+            edition = "2021"
+
+            [lib]
+            path = "lib.rs"
+
+            [[test]]
+            name = "integration"
+            path = {}
+
+            [workspace]
+            "#,
+            toml::Value::String(test_path.to_string_lossy().to_string())
+        ))
+        .unwrap();
+        check_manifest.insert(
+            "dev-dependencies".to_string(),
+            manifest["dev-dependencies"].clone(),
+        );
+        std::fs::write(
+            check.path().join("Cargo.toml"),
+            toml::to_string(&check_manifest).unwrap(),
+        )
+        .unwrap();
+        // Resolving the dependencies from scratch could pick newer, incompatible releases than the workspace does
+        let lock = Path::new(env!("CARGO_MANIFEST_DIR")).join("../Cargo.lock");
+        if lock.exists() {
+            std::fs::copy(lock, check.path().join("Cargo.lock")).unwrap();
+        }
+
+        let output = Command::new(std::env::var("CARGO").unwrap_or("cargo".to_string()))
+            .args(["check", "--tests", "--manifest-path"])
+            .arg(check.path().join("Cargo.toml"))
+            .env(
+                "CARGO_TARGET_DIR",
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/integration-test-check"),
+            )
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn stubs_with_metadata_pass_it_with_every_invocation() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new("example", target.path());
+        options.metadata = true;
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let wit = std::fs::read_to_string(generated.stub_wit).unwrap();
+        assert!(wit.contains("set-metadata: func(metadata: list<tuple<string, string>>);"));
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        syn::parse_file(&source).unwrap();
+        let source: String = source.split_whitespace().collect();
+        assert!(source.contains("fnset_metadata(&self,metadata:Vec<(String,String)>)"));
+        assert!(source.contains("&self.metadata.borrow()"));
+        assert!(!source.contains(".invoke_and_await("));
+    }
+
+    #[test]
+    fn parameters_are_used_without_the_interface_feature() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(
+            source.path(),
+            "",
+            "  get: func(name: string, count: u32) -> u32;\n  reset: func();\n",
+        );
+        let target = TempDir::new("stubgen-test").unwrap();
+        let options = GenerateOptions::new(source.path(), target.path());
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        syn::parse_file(&source).unwrap();
+        let source: String = source.split_whitespace().collect();
+        assert!(source.contains(
+            "fnget(&self,name:String,count:u32)->u32{#[cfg(feature=\"iface-api\")]{letresult=self.rpc.invoke_and_await("
+        ));
+        assert!(source.contains(
+            "#[cfg(not(feature=\"iface-api\"))]{let_=(name,count);panic!(\"Callingtest:versioned/api/get"
+        ));
+        assert!(source.contains(
+            "#[cfg(not(feature=\"iface-api\"))]{panic!(\"Callingtest:versioned/api/reset"
+        ));
+    }
+
+    #[test]
+    fn interface_features_do_not_clash_with_reserved_features() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        std::fs::write(
+            source.path().join("reserved.wit"),
+            "package test:reserved;\n\ninterface default {\n  get: func() -> u32;\n}\n\nworld reserved {\n  export default;\n}\n",
+        )
+        .unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+        let options = GenerateOptions::new(source.path(), target.path());
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let manifest: toml::Value =
+            toml::from_str(&std::fs::read_to_string(generated.cargo_toml).unwrap()).unwrap();
+        let features = manifest["features"].as_table().unwrap();
+        assert_eq!(
+            features["default"].as_array().unwrap(),
+            &vec![toml::Value::from("iface-default")]
+        );
+        assert!(features["iface-default"].as_array().unwrap().is_empty());
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        let source: String = source.split_whitespace().collect();
+        assert!(source.contains("#[cfg(feature=\"iface-default\")]"));
+    }
+
+    fn write_versioned_wit(root: &std::path::Path, version: &str, functions: &str) {
+        std::fs::write(
+            root.join("versioned.wit"),
+            format!(
+                "package test:versioned{version};\n\ninterface api {{\n  record item {{ name: string }}\n\n{functions}}}\n\nworld versioned {{\n  export api;\n}}\n"
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn stubs_down_convert_the_calls_to_the_older_target() {
+        let older = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(
+            older.path(),
+            "@0.1.0",
+            "  add: func(item: item, tag: option<string>) -> u32;\n  get: func(name: string) -> u32;\n  reset: func(force: bool);\n",
+        );
+        let newer = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(
+            newer.path(),
+            "@0.2.0",
+            "  add: func(item: item, quantity: option<u32>) -> u32;\n  get: func(name: string) -> u32;\n  reset: func(force: bool, reason: string);\n  clear: func();\n",
+        );
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new(newer.path(), target.path());
+        options.older_wit_root = Some(older.path().to_path_buf());
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let wit = std::fs::read_to_string(generated.stub_wit).unwrap();
+        assert!(wit.contains("set-target-version: func(version: string);"));
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        syn::parse_file(&source).unwrap();
+        for expected in [
+            "self.older_target.set(version == \"0.1.0\")",
+            "WitValue::builder().option_none()",
+            "version 0.1.0 of the target: parameter reason was added",
+            "version 0.1.0 of the target: the function does not exist",
+        ] {
+            assert!(source.contains(expected), "{expected} is not generated");
+        }
+        assert_eq!(source.matches("if self.older_target.get()").count(), 3);
+    }
+
+    #[test]
+    fn the_older_target_must_have_a_version() {
+        let older = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(older.path(), "", "  get: func(name: string) -> u32;\n");
+        let newer = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(
+            newer.path(),
+            "@0.2.0",
+            "  get: func(name: string) -> u32;\n",
+        );
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new(newer.path(), target.path());
+        options.older_wit_root = Some(older.path().to_path_buf());
+
+        assert!(matches!(
+            generate_stub(&options, &NoProgress),
+            Err(Error::InvalidWit(_))
+        ));
+    }
+
+    #[test]
+    fn stubs_are_grouped_into_a_module_per_interface() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        std::fs::write(
+            source.path().join("modules.wit"),
+            "package test:modules;\n\n\
+             interface prelude {\n  resource counter {\n    constructor();\n    get: func() -> u64;\n  }\n  \
+             merge: func(a: borrow<counter>) -> counter;\n}\n\n\
+             interface admin-api {\n  reset: func();\n}\n\n\
+             world modules {\n  export prelude;\n  export admin-api;\n}\n",
+        )
+        .unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+
+        let generated = generate_stub(
+            &GenerateOptions::new(source.path(), target.path()),
+            &NoProgress,
+        )
+        .unwrap();
+
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        let file = syn::parse_file(&source).unwrap();
+        let modules = file
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                syn::Item::Mod(module) => Some(module.ident.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            modules,
+            vec!["bindings", "prelude_interface", "admin_api", "prelude"]
+        );
+        let source: String = source.split_whitespace().collect();
+        assert!(source.contains("pubmodprelude_interface{usesuper::*;pubstructPrelude{"));
+        assert!(source.contains("pubstructCounter{"));
+        assert!(source.contains("pubmodadmin_api{usesuper::*;pubstructAdminApi{"));
+        assert!(source.contains(
+            "pubmodprelude{pubusesuper::prelude_interface::*;pubusesuper::admin_api::*;}pubuseprelude::*;"
+        ));
+        assert!(source.contains("crate::Counter"));
+    }
+
+    #[test]
+    fn regenerating_a_stub_reports_its_changes() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(
+            source.path(),
+            "@0.1.0",
+            "  add: func(item: item) -> u32;\n  get: func(name: string) -> u32;\n",
+        );
+        let target = TempDir::new("stubgen-test").unwrap();
+        let options = GenerateOptions::new(source.path(), target.path());
+        assert!(generate_stub(&options, &NoProgress)
+            .unwrap()
+            .changes
+            .is_empty());
+        assert!(generate_stub(&options, &NoProgress)
+            .unwrap()
+            .changes
+            .is_empty());
+
+        write_versioned_wit(
+            source.path(),
+            "@0.1.0",
+            "  add: func(item: item, quantity: u32) -> u32;\n  clear: func();\n",
+        );
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let changes = generated
+            .changes
+            .iter()
+            .map(|change| (change.function.as_deref().unwrap(), change.breaking))
+            .collect::<Vec<_>>();
+        assert!(changes.contains(&("[method]api.add", true)), "{changes:?}");
+        assert!(changes.contains(&("[method]api.get", true)), "{changes:?}");
+        assert!(
+            changes.contains(&("[method]api.clear", false)),
+            "{changes:?}"
+        );
+    }
+
+    #[test]
+    fn breaking_changes_can_be_denied() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(source.path(), "", "  get: func(name: string) -> u32;\n");
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new(source.path(), target.path());
+        options.deny_breaking = true;
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+        let previous = std::fs::read_to_string(&generated.stub_wit).unwrap();
+
+        write_versioned_wit(source.path(), "", "  get: func(name: string) -> u64;\n");
+
+        assert!(matches!(
+            generate_stub(&options, &NoProgress),
+            Err(Error::BreakingChanges(_))
+        ));
+        assert_eq!(
+            std::fs::read_to_string(&generated.stub_wit).unwrap(),
+            previous
+        );
+    }
+
+    #[test]
+    fn conventions_flatten_the_results_and_return_the_rpc_errors() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(
+            source.path(),
+            "",
+            "  get: func(name: string) -> result<u32, string>;\n  find: func(name: string) -> option<item>;\n  reset: func();\n",
+        );
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new(source.path(), target.path());
+        options.conventions = BTreeMap::from([
+            (
+                "api.get".to_string(),
+                FunctionConvention {
+                    flatten_result: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "api.find".to_string(),
+                FunctionConvention {
+                    flatten_option: true,
+                    rpc_errors: RpcErrors::Result,
+                    ..Default::default()
+                },
+            ),
+            (
+                "api.reset".to_string(),
+                FunctionConvention {
+                    rpc_errors: RpcErrors::Result,
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let wit = std::fs::read_to_string(&generated.stub_wit).unwrap();
+        assert!(
+            wit.contains("use golem:rpc/types@0.2.0.{uri, rpc-error};"),
+            "{wit}"
+        );
+        assert!(wit.contains("get: func(name: string) -> u32;"), "{wit}");
+        assert!(
+            wit.contains("find: func(name: string) -> result<item, rpc-error>;"),
+            "{wit}"
+        );
+        assert!(
+            wit.contains("reset: func() -> result<_, rpc-error>;"),
+            "{wit}"
+        );
+        wit_parser::Resolve::new()
+            .push_dir(&generated.wit_root)
+            .unwrap();
+
+        let source = std::fs::read_to_string(&generated.rust_source).unwrap();
+        assert!(source.contains("returned an error"), "{source}");
+        assert!(source.contains("returned none"), "{source}");
+        assert!(source.contains("RpcError::RemoteInternalError"), "{source}");
+    }
+
+    #[test]
+    fn conventions_must_fit_the_functions() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        write_versioned_wit(source.path(), "", "  get: func(name: string) -> u32;\n");
+        let target = TempDir::new("stubgen-test").unwrap();
+        let flatten_result = FunctionConvention {
+            flatten_result: true,
+            ..Default::default()
+        };
+
+        let mut options = GenerateOptions::new(source.path(), target.path());
+        options.conventions = BTreeMap::from([("api.get".to_string(), flatten_result.clone())]);
+        assert!(matches!(
+            generate_stub(&options, &NoProgress),
+            Err(Error::Generation(_))
+        ));
+
+        options.conventions = BTreeMap::from([("api.put".to_string(), flatten_result)]);
+        assert!(matches!(
+            generate_stub(&options, &NoProgress),
+            Err(Error::Generation(_))
+        ));
+    }
+
+    const GATED_WIT: &str = r#"
+package test:gated@0.2.0;
+
+@since(version = 0.1.0)
+interface api {
+  @since(version = 0.1.0)
+  get: func(name: string) -> u32;
+
+  @unstable(feature = bulk)
+  get-all: func() -> list<u32>;
+}
+
+@unstable(feature = admin)
+interface admin {
+  wipe: func();
+}
+
+world gated {
+  @since(version = 0.2.0)
+  export api;
+  export admin;
+}
+"#;
+
+    #[test]
+    fn unstable_items_are_excluded_from_stubs() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        std::fs::write(source.path().join("gated.wit"), GATED_WIT).unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+
+        let generated = generate_stub(
+            &GenerateOptions::new(source.path(), target.path()),
+            &NoProgress,
+        )
+        .unwrap();
+
+        let wit = std::fs::read_to_string(&generated.stub_wit).unwrap();
+        assert!(wit.contains("get: func(name: string) -> u32;"), "{wit}");
+        assert!(!wit.contains("get-all"), "{wit}");
+        assert!(!wit.contains("resource admin"), "{wit}");
+        let cargo_toml = std::fs::read_to_string(&generated.cargo_toml).unwrap();
+        assert!(
+            cargo_toml.contains("[package.metadata.wasm-rpc-stub]\nmin-target-version = \"0.2.0\""),
+            "{cargo_toml}"
+        );
+        wit_parser::Resolve::new()
+            .push_dir(&generated.wit_root)
+            .unwrap();
+    }
+
+    #[test]
+    fn unstable_items_can_be_included() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        std::fs::write(source.path().join("gated.wit"), GATED_WIT).unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new(source.path(), target.path());
+        options.include_unstable = true;
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let wit = std::fs::read_to_string(&generated.stub_wit).unwrap();
+        assert!(wit.contains("get-all: func() -> list<u32>;"), "{wit}");
+        assert!(wit.contains("resource admin"), "{wit}");
+    }
+
+    #[test]
+    fn failed_generations_are_rolled_back() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        // The stub source cannot be written over a directory, after the WIT files and the Cargo.toml are
+        std::fs::create_dir_all(target.path().join("src").join("lib.rs")).unwrap();
+
+        let result = generate_stub(&GenerateOptions::new("example", target.path()), &NoProgress);
+
+        assert!(matches!(result, Err(Error::Generation(_))), "{result:?}");
+        assert!(!target.path().join("wit").exists());
+        assert!(!target.path().join("Cargo.toml").exists());
+        assert!(target.path().join("src").join("lib.rs").is_dir());
+    }
+
+    #[test]
+    fn backups_of_the_regenerated_files_can_be_kept() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        generate_stub(&GenerateOptions::new("example", target.path()), &NoProgress).unwrap();
+        let original = std::fs::read_to_string(target.path().join("src/lib.rs")).unwrap();
+
+        let mut options = GenerateOptions::new("example", target.path());
+        options.metadata = true;
+        options.keep_backup = true;
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let backup = generated.backup.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(backup.join("src/lib.rs")).unwrap(),
+            original
+        );
+        assert_ne!(
+            std::fs::read_to_string(target.path().join("src/lib.rs")).unwrap(),
+            original
+        );
+        std::fs::remove_dir_all(backup).unwrap();
+    }
+
+    #[test]
+    fn cache_keys_identify_the_generated_stub_crates() {
+        let options = BuildOptions::new("example", "unused.wasm", "unused-wit");
+        let key = stub_cache_key(&options).unwrap();
+
+        assert_eq!(key.len(), 64);
+        assert_eq!(stub_cache_key(&options).unwrap(), key);
+        assert_eq!(
+            stub_cache_key(&BuildOptions {
+                target_dir: Some("shared-target".into()),
+                rustc_wrapper: Some("sccache".into()),
+                ..options.clone()
+            })
+            .unwrap(),
+            key
+        );
+        assert_ne!(
+            stub_cache_key(&BuildOptions {
+                metadata: true,
+                ..options.clone()
+            })
+            .unwrap(),
+            key
+        );
+    }
+
+    #[test]
+    fn stubs_are_compiled_in_the_shared_target_directory() {
+        let options = BuildOptions::new("example", "unused.wasm", "unused-wit");
+        let crate_root = std::path::Path::new("/tmp/wasm-rpc-stubgen-build");
+
+        assert_eq!(
+            stub_target_dir(&options, crate_root),
+            crate_root.join("target")
+        );
+        assert_eq!(
+            stub_target_dir(
+                &BuildOptions {
+                    target_dir: Some("/workspace/target/wasm-rpc-stubs".into()),
+                    ..options.clone()
+                },
+                crate_root
+            ),
+            std::path::Path::new("/workspace/target/wasm-rpc-stubs")
+        );
+    }
+
+    #[test]
+    fn concurrent_generations_do_not_collide() {
+        let targets = (0..4)
+            .map(|_| TempDir::new("stubgen-test").unwrap())
+            .collect::<Vec<_>>();
+
+        std::thread::scope(|scope| {
+            for target in &targets {
+                scope.spawn(|| {
+                    generate_stub(&GenerateOptions::new("example", target.path()), &NoProgress)
+                        .unwrap()
+                });
+            }
+        });
+
+        let sources = targets
+            .iter()
+            .map(|target| std::fs::read_to_string(target.path().join("src/lib.rs")).unwrap())
+            .collect::<Vec<_>>();
+        assert!(sources.iter().all(|source| *source == sources[0]));
+    }
+
+    #[test]
+    fn concurrent_builds_sharing_a_target_dir_read_their_own_artifacts() {
+        let target_dir = TempDir::new("stubgen-test").unwrap();
+        let wasm_path = target_dir
+            .path()
+            .join("wasm32-wasi/release/example_stub.wasm");
+
+        std::thread::scope(|scope| {
+            for byte in 0..4u8 {
+                let (target_dir, wasm_path) = (target_dir.path(), &wasm_path);
+                scope.spawn(move || {
+                    // Each build writes the shared artifact, and the others would overwrite it before it is read
+                    // without the lock
+                    let compilation = async move {
+                        std::fs::write(wasm_path, vec![byte; 1024])?;
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        Ok(())
+                    };
+                    let wasm = tokio::runtime::Builder::new_current_thread()
+                        .build()
+                        .unwrap()
+                        .block_on(compile_artifact(target_dir, "example-stub", compilation))
+                        .unwrap();
+                    assert_eq!(wasm, vec![byte; 1024]);
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn concurrently_written_outputs_are_never_partial() {
+        let dir = TempDir::new("stubgen-test").unwrap();
+        let path = dir.path().join("out.wasm");
+
+        std::thread::scope(|scope| {
+            for byte in 0..8u8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        write_output(path, &vec![byte; 256 * 1024]).unwrap();
+                    }
+                });
+            }
+        });
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written.len(), 256 * 1024);
+        assert!(written.iter().all(|byte| *byte == written[0]));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn converted_types_get_from_implementations() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let mut options = GenerateOptions::new("example", target.path());
+        options.dependencies.converted_types = vec![
+            "test:main/iface1=main::iface1".parse().unwrap(),
+            "test:dep1/iface2@0.1.0=dep::iface2".parse().unwrap(),
+        ];
+
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        let source: String = source.split_whitespace().collect();
+        for conversion in [
+            "implFrom<crate::bindings::test::main::iface1::Point>formain::iface1::Point",
+            "implFrom<main::iface1::Point>forcrate::bindings::test::main::iface1::Point",
+            "metadata:main::iface1::Metadata::from(value.metadata)",
+            "main::iface1::Permissions::from_bits_retain(value.bits())",
+            "implFrom<dep::iface2::AOrB>forcrate::bindings::test::dep1::iface2::AOrB",
+        ] {
+            assert!(source.contains(conversion), "{conversion} not generated");
+        }
+
+        options.dependencies.shared_types = vec!["test:main/iface1=main::iface1".parse().unwrap()];
+        let generated = generate_stub(&options, &NoProgress).unwrap();
+        let source = std::fs::read_to_string(generated.rust_source).unwrap();
+        assert!(!source.contains("impl From<main::iface1::Point>"));
+
+        options.dependencies.converted_types =
+            vec!["test:main/missing=main::missing".parse().unwrap()];
+        assert!(generate_stub(&options, &NoProgress).is_err());
+    }
+
+    #[test]
+    fn invalid_source_is_returned_as_error() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let result = generate_stub(
+            &GenerateOptions::new(target.path().join("missing"), target.path()),
+            &NoProgress,
+        );
+
+        assert!(matches!(result, Err(Error::InvalidWit(_))));
+    }
+
+    #[test]
+    fn compose_config_paths_are_absolute() {
+        let config = wasm_compose_config(&ComposeConfig {
+            definitions: vec!["defs/definition.wasm".into()],
+            search_paths: vec!["/components".into()],
+            disallow_imports: true,
+            ..ComposeConfig::default()
+        })
+        .unwrap();
+
+        let current_dir = std::env::current_dir().unwrap();
+        assert_eq!(
+            config.definitions,
+            vec![current_dir.join("defs/definition.wasm")]
+        );
+        assert_eq!(
+            config.search_paths,
+            vec![std::path::PathBuf::from("/components")]
+        );
+        assert!(config.disallow_imports);
+        assert!(!config.import_components);
+    }
+
+    fn import_section(names: &[&str]) -> Vec<u8> {
+        let mut content = vec![names.len() as u8];
+        for name in names {
+            content.extend([0x00, name.len() as u8]);
+            content.extend(name.as_bytes());
+            content.extend([0x05, 0x00]);
+        }
+        let mut section = vec![10, content.len() as u8];
+        section.extend(content);
+        section
+    }
+
+    #[test]
+    fn top_level_imports_of_components_are_found() {
+        const HEADER: &[u8] = b"\0asm\x0d\0\x01\0";
+        let mut nested = HEADER.to_vec();
+        nested.extend(import_section(&["rpc:other-stub/stub-other"]));
+        let mut component = HEADER.to_vec();
+        component.extend(import_section(&[
+            "rpc:counter-stub/stub-counter",
+            "wasi:io/streams@0.2.0",
+        ]));
+        component.extend([4, nested.len() as u8]);
+        component.extend(nested);
+
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let path = temp_dir.path().join("caller.wasm");
+        std::fs::write(&path, component).unwrap();
+
+        let imports = component_imports(&path).unwrap();
+        assert_eq!(
+            imports,
+            vec!["rpc:counter-stub/stub-counter", "wasi:io/streams@0.2.0"]
+        );
+        assert_eq!(
+            imports
+                .iter()
+                .map(|import| is_stub_import(import))
+                .collect::<Vec<_>>(),
+            vec![true, false]
+        );
+        assert!(is_stub_import("rpc:counter-stub/stub-counter@0.0.1"));
+    }
+
+    #[test]
+    fn unstable_sections_are_stripped_from_the_top_level() {
+        const HEADER: &[u8] = b"\0asm\x0d\0\x01\0";
+        let producers = [vec![0, 10, 9], b"producers".to_vec()].concat();
+        let mut nested = HEADER.to_vec();
+        nested.extend(&producers);
+        let mut component = HEADER.to_vec();
+        component.extend(&producers);
+        component.extend([4, nested.len() as u8]);
+        component.extend(&nested);
+
+        let stripped = strip_custom_sections(&component, &["producers"]).unwrap();
+
+        let mut expected = HEADER.to_vec();
+        expected.extend([4, nested.len() as u8]);
+        expected.extend(&nested);
+        assert_eq!(stripped, expected);
+        assert_eq!(
+            content_hash(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn lost_exports_and_exported_stubs_are_reported() {
+        let stubs = StubMatches::default();
+        assert!(export_issues(&["rpc:caller/api"], &["rpc:caller/api"], &stubs).is_empty());
+        assert_eq!(
+            export_issues(
+                &["rpc:caller/api", "run"],
+                &["run", "rpc:counter-stub/stub-counter"],
+                &stubs
+            ),
+            vec![
+                "  missing export: rpc:caller/api",
+                "  stub interface exported: rpc:counter-stub/stub-counter"
+            ]
+        );
+    }
+
+    #[test]
+    fn pipelines_link_the_exports_imported_by_the_next_component() {
+        let instance = |name: &str| {
+            AnalysedExport::Instance(AnalysedInstance {
+                name: name.to_string(),
+                funcs: vec![],
+            })
+        };
+        let exports = vec![
+            instance("rpc:parser/parse"),
+            AnalysedExport::Function(AnalysedFunction {
+                name: "rpc:parser/api".to_string(),
+                params: vec![],
+                results: vec![],
+            }),
+            instance("rpc:parser/api"),
+            instance("rpc:parser/admin"),
+        ];
+        let consumer_imports = vec![
+            "rpc:parser/parse".to_string(),
+            "rpc:parser/api".to_string(),
+            "wasi:io/streams@0.2.0".to_string(),
+        ];
+
+        assert_eq!(
+            linked_interfaces(exports, &consumer_imports),
+            vec!["rpc:parser/api", "rpc:parser/parse"]
+        );
+        assert!(
+            linked_interfaces(vec![instance("rpc:parser/admin")], &consumer_imports).is_empty()
+        );
+    }
+
+    #[test]
+    fn pipelines_need_at_least_two_components() {
+        let result = plan_compose_chain(&ComposeChainOptions {
+            wasm: vec!["parser.wasm".into()],
+            dest_wasm: "pipeline.wasm".into(),
+            engine: ComposeEngine::default(),
+            sbom: false,
+        });
+
+        assert!(
+            matches!(&result, Err(Error::Composition(message)) if message.contains("At least two")),
+            "{result:?}"
+        );
+    }
+}
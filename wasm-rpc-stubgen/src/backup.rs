@@ -0,0 +1,264 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// The directory next to the destination WIT root keeping the snapshots of its `deps` directory
+pub const BACKUPS_DIR: &str = ".wasm-rpc-backups";
+
+const SNAPSHOT_MANIFEST: &str = "snapshot.json";
+
+/// The dependency directories saved in a snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The name of the snapshot, the UTC time it was taken at, like `20240315T101502Z`
+    pub name: String,
+    /// The dependency directories in `deps` which existed, and were saved
+    pub saved: Vec<String>,
+    /// The dependency directories in `deps` which did not exist yet, and are removed when restoring the snapshot
+    pub missing: Vec<String>,
+}
+
+/// The directory of the snapshots of the given WIT root's dependencies
+pub fn backups_dir(dest_wit_root: &Path) -> PathBuf {
+    dest_wit_root
+        .parent()
+        .unwrap_or(dest_wit_root)
+        .join(BACKUPS_DIR)
+}
+
+/// Saves the given dependency directories of the WIT root's `deps` to a new snapshot, before overwriting them
+pub fn take_snapshot(dest_wit_root: &Path, dep_dirs: &[String]) -> anyhow::Result<Snapshot> {
+    let backups = backups_dir(dest_wit_root);
+    let base_name = utc_timestamp(SystemTime::now());
+    let mut name = base_name.clone();
+    let mut counter = 1;
+    while backups.join(&name).exists() {
+        name = format!("{base_name}-{counter}");
+        counter += 1;
+    }
+    let snapshot_dir = backups.join(&name);
+    fs::create_dir_all(snapshot_dir.join("deps"))
+        .context(format!("Failed to create {snapshot_dir:?}"))?;
+
+    let mut snapshot = Snapshot {
+        name,
+        saved: Vec::new(),
+        missing: Vec::new(),
+    };
+    for dep_dir in dep_dirs {
+        let source = dest_wit_root.join("deps").join(dep_dir);
+        if source.exists() {
+            let target = snapshot_dir.join("deps").join(dep_dir);
+            fs::create_dir_all(&target)?;
+            // Symbolic links are followed, saving the files they pointed to
+            fs_extra::dir::copy(
+                &source,
+                &target,
+                &fs_extra::dir::CopyOptions::new().content_only(true),
+            )
+            .context(format!("Failed to back up {source:?}"))?;
+            snapshot.saved.push(dep_dir.clone());
+        } else {
+            snapshot.missing.push(dep_dir.clone());
+        }
+    }
+    fs::write(
+        snapshot_dir.join(SNAPSHOT_MANIFEST),
+        serde_json::to_string_pretty(&snapshot)?,
+    )?;
+    info!("Saved the overwritten dependencies to {snapshot_dir:?}");
+    Ok(snapshot)
+}
+
+/// The snapshots of the given WIT root's dependencies, the oldest first
+pub fn list_snapshots(dest_wit_root: &Path) -> anyhow::Result<Vec<Snapshot>> {
+    let backups = backups_dir(dest_wit_root);
+    if !backups.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&backups).context(format!("Failed to read {backups:?}"))? {
+        let manifest = entry?.path().join(SNAPSHOT_MANIFEST);
+        if manifest.is_file() {
+            let content =
+                fs::read_to_string(&manifest).context(format!("Failed to read {manifest:?}"))?;
+            snapshots.push(
+                serde_json::from_str::<Snapshot>(&content)
+                    .context(format!("Failed to parse {manifest:?}"))?,
+            );
+        }
+    }
+    snapshots.sort_by(|a, b| snapshot_order(&a.name).cmp(&snapshot_order(&b.name)));
+    Ok(snapshots)
+}
+
+/// Restores the named snapshot, or the latest one, replacing the saved dependency directories and removing the ones
+/// created since
+pub fn restore_snapshot(dest_wit_root: &Path, name: Option<&str>) -> anyhow::Result<Snapshot> {
+    let snapshots = list_snapshots(dest_wit_root)?;
+    let snapshot = match name {
+        Some(name) => snapshots
+            .into_iter()
+            .find(|snapshot| snapshot.name == name)
+            .ok_or(anyhow!(
+                "There is no snapshot named {name} in {:?}",
+                backups_dir(dest_wit_root)
+            ))?,
+        None => match snapshots.into_iter().last() {
+            Some(snapshot) => snapshot,
+            None => bail!(
+                "There are no snapshots of the dependencies of {dest_wit_root:?} in {:?}",
+                backups_dir(dest_wit_root)
+            ),
+        },
+    };
+
+    let deps = dest_wit_root.join("deps");
+    let snapshot_deps = backups_dir(dest_wit_root).join(&snapshot.name).join("deps");
+    for dep_dir in snapshot.saved.iter().chain(&snapshot.missing) {
+        let target = deps.join(dep_dir);
+        if target.is_symlink() || target.is_file() {
+            fs::remove_file(&target).context(format!("Failed to remove {target:?}"))?;
+        } else if target.is_dir() {
+            fs::remove_dir_all(&target).context(format!("Failed to remove {target:?}"))?;
+        }
+    }
+    for dep_dir in &snapshot.saved {
+        let target = deps.join(dep_dir);
+        fs::create_dir_all(&target)?;
+        fs_extra::dir::copy(
+            snapshot_deps.join(dep_dir),
+            &target,
+            &fs_extra::dir::CopyOptions::new().content_only(true),
+        )
+        .context(format!("Failed to restore {target:?}"))?;
+    }
+    info!(
+        "Restored the dependencies of {dest_wit_root:?} from snapshot {}",
+        snapshot.name
+    );
+    Ok(snapshot)
+}
+
+/// Orders the snapshots taken in the same second by their counter suffix
+fn snapshot_order(name: &str) -> (&str, u32) {
+    match name.split_once('-') {
+        Some((timestamp, counter)) => (timestamp, counter.parse().unwrap_or_default()),
+        None => (name, 0),
+    }
+}
+
+/// Formats the time as a compact ISO 8601 UTC timestamp, like `20240315T101502Z`
+fn utc_timestamp(time: SystemTime) -> String {
+    let [year, month, day, hour, minute, second] = utc_date_time(time);
+    format!("{year:04}{month:02}{day:02}T{hour:02}{minute:02}{second:02}Z")
+}
+
+/// The UTC year, month, day, hour, minute and second of the given time
+pub(crate) fn utc_date_time(time: SystemTime) -> [i64; 6] {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, day_secs) = (secs / 86400, secs % 86400);
+
+    // Converts the days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let day_secs = day_secs as i64;
+    [
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::{
+        list_snapshots, restore_snapshot, take_snapshot, utc_timestamp, BACKUPS_DIR,
+    };
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempdir::TempDir;
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(1710497702)),
+            "20240315T101502Z"
+        );
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(951782400)),
+            "20000229T000000Z"
+        );
+    }
+
+    #[test]
+    fn snapshots_restore_the_overwritten_dependencies() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit_root = root.path().join("wit");
+        let stub_dir = wit_root.join("deps").join("rpc_counter-stub");
+        fs::create_dir_all(&stub_dir).unwrap();
+        fs::write(stub_dir.join("_stub.wit"), "original").unwrap();
+
+        let names = ["rpc_counter-stub".to_string(), "rpc_counter".to_string()];
+        let first = take_snapshot(&wit_root, &names).unwrap();
+        let second = take_snapshot(&wit_root, &names).unwrap();
+        assert_eq!(first.saved, vec!["rpc_counter-stub".to_string()]);
+        assert_eq!(first.missing, vec!["rpc_counter".to_string()]);
+        assert_ne!(first.name, second.name);
+        assert!(root.path().join(BACKUPS_DIR).join(&first.name).is_dir());
+
+        fs::write(stub_dir.join("_stub.wit"), "overwritten").unwrap();
+        fs::write(stub_dir.join("added.wit"), "added").unwrap();
+        fs::create_dir_all(wit_root.join("deps").join("rpc_counter")).unwrap();
+
+        let restored = restore_snapshot(&wit_root, None).unwrap();
+        assert_eq!(restored.name, second.name);
+        assert_eq!(
+            fs::read_to_string(stub_dir.join("_stub.wit")).unwrap(),
+            "original"
+        );
+        assert!(!stub_dir.join("added.wit").exists());
+        assert!(!wit_root.join("deps").join("rpc_counter").exists());
+
+        let names = list_snapshots(&wit_root)
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![first.name.clone(), second.name]);
+        assert!(restore_snapshot(&wit_root, Some(&first.name)).is_ok());
+        assert!(restore_snapshot(&wit_root, Some("19700101T000000Z")).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use clap::Parser;
+use golem_wasm_rpc_stubgen::*;
+
+#[tokio::main]
+async fn main() {
+    match CargoCommand::parse() {
+        CargoCommand::WasmRpc(args) => {
+            init_logging(args.verbosity);
+            let check_for_update = !matches!(args.command, WorkspaceCommand::SelfUpdate(_));
+            let result = cargo_wasm_rpc(args).await;
+            if check_for_update {
+                notify_newer_version();
+            }
+            if let Err(err) = result {
+                eprintln!("Error: {:?}", err);
+                std::process::exit(ErrorKind::of(&err).exit_code());
+            }
+        }
+    }
+}
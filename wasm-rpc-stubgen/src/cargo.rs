@@ -12,29 +12,61 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::changes::ManifestUpdate;
+use crate::error::{CommandError, ErrorKind};
+use crate::paths::{canonicalize, manifest_path};
+use crate::progress::{Progress, ProgressEvent};
 use crate::stub::StubDefinition;
-use crate::wit;
 use anyhow::{anyhow, bail, Context};
 use cargo_toml::{
     Dependency, DependencyDetail, DepsSet, Edition, Inheritable, LtoSetting, Manifest, Profile,
     Profiles, StripSetting,
 };
 use golem_wasm_rpc::WASM_RPC_VERSION;
+use semver::VersionReq;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use toml::Value;
+use toml_edit::{DocumentMut, InlineTable, Item, Table, TableLike};
+use tracing::info;
+use wit_parser::PackageName;
 
 #[derive(Serialize, Deserialize, Default)]
 struct MetadataRoot {
     component: Option<ComponentMetadata>,
+    #[serde(
+        rename = "wasm-rpc-stub",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    wasm_rpc_stub: Option<StubMetadata>,
+}
+
+/// The `[package.metadata.wasm-rpc-stub]` table of a generated stub crate
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct StubMetadata {
+    /// The minimum version of the target the stub works with, by the `@since` gates of the items it calls
+    min_target_version: String,
 }
 
 #[derive(Serialize, Deserialize)]
 struct ComponentMetadata {
     package: Option<String>,
     target: Option<ComponentTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bindings: Option<ComponentBindings>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ComponentBindings {
+    /// The Rust modules generated by other crates, used instead of generating the bindings of the interfaces again
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    with: BTreeMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -43,32 +75,198 @@ struct ComponentTarget {
     #[serde(default = "default_path")]
     path: String,
     #[serde(default)]
-    dependencies: HashMap<String, WitDependency>,
+    dependencies: BTreeMap<String, WitDependency>,
 }
 
 fn default_path() -> String {
     "wit".to_string()
 }
 
-impl Default for ComponentTarget {
+#[derive(Serialize, Deserialize)]
+struct WitDependency {
+    path: String,
+}
+
+const WIT_BINDGEN_VERSION: &str = "0.17.0";
+const GOLEM_WASM_AST_VERSION: &str = "0.2.0";
+const WASMTIME_VERSION: &str = "=17.0.0";
+
+/// Where a dependency of the generated stub crate is taken from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencySource {
+    /// A version requirement resolved from crates.io
+    Version(String),
+    /// A version requirement resolved from an alternative registry configured in `.cargo/config.toml`
+    Registry { registry: String, version: String },
+    /// A local path
+    Path(String),
+    /// A git repository, optionally pinned to a revision
+    Git { url: String, rev: Option<String> },
+}
+
+impl DependencySource {
+    pub fn default_wasm_rpc() -> Self {
+        DependencySource::Version(WASM_RPC_VERSION.to_string())
+    }
+
+    pub fn default_wit_bindgen() -> Self {
+        DependencySource::Version(WIT_BINDGEN_VERSION.to_string())
+    }
+
+    fn to_dependency(&self, features: Vec<String>) -> Dependency {
+        let mut detail = DependencyDetail {
+            default_features: false,
+            features,
+            ..Default::default()
+        };
+        match self {
+            DependencySource::Version(version) => {
+                detail.version = Some(version.clone());
+            }
+            DependencySource::Registry { registry, version } => {
+                detail.registry = Some(registry.clone());
+                detail.version = Some(version.clone());
+            }
+            DependencySource::Path(path) => {
+                detail.path = Some(path.clone());
+            }
+            DependencySource::Git { url, rev } => {
+                detail.git = Some(url.clone());
+                detail.rev = rev.clone();
+            }
+        }
+        Dependency::Detailed(Box::new(detail))
+    }
+}
+
+/// Sources of the dependencies of the generated stub crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StubCrateDependencies {
+    pub wasm_rpc: DependencySource,
+    pub wit_bindgen: DependencySource,
+    /// The interfaces whose types are taken from the bindings of another crate
+    pub shared_types: Vec<SharedTypes>,
+    /// The interfaces whose types get `From` conversions to and from the types of another crate
+    pub converted_types: Vec<SharedTypes>,
+}
+
+impl Default for StubCrateDependencies {
     fn default() -> Self {
         Self {
-            world: None,
-            path: "wit".to_string(),
-            dependencies: HashMap::new(),
+            wasm_rpc: DependencySource::default_wasm_rpc(),
+            wit_bindgen: DependencySource::default_wit_bindgen(),
+            shared_types: Vec::new(),
+            converted_types: Vec::new(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct WitDependency {
-    path: String,
+/// Takes the types of a WIT interface from the bindings generated by another crate, like the caller's, instead of
+/// generating them again in the stub crate, so the values pass between the caller and the stub without conversions.
+/// The other crate has to be added to the dependencies of the stub crate.
+///
+/// The same mapping also configures the interfaces whose types are converted from and to the types of another crate,
+/// like the target component's bindings, when they cannot be shared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedTypes {
+    /// The name of the interface, like `rpc:counter/api`, or `rpc:counter/api@0.1.0` for versioned packages
+    pub interface: String,
+    /// The path of the Rust module of the interface in the other crate, like `counter_types::bindings::rpc::counter::api`
+    pub module: String,
+}
+
+impl FromStr for SharedTypes {
+    type Err = String;
+
+    /// Parses `<interface>=<module>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (interface, module) = s
+            .split_once('=')
+            .ok_or("expected <interface>=<module>".to_string())?;
+        let (interface, module) = (interface.trim(), module.trim());
+        if !interface.contains(':') || !interface.contains('/') {
+            return Err(format!(
+                "expected a qualified interface name like rpc:counter/api, got {interface}"
+            ));
+        }
+        syn::parse_str::<syn::Path>(module)
+            .map_err(|err| format!("invalid Rust module path {module}: {err}"))?;
+        Ok(SharedTypes {
+            interface: interface.to_string(),
+            module: module.to_string(),
+        })
+    }
+}
+
+impl Display for SharedTypes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.interface, self.module)
+    }
+}
+
+impl FromStr for DependencySource {
+    type Err = String;
+
+    /// Parses `<version>`, `registry:<name>@<version>`, `path:<path>` or `git:<url>[#<rev>]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("path:") {
+            Ok(DependencySource::Path(path.to_string()))
+        } else if let Some(git) = s.strip_prefix("git:") {
+            match git.rsplit_once('#') {
+                Some((url, rev)) => Ok(DependencySource::Git {
+                    url: url.to_string(),
+                    rev: Some(rev.to_string()),
+                }),
+                None => Ok(DependencySource::Git {
+                    url: git.to_string(),
+                    rev: None,
+                }),
+            }
+        } else if let Some(registry) = s.strip_prefix("registry:") {
+            let (registry, version) = registry
+                .split_once('@')
+                .ok_or("expected registry:<name>@<version>".to_string())?;
+            VersionReq::parse(version)
+                .map_err(|err| format!("invalid version {version}: {err}"))?;
+            Ok(DependencySource::Registry {
+                registry: registry.to_string(),
+                version: version.to_string(),
+            })
+        } else {
+            VersionReq::parse(s).map_err(|err| {
+                format!("expected a version, registry:<name>@<version>, path:<path> or git:<url>[#<rev>], {err}")
+            })?;
+            Ok(DependencySource::Version(s.to_string()))
+        }
+    }
+}
+
+impl Display for DependencySource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencySource::Version(version) => write!(f, "{version}"),
+            DependencySource::Registry { registry, version } => {
+                write!(f, "registry:{registry}@{version}")
+            }
+            DependencySource::Path(path) => write!(f, "path:{path}"),
+            DependencySource::Git { url, rev: None } => write!(f, "git:{url}"),
+            DependencySource::Git {
+                url,
+                rev: Some(rev),
+            } => write!(f, "git:{url}#{rev}"),
+        }
+    }
 }
 
-pub fn generate_cargo_toml(def: &StubDefinition) -> anyhow::Result<()> {
+pub fn generate_cargo_toml(
+    def: &StubDefinition,
+    force_manifest: bool,
+    integration_test: bool,
+    progress: &dyn Progress,
+) -> anyhow::Result<()> {
     let mut manifest = Manifest::default();
 
-    let mut wit_dependencies = HashMap::new();
+    let mut wit_dependencies = BTreeMap::new();
 
     wit_dependencies.insert(
         def.root_package_name.to_string(),
@@ -86,6 +284,16 @@ pub fn generate_cargo_toml(def: &StubDefinition) -> anyhow::Result<()> {
         },
     );
     for dep in &def.unresolved_deps {
+        if def.is_source_root_package(dep) {
+            wit_dependencies.insert(
+                format!("{}:{}", dep.name.namespace, dep.name.name),
+                WitDependency {
+                    path: format!("wit/deps/{}_{}", dep.name.namespace, dep.name.name),
+                },
+            );
+            continue;
+        }
+
         let mut dirs = HashSet::new();
         for source in dep.source_files() {
             let relative = source.strip_prefix(&def.source_wit_root)?;
@@ -102,7 +310,7 @@ pub fn generate_cargo_toml(def: &StubDefinition) -> anyhow::Result<()> {
         wit_dependencies.insert(
             format!("{}:{}", dep.name.namespace, dep.name.name),
             WitDependency {
-                path: format!("wit/{}", dirs.iter().next().unwrap().to_str().unwrap()),
+                path: manifest_path(&Path::new("wit").join(dirs.iter().next().unwrap())),
             },
         );
     }
@@ -118,6 +326,17 @@ pub fn generate_cargo_toml(def: &StubDefinition) -> anyhow::Result<()> {
                 path: "wit".to_string(),
                 dependencies: wit_dependencies,
             }),
+            bindings: (!def.dependencies.shared_types.is_empty()).then(|| ComponentBindings {
+                with: def
+                    .dependencies
+                    .shared_types
+                    .iter()
+                    .map(|shared| (shared.interface.clone(), shared.module.clone()))
+                    .collect(),
+            }),
+        }),
+        wasm_rpc_stub: def.min_target_version().map(|version| StubMetadata {
+            min_target_version: version.to_string(),
         }),
     };
 
@@ -153,52 +372,185 @@ pub fn generate_cargo_toml(def: &StubDefinition) -> anyhow::Result<()> {
         ..Default::default()
     };
 
-    let dep_wit_bindgen = Dependency::Detailed(Box::new(DependencyDetail {
-        version: Some("0.17.0".to_string()),
-        default_features: false,
-        features: vec!["realloc".to_string()],
-        ..Default::default()
-    }));
-
-    let dep_golem_wasm_rpc = Dependency::Detailed(Box::new(DependencyDetail {
-        version: if def.wasm_rpc_path_override.is_none() {
-            Some(WASM_RPC_VERSION.to_string())
-        } else {
-            None
-        },
-        path: def.wasm_rpc_path_override.clone(),
-        default_features: false,
-        features: vec!["stub".to_string()],
-        ..Default::default()
-    }));
+    let dep_wit_bindgen = def
+        .dependencies
+        .wit_bindgen
+        .to_dependency(vec!["realloc".to_string()]);
+    let dep_golem_wasm_rpc_guest = def.dependencies.wasm_rpc.to_dependency(vec![]);
 
     let mut deps = DepsSet::new();
     deps.insert("wit-bindgen".to_string(), dep_wit_bindgen);
-    deps.insert("golem-wasm-rpc".to_string(), dep_golem_wasm_rpc);
+    deps.insert("golem-wasm-rpc-guest".to_string(), dep_golem_wasm_rpc_guest);
     manifest.dependencies = deps;
 
-    let cargo_toml = toml::to_string(&manifest)?;
+    if integration_test {
+        manifest.dev_dependencies = integration_test_dependencies(def);
+    }
 
-    println!(
-        "Generating Cargo.toml to {}",
-        def.target_cargo_path().to_string_lossy()
+    let interface_features = def.interface_features();
+    manifest.features.insert(
+        "default".to_string(),
+        interface_features.iter().cloned().collect(),
     );
-    fs::write(def.target_cargo_path(), cargo_toml)?;
+    for feature in interface_features {
+        manifest.features.insert(feature, vec![]);
+    }
+
+    let mut cargo_toml = toml::to_string(&manifest)?;
+
+    let target_cargo_path = def.target_cargo_path();
+    if target_cargo_path.is_file() && !force_manifest {
+        progress.on_event(&ProgressEvent::Merging {
+            path: target_cargo_path.clone(),
+        });
+        let existing = fs::read_to_string(&target_cargo_path)?;
+        cargo_toml = merge_manifests(&existing, &cargo_toml)
+            .context(format!("Failed to merge into {target_cargo_path:?}"))?;
+    } else {
+        progress.on_event(&ProgressEvent::Generating {
+            description: "Cargo.toml".to_string(),
+            path: target_cargo_path.clone(),
+        });
+    }
+    fs::write(target_cargo_path, cargo_toml)?;
     Ok(())
 }
 
-pub fn is_cargo_component_toml(path: &Path) -> anyhow::Result<bool> {
-    let manifest: Manifest<MetadataRoot> = Manifest::from_path_with_metadata(path)?;
+/// The dependencies of the generated integration test, running the target component with wasmtime on the host
+fn integration_test_dependencies(def: &StubDefinition) -> DepsSet {
+    let with_default_features = |version: &str, features: &[&str]| {
+        Dependency::Detailed(Box::new(DependencyDetail {
+            version: Some(version.to_string()),
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+            ..Default::default()
+        }))
+    };
+
+    let mut deps = DepsSet::new();
+    deps.insert(
+        "golem-wasm-rpc-guest".to_string(),
+        def.dependencies
+            .wasm_rpc
+            .to_dependency(vec!["integration-test".to_string()]),
+    );
+    deps.insert(
+        "golem-wasm-ast".to_string(),
+        with_default_features(GOLEM_WASM_AST_VERSION, &["analysis"]),
+    );
+    deps.insert(
+        "wasmtime".to_string(),
+        with_default_features(WASMTIME_VERSION, &["component-model"]),
+    );
+    deps.insert("arbitrary".to_string(), with_default_features("1.3.2", &[]));
+    deps.insert(
+        "async-trait".to_string(),
+        with_default_features("0.1.77", &[]),
+    );
+    deps.insert(
+        "tokio".to_string(),
+        with_default_features("1.36.0", &["macros", "rt"]),
+    );
+    deps
+}
+
+/// Merges a freshly generated manifest into an existing one, keeping everything the generator does not produce.
+///
+/// Values set by the generator are updated, except in `[profile]` where the user's settings win. Tables, dependencies
+/// and keys unknown to the generator are kept together with their formatting.
+fn merge_manifests(existing: &str, generated: &str) -> anyhow::Result<String> {
+    let mut existing = existing.parse::<DocumentMut>()?;
+    let generated = generated.parse::<DocumentMut>()?;
+    remove_legacy_wasm_rpc_dependency(&mut existing);
+
+    for (key, item) in generated.iter() {
+        let keep_existing = key == "profile";
+        merge_item(existing.as_table_mut(), key, item, keep_existing);
+    }
+
+    Ok(existing.to_string())
+}
+
+/// Stubs generated before the guest bindings moved to `golem-wasm-rpc-guest` depend on `golem-wasm-rpc` with the
+/// `stub` feature. The generated `golem-wasm-rpc-guest` dependency replaces it.
+fn remove_legacy_wasm_rpc_dependency(manifest: &mut DocumentMut) {
+    let Some(dependencies) = manifest
+        .get_mut("dependencies")
+        .and_then(Item::as_table_like_mut)
+    else {
+        return;
+    };
+    let is_legacy = dependencies
+        .get("golem-wasm-rpc")
+        .and_then(Item::as_table_like)
+        .and_then(|dependency| dependency.get("features"))
+        .and_then(Item::as_array)
+        .is_some_and(|features| {
+            features
+                .iter()
+                .any(|feature| feature.as_str() == Some("stub"))
+        });
+    if is_legacy {
+        dependencies.remove("golem-wasm-rpc");
+    }
+}
+
+/// Fails if a path dependency meant to be `golem-wasm-rpc-guest` points at the `golem-wasm-rpc` crate, which the
+/// stubs depended on before the guest bindings moved out of it. Relative paths are resolved from `base`, the stub
+/// crate's root.
+pub fn check_wasm_rpc_path(path: &str, base: &Path) -> anyhow::Result<()> {
+    let cargo_path = base.join(path).join("Cargo.toml");
+    if !cargo_path.is_file() {
+        return Ok(());
+    }
+    let manifest = read_document(&cargo_path)?;
+    let name = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(Item::as_str);
+    if name == Some("golem-wasm-rpc") {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!(
+                "{path} is the golem-wasm-rpc crate, but the stubs depend on golem-wasm-rpc-guest. Use the path of the wasm-rpc-guest crate instead."
+            )
+        ));
+    }
+    Ok(())
+}
 
-    if let Some(package) = manifest.package {
-        if let Some(metadata) = package.metadata {
-            if metadata.component.is_some() {
-                return Ok(true);
+fn merge_item(existing: &mut dyn TableLike, key: &str, generated: &Item, keep_existing: bool) {
+    match existing.get_mut(key) {
+        Some(current) if current.is_table_like() && generated.is_table_like() => {
+            let current = current.as_table_like_mut().unwrap();
+            for (key, item) in generated.as_table_like().unwrap().iter() {
+                merge_item(current, key, item, keep_existing);
             }
         }
+        Some(_) if keep_existing => {}
+        _ => {
+            existing.insert(key, generated.clone());
+        }
     }
+}
 
-    Ok(false)
+/// Checks whether the crate is a cargo-component project, either by its own component metadata or by the
+/// shared `[workspace.metadata.component]` table of its workspace
+pub fn is_cargo_component_toml(path: &Path) -> anyhow::Result<bool> {
+    let manifest = read_document(path)?;
+    if has_table(manifest.as_item(), &["package", "metadata", "component"]) {
+        return Ok(true);
+    }
+
+    match find_workspace_root(path)? {
+        Some(workspace_cargo) => {
+            let workspace = read_document(&workspace_cargo)?;
+            Ok(has_table(
+                workspace.as_item(),
+                &["workspace", "metadata", "component"],
+            ))
+        }
+        None => Ok(false),
+    }
 }
 
 pub fn is_cargo_workspace_toml(path: &Path) -> anyhow::Result<bool> {
@@ -214,6 +566,14 @@ pub fn is_cargo_workspace_toml(path: &Path) -> anyhow::Result<bool> {
     }
 }
 
+pub fn get_workspace_members(path: &Path) -> anyhow::Result<Vec<String>> {
+    let manifest = Manifest::from_path(path)?;
+    match manifest.workspace {
+        Some(workspace) => Ok(workspace.members),
+        None => Err(anyhow!("{path:?} is not a cargo workspace")),
+    }
+}
+
 pub fn add_workspace_members(path: &Path, members: &[String]) -> anyhow::Result<()> {
     let mut manifest = Manifest::from_path(path)?;
     if let Some(workspace) = manifest.workspace.as_mut() {
@@ -226,48 +586,660 @@ pub fn add_workspace_members(path: &Path, members: &[String]) -> anyhow::Result<
 
     let cargo_toml = toml::to_string(&manifest)?;
 
-    println!("Writing updated Cargo.toml to {:?}", path);
+    info!("Writing updated Cargo.toml to {:?}", path);
     fs::write(path, cargo_toml)?;
     Ok(())
 }
 
-pub fn add_dependencies_to_cargo_toml(cargo_path: &Path, names: &[String]) -> anyhow::Result<()> {
-    let mut manifest: Manifest<MetadataRoot> = Manifest::from_path_with_metadata(cargo_path)?;
-    if let Some(ref mut package) = manifest.package {
-        if let Some(ref mut metadata) = package.metadata {
-            if let Some(ref mut component) = metadata.component {
-                let mut new_target = ComponentTarget::default();
-                let target = component.target.as_mut().unwrap_or(&mut new_target);
-                let existing: HashSet<_> = target.dependencies.keys().cloned().collect();
-                for name in names {
-                    if !existing.contains(name) {
-                        let relative_path = format!("wit/deps/{}", name);
-                        let path = cargo_path
-                            .parent()
-                            .context("Parent directory of Cargo.toml")?
-                            .join(&relative_path);
-                        let package_name = wit::get_package_name(&path)?;
-
-                        target.dependencies.insert(
-                            format!("{}:{}", package_name.namespace, package_name.name),
-                            WitDependency {
-                                path: relative_path,
-                            },
-                        );
-                    }
-                }
-
-                if component.target.is_none() {
-                    component.target = Some(new_target);
-                }
-
-                let cargo_toml = toml::to_string(&manifest)?;
-
-                println!("Writing updated Cargo.toml to {:?}", cargo_path);
-                fs::write(cargo_path, cargo_toml)?;
+/// Registers WIT dependencies copied to the crate's `wit/deps` directory in the cargo-component metadata.
+///
+/// The dependencies are added to the crate's own `[package.metadata.component.target.dependencies]` table. If the
+/// crate has no component metadata of its own but is a member of a workspace defining
+/// `[workspace.metadata.component]`, they are added to the workspace's shared table instead, relative to the
+/// workspace root. If the crate inherits its dependencies from the `[workspace.dependencies]` of its workspace, the
+/// WIT dependencies are inherited the same way: their paths are added to the workspace's shared table, and the
+/// crate's own table refers to them with `workspace = true`. The manifests are edited in place, so workspace
+/// inherited fields (`version.workspace = true`, `[workspace.dependencies]` references) and formatting are kept.
+pub fn add_dependencies_to_cargo_toml(
+    cargo_path: &Path,
+    dependencies: &[(String, PackageName)],
+) -> anyhow::Result<()> {
+    write_manifest_updates(plan_cargo_toml_dependencies(
+        cargo_path,
+        dependencies,
+        false,
+        &[],
+    )?)
+}
+
+/// Updates the WIT dependencies registered by [add_dependencies_to_cargo_toml] to a newer version of the stub:
+/// the existing entries of the dependencies are replaced, and the entries of the removed ones are dropped.
+pub fn update_dependencies_in_cargo_toml(
+    cargo_path: &Path,
+    dependencies: &[(String, PackageName)],
+    removed: &[PackageName],
+) -> anyhow::Result<()> {
+    write_manifest_updates(plan_cargo_toml_dependencies(
+        cargo_path,
+        dependencies,
+        true,
+        removed,
+    )?)
+}
+
+fn write_manifest_updates(updates: Vec<ManifestUpdate>) -> anyhow::Result<()> {
+    for update in updates {
+        info!("Writing updated Cargo.toml to {:?}", update.path);
+        fs::write(&update.path, update.content)?;
+    }
+    Ok(())
+}
+
+/// Computes the manifest edits of [add_dependencies_to_cargo_toml], or of [update_dependencies_in_cargo_toml] with
+/// `replace`, without writing them
+pub fn plan_cargo_toml_dependencies(
+    cargo_path: &Path,
+    dependencies: &[(String, PackageName)],
+    replace: bool,
+    removed: &[PackageName],
+) -> anyhow::Result<Vec<ManifestUpdate>> {
+    let crate_root = cargo_path
+        .parent()
+        .ok_or(anyhow!("{cargo_path:?} has no parent directory"))?;
+    let mut manifest = read_document(cargo_path)?;
+    let has_own_metadata = has_table(manifest.as_item(), &["package", "metadata", "component"]);
+    let workspace = match find_workspace_root(cargo_path)? {
+        Some(workspace_cargo) => {
+            let workspace = read_document(&workspace_cargo)?;
+            Some((workspace_cargo, workspace))
+        }
+        None => None,
+    };
+
+    let (workspace_cargo, mut workspace) = match workspace {
+        Some((workspace_cargo, workspace))
+            if !has_own_metadata || inherits_dependencies(&manifest, &workspace) =>
+        {
+            if !has_own_metadata
+                && !has_table(workspace.as_item(), &["workspace", "metadata", "component"])
+            {
+                bail!(
+                    "Neither {cargo_path:?} nor {workspace_cargo:?} have cargo-component metadata"
+                );
             }
+            (workspace_cargo, workspace)
+        }
+        _ if has_own_metadata => {
+            let table = get_or_insert_table(
+                manifest.as_item_mut(),
+                &["package", "metadata", "component", "target", "dependencies"],
+            )?;
+            remove_wit_dependencies(table, removed);
+            let edits = dependencies
+                .iter()
+                .filter_map(|(name, package_name)| {
+                    insert_wit_dependency(
+                        table,
+                        package_name,
+                        path_entry(&format!("wit/deps/{name}")),
+                        replace,
+                    )
+                })
+                .collect();
+
+            return Ok(vec![ManifestUpdate {
+                path: cargo_path.to_path_buf(),
+                content: manifest.to_string(),
+                edits,
+            }]);
         }
+        _ => bail!(
+            "{cargo_path:?} has no cargo-component metadata and is not a member of a workspace"
+        ),
+    };
+
+    let workspace_root = workspace_cargo
+        .parent()
+        .ok_or(anyhow!("{workspace_cargo:?} has no parent directory"))?;
+    let member_dir = pathdiff::diff_paths(canonicalize(crate_root)?, canonicalize(workspace_root)?)
+        .ok_or(anyhow!("{crate_root:?} is not inside {workspace_root:?}"))?;
+    let table = get_or_insert_table(
+        workspace.as_item_mut(),
+        &[
+            "workspace",
+            "metadata",
+            "component",
+            "target",
+            "dependencies",
+        ],
+    )?;
+    remove_wit_dependencies(table, removed);
+    let edits = dependencies
+        .iter()
+        .filter_map(|(name, package_name)| {
+            let path = member_dir.join("wit").join("deps").join(name);
+            insert_wit_dependency(
+                table,
+                package_name,
+                path_entry(&manifest_path(&path)),
+                replace,
+            )
+        })
+        .collect();
+
+    let mut updates = Vec::new();
+    if has_own_metadata {
+        let table = get_or_insert_table(
+            manifest.as_item_mut(),
+            &["package", "metadata", "component", "target", "dependencies"],
+        )?;
+        remove_wit_dependencies(table, removed);
+        let edits = dependencies
+            .iter()
+            .filter_map(|(_, package_name)| {
+                let mut entry = InlineTable::new();
+                entry.insert("workspace", true.into());
+                insert_wit_dependency(table, package_name, entry, replace)
+            })
+            .collect();
+        updates.push(ManifestUpdate {
+            path: cargo_path.to_path_buf(),
+            content: manifest.to_string(),
+            edits,
+        });
+    }
+    updates.push(ManifestUpdate {
+        path: workspace_cargo,
+        content: workspace.to_string(),
+        edits,
+    });
+    Ok(updates)
+}
+
+/// Whether the crate takes any of its dependencies from the `[workspace.dependencies]` of its workspace
+fn inherits_dependencies(manifest: &DocumentMut, workspace: &DocumentMut) -> bool {
+    if !has_table(workspace.as_item(), &["workspace", "dependencies"]) {
+        return false;
     }
+    ["dependencies", "dev-dependencies", "build-dependencies"]
+        .iter()
+        .filter_map(|key| manifest.get(key).and_then(|item| item.as_table_like()))
+        .flat_map(|table| table.iter())
+        .any(|(_, dependency)| {
+            dependency
+                .as_table_like()
+                .and_then(|dependency| dependency.get("workspace"))
+                .and_then(|workspace| workspace.as_bool())
+                == Some(true)
+        })
+}
 
-    Ok(())
+/// Finds the `Cargo.toml` of the workspace the crate of the given manifest is a member of
+pub fn find_workspace_root(cargo_path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let crate_root = canonicalize(cargo_path)?
+        .parent()
+        .ok_or(anyhow!("{cargo_path:?} has no parent directory"))?
+        .to_path_buf();
+
+    for dir in crate_root.ancestors() {
+        let candidate = dir.join("Cargo.toml");
+        if !candidate.is_file() {
+            continue;
+        }
+        let doc = read_document(&candidate)?;
+        let Some(workspace) = doc.get("workspace").and_then(|item| item.as_table_like()) else {
+            continue;
+        };
+        if dir == crate_root {
+            return Ok(Some(candidate));
+        }
+
+        let relative = crate_root.strip_prefix(dir)?;
+        let patterns = |key: &str| -> Vec<String> {
+            workspace
+                .get(key)
+                .and_then(|item| item.as_array())
+                .map(|array| {
+                    array
+                        .iter()
+                        .filter_map(|value| value.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let excluded = patterns("exclude")
+            .iter()
+            .any(|pattern| matches_member_pattern(pattern, relative));
+        let included = patterns("members")
+            .iter()
+            .any(|pattern| matches_member_pattern(pattern, relative));
+        if included && !excluded {
+            return Ok(Some(candidate));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Matches a workspace `members` entry, supporting plain paths and a trailing `*` wildcard
+fn matches_member_pattern(pattern: &str, relative: &Path) -> bool {
+    let pattern = Path::new(pattern.trim_end_matches('/'));
+    match pattern.file_name().and_then(|name| name.to_str()) {
+        Some("*") => relative.parent() == pattern.parent(),
+        _ => relative == pattern,
+    }
+}
+
+fn read_document(path: &Path) -> anyhow::Result<DocumentMut> {
+    fs::read_to_string(path)
+        .context(format!("Failed to read {path:?}"))?
+        .parse::<DocumentMut>()
+        .context(format!("Failed to parse {path:?}"))
+}
+
+fn has_table(item: &Item, keys: &[&str]) -> bool {
+    let mut current = item;
+    for key in keys {
+        match current.get(key) {
+            Some(next) if next.is_table_like() => current = next,
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn get_or_insert_table<'a>(item: &'a mut Item, keys: &[&str]) -> anyhow::Result<&'a mut Table> {
+    let mut current = item
+        .as_table_mut()
+        .ok_or(anyhow!("The manifest root is not a table"))?;
+    for key in keys {
+        let next = current.entry(key).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        if let Some(inline) = next.as_inline_table() {
+            *next = Item::Table(inline.clone().into_table());
+        }
+        current = next
+            .as_table_mut()
+            .ok_or(anyhow!("The {key} entry of the manifest is not a table"))?;
+    }
+    Ok(current)
+}
+
+fn path_entry(path: &str) -> InlineTable {
+    let mut entry = InlineTable::new();
+    entry.insert("path", path.into());
+    entry
+}
+
+fn wit_dependency_key(package_name: &PackageName) -> String {
+    format!("{}:{}", package_name.namespace, package_name.name)
+}
+
+/// Adds the dependency unless the table already has one for the package, which is only replaced with `replace`,
+/// returning the added entry
+fn insert_wit_dependency(
+    table: &mut Table,
+    package_name: &PackageName,
+    entry: InlineTable,
+    replace: bool,
+) -> Option<String> {
+    let key = wit_dependency_key(package_name);
+    let unchanged = |existing: &Item| {
+        existing
+            .as_inline_table()
+            .is_some_and(|existing| existing.to_string().trim() == entry.to_string().trim())
+    };
+    match table.get(&key) {
+        Some(existing) if !replace || unchanged(existing) => None,
+        _ => {
+            let edit = format!("\"{key}\" = {entry}");
+            table.insert(&key, Item::Value(entry.into()));
+            Some(edit)
+        }
+    }
+}
+
+fn remove_wit_dependencies(table: &mut Table, removed: &[PackageName]) {
+    for package_name in removed {
+        table.remove(&wit_dependency_key(package_name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cargo::{
+        add_dependencies_to_cargo_toml, check_wasm_rpc_path, find_workspace_root,
+        generate_cargo_toml, is_cargo_component_toml, merge_manifests,
+        update_dependencies_in_cargo_toml, DependencySource, SharedTypes, StubCrateDependencies,
+    };
+    use crate::error::ErrorKind;
+    use crate::progress::NoProgress;
+    use crate::stub::{StubDefinition, StubPackageVersion};
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+    use wit_parser::PackageName;
+
+    const WORKSPACE_TOML: &str = "[workspace]\nresolver = \"2\"\nmembers = [\"components/*\"]\n\n\
+                                  [workspace.package]\nversion = \"0.1.0\"\n\n\
+                                  [workspace.dependencies]\nwit-bindgen = \"0.17.0\"\n";
+
+    fn stub_dependencies() -> Vec<(String, PackageName)> {
+        vec![(
+            "test_main-stub".to_string(),
+            PackageName {
+                namespace: "test".to_string(),
+                name: "main-stub".to_string(),
+                version: None,
+            },
+        )]
+    }
+
+    fn write_member(root: &Path, contents: &str) -> std::path::PathBuf {
+        fs::write(root.join("Cargo.toml"), WORKSPACE_TOML).unwrap();
+        let member = root.join("components").join("caller");
+        fs::create_dir_all(&member).unwrap();
+        let cargo_path = member.join("Cargo.toml");
+        fs::write(&cargo_path, contents).unwrap();
+        cargo_path
+    }
+
+    #[test]
+    fn workspace_root_is_found_through_member_globs() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let cargo_path = write_member(root.path(), "[package]\nname = \"caller\"\n");
+
+        assert_eq!(
+            find_workspace_root(&cargo_path).unwrap(),
+            Some(root.path().canonicalize().unwrap().join("Cargo.toml"))
+        );
+    }
+
+    #[test]
+    fn member_metadata_is_updated_keeping_inherited_fields() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let cargo_path = write_member(
+            root.path(),
+            "[package]\nname = \"caller\"\nversion.workspace = true\n\n\
+             [dependencies]\nwit-bindgen = \"0.17.0\"\n\n\
+             [package.metadata.component]\npackage = \"test:caller\"\n",
+        );
+
+        assert!(is_cargo_component_toml(&cargo_path).unwrap());
+        add_dependencies_to_cargo_toml(&cargo_path, &stub_dependencies()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&cargo_path).unwrap(),
+            "[package]\nname = \"caller\"\nversion.workspace = true\n\n\
+             [dependencies]\nwit-bindgen = \"0.17.0\"\n\n\
+             [package.metadata.component]\npackage = \"test:caller\"\n\n\
+             [package.metadata.component.target.dependencies]\n\
+             \"test:main-stub\" = { path = \"wit/deps/test_main-stub\" }\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.path().join("Cargo.toml")).unwrap(),
+            WORKSPACE_TOML
+        );
+    }
+
+    #[test]
+    fn updated_dependencies_replace_the_existing_entries() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let cargo_path = write_member(
+            root.path(),
+            "[package]\nname = \"caller\"\n\n\
+             [package.metadata.component.target.dependencies]\n\
+             \"test:main-stub\" = { path = \"wit/deps/main-stub\" }\n\
+             \"test:legacy\" = { path = \"wit/deps/test_legacy\" }\n\
+             \"test:other\" = { path = \"wit/deps/test_other\" }\n",
+        );
+        let removed = PackageName {
+            namespace: "test".to_string(),
+            name: "legacy".to_string(),
+            version: None,
+        };
+
+        add_dependencies_to_cargo_toml(&cargo_path, &stub_dependencies()).unwrap();
+        assert!(fs::read_to_string(&cargo_path)
+            .unwrap()
+            .contains("\"test:main-stub\" = { path = \"wit/deps/main-stub\" }"));
+
+        update_dependencies_in_cargo_toml(&cargo_path, &stub_dependencies(), &[removed]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&cargo_path).unwrap(),
+            "[package]\nname = \"caller\"\n\n\
+             [package.metadata.component.target.dependencies]\n\
+             \"test:main-stub\" = { path = \"wit/deps/test_main-stub\" }\n\
+             \"test:other\" = { path = \"wit/deps/test_other\" }\n"
+        );
+    }
+
+    #[test]
+    fn dependencies_are_inherited_by_members_using_workspace_dependencies() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let cargo_path = write_member(
+            root.path(),
+            "[package]\nname = \"caller\"\nversion.workspace = true\n\n\
+             [dependencies]\nwit-bindgen = { workspace = true }\n\n\
+             [package.metadata.component]\npackage = \"test:caller\"\n",
+        );
+
+        add_dependencies_to_cargo_toml(&cargo_path, &stub_dependencies()).unwrap();
+        add_dependencies_to_cargo_toml(&cargo_path, &stub_dependencies()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&cargo_path).unwrap(),
+            "[package]\nname = \"caller\"\nversion.workspace = true\n\n\
+             [dependencies]\nwit-bindgen = { workspace = true }\n\n\
+             [package.metadata.component]\npackage = \"test:caller\"\n\n\
+             [package.metadata.component.target.dependencies]\n\
+             \"test:main-stub\" = { workspace = true }\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.path().join("Cargo.toml")).unwrap(),
+            format!(
+                "{WORKSPACE_TOML}\n\
+                 [workspace.metadata.component.target.dependencies]\n\
+                 \"test:main-stub\" = {{ path = \"components/caller/wit/deps/test_main-stub\" }}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn workspace_metadata_is_updated_for_members_without_their_own() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        fs::write(
+            root.path().join("Cargo.toml"),
+            format!("{WORKSPACE_TOML}\n[workspace.metadata.component]\n"),
+        )
+        .unwrap();
+        let member = root.path().join("components").join("caller");
+        fs::create_dir_all(&member).unwrap();
+        let cargo_path = member.join("Cargo.toml");
+        let member_toml = "[package]\nname = \"caller\"\nversion.workspace = true\n";
+        fs::write(&cargo_path, member_toml).unwrap();
+
+        assert!(is_cargo_component_toml(&cargo_path).unwrap());
+        add_dependencies_to_cargo_toml(&cargo_path, &stub_dependencies()).unwrap();
+
+        assert_eq!(fs::read_to_string(&cargo_path).unwrap(), member_toml);
+        assert_eq!(
+            fs::read_to_string(root.path().join("Cargo.toml")).unwrap(),
+            format!(
+                "{WORKSPACE_TOML}\n[workspace.metadata.component]\n\n\
+                 [workspace.metadata.component.target.dependencies]\n\
+                 \"test:main-stub\" = {{ path = \"components/caller/wit/deps/test_main-stub\" }}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn generated_manifest_is_merged_into_existing_one() {
+        let existing =
+            "[package]\nname = \"old-name\"\nversion = \"0.0.1\"\ndescription = \"My stub\"\n\n\
+                        [dependencies]\n# extra dependency\nlog = \"0.4\"\n\n\
+                        [dependencies.golem-wasm-rpc]\nversion = \"0.0.1\"\n\n\
+                        [profile.release]\nopt-level = 3\n";
+        let generated = "[package]\nname = \"main-stub\"\nversion = \"0.0.2\"\n\n\
+                         [dependencies.golem-wasm-rpc]\nversion = \"0.0.2\"\n\n\
+                         [profile.release]\nopt-level = \"s\"\nlto = true\n";
+
+        assert_eq!(
+            merge_manifests(existing, generated).unwrap(),
+            "[package]\nname = \"main-stub\"\nversion = \"0.0.2\"\ndescription = \"My stub\"\n\n\
+             [dependencies]\n# extra dependency\nlog = \"0.4\"\n\n\
+             [dependencies.golem-wasm-rpc]\nversion = \"0.0.2\"\n\n\
+             [profile.release]\nopt-level = 3\nlto = true\n"
+        );
+    }
+
+    #[test]
+    fn legacy_wasm_rpc_dependency_is_replaced() {
+        let existing = "[package]\nname = \"main-stub\"\nversion = \"0.0.1\"\n\n\
+                        [dependencies]\nlog = \"0.4\"\n\n\
+                        [dependencies.golem-wasm-rpc]\nversion = \"0.0.1\"\nfeatures = [\"stub\"]\n";
+        let generated = "[package]\nname = \"main-stub\"\nversion = \"0.0.2\"\n\n\
+                         [dependencies.golem-wasm-rpc-guest]\nversion = \"0.0.2\"\n";
+
+        assert_eq!(
+            merge_manifests(existing, generated).unwrap(),
+            "[package]\nname = \"main-stub\"\nversion = \"0.0.2\"\n\n\
+             [dependencies]\nlog = \"0.4\"\n\n\
+             [dependencies.golem-wasm-rpc-guest]\nversion = \"0.0.2\"\n"
+        );
+    }
+
+    #[test]
+    fn paths_of_the_wasm_rpc_crate_are_rejected() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        for (dir, name) in [
+            ("wasm-rpc", "golem-wasm-rpc"),
+            ("wasm-rpc-guest", "golem-wasm-rpc-guest"),
+        ] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+            fs::write(
+                root.path().join(dir).join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion = \"0.0.0\"\n"),
+            )
+            .unwrap();
+        }
+        let stub_root = root.path().join("stub");
+        fs::create_dir_all(&stub_root).unwrap();
+
+        let error = check_wasm_rpc_path("../wasm-rpc", &stub_root).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::InvalidArguments);
+        check_wasm_rpc_path("../wasm-rpc-guest", &stub_root).unwrap();
+        check_wasm_rpc_path("../missing", &stub_root).unwrap();
+    }
+
+    #[test]
+    fn dependency_sources_roundtrip() {
+        for (spec, source) in [
+            ("0.0.18", DependencySource::Version("0.0.18".to_string())),
+            (
+                "registry:mirror@^0.0.18",
+                DependencySource::Registry {
+                    registry: "mirror".to_string(),
+                    version: "^0.0.18".to_string(),
+                },
+            ),
+            (
+                "path:../wasm-rpc",
+                DependencySource::Path("../wasm-rpc".to_string()),
+            ),
+            (
+                "git:https://github.com/golemcloud/wasm-rpc",
+                DependencySource::Git {
+                    url: "https://github.com/golemcloud/wasm-rpc".to_string(),
+                    rev: None,
+                },
+            ),
+            (
+                "git:https://github.com/golemcloud/wasm-rpc#4b5d9ab",
+                DependencySource::Git {
+                    url: "https://github.com/golemcloud/wasm-rpc".to_string(),
+                    rev: Some("4b5d9ab".to_string()),
+                },
+            ),
+        ] {
+            assert_eq!(spec.parse::<DependencySource>(), Ok(source.clone()));
+            assert_eq!(source.to_string(), spec);
+        }
+
+        assert!("latest".parse::<DependencySource>().is_err());
+        assert!("registry:mirror".parse::<DependencySource>().is_err());
+    }
+
+    #[test]
+    fn shared_types_are_parsed() {
+        assert_eq!(
+            "rpc:counter/api@0.1.0 = counter_types::bindings::rpc::counter::api".parse(),
+            Ok(SharedTypes {
+                interface: "rpc:counter/api@0.1.0".to_string(),
+                module: "counter_types::bindings::rpc::counter::api".to_string(),
+            })
+        );
+        assert!("rpc:counter/api".parse::<SharedTypes>().is_err());
+        assert!("api=counter_types::api".parse::<SharedTypes>().is_err());
+        assert!("rpc:counter/api=counter types"
+            .parse::<SharedTypes>()
+            .is_err());
+    }
+
+    #[test]
+    fn shared_types_are_configured_for_the_bindings() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let source_wit_root = root.path().join("wit");
+        fs::create_dir_all(&source_wit_root).unwrap();
+        fs::write(
+            source_wit_root.join("api.wit"),
+            "package rpc:counter;\n\ninterface api {\n  record point { x: s32 }\n  \
+             get: func() -> point;\n}\n\nworld counter {\n  export api;\n}\n",
+        )
+        .unwrap();
+        let dependencies = StubCrateDependencies {
+            shared_types: vec!["rpc:counter/api=counter_types::api".parse().unwrap()],
+            ..Default::default()
+        };
+        let def = StubDefinition::new(
+            &source_wit_root,
+            &root.path().join("stub"),
+            &None,
+            &None,
+            "0.0.1",
+            &StubPackageVersion::Same,
+            &dependencies,
+        )
+        .unwrap();
+        let api = def
+            .resolve
+            .interfaces
+            .iter()
+            .find(|(_, interface)| interface.name.as_deref() == Some("api"))
+            .map(|(id, _)| id)
+            .unwrap();
+        assert_eq!(def.shared_types_module(api), Some("counter_types::api"));
+
+        fs::create_dir_all(&def.target_root).unwrap();
+        generate_cargo_toml(&def, false, false, &NoProgress).unwrap();
+        let manifest: toml::Value =
+            toml::from_str(&fs::read_to_string(def.target_cargo_path()).unwrap()).unwrap();
+        assert_eq!(
+            manifest["package"]["metadata"]["component"]["bindings"]["with"]["rpc:counter/api"]
+                .as_str(),
+            Some("counter_types::api")
+        );
+    }
+
+    #[test]
+    fn crates_without_component_metadata_are_rejected() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let cargo_path = write_member(root.path(), "[package]\nname = \"caller\"\n");
+
+        assert!(!is_cargo_component_toml(&cargo_path).unwrap());
+        assert!(add_dependencies_to_cargo_toml(&cargo_path, &stub_dependencies()).is_err());
+    }
 }
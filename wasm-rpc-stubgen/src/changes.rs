@@ -0,0 +1,157 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    /// A new file is created
+    Create,
+    /// An existing file is replaced with a different content
+    Overwrite,
+    /// An existing file is edited in place, such as a Cargo.toml
+    Modify,
+}
+
+/// A change a command would make to the file system, reported by `--dry-run`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// The entries added to an edited file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<String>,
+}
+
+impl Display for FileChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let verb = match self.kind {
+            ChangeKind::Create => "create",
+            ChangeKind::Overwrite => "overwrite",
+            ChangeKind::Modify => "modify",
+        };
+        write!(f, "Would {verb} {}", self.path.to_string_lossy())?;
+        for edit in &self.edits {
+            write!(f, "\n  + {edit}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An edited manifest, not written yet
+pub struct ManifestUpdate {
+    pub path: PathBuf,
+    pub content: String,
+    /// The added entries, as TOML
+    pub edits: Vec<String>,
+}
+
+impl ManifestUpdate {
+    pub fn change(&self) -> Option<FileChange> {
+        file_change(&self.path, self.content.as_bytes()).map(|change| FileChange {
+            kind: ChangeKind::Modify,
+            edits: self.edits.clone(),
+            ..change
+        })
+    }
+}
+
+/// The change of writing the given content to a file, or `None` if the file already has it
+pub fn file_change(path: &Path, content: &[u8]) -> Option<FileChange> {
+    let kind = match fs::read(path) {
+        Ok(existing) if existing == content => return None,
+        Ok(_) => ChangeKind::Overwrite,
+        Err(_) => ChangeKind::Create,
+    };
+    Some(FileChange {
+        path: path.to_path_buf(),
+        kind,
+        edits: Vec::new(),
+    })
+}
+
+/// The changes of copying the files of a directory, recursively, into another one
+pub fn dir_copy_changes(source_dir: &Path, target_dir: &Path) -> anyhow::Result<Vec<FileChange>> {
+    let mut changes = Vec::new();
+    for (source, target) in copied_files(source_dir, target_dir)? {
+        let content = fs::read(&source).context(format!("Failed to read {source:?}"))?;
+        changes.extend(file_change(&target, &content));
+    }
+    Ok(changes)
+}
+
+/// The source and target paths of the files copied from a directory, recursively, into another one
+pub fn copied_files(
+    source_dir: &Path,
+    target_dir: &Path,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(source_dir)
+        .context(format!("Failed to read {source_dir:?}"))?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let source = entry.path();
+        let target = target_dir.join(entry.file_name());
+        if source.is_dir() {
+            files.extend(copied_files(&source, &target)?);
+        } else {
+            files.push((source, target));
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::changes::{dir_copy_changes, ChangeKind, FileChange};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn only_differing_files_are_changes() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+        fs::create_dir_all(source.path().join("nested")).unwrap();
+        fs::write(source.path().join("same.wit"), "a").unwrap();
+        fs::write(source.path().join("changed.wit"), "b").unwrap();
+        fs::write(source.path().join("nested").join("new.wit"), "c").unwrap();
+        fs::write(target.path().join("same.wit"), "a").unwrap();
+        fs::write(target.path().join("changed.wit"), "old").unwrap();
+
+        let changes = dir_copy_changes(source.path(), target.path()).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                FileChange {
+                    path: target.path().join("changed.wit"),
+                    kind: ChangeKind::Overwrite,
+                    edits: vec![],
+                },
+                FileChange {
+                    path: target.path().join("nested").join("new.wit"),
+                    kind: ChangeKind::Create,
+                    edits: vec![],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,1017 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::inspect::read_component;
+use crate::stub::{FunctionResultStub, FunctionStub};
+use anyhow::{anyhow, bail, Context};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use wasmparser::types::{
+    ComponentAnyTypeId, ComponentDefinedType, ComponentEntityType, ComponentValType, ResourceId,
+    Types, TypesRef,
+};
+use wasmparser::{Chunk, Parser, Payload, PrimitiveValType, Validator};
+use wit_parser::{Handle, PackageId, Resolve, Results, Type, TypeDefKind};
+
+/// The functions and types of an interface. Types are resolved structurally, so two interfaces are compatible if
+/// their signatures are equal, no matter how the types are named or aliased.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceShape {
+    pub functions: BTreeMap<String, FunctionType>,
+    pub types: BTreeMap<String, WitType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+    pub params: Vec<(String, WitType)>,
+    pub results: Vec<(Option<String>, WitType)>,
+}
+
+/// A type of an interface, displayed like it is written in WIT, with the records, variants, enums and flags
+/// written out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitType {
+    Primitive(PrimitiveValType),
+    Record(Vec<(String, WitType)>),
+    Variant(Vec<(String, Option<WitType>)>),
+    List(Box<WitType>),
+    Tuple(Vec<WitType>),
+    Flags(Vec<String>),
+    Enum(Vec<String>),
+    Option(Box<WitType>),
+    Result {
+        ok: Option<Box<WitType>>,
+        err: Option<Box<WitType>>,
+    },
+    Own(String),
+    Borrow(String),
+    /// The definition of a resource, only used among the types of an interface
+    Resource,
+}
+
+/// The type information of a validated component
+pub struct ComponentTypes {
+    types: Types,
+    exports: Vec<String>,
+}
+
+impl ComponentTypes {
+    pub fn new(bytes: &[u8], path: &Path) -> anyhow::Result<Self> {
+        let types = Validator::new()
+            .validate_all(bytes)
+            .context(format!("Failed to validate {path:?}"))?;
+        let exports = top_level_export_names(bytes).context(format!("Failed to parse {path:?}"))?;
+        Ok(Self { types, exports })
+    }
+
+    /// The shape of an instance imported by the component
+    pub fn imported_interface(&self, name: &str) -> anyhow::Result<InterfaceShape> {
+        let types = self.types.as_ref();
+        let entity = types
+            .component_entity_type_of_import(name)
+            .ok_or_else(|| anyhow!("{name} is not imported"))?;
+        interface_shape(types, entity).ok_or_else(|| anyhow!("{name} is not an interface"))
+    }
+
+    /// The shape of an instance exported by the component
+    pub fn exported_interface(&self, name: &str) -> anyhow::Result<InterfaceShape> {
+        let types = self.types.as_ref();
+        let entity = types
+            .component_entity_type_of_export(name)
+            .ok_or_else(|| anyhow!("{name} is not exported"))?;
+        interface_shape(types, entity).ok_or_else(|| anyhow!("{name} is not an interface"))
+    }
+
+    /// The shapes of all the instances exported by the component, by their names
+    pub fn exported_interfaces(&self) -> BTreeMap<String, InterfaceShape> {
+        let types = self.types.as_ref();
+        self.exports
+            .iter()
+            .filter_map(|name| {
+                let entity = types.component_entity_type_of_export(name)?;
+                Some((name.clone(), interface_shape(types, entity)?))
+            })
+            .collect()
+    }
+}
+
+fn top_level_export_names(bytes: &[u8]) -> anyhow::Result<Vec<String>> {
+    let mut parser = Parser::new(0);
+    let mut offset = 0;
+    let mut exports = Vec::new();
+    loop {
+        let payload = match parser.parse(&bytes[offset..], true)? {
+            Chunk::Parsed { payload, consumed } => {
+                offset += consumed;
+                payload
+            }
+            Chunk::NeedMoreData(_) => bail!("Unexpected end of the component"),
+        };
+        match payload {
+            Payload::ModuleSection { range, .. } | Payload::ComponentSection { range, .. } => {
+                offset += range.len();
+            }
+            Payload::ComponentExportSection(reader) => {
+                for export in reader {
+                    exports.push(export?.name.0.to_string());
+                }
+            }
+            Payload::End(_) => break,
+            _ => {}
+        }
+    }
+    Ok(exports)
+}
+
+fn interface_shape(types: TypesRef, entity: ComponentEntityType) -> Option<InterfaceShape> {
+    let ComponentEntityType::Instance(id) = entity else {
+        return None;
+    };
+    let instance = &types[id];
+
+    let resources = instance
+        .exports
+        .iter()
+        .filter_map(|(name, export)| match export {
+            ComponentEntityType::Type {
+                created: ComponentAnyTypeId::Resource(resource),
+                ..
+            } => Some((resource.resource(), name.clone())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let resolver = Resolver { types, resources };
+
+    let mut shape = InterfaceShape::default();
+    for (name, export) in &instance.exports {
+        match export {
+            ComponentEntityType::Func(id) => {
+                let func = &types[*id];
+                let function = FunctionType {
+                    params: func
+                        .params
+                        .iter()
+                        .map(|(name, ty)| (name.to_string(), resolver.val_type(ty)))
+                        .collect(),
+                    results: func
+                        .results
+                        .iter()
+                        .map(|(name, ty)| {
+                            (
+                                name.as_ref().map(|name| name.to_string()),
+                                resolver.val_type(ty),
+                            )
+                        })
+                        .collect(),
+                };
+                shape.functions.insert(name.clone(), function);
+            }
+            ComponentEntityType::Type {
+                created: ComponentAnyTypeId::Resource(_),
+                ..
+            } => {
+                shape.types.insert(name.clone(), WitType::Resource);
+            }
+            ComponentEntityType::Type {
+                created: ComponentAnyTypeId::Defined(id),
+                ..
+            } => {
+                shape
+                    .types
+                    .insert(name.clone(), resolver.defined_type(&types[*id]));
+            }
+            _ => {}
+        }
+    }
+    Some(shape)
+}
+
+struct Resolver<'a> {
+    types: TypesRef<'a>,
+    /// The names the resources are exported with from the interface
+    resources: Vec<(ResourceId, String)>,
+}
+
+impl Resolver<'_> {
+    fn val_type(&self, ty: &ComponentValType) -> WitType {
+        match ty {
+            ComponentValType::Primitive(primitive) => WitType::Primitive(*primitive),
+            ComponentValType::Type(id) => self.defined_type(&self.types[*id]),
+        }
+    }
+
+    fn opt_val_type(&self, ty: &Option<ComponentValType>) -> Option<Box<WitType>> {
+        ty.as_ref().map(|ty| Box::new(self.val_type(ty)))
+    }
+
+    fn resource(&self, id: ResourceId) -> String {
+        self.resources
+            .iter()
+            .find(|(resource, _)| *resource == id)
+            .map(|(_, name)| name.clone())
+            .unwrap_or_else(|| "resource".to_string())
+    }
+
+    fn defined_type(&self, ty: &ComponentDefinedType) -> WitType {
+        match ty {
+            ComponentDefinedType::Primitive(primitive) => WitType::Primitive(*primitive),
+            ComponentDefinedType::Record(record) => WitType::Record(
+                record
+                    .fields
+                    .iter()
+                    .map(|(name, ty)| (name.to_string(), self.val_type(ty)))
+                    .collect(),
+            ),
+            ComponentDefinedType::Variant(variant) => WitType::Variant(
+                variant
+                    .cases
+                    .iter()
+                    .map(|(name, case)| {
+                        (
+                            name.to_string(),
+                            case.ty.as_ref().map(|ty| self.val_type(ty)),
+                        )
+                    })
+                    .collect(),
+            ),
+            ComponentDefinedType::List(ty) => WitType::List(Box::new(self.val_type(ty))),
+            ComponentDefinedType::Tuple(tuple) => {
+                WitType::Tuple(tuple.types.iter().map(|ty| self.val_type(ty)).collect())
+            }
+            ComponentDefinedType::Flags(names) => {
+                WitType::Flags(names.iter().map(|name| name.to_string()).collect())
+            }
+            ComponentDefinedType::Enum(names) => {
+                WitType::Enum(names.iter().map(|name| name.to_string()).collect())
+            }
+            ComponentDefinedType::Option(ty) => WitType::Option(Box::new(self.val_type(ty))),
+            ComponentDefinedType::Result { ok, err } => WitType::Result {
+                ok: self.opt_val_type(ok),
+                err: self.opt_val_type(err),
+            },
+            ComponentDefinedType::Own(resource) => WitType::Own(self.resource(resource.resource())),
+            ComponentDefinedType::Borrow(resource) => {
+                WitType::Borrow(self.resource(resource.resource()))
+            }
+        }
+    }
+}
+
+/// The shapes of the interfaces of a resolved WIT package, by their names without the package
+pub fn package_interfaces(
+    resolve: &Resolve,
+    package: PackageId,
+) -> BTreeMap<String, InterfaceShape> {
+    resolve.packages[package]
+        .interfaces
+        .iter()
+        .map(|(name, id)| {
+            let interface = &resolve.interfaces[*id];
+            let functions = interface
+                .functions
+                .iter()
+                .map(|(name, function)| {
+                    let results = match &function.results {
+                        Results::Anon(typ) => vec![(None, wit_type(resolve, typ))],
+                        Results::Named(results) => results
+                            .iter()
+                            .map(|(name, typ)| (Some(name.clone()), wit_type(resolve, typ)))
+                            .collect(),
+                    };
+                    let function = FunctionType {
+                        params: function
+                            .params
+                            .iter()
+                            .map(|(name, typ)| (name.clone(), wit_type(resolve, typ)))
+                            .collect(),
+                        results,
+                    };
+                    (name.clone(), function)
+                })
+                .collect();
+            let types = interface
+                .types
+                .iter()
+                .map(|(name, id)| (name.clone(), wit_type(resolve, &Type::Id(*id))))
+                .collect();
+            (name.clone(), InterfaceShape { functions, types })
+        })
+        .collect()
+}
+
+/// The structural type of a type of a resolved WIT package
+pub fn wit_type(resolve: &Resolve, typ: &Type) -> WitType {
+    let typ = match typ {
+        Type::Bool => return WitType::Primitive(PrimitiveValType::Bool),
+        Type::U8 => return WitType::Primitive(PrimitiveValType::U8),
+        Type::U16 => return WitType::Primitive(PrimitiveValType::U16),
+        Type::U32 => return WitType::Primitive(PrimitiveValType::U32),
+        Type::U64 => return WitType::Primitive(PrimitiveValType::U64),
+        Type::S8 => return WitType::Primitive(PrimitiveValType::S8),
+        Type::S16 => return WitType::Primitive(PrimitiveValType::S16),
+        Type::S32 => return WitType::Primitive(PrimitiveValType::S32),
+        Type::S64 => return WitType::Primitive(PrimitiveValType::S64),
+        Type::Float32 => return WitType::Primitive(PrimitiveValType::Float32),
+        Type::Float64 => return WitType::Primitive(PrimitiveValType::Float64),
+        Type::Char => return WitType::Primitive(PrimitiveValType::Char),
+        Type::String => return WitType::Primitive(PrimitiveValType::String),
+        Type::Id(id) => &resolve.types[*id],
+    };
+    let resource = |id| {
+        resolve.types[id]
+            .name
+            .clone()
+            .unwrap_or_else(|| "resource".to_string())
+    };
+    let optional = |typ: &Option<Type>| typ.as_ref().map(|typ| Box::new(wit_type(resolve, typ)));
+    match &typ.kind {
+        TypeDefKind::Record(record) => WitType::Record(
+            record
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), wit_type(resolve, &field.ty)))
+                .collect(),
+        ),
+        TypeDefKind::Variant(variant) => WitType::Variant(
+            variant
+                .cases
+                .iter()
+                .map(|case| {
+                    (
+                        case.name.clone(),
+                        case.ty.as_ref().map(|typ| wit_type(resolve, typ)),
+                    )
+                })
+                .collect(),
+        ),
+        TypeDefKind::Enum(enum_) => {
+            WitType::Enum(enum_.cases.iter().map(|case| case.name.clone()).collect())
+        }
+        TypeDefKind::Flags(flags) => {
+            WitType::Flags(flags.flags.iter().map(|flag| flag.name.clone()).collect())
+        }
+        TypeDefKind::Tuple(tuple) => WitType::Tuple(
+            tuple
+                .types
+                .iter()
+                .map(|typ| wit_type(resolve, typ))
+                .collect(),
+        ),
+        TypeDefKind::List(typ) => WitType::List(Box::new(wit_type(resolve, typ))),
+        TypeDefKind::Option(typ) => WitType::Option(Box::new(wit_type(resolve, typ))),
+        TypeDefKind::Result(result) => WitType::Result {
+            ok: optional(&result.ok),
+            err: optional(&result.err),
+        },
+        TypeDefKind::Handle(Handle::Own(id)) => WitType::Own(resource(*id)),
+        TypeDefKind::Handle(Handle::Borrow(id)) => WitType::Borrow(resource(*id)),
+        TypeDefKind::Type(typ) => wit_type(resolve, typ),
+        TypeDefKind::Resource
+        | TypeDefKind::Future(_)
+        | TypeDefKind::Stream(_)
+        | TypeDefKind::Unknown => WitType::Resource,
+    }
+}
+
+impl Display for WitType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn list<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+            items.iter().map(f).collect::<Vec<_>>().join(", ")
+        }
+        let optional = |ty: &Option<Box<WitType>>| {
+            ty.as_ref()
+                .map(|ty| ty.to_string())
+                .unwrap_or_else(|| "_".to_string())
+        };
+        match self {
+            WitType::Primitive(primitive) => write!(f, "{primitive}"),
+            WitType::Record(fields) => write!(
+                f,
+                "record {{ {} }}",
+                list(fields, |(name, ty)| format!("{name}: {ty}"))
+            ),
+            WitType::Variant(cases) => write!(
+                f,
+                "variant {{ {} }}",
+                list(cases, |(name, ty)| match ty {
+                    Some(ty) => format!("{name}({ty})"),
+                    None => name.clone(),
+                })
+            ),
+            WitType::List(ty) => write!(f, "list<{ty}>"),
+            WitType::Tuple(types) => write!(f, "tuple<{}>", list(types, |ty| ty.to_string())),
+            WitType::Flags(names) => write!(f, "flags {{ {} }}", names.join(", ")),
+            WitType::Enum(names) => write!(f, "enum {{ {} }}", names.join(", ")),
+            WitType::Option(ty) => write!(f, "option<{ty}>"),
+            WitType::Result { ok, err } => {
+                write!(f, "result<{}, {}>", optional(ok), optional(err))
+            }
+            WitType::Own(resource) => write!(f, "own<{resource}>"),
+            WitType::Borrow(resource) => write!(f, "borrow<{resource}>"),
+            WitType::Resource => write!(f, "resource"),
+        }
+    }
+}
+
+impl Display for FunctionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "func({params})")?;
+        match self.results.as_slice() {
+            [] => Ok(()),
+            [(None, ty)] => write!(f, " -> {ty}"),
+            results => write!(
+                f,
+                " -> ({})",
+                results
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {ty}", name.clone().unwrap_or_default()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Describes how the interface exported by a stub fails to provide what the caller imports. Additional functions
+/// and types of the stub are not incompatibilities, as the caller does not use them.
+pub fn incompatibilities(caller: &InterfaceShape, stub: &InterfaceShape) -> Vec<String> {
+    fn compare<T: PartialEq + Display>(
+        issues: &mut Vec<String>,
+        kind: &str,
+        imported: &BTreeMap<String, T>,
+        exported: &BTreeMap<String, T>,
+    ) {
+        for (name, expected) in imported {
+            match exported.get(name) {
+                None => issues.push(format!("  missing {kind}: {name}")),
+                Some(actual) if actual != expected => issues.push(format!(
+                    "  changed {kind}: {name}\n    caller: {expected}\n    stub:   {actual}"
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let mut issues = Vec::new();
+    compare(&mut issues, "type", &caller.types, &stub.types);
+    compare(&mut issues, "function", &caller.functions, &stub.functions);
+    issues
+}
+
+/// A difference between the exported interfaces of two versions of a component
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceChange {
+    pub interface: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    /// Whether the callers of the old version may fail calling the new one
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl Display for InterfaceChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "{} {function}: {}", self.interface, self.description),
+            None => write!(f, "{}: {}", self.interface, self.description),
+        }
+    }
+}
+
+/// The differences between the exported interfaces of two versions of a component
+#[derive(Debug, Clone, Serialize)]
+pub struct CompatReport {
+    pub old_wasm: PathBuf,
+    pub new_wasm: PathBuf,
+    pub changes: Vec<InterfaceChange>,
+}
+
+impl CompatReport {
+    pub fn breaking_count(&self) -> usize {
+        self.changes.iter().filter(|change| change.breaking).count()
+    }
+}
+
+impl Display for CompatReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            let kind = if change.breaking {
+                "breaking"
+            } else {
+                "compatible"
+            };
+            writeln!(f, "{kind:<10}  {change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the interfaces exported by two versions of a component
+pub fn compare(old_wasm: &Path, new_wasm: &Path) -> anyhow::Result<CompatReport> {
+    let old = read_component_types(old_wasm)?;
+    let new = read_component_types(new_wasm)?;
+    Ok(CompatReport {
+        old_wasm: old_wasm.to_path_buf(),
+        new_wasm: new_wasm.to_path_buf(),
+        changes: interface_changes(&old.exported_interfaces(), &new.exported_interfaces()),
+    })
+}
+
+fn read_component_types(path: &Path) -> anyhow::Result<ComponentTypes> {
+    ComponentTypes::new(&read_component(path)?, path)
+}
+
+/// The direction a value is passed in an RPC call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    /// A parameter, sent by the caller to the called component
+    Parameter,
+    /// A result, sent back to the caller
+    Result,
+}
+
+/// Compares the exported interfaces of two versions of a component from the point of view of the callers built
+/// against the old one. Values are passed by the position of their fields and cases, so a parameter may only gain
+/// variant, enum or flag cases at the end, and a result may only lose them from the end.
+pub fn interface_changes(
+    old: &BTreeMap<String, InterfaceShape>,
+    new: &BTreeMap<String, InterfaceShape>,
+) -> Vec<InterfaceChange> {
+    let mut changes = Vec::new();
+    let change = |interface: &str, function: Option<&String>, breaking, description: String| {
+        InterfaceChange {
+            interface: interface.to_string(),
+            function: function.cloned(),
+            breaking,
+            description,
+        }
+    };
+    for (interface, old_shape) in old {
+        let Some(new_shape) = new.get(interface) else {
+            changes.push(change(interface, None, true, "removed".to_string()));
+            continue;
+        };
+        for (function, old_type) in &old_shape.functions {
+            match new_shape.functions.get(function) {
+                None => changes.push(change(
+                    interface,
+                    Some(function),
+                    true,
+                    "removed".to_string(),
+                )),
+                Some(new_type) if new_type != old_type => changes.push(change(
+                    interface,
+                    Some(function),
+                    !is_compatible_function(old_type, new_type),
+                    format!("changed from `{old_type}` to `{new_type}`"),
+                )),
+                Some(_) => {}
+            }
+        }
+        for function in new_shape.functions.keys() {
+            if !old_shape.functions.contains_key(function) {
+                changes.push(change(
+                    interface,
+                    Some(function),
+                    false,
+                    "added".to_string(),
+                ));
+            }
+        }
+    }
+    for interface in new.keys() {
+        if !old.contains_key(interface) {
+            changes.push(change(interface, None, false, "added".to_string()));
+        }
+    }
+    changes
+}
+
+fn is_compatible_function(old: &FunctionType, new: &FunctionType) -> bool {
+    // The names of the parameters and the results are not part of the calls
+    old.params.len() == new.params.len()
+        && old.results.len() == new.results.len()
+        && old
+            .params
+            .iter()
+            .zip(&new.params)
+            .all(|((_, old), (_, new))| is_compatible(old, new, Flow::Parameter))
+        && old
+            .results
+            .iter()
+            .zip(&new.results)
+            .all(|((_, old), (_, new))| is_compatible(old, new, Flow::Result))
+}
+
+fn is_compatible(old: &WitType, new: &WitType, flow: Flow) -> bool {
+    let optional = |old: &Option<Box<WitType>>, new: &Option<Box<WitType>>| match (old, new) {
+        (None, None) => true,
+        (Some(old), Some(new)) => is_compatible(old, new, flow),
+        _ => false,
+    };
+    match (old, new) {
+        (WitType::Record(old), WitType::Record(new)) => {
+            old.len() == new.len()
+                && old
+                    .iter()
+                    .zip(new)
+                    .all(|((old_name, old), (new_name, new))| {
+                        old_name == new_name && is_compatible(old, new, flow)
+                    })
+        }
+        (WitType::Variant(old), WitType::Variant(new)) => {
+            is_compatible_cases(old, new, flow, |(old_name, old), (new_name, new)| {
+                old_name == new_name
+                    && match (old, new) {
+                        (None, None) => true,
+                        (Some(old), Some(new)) => is_compatible(old, new, flow),
+                        _ => false,
+                    }
+            })
+        }
+        (WitType::Enum(old), WitType::Enum(new)) | (WitType::Flags(old), WitType::Flags(new)) => {
+            is_compatible_cases(old, new, flow, |old, new| old == new)
+        }
+        (WitType::List(old), WitType::List(new)) | (WitType::Option(old), WitType::Option(new)) => {
+            is_compatible(old, new, flow)
+        }
+        (WitType::Tuple(old), WitType::Tuple(new)) => {
+            old.len() == new.len()
+                && old
+                    .iter()
+                    .zip(new)
+                    .all(|(old, new)| is_compatible(old, new, flow))
+        }
+        (
+            WitType::Result {
+                ok: old_ok,
+                err: old_err,
+            },
+            WitType::Result {
+                ok: new_ok,
+                err: new_err,
+            },
+        ) => optional(old_ok, new_ok) && optional(old_err, new_err),
+        _ => old == new,
+    }
+}
+
+/// How a call of a function of the newer version of a component's API is passed to a target of an older version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Downgrade {
+    /// The call is passed unchanged
+    Unchanged,
+    /// The parameters of the older version, each taken from the parameter of the call with the same name, or `None`
+    /// for the optional parameters the newer version removed. The optional parameters the newer version added are
+    /// not passed.
+    Params(Vec<Option<usize>>),
+    /// The call cannot be passed to the older version, for the given reason
+    Unsupported(String),
+}
+
+/// Determines how the calls of a function of the newer version are passed to the older version of the function, if
+/// it exists. The values are passed as they are, so their types must be compatible with the older ones.
+pub fn downgrade(
+    newer_resolve: &Resolve,
+    newer: &FunctionStub,
+    older_resolve: &Resolve,
+    older: Option<&FunctionStub>,
+) -> Downgrade {
+    let Some(older) = older else {
+        return Downgrade::Unsupported("the function does not exist".to_string());
+    };
+    let results = |resolve: &Resolve, results: &FunctionResultStub| match results {
+        FunctionResultStub::Single(typ) => vec![wit_type(resolve, typ)],
+        FunctionResultStub::Multi(params) => params
+            .iter()
+            .map(|param| wit_type(resolve, &param.typ))
+            .collect(),
+        FunctionResultStub::SelfType => vec![],
+    };
+    let newer_results = results(newer_resolve, &newer.results);
+    let older_results = results(older_resolve, &older.results);
+    if newer_results.len() != older_results.len()
+        || newer_results
+            .iter()
+            .zip(&older_results)
+            .any(|(newer, older)| !is_compatible(newer, older, Flow::Result))
+    {
+        return Downgrade::Unsupported("the results changed".to_string());
+    }
+
+    let mut params = Vec::new();
+    for older_param in &older.params {
+        let older_type = wit_type(older_resolve, &older_param.typ);
+        match newer
+            .params
+            .iter()
+            .position(|param| param.name == older_param.name)
+        {
+            Some(idx) => {
+                let newer_type = wit_type(newer_resolve, &newer.params[idx].typ);
+                if !is_compatible(&newer_type, &older_type, Flow::Parameter) {
+                    return Downgrade::Unsupported(format!(
+                        "the type of parameter {} changed",
+                        older_param.name
+                    ));
+                }
+                params.push(Some(idx));
+            }
+            None if matches!(older_type, WitType::Option(_)) => params.push(None),
+            None => {
+                return Downgrade::Unsupported(format!(
+                    "parameter {} was removed",
+                    older_param.name
+                ))
+            }
+        }
+    }
+    for (idx, param) in newer.params.iter().enumerate() {
+        if !params.contains(&Some(idx))
+            && !matches!(wit_type(newer_resolve, &param.typ), WitType::Option(_))
+        {
+            return Downgrade::Unsupported(format!("parameter {} was added", param.name));
+        }
+    }
+
+    if params.len() == newer.params.len()
+        && params
+            .iter()
+            .enumerate()
+            .all(|(idx, param)| *param == Some(idx))
+    {
+        Downgrade::Unchanged
+    } else {
+        Downgrade::Params(params)
+    }
+}
+
+/// Cases are identified by their position, so the common ones must be the same, and only the receiving side may
+/// know more of them
+fn is_compatible_cases<T>(old: &[T], new: &[T], flow: Flow, same: impl Fn(&T, &T) -> bool) -> bool {
+    let count_ok = match flow {
+        Flow::Parameter => old.len() <= new.len(),
+        Flow::Result => new.len() <= old.len(),
+    };
+    count_ok && old.iter().zip(new).all(|(old, new)| same(old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compat::{
+        incompatibilities, interface_changes, package_interfaces, FunctionType, InterfaceShape,
+        WitType,
+    };
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use wasmparser::PrimitiveValType;
+    use wit_parser::{Resolve, UnresolvedPackage};
+
+    fn func(params: &[(&str, WitType)], result: Option<WitType>) -> FunctionType {
+        FunctionType {
+            params: params
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+            results: result.into_iter().map(|ty| (None, ty)).collect(),
+        }
+    }
+
+    fn shape(functions: &[(&str, FunctionType)], types: &[(&str, WitType)]) -> InterfaceShape {
+        InterfaceShape {
+            functions: functions
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+            types: types
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+        }
+    }
+
+    fn record(fields: &[(&str, WitType)]) -> WitType {
+        WitType::Record(
+            fields
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+        )
+    }
+
+    fn enumeration(cases: &[&str]) -> WitType {
+        WitType::Enum(cases.iter().map(|case| case.to_string()).collect())
+    }
+
+    const U32: WitType = WitType::Primitive(PrimitiveValType::U32);
+    const U64: WitType = WitType::Primitive(PrimitiveValType::U64);
+    const STRING: WitType = WitType::Primitive(PrimitiveValType::String);
+
+    fn api() -> WitType {
+        WitType::Borrow("api".to_string())
+    }
+
+    #[test]
+    fn stale_stub_signatures_are_reported() {
+        let constructor = func(
+            &[("location", record(&[("value", STRING)]))],
+            Some(WitType::Own("api".to_string())),
+        );
+        let caller = shape(
+            &[
+                ("[constructor]api", constructor.clone()),
+                (
+                    "[method]api.add",
+                    func(&[("self", api()), ("value", U64)], None),
+                ),
+                ("[method]api.reset", func(&[("self", api())], None)),
+            ],
+            &[
+                ("api", WitType::Resource),
+                ("options", record(&[("limit", U64), ("name", STRING)])),
+            ],
+        );
+        let stub = shape(
+            &[
+                ("[constructor]api", constructor),
+                (
+                    "[method]api.add",
+                    func(&[("self", api()), ("value", U32)], None),
+                ),
+                ("[method]api.get", func(&[("self", api())], Some(U64))),
+            ],
+            &[
+                ("api", WitType::Resource),
+                ("options", record(&[("limit", U64)])),
+            ],
+        );
+
+        assert_eq!(
+            incompatibilities(&caller, &stub),
+            vec![
+                "  changed type: options\n    caller: record { limit: u64, name: string }\n    stub:   record { limit: u64 }",
+                "  changed function: [method]api.add\n    caller: func(self: borrow<api>, value: u64)\n    stub:   func(self: borrow<api>, value: u32)",
+                "  missing function: [method]api.reset",
+            ]
+        );
+        assert!(incompatibilities(&stub, &stub).is_empty());
+    }
+
+    #[test]
+    fn changes_are_classified_for_callers_of_the_old_version() {
+        let old = BTreeMap::from([
+            (
+                "rpc:counter/api".to_string(),
+                shape(
+                    &[
+                        ("add", func(&[("value", U64)], None)),
+                        ("mode", func(&[], Some(enumeration(&["up", "down"])))),
+                        (
+                            "set-mode",
+                            func(&[("mode", enumeration(&["up", "down"]))], None),
+                        ),
+                        ("reset", func(&[], None)),
+                        ("get", func(&[], Some(U64))),
+                    ],
+                    &[],
+                ),
+            ),
+            ("rpc:counter/admin".to_string(), shape(&[], &[])),
+        ]);
+        let new = BTreeMap::from([
+            (
+                "rpc:counter/api".to_string(),
+                shape(
+                    &[
+                        ("add", func(&[("amount", U64)], None)),
+                        (
+                            "mode",
+                            func(&[], Some(enumeration(&["up", "down", "paused"]))),
+                        ),
+                        (
+                            "set-mode",
+                            func(&[("mode", enumeration(&["up", "down", "paused"]))], None),
+                        ),
+                        ("get", func(&[], Some(U32))),
+                        ("get-all", func(&[], Some(U64))),
+                    ],
+                    &[],
+                ),
+            ),
+            ("rpc:counter/metrics".to_string(), shape(&[], &[])),
+        ]);
+
+        let changes = interface_changes(&old, &new)
+            .into_iter()
+            .map(|change| {
+                (
+                    change.interface,
+                    change.function.unwrap_or_default(),
+                    change.breaking,
+                )
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("rpc:counter/admin", "", true),
+            ("rpc:counter/api", "add", false),
+            ("rpc:counter/api", "get", true),
+            ("rpc:counter/api", "mode", true),
+            ("rpc:counter/api", "reset", true),
+            ("rpc:counter/api", "set-mode", false),
+            ("rpc:counter/api", "get-all", false),
+            ("rpc:counter/metrics", "", false),
+        ]
+        .map(|(interface, function, breaking)| {
+            (interface.to_string(), function.to_string(), breaking)
+        });
+        assert_eq!(changes, expected);
+    }
+
+    /// The changes between two versions of a WIT package, like the ones reported when updating a stub dependency
+    fn package_changes(old_wit: &str, new_wit: &str) -> Vec<(String, String, bool)> {
+        let interfaces = |wit: &str| {
+            let mut resolve = Resolve::new();
+            let package = resolve
+                .push(UnresolvedPackage::parse(Path::new("stub.wit"), wit).unwrap())
+                .unwrap();
+            package_interfaces(&resolve, package)
+        };
+        interface_changes(&interfaces(old_wit), &interfaces(new_wit))
+            .into_iter()
+            .map(|change| {
+                (
+                    change.interface,
+                    change.function.unwrap_or_default(),
+                    change.breaking,
+                )
+            })
+            .collect()
+    }
+
+    const COUNTER_STUB_WIT: &str = r#"
+package rpc:counter-stub;
+
+interface stub-counter {
+  record state {
+    value: u64,
+  }
+
+  get: func() -> state;
+  inc: func(by: u64);
+}
+
+interface stub-admin {
+  reset: func();
+}
+"#;
+
+    #[test]
+    fn package_changes_are_reported() {
+        assert!(package_changes(COUNTER_STUB_WIT, COUNTER_STUB_WIT).is_empty());
+
+        let new_wit = COUNTER_STUB_WIT
+            .replace("inc: func(by: u64);", "dec: func(by: u64);")
+            .replace(
+                "interface stub-admin {\n  reset: func();",
+                "interface stub-events {",
+            );
+        let expected = [
+            ("stub-admin", "", true),
+            ("stub-counter", "inc", true),
+            ("stub-counter", "dec", false),
+            ("stub-events", "", false),
+        ]
+        .map(|(interface, function, breaking)| {
+            (interface.to_string(), function.to_string(), breaking)
+        });
+        assert_eq!(package_changes(COUNTER_STUB_WIT, &new_wit), expected);
+    }
+
+    #[test]
+    fn changed_shapes_of_named_types_are_reported() {
+        let new_wit = COUNTER_STUB_WIT.replace("value: u64,", "value: u64,\n    label: string,");
+        assert_eq!(
+            package_changes(COUNTER_STUB_WIT, &new_wit),
+            vec![("stub-counter".to_string(), "get".to_string(), true)]
+        );
+    }
+}
@@ -15,15 +15,205 @@
 use cargo_component::config::{CargoArguments, Config};
 use cargo_component::{load_component_metadata, load_metadata, run_cargo_command};
 use cargo_component_core::terminal::{Color, Terminal, Verbosity};
-use std::path::Path;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, instrument};
 
-pub async fn compile(root: &Path) -> anyhow::Result<()> {
-    let current_dir = std::env::current_dir()?;
-    std::env::set_current_dir(root)?;
+/// The Rust target the stubs are compiled to
+pub const WASM_TARGET: &str = "wasm32-wasi";
 
+/// The version of `cargo-component` embedded in the stub generator. Building stubs does not need the CLI, but the
+/// generated crates are meant to be compiled with a compatible one.
+const CARGO_COMPONENT_REQUIREMENT: &str = "^0.7.0";
+
+/// `wasm-tools` matching the `wasm-compose` and `wit-parser` versions used by the stub generator
+const WASM_TOOLS_REQUIREMENT: &str = ">=1.201.0";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolchainCheck {
+    pub tool: String,
+    pub requirement: String,
+    pub found: Option<String>,
+    /// Whether building stubs fails without this tool
+    pub required: bool,
+    pub satisfied: bool,
+    pub fix: Option<String>,
+}
+
+/// Result of checking the tools needed for building stubs
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolchainReport {
+    pub checks: Vec<ToolchainCheck>,
+}
+
+impl ToolchainReport {
+    /// Whether all the required tools are available
+    pub fn is_ok(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.satisfied || !check.required)
+    }
+
+    pub fn is_wasm_target_missing(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.tool == WASM_TARGET && !check.satisfied)
+    }
+}
+
+impl Display for ToolchainReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            let status = match (check.satisfied, check.required) {
+                (true, _) => "ok",
+                (false, true) => "missing",
+                (false, false) => "warning",
+            };
+            write!(f, "{status}: {} ({})", check.tool, check.requirement)?;
+            match &check.found {
+                Some(found) => writeln!(f, ", found {found}")?,
+                None => writeln!(f, ", not found")?,
+            }
+            if let (false, Some(fix)) = (check.satisfied, &check.fix) {
+                writeln!(f, "  fix: {fix}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks for cargo, the WASM target of the active Rust toolchain, `cargo-component` and `wasm-tools`
+pub fn check_toolchain() -> ToolchainReport {
+    let mut checks = Vec::new();
+
+    let cargo = tool_version("cargo", &["--version"]);
+    checks.push(ToolchainCheck {
+        tool: "cargo".to_string(),
+        requirement: "any".to_string(),
+        satisfied: cargo.is_some(),
+        found: cargo,
+        required: true,
+        fix: Some("Install Rust from https://rustup.rs".to_string()),
+    });
+
+    let installed_targets = Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).to_string());
+    let has_target = installed_targets
+        .as_ref()
+        .map(|targets| targets.lines().any(|line| line.trim() == WASM_TARGET));
+    checks.push(ToolchainCheck {
+        tool: WASM_TARGET.to_string(),
+        requirement: "installed Rust target".to_string(),
+        found: match has_target {
+            Some(true) => Some("installed".to_string()),
+            Some(false) => None,
+            None => Some("unknown, rustup is not available".to_string()),
+        },
+        // Without rustup the installed targets cannot be listed, so the build is attempted anyway
+        satisfied: has_target.unwrap_or(true),
+        required: true,
+        fix: Some(format!("rustup target add {WASM_TARGET}")),
+    });
+
+    checks.push(versioned_check(
+        "cargo-component",
+        &["component", "--version"],
+        "cargo",
+        CARGO_COMPONENT_REQUIREMENT,
+        "cargo install cargo-component --locked --version 0.7.0",
+    ));
+    checks.push(versioned_check(
+        "wasm-tools",
+        &["--version"],
+        "wasm-tools",
+        WASM_TOOLS_REQUIREMENT,
+        "cargo install wasm-tools --locked",
+    ));
+
+    ToolchainReport { checks }
+}
+
+/// Installs the WASM target of the active Rust toolchain with rustup
+pub fn install_wasm_target() -> anyhow::Result<()> {
+    let status = Command::new("rustup")
+        .args(["target", "add", WASM_TARGET])
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "rustup target add {WASM_TARGET} failed with {status}"
+        ))
+    }
+}
+
+fn versioned_check(
+    tool: &str,
+    args: &[&str],
+    command: &str,
+    requirement: &str,
+    fix: &str,
+) -> ToolchainCheck {
+    let found = tool_version(command, args);
+    let satisfied = match (&found, VersionReq::parse(requirement)) {
+        (Some(found), Ok(requirement)) => Version::parse(found)
+            .map(|version| requirement.matches(&version))
+            .unwrap_or(false),
+        _ => false,
+    };
+    ToolchainCheck {
+        tool: tool.to_string(),
+        requirement: requirement.to_string(),
+        found,
+        required: false,
+        satisfied,
+        fix: Some(fix.to_string()),
+    }
+}
+
+fn tool_version(command: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(command).args(args).output().ok()?;
+    if output.status.success() {
+        parse_tool_version(&String::from_utf8_lossy(&output.stdout))
+    } else {
+        None
+    }
+}
+
+/// Extracts the version from `<tool> <version> (<details>)` style outputs
+fn parse_tool_version(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .nth(1)
+        .map(|version| version.to_string())
+}
+
+/// Compiles the crate in the given root to the given target directory. The manifest and the target directory are passed
+/// to cargo explicitly instead of through the working directory and `CARGO_TARGET_DIR`, so concurrent compilations,
+/// within one process or by parallel processes, do not depend on or change shared state.
+///
+/// The `rustc_wrapper`, like `sccache`, is passed to cargo as its `build.rustc-wrapper` setting for the same reason,
+/// without it cargo uses the `RUSTC_WRAPPER` inherited from the environment, if any.
+#[instrument(name = "compile", skip_all, fields(crate_root = ?root))]
+pub async fn compile(
+    root: &Path,
+    target_dir: &Path,
+    rustc_wrapper: Option<&Path>,
+) -> anyhow::Result<()> {
+    debug!("Running cargo component build --release");
+    let manifest_path = root.join("Cargo.toml");
     let cargo_args = CargoArguments {
         release: true,
-        manifest_path: Some(root.join("Cargo.toml")),
+        manifest_path: Some(manifest_path.clone()),
         ..Default::default()
     };
 
@@ -33,16 +223,127 @@ pub async fn compile(root: &Path) -> anyhow::Result<()> {
     let packages =
         load_component_metadata(&metadata, cargo_args.packages.iter(), cargo_args.workspace)?;
 
+    let mut args = vec![
+        "build".to_string(),
+        "--release".to_string(),
+        "--manifest-path".to_string(),
+        manifest_path.to_string_lossy().to_string(),
+        "--target-dir".to_string(),
+        target_dir.to_string_lossy().to_string(),
+    ];
+    if let Some(rustc_wrapper) = rustc_wrapper {
+        debug!("Compiling with rustc wrapper {rustc_wrapper:?}");
+        args.push("--config".to_string());
+        args.push(format!(
+            "build.rustc-wrapper={}",
+            toml::Value::String(rustc_wrapper.to_string_lossy().to_string())
+        ));
+    }
+
     run_cargo_command(
         &config,
         &metadata,
         &packages,
         Some("build"),
         &cargo_args,
-        &["build".to_string(), "--release".to_string()],
+        &args,
     )
     .await?;
 
-    std::env::set_current_dir(current_dir)?;
     Ok(())
 }
+
+/// A key identifying the output of compiling the crate in the given root: the SHA-256 hash, in hexadecimal, of the
+/// Rust toolchain's `rustc -vV` output, the WASM target, the embedded `cargo-component` version and the paths and
+/// contents of the crate's files, except its target directory and `Cargo.lock`.
+///
+/// Caches of the compiled stubs, like the ones of CI runs, can be keyed by it. Dependencies taken from local paths
+/// are not part of the key, only where they are taken from.
+pub fn cache_key(root: &Path) -> anyhow::Result<String> {
+    let toolchain = Command::new("rustc").arg("-vV").output()?;
+    if !toolchain.status.success() {
+        return Err(anyhow::anyhow!(
+            "rustc -vV failed with {}",
+            toolchain.status
+        ));
+    }
+    crate_digest(root, &String::from_utf8_lossy(&toolchain.stdout))
+}
+
+fn crate_digest(root: &Path, toolchain: &str) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    for part in [toolchain, WASM_TARGET, CARGO_COMPONENT_REQUIREMENT] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+
+    let mut files = Vec::new();
+    crate_files(root, root, &mut files)?;
+    files.sort();
+    for relative in files {
+        hasher.update(relative.to_string_lossy().replace('\\', "/").as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(root.join(&relative))?);
+        hasher.update([0]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn crate_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(root)?.to_path_buf();
+        if relative == Path::new("target") || relative == Path::new("Cargo.lock") {
+            continue;
+        }
+        if path.is_dir() {
+            crate_files(root, &path, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compilation::{crate_digest, parse_tool_version};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn tool_versions_are_parsed() {
+        assert_eq!(
+            parse_tool_version("cargo 1.76.0 (c84b36747 2024-01-18)\n"),
+            Some("1.76.0".to_string())
+        );
+        assert_eq!(
+            parse_tool_version("cargo-component 0.7.0 (wasi:040ec92)\n"),
+            Some("0.7.0".to_string())
+        );
+        assert_eq!(parse_tool_version(""), None);
+    }
+
+    #[test]
+    fn cache_keys_follow_the_crate_contents() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(
+            root.path().join("Cargo.toml"),
+            "[package]\nname = \"stub\"\n",
+        )
+        .unwrap();
+        fs::write(root.path().join("src/lib.rs"), "pub fn stub() {}\n").unwrap();
+        let key = crate_digest(root.path(), "rustc 1.76.0").unwrap();
+
+        fs::create_dir_all(root.path().join("target/release")).unwrap();
+        fs::write(root.path().join("target/release/stub.wasm"), "wasm").unwrap();
+        fs::write(root.path().join("Cargo.lock"), "version = 3\n").unwrap();
+        assert_eq!(crate_digest(root.path(), "rustc 1.76.0").unwrap(), key);
+
+        assert_ne!(crate_digest(root.path(), "rustc 1.77.0").unwrap(), key);
+        fs::write(root.path().join("src/lib.rs"), "pub fn stub2() {}\n").unwrap();
+        assert_ne!(crate_digest(root.path(), "rustc 1.76.0").unwrap(), key);
+    }
+}
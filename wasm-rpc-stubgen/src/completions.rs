@@ -0,0 +1,98 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Context;
+use clap_complete::Shell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes the completion script of the command line interface for the given shell
+pub fn write_completions(mut command: clap::Command, shell: Shell, out: &mut dyn Write) {
+    let bin_name = command
+        .get_bin_name()
+        .unwrap_or(command.get_name())
+        .to_string();
+    clap_complete::generate(shell, &mut command, bin_name, out);
+}
+
+/// Writes a man page for the command and one for each of its visible subcommands, returning their paths
+pub fn write_man_pages(mut command: clap::Command, out_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    command.build();
+    fs::create_dir_all(out_dir).context(format!("Failed to create {out_dir:?}"))?;
+
+    let mut pages = Vec::new();
+    write_man_page(&command, out_dir, &mut pages)?;
+    Ok(pages)
+}
+
+fn write_man_page(
+    command: &clap::Command,
+    out_dir: &Path,
+    pages: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    // Building the command names the subcommands after their parents, like `wasm-rpc-stubgen-generate`
+    let name = command
+        .get_display_name()
+        .unwrap_or(command.get_name())
+        .to_string();
+    let mut content = Vec::new();
+    clap_mangen::Man::new(command.clone()).render(&mut content)?;
+
+    let path = out_dir.join(format!("{name}.1"));
+    fs::write(&path, content).context(format!("Failed to write {path:?}"))?;
+    pages.push(path);
+
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+    {
+        write_man_page(subcommand, out_dir, pages)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::completions::{write_completions, write_man_pages};
+    use crate::Cli;
+    use clap::CommandFactory;
+    use clap_complete::Shell;
+    use tempdir::TempDir;
+
+    #[test]
+    fn completions_cover_subcommands() {
+        let mut script = Vec::new();
+        write_completions(Cli::command(), Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.contains("wasm-rpc-stubgen"));
+        assert!(script.contains("add-stub-dependency"));
+    }
+
+    #[test]
+    fn man_pages_are_written_for_visible_subcommands() {
+        let out_dir = TempDir::new("stubgen-test").unwrap();
+        let pages = write_man_pages(Cli::command(), out_dir.path()).unwrap();
+
+        let names = pages
+            .iter()
+            .map(|page| page.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"wasm-rpc-stubgen.1".to_string()));
+        assert!(names.contains(&"wasm-rpc-stubgen-generate.1".to_string()));
+        assert!(!names.contains(&"wasm-rpc-stubgen-mangen.1".to_string()));
+        assert!(pages.iter().all(|page| page.is_file()));
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::{CommandError, ErrorKind};
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+
+/// How `add-stub-dependency` handles destination WIT files which differ from the stub's
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Do not change anything if any of the files differ
+    #[default]
+    Fail,
+    /// Keep the existing files, copying only the new ones
+    Keep,
+    /// Overwrite the existing files with the stub's
+    Overwrite,
+    /// Merge the stub's files into the existing ones, marking the existing lines the stub does not have
+    Merge,
+    /// Show the diff of each differing file and ask which of the above to do
+    Ask,
+}
+
+/// The chosen way of resolving a single conflicting file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resolution {
+    Keep,
+    Overwrite,
+    Merge,
+}
+
+/// A destination file which differs from the one the stub would copy over it
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub path: PathBuf,
+    pub existing: String,
+    pub incoming: String,
+}
+
+impl Conflict {
+    /// Resolves the conflict according to the policy, asking the user on the terminal with [ConflictPolicy::Ask]
+    pub fn resolve(&self, policy: ConflictPolicy) -> anyhow::Result<Resolution> {
+        match policy {
+            ConflictPolicy::Fail => Err(anyhow!(CommandError::new(
+                ErrorKind::Conflict,
+                format!("{:?} already exists with a different content", self.path)
+            ))),
+            ConflictPolicy::Keep => Ok(Resolution::Keep),
+            ConflictPolicy::Overwrite => Ok(Resolution::Overwrite),
+            ConflictPolicy::Merge => Ok(Resolution::Merge),
+            ConflictPolicy::Ask => self.ask(),
+        }
+    }
+
+    /// The content the file has after applying the resolution
+    pub fn resolved_content(&self, resolution: Resolution) -> String {
+        match resolution {
+            Resolution::Keep => self.existing.clone(),
+            Resolution::Overwrite => self.incoming.clone(),
+            Resolution::Merge => merge(&self.existing, &self.incoming),
+        }
+    }
+
+    fn ask(&self) -> anyhow::Result<Resolution> {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            bail!(CommandError::new(
+                ErrorKind::InvalidArguments,
+                format!(
+                    "Cannot ask how to resolve the conflict in {:?} because the standard input is not a terminal. Use --on-conflict with keep, overwrite or merge instead.",
+                    self.path
+                )
+            ));
+        }
+
+        let mut stderr = std::io::stderr();
+        writeln!(
+            stderr,
+            "{:?} differs from the stub's version:\n{}",
+            self.path,
+            diff(&self.existing, &self.incoming)
+        )?;
+        loop {
+            write!(
+                stderr,
+                "[k]eep the existing file, [o]verwrite it or [m]erge both? "
+            )?;
+            stderr.flush()?;
+
+            let mut answer = String::new();
+            if stdin.lock().read_line(&mut answer)? == 0 {
+                bail!("No answer given for {:?}", self.path);
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "k" | "keep" => return Ok(Resolution::Keep),
+                "o" | "overwrite" => return Ok(Resolution::Overwrite),
+                "m" | "merge" => return Ok(Resolution::Merge),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A line based diff of two texts, using their longest common subsequence
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Renders the changed lines of a diff with two lines of context around them
+pub fn diff(old: &str, new: &str) -> String {
+    const CONTEXT: usize = 2;
+
+    let lines = diff_lines(old, new);
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+
+    let mut result = String::new();
+    let mut last_shown = None;
+    for (idx, line) in lines.iter().enumerate() {
+        let near_change = changed
+            .iter()
+            .any(|changed_idx| idx + CONTEXT >= *changed_idx && idx <= changed_idx + CONTEXT);
+        if !near_change {
+            continue;
+        }
+        if last_shown.is_some_and(|last| last + 1 < idx) {
+            result.push_str("  ...\n");
+        }
+        last_shown = Some(idx);
+        match line {
+            Line::Same(text) => result.push_str(&format!("  {text}\n")),
+            Line::Removed(text) => result.push_str(&format!("- {text}\n")),
+            Line::Added(text) => result.push_str(&format!("+ {text}\n")),
+        }
+    }
+    result
+}
+
+/// Merges the stub's version of a file into the existing one. Lines only the stub has are added, while the existing
+/// lines the stub does not have are surrounded by conflict markers to be resolved by hand, as without the previously
+/// generated version it is unknown whether the stub removed them or they were added locally.
+pub fn merge(existing: &str, incoming: &str) -> String {
+    let mut result = String::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+
+    for line in diff_lines(existing, incoming) {
+        match line {
+            Line::Same(text) => {
+                flush_hunk(&mut result, &mut removed, &mut added);
+                result.push_str(&format!("{text}\n"));
+            }
+            Line::Removed(text) => removed.push(text),
+            Line::Added(text) => added.push(text),
+        }
+    }
+    flush_hunk(&mut result, &mut removed, &mut added);
+    result
+}
+
+/// Writes the lines of a changed hunk, with conflict markers if it has existing lines missing from the stub
+fn flush_hunk(result: &mut String, removed: &mut Vec<&str>, added: &mut Vec<&str>) {
+    if !removed.is_empty() {
+        result.push_str("<<<<<<< existing\n");
+        for line in removed.iter() {
+            result.push_str(&format!("{line}\n"));
+        }
+        result.push_str("=======\n");
+        for line in added.iter() {
+            result.push_str(&format!("{line}\n"));
+        }
+        result.push_str(">>>>>>> stub\n");
+    } else {
+        for line in added.iter() {
+            result.push_str(&format!("{line}\n"));
+        }
+    }
+    removed.clear();
+    added.clear();
+}
+
+/// Whether a merged file still has conflict markers to be resolved by hand
+pub fn has_conflict_markers(content: &str) -> bool {
+    content.lines().any(|line| line == "<<<<<<< existing")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conflict::{diff, has_conflict_markers, merge};
+
+    #[test]
+    fn merge_marks_the_replaced_lines() {
+        let existing = "package a:b;\ninterface api {\n  f: func();\n  local: func();\n}\n";
+        let incoming = "package a:b;\ninterface api {\n  f: func();\n  g: func();\n}\n";
+
+        let merged = merge(existing, incoming);
+        assert_eq!(
+            merged,
+            "package a:b;\ninterface api {\n  f: func();\n<<<<<<< existing\n  local: func();\n=======\n  g: func();\n>>>>>>> stub\n}\n"
+        );
+        assert!(has_conflict_markers(&merged));
+    }
+
+    #[test]
+    fn merge_adds_the_new_lines_of_the_stub() {
+        let merged = merge("a\nc\n", "a\nb\nc\n");
+        assert_eq!(merged, "a\nb\nc\n");
+        assert!(!has_conflict_markers(&merged));
+    }
+
+    #[test]
+    fn merge_marks_the_lines_removed_from_the_stub() {
+        let existing = "package a:b;\ninterface api {\n  f: func();\n  g: func();\n}\n";
+        let incoming = "package a:b;\ninterface api {\n  f: func();\n}\n";
+
+        let merged = merge(existing, incoming);
+        assert_eq!(
+            merged,
+            "package a:b;\ninterface api {\n  f: func();\n<<<<<<< existing\n  g: func();\n=======\n>>>>>>> stub\n}\n"
+        );
+        assert!(has_conflict_markers(&merged));
+    }
+
+    #[test]
+    fn diff_shows_context_around_changes() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\n5\nsix\n7\n8\n9\n";
+
+        assert_eq!(diff(old, new), "  4\n  5\n- 6\n+ six\n  7\n  8\n");
+    }
+}
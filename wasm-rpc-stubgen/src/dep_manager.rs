@@ -0,0 +1,412 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::changes::ManifestUpdate;
+use crate::conflict::ConflictPolicy;
+use crate::error::{CommandError, ErrorKind};
+use crate::paths::{canonicalize, manifest_path};
+use anyhow::{anyhow, bail, Context};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+use tracing::info;
+
+/// An external tool managing the contents of a WIT root's `deps` directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyManager {
+    /// [wit-deps](https://github.com/bytecodealliance/wit-deps), configured by `wit/deps.toml`
+    WitDeps { manifest: PathBuf },
+    /// [wkg](https://github.com/bytecodealliance/wasm-pkg-tools), configured by `wkg.toml`
+    Wkg { manifest: PathBuf },
+}
+
+/// A WIT dependency to be registered in a dependency manager's manifest
+#[derive(Debug, Clone)]
+pub struct ManagedDependency {
+    /// Name of the directory in `deps`
+    pub dir_name: String,
+    /// Name of the WIT package, without version
+    pub package_name: String,
+    /// Directory containing the dependency's WIT files
+    pub source: PathBuf,
+}
+
+impl Display for DependencyManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyManager::WitDeps { manifest } => {
+                write!(f, "wit-deps ({})", manifest.to_string_lossy())
+            }
+            DependencyManager::Wkg { manifest } => {
+                write!(f, "wkg ({})", manifest.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl DependencyManager {
+    /// Detects whether the given WIT root's dependencies are managed by wit-deps or wkg
+    pub fn detect(wit_root: &Path) -> Option<DependencyManager> {
+        let deps_toml = wit_root.join("deps.toml");
+        if deps_toml.is_file() {
+            return Some(DependencyManager::WitDeps {
+                manifest: deps_toml,
+            });
+        }
+
+        let project_root = wit_root.parent().unwrap_or(Path::new("."));
+        for candidate in [wit_root.join("wkg.toml"), project_root.join("wkg.toml")] {
+            if candidate.is_file() {
+                return Some(DependencyManager::Wkg {
+                    manifest: candidate,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// The manifest the dependencies are registered in
+    pub fn manifest(&self) -> &Path {
+        match self {
+            DependencyManager::WitDeps { manifest } | DependencyManager::Wkg { manifest } => {
+                manifest
+            }
+        }
+    }
+
+    /// The command the user has to run to fetch the registered dependencies into `deps`
+    pub fn fetch_command(&self) -> &'static str {
+        match self {
+            DependencyManager::WitDeps { .. } => "wit-deps update",
+            DependencyManager::Wkg { .. } => "wkg wit fetch",
+        }
+    }
+
+    /// Registers the given dependencies as local path dependencies in the manager's manifest,
+    /// keeping all the other entries and formatting. Entries already registering a dependency with a different source
+    /// are handled according to the policy, which can be [ConflictPolicy::Fail], [ConflictPolicy::Keep] or
+    /// [ConflictPolicy::Overwrite].
+    pub fn add_dependencies(
+        &self,
+        dependencies: &[ManagedDependency],
+        policy: ConflictPolicy,
+    ) -> anyhow::Result<()> {
+        let update = self.plan_dependencies(dependencies, policy)?;
+        info!("Writing updated {:?}", update.path);
+        fs::write(&update.path, update.content)?;
+        Ok(())
+    }
+
+    /// Whether the dependency is registered in the manager's manifest, with any source. The managed dependencies are
+    /// fetched into `deps` by the manager, so its manifest tells which ones the WIT root has, not the directories.
+    pub fn is_registered(&self, dependency: &ManagedDependency) -> anyhow::Result<bool> {
+        let manifest = self.manifest();
+        let contents = fs::read_to_string(manifest)
+            .context(format!("Failed to read {}", manifest.to_string_lossy()))?;
+        let doc = contents
+            .parse::<DocumentMut>()
+            .context(format!("Failed to parse {}", manifest.to_string_lossy()))?;
+        let table = match self {
+            DependencyManager::WitDeps { .. } => Some(doc.as_item()),
+            DependencyManager::Wkg { .. } => doc.get("overrides"),
+        };
+        Ok(table
+            .and_then(Item::as_table_like)
+            .is_some_and(|table| table.contains_key(self.key(dependency))))
+    }
+
+    /// The key of a dependency in the manager's manifest
+    fn key<'a>(&self, dependency: &'a ManagedDependency) -> &'a str {
+        match self {
+            DependencyManager::WitDeps { .. } => &dependency.dir_name,
+            DependencyManager::Wkg { .. } => &dependency.package_name,
+        }
+    }
+
+    /// Computes the manifest edits of [DependencyManager::add_dependencies] without writing them
+    pub fn plan_dependencies(
+        &self,
+        dependencies: &[ManagedDependency],
+        policy: ConflictPolicy,
+    ) -> anyhow::Result<ManifestUpdate> {
+        let manifest = self.manifest();
+        let manifest_dir = manifest
+            .parent()
+            .context("Parent directory of the dependency manifest")?;
+
+        let contents = fs::read_to_string(manifest)
+            .context(format!("Failed to read {}", manifest.to_string_lossy()))?;
+        let mut doc = contents
+            .parse::<DocumentMut>()
+            .context(format!("Failed to parse {}", manifest.to_string_lossy()))?;
+
+        let table = match self {
+            DependencyManager::WitDeps { .. } => doc.as_table_mut(),
+            DependencyManager::Wkg { .. } => {
+                let overrides = doc
+                    .entry("overrides")
+                    .or_insert(Item::Table(Table::new()))
+                    .as_table_mut()
+                    .ok_or(anyhow!(
+                        "The overrides entry of {manifest:?} is not a table"
+                    ))?;
+                overrides.set_implicit(true);
+                overrides
+            }
+        };
+
+        let mut edits = Vec::new();
+        for dependency in dependencies {
+            let key = self.key(dependency);
+            let path = manifest_path(&relative_path(&dependency.source, manifest_dir)?);
+            if let Some(existing) = table.get(key) {
+                let existing_path = existing
+                    .as_table_like()
+                    .and_then(|existing| existing.get("path"))
+                    .and_then(|path| path.as_str());
+                if existing_path == Some(path.as_str()) {
+                    continue;
+                }
+                match policy {
+                    ConflictPolicy::Keep => continue,
+                    ConflictPolicy::Overwrite => {}
+                    ConflictPolicy::Fail => bail!(CommandError::new(
+                        ErrorKind::Conflict,
+                        format!(
+                            "{key} is already registered in {manifest:?} with a different source. Use --on-conflict keep or overwrite to resolve it."
+                        )
+                    )),
+                    ConflictPolicy::Merge | ConflictPolicy::Ask => bail!(CommandError::new(
+                        ErrorKind::InvalidArguments,
+                        format!("Dependencies registered in {manifest:?} can only be kept or overwritten")
+                    )),
+                }
+            }
+
+            let mut entry = InlineTable::new();
+            entry.insert("path", Value::from(path));
+            edits.push(format!("\"{key}\" = {entry}"));
+            table.insert(key, Item::Value(Value::InlineTable(entry)));
+        }
+
+        Ok(ManifestUpdate {
+            path: manifest.to_path_buf(),
+            content: doc.to_string(),
+            edits,
+        })
+    }
+}
+
+fn relative_path(source: &Path, base: &Path) -> anyhow::Result<PathBuf> {
+    let source = canonicalize(source).context(format!("Failed to resolve {source:?}"))?;
+    let base = canonicalize(base).context(format!("Failed to resolve {base:?}"))?;
+    pathdiff::diff_paths(&source, &base)
+        .ok_or(anyhow!("Cannot express {source:?} relative to {base:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conflict::ConflictPolicy;
+    use crate::dep_manager::{DependencyManager, ManagedDependency};
+    use crate::error::ErrorKind;
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    fn stub_dependencies(root: &Path) -> Vec<ManagedDependency> {
+        let stub_wit = root.join("stub").join("wit");
+        let dep_dir = stub_wit.join("deps").join("wasm-rpc");
+        fs::create_dir_all(&dep_dir).unwrap();
+
+        vec![
+            ManagedDependency {
+                dir_name: "wasm-rpc".to_string(),
+                package_name: "golem:rpc".to_string(),
+                source: dep_dir,
+            },
+            ManagedDependency {
+                dir_name: "test_main-stub".to_string(),
+                package_name: "test:main-stub".to_string(),
+                source: stub_wit,
+            },
+        ]
+    }
+
+    #[test]
+    fn no_manager_detected_for_plain_layout() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit_root = root.path().join("caller").join("wit");
+        fs::create_dir_all(&wit_root).unwrap();
+
+        assert_eq!(DependencyManager::detect(&wit_root), None);
+    }
+
+    #[test]
+    fn wit_deps_manifest_is_updated() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit_root = root.path().join("caller").join("wit");
+        fs::create_dir_all(&wit_root).unwrap();
+        let manifest = wit_root.join("deps.toml");
+        fs::write(
+            &manifest,
+            "# managed by wit-deps\nio = \"https://github.com/WebAssembly/wasi-io/archive/v0.2.0.tar.gz\"\n",
+        )
+        .unwrap();
+
+        let manager = DependencyManager::detect(&wit_root).unwrap();
+        assert_eq!(
+            manager,
+            DependencyManager::WitDeps {
+                manifest: manifest.clone()
+            }
+        );
+
+        manager
+            .add_dependencies(&stub_dependencies(root.path()), ConflictPolicy::Fail)
+            .unwrap();
+
+        let result = fs::read_to_string(&manifest).unwrap();
+        assert_eq!(
+            result,
+            "# managed by wit-deps\n\
+             io = \"https://github.com/WebAssembly/wasi-io/archive/v0.2.0.tar.gz\"\n\
+             wasm-rpc = { path = \"../../stub/wit/deps/wasm-rpc\" }\n\
+             test_main-stub = { path = \"../../stub/wit\" }\n"
+        );
+
+        // Adding the same dependencies again does not duplicate them
+        manager
+            .add_dependencies(&stub_dependencies(root.path()), ConflictPolicy::Fail)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), result);
+    }
+
+    #[test]
+    fn wkg_manifest_is_updated() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let project_root = root.path().join("caller");
+        let wit_root = project_root.join("wit");
+        fs::create_dir_all(&wit_root).unwrap();
+        let manifest = project_root.join("wkg.toml");
+        fs::write(
+            &manifest,
+            "[overrides]\n\"wasi:io\" = { path = \"../wasi-io\" }\n",
+        )
+        .unwrap();
+
+        let manager = DependencyManager::detect(&wit_root).unwrap();
+        assert_eq!(
+            manager,
+            DependencyManager::Wkg {
+                manifest: manifest.clone()
+            }
+        );
+
+        manager
+            .add_dependencies(&stub_dependencies(root.path()), ConflictPolicy::Fail)
+            .unwrap();
+
+        let result = fs::read_to_string(&manifest).unwrap();
+        assert_eq!(
+            result,
+            "[overrides]\n\
+             \"wasi:io\" = { path = \"../wasi-io\" }\n\
+             \"golem:rpc\" = { path = \"../stub/wit/deps/wasm-rpc\" }\n\
+             \"test:main-stub\" = { path = \"../stub/wit\" }\n"
+        );
+    }
+
+    #[test]
+    fn wkg_manifest_without_overrides_is_updated() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let project_root = root.path().join("caller");
+        let wit_root = project_root.join("wit");
+        fs::create_dir_all(&wit_root).unwrap();
+        let manifest = project_root.join("wkg.toml");
+        fs::write(&manifest, "").unwrap();
+
+        let manager = DependencyManager::detect(&wit_root).unwrap();
+        manager
+            .add_dependencies(&stub_dependencies(root.path()), ConflictPolicy::Fail)
+            .unwrap();
+
+        let result = fs::read_to_string(&manifest).unwrap();
+        assert_eq!(
+            result,
+            "[overrides]\n\
+             \"golem:rpc\" = { path = \"../stub/wit/deps/wasm-rpc\" }\n\
+             \"test:main-stub\" = { path = \"../stub/wit\" }\n"
+        );
+    }
+
+    #[test]
+    fn differing_entries_follow_the_conflict_policy() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit_root = root.path().join("caller").join("wit");
+        fs::create_dir_all(&wit_root).unwrap();
+        let manifest = wit_root.join("deps.toml");
+        let existing = "test_main-stub = { path = \"../../other/wit\" }\n";
+        fs::write(&manifest, existing).unwrap();
+        let manager = DependencyManager::detect(&wit_root).unwrap();
+        let dependencies = stub_dependencies(root.path());
+
+        let err = manager
+            .add_dependencies(&dependencies, ConflictPolicy::Fail)
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Conflict);
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), existing);
+
+        let err = manager
+            .add_dependencies(&dependencies, ConflictPolicy::Merge)
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::InvalidArguments);
+
+        manager
+            .add_dependencies(&dependencies, ConflictPolicy::Keep)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&manifest).unwrap(),
+            "test_main-stub = { path = \"../../other/wit\" }\n\
+             wasm-rpc = { path = \"../../stub/wit/deps/wasm-rpc\" }\n"
+        );
+
+        manager
+            .add_dependencies(&dependencies, ConflictPolicy::Overwrite)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&manifest).unwrap(),
+            "test_main-stub = { path = \"../../stub/wit\" }\n\
+             wasm-rpc = { path = \"../../stub/wit/deps/wasm-rpc\" }\n"
+        );
+    }
+
+    #[test]
+    fn registered_dependencies_are_found_in_the_manifest() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit_root = root.path().join("caller").join("wit");
+        fs::create_dir_all(&wit_root).unwrap();
+        fs::write(root.path().join("caller").join("wkg.toml"), "").unwrap();
+        let dependencies = stub_dependencies(root.path());
+
+        let manager = DependencyManager::detect(&wit_root).unwrap();
+        assert!(!manager.is_registered(&dependencies[1]).unwrap());
+        manager
+            .add_dependencies(&dependencies[1..], ConflictPolicy::Fail)
+            .unwrap();
+        assert!(manager.is_registered(&dependencies[1]).unwrap());
+        assert!(!manager.is_registered(&dependencies[0]).unwrap());
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api;
+use crate::output::DetailedError;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+/// The category of a failed command. Each kind has a stable code in the JSON output and its own process exit code, so
+/// wrapper scripts can branch on the failure without parsing the messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// Any other failure
+    Failed,
+    /// The arguments cannot be used together. Usage errors reported by the argument parser use the same exit code.
+    InvalidArguments,
+    /// A WIT root cannot be parsed or resolved
+    InvalidWit,
+    /// A stub or a file the command works on is missing
+    MissingDependency,
+    /// Existing files at the destination differ from the ones to be written
+    Conflict,
+    /// Tools required for building the stub are missing
+    MissingTools,
+    /// Writing the stub crate failed
+    Generation,
+    /// Compiling the generated stub crate failed
+    Compilation,
+    /// Composing the components failed
+    Composition,
+    /// A check, such as `validate`, found errors
+    CheckFailed,
+    /// Writing the results to their destination failed
+    Output,
+}
+
+impl ErrorKind {
+    /// All the kinds, in the order of their exit codes
+    pub const ALL: [ErrorKind; 11] = [
+        ErrorKind::Failed,
+        ErrorKind::InvalidArguments,
+        ErrorKind::InvalidWit,
+        ErrorKind::MissingDependency,
+        ErrorKind::Conflict,
+        ErrorKind::MissingTools,
+        ErrorKind::Generation,
+        ErrorKind::Compilation,
+        ErrorKind::Composition,
+        ErrorKind::CheckFailed,
+        ErrorKind::Output,
+    ];
+
+    /// The identifier of the kind in the JSON output
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Failed => "failed",
+            ErrorKind::InvalidArguments => "invalid-arguments",
+            ErrorKind::InvalidWit => "invalid-wit",
+            ErrorKind::MissingDependency => "missing-dependency",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::MissingTools => "missing-tools",
+            ErrorKind::Generation => "generation-failed",
+            ErrorKind::Compilation => "compilation-failed",
+            ErrorKind::Composition => "composition-failed",
+            ErrorKind::CheckFailed => "check-failed",
+            ErrorKind::Output => "output-failed",
+        }
+    }
+
+    /// The exit code of the process failing with this kind of error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Failed => 1,
+            ErrorKind::InvalidArguments => 2,
+            ErrorKind::InvalidWit => 3,
+            ErrorKind::MissingDependency => 4,
+            ErrorKind::Conflict => 5,
+            ErrorKind::MissingTools => 6,
+            ErrorKind::Generation => 7,
+            ErrorKind::Compilation => 8,
+            ErrorKind::Composition => 9,
+            ErrorKind::CheckFailed => 10,
+            ErrorKind::Output => 11,
+        }
+    }
+
+    /// The kind of a command's error, looking through the context added to it
+    pub fn of(err: &anyhow::Error) -> ErrorKind {
+        if let Some(err) = err.downcast_ref::<CommandError>() {
+            err.kind
+        } else if let Some(err) = err.downcast_ref::<DetailedError>() {
+            err.kind
+        } else if let Some(err) = err.downcast_ref::<api::Error>() {
+            match err {
+                api::Error::InvalidWit(_) => ErrorKind::InvalidWit,
+                api::Error::Generation(_) => ErrorKind::Generation,
+                api::Error::MissingTools(_) => ErrorKind::MissingTools,
+                api::Error::Compilation(_) => ErrorKind::Compilation,
+                api::Error::Composition(_) => ErrorKind::Composition,
+                api::Error::Output(_) => ErrorKind::Output,
+                api::Error::BreakingChanges(_) => ErrorKind::CheckFailed,
+            }
+        } else {
+            ErrorKind::Failed
+        }
+    }
+}
+
+/// A command failure of a known [ErrorKind]
+#[derive(Debug)]
+pub struct CommandError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl CommandError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::api;
+    use crate::error::{CommandError, ErrorKind};
+    use anyhow::{anyhow, Context};
+    use std::collections::HashSet;
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = ErrorKind::ALL
+            .iter()
+            .map(|kind| kind.exit_code())
+            .collect::<HashSet<_>>();
+        assert_eq!(codes.len(), ErrorKind::ALL.len());
+        assert!(!codes.contains(&0));
+    }
+
+    #[test]
+    fn kind_is_found_through_context() {
+        let err = Err::<(), _>(anyhow!(CommandError::new(
+            ErrorKind::Conflict,
+            "files differ"
+        )))
+        .context("Failed to add the stub")
+        .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Conflict);
+
+        let err = Err::<(), _>(anyhow!(api::Error::Composition("no match".to_string())))
+            .context("Failed to compose")
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Composition);
+
+        assert_eq!(ErrorKind::of(&anyhow!("unknown")), ErrorKind::Failed);
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, bail, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const BEGIN_GUARD: &str = "# BEGIN wasm-rpc-stubgen managed entries";
+const END_GUARD: &str = "# END wasm-rpc-stubgen managed entries";
+
+/// Directories never searched for `.gitignore` files by [managed_artifacts]
+const SKIPPED_DIRS: [&str; 3] = [".git", "target", "node_modules"];
+
+/// Adds the paths of generated artifacts to the managed section of the `.gitignore` in the given directory, creating
+/// the file or the section if needed. The entries are relative to the directory, and the ones already listed are
+/// kept. Outside of git repositories nothing is written, returning whether the file was changed. Entries naming the
+/// directory itself, like `/` or `.`, are rejected.
+pub fn add_managed_entries(dir: &Path, entries: &[String]) -> anyhow::Result<bool> {
+    if let Some(entry) = entries.iter().find(|entry| is_root_like(entry)) {
+        bail!("{entry} would list the whole {dir:?} directory as a generated artifact");
+    }
+    if !is_in_git_repository(dir) {
+        return Ok(false);
+    }
+    let path = dir.join(".gitignore");
+    let existing = if path.is_file() {
+        fs::read_to_string(&path).context(format!("Failed to read {path:?}"))?
+    } else {
+        String::new()
+    };
+    let updated = with_managed_entries(&existing, entries);
+    if updated == existing {
+        return Ok(false);
+    }
+    info!("Updating the managed entries of {path:?}");
+    fs::write(&path, updated).context(format!("Failed to write {path:?}"))?;
+    Ok(true)
+}
+
+/// Adds a generated file or directory to the managed section of the `.gitignore` next to it, see
+/// [add_managed_entries]. Crate roots are never listed, only their build outputs, see [add_build_outputs].
+pub fn add_managed_artifact(path: &Path) -> anyhow::Result<bool> {
+    let path = crate::paths::canonicalize(path).context(format!("Failed to resolve {path:?}"))?;
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(false);
+    };
+    let name = name
+        .to_str()
+        .ok_or(anyhow!("The name of {path:?} is not valid UTF-8"))?;
+    if path.join("Cargo.toml").is_file() {
+        bail!("{path:?} is a crate root, which is not a generated artifact");
+    }
+    let entry = if path.is_dir() {
+        format!("/{name}/")
+    } else {
+        format!("/{name}")
+    };
+    add_managed_entries(dir, &[entry])
+}
+
+/// Lists the build outputs of a generated crate in the managed section of the `.gitignore` in its root. The crate
+/// itself is kept, as it can be edited by the user and built as a workspace member.
+pub fn add_build_outputs(crate_root: &Path) -> anyhow::Result<bool> {
+    add_managed_entries(crate_root, &["/target/".to_string()])
+}
+
+/// The entries of the managed section of a `.gitignore` file's content
+pub fn managed_entries(gitignore: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut in_section = false;
+    for line in gitignore.lines() {
+        match line.trim() {
+            BEGIN_GUARD => in_section = true,
+            END_GUARD => in_section = false,
+            "" => {}
+            entry if in_section && !entry.starts_with('#') => entries.push(entry.to_string()),
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// The existing artifacts listed in the managed sections of the `.gitignore` files in the directory and its
+/// subdirectories
+pub fn managed_artifacts(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut artifacts = Vec::new();
+    collect_managed_artifacts(root, &mut artifacts)?;
+    artifacts.sort();
+    artifacts.dedup();
+    Ok(artifacts)
+}
+
+fn collect_managed_artifacts(dir: &Path, artifacts: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let gitignore = dir.join(".gitignore");
+    let mut listed = Vec::new();
+    if gitignore.is_file() {
+        let content =
+            fs::read_to_string(&gitignore).context(format!("Failed to read {gitignore:?}"))?;
+        for entry in managed_entries(&content) {
+            // Only the artifacts inside the directory are removed, whatever the entries were edited to
+            if is_root_like(&entry) || entry.split('/').any(|component| component == "..") {
+                continue;
+            }
+            let path = dir.join(entry.trim_matches('/'));
+            // Crate roots listed by older versions are kept, with the user's edits in them
+            if path.join("Cargo.toml").is_file() {
+                continue;
+            }
+            if path.exists() || path.is_symlink() {
+                listed.push(path);
+            }
+        }
+    }
+
+    for entry in fs::read_dir(dir).context(format!("Failed to read {dir:?}"))? {
+        let path = entry?.path();
+        let skipped = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| SKIPPED_DIRS.contains(&name))
+            .unwrap_or(false);
+        // The listed artifacts are removed as a whole, without looking for more inside them
+        if path.is_dir() && !path.is_symlink() && !skipped && !listed.contains(&path) {
+            collect_managed_artifacts(&path, artifacts)?;
+        }
+    }
+    artifacts.extend(listed);
+    Ok(())
+}
+
+/// Merges the entries into the managed section of the content, appending the section if there is none
+fn with_managed_entries(existing: &str, entries: &[String]) -> String {
+    let mut merged = managed_entries(existing);
+    for entry in entries {
+        if !merged.contains(entry) {
+            merged.push(entry.clone());
+        }
+    }
+    merged.sort();
+
+    let mut section = format!("{BEGIN_GUARD}\n");
+    for entry in &merged {
+        section.push_str(entry);
+        section.push('\n');
+    }
+    section.push_str(END_GUARD);
+    section.push('\n');
+
+    match (existing.find(BEGIN_GUARD), existing.find(END_GUARD)) {
+        (Some(begin), Some(end)) if begin < end => {
+            let after = existing[end..]
+                .find('\n')
+                .map(|newline| &existing[end + newline + 1..])
+                .unwrap_or_default();
+            format!("{}{section}{after}", &existing[..begin])
+        }
+        _ if existing.is_empty() => section,
+        _ if existing.ends_with('\n') => format!("{existing}\n{section}"),
+        _ => format!("{existing}\n\n{section}"),
+    }
+}
+
+/// Whether a `.gitignore` entry names the directory of the file itself, like `/`, `.` or `./`
+fn is_root_like(entry: &str) -> bool {
+    entry
+        .split('/')
+        .all(|component| component.is_empty() || component == ".")
+}
+
+fn is_in_git_repository(dir: &Path) -> bool {
+    let dir = crate::paths::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .any(|ancestor| ancestor.join(".git").exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gitignore::{
+        add_build_outputs, add_managed_artifact, add_managed_entries, managed_artifacts,
+        managed_entries, with_managed_entries,
+    };
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn managed_sections_are_merged_keeping_the_rest() {
+        let existing = "/target\n*.swp\n";
+        let added = with_managed_entries(existing, &["/counter-stub/".to_string()]);
+        assert_eq!(
+            added,
+            "/target\n*.swp\n\n# BEGIN wasm-rpc-stubgen managed entries\n/counter-stub/\n# END wasm-rpc-stubgen managed entries\n"
+        );
+
+        let edited = format!("{added}/dist\n");
+        let merged = with_managed_entries(
+            &edited,
+            &[
+                "/.wasm-rpc-backups/".to_string(),
+                "/counter-stub/".to_string(),
+            ],
+        );
+        assert!(merged.starts_with("/target\n*.swp\n"));
+        assert!(merged.ends_with("# END wasm-rpc-stubgen managed entries\n/dist\n"));
+        assert_eq!(
+            managed_entries(&merged),
+            vec!["/.wasm-rpc-backups/", "/counter-stub/"]
+        );
+        assert_eq!(with_managed_entries(&merged, &[]), merged);
+    }
+
+    #[test]
+    fn only_gitignores_in_repositories_are_updated() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let entries = ["/counter-stub/".to_string()];
+
+        assert!(!add_managed_entries(root.path(), &entries).unwrap());
+        assert!(!root.path().join(".gitignore").exists());
+
+        fs::create_dir(root.path().join(".git")).unwrap();
+        assert!(add_managed_entries(root.path(), &entries).unwrap());
+        assert!(!add_managed_entries(root.path(), &entries).unwrap());
+    }
+
+    #[test]
+    fn managed_artifacts_are_found_in_subdirectories() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        fs::create_dir(root.path().join(".git")).unwrap();
+        let component = root.path().join("components").join("caller");
+        fs::create_dir_all(component.join(".wasm-rpc-backups").join("20240315T101502Z")).unwrap();
+        fs::create_dir_all(root.path().join("counter-stub").join("target")).unwrap();
+        fs::write(root.path().join("counter-stub").join("Cargo.toml"), "").unwrap();
+        add_build_outputs(&root.path().join("counter-stub")).unwrap();
+        fs::create_dir_all(root.path().join("dist").join("nested")).unwrap();
+        fs::write(
+            root.path().join("dist").join(".gitignore"),
+            "# BEGIN wasm-rpc-stubgen managed entries\n/nested/\n# END wasm-rpc-stubgen managed entries\n",
+        )
+        .unwrap();
+        // Listed by older versions, or edited by hand
+        fs::write(
+            root.path().join(".gitignore"),
+            "# BEGIN wasm-rpc-stubgen managed entries\n/\n./\n/counter-stub/\n/dist/\n/missing/\n# END wasm-rpc-stubgen managed entries\n",
+        )
+        .unwrap();
+        add_managed_entries(&component, &["/.wasm-rpc-backups/".to_string()]).unwrap();
+
+        assert_eq!(
+            managed_artifacts(root.path()).unwrap(),
+            vec![
+                component.join(".wasm-rpc-backups"),
+                root.path().join("counter-stub").join("target"),
+                root.path().join("dist"),
+            ]
+        );
+    }
+
+    #[test]
+    fn root_like_entries_and_crate_roots_are_rejected() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        fs::create_dir(root.path().join(".git")).unwrap();
+        for entry in ["/", ".", "./", "/./"] {
+            assert!(add_managed_entries(root.path(), &[entry.to_string()]).is_err());
+        }
+        assert!(!root.path().join(".gitignore").exists());
+
+        let stub = root.path().join("counter-stub");
+        fs::create_dir(&stub).unwrap();
+        fs::write(stub.join("Cargo.toml"), "").unwrap();
+        assert!(add_managed_artifact(&stub).is_err());
+        assert!(add_build_outputs(&stub).unwrap());
+        assert_eq!(
+            managed_entries(&fs::read_to_string(stub.join(".gitignore")).unwrap()),
+            vec!["/target/"]
+        );
+    }
+}
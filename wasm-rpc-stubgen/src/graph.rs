@@ -0,0 +1,377 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::paths::manifest_path;
+use crate::stability::{parse_wit_dir, parse_wit_path};
+use crate::workspace::member_dirs;
+use crate::{cargo, wit};
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::Path;
+use tracing::warn;
+use wit_parser::PackageName;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeKind {
+    /// A workspace member defining a component
+    Component,
+    /// A generated stub package, used by callers as a WIT dependency
+    Stub,
+    /// A component called through a stub which is not part of the workspace
+    External,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeKind {
+    /// A component depends on a stub
+    Uses,
+    /// A stub calls its target component
+    Calls,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    pub label: String,
+    pub kind: NodeKind,
+}
+
+/// The RPC topology of a workspace: which components use which stubs, and which components the
+/// stubs are calling
+#[derive(Debug, Default, Serialize)]
+pub struct RpcGraph {
+    pub nodes: IndexMap<String, Node>,
+    pub edges: IndexSet<(String, String, EdgeKind)>,
+}
+
+impl RpcGraph {
+    fn add_node(&mut self, id: &str, label: &str, kind: NodeKind) {
+        self.nodes.entry(id.to_string()).or_insert(Node {
+            label: label.to_string(),
+            kind,
+        });
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph rpc {{");
+        let _ = writeln!(out, "  rankdir=LR;");
+        for (id, node) in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Component => "box",
+                NodeKind::Stub => "ellipse",
+                NodeKind::External => "box, style=dashed",
+            };
+            let _ = writeln!(
+                out,
+                "  {} [label=\"{}\", shape={shape}];",
+                node_id(id),
+                dot_label(&node.label)
+            );
+        }
+        for (from, to, kind) in &self.edges {
+            let label = match kind {
+                EdgeKind::Uses => "uses",
+                EdgeKind::Calls => "calls",
+            };
+            let _ = writeln!(
+                out,
+                "  {} -> {} [label=\"{label}\"];",
+                node_id(from),
+                node_id(to)
+            );
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "graph LR");
+        for (id, node) in &self.nodes {
+            let label = mermaid_label(&node.label);
+            let node = match node.kind {
+                NodeKind::Component => format!("{}[\"{label}\"]", node_id(id)),
+                NodeKind::Stub => format!("{}([\"{label}\"])", node_id(id)),
+                NodeKind::External => format!("{}[/\"{label}\"/]", node_id(id)),
+            };
+            let _ = writeln!(out, "  {node}");
+        }
+        for (from, to, kind) in &self.edges {
+            let label = match kind {
+                EdgeKind::Uses => "uses",
+                EdgeKind::Calls => "calls",
+            };
+            let _ = writeln!(out, "  {} -->|{label}| {}", node_id(from), node_id(to));
+        }
+        out
+    }
+}
+
+/// Walks the WIT roots and `wit/deps` directories of all the members of a Cargo workspace and collects the
+/// component → stub → target relationships. Stubs imported by a member's worlds are included even if they have not
+/// been added to its `wit/deps` yet. Members are identified by their directory, relative to the workspace root.
+pub fn collect_rpc_graph(workspace_root: &Path) -> anyhow::Result<RpcGraph> {
+    let patterns = cargo::get_workspace_members(&workspace_root.join("Cargo.toml"))?;
+    let members = member_dirs(workspace_root, &patterns)?
+        .iter()
+        .map(|dir| manifest_path(dir))
+        .collect::<Vec<_>>();
+
+    let mut member_packages = Vec::new();
+    for member in members {
+        let wit_root = workspace_root.join(&member).join("wit");
+        if !wit_root.is_dir() {
+            continue;
+        }
+        match parse_wit_dir(&wit_root) {
+            Ok(pkg) => member_packages.push((
+                member,
+                wit_root,
+                pkg.name,
+                pkg.foreign_deps.into_keys().collect::<Vec<_>>(),
+            )),
+            Err(err) => warn!("skipping {member} because its WIT root cannot be parsed: {err:#}"),
+        }
+    }
+
+    let components_by_package = member_packages
+        .iter()
+        .filter(|(_, _, name, _)| stub_target(name).is_none())
+        .map(|(member, _, name, _)| (package_key(name), member.clone()))
+        .collect::<IndexMap<_, _>>();
+
+    let mut graph = RpcGraph::default();
+    for (member, _, name, _) in &member_packages {
+        if stub_target(name).is_none() {
+            graph.add_node(member, member, NodeKind::Component);
+        }
+    }
+
+    for (member, wit_root, name, imported) in &member_packages {
+        if stub_target(name).is_some() {
+            // Stub crates themselves are represented by their package
+            continue;
+        }
+
+        let mut dep_names = imported.clone();
+        for dep_dir in wit::get_dep_dirs(wit_root)? {
+            if let Ok(pkg) = parse_wit_path(&dep_dir) {
+                dep_names.push(pkg.name);
+            }
+        }
+        for dep_name in dep_names {
+            if let Some(target) = stub_target(&dep_name) {
+                let stub_id = package_key(&dep_name);
+                graph.add_node(&stub_id, &stub_id, NodeKind::Stub);
+                graph
+                    .edges
+                    .insert((member.clone(), stub_id.clone(), EdgeKind::Uses));
+
+                let target_id = match components_by_package.get(&target) {
+                    Some(target_member) => target_member.clone(),
+                    None => {
+                        graph.add_node(&target, &target, NodeKind::External);
+                        target
+                    }
+                };
+                graph.edges.insert((stub_id, target_id, EdgeKind::Calls));
+            }
+        }
+    }
+
+    Ok(graph)
+}
+
+/// The members of a workspace called through stubs and the members calling them, in the order of the workspace
+/// members. Stubs of components which are not workspace members are reported as warnings.
+pub fn infer_targets_and_callers(
+    workspace_root: &Path,
+) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let graph = collect_rpc_graph(workspace_root)?;
+
+    let mut targets = IndexSet::new();
+    let mut callers = IndexSet::new();
+    for (from, to, kind) in &graph.edges {
+        match kind {
+            EdgeKind::Uses => {
+                callers.insert(from.clone());
+            }
+            EdgeKind::Calls => match graph.nodes.get(to).map(|node| node.kind) {
+                Some(NodeKind::Component) => {
+                    targets.insert(to.clone());
+                }
+                _ => warn!("{from} is used by the workspace, but {to} is not a member of it"),
+            },
+        }
+    }
+
+    let in_member_order = |members: IndexSet<String>| {
+        graph
+            .nodes
+            .keys()
+            .filter(|id| members.contains(*id))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    Ok((in_member_order(targets), in_member_order(callers)))
+}
+
+fn package_key(name: &PackageName) -> String {
+    format!("{}:{}", name.namespace, name.name)
+}
+
+/// If the package is a generated stub, returns the name of the package it is calling
+fn stub_target(name: &PackageName) -> Option<String> {
+    name.name
+        .strip_suffix("-stub")
+        .map(|target| format!("{}:{}", name.namespace, target))
+}
+
+/// Escapes a label to be quoted in DOT
+fn dot_label(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes a label to be quoted in Mermaid, which uses entity codes instead of backslashes
+fn mermaid_label(label: &str) -> String {
+    label
+        .replace('"', "#quot;")
+        .replace('[', "#91;")
+        .replace(']', "#93;")
+}
+
+/// The identifier of a node in DOT and Mermaid. Every character other than an ASCII letter or digit, including `_`,
+/// is escaped as `_<hex code>_`, so distinct names never share an identifier.
+fn node_id(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_string()
+            } else {
+                format!("_{:x}_", c as u32)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{infer_targets_and_callers, EdgeKind, NodeKind, RpcGraph};
+    use crate::test_utils::write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn targets_and_callers_are_inferred_from_imported_stubs() {
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let root = temp_dir.path();
+        write(
+            &root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"counter\", \"caller\", \"other\"]\n",
+        );
+        write(
+            &root.join("counter/wit/counter.wit"),
+            "package rpc:counter;\n\ninterface api {\n  get: func() -> u64;\n}\n\nworld counter {\n  export api;\n}\n",
+        );
+        write(
+            &root.join("caller/wit/caller.wit"),
+            "package rpc:caller;\n\nworld caller {\n  import rpc:counter-stub/stub-counter;\n}\n",
+        );
+        write(
+            &root.join("other/wit/other.wit"),
+            "package rpc:other;\n\nworld other {\n  export run: func();\n}\n",
+        );
+
+        let (targets, callers) = infer_targets_and_callers(root).unwrap();
+
+        assert_eq!(targets, vec!["counter"]);
+        assert_eq!(callers, vec!["caller"]);
+    }
+
+    #[test]
+    fn glob_members_are_expanded() {
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let root = temp_dir.path();
+        write(
+            &root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"components/*\"]\n",
+        );
+        for member in ["counter", "caller"] {
+            write(
+                &root.join(format!("components/{member}/Cargo.toml")),
+                &format!("[package]\nname = \"{member}\"\n"),
+            );
+        }
+        write(
+            &root.join("components/counter/wit/counter.wit"),
+            "package rpc:counter;\n\ninterface api {\n  get: func() -> u64;\n}\n\nworld counter {\n  export api;\n}\n",
+        );
+        write(
+            &root.join("components/caller/wit/caller.wit"),
+            "package rpc:caller;\n\nworld caller {\n  import rpc:counter-stub/stub-counter;\n}\n",
+        );
+
+        let (targets, callers) = infer_targets_and_callers(root).unwrap();
+
+        assert_eq!(targets, vec!["components/counter"]);
+        assert_eq!(callers, vec!["components/caller"]);
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        let mut graph = RpcGraph::default();
+        graph.add_node("a", "say \"hi\" [now]", NodeKind::Component);
+        graph.add_node("b", "c:\\dir", NodeKind::External);
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph rpc {\n  rankdir=LR;\n  \
+             a [label=\"say \\\"hi\\\" [now]\", shape=box];\n  \
+             b [label=\"c:\\\\dir\", shape=box, style=dashed];\n}\n"
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph LR\n  a[\"say #quot;hi#quot; #91;now#93;\"]\n  b[/\"c:\\dir\"/]\n"
+        );
+    }
+
+    #[test]
+    fn colliding_names_get_distinct_ids() {
+        let mut graph = RpcGraph::default();
+        graph.add_node("rpc/counter", "rpc/counter", NodeKind::Component);
+        graph.add_node("rpc_counter", "rpc_counter", NodeKind::Component);
+        graph.add_node("rpc:counter", "rpc:counter", NodeKind::External);
+        graph.edges.insert((
+            "rpc/counter".to_string(),
+            "rpc:counter".to_string(),
+            EdgeKind::Calls,
+        ));
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph rpc {\n  rankdir=LR;\n  \
+             rpc_2f_counter [label=\"rpc/counter\", shape=box];\n  \
+             rpc_5f_counter [label=\"rpc_counter\", shape=box];\n  \
+             rpc_3a_counter [label=\"rpc:counter\", shape=box, style=dashed];\n  \
+             rpc_2f_counter -> rpc_3a_counter [label=\"calls\"];\n}\n"
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph LR\n  rpc_2f_counter[\"rpc/counter\"]\n  rpc_5f_counter[\"rpc_counter\"]\n  \
+             rpc_3a_counter[/\"rpc:counter\"/]\n  rpc_2f_counter -->|calls| rpc_3a_counter\n"
+        );
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::{is_stub_import, top_level_imports};
+use crate::error::{CommandError, ErrorKind};
+use crate::signing::{read_signature, ComponentSignature, Verifier};
+use anyhow::{anyhow, bail, Context};
+use golem_wasm_ast::analysis::{
+    AnalysedExport, AnalysedFunction, AnalysedResourceMode, AnalysedType,
+};
+use golem_wasm_rpc::metadata;
+use golem_wasm_rpc::metadata::ComponentExports;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasmparser::{Parser, ProducersSectionReader};
+
+/// The RPC surface of a component
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentInspection {
+    pub wasm: PathBuf,
+    /// The exported interfaces with their functions
+    pub interfaces: Vec<InspectedInterface>,
+    /// The functions exported directly by the component, outside of interfaces
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<InspectedFunction>,
+    /// The imported interfaces of generated stub packages, which are to be composed with the stubs
+    pub stub_imports: Vec<String>,
+    pub metadata: ComponentMetadata,
+    /// The signature embedded in the component, if it is signed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ComponentSignature>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectedInterface {
+    pub name: String,
+    pub functions: Vec<InspectedFunction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectedFunction {
+    pub name: String,
+    /// The WIT-style signature of the function, like `func(value: u64) -> string`
+    pub signature: String,
+}
+
+/// The metadata embedded in the custom sections of the component
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComponentMetadata {
+    /// The fields of the `producers` section, like the language and the tools the component was built with, each with
+    /// the names and the versions of its values
+    pub producers: BTreeMap<String, Vec<String>>,
+    /// The names of all the top level custom sections
+    pub custom_sections: Vec<String>,
+}
+
+/// Inspects a component, verifying its signature with the verifier if one is given
+pub fn inspect(
+    path: &Path,
+    verifier: Option<&dyn Verifier>,
+) -> anyhow::Result<ComponentInspection> {
+    let bytes = read_component(path)?;
+    let signature = read_signature(&bytes, verifier)
+        .context(format!("Failed to check the signature of {path:?}"))?;
+    let exports = analysed_exports(&bytes, path)?;
+    let (imports, metadata) =
+        imports_and_metadata(&bytes).context(format!("Failed to parse {path:?}"))?;
+
+    let mut interfaces = Vec::new();
+    let mut functions = Vec::new();
+    for export in exports.exports() {
+        match export {
+            AnalysedExport::Instance(instance) => interfaces.push(InspectedInterface {
+                name: instance.name.clone(),
+                functions: instance.funcs.iter().map(inspect_function).collect(),
+            }),
+            AnalysedExport::Function(function) => functions.push(inspect_function(function)),
+        }
+    }
+
+    Ok(ComponentInspection {
+        wasm: path.to_path_buf(),
+        interfaces,
+        functions,
+        stub_imports: imports
+            .into_iter()
+            .filter(|import| is_stub_import(import))
+            .collect(),
+        metadata,
+        signature,
+    })
+}
+
+/// Reads the bytes of a component, failing if the file is missing or is not a component
+pub(crate) fn read_component(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let bytes = fs::read(path).map_err(|err| {
+        anyhow!(CommandError::new(
+            ErrorKind::MissingDependency,
+            format!("Failed to read {path:?}: {err}")
+        ))
+    })?;
+    if !Parser::is_component(&bytes) {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!("{path:?} is not a WASM component")
+        ));
+    }
+    Ok(bytes)
+}
+
+/// The interfaces and functions exported by a component, with their types
+pub(crate) fn analysed_exports(bytes: &[u8], path: &Path) -> anyhow::Result<Arc<ComponentExports>> {
+    metadata::load(bytes).map_err(|err| anyhow!("Failed to load the exports of {path:?}: {err}"))
+}
+
+/// The names of the top level imports and the metadata of a component
+fn imports_and_metadata(bytes: &[u8]) -> anyhow::Result<(Vec<String>, ComponentMetadata)> {
+    let mut metadata = ComponentMetadata::default();
+    let imports = top_level_imports(bytes, |section| {
+        if section.name() == "producers" {
+            let reader = ProducersSectionReader::new(section.data(), section.data_offset())?;
+            for field in reader {
+                let field = field?;
+                let values = metadata
+                    .producers
+                    .entry(field.name.to_string())
+                    .or_default();
+                for value in field.values {
+                    let value = value?;
+                    values.push(
+                        format!("{} {}", value.name, value.version)
+                            .trim()
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        metadata.custom_sections.push(section.name().to_string());
+        Ok(())
+    })?;
+    Ok((imports, metadata))
+}
+
+fn inspect_function(function: &AnalysedFunction) -> InspectedFunction {
+    let params = function
+        .params
+        .iter()
+        .map(|param| format!("{}: {}", param.name, wit_type(&param.typ)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let results = match function.results.as_slice() {
+        [] => String::new(),
+        [result] if result.name.is_none() => format!(" -> {}", wit_type(&result.typ)),
+        results => format!(
+            " -> ({})",
+            results
+                .iter()
+                .map(|result| format!(
+                    "{}: {}",
+                    result.name.clone().unwrap_or_default(),
+                    wit_type(&result.typ)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    InspectedFunction {
+        name: function.name.clone(),
+        signature: format!("func({params}){results}"),
+    }
+}
+
+/// Renders a type like it is written in WIT, with the records, variants, enums and flags written out structurally as
+/// the analysis does not know their names
+fn wit_type(typ: &AnalysedType) -> String {
+    let list = |types: &mut dyn Iterator<Item = String>| types.collect::<Vec<_>>().join(", ");
+    let optional = |typ: &Option<Box<AnalysedType>>| {
+        typ.as_ref()
+            .map(|typ| wit_type(typ))
+            .unwrap_or_else(|| "_".to_string())
+    };
+    match typ {
+        AnalysedType::Bool => "bool".to_string(),
+        AnalysedType::S8 => "s8".to_string(),
+        AnalysedType::U8 => "u8".to_string(),
+        AnalysedType::S16 => "s16".to_string(),
+        AnalysedType::U16 => "u16".to_string(),
+        AnalysedType::S32 => "s32".to_string(),
+        AnalysedType::U32 => "u32".to_string(),
+        AnalysedType::S64 => "s64".to_string(),
+        AnalysedType::U64 => "u64".to_string(),
+        AnalysedType::F32 => "float32".to_string(),
+        AnalysedType::F64 => "float64".to_string(),
+        AnalysedType::Chr => "char".to_string(),
+        AnalysedType::Str => "string".to_string(),
+        AnalysedType::List(typ) => format!("list<{}>", wit_type(typ)),
+        AnalysedType::Option(typ) => format!("option<{}>", wit_type(typ)),
+        AnalysedType::Tuple(types) => format!("tuple<{}>", list(&mut types.iter().map(wit_type))),
+        AnalysedType::Result { ok, error } => {
+            format!("result<{}, {}>", optional(ok), optional(error))
+        }
+        AnalysedType::Record(fields) => format!(
+            "record {{ {} }}",
+            list(
+                &mut fields
+                    .iter()
+                    .map(|(name, typ)| format!("{name}: {}", wit_type(typ)))
+            )
+        ),
+        AnalysedType::Variant(cases) => format!(
+            "variant {{ {} }}",
+            list(&mut cases.iter().map(|(name, typ)| match typ {
+                Some(typ) => format!("{name}({})", wit_type(typ)),
+                None => name.clone(),
+            }))
+        ),
+        AnalysedType::Enum(cases) => format!("enum {{ {} }}", cases.join(", ")),
+        AnalysedType::Flags(flags) => format!("flags {{ {} }}", flags.join(", ")),
+        AnalysedType::Resource { id, resource_mode } => match resource_mode {
+            AnalysedResourceMode::Owned => format!("own<resource-{}>", id.value),
+            AnalysedResourceMode::Borrowed => format!("borrow<resource-{}>", id.value),
+        },
+    }
+}
+
+impl Display for ComponentInspection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Exports:")?;
+        for interface in &self.interfaces {
+            writeln!(f, "  {}", interface.name)?;
+            for function in &interface.functions {
+                writeln!(f, "    {}: {}", function.name, function.signature)?;
+            }
+        }
+        for function in &self.functions {
+            writeln!(f, "  {}: {}", function.name, function.signature)?;
+        }
+
+        writeln!(f, "Stub imports:")?;
+        if self.stub_imports.is_empty() {
+            writeln!(f, "  (none)")?;
+        }
+        for import in &self.stub_imports {
+            writeln!(f, "  {import}")?;
+        }
+
+        writeln!(f, "Metadata:")?;
+        for (field, values) in &self.metadata.producers {
+            writeln!(f, "  {field}: {}", values.join(", "))?;
+        }
+        writeln!(
+            f,
+            "  custom sections: {}",
+            self.metadata.custom_sections.join(", ")
+        )?;
+
+        if let Some(signature) = &self.signature {
+            writeln!(f, "Signature:")?;
+            writeln!(
+                f,
+                "  {} key {}{}",
+                signature.algorithm,
+                signature.key_id,
+                if signature.verified {
+                    " (verified)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inspect::{imports_and_metadata, inspect_function};
+    use golem_wasm_ast::analysis::{
+        AnalysedFunction, AnalysedFunctionParameter, AnalysedFunctionResult, AnalysedResourceId,
+        AnalysedResourceMode, AnalysedType,
+    };
+
+    #[test]
+    fn signatures_are_rendered_like_wit() {
+        let function = AnalysedFunction {
+            name: "[method]api.update".to_string(),
+            params: vec![
+                AnalysedFunctionParameter {
+                    name: "self".to_string(),
+                    typ: AnalysedType::Resource {
+                        id: AnalysedResourceId { value: 0 },
+                        resource_mode: AnalysedResourceMode::Borrowed,
+                    },
+                },
+                AnalysedFunctionParameter {
+                    name: "changes".to_string(),
+                    typ: AnalysedType::List(Box::new(AnalysedType::Record(vec![
+                        ("key".to_string(), AnalysedType::Str),
+                        (
+                            "value".to_string(),
+                            AnalysedType::Option(Box::new(AnalysedType::U64)),
+                        ),
+                    ]))),
+                },
+            ],
+            results: vec![AnalysedFunctionResult {
+                name: None,
+                typ: AnalysedType::Result {
+                    ok: None,
+                    error: Some(Box::new(AnalysedType::Enum(vec![
+                        "not-found".to_string(),
+                        "conflict".to_string(),
+                    ]))),
+                },
+            }],
+        };
+
+        assert_eq!(
+            inspect_function(&function).signature,
+            "func(self: borrow<resource-0>, changes: list<record { key: string, value: option<u64> }>) \
+             -> result<_, enum { not-found, conflict }>"
+        );
+    }
+
+    #[test]
+    fn stub_imports_and_producers_are_found() {
+        let mut component = b"\0asm\x0d\0\x01\0".to_vec();
+        let mut imports = vec![2];
+        for name in ["rpc:counter-stub/stub-counter", "wasi:io/streams"] {
+            imports.extend([0, name.len() as u8]);
+            imports.extend(name.as_bytes());
+            imports.extend([5, 0]);
+        }
+        component.extend([10, imports.len() as u8]);
+        component.extend(imports);
+        let mut producers = b"\x09producers\x01\x0cprocessed-by\x01".to_vec();
+        producers.extend(b"\x0dwit-component\x070.201.0");
+        component.extend([0, producers.len() as u8]);
+        component.extend(producers);
+
+        let (imports, metadata) = imports_and_metadata(&component).unwrap();
+        assert_eq!(
+            imports,
+            vec!["rpc:counter-stub/stub-counter", "wasi:io/streams"]
+        );
+        assert_eq!(
+            metadata.producers.get("processed-by"),
+            Some(&vec!["wit-component 0.201.0".to_string()])
+        );
+        assert_eq!(metadata.custom_sections, vec!["producers"]);
+    }
+}
@@ -0,0 +1,527 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::progress::{Progress, ProgressEvent};
+use crate::stub::{FunctionResultStub, FunctionStub, InterfaceStub, StubDefinition};
+use anyhow::{anyhow, bail};
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::fs;
+use wit_parser::{Handle, Type, TypeDefKind};
+
+/// Generates an integration test of the stub crate, invoking every function of the stub on the target component hosted
+/// by wasmtime, through a `WasmRpcHost` calling the component in-process
+pub fn generate_integration_test(
+    def: &StubDefinition,
+    progress: &dyn Progress,
+) -> anyhow::Result<()> {
+    let mut functions = Vec::new();
+    for interface in &def.interfaces {
+        functions.extend(expected_functions(def, interface)?);
+    }
+
+    let test = quote! {
+        //! Smoke test of the stub against its target component, generated by wasm-rpc-stubgen
+        //!
+        //! Runs the target component, read from the WASM file given by the `TARGET_COMPONENT` environment variable,
+        //! with wasmtime, and invokes every function of the stub through a `WasmRpcHost` calling the component
+        //! in-process, with arbitrary values of the parameter types. The test fails if a function is not exported by
+        //! the component, its signature differs from the one the stub was generated for, or its parameters or results
+        //! cannot be converted. Traps of the component, like the calls of its imports, which are not provided, are
+        //! printed without failing the test.
+
+        #![allow(warnings)]
+
+        use arbitrary::Unstructured;
+        use async_trait::async_trait;
+        use golem_wasm_ast::analysis::{AnalysedResourceId, AnalysedResourceMode, AnalysedType};
+        use golem_wasm_rpc_guest::metadata::{types_match, ComponentExports};
+        use golem_wasm_rpc_guest::sample::arbitrary_value;
+        use golem_wasm_rpc_guest::wasmtime::{
+            decode_param, define_imports_as_traps, encode_output, EncodingError, ResourceStore,
+        };
+        use golem_wasm_rpc_guest::{RpcError, RpcTransport, Uri, Value, WasmRpcHost, WitValue};
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use wasmtime::component::{Component, Instance, Linker, ResourceAny, Val};
+        use wasmtime::{Config, Engine, Store};
+
+        enum Kind {
+            Function,
+            /// Creates an instance of the resource
+            Constructor(&'static str),
+            /// Invoked on the last created instance of the resource
+            Method(&'static str),
+        }
+
+        struct ExpectedFunction {
+            /// The name the stub invokes the function by
+            rpc_name: &'static str,
+            /// The exported interface of the function, or `None` for the functions exported by the component directly
+            interface: Option<&'static str>,
+            function: &'static str,
+            kind: Kind,
+            params: Vec<AnalysedType>,
+            results: Vec<AnalysedType>,
+        }
+
+        fn resource(resource_mode: AnalysedResourceMode) -> AnalysedType {
+            AnalysedType::Resource {
+                id: AnalysedResourceId { value: 0 },
+                resource_mode,
+            }
+        }
+
+        fn expected_functions() -> Vec<ExpectedFunction> {
+            Vec::from([#(#functions),*])
+        }
+
+        struct Resources {
+            uri: Uri,
+            next_id: u64,
+            resources: HashMap<u64, ResourceAny>,
+        }
+
+        impl ResourceStore for Resources {
+            fn self_uri(&self) -> Uri {
+                self.uri.clone()
+            }
+
+            fn add(&mut self, resource: ResourceAny) -> u64 {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.resources.insert(id, resource);
+                id
+            }
+
+            fn get(&mut self, resource_id: u64) -> Option<ResourceAny> {
+                self.resources.remove(&resource_id)
+            }
+
+            fn borrow(&self, resource_id: u64) -> Option<ResourceAny> {
+                self.resources.get(&resource_id).cloned()
+            }
+        }
+
+        /// Invokes the functions of a component instance in the same process
+        struct LoopbackTransport {
+            store: Mutex<Store<Resources>>,
+            instance: Instance,
+            exports: HashMap<&'static str, (Option<&'static str>, &'static str)>,
+        }
+
+        fn encoding_error(err: EncodingError) -> RpcError {
+            RpcError::ProtocolError(match err {
+                EncodingError::ParamTypeMismatch => "Parameter type mismatch".to_string(),
+                EncodingError::ValueMismatch { details } => format!("Value mismatch: {details}"),
+                EncodingError::Unknown { details } => details,
+            })
+        }
+
+        #[async_trait]
+        impl RpcTransport for LoopbackTransport {
+            async fn invoke_and_await(
+                &self,
+                _uri: &Uri,
+                function_name: &str,
+                function_params: Vec<WitValue>,
+            ) -> Result<WitValue, RpcError> {
+                let (interface, function) = *self.exports.get(function_name).ok_or_else(|| {
+                    RpcError::NotFound(format!("Unknown function {function_name}"))
+                })?;
+                let mut store = self.store.lock().unwrap();
+                let func = {
+                    let mut exports = self.instance.exports(&mut *store);
+                    match interface {
+                        Some(interface) => exports
+                            .instance(interface)
+                            .and_then(|mut instance| instance.func(function)),
+                        None => exports.root().func(function),
+                    }
+                }
+                .ok_or_else(|| RpcError::NotFound(format!("{function_name} is not exported")))?;
+
+                let param_types = func.params(&*store);
+                if param_types.len() != function_params.len() {
+                    return Err(RpcError::ProtocolError(format!(
+                        "{function_name} expects {} parameters, got {}",
+                        param_types.len(),
+                        function_params.len()
+                    )));
+                }
+                let mut params = Vec::new();
+                for (param, typ) in function_params.into_iter().zip(param_types.iter()) {
+                    let decoded = decode_param(&Value::from(param), typ, store.data_mut())
+                        .map_err(encoding_error)?;
+                    params.push(decoded.val);
+                }
+
+                let mut results = vec![Val::Bool(false); func.results(&*store).len()];
+                func.call(&mut *store, &params, &mut results)
+                    .map_err(|err| RpcError::RemoteInternalError(format!("{err:?}")))?;
+                func.post_return(&mut *store)
+                    .map_err(|err| RpcError::RemoteInternalError(format!("{err:?}")))?;
+
+                let mut values = Vec::new();
+                for result in &results {
+                    values.push(encode_output(result, store.data_mut()).map_err(encoding_error)?);
+                }
+                Ok(Value::Tuple(values).into())
+            }
+        }
+
+        #[tokio::test]
+        async fn stub_functions_match_the_target_component() {
+            let path = std::env::var("TARGET_COMPONENT")
+                .expect("TARGET_COMPONENT has to be the path of the target component's WASM file");
+            let bytes = std::fs::read(&path).expect("Failed to read the target component");
+            let exports = ComponentExports::from_bytes(&bytes).expect("Failed to analyse the target component");
+
+            let mut config = Config::new();
+            config.wasm_component_model(true);
+            let engine = Engine::new(&config).unwrap();
+            let component = Component::new(&engine, &bytes).expect("Failed to compile the target component");
+            let mut linker = Linker::new(&engine);
+            define_imports_as_traps(&mut linker, &component, &bytes).unwrap();
+            let uri = Uri::worker("target", "loopback");
+            let mut store = Store::new(
+                &engine,
+                Resources {
+                    uri: uri.clone(),
+                    next_id: 0,
+                    resources: HashMap::new(),
+                },
+            );
+            let instance = linker
+                .instantiate(&mut store, &component)
+                .expect("Failed to instantiate the target component");
+
+            let functions = expected_functions();
+            let transport = LoopbackTransport {
+                store: Mutex::new(store),
+                instance,
+                exports: functions
+                    .iter()
+                    .map(|function| (function.rpc_name, (function.interface, function.function)))
+                    .collect(),
+            };
+            let host = WasmRpcHost::builder(transport).build();
+
+            let seed: Vec<u8> = (0..65536u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+            let mut u = Unstructured::new(&seed);
+            let mut handles: HashMap<&str, Value> = HashMap::new();
+            let mut failures = Vec::new();
+
+            for function in &functions {
+                let name = match function.interface {
+                    Some(interface) => format!("{interface}.{{{}}}", function.function),
+                    None => format!("{{{}}}", function.function),
+                };
+                let signature = match exports.interface_function(function.interface, function.function) {
+                    Some(signature) => signature,
+                    None => {
+                        failures.push(format!("{name} is not exported by the target component"));
+                        continue;
+                    }
+                };
+                let params_match = signature.params.len() == function.params.len()
+                    && signature.params.iter().zip(&function.params).all(|(actual, expected)| types_match(expected, &actual.typ));
+                let results_match = signature.results.len() == function.results.len()
+                    && signature.results.iter().zip(&function.results).all(|(actual, expected)| types_match(expected, &actual.typ));
+                if !params_match || !results_match {
+                    failures.push(format!(
+                        "{name} has a different signature in the target component: {:?} -> {:?}",
+                        signature.params, signature.results
+                    ));
+                    continue;
+                }
+
+                let mut params = Vec::new();
+                for (idx, typ) in function.params.iter().enumerate() {
+                    let param = match (&function.kind, idx) {
+                        (Kind::Method(resource), 0) => handles.get(resource).cloned(),
+                        _ => arbitrary_value(typ, &mut u).ok(),
+                    };
+                    match param {
+                        Some(param) => params.push(WitValue::from(param)),
+                        None => break,
+                    }
+                }
+                if params.len() != function.params.len() {
+                    println!("Skipping {name}, its parameters cannot be generated");
+                    continue;
+                }
+
+                match host.invoke_and_await(&uri, function.rpc_name, params).await {
+                    Ok(result) => match Value::from(result) {
+                        Value::Tuple(values) if values.len() == function.results.len() => {
+                            if let (Kind::Constructor(resource), Some(handle)) = (&function.kind, values.first()) {
+                                handles.insert(resource, handle.clone());
+                            }
+                        }
+                        other => failures.push(format!("{name} returned unexpected results: {other:?}")),
+                    },
+                    Err(RpcError::RemoteInternalError(err)) => println!("{name} trapped: {err}"),
+                    Err(err) => failures.push(format!("Invoking {name} failed: {err:?}")),
+                }
+            }
+
+            assert!(
+                failures.is_empty(),
+                "The stub does not match the target component:\n{}",
+                failures.join("\n")
+            );
+        }
+    };
+
+    let syntax_tree = syn::parse2(test)?;
+    let src = prettyplease::unparse(&syntax_tree);
+
+    let path = def.target_integration_test_path();
+    progress.on_event(&ProgressEvent::Generating {
+        description: "integration test".to_string(),
+        path: path.clone(),
+    });
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, src)?;
+    Ok(())
+}
+
+/// The functions the stub of an interface invokes, with the signatures the stub expects them to have
+fn expected_functions(
+    def: &StubDefinition,
+    interface: &InterfaceStub,
+) -> anyhow::Result<Vec<TokenStream>> {
+    let rpc_interface = if interface.global {
+        None
+    } else {
+        Some(match &interface.owner_interface {
+            Some(owner) => format!("{owner}/{}", interface.name),
+            None => interface.name.clone(),
+        })
+    };
+    let exported_interface = if interface.global {
+        None
+    } else {
+        Some(
+            def.root_package_name.interface_id(
+                interface
+                    .owner_interface
+                    .as_ref()
+                    .unwrap_or(&interface.name),
+            ),
+        )
+    };
+    let exported_interface = match exported_interface {
+        Some(name) => quote! { Some(#name) },
+        None => quote! { None },
+    };
+
+    let mut functions = Vec::new();
+    let mut add = |stub_name: &str,
+                   function: String,
+                   kind: TokenStream,
+                   params: Vec<TokenStream>,
+                   results: Vec<TokenStream>| {
+        let rpc_name = match &rpc_interface {
+            Some(rpc_interface) => format!(
+                "{}:{}/{rpc_interface}/{stub_name}",
+                def.root_package_name.namespace, def.root_package_name.name
+            ),
+            None => format!(
+                "{}:{}/{stub_name}",
+                def.root_package_name.namespace, def.root_package_name.name
+            ),
+        };
+        functions.push(quote! {
+            ExpectedFunction {
+                rpc_name: #rpc_name,
+                interface: #exported_interface,
+                function: #function,
+                kind: #kind,
+                params: Vec::from([#(#params),*]),
+                results: Vec::from([#(#results),*]),
+            }
+        });
+    };
+
+    if interface.is_resource() {
+        let resource = &interface.name;
+        let mut params = Vec::new();
+        for param in interface.constructor_params.iter().flatten() {
+            params.push(analysed_type(&param.typ, def)?);
+        }
+        add(
+            "new",
+            format!("[constructor]{resource}"),
+            quote! { Kind::Constructor(#resource) },
+            params,
+            vec![quote! { resource(AnalysedResourceMode::Owned) }],
+        );
+        for function in &interface.functions {
+            let mut params = vec![quote! { resource(AnalysedResourceMode::Borrowed) }];
+            params.extend(function_params(function, def)?);
+            add(
+                &function.name,
+                format!("[method]{resource}.{}", function.name),
+                quote! { Kind::Method(#resource) },
+                params,
+                function_results(function, def)?,
+            );
+        }
+        for function in &interface.static_functions {
+            add(
+                &function.name,
+                format!("[static]{resource}.{}", function.name),
+                quote! { Kind::Function },
+                function_params(function, def)?,
+                function_results(function, def)?,
+            );
+        }
+    } else {
+        for function in &interface.functions {
+            add(
+                &function.name,
+                function.name.clone(),
+                quote! { Kind::Function },
+                function_params(function, def)?,
+                function_results(function, def)?,
+            );
+        }
+    }
+    Ok(functions)
+}
+
+fn function_params(
+    function: &FunctionStub,
+    def: &StubDefinition,
+) -> anyhow::Result<Vec<TokenStream>> {
+    function
+        .params
+        .iter()
+        .map(|param| analysed_type(&param.typ, def))
+        .collect()
+}
+
+fn function_results(
+    function: &FunctionStub,
+    def: &StubDefinition,
+) -> anyhow::Result<Vec<TokenStream>> {
+    match &function.results {
+        FunctionResultStub::Single(typ) => Ok(vec![analysed_type(typ, def)?]),
+        FunctionResultStub::Multi(params) => params
+            .iter()
+            .map(|param| analysed_type(&param.typ, def))
+            .collect(),
+        FunctionResultStub::SelfType => Ok(vec![quote! { resource(AnalysedResourceMode::Owned) }]),
+    }
+}
+
+/// The expression building the `AnalysedType` of a WIT type, as the analysis of a component describes it
+fn analysed_type(typ: &Type, def: &StubDefinition) -> anyhow::Result<TokenStream> {
+    match typ {
+        Type::Bool => Ok(quote! { AnalysedType::Bool }),
+        Type::U8 => Ok(quote! { AnalysedType::U8 }),
+        Type::U16 => Ok(quote! { AnalysedType::U16 }),
+        Type::U32 => Ok(quote! { AnalysedType::U32 }),
+        Type::U64 => Ok(quote! { AnalysedType::U64 }),
+        Type::S8 => Ok(quote! { AnalysedType::S8 }),
+        Type::S16 => Ok(quote! { AnalysedType::S16 }),
+        Type::S32 => Ok(quote! { AnalysedType::S32 }),
+        Type::S64 => Ok(quote! { AnalysedType::S64 }),
+        Type::Float32 => Ok(quote! { AnalysedType::F32 }),
+        Type::Float64 => Ok(quote! { AnalysedType::F64 }),
+        Type::Char => Ok(quote! { AnalysedType::Chr }),
+        Type::String => Ok(quote! { AnalysedType::Str }),
+        Type::Id(type_id) => {
+            let typedef = def
+                .resolve
+                .types
+                .get(*type_id)
+                .ok_or(anyhow!("type not found"))?;
+            match &typedef.kind {
+                TypeDefKind::Record(record) => {
+                    let mut fields = Vec::new();
+                    for field in &record.fields {
+                        let name = &field.name;
+                        let typ = analysed_type(&field.ty, def)?;
+                        fields.push(quote! { (#name.to_string(), #typ) });
+                    }
+                    Ok(quote! { AnalysedType::Record(Vec::from([#(#fields),*])) })
+                }
+                TypeDefKind::Variant(variant) => {
+                    let mut cases = Vec::new();
+                    for case in &variant.cases {
+                        let name = &case.name;
+                        let typ = match &case.ty {
+                            Some(typ) => {
+                                let typ = analysed_type(typ, def)?;
+                                quote! { Some(#typ) }
+                            }
+                            None => quote! { None },
+                        };
+                        cases.push(quote! { (#name.to_string(), #typ) });
+                    }
+                    Ok(quote! { AnalysedType::Variant(Vec::from([#(#cases),*])) })
+                }
+                TypeDefKind::Enum(enum_def) => {
+                    let cases = enum_def.cases.iter().map(|case| &case.name);
+                    Ok(quote! { AnalysedType::Enum(Vec::from([#(#cases.to_string()),*])) })
+                }
+                TypeDefKind::Flags(flags) => {
+                    let flags = flags.flags.iter().map(|flag| &flag.name);
+                    Ok(quote! { AnalysedType::Flags(Vec::from([#(#flags.to_string()),*])) })
+                }
+                TypeDefKind::Tuple(tuple) => {
+                    let types = tuple
+                        .types
+                        .iter()
+                        .map(|typ| analysed_type(typ, def))
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    Ok(quote! { AnalysedType::Tuple(Vec::from([#(#types),*])) })
+                }
+                TypeDefKind::Option(inner) => {
+                    let inner = analysed_type(inner, def)?;
+                    Ok(quote! { AnalysedType::Option(Box::new(#inner)) })
+                }
+                TypeDefKind::List(inner) => {
+                    let inner = analysed_type(inner, def)?;
+                    Ok(quote! { AnalysedType::List(Box::new(#inner)) })
+                }
+                TypeDefKind::Result(result) => {
+                    let optional = |typ: &Option<Type>| -> anyhow::Result<TokenStream> {
+                        match typ {
+                            Some(typ) => {
+                                let typ = analysed_type(typ, def)?;
+                                Ok(quote! { Some(Box::new(#typ)) })
+                            }
+                            None => Ok(quote! { None }),
+                        }
+                    };
+                    let ok = optional(&result.ok)?;
+                    let error = optional(&result.err)?;
+                    Ok(quote! { AnalysedType::Result { ok: #ok, error: #error } })
+                }
+                TypeDefKind::Handle(Handle::Own(_)) => {
+                    Ok(quote! { resource(AnalysedResourceMode::Owned) })
+                }
+                TypeDefKind::Handle(Handle::Borrow(_)) => {
+                    Ok(quote! { resource(AnalysedResourceMode::Borrowed) })
+                }
+                TypeDefKind::Type(inner) => analysed_type(inner, def),
+                other => bail!("Unsupported type in the integration test: {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,588 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::{CommandError, ErrorKind};
+use crate::inspect::{analysed_exports, read_component};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use golem_wasm_ast::analysis::{
+    AnalysedFunction, AnalysedFunctionParameter, AnalysedFunctionResult, AnalysedType,
+};
+use golem_wasm_rpc::metadata::ComponentExports;
+use golem_wasm_rpc::{
+    json, parse_value, print_value, RpcError, RpcTransport, Uri, Value, WasmRpcHost, WitValue,
+    WorkerUri,
+};
+use serde::Serialize;
+use serde_json::{json as json_value, Value as JsonValue};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::debug;
+
+/// The outcome of invoking a function of a worker
+#[derive(Debug, Clone, Serialize)]
+pub struct Invocation {
+    pub uri: String,
+    pub function: String,
+    /// The results in the typed JSON format of the Golem API
+    pub result: JsonValue,
+    /// The results in the WAVE text format
+    pub wave: Vec<String>,
+}
+
+impl Display for Invocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for value in &self.wave {
+            writeln!(f, "{value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Invokes a function of a running worker through the worker API of Golem, and waits for its results. The arguments
+/// are parsed by the types of the function's parameters, as exported by the worker's component. The function is
+/// either given explicitly or is part of the worker URI.
+pub async fn invoke(
+    api_url: &str,
+    uri: &str,
+    function: Option<&str>,
+    args: &[String],
+    component_wasm: &Path,
+) -> anyhow::Result<Invocation> {
+    let (uri, function) = target(uri, function)?;
+    let function = function.as_str();
+    let bytes = read_component(component_wasm)?;
+    let exports = analysed_exports(&bytes, component_wasm)?;
+    let signature = exports.function(function).cloned().ok_or_else(|| {
+        anyhow!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!("{function} is not exported by {component_wasm:?}")
+        ))
+    })?;
+    let values = arguments(&signature, args)?;
+
+    debug!("Invoking {function} of {uri} at {api_url}");
+    let host = WasmRpcHost::builder(WorkerApiTransport::new(api_url, exports)).build();
+    let result = host
+        .invoke_and_await(
+            &Uri::from(uri.worker()),
+            function,
+            values.into_iter().map(WitValue::from).collect(),
+        )
+        .await
+        .map_err(|err| anyhow!("Invoking {function} of {uri} failed: {err}"))?;
+    let results = match Value::try_from_wit_value(result) {
+        Ok(Value::Tuple(results)) => results,
+        Ok(result) => bail!("Unexpected result of {function}: {result:?}"),
+        Err(err) => bail!("Invalid result of {function}: {err}"),
+    };
+    let (result, wave) = render(results, &signature.results)?;
+
+    Ok(Invocation {
+        uri: uri.worker().to_string(),
+        function: function.to_string(),
+        result,
+        wave,
+    })
+}
+
+/// Delivers the invocations through the worker API of Golem. The API passes the values in its typed JSON format, so
+/// they are encoded and decoded by the signatures of the functions exported by the component of the workers.
+pub struct WorkerApiTransport {
+    api_url: String,
+    exports: Arc<ComponentExports>,
+}
+
+impl WorkerApiTransport {
+    pub fn new(api_url: &str, exports: Arc<ComponentExports>) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            exports,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for WorkerApiTransport {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        let worker = uri
+            .parse_worker()
+            .map_err(|err| RpcError::ProtocolError(format!("Invalid worker URI: {err}")))?;
+        let signature = self.exports.function(function_name).ok_or_else(|| {
+            RpcError::NotFound(format!("{function_name} is not exported by {worker}"))
+        })?;
+        let body = request_body(signature, function_params).map_err(RpcError::ProtocolError)?;
+
+        let url = format!(
+            "{}/v2/components/{}/workers/{}/invoke-and-await",
+            self.api_url, worker.component_id, worker.worker_name
+        );
+        let function = function_name.to_string();
+        let api_url = self.api_url.clone();
+        let body = tokio::task::spawn_blocking(move || post(&api_url, &url, &function, &body))
+            .await
+            .map_err(|err| {
+                RpcError::ProtocolError(format!("Failed to call the worker API: {err}"))
+            })??;
+        let body: JsonValue = serde_json::from_str(&body).map_err(|err| {
+            RpcError::ProtocolError(format!("Failed to parse the response: {err}"))
+        })?;
+        let results = response_values(signature, &body).map_err(RpcError::ProtocolError)?;
+        Ok(Value::Tuple(results).into())
+    }
+}
+
+/// Posts an invocation request to the worker API, returning the body of the response
+fn post(api_url: &str, url: &str, function: &str, body: &JsonValue) -> Result<String, RpcError> {
+    let response = ureq::post(url)
+        .query("function", function)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string());
+    match response {
+        Ok(response) => response
+            .into_string()
+            .map_err(|err| RpcError::ProtocolError(format!("Failed to read the response: {err}"))),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            Err(match status {
+                400 => RpcError::ProtocolError(body),
+                401 | 403 => RpcError::Denied(body),
+                404 => RpcError::NotFound(body),
+                _ => RpcError::RemoteInternalError(format!("Status {status}: {body}")),
+            })
+        }
+        Err(err) => Err(RpcError::ProtocolError(format!(
+            "Failed to connect to {api_url}: {err}"
+        ))),
+    }
+}
+
+/// The worker and the function to be invoked. The explicitly given function overrides the one in the URI.
+fn target(uri: &str, function: Option<&str>) -> anyhow::Result<(WorkerUri, String)> {
+    let uri: WorkerUri = uri.parse().map_err(|err| {
+        anyhow!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!("Invalid worker URI {uri}: {err}")
+        ))
+    })?;
+    let function = match (function, uri.function.as_deref()) {
+        (Some(function), _) | (None, Some(function)) => function.to_string(),
+        (None, None) => bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!(
+                "The function to invoke is missing from {uri}, it can be given with --function"
+            )
+        )),
+    };
+    Ok((uri, function))
+}
+
+/// Parses the arguments of a function by the types of its parameters
+fn arguments(signature: &AnalysedFunction, args: &[String]) -> anyhow::Result<Vec<Value>> {
+    if args.len() != signature.params.len() {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!(
+                "{} expects {} argument(s), got {}",
+                signature.name,
+                signature.params.len(),
+                args.len()
+            )
+        ));
+    }
+    args.iter()
+        .zip(&signature.params)
+        .map(|(arg, param)| {
+            parse_argument(arg, &param.typ).map_err(|err| {
+                anyhow!(CommandError::new(
+                    ErrorKind::InvalidArguments,
+                    format!("Invalid value for the {} parameter: {err}", param.name)
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Parses an argument of the given type, either from the typed JSON format of the Golem API, or from WAVE
+fn parse_argument(text: &str, typ: &AnalysedType) -> Result<Value, String> {
+    let json_error = match serde_json::from_str::<JsonValue>(text) {
+        Ok(json) => {
+            let param = AnalysedFunctionParameter {
+                name: "arg".to_string(),
+                typ: typ.clone(),
+            };
+            match json::function_parameters(&JsonValue::Array(vec![json]), &[param]) {
+                Ok(mut values) => return Ok(values.remove(0)),
+                Err(errors) => errors.join(", "),
+            }
+        }
+        Err(err) => err.to_string(),
+    };
+    parse_value(typ, text)
+        .map_err(|wave_error| format!("it is neither JSON ({json_error}) nor WAVE ({wave_error})"))
+}
+
+/// The body of an invocation request of the worker API, with the parameters in its typed JSON format
+fn request_body(
+    signature: &AnalysedFunction,
+    function_params: Vec<WitValue>,
+) -> Result<JsonValue, String> {
+    let values = function_params
+        .into_iter()
+        .map(Value::try_from_wit_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let params = json::function_result(values, &unnamed_results(&signature.params))
+        .map_err(|errors| format!("Failed to encode the parameters: {}", errors.join(", ")))?;
+    Ok(json_value!({ "params": params }))
+}
+
+fn unnamed_results(params: &[AnalysedFunctionParameter]) -> Vec<AnalysedFunctionResult> {
+    params
+        .iter()
+        .map(|param| AnalysedFunctionResult {
+            name: None,
+            typ: param.typ.clone(),
+        })
+        .collect()
+}
+
+/// Decodes the results in the response of the worker API. Unnamed results are returned as an array, named ones as an
+/// object.
+fn response_values(signature: &AnalysedFunction, body: &JsonValue) -> Result<Vec<Value>, String> {
+    let result = body
+        .get("result")
+        .ok_or_else(|| format!("The response of the worker API has no result: {body}"))?;
+    let values = match result {
+        JsonValue::Object(fields) => JsonValue::Array(
+            signature
+                .results
+                .iter()
+                .enumerate()
+                .map(|(idx, result)| {
+                    let name = result.name.clone().unwrap_or_else(|| idx.to_string());
+                    fields.get(&name).cloned().unwrap_or(JsonValue::Null)
+                })
+                .collect(),
+        ),
+        result => result.clone(),
+    };
+    let params = signature
+        .results
+        .iter()
+        .enumerate()
+        .map(|(idx, result)| AnalysedFunctionParameter {
+            name: result.name.clone().unwrap_or_else(|| idx.to_string()),
+            typ: result.typ.clone(),
+        })
+        .collect::<Vec<_>>();
+    json::function_parameters(&values, &params)
+        .map_err(|errors| format!("Unexpected result {result}: {}", errors.join(", ")))
+}
+
+/// Renders the results both in the typed JSON format of the Golem API and in WAVE
+fn render(
+    results: Vec<Value>,
+    types: &[AnalysedFunctionResult],
+) -> anyhow::Result<(JsonValue, Vec<String>)> {
+    let json = json::function_result(results.clone(), types)
+        .map_err(|errors| anyhow!("Failed to render the results: {}", errors.join(", ")))?;
+    let wave = results
+        .into_iter()
+        .zip(types)
+        .map(|(value, result)| {
+            print_value(value, &result.typ)
+                .map_err(|err| anyhow!("Failed to print {result:?}: {err}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok((json, wave))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::invoke::{
+        arguments, render, request_body, response_values, target, WorkerApiTransport,
+    };
+    use golem_wasm_ast::analysis::{
+        AnalysedExport, AnalysedFunction, AnalysedFunctionParameter, AnalysedFunctionResult,
+        AnalysedInstance, AnalysedType,
+    };
+    use golem_wasm_rpc::metadata::ComponentExports;
+    use golem_wasm_rpc::{RpcError, Uri, Value, WasmRpcHost, WitValue};
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn exports() -> ComponentExports {
+        let param = |name: &str, typ: AnalysedType| AnalysedFunctionParameter {
+            name: name.to_string(),
+            typ,
+        };
+        ComponentExports::new(vec![AnalysedExport::Instance(AnalysedInstance {
+            name: "rpc:counter/api".to_string(),
+            funcs: vec![
+                AnalysedFunction {
+                    name: "add".to_string(),
+                    params: vec![
+                        param("name", AnalysedType::Str),
+                        param("value", AnalysedType::U64),
+                        param(
+                            "tags",
+                            AnalysedType::Option(Box::new(AnalysedType::List(Box::new(
+                                AnalysedType::Str,
+                            )))),
+                        ),
+                    ],
+                    results: vec![AnalysedFunctionResult {
+                        name: None,
+                        typ: AnalysedType::U64,
+                    }],
+                },
+                AnalysedFunction {
+                    name: "stats".to_string(),
+                    params: vec![],
+                    results: vec![
+                        AnalysedFunctionResult {
+                            name: Some("count".to_string()),
+                            typ: AnalysedType::U32,
+                        },
+                        AnalysedFunctionResult {
+                            name: Some("last".to_string()),
+                            typ: AnalysedType::Option(Box::new(AnalysedType::Str)),
+                        },
+                    ],
+                },
+            ],
+        })])
+    }
+
+    #[test]
+    fn functions_are_taken_from_the_arguments_or_the_uri() {
+        let target = |uri: &str, function: Option<&str>| {
+            target(uri, function).map(|(uri, function)| (uri.worker().to_string(), function))
+        };
+        assert_eq!(
+            target("worker://4b1c/counter-1", Some("rpc:counter/api.{add}")).unwrap(),
+            (
+                "worker://4b1c/counter-1".to_string(),
+                "rpc:counter/api.{add}".to_string()
+            )
+        );
+        assert_eq!(
+            target("worker://4b1c/counter-1/rpc:counter/api.{get}", None).unwrap(),
+            (
+                "worker://4b1c/counter-1".to_string(),
+                "rpc:counter/api.{get}".to_string()
+            )
+        );
+        assert_eq!(
+            target(
+                "worker://4b1c/counter-1/rpc:counter/api.{get}",
+                Some("rpc:counter/api.{add}")
+            )
+            .unwrap()
+            .1,
+            "rpc:counter/api.{add}"
+        );
+        assert!(target("worker://4b1c/counter-1", None).is_err());
+        assert!(target("worker://4b1c", Some("rpc:counter/api.{add}")).is_err());
+        assert!(target("http://4b1c/counter-1", Some("rpc:counter/api.{add}")).is_err());
+    }
+
+    #[test]
+    fn arguments_are_parsed_by_the_exported_parameter_types() {
+        let exports = exports();
+        let add = exports.function("rpc:counter/api.{add}").unwrap();
+        let expected = vec![
+            Value::String("visits".to_string()),
+            Value::U64(3),
+            Value::Option(Some(Box::new(Value::List(vec![Value::String(
+                "home".to_string(),
+            )])))),
+        ];
+
+        let json = arguments(
+            add,
+            &[
+                r#""visits""#.to_string(),
+                "3".to_string(),
+                r#"["home"]"#.to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(json, expected);
+
+        let wave = arguments(
+            add,
+            &[
+                r#""visits""#.to_string(),
+                "3".to_string(),
+                r#"some(["home"])"#.to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(wave, expected);
+
+        assert!(arguments(add, &["visits".to_string()]).is_err());
+        assert!(arguments(
+            add,
+            &["visits".to_string(), "-1".to_string(), "none".to_string()]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn requests_and_responses_are_encoded_by_the_signatures() {
+        let exports = exports();
+        let add = exports.function("rpc:counter/api.{add}").unwrap();
+        let params = vec![
+            WitValue::from(Value::String("visits".to_string())),
+            WitValue::from(Value::U64(3)),
+            WitValue::from(Value::Option(None)),
+        ];
+        assert_eq!(
+            request_body(add, params).unwrap(),
+            json!({ "params": ["visits", 3, null] })
+        );
+        assert_eq!(
+            response_values(add, &json!({ "result": [4] })).unwrap(),
+            vec![Value::U64(4)]
+        );
+
+        let stats = exports.function("rpc:counter/api.{stats}").unwrap();
+        assert_eq!(
+            response_values(
+                stats,
+                &json!({ "result": { "count": 2, "last": "visits" } })
+            )
+            .unwrap(),
+            vec![
+                Value::U32(2),
+                Value::Option(Some(Box::new(Value::String("visits".to_string()))))
+            ]
+        );
+        assert!(response_values(stats, &json!({ "count": 2 })).is_err());
+    }
+
+    #[test]
+    fn results_are_rendered_as_json_and_wave() {
+        let exports = exports();
+        let stats = exports.function("rpc:counter/api.{stats}").unwrap();
+        let (json, wave) = render(
+            vec![
+                Value::U32(2),
+                Value::Option(Some(Box::new(Value::String("visits".to_string())))),
+            ],
+            &stats.results,
+        )
+        .unwrap();
+        assert_eq!(json, json!({ "count": 2, "last": "visits" }));
+        assert_eq!(wave, vec!["2".to_string(), r#"some("visits")"#.to_string()]);
+
+        let add = exports.function("rpc:counter/api.{add}").unwrap();
+        let (json, wave) = render(vec![Value::U64(4)], &add.results).unwrap();
+        assert_eq!(json, json!([4]));
+        assert_eq!(wave, vec!["4".to_string()]);
+    }
+
+    /// Serves a single request of the worker API, returning its request line and body
+    fn serve(
+        status: &'static str,
+        response: &'static str,
+    ) -> (String, thread::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{response}",
+                response.len()
+            )
+            .unwrap();
+            (request_line, String::from_utf8(body).unwrap())
+        });
+        (api_url, handle)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn invocations_are_delivered_through_the_worker_api() {
+        let (api_url, server) = serve("200 OK", r#"{ "result": [4] }"#);
+        let host =
+            WasmRpcHost::builder(WorkerApiTransport::new(&api_url, Arc::new(exports()))).build();
+        let result = host
+            .invoke_and_await(
+                &Uri::worker("4b1c", "counter-1"),
+                "rpc:counter/api.{add}",
+                vec![
+                    Value::String("visits".to_string()).into(),
+                    Value::U64(3).into(),
+                    Value::Option(None).into(),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            Value::try_from_wit_value(result).unwrap(),
+            Value::Tuple(vec![Value::U64(4)])
+        );
+
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with(
+            "POST /v2/components/4b1c/workers/counter-1/invoke-and-await?function=rpc%3Acounter%2Fapi.%7Badd%7D "
+        ));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({ "params": ["visits", 3, null] })
+        );
+
+        let (api_url, server) = serve("404 Not Found", "no such worker");
+        let host =
+            WasmRpcHost::builder(WorkerApiTransport::new(&api_url, Arc::new(exports()))).build();
+        let result = host
+            .invoke_and_await(
+                &Uri::worker("4b1c", "counter-2"),
+                "rpc:counter/api.{stats}",
+                vec![],
+            )
+            .await;
+        assert!(matches!(result, Err(RpcError::NotFound(message)) if message == "no such worker"));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use tracing::{info, warn};
+
+/// Records the original state of the files and directories a command changes before changing them, so a command
+/// failing halfway restores them instead of leaving a half-updated tree behind.
+///
+/// The originals are copied into a temporary backup directory, mirroring their paths relative to the journal's root.
+/// The changes are kept by [Journal::commit], and rolled back when the journal is dropped without committing it.
+pub struct Journal {
+    root: PathBuf,
+    backup: Option<TempDir>,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    path: PathBuf,
+    original: Original,
+}
+
+enum Original {
+    /// The path did not exist, it is removed on rollback
+    Missing,
+    File(PathBuf),
+    Dir(PathBuf),
+    /// A symbolic link, restored pointing to the same target
+    Link(PathBuf),
+}
+
+impl Journal {
+    /// Starts a journal of the changes made under the given root directory
+    pub fn new(root: &Path) -> anyhow::Result<Self> {
+        let backup = TempDir::new(&format!("wasm-rpc-stubgen-backup-{}", std::process::id()))
+            .context("Failed to create the backup directory")?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            backup: Some(backup),
+            entries: Vec::new(),
+        })
+    }
+
+    /// Records the current state of the path, which is about to be changed. Paths inside already recorded ones are
+    /// not recorded again, and for a missing path its topmost missing ancestor is recorded, so the directories
+    /// created for it are removed on rollback too.
+    pub fn record(&mut self, path: &Path) -> anyhow::Result<()> {
+        let mut path = path.to_path_buf();
+        while !path.exists() && !path.is_symlink() {
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => {
+                    path = parent.to_path_buf()
+                }
+                _ => break,
+            }
+        }
+        if self
+            .entries
+            .iter()
+            .any(|entry| path.starts_with(&entry.path))
+        {
+            return Ok(());
+        }
+
+        let original = if path.is_symlink() {
+            Original::Link(fs::read_link(&path).context(format!("Failed to read {path:?}"))?)
+        } else if path.is_file() {
+            let backup = self.backup_path(&path)?;
+            fs::copy(&path, &backup).context(format!("Failed to back up {path:?}"))?;
+            Original::File(backup)
+        } else if path.is_dir() {
+            let backup = self.backup_path(&path)?;
+            fs_extra::dir::copy(
+                &path,
+                &backup,
+                &fs_extra::dir::CopyOptions::new().content_only(true),
+            )
+            .context(format!("Failed to back up {path:?}"))?;
+            Original::Dir(backup)
+        } else {
+            Original::Missing
+        };
+        self.entries.push(Entry { path, original });
+        Ok(())
+    }
+
+    /// Keeps the changes. With `keep_backup` the backup of the original files is kept, and its directory returned.
+    pub fn commit(mut self, keep_backup: bool) -> Option<PathBuf> {
+        self.entries.clear();
+        let backup = self.backup.take()?;
+        if keep_backup {
+            let kept = backup.into_path();
+            info!("The original files are kept in {kept:?}");
+            Some(kept)
+        } else {
+            None
+        }
+    }
+
+    /// Restores the recorded paths to their original state, the most recently recorded first
+    fn rollback(&mut self) -> anyhow::Result<()> {
+        let mut failures = Vec::new();
+        while let Some(entry) = self.entries.pop() {
+            if let Err(err) = entry.restore() {
+                failures.push(format!("{:?}: {err:#}", entry.path));
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Failed to restore {}{}",
+                failures.join(", "),
+                match &self.backup {
+                    Some(backup) => format!(", the originals are in {:?}", backup.path()),
+                    None => String::new(),
+                }
+            ))
+        }
+    }
+
+    fn backup_path(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        let backup = self
+            .backup
+            .as_ref()
+            .ok_or(anyhow!("The journal is already committed"))?;
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+            _ => PathBuf::from(format!("entry-{}", self.entries.len())),
+        };
+        let backup_path = backup.path().join(relative);
+        if let Some(parent) = backup_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(backup_path)
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        info!("Rolling back the changes under {:?}", self.root);
+        if let Err(err) = self.rollback() {
+            // Keeping the backup, as it holds the only copy of the originals which could not be restored
+            if let Some(backup) = self.backup.take() {
+                backup.into_path();
+            }
+            warn!("{err:#}");
+        }
+    }
+}
+
+impl Entry {
+    fn restore(&self) -> anyhow::Result<()> {
+        remove_existing(&self.path)?;
+        match &self.original {
+            Original::Missing => {}
+            Original::File(backup) => {
+                fs::copy(backup, &self.path)?;
+            }
+            Original::Dir(backup) => {
+                fs::create_dir_all(&self.path)?;
+                fs_extra::dir::copy(
+                    backup,
+                    &self.path,
+                    &fs_extra::dir::CopyOptions::new().content_only(true),
+                )?;
+            }
+            Original::Link(target) => restore_link(target, &self.path)?,
+        }
+        Ok(())
+    }
+}
+
+fn remove_existing(path: &Path) -> std::io::Result<()> {
+    if path.is_symlink() || path.is_file() {
+        fs::remove_file(path)
+    } else if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn restore_link(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn restore_link(target: &Path, link: &Path) -> std::io::Result<()> {
+    let resolved = link.parent().unwrap_or(Path::new(".")).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::Journal;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn dropped_journals_restore_the_originals() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let deps = root.path().join("wit").join("deps");
+        fs::create_dir_all(deps.join("counter")).unwrap();
+        fs::write(deps.join("counter").join("api.wit"), "original").unwrap();
+        fs::write(root.path().join("Cargo.toml"), "[package]").unwrap();
+
+        {
+            let mut journal = Journal::new(root.path()).unwrap();
+            journal.record(&deps.join("counter")).unwrap();
+            journal.record(&root.path().join("Cargo.toml")).unwrap();
+            journal
+                .record(&deps.join("new").join("nested").join("new.wit"))
+                .unwrap();
+
+            fs::write(deps.join("counter").join("api.wit"), "changed").unwrap();
+            fs::write(deps.join("counter").join("added.wit"), "added").unwrap();
+            fs::write(root.path().join("Cargo.toml"), "[workspace]").unwrap();
+            fs::create_dir_all(deps.join("new").join("nested")).unwrap();
+        }
+
+        assert_eq!(
+            fs::read_to_string(deps.join("counter").join("api.wit")).unwrap(),
+            "original"
+        );
+        assert!(!deps.join("counter").join("added.wit").exists());
+        assert_eq!(
+            fs::read_to_string(root.path().join("Cargo.toml")).unwrap(),
+            "[package]"
+        );
+        assert!(!deps.join("new").exists());
+    }
+
+    #[test]
+    fn committed_changes_are_kept_with_an_optional_backup() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit = root.path().join("api.wit");
+        fs::write(&wit, "original").unwrap();
+
+        let mut journal = Journal::new(root.path()).unwrap();
+        journal.record(&wit).unwrap();
+        fs::write(&wit, "changed").unwrap();
+        let backup = journal.commit(true).unwrap();
+
+        assert_eq!(fs::read_to_string(&wit).unwrap(), "changed");
+        assert_eq!(
+            fs::read_to_string(backup.join("api.wit")).unwrap(),
+            "original"
+        );
+        fs::remove_dir_all(backup).unwrap();
+
+        let mut journal = Journal::new(root.path()).unwrap();
+        journal.record(&wit).unwrap();
+        assert_eq!(journal.commit(false), None);
+    }
+}
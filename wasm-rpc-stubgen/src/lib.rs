@@ -12,33 +12,98 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod api;
+mod backup;
 mod cargo;
+mod changes;
+mod compat;
 mod compilation;
+mod completions;
+mod conflict;
+mod dep_manager;
+mod error;
+mod gitignore;
+mod graph;
+mod inspect;
+mod integration;
+mod invoke;
+mod journal;
+mod logging;
 mod make;
+mod optimize;
+mod output;
+mod paths;
+mod progress;
+mod registry;
 mod rust;
+mod sbom;
+mod scaffold;
+mod signing;
+mod stability;
 mod stub;
+#[cfg(test)]
+mod test_utils;
+mod update;
+mod validation;
+mod wac;
 mod wit;
+mod workspace;
 
-use crate::cargo::generate_cargo_toml;
-use crate::compilation::compile;
-use crate::rust::generate_stub_source;
-use crate::stub::StubDefinition;
-use crate::wit::{copy_wit_files, generate_stub_wit, verify_action, WitAction};
-use anyhow::{anyhow, Context};
-use clap::Parser;
-use fs_extra::dir::CopyOptions;
-use golem_wasm_ast::analysis::{AnalysedExport, AnalysisContext, AnalysisFailure};
-use golem_wasm_ast::component::Component;
-use golem_wasm_ast::IgnoreAllButMetadata;
-use heck::ToSnakeCase;
+use crate::cargo::StubCrateDependencies;
+use crate::changes::{ChangeKind, FileChange};
+use crate::compat::{interface_changes, package_interfaces};
+use crate::compilation::{check_toolchain, install_wasm_target, WASM_TARGET};
+use crate::conflict::has_conflict_markers;
+use crate::dep_manager::{DependencyManager, ManagedDependency};
+use crate::graph::RpcGraph;
+use crate::journal::Journal;
+use crate::progress::cli_progress;
+use crate::validation::ValidationReport;
+use crate::wit::{verify_action, WitAction};
+use anyhow::{anyhow, bail, Context};
+use clap::{CommandFactory, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
-use tempdir::TempDir;
-use wasm_compose::config::Dependency;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+use wit_parser::{PackageName, Resolve};
+
+pub use crate::backup::Snapshot;
+pub use crate::cargo::{DependencySource, SharedTypes};
+pub use crate::compat::{CompatReport, InterfaceChange};
+pub use crate::compilation::ToolchainReport;
+pub use crate::conflict::{ConflictPolicy, Resolution};
+pub use crate::error::{CommandError, ErrorKind};
+pub use crate::inspect::{
+    ComponentInspection, ComponentMetadata, InspectedFunction, InspectedInterface,
+};
+pub use crate::invoke::Invocation;
+pub use crate::logging::{init_logging, Verbosity};
+pub use crate::make::BuildTool;
+pub use crate::optimize::{ComponentSize, OptimizedComponent, SizeReport};
+pub use crate::output::DetailedError;
+pub use crate::output::{json_output, set_output_format, OutputFormat};
+pub use crate::scaffold::ProjectTemplate;
+pub use crate::stub::{FunctionConvention, RpcErrors, StubPackageVersion};
+pub use crate::update::notify_newer_version;
 
 #[derive(Parser, Debug)]
 #[command(name = "wasm-rpc-stubgen", version)]
 #[command(bin_name = "wasm-rpc-stubgen")]
+pub struct Cli {
+    /// The format of the commands' output. In `json` mode the result of the command is printed to the standard
+    /// output as a single JSON document. Status messages always go to the standard error.
+    #[clap(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub verbosity: Verbosity,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
 pub enum Command {
     /// Generate a Rust RPC stub crate for a WASM component
     Generate(GenerateArgs),
@@ -46,11 +111,52 @@ pub enum Command {
     Build(BuildArgs),
     /// Adds a generated stub as a dependency to another WASM component
     AddStubDependency(AddStubDependencyArgs),
+    /// Updates a previously added stub dependency of another WASM component to a newer version
+    UpdateStubDependency(UpdateStubDependencyArgs),
+    /// Restores the stub dependencies of a WASM component saved before overwriting them
+    Restore(RestoreArgs),
+    /// Removes the artifacts generated by the stub generator
+    Clean(CleanArgs),
     /// Compose a WASM component with a generated stub WASM
     Compose(ComposeArgs),
+    /// Compose a pipeline of WASM components, each satisfying the imports of the next one
+    ComposeChain(ComposeChainArgs),
+    /// Signs WASM components, so deployments can check that they were not changed after the build
+    Sign(SignArgs),
+    /// Generates an Ed25519 key pair for signing WASM components
+    GenerateKey(GenerateKeyArgs),
+    /// Optimizes the size of WASM components with wasm-opt
+    Optimize(OptimizeArgs),
+    /// Prints the sizes of WASM components per build profile
+    SizeReport(SizeReportArgs),
+    /// Prints the RPC surface of a WASM component
+    Inspect(InspectArgs),
+    /// Classifies the changes between two versions of a component as breaking or not for its RPC callers
+    Compat(CompatArgs),
+    /// Invokes a function of a running worker, for testing and debugging RPC calls
+    Invoke(InvokeArgs),
+    /// Checks whether a component's WIT definition can be used for generating stubs
+    Validate(ValidateArgs),
+    /// Visualizes the RPC dependencies between the components of a Cargo workspace
+    Graph(GraphArgs),
+    /// Fetches a component's WIT package and its dependencies from a registry
+    Fetch(FetchArgs),
+    /// Publishes a generated stub's WIT package and compiled WASM to a registry
+    Publish(PublishArgs),
+    /// Checks whether the tools required for building stubs are installed
+    CheckToolchain(CheckToolchainArgs),
     /// Initializes a Golem-specific cargo-make configuration in a Cargo workspace for automatically
     /// generating stubs and composing results.
     InitializeWorkspace(InitializeWorkspaceArgs),
+    /// Creates a Cargo workspace with components calling each other through RPC
+    New(NewArgs),
+    /// Prints the completion script of a shell
+    Completions(CompletionsArgs),
+    /// Writes the man pages of all the commands, for packaging
+    #[command(hide = true)]
+    Mangen(MangenArgs),
+    /// Checks for a newer release of the stub generator and installs it
+    SelfUpdate(SelfUpdateArgs),
 }
 
 /// Generate a Rust RPC stub crate for a WASM component
@@ -65,6 +171,10 @@ pub struct GenerateArgs {
     /// The target path to generate a new stub crate to
     #[clap(short, long)]
     pub dest_crate_root: PathBuf,
+    /// The package to generate the stub for, as `namespace:name`. If there is only a single package in the source
+    /// root, no need to specify.
+    #[clap(short, long)]
+    pub package: Option<String>,
     /// The world name to be used in the generated stub crate. If there is only a single world in the source root
     ///  package, no need to specify.
     #[clap(short, long)]
@@ -72,10 +182,67 @@ pub struct GenerateArgs {
     /// The crate version of the generated stub crate
     #[clap(long, default_value = "0.0.1")]
     pub stub_crate_version: String,
-    /// The path to the `wasm-rpc` crate to be used in the generated stub crate. If not specified,
-    /// the latest version of `wasm-rpc` will be used.
+    /// The version of the generated stub WIT package: `same` as the source package's version, `none`, or a fixed
+    /// semantic version
+    #[clap(long, default_value_t = StubPackageVersion::Same)]
+    pub stub_package_version: StubPackageVersion,
+    /// The path to the `wasm-rpc-guest` crate to be used in the generated stub crate. If not specified,
+    /// the latest version of `wasm-rpc-guest` will be used. The path of the `wasm-rpc` crate, which stubs depended
+    /// on before, is rejected.
     #[clap(long)]
     pub wasm_rpc_path_override: Option<String>,
+    /// Where the generated stub crate takes the `golem-wasm-rpc-guest` dependency from: a version from crates.io,
+    /// `registry:<name>@<version>`, `path:<path>` or `git:<url>[#<rev>]`. Overrides `wasm-rpc-path-override`.
+    #[clap(long, conflicts_with = "wasm_rpc_path_override")]
+    pub wasm_rpc_source: Option<DependencySource>,
+    /// Where the generated stub crate takes the `wit-bindgen` dependency from, in the same format as
+    /// `wasm-rpc-source`
+    #[clap(long)]
+    pub wit_bindgen_source: Option<DependencySource>,
+    /// Takes the types of a WIT interface from the bindings of another crate instead of generating them, as
+    /// `<interface>=<module>`, like `rpc:counter/api=counter_types::bindings::rpc::counter::api`. Can be repeated.
+    #[clap(long = "with", value_name = "INTERFACE=MODULE")]
+    pub shared_types: Vec<SharedTypes>,
+    /// Generates `From` conversions in both directions between the stub's types of a WIT interface and the types of
+    /// another crate, as `<interface>=<module>`, like `rpc:counter/api=counter::bindings::rpc::counter::api`. Can be
+    /// repeated.
+    #[clap(long = "convert", value_name = "INTERFACE=MODULE")]
+    pub converted_types: Vec<SharedTypes>,
+    /// Generate `tests/stub_integration.rs`, invoking every function of the stub on the target component hosted by
+    /// wasmtime, read from the WASM file given by the `TARGET_COMPONENT` environment variable
+    #[clap(long)]
+    pub with_integration_test: bool,
+    /// Give the stubs a `set-metadata` method, setting the metadata passed with their invocations, like the tracing
+    /// context, auth tokens or tenancy
+    #[clap(long)]
+    pub with_metadata: bool,
+    /// The root directory of the WIT definition of an older version of the component. The stubs get a
+    /// `set-target-version` method, and down-convert their calls to the older API when the target has its version.
+    #[clap(long)]
+    pub older_wit_root: Option<PathBuf>,
+    /// A Cargo.toml whose `[package.metadata.wasm-rpc.functions]` table configures how the stub functions surface the
+    /// results of the target functions
+    #[clap(long, value_name = "MANIFEST")]
+    pub conventions: Option<PathBuf>,
+    /// Keep the interfaces and functions marked `@unstable` in the source WIT in the stub
+    #[clap(long)]
+    pub include_unstable: bool,
+    /// Fail instead of warning when the destination has a previously generated stub WIT, and regenerating it breaks
+    /// its callers by removing functions or changing their types
+    #[clap(long)]
+    pub deny_breaking: bool,
+    /// Overwrite an existing Cargo.toml in the destination crate root instead of merging the generated manifest into it
+    #[clap(long)]
+    pub force_manifest: bool,
+    /// Keep the backup of the changed files after a successful generation, a failed one is always rolled back from it
+    #[clap(long)]
+    pub keep_backup: bool,
+    /// Do not list the build outputs of the generated stub crate in the managed section of its `.gitignore`
+    #[clap(long)]
+    pub no_gitignore: bool,
+    /// Print the files that would be created, overwritten or modified, without changing anything
+    #[clap(long)]
+    pub dry_run: bool,
 }
 
 /// Build an RPC stub for a WASM component
@@ -96,6 +263,10 @@ pub struct BuildArgs {
     /// The directory name where the generated WIT files should be placed
     #[clap(long)]
     pub dest_wit_root: PathBuf,
+    /// The package to generate the stub for, as `namespace:name`. If there is only a single package in the source
+    /// root, no need to specify.
+    #[clap(short, long)]
+    pub package: Option<String>,
     /// The world name to be used in the generated stub crate. If there is only a single world in the source root
     ///   package, no need to specify.
     #[clap(short, long)]
@@ -103,9 +274,60 @@ pub struct BuildArgs {
     /// The crate version of the generated stub crate
     #[clap(long, default_value = "0.0.1")]
     pub stub_crate_version: String,
-    /// The path to the `wasm-rpc` crate to be used in the generated stub crate. If not specified, the latest version of `wasm-rpc` will be used. It needs to be an **absolute path**.
+    /// The version of the generated stub WIT package: `same` as the source package's version, `none`, or a fixed
+    /// semantic version
+    #[clap(long, default_value_t = StubPackageVersion::Same)]
+    pub stub_package_version: StubPackageVersion,
+    /// The path to the `wasm-rpc-guest` crate to be used in the generated stub crate. If not specified, the latest version of `wasm-rpc-guest` will be used. It needs to be an **absolute path**. The path of the `wasm-rpc` crate, which stubs depended on before, is rejected.
     #[clap(long)]
     pub wasm_rpc_path_override: Option<String>,
+    /// Where the generated stub crate takes the `golem-wasm-rpc-guest` dependency from: a version from crates.io,
+    /// `registry:<name>@<version>`, `path:<path>` or `git:<url>[#<rev>]`. Overrides `wasm-rpc-path-override`.
+    #[clap(long, conflicts_with = "wasm_rpc_path_override")]
+    pub wasm_rpc_source: Option<DependencySource>,
+    /// Where the generated stub crate takes the `wit-bindgen` dependency from, in the same format as
+    /// `wasm-rpc-source`
+    #[clap(long)]
+    pub wit_bindgen_source: Option<DependencySource>,
+    /// Takes the types of a WIT interface from the bindings of another crate instead of generating them, as
+    /// `<interface>=<module>`. Can be repeated.
+    #[clap(long = "with", value_name = "INTERFACE=MODULE")]
+    pub shared_types: Vec<SharedTypes>,
+    /// Generates `From` conversions in both directions between the stub's types of a WIT interface and the types of
+    /// another crate, as `<interface>=<module>`. Can be repeated.
+    #[clap(long = "convert", value_name = "INTERFACE=MODULE")]
+    pub converted_types: Vec<SharedTypes>,
+    /// Give the stubs a `set-metadata` method, setting the metadata passed with their invocations
+    #[clap(long)]
+    pub with_metadata: bool,
+    /// The root directory of the WIT definition of an older version of the component, the stubs down-convert their
+    /// calls to
+    #[clap(long)]
+    pub older_wit_root: Option<PathBuf>,
+    /// A Cargo.toml whose `[package.metadata.wasm-rpc.functions]` table configures how the stub functions surface the
+    /// results of the target functions
+    #[clap(long, value_name = "MANIFEST")]
+    pub conventions: Option<PathBuf>,
+    /// Keep the interfaces and functions marked `@unstable` in the source WIT in the stub
+    #[clap(long)]
+    pub include_unstable: bool,
+    /// The cargo target directory to compile the stub in. Builds of several stubs sharing one reuse the compiled
+    /// dependencies.
+    #[clap(long)]
+    pub target_dir: Option<PathBuf>,
+    /// The program rustc is run through when compiling the stub, like `sccache`. Without it cargo uses the
+    /// `RUSTC_WRAPPER` environment variable, if set.
+    #[clap(long, value_name = "PROGRAM")]
+    pub rustc_wrapper: Option<PathBuf>,
+    /// Write a CycloneDX software bill of materials next to the stub WASM, to `<dest-wasm>.cdx.json`, listing the
+    /// hashes of the source WIT files, the versions of the crates the stub was compiled from and the version of the
+    /// stub generator
+    #[clap(long)]
+    pub sbom: bool,
+    /// Print the key identifying the compiled stub, the hash of the generated stub crate and the Rust toolchain,
+    /// instead of building it
+    #[clap(long)]
+    pub print_cache_key: bool,
 }
 
 /// Adds a generated stub as a dependency to another WASM component
@@ -121,15 +343,86 @@ pub struct AddStubDependencyArgs {
     #[clap(short, long)]
     pub dest_wit_root: PathBuf,
     /// This command would not do anything if it detects that it would change an existing WIT file's contents at
-    /// the destination. With this flag, it can be forced to overwrite those files.
+    /// the destination. With this flag, it can be forced to overwrite those files. Same as `--on-conflict overwrite`.
     #[clap(short, long)]
     pub overwrite: bool,
+    /// How to handle existing WIT files at the destination with a different content: fail without changing anything,
+    /// keep or overwrite them, merge the stub's version into them, or ask for each file showing its diff
+    #[clap(long, value_enum, default_value_t = ConflictPolicy::Fail)]
+    pub on_conflict: ConflictPolicy,
+    /// Enables updating the Cargo.toml file in the parent directory of `dest-wit-root` with the copied
+    /// dependencies.
+    #[clap(short, long)]
+    pub update_cargo_toml: bool,
+    /// Instead of copying the WIT files, creates relative symbolic links pointing to the stub WIT root, so
+    /// regenerating the stub automatically updates the destination.
+    #[clap(long)]
+    pub link: bool,
+    /// Print the files that would be created, overwritten or modified, and the Cargo.toml edits, without changing
+    /// anything
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Keep the backup of the changed files after successfully adding the dependency, a failed attempt is always
+    /// rolled back from it
+    #[clap(long)]
+    pub keep_backup: bool,
+}
+
+/// Updates a previously added stub dependency of another WASM component to a newer version
+///
+/// The command copies a newly generated RPC stub over the one previously merged into another component's WIT root,
+/// printing a summary of the added, removed and changed interfaces and functions.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct UpdateStubDependencyArgs {
+    /// The WIT root generated by either `generate` or `build` command
+    #[clap(short, long)]
+    pub stub_wit_root: PathBuf,
+    /// The WIT root of the component where the stub has been added as a dependency
+    #[clap(short, long)]
+    pub dest_wit_root: PathBuf,
     /// Enables updating the Cargo.toml file in the parent directory of `dest-wit-root` with the copied
     /// dependencies.
     #[clap(short, long)]
     pub update_cargo_toml: bool,
 }
 
+/// Restores the stub dependencies of a WASM component saved before overwriting them
+///
+/// The `add-stub-dependency` command, when overwriting existing dependencies, and the `update-stub-dependency` command
+/// save the dependency directories they change to a snapshot in the `.wasm-rpc-backups` directory next to the
+/// destination WIT root. The command restores the latest or the given snapshot.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct RestoreArgs {
+    /// The WIT root of the component whose dependencies are restored
+    #[clap(short, long)]
+    pub dest_wit_root: PathBuf,
+    /// The name of the snapshot to restore, by default the latest one
+    #[clap(long)]
+    pub snapshot: Option<String>,
+    /// List the snapshots instead of restoring one
+    #[clap(long, conflicts_with = "snapshot")]
+    pub list: bool,
+}
+
+/// Removes the artifacts generated by the stub generator
+///
+/// Inside git repositories, the `generate` and `initialize-workspace` commands list the build outputs of the generated
+/// stub crates, the composed components and the backups of the stub dependencies in the managed section of the
+/// `.gitignore` next to them. The command removes the listed artifacts of all the `.gitignore` files in the root
+/// directory and its subdirectories. Crate roots are never removed, even if they are listed.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct CleanArgs {
+    /// The directory to clean, by default the current one
+    #[clap(long, default_value = ".")]
+    pub root: PathBuf,
+    /// Print the artifacts that would be removed, without removing them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
 /// Compose a WASM component with a generated stub WASM
 ///
 /// The command composes a caller component's WASM (which uses the generated stub to call a remote worker) with the
@@ -138,111 +431,1298 @@ pub struct AddStubDependencyArgs {
 #[command(version, about, long_about = None)]
 pub struct ComposeArgs {
     /// The WASM file of the caller component
-    #[clap(long)]
-    pub source_wasm: PathBuf,
+    #[clap(long, required_unless_present = "caller_crate")]
+    pub source_wasm: Option<PathBuf>,
     /// The WASM file of the generated stub. Multiple stubs can be listed.
-    #[clap(long, required = true)]
+    #[clap(long, required_unless_present = "caller_crate")]
     pub stub_wasm: Vec<PathBuf>,
     /// The name of the composed WASM file to be generated
+    #[clap(long, required_unless_present = "caller_crate")]
+    pub dest_wasm: Option<PathBuf>,
+    /// The crate directory of the caller component. Its WASM and the WASM of the stubs in its `wit/deps` directory
+    /// are looked up in the build output directory of its workspace, and the composed WASM is written next to them
+    /// unless `dest-wasm` is specified.
+    #[clap(long, conflicts_with_all = ["source_wasm", "stub_wasm"])]
+    pub caller_crate: Option<PathBuf>,
+    /// Compose the release build of the caller crate instead of the debug one
+    #[clap(long, conflicts_with = "source_wasm")]
+    pub release: bool,
+    /// The cargo target directory the caller crate is built to, if it is not the `target` directory of its workspace
+    #[clap(long, conflicts_with = "source_wasm")]
+    pub target_dir: Option<PathBuf>,
+    /// A wasm-compose configuration file, for example with instantiation arguments. The stubs are added to its
+    /// dependencies.
+    #[clap(long)]
+    pub compose_config: Option<PathBuf>,
+    /// A component defining additional exports of the composed component. Multiple definitions can be listed.
+    #[clap(long)]
+    pub definition: Vec<PathBuf>,
+    /// A directory to look for the components of the imports not satisfied by the stubs, by their names. Multiple
+    /// directories can be listed.
+    #[clap(long)]
+    pub search_path: Vec<PathBuf>,
+    /// Fail if an import cannot be satisfied. By default such imports, like the host interfaces, are left open as
+    /// imports of the composed component.
+    #[clap(long)]
+    pub disallow_imports: bool,
+    /// Import the dependencies as components instead of embedding them in the composed component
+    #[clap(long)]
+    pub import_components: bool,
+    /// Skip validating the composed component
+    #[clap(long)]
+    pub skip_validation: bool,
+    /// Compose even if a stub satisfies none of the imports of the caller component, or an imported stub interface
+    /// is not exported by any of the stubs, only warning about them
+    #[clap(long)]
+    pub allow_unused: bool,
+    /// Write the SHA-256 hash of the composed WASM next to it, to `<dest-wasm>.sha256`, in the format of `sha256sum`
+    #[clap(long)]
+    pub write_hash: bool,
+    /// Write a CycloneDX software bill of materials next to the composed WASM, to `<dest-wasm>.cdx.json`, listing the
+    /// hashes of the caller and the stubs it was composed of and the version of the stub generator
+    #[clap(long)]
+    pub sbom: bool,
+    #[command(flatten)]
+    pub signing: SigningArgs,
+    /// Skip checking that the composed component has all the exports of the caller component, and that no stub
+    /// interfaces are exported by it
+    #[clap(long)]
+    pub skip_verify: bool,
+    /// Remove the debug information, like the DWARF sections and the function names, from the composed WASM and from
+    /// all the components and modules linked into it
+    #[clap(long)]
+    pub strip_debug: bool,
+    /// Remove all the custom sections from the composed WASM and from all the components and modules linked into it
+    #[clap(long)]
+    pub strip_custom_sections: bool,
+    /// Optimize the composed WASM with wasm-opt, if it is installed
+    #[clap(long)]
+    pub optimize: bool,
+    /// An argument passed to wasm-opt when optimizing. Multiple arguments can be listed.
+    #[clap(
+        long = "wasm-opt-arg",
+        allow_hyphen_values = true,
+        default_values = ["-Os"],
+        requires = "optimize"
+    )]
+    pub wasm_opt_args: Vec<String>,
+    /// The library composing the components
+    #[clap(long, value_enum, default_value_t = api::ComposeEngine::WasmCompose)]
+    pub engine: api::ComposeEngine,
+    /// Compose the component and print whether the destination would be created or overwritten, without writing it
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+/// Compose a pipeline of WASM components, each satisfying the imports of the next one
+///
+/// The command links the imports of each component to the interfaces exported by the component before it, and
+/// writes out a composed WASM with the exports of the last component. It is meant for testing small pipelines of
+/// components locally.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ComposeChainArgs {
+    /// The WASM files of the components, in the order of the pipeline. At least two components must be listed.
+    #[clap(long, required = true)]
+    pub wasm: Vec<PathBuf>,
+    /// The name of the composed WASM file to be generated
     #[clap(long)]
     pub dest_wasm: PathBuf,
+    /// The library composing the components
+    #[clap(long, value_enum, default_value_t = api::ComposeEngine::WasmCompose)]
+    pub engine: api::ComposeEngine,
+    /// Write a CycloneDX software bill of materials next to the composed WASM, to `<dest-wasm>.cdx.json`, listing the
+    /// hashes of the components it was composed of and the version of the stub generator
+    #[clap(long)]
+    pub sbom: bool,
+    /// Compose the pipeline and print whether the destination would be created or overwritten, without writing it
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+/// Signs WASM components, so deployments can check that they were not changed after the build
+///
+/// The signature is made over the SHA-256 digest of the component and embedded in its `wasm-rpc-signature` custom
+/// section, replacing the previous signature. It is checked by `inspect --verify-key` or `--verify-command`.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct SignArgs {
+    /// The WASM files to sign in place
+    #[clap(long, required = true)]
+    pub wasm: Vec<PathBuf>,
+    #[command(flatten)]
+    pub signing: SigningArgs,
+}
+
+/// How the components are signed, by a secret key or by an external program
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct SigningArgs {
+    /// Sign with the Ed25519 secret key in the file, as written by `generate-key`
+    #[clap(long, value_name = "SECRET_KEY")]
+    pub sign_key: Option<PathBuf>,
+    /// Sign by running the program, which gets the hexadecimal SHA-256 digest of the component on its standard
+    /// input and prints the hexadecimal signature
+    #[clap(
+        long,
+        value_name = "PROGRAM",
+        conflicts_with = "sign_key",
+        requires = "key_id"
+    )]
+    pub sign_command: Option<PathBuf>,
+    /// The id of the key the program signs with, recorded in the signature
+    #[clap(long, requires = "sign_command")]
+    pub key_id: Option<String>,
+}
+
+impl SigningArgs {
+    fn signer(&self) -> anyhow::Result<Option<Arc<dyn api::Signer>>> {
+        match (&self.sign_key, &self.sign_command) {
+            (Some(key), _) => Ok(Some(Arc::new(api::Ed25519Signer::from_file(key)?))),
+            (None, Some(program)) => Ok(Some(Arc::new(api::CommandSigner {
+                program: program.clone(),
+                args: vec![],
+                key_id: self.key_id.clone().unwrap_or_default(),
+            }))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// Generates an Ed25519 key pair for signing WASM components
+///
+/// The keys are written in hexadecimal. The secret key is used by `sign` and `compose --sign-key`, the public key by
+/// `inspect --verify-key`.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct GenerateKeyArgs {
+    /// The file to write the secret key to. Existing files are not overwritten.
+    #[clap(long)]
+    pub secret_key: PathBuf,
+    /// The file to write the public key to
+    #[clap(long)]
+    pub public_key: PathBuf,
+}
+
+/// Optimizes the size of WASM components with wasm-opt
+///
+/// `wasm-opt` only works on core WASM modules, so the command runs it on each module embedded in the components, and
+/// overwrites the components with the results. If `wasm-opt` is not installed, the components are left unchanged.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct OptimizeArgs {
+    /// The WASM files to optimize in place
+    #[clap(long, required = true)]
+    pub wasm: Vec<PathBuf>,
+    /// An argument passed to wasm-opt. Multiple arguments can be listed.
+    #[clap(long = "wasm-opt-arg", allow_hyphen_values = true, default_values = ["-Os"])]
+    pub wasm_opt_args: Vec<String>,
+}
+
+/// Prints the sizes of WASM components per build profile
+///
+/// The sizes are printed as a table with a row for each component and a column for each profile, taken from the
+/// name of the directory containing the WASM file, like `target/wasm32-wasi/release`.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct SizeReportArgs {
+    /// The WASM files to report. Multiple files can be listed.
+    #[clap(long, required = true)]
+    pub wasm: Vec<PathBuf>,
+}
+
+/// Prints the RPC surface of a WASM component
+///
+/// The command lists the interfaces exported by the component with the WIT signatures of their functions, the stub
+/// interfaces it imports, and the metadata embedded in it, like the tools it was built with.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct InspectArgs {
+    /// The WASM file of the component
+    #[clap(value_name = "COMPONENT")]
+    pub wasm: PathBuf,
+    /// Verify the signature of the component with the Ed25519 public key in the file, failing if the component is
+    /// not signed with its secret key
+    #[clap(long, value_name = "PUBLIC_KEY")]
+    pub verify_key: Option<PathBuf>,
+    /// Verify the signature of the component by running the program, which gets the hexadecimal SHA-256 digest of
+    /// the component on its standard input and the signature in the `WASM_RPC_SIGNATURE`,
+    /// `WASM_RPC_SIGNATURE_ALGORITHM` and `WASM_RPC_KEY_ID` environment variables, and exits successfully if it is
+    /// valid
+    #[clap(long, value_name = "PROGRAM", conflicts_with = "verify_key")]
+    pub verify_command: Option<PathBuf>,
+}
+
+/// Classifies the changes between two versions of a component as breaking or not for its RPC callers
+///
+/// The command compares the interfaces exported by the two components. Removed interfaces and functions, and
+/// changed signatures the callers of the old version cannot call are breaking; added interfaces, functions and
+/// variant cases of parameters are not. The command fails if there are breaking changes, so it can be used as a
+/// release gate.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct CompatArgs {
+    /// The WASM file of the old version of the component
+    #[clap(value_name = "OLD")]
+    pub old_wasm: PathBuf,
+    /// The WASM file of the new version of the component
+    #[clap(value_name = "NEW")]
+    pub new_wasm: PathBuf,
+}
+
+/// Invokes a function of a running worker, for testing and debugging RPC calls
+///
+/// The command calls the worker through the worker API of Golem and prints the results. The arguments are parsed by
+/// the types of the function's parameters, as exported by the worker's component, and can be written either in the
+/// typed JSON format of the Golem API or in WAVE.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct InvokeArgs {
+    /// The URI of the worker, like worker://<component-id>/<worker-name>, optionally followed by /<function>
+    #[clap(long)]
+    pub uri: String,
+    /// The name of the function, like rpc:counter/api.{add}. Required if the URI does not contain the function
+    #[clap(long)]
+    pub function: Option<String>,
+    /// The arguments of the function, in the order of its parameters, each in JSON or WAVE
+    #[clap(long = "arg", value_name = "WAVE_OR_JSON", allow_hyphen_values = true)]
+    pub args: Vec<String>,
+    /// The WASM file of the worker's component, used for the types of the function
+    #[clap(long)]
+    pub component: PathBuf,
+    /// The URL of the Golem worker API
+    #[clap(long, default_value = "http://localhost:9881")]
+    pub api_url: String,
+}
+
+/// Checks whether a component's WIT definition can be used for generating stubs
+///
+/// The command resolves the WIT package with all its dependencies, and reports missing or duplicate dependencies,
+/// missing worlds and WIT features not supported by WASM RPC, together with suggestions on how to fix them.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ValidateArgs {
+    /// The root directory of the component's WIT definition to be validated
+    #[clap(long)]
+    pub wit_root: PathBuf,
+    /// The package to generate the stub for, as `namespace:name`. If there is only a single package in the WIT root,
+    /// no need to specify.
+    #[clap(short, long)]
+    pub package: Option<String>,
+    /// The world name to be used for the stub generation. If there is only a single world in the root
+    /// package, no need to specify.
+    #[clap(short, long)]
+    pub world: Option<String>,
+}
+
+/// Visualizes the RPC dependencies between the components of a Cargo workspace
+///
+/// The command walks the `wit/deps` directories of all the workspace members and emits a graph of the
+/// component → stub → target relationships.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct GraphArgs {
+    /// The root directory of the Cargo workspace
+    #[clap(long, default_value = ".")]
+    pub workspace_root: PathBuf,
+    /// The syntax of the emitted graph. In JSON output mode the graph is part of the JSON result instead.
+    #[clap(long, value_enum, default_value_t = GraphFormat::Dot)]
+    pub graph_format: GraphFormat,
+    /// The file to write the graph to. If not specified, the graph is printed to the standard output.
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+/// Fetches a component's WIT package and its dependencies from a registry
+///
+/// The fetched WIT root can be used as the `source-wit-root` of the `generate` and `build` commands. Registry access
+/// requires the `wkg` CLI, which supports Warg registries such as wa.dev.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct FetchArgs {
+    /// The WIT package to fetch, in the `<namespace>:<name>[@<version>]` format
+    #[clap(short, long)]
+    pub package: String,
+    /// The directory to download the WIT package and its dependencies to
+    #[clap(short, long)]
+    pub dest_wit_root: PathBuf,
+    /// The registry to fetch from. If not specified, the default registry of the `wkg` configuration is used.
+    #[clap(long)]
+    pub registry: Option<String>,
+}
+
+/// Publishes a generated stub's WIT package and compiled WASM to a registry
+///
+/// Callers in other repositories can then depend on the published stub instead of vendoring its files. Registry
+/// access requires the `wkg` CLI, which supports Warg registries such as wa.dev.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct PublishArgs {
+    /// The WIT root generated by either `generate` or `build` command
+    #[clap(short, long)]
+    pub stub_wit_root: PathBuf,
+    /// The compiled stub WASM to publish together with the WIT package
+    #[clap(long)]
+    pub stub_wasm: Option<PathBuf>,
+    /// The package name to publish the stub WASM as. Defaults to the stub WIT package's name with a `-wasm` suffix.
+    #[clap(long)]
+    pub stub_wasm_package: Option<String>,
+    /// The version to publish the packages with
+    #[clap(long)]
+    pub package_version: String,
+    /// The registry to publish to. If not specified, the default registry of the `wkg` configuration is used.
+    #[clap(long)]
+    pub registry: Option<String>,
 }
 
-/// Initializes a Golem-specific cargo-make configuration in a Cargo workspace for automatically
+/// Checks whether the tools required for building stubs are installed
+///
+/// The `build` command needs cargo and the `wasm32-wasi` Rust target. `cargo-component` and `wasm-tools` are not
+/// needed by the stub generator itself, but are checked against the versions it is compatible with.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct CheckToolchainArgs {
+    /// Install the missing Rust target with rustup, after asking for confirmation
+    #[clap(long)]
+    pub install: bool,
+    /// Do not ask for confirmation before installing
+    #[clap(short, long)]
+    pub yes: bool,
+}
+
+/// Initializes a Golem-specific cargo-make, just, make or task configuration in a Cargo workspace for automatically
 /// generating stubs and composing results.
 #[derive(clap::Args, Debug)]
 #[command(version, about, long_about = None)]
 pub struct InitializeWorkspaceArgs {
     /// List of subprojects to be called via RPC
-    #[clap(long, required = true)]
+    #[clap(long, required_unless_present = "auto")]
     pub targets: Vec<String>,
     /// List of subprojects using the generated stubs for calling remote workers
-    #[clap(long, required = true)]
+    #[clap(long, required_unless_present = "auto")]
     pub callers: Vec<String>,
-    /// The path to the `wasm-rpc` crate to be used in the generated stub crate. If not specified,
-    /// the latest version of `wasm-rpc` will be used.
+    /// Infer the targets and the callers from the workspace members: members importing a stub package in their WIT
+    /// worlds or having one in `wit/deps` are callers, and the members the stubs are generated from are targets
+    #[clap(long, conflicts_with_all = ["targets", "callers"])]
+    pub auto: bool,
+    /// The path to the `wasm-rpc-guest` crate to be used in the generated stub crate. If not specified,
+    /// the latest version of `wasm-rpc-guest` will be used. The path of the `wasm-rpc` crate, which stubs depended
+    /// on before, is rejected.
     #[clap(long)]
     pub wasm_rpc_path_override: Option<String>,
+    /// Where the generated stub crate takes the `golem-wasm-rpc-guest` dependency from: a version from crates.io,
+    /// `registry:<name>@<version>`, `path:<path>` or `git:<url>[#<rev>]`. Overrides `wasm-rpc-path-override`.
+    #[clap(long, conflicts_with = "wasm_rpc_path_override")]
+    pub wasm_rpc_source: Option<DependencySource>,
+    /// Where the generated stub crate takes the `wit-bindgen` dependency from, in the same format as
+    /// `wasm-rpc-source`
+    #[clap(long)]
+    pub wit_bindgen_source: Option<DependencySource>,
+    /// The task runner to generate the build configuration for: `cargo-make` (Makefile.toml), `just` (justfile),
+    /// `make` (Makefile) or `task` (Taskfile.yml)
+    #[clap(long, value_enum, default_value_t = BuildTool::CargoMake)]
+    pub build_tool: BuildTool,
+    /// Do not list the build outputs of the generated stub crates and the composed components in the managed
+    /// sections of the `.gitignore` files
+    #[clap(long)]
+    pub no_gitignore: bool,
 }
 
-pub fn generate(args: GenerateArgs) -> anyhow::Result<()> {
-    let stub_def = StubDefinition::new(
-        &args.source_wit_root,
-        &args.dest_crate_root,
-        &args.world,
-        &args.stub_crate_version,
-        &args.wasm_rpc_path_override,
-    )
-    .context("Failed to gather information for the stub generator")?;
-
-    generate_stub_wit(&stub_def).context("Failed to generate the stub wit file")?;
-    copy_wit_files(&stub_def).context("Failed to copy the dependent wit files")?;
-    stub_def
-        .verify_target_wits()
-        .context("Failed to resolve the result WIT root")?;
-    generate_cargo_toml(&stub_def).context("Failed to generate the Cargo.toml file")?;
-    generate_stub_source(&stub_def).context("Failed to generate the stub Rust source")?;
-    Ok(())
+/// Creates a Cargo workspace with components calling each other through RPC
+///
+/// The `target` template creates a counter component and generates its stub, the `caller` template adds a component
+/// calling the counter through the stub to a workspace created with the `target` template, and `pair` creates both.
+/// Each component has its WIT definition, Cargo.toml and sample code, and the workspace gets a cargo-make
+/// configuration regenerating the stubs and composing the caller.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct NewArgs {
+    /// The directory of the workspace. It is created if it does not exist.
+    pub path: PathBuf,
+    /// The components to create
+    #[clap(long, value_enum, default_value_t = ProjectTemplate::Pair)]
+    pub template: ProjectTemplate,
+    /// The namespace of the components' WIT packages
+    #[clap(long, default_value = "rpc")]
+    pub namespace: String,
+    /// The name of the target component
+    #[clap(long, default_value = "counter")]
+    pub target_name: String,
+    /// The name of the caller component
+    #[clap(long, default_value = "caller")]
+    pub caller_name: String,
+    /// Where the generated stub crate takes the `golem-wasm-rpc-guest` dependency from, in the same format as in
+    /// `generate`
+    #[clap(long)]
+    pub wasm_rpc_source: Option<DependencySource>,
+    /// Where the generated stub crate takes the `wit-bindgen` dependency from, in the same format as
+    /// `wasm-rpc-source`
+    #[clap(long)]
+    pub wit_bindgen_source: Option<DependencySource>,
+}
+
+/// Prints the completion script of a shell
+///
+/// For example `wasm-rpc-stubgen completions bash > /etc/bash_completion.d/wasm-rpc-stubgen`.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct CompletionsArgs {
+    /// The shell to generate the completion script for
+    #[clap(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+/// Writes the man pages of all the commands, for packaging
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct MangenArgs {
+    /// The directory to write the man pages to
+    #[clap(long, default_value = "man")]
+    pub out_dir: PathBuf,
+}
+
+/// Checks for a newer release of the stub generator and installs it
+///
+/// Generated stubs and the RPC wire format are only compatible within the same version, so all the components of an
+/// application should be built with the latest release. The binaries are replaced with `cargo install`. Setting the
+/// `WASM_RPC_STUBGEN_UPDATE_CHECK` environment variable to `1` makes every command print a notice when a newer
+/// release is available.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct SelfUpdateArgs {
+    /// Only check whether a newer version is available, without installing it
+    #[clap(long)]
+    pub check: bool,
+    /// Install this version instead of the latest one
+    #[clap(long)]
+    pub target_version: Option<semver::Version>,
+}
+
+/// The `cargo wasm-rpc` subcommand
+#[derive(Parser, Debug)]
+#[command(name = "cargo", bin_name = "cargo")]
+pub enum CargoCommand {
+    /// Generates, builds and composes the WASM RPC stubs of a Cargo workspace, configured by
+    /// `[package.metadata.wasm-rpc]`
+    WasmRpc(CargoWasmRpcArgs),
+}
+
+/// Generates, builds and composes the WASM RPC stubs of a Cargo workspace
+///
+/// Members listing other members in the `calls` field of their `[package.metadata.wasm-rpc]` table are callers, and
+/// get the stubs of the called members added as WIT dependencies.
+#[derive(clap::Args, Debug)]
+#[command(version, about, long_about = None)]
+pub struct CargoWasmRpcArgs {
+    /// Path to a Cargo.toml of the workspace or one of its members. Defaults to the nearest Cargo.toml of the
+    /// current directory.
+    #[clap(long, global = true)]
+    pub manifest_path: Option<PathBuf>,
+    #[command(flatten)]
+    pub verbosity: Verbosity,
+    #[command(subcommand)]
+    pub command: WorkspaceCommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum WorkspaceCommand {
+    /// Generates the stub crates of the called members and adds them to the callers
+    Generate,
+    /// Generates the stub crates and compiles them to the release target directory
+    Build,
+    /// Composes the compiled callers with the stubs of the members they call
+    Compose {
+        /// Compose the release build instead of the debug one
+        #[clap(long)]
+        release: bool,
+    },
+    /// Checks for a newer release of the stub generator and installs it
+    SelfUpdate(SelfUpdateArgs),
+}
+
+/// The result of a command, or the changes it would make with `--dry-run`
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Outcome<T> {
+    Performed(T),
+    DryRun { changes: Vec<FileChange> },
+}
+
+impl<T> Outcome<T> {
+    fn dry_run(changes: Vec<FileChange>) -> Self {
+        if !output::is_json_output() {
+            if changes.is_empty() {
+                println!("No changes");
+            }
+            for change in &changes {
+                println!("{change}");
+            }
+        }
+        Outcome::DryRun { changes }
+    }
+}
+
+/// The outcome of the `build` command
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BuildOutput {
+    Built(api::BuiltStub),
+    /// The key of the stub, with `--print-cache-key`
+    CacheKey {
+        cache_key: String,
+    },
+}
+
+/// The outcome of the `add-stub-dependency` and `update-stub-dependency` commands
+#[derive(Debug, Serialize)]
+pub struct StubDependencyOutput {
+    pub stub_package: String,
+    pub performed: Vec<String>,
+    /// The WIT dependency manager the stub was registered in instead of copying the files
+    pub managed_by: Option<String>,
+    pub updated_cargo_toml: Option<PathBuf>,
+    /// The interface changes compared to the previously added version of the stub
+    pub changes: Option<Vec<InterfaceChange>>,
+    /// The existing destination files which differed from the stub's, and how they were resolved
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<ResolvedConflict>,
+    /// The directory of the kept backup of the changed files, with `--keep-backup`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    /// The snapshot the overwritten dependencies were saved to, see the `restore` command
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+/// The outcome of the `clean` command
+#[derive(Debug, Serialize)]
+pub struct CleanOutput {
+    /// The removed artifacts, or the ones that would be removed with `--dry-run`
+    pub removed: Vec<PathBuf>,
+}
+
+/// The outcome of the `restore` command
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum RestoreOutput {
+    Restored(Snapshot),
+    /// The snapshots, the oldest first, with `--list`
+    Listed {
+        snapshots: Vec<Snapshot>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolvedConflict {
+    pub path: PathBuf,
+    pub resolution: Resolution,
+    /// Whether the merged file has conflict markers to be resolved by hand
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unresolved_markers: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FetchOutput {
+    pub package: String,
+    pub dest_wit_root: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishOutput {
+    /// The published packages, as `<package>@<version>`
+    pub published: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignOutput {
+    pub components: Vec<SignedComponent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedComponent {
+    pub wasm: PathBuf,
+    pub signature: api::ComponentSignature,
 }
 
-pub async fn build(args: BuildArgs) -> anyhow::Result<()> {
-    let target_root = TempDir::new("wasm-rpc-stubgen")?;
+#[derive(Debug, Serialize)]
+pub struct GeneratedKey {
+    pub secret_key: PathBuf,
+    pub public_key: PathBuf,
+    /// The id of the key in the signatures, the hexadecimal public key
+    pub key_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OptimizeOutput {
+    /// Whether wasm-opt was installed. Without it, the components are not changed.
+    pub wasm_opt_installed: bool,
+    pub components: Vec<OptimizedComponent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewProjectOutput {
+    pub workspace: PathBuf,
+    /// The created components
+    pub components: Vec<String>,
+    pub stub_crates: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub makefile: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManPagesOutput {
+    pub pages: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfUpdateOutput {
+    pub current_version: String,
+    pub latest_version: String,
+    pub updated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InitializeWorkspaceOutput {
+    pub makefile: PathBuf,
+    pub targets: Vec<String>,
+    pub callers: Vec<String>,
+    pub stub_crates: Vec<String>,
+    pub tasks: ManagedTaskChanges,
+}
+
+/// The changes of the tasks managed by `initialize-workspace` in the build configuration
+#[derive(Debug, Default, Serialize)]
+pub struct ManagedTaskChanges {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    /// Managed tasks not written, because the configuration defines a task with the same name outside the managed
+    /// section
+    pub kept: Vec<String>,
+}
 
-    let stub_def = StubDefinition::new(
-        &args.source_wit_root,
-        target_root.path(),
-        &args.world,
-        &args.stub_crate_version,
+pub fn generate(args: GenerateArgs) -> anyhow::Result<Outcome<api::GeneratedStub>> {
+    let dependencies = stub_crate_dependencies(
+        &args.wasm_rpc_source,
         &args.wasm_rpc_path_override,
-    )
-    .context("Failed to gather information for the stub generator")?;
-
-    generate_stub_wit(&stub_def).context("Failed to generate the stub wit file")?;
-    copy_wit_files(&stub_def).context("Failed to copy the dependent wit files")?;
-    stub_def
-        .verify_target_wits()
-        .context("Failed to resolve the result WIT root")?;
-    generate_cargo_toml(&stub_def).context("Failed to generate the Cargo.toml file")?;
-    generate_stub_source(&stub_def).context("Failed to generate the stub Rust source")?;
-
-    compile(target_root.path())
-        .await
-        .context("Failed to compile the generated stub")?;
-
-    let wasm_path = target_root
-        .path()
-        .join("target")
-        .join("wasm32-wasi")
-        .join("release")
-        .join(format!(
-            "{}.wasm",
-            stub_def.target_crate_name()?.to_snake_case()
+        &args.wit_bindgen_source,
+        &args.shared_types,
+        &args.converted_types,
+        &args.dest_crate_root,
+    )?;
+    let options = api::GenerateOptions {
+        source_wit_root: args.source_wit_root,
+        dest_crate_root: args.dest_crate_root,
+        package: args.package,
+        world: args.world,
+        stub_crate_version: args.stub_crate_version,
+        stub_package_version: args.stub_package_version,
+        dependencies,
+        force_manifest: args.force_manifest,
+        integration_test: args.with_integration_test,
+        metadata: args.with_metadata,
+        older_wit_root: args.older_wit_root,
+        conventions: read_conventions(&args.conventions)?,
+        include_unstable: args.include_unstable,
+        deny_breaking: args.deny_breaking,
+        keep_backup: args.keep_backup,
+    };
+    if args.dry_run {
+        Ok(Outcome::dry_run(api::plan_stub(&options)?))
+    } else {
+        let generated = api::generate_stub(&options, cli_progress().as_ref())?;
+        if !args.no_gitignore {
+            gitignore::add_build_outputs(&options.dest_crate_root)?;
+        }
+        Ok(Outcome::Performed(generated))
+    }
+}
+
+fn read_conventions(
+    manifest: &Option<PathBuf>,
+) -> anyhow::Result<BTreeMap<String, FunctionConvention>> {
+    match manifest {
+        Some(manifest) => workspace::function_conventions(manifest),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// The dependencies of the generated stub crate. `stub_crate_root` is where relative paths of the dependencies are
+/// resolved from.
+fn stub_crate_dependencies(
+    wasm_rpc_source: &Option<DependencySource>,
+    wasm_rpc_path_override: &Option<String>,
+    wit_bindgen_source: &Option<DependencySource>,
+    shared_types: &[SharedTypes],
+    converted_types: &[SharedTypes],
+    stub_crate_root: &Path,
+) -> anyhow::Result<StubCrateDependencies> {
+    let defaults = StubCrateDependencies::default();
+    let wasm_rpc = match (wasm_rpc_source, wasm_rpc_path_override) {
+        (Some(source), _) => source.clone(),
+        (None, Some(path)) => DependencySource::Path(path.clone()),
+        (None, None) => defaults.wasm_rpc,
+    };
+    if let DependencySource::Path(path) = &wasm_rpc {
+        cargo::check_wasm_rpc_path(path, stub_crate_root)?;
+    }
+    Ok(StubCrateDependencies {
+        wasm_rpc,
+        wit_bindgen: wit_bindgen_source.clone().unwrap_or(defaults.wit_bindgen),
+        shared_types: shared_types.to_vec(),
+        converted_types: converted_types.to_vec(),
+    })
+}
+
+pub async fn build(args: BuildArgs) -> anyhow::Result<BuildOutput> {
+    let options = api::BuildOptions {
+        source_wit_root: args.source_wit_root,
+        dest_wasm: args.dest_wasm,
+        dest_wit_root: args.dest_wit_root,
+        package: args.package,
+        world: args.world,
+        stub_crate_version: args.stub_crate_version,
+        stub_package_version: args.stub_package_version,
+        dependencies: stub_crate_dependencies(
+            &args.wasm_rpc_source,
+            &args.wasm_rpc_path_override,
+            &args.wit_bindgen_source,
+            &args.shared_types,
+            &args.converted_types,
+            Path::new("."),
+        )?,
+        metadata: args.with_metadata,
+        older_wit_root: args.older_wit_root,
+        conventions: read_conventions(&args.conventions)?,
+        include_unstable: args.include_unstable,
+        target_dir: args.target_dir,
+        rustc_wrapper: args.rustc_wrapper,
+        sbom: args.sbom,
+    };
+    if args.print_cache_key {
+        let cache_key = api::stub_cache_key(&options)?;
+        if !output::is_json_output() {
+            println!("{cache_key}");
+        }
+        return Ok(BuildOutput::CacheKey { cache_key });
+    }
+    Ok(BuildOutput::Built(
+        api::build_stub(&options, cli_progress().as_ref()).await?,
+    ))
+}
+
+pub fn add_stub_dependency(
+    args: AddStubDependencyArgs,
+) -> anyhow::Result<Outcome<StubDependencyOutput>> {
+    let (main_wit_package_name, actions) = stub_dependency_actions(&args.stub_wit_root)?;
+    warn_on_stub_version_change(&args.dest_wit_root, &main_wit_package_name);
+    let policy = args.conflict_policy();
+    if args.link && !matches!(policy, ConflictPolicy::Fail | ConflictPolicy::Overwrite) {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            "The --link flag only supports the fail and overwrite conflict policies"
+        ));
+    }
+    if let Some(manager) = DependencyManager::detect(&args.dest_wit_root) {
+        if args.link {
+            bail!(CommandError::new(
+                ErrorKind::InvalidArguments,
+                format!("The --link flag cannot be used because the WIT dependencies of the destination are managed by {manager}")
+            ));
+        }
+        if matches!(policy, ConflictPolicy::Merge | ConflictPolicy::Ask) {
+            bail!(CommandError::new(
+                ErrorKind::InvalidArguments,
+                format!("The WIT dependencies of the destination are managed by {manager}, which only supports the fail, keep and overwrite conflict policies")
+            ));
+        }
+    }
+    if args.dry_run {
+        return Ok(Outcome::dry_run(plan_stub_dependency(&args, &actions)?));
+    }
+    let mut output = StubDependencyOutput::new(&main_wit_package_name);
+    // Failing halfway restores the destination from the journal when it is dropped
+    let journal =
+        stub_dependency_journal(&args.dest_wit_root, &actions, None, args.update_cargo_toml)?;
+
+    if let Some(manager) = DependencyManager::detect(&args.dest_wit_root) {
+        add_managed_dependencies(&manager, &actions, policy)?;
+        output.managed_by = Some(manager.to_string());
+        output.performed = actions.iter().map(|action| action.to_string()).collect();
+    } else {
+        let mut conflicting = Vec::new();
+        let mut resolved = Vec::new();
+        for action in &actions {
+            if args.link || policy == ConflictPolicy::Fail {
+                if !verify_action(
+                    action,
+                    &args.dest_wit_root,
+                    policy == ConflictPolicy::Overwrite,
+                )? {
+                    conflicting.push(action.to_string());
+                }
+            } else {
+                for conflict in action.conflicts(&args.dest_wit_root)? {
+                    let resolution = conflict.resolve(policy)?;
+                    resolved.push((conflict, resolution));
+                }
+            }
+        }
+
+        if !conflicting.is_empty() {
+            bail!(CommandError::new(
+                ErrorKind::Conflict,
+                format!(
+                    "Cannot {} because the destination already exists with a different content. Use --on-conflict to resolve it.",
+                    conflicting.join(", ")
+                )
+            ));
+        }
+        if !matches!(policy, ConflictPolicy::Fail | ConflictPolicy::Keep) {
+            output.snapshot = snapshot_overwritten(&args.dest_wit_root, &actions, &[])?;
+        }
+
+        for action in &actions {
+            if args.link {
+                action.link(&args.dest_wit_root)?;
+                output.performed.push(action.to_string());
+            } else {
+                action.perform(&args.dest_wit_root)?;
+                output.performed.push(action.to_string());
+            }
+        }
+
+        // The copied files are replaced by the chosen content of the conflicting ones
+        for (conflict, resolution) in resolved {
+            let content = conflict.resolved_content(resolution);
+            let unresolved_markers =
+                resolution == Resolution::Merge && has_conflict_markers(&content);
+            match resolution {
+                Resolution::Keep => info!("Keeping {}", conflict.path.to_string_lossy()),
+                Resolution::Overwrite => {
+                    info!("Overwriting {}", conflict.path.to_string_lossy())
+                }
+                Resolution::Merge => info!("Merging into {}", conflict.path.to_string_lossy()),
+            }
+            if unresolved_markers {
+                warn!(
+                    "{} has conflicting changes marked with <<<<<<< and >>>>>>>, resolve them before building",
+                    conflict.path.to_string_lossy()
+                );
+            }
+            fs::write(&conflict.path, content)
+                .context(format!("Failed to write {:?}", conflict.path))?;
+            output.conflicts.push(ResolvedConflict {
+                path: conflict.path,
+                resolution,
+                unresolved_markers,
+            });
+        }
+    }
+
+    output.updated_cargo_toml =
+        update_cargo_toml_dependencies(&args.dest_wit_root, args.update_cargo_toml, actions, None)?;
+    output.backup = journal.commit(args.keep_backup);
+    Ok(Outcome::Performed(output))
+}
+
+/// Records the paths `add-stub-dependency` changes: the dependency manager's manifest or the dependency directories
+/// in `deps`, and the Cargo.toml next to the destination WIT root
+/// Starts the journal of the changes of `add-stub-dependency`, or of `update-stub-dependency` with the `removed`
+/// dependency directories: the dependency directories or the dependency manager's manifest, and the Cargo.toml files
+fn stub_dependency_journal(
+    dest_wit_root: &Path,
+    actions: &[WitAction],
+    removed: Option<&[(PathBuf, PackageName)]>,
+    update_cargo_toml: bool,
+) -> anyhow::Result<Journal> {
+    let root = dest_wit_root.parent().unwrap_or(dest_wit_root);
+    let mut journal = Journal::new(root)?;
+    match DependencyManager::detect(dest_wit_root) {
+        Some(manager) => journal.record(manager.manifest())?,
+        None => {
+            for action in actions {
+                journal.record(&action.target_dep_dir(dest_wit_root)?)?;
+            }
+        }
+    }
+    for (dep_dir, _) in removed.unwrap_or_default() {
+        journal.record(dep_dir)?;
+    }
+    let cargo_toml = root.join("Cargo.toml");
+    // Other manifests are rejected by the update, after the journal rolled back the dependencies
+    if update_cargo_toml && cargo_toml.is_file() && cargo::is_cargo_component_toml(&cargo_toml)? {
+        // The manifests of the workspace the crate inherits its dependencies from are updated too
+        let removed_packages = removed
+            .unwrap_or_default()
+            .iter()
+            .map(|(_, package_name)| package_name.clone())
+            .collect::<Vec<_>>();
+        for update in cargo::plan_cargo_toml_dependencies(
+            &cargo_toml,
+            &cargo_dependencies(actions)?,
+            removed.is_some(),
+            &removed_packages,
+        )? {
+            journal.record(&update.path)?;
+        }
+    }
+    Ok(journal)
+}
+
+/// The changes `add-stub-dependency` would make: the copied files, or the dependency manager's manifest, and the
+/// Cargo.toml edits
+fn plan_stub_dependency(
+    args: &AddStubDependencyArgs,
+    actions: &[WitAction],
+) -> anyhow::Result<Vec<FileChange>> {
+    let mut changes = Vec::new();
+    if let Some(manager) = DependencyManager::detect(&args.dest_wit_root) {
+        changes.extend(
+            manager
+                .plan_dependencies(&managed_dependencies(actions)?, args.conflict_policy())?
+                .change(),
+        );
+    } else {
+        let policy = args.conflict_policy();
+        for action in actions {
+            if policy == ConflictPolicy::Fail && !verify_action(action, &args.dest_wit_root, false)?
+            {
+                bail!(CommandError::new(
+                    ErrorKind::Conflict,
+                    format!("Cannot {action} because the destination already exists with a different content. Use --on-conflict to resolve it.")
+                ));
+            }
+            for change in action.planned_changes(&args.dest_wit_root, args.link)? {
+                match (policy, change.kind) {
+                    (ConflictPolicy::Keep, ChangeKind::Overwrite) => {}
+                    (ConflictPolicy::Merge, ChangeKind::Overwrite) => changes.push(FileChange {
+                        kind: ChangeKind::Modify,
+                        ..change
+                    }),
+                    _ => changes.push(change),
+                }
+            }
+        }
+    }
+
+    if args.update_cargo_toml {
+        let target_cargo_toml = args
+            .dest_wit_root
+            .parent()
+            .map(|parent| parent.join("Cargo.toml"))
+            .filter(|path| path.is_file())
+            .ok_or(anyhow!("Cannot update the Cargo.toml file next to the destination WIT root because it does not exist"))?;
+        changes.extend(
+            cargo::plan_cargo_toml_dependencies(
+                &target_cargo_toml,
+                &cargo_dependencies(actions)?,
+                false,
+                &[],
+            )?
+            .iter()
+            .filter_map(|update| update.change()),
+        );
+    }
+    Ok(changes)
+}
+
+impl AddStubDependencyArgs {
+    fn conflict_policy(&self) -> ConflictPolicy {
+        if self.overwrite {
+            ConflictPolicy::Overwrite
+        } else {
+            self.on_conflict
+        }
+    }
+}
+
+impl StubDependencyOutput {
+    fn new(stub_package: &PackageName) -> Self {
+        Self {
+            stub_package: stub_package.to_string(),
+            performed: Vec::new(),
+            managed_by: None,
+            updated_cargo_toml: None,
+            changes: None,
+            conflicts: Vec::new(),
+            backup: None,
+            snapshot: None,
+        }
+    }
+}
+
+pub fn update_stub_dependency(
+    args: UpdateStubDependencyArgs,
+) -> anyhow::Result<StubDependencyOutput> {
+    let (main_wit_package_name, actions) = stub_dependency_actions(&args.stub_wit_root)?;
+
+    let manager = DependencyManager::detect(&args.dest_wit_root);
+    let is_dependency = match &manager {
+        // The managed dependencies are only fetched into `deps` by the manager
+        Some(manager) => {
+            let stub = managed_dependencies(&actions)?
+                .into_iter()
+                .find(|dependency| {
+                    dependency.package_name
+                        == format!(
+                            "{}:{}",
+                            main_wit_package_name.namespace, main_wit_package_name.name
+                        )
+                })
+                .context("The stub is missing from its own dependencies")?;
+            manager.is_registered(&stub)?
+        }
+        None => args
+            .dest_wit_root
+            .join("deps")
+            .join(format!(
+                "{}_{}",
+                main_wit_package_name.namespace, main_wit_package_name.name
+            ))
+            .exists(),
+    };
+    if !is_dependency {
+        bail!(CommandError::new(
+            ErrorKind::MissingDependency,
+            format!(
+                "The stub {} is not a dependency of {:?} yet. Use the add-stub-dependency command to add it.",
+                main_wit_package_name, args.dest_wit_root
+            )
         ));
-    if let Some(parent) = args.dest_wasm.parent() {
-        fs::create_dir_all(parent)
-            .context("Failed to create parent directory of the target WASM file")?;
     }
-    fs::copy(wasm_path, &args.dest_wasm)
-        .context("Failed to copy the WASM file to the destination")?;
 
-    fs::create_dir_all(&args.dest_wit_root)
-        .context("Failed to create the target WIT root directory")?;
+    warn_on_stub_version_change(&args.dest_wit_root, &main_wit_package_name);
+    let mut output = StubDependencyOutput::new(&main_wit_package_name);
+    match summarize_stub_changes(
+        &args.stub_wit_root,
+        &args.dest_wit_root,
+        &main_wit_package_name,
+    ) {
+        Ok(changes) => {
+            if changes.is_empty() {
+                info!("No interface changes in {main_wit_package_name}");
+            } else {
+                let summary = changes
+                    .iter()
+                    .map(|change| {
+                        let kind = if change.breaking {
+                            "breaking"
+                        } else {
+                            "compatible"
+                        };
+                        format!("  {kind:<10}  {change}")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                info!("Changes in {main_wit_package_name}:\n{summary}");
+            }
+            output.changes = Some(changes);
+        }
+        Err(err) => {
+            warn!("failed to compare the stub with the previous version: {err:?}")
+        }
+    }
 
-    fs_extra::dir::copy(
-        target_root.path().join("wit"),
+    let stale = match &manager {
+        Some(_) => Vec::new(),
+        None => {
+            let new_packages = actions
+                .iter()
+                .map(|action| action.get_package_name())
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            wit::stale_dep_dirs(&args.dest_wit_root, &main_wit_package_name, &new_packages)?
+        }
+    };
+    // Failing halfway restores the destination from the journal when it is dropped
+    let journal = stub_dependency_journal(
         &args.dest_wit_root,
-        &CopyOptions::new().content_only(true).overwrite(true),
-    )
-    .context("Failed to copy the generated WIT files to the destination")?;
+        &actions,
+        Some(&stale),
+        args.update_cargo_toml,
+    )?;
+
+    if let Some(manager) = &manager {
+        add_managed_dependencies(manager, &actions, ConflictPolicy::Overwrite)?;
+        output.managed_by = Some(manager.to_string());
+        output.performed = actions.iter().map(|action| action.to_string()).collect();
+    } else {
+        let stale_dir_names = stale
+            .iter()
+            .filter_map(|(dep_dir, _)| dep_dir.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+        output.snapshot = snapshot_overwritten(&args.dest_wit_root, &actions, &stale_dir_names)?;
+        for action in &actions {
+            action.perform(&args.dest_wit_root)?;
+            output.performed.push(action.to_string());
+        }
+        for (dep_dir, package_name) in &stale {
+            info!("Removing {dep_dir:?}, which is no longer used since the previous version of the stub");
+            if dep_dir.is_symlink() {
+                fs::remove_file(dep_dir)
+            } else {
+                fs::remove_dir_all(dep_dir)
+            }
+            .context(format!("Failed to remove {dep_dir:?}"))?;
+            output.performed.push(format!(
+                "remove stale WIT dependency {package_name} from {}",
+                dep_dir.to_string_lossy()
+            ));
+        }
+    }
+
+    let removed = stale
+        .into_iter()
+        .map(|(_, package_name)| package_name)
+        .collect::<Vec<_>>();
+    output.updated_cargo_toml = update_cargo_toml_dependencies(
+        &args.dest_wit_root,
+        args.update_cargo_toml,
+        actions,
+        Some(&removed),
+    )?;
+    journal.commit(false);
+    Ok(output)
+}
+
+/// Saves the existing dependency directories the actions overwrite, and the given ones to be removed, to a snapshot,
+/// returning its name
+fn snapshot_overwritten(
+    dest_wit_root: &Path,
+    actions: &[WitAction],
+    removed_dep_dirs: &[String],
+) -> anyhow::Result<Option<String>> {
+    let mut dep_dirs = actions
+        .iter()
+        .map(|action| action.get_dep_dir_name())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    dep_dirs.extend(removed_dep_dirs.iter().cloned());
+    if dep_dirs
+        .iter()
+        .any(|dep_dir| dest_wit_root.join("deps").join(dep_dir).exists())
+    {
+        let snapshot = backup::take_snapshot(dest_wit_root, &dep_dirs)?;
+        gitignore::add_managed_artifact(&backup::backups_dir(dest_wit_root))?;
+        Ok(Some(snapshot.name))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn clean(args: CleanArgs) -> anyhow::Result<CleanOutput> {
+    let artifacts = gitignore::managed_artifacts(&args.root)?;
+    for artifact in &artifacts {
+        if args.dry_run {
+            if !output::is_json_output() {
+                println!("Would remove {}", artifact.display());
+            }
+        } else {
+            info!("Removing {artifact:?}");
+            if artifact.is_dir() && !artifact.is_symlink() {
+                fs::remove_dir_all(artifact)
+            } else {
+                fs::remove_file(artifact)
+            }
+            .context(format!("Failed to remove {artifact:?}"))?;
+        }
+    }
+    Ok(CleanOutput { removed: artifacts })
+}
+
+pub fn restore(args: RestoreArgs) -> anyhow::Result<RestoreOutput> {
+    let snapshots = backup::list_snapshots(&args.dest_wit_root)?;
+    if args.list {
+        if !output::is_json_output() {
+            for snapshot in &snapshots {
+                println!("{}", snapshot.name);
+            }
+        }
+        return Ok(RestoreOutput::Listed { snapshots });
+    }
+
+    let found = match &args.snapshot {
+        Some(name) => snapshots.iter().any(|snapshot| &snapshot.name == name),
+        None => !snapshots.is_empty(),
+    };
+    if !found {
+        bail!(CommandError::new(
+            ErrorKind::MissingDependency,
+            format!(
+                "{} in {:?}",
+                match &args.snapshot {
+                    Some(name) => format!("There is no snapshot named {name}"),
+                    None => "There are no snapshots".to_string(),
+                },
+                backup::backups_dir(&args.dest_wit_root)
+            )
+        ));
+    }
+    Ok(RestoreOutput::Restored(backup::restore_snapshot(
+        &args.dest_wit_root,
+        args.snapshot.as_deref(),
+    )?))
+}
+
+/// Registers the stub in the destination's WIT dependency manager instead of copying the files,
+/// so the manager stays the source of truth for the `deps` directory
+fn add_managed_dependencies(
+    manager: &DependencyManager,
+    actions: &[WitAction],
+    policy: ConflictPolicy,
+) -> anyhow::Result<()> {
+    info!("The WIT dependencies of the destination are managed by {manager}");
+    manager.add_dependencies(&managed_dependencies(actions)?, policy)?;
 
+    info!(
+        "Run `{}` to fetch the updated dependencies",
+        manager.fetch_command()
+    );
     Ok(())
 }
 
-pub fn add_stub_dependency(args: AddStubDependencyArgs) -> anyhow::Result<()> {
-    let source_deps = wit::get_dep_dirs(&args.stub_wit_root)?;
+fn managed_dependencies(actions: &[WitAction]) -> anyhow::Result<Vec<ManagedDependency>> {
+    let mut dependencies = Vec::new();
+    for action in actions {
+        let package_name = action.get_package_name()?;
+        let source = match action {
+            WitAction::CopyDepDir { source_dir } => source_dir.clone(),
+            WitAction::CopyDepWit { source_wit, .. } => source_wit
+                .parent()
+                .context("Parent directory of the stub WIT")?
+                .to_path_buf(),
+        };
+        dependencies.push(ManagedDependency {
+            dir_name: action.get_dep_dir_name()?,
+            package_name: format!("{}:{}", package_name.namespace, package_name.name),
+            source,
+        });
+    }
+    Ok(dependencies)
+}
 
-    let main_wit = args.stub_wit_root.join("_stub.wit");
-    let main_wit_package_name = wit::get_package_name(&main_wit)?;
+fn stub_dependency_actions(stub_wit_root: &Path) -> anyhow::Result<(PackageName, Vec<WitAction>)> {
+    let main_wit = stub_wit_root.join("_stub.wit");
+    if !main_wit.is_file() {
+        bail!(CommandError::new(
+            ErrorKind::MissingDependency,
+            format!(
+                "{:?} is not a stub WIT root generated by the generate or build command, it has no _stub.wit",
+                stub_wit_root
+            )
+        ));
+    }
+    let main_wit_package_name = wit::get_package_name(&main_wit)
+        .context(CommandError::new(ErrorKind::InvalidWit, "Invalid stub WIT"))?;
+    let source_deps = wit::get_dep_dirs(stub_wit_root)?;
 
     let mut actions = Vec::new();
     for source_dir in source_deps {
@@ -256,90 +1736,569 @@ pub fn add_stub_dependency(args: AddStubDependencyArgs) -> anyhow::Result<()> {
         ),
     });
 
-    let mut proceed = true;
-    for action in &actions {
-        if !verify_action(action, &args.dest_wit_root, args.overwrite)? {
-            eprintln!("Cannot {action} because the destination already exists with a different content. Use --overwrite to force.");
-            proceed = false;
-        }
-    }
+    Ok((main_wit_package_name, actions))
+}
 
-    if proceed {
-        for action in &actions {
-            action.perform(&args.dest_wit_root)?;
+/// Warns if the destination already holds the same stub with a different package version
+fn warn_on_stub_version_change(dest_wit_root: &Path, stub_package_name: &PackageName) {
+    let existing_wit = dest_wit_root
+        .join("deps")
+        .join(format!(
+            "{}_{}",
+            stub_package_name.namespace, stub_package_name.name
+        ))
+        .join("_stub.wit");
+    if let Ok(existing) = wit::get_package_name(&existing_wit) {
+        if existing.version != stub_package_name.version {
+            let version = |version: &Option<semver::Version>| match version {
+                Some(version) => version.to_string(),
+                None => "unversioned".to_string(),
+            };
+            warn!(
+                "{} holds version {} of {}:{}, which is being replaced with version {}",
+                dest_wit_root.to_string_lossy(),
+                version(&existing.version),
+                stub_package_name.namespace,
+                stub_package_name.name,
+                version(&stub_package_name.version)
+            );
         }
     }
+}
+
+fn summarize_stub_changes(
+    stub_wit_root: &Path,
+    dest_wit_root: &Path,
+    stub_package_name: &PackageName,
+) -> anyhow::Result<Vec<InterfaceChange>> {
+    let mut old_resolve = Resolve::new();
+    old_resolve
+        .push_dir(dest_wit_root)
+        .context("Failed to resolve the destination WIT root")?;
+    let old_package = old_resolve
+        .package_names
+        .iter()
+        .find(|(name, _)| {
+            name.namespace == stub_package_name.namespace && name.name == stub_package_name.name
+        })
+        .map(|(_, id)| *id)
+        .ok_or(anyhow!(
+            "Package {stub_package_name} not found in the destination WIT root"
+        ))?;
+
+    let mut new_resolve = Resolve::new();
+    let (new_package, _) = new_resolve
+        .push_dir(stub_wit_root)
+        .context("Failed to resolve the stub WIT root")?;
+
+    Ok(interface_changes(
+        &package_interfaces(&old_resolve, old_package),
+        &package_interfaces(&new_resolve, new_package),
+    ))
+}
+
+/// The WIT dependencies to be registered in the destination's Cargo.toml
+fn cargo_dependencies(actions: &[WitAction]) -> anyhow::Result<Vec<(String, PackageName)>> {
+    actions
+        .iter()
+        .map(|action| Ok((action.get_dep_dir_name()?, action.get_package_name()?)))
+        .collect()
+}
 
-    if let Some(target_parent) = args.dest_wit_root.parent() {
+/// Registers the copied dependencies in the Cargo.toml next to the destination WIT root, returning its path if it was
+/// updated. When updating a stub, the existing entries are replaced, and the ones of the removed packages are dropped.
+fn update_cargo_toml_dependencies(
+    dest_wit_root: &Path,
+    update_cargo_toml: bool,
+    actions: Vec<WitAction>,
+    removed: Option<&[PackageName]>,
+) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(target_parent) = dest_wit_root.parent() {
         let target_cargo_toml = target_parent.join("Cargo.toml");
         if target_cargo_toml.exists() && target_cargo_toml.is_file() {
-            if !args.update_cargo_toml {
-                eprintln!("Warning: the newly copied dependencies have to be added to {}. Use the --update-cargo-toml flag to update it automatically.", target_cargo_toml.to_string_lossy());
+            if !update_cargo_toml {
+                warn!("the newly copied dependencies have to be added to {}. Use the --update-cargo-toml flag to update it automatically.", target_cargo_toml.to_string_lossy());
             } else {
-                cargo::is_cargo_component_toml(&target_cargo_toml).context(format!(
-                    "The file {target_cargo_toml:?} is not a valid cargo-component project"
-                ))?;
-                let mut names = Vec::new();
-                for action in actions {
-                    names.push(action.get_dep_dir_name()?);
+                if !cargo::is_cargo_component_toml(&target_cargo_toml)? {
+                    return Err(anyhow!(
+                        "The file {target_cargo_toml:?} is not a valid cargo-component project"
+                    ));
+                }
+                let dependencies = cargo_dependencies(&actions)?;
+                match removed {
+                    Some(removed) => cargo::update_dependencies_in_cargo_toml(
+                        &target_cargo_toml,
+                        &dependencies,
+                        removed,
+                    )?,
+                    None => {
+                        cargo::add_dependencies_to_cargo_toml(&target_cargo_toml, &dependencies)?
+                    }
                 }
-                cargo::add_dependencies_to_cargo_toml(&target_cargo_toml, &names)?;
+                return Ok(Some(target_cargo_toml));
             }
-        } else if args.update_cargo_toml {
+        } else if update_cargo_toml {
             return Err(anyhow!(
                 "Cannot update {:?} file because it does not exist or is not a file",
                 target_cargo_toml
             ));
         }
-    } else if args.update_cargo_toml {
+    } else if update_cargo_toml {
         return Err(anyhow!("Cannot update the Cargo.toml file because parent directory of the destination WIT root does not exist."));
     }
 
-    Ok(())
+    Ok(None)
 }
 
-pub fn compose(args: ComposeArgs) -> anyhow::Result<()> {
-    let mut config = wasm_compose::config::Config::default();
+pub fn compose(args: ComposeArgs) -> anyhow::Result<Outcome<api::ComposedComponent>> {
+    let (source_wasm, stub_wasm, dest_wasm) = match &args.caller_crate {
+        Some(caller_crate) => {
+            let profile = if args.release { "release" } else { "debug" };
+            let discovered = workspace::discover_caller_composition(
+                caller_crate,
+                profile,
+                args.target_dir.as_deref(),
+            )
+            .context(format!(
+                "Failed to locate the WASM files of {}",
+                caller_crate.to_string_lossy()
+            ))?;
+            for stub_wasm in &discovered.stub_wasm {
+                info!("Found stub {}", stub_wasm.to_string_lossy());
+            }
+            (
+                discovered.source_wasm,
+                discovered.stub_wasm,
+                args.dest_wasm.unwrap_or(discovered.dest_wasm),
+            )
+        }
+        None => match (args.source_wasm, args.dest_wasm) {
+            (Some(source_wasm), Some(dest_wasm)) if !args.stub_wasm.is_empty() => {
+                (source_wasm, args.stub_wasm, dest_wasm)
+            }
+            _ => bail!(CommandError::new(
+                ErrorKind::InvalidArguments,
+                "Either `caller-crate` or all of `source-wasm`, `stub-wasm` and `dest-wasm` must be specified"
+            )),
+        },
+    };
+    let options = api::ComposeOptions {
+        source_wasm,
+        stub_wasm,
+        dest_wasm,
+        allow_unused: args.allow_unused,
+        skip_verify: args.skip_verify,
+        strip_debug: args.strip_debug,
+        strip_custom_sections: args.strip_custom_sections,
+        wasm_opt_args: args.optimize.then_some(args.wasm_opt_args),
+        engine: args.engine,
+        config: api::ComposeConfig {
+            config_file: args.compose_config,
+            definitions: args.definition,
+            search_paths: args.search_path,
+            disallow_imports: args.disallow_imports,
+            import_components: args.import_components,
+            skip_validation: args.skip_validation,
+        },
+        sbom: args.sbom,
+        signer: args.signing.signer()?,
+    };
+    if args.dry_run {
+        Ok(Outcome::dry_run(api::plan_compose(&options)?))
+    } else {
+        let composed = api::compose(&options, cli_progress().as_ref())?;
+        if args.write_hash {
+            let mut hash_file = composed.wasm.clone().into_os_string();
+            hash_file.push(".sha256");
+            let file_name = composed
+                .wasm
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            info!("Writing the hash of the composed component to {hash_file:?}");
+            fs::write(&hash_file, format!("{}  {file_name}\n", composed.sha256))
+                .context("Failed to write the hash of the composed component")?;
+        }
+        if !output::is_json_output() {
+            print!("{}", composed.stubs);
+            println!("{}", composed.sizes);
+            println!("sha256: {}", composed.sha256);
+            if let Some(sbom) = &composed.sbom {
+                println!("sbom: {}", sbom.to_string_lossy());
+            }
+            if let Some(signature) = &composed.signature {
+                println!("signed: {} key {}", signature.algorithm, signature.key_id);
+            }
+        }
+        Ok(Outcome::Performed(composed))
+    }
+}
 
-    for stub_wasm in &args.stub_wasm {
-        let stub_bytes = fs::read(stub_wasm)?;
-        let stub_component = Component::<IgnoreAllButMetadata>::from_bytes(&stub_bytes)
-            .map_err(|err| anyhow!(err))?;
+pub fn sign(args: SignArgs) -> anyhow::Result<SignOutput> {
+    let signer = args.signing.signer()?.ok_or(anyhow!(CommandError::new(
+        ErrorKind::InvalidArguments,
+        "Either `sign-key` or `sign-command` must be specified"
+    )))?;
+    let mut components = Vec::new();
+    for wasm in args.wasm {
+        let signature = api::sign_component(&wasm, signer.as_ref())?;
+        if !output::is_json_output() {
+            println!(
+                "Signed {} with {} key {}",
+                wasm.to_string_lossy(),
+                signature.algorithm,
+                signature.key_id
+            );
+        }
+        components.push(SignedComponent { wasm, signature });
+    }
+    Ok(SignOutput { components })
+}
 
-        let state = AnalysisContext::new(stub_component);
-        let stub_exports = state.get_top_level_exports().map_err(|err| match err {
-            AnalysisFailure::Failed(msg) => anyhow!(msg),
-        })?;
+pub fn generate_key(args: GenerateKeyArgs) -> anyhow::Result<GeneratedKey> {
+    if args.secret_key.exists() {
+        bail!(CommandError::new(
+            ErrorKind::Conflict,
+            format!("{:?} already exists", args.secret_key)
+        ));
+    }
+    let key_id = signing::generate_key_pair(&args.secret_key, &args.public_key)?;
+    if !output::is_json_output() {
+        println!("{key_id}");
+    }
+    Ok(GeneratedKey {
+        secret_key: args.secret_key,
+        public_key: args.public_key,
+        key_id,
+    })
+}
 
-        for export in stub_exports {
-            if let AnalysedExport::Instance(instance) = export {
-                config.dependencies.insert(
-                    instance.name.clone(),
-                    Dependency {
-                        path: stub_wasm.clone(),
-                    },
+pub fn compose_chain(args: ComposeChainArgs) -> anyhow::Result<Outcome<api::ComposedChain>> {
+    if args.wasm.len() < 2 {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            "At least two components must be listed with `wasm`"
+        ));
+    }
+    let options = api::ComposeChainOptions {
+        wasm: args.wasm,
+        dest_wasm: args.dest_wasm,
+        engine: args.engine,
+        sbom: args.sbom,
+    };
+    if args.dry_run {
+        Ok(Outcome::dry_run(api::plan_compose_chain(&options)?))
+    } else {
+        let composed = api::compose_chain(&options, cli_progress().as_ref())?;
+        if !output::is_json_output() {
+            for link in &composed.links {
+                println!(
+                    "{}: {} -> {}",
+                    link.interface,
+                    link.provider.to_string_lossy(),
+                    link.consumer.to_string_lossy()
                 );
             }
+            println!("sha256: {}", composed.sha256);
+            if let Some(sbom) = &composed.sbom {
+                println!("sbom: {}", sbom.to_string_lossy());
+            }
         }
+        Ok(Outcome::Performed(composed))
     }
+}
 
-    let composer = wasm_compose::composer::ComponentComposer::new(&args.source_wasm, &config);
-    let result = composer.compose()?;
-    println!("Writing composed component to {:?}", args.dest_wasm);
-    fs::write(&args.dest_wasm, result).context("Failed to write the composed component")?;
-    Ok(())
+pub fn inspect(args: InspectArgs) -> anyhow::Result<ComponentInspection> {
+    let verifier: Option<Box<dyn api::Verifier>> = match (args.verify_key, args.verify_command) {
+        (Some(key), _) => Some(Box::new(api::Ed25519Verifier::from_file(&key)?)),
+        (None, Some(program)) => Some(Box::new(api::CommandVerifier {
+            program,
+            args: vec![],
+        })),
+        (None, None) => None,
+    };
+    let inspection = inspect::inspect(&args.wasm, verifier.as_deref())?;
+    if !output::is_json_output() {
+        print!("{inspection}");
+    }
+    Ok(inspection)
+}
+
+pub async fn invoke(args: InvokeArgs) -> anyhow::Result<Invocation> {
+    let invocation = invoke::invoke(
+        &args.api_url,
+        &args.uri,
+        args.function.as_deref(),
+        &args.args,
+        &args.component,
+    )
+    .await?;
+    if !output::is_json_output() {
+        print!("{invocation}");
+    }
+    Ok(invocation)
+}
+
+pub fn compat(args: CompatArgs) -> anyhow::Result<CompatReport> {
+    let report = compat::compare(&args.old_wasm, &args.new_wasm)?;
+    if !output::is_json_output() {
+        print!("{report}");
+    }
+
+    let breaking = report.breaking_count();
+    if breaking > 0 {
+        Err(anyhow!(DetailedError {
+            kind: ErrorKind::CheckFailed,
+            message: format!(
+                "{} has {breaking} breaking change(s) compared to {}",
+                args.new_wasm.to_string_lossy(),
+                args.old_wasm.to_string_lossy()
+            ),
+            details: serde_json::to_value(&report)?,
+        }))
+    } else {
+        info!(
+            "{} is compatible with {} ({} change(s))",
+            args.new_wasm.to_string_lossy(),
+            args.old_wasm.to_string_lossy(),
+            report.changes.len()
+        );
+        Ok(report)
+    }
+}
+
+pub fn optimize(args: OptimizeArgs) -> anyhow::Result<OptimizeOutput> {
+    if !optimize::is_wasm_opt_installed() {
+        info!("wasm-opt is not installed, skipping the optimization. It can be installed from https://github.com/WebAssembly/binaryen");
+        return Ok(OptimizeOutput {
+            wasm_opt_installed: false,
+            components: vec![],
+        });
+    }
+
+    let mut components = Vec::new();
+    for path in &args.wasm {
+        let component = optimize::optimize_component(path, &args.wasm_opt_args)?;
+        info!(
+            "Optimized {}: {} -> {} bytes",
+            path.to_string_lossy(),
+            component.original_size,
+            component.optimized_size
+        );
+        components.push(component);
+    }
+    Ok(OptimizeOutput {
+        wasm_opt_installed: true,
+        components,
+    })
+}
+
+pub fn size_report(args: SizeReportArgs) -> anyhow::Result<SizeReport> {
+    let report = optimize::size_report(&args.wasm)?;
+    if !output::is_json_output() {
+        print!("{report}");
+    }
+    Ok(report)
+}
+
+pub fn validate(args: ValidateArgs) -> anyhow::Result<ValidationReport> {
+    let report = validation::validate_wit_root(&args.wit_root, &args.package, &args.world);
+    if !output::is_json_output() {
+        for issue in &report.issues {
+            eprintln!("{issue}");
+        }
+    }
+
+    let errors = report.error_count();
+    let warnings = report.warning_count();
+    if errors > 0 {
+        Err(anyhow!(DetailedError {
+            kind: ErrorKind::CheckFailed,
+            message: format!(
+                "{} is not valid: found {errors} error(s) and {warnings} warning(s)",
+                args.wit_root.to_string_lossy()
+            ),
+            details: serde_json::to_value(&report)?,
+        }))
+    } else {
+        info!(
+            "{} is valid ({warnings} warning(s))",
+            args.wit_root.to_string_lossy()
+        );
+        Ok(report)
+    }
+}
+
+pub fn graph(args: GraphArgs) -> anyhow::Result<RpcGraph> {
+    let graph = graph::collect_rpc_graph(&args.workspace_root)
+        .context("Failed to collect the RPC dependencies of the workspace")?;
+    let rendered = match args.graph_format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Mermaid => graph.to_mermaid(),
+    };
+
+    match args.output {
+        Some(output) => {
+            info!("Writing RPC dependency graph to {:?}", output);
+            fs::write(output, rendered).context("Failed to write the graph")?;
+        }
+        None if !output::is_json_output() => print!("{rendered}"),
+        None => {}
+    }
+    Ok(graph)
+}
+
+pub fn fetch(args: FetchArgs) -> anyhow::Result<FetchOutput> {
+    registry::fetch(&args.package, &args.dest_wit_root, &args.registry)
+        .context(format!("Failed to fetch {}", args.package))?;
+    Ok(FetchOutput {
+        package: args.package,
+        dest_wit_root: args.dest_wit_root,
+    })
+}
+
+pub fn publish(args: PublishArgs) -> anyhow::Result<PublishOutput> {
+    let published = registry::publish(
+        &args.stub_wit_root,
+        &args.stub_wasm,
+        &args.stub_wasm_package,
+        &args.package_version,
+        &args.registry,
+    )
+    .context("Failed to publish the stub")?;
+    Ok(PublishOutput { published })
+}
+
+pub fn check_toolchain_requirements(args: CheckToolchainArgs) -> anyhow::Result<ToolchainReport> {
+    let mut report = check_toolchain();
+
+    if args.install && report.is_wasm_target_missing() {
+        let confirmed = args.yes || {
+            eprint!("Install the {WASM_TARGET} target with rustup? [y/N] ");
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            matches!(answer.trim(), "y" | "Y" | "yes")
+        };
+        if confirmed {
+            install_wasm_target().context("Failed to install the WASM target")?;
+            report = check_toolchain();
+        }
+    }
+
+    if !output::is_json_output() {
+        print!("{report}");
+    }
+
+    if report.is_ok() {
+        Ok(report)
+    } else {
+        Err(anyhow!(DetailedError {
+            kind: ErrorKind::MissingTools,
+            message: "Required tools are missing".to_string(),
+            details: serde_json::to_value(&report)?,
+        }))
+    }
+}
+
+pub async fn cargo_wasm_rpc(args: CargoWasmRpcArgs) -> anyhow::Result<()> {
+    // Updating the stub generator does not need a workspace
+    let config = || workspace::discover_workspace(&args.manifest_path);
+    match args.command {
+        WorkspaceCommand::Generate => workspace::generate_stubs(&config()?),
+        WorkspaceCommand::Build => workspace::build_stubs(&config()?).await,
+        WorkspaceCommand::Compose { release } => workspace::compose_callers(&config()?, release),
+        WorkspaceCommand::SelfUpdate(self_update_args) => self_update(self_update_args).map(|_| ()),
+    }
 }
 
 pub fn initialize_workspace(
     args: InitializeWorkspaceArgs,
     stubgen_command: &str,
     stubgen_prefix: &[&str],
-) -> anyhow::Result<()> {
+) -> anyhow::Result<InitializeWorkspaceOutput> {
+    let (targets, callers) = if args.auto {
+        let (targets, callers) = graph::infer_targets_and_callers(&std::env::current_dir()?)?;
+        if callers.is_empty() {
+            bail!(CommandError::new(
+                ErrorKind::InvalidArguments,
+                "No callers found in the workspace. Import the stubs of the targets in the callers' worlds, for \
+                 example `import <namespace>:<target>-stub/stub-<world>;`, or list them with `--targets` and `--callers`."
+            ));
+        }
+        info!(
+            "Discovered targets: {}, callers: {}",
+            targets.join(", "),
+            callers.join(", ")
+        );
+        (targets, callers)
+    } else {
+        (args.targets, args.callers)
+    };
+
     make::initialize_workspace(
-        &args.targets,
-        &args.callers,
+        &targets,
+        &callers,
         args.wasm_rpc_path_override,
+        args.wasm_rpc_source,
+        args.wit_bindgen_source,
+        args.build_tool,
+        !args.no_gitignore,
         stubgen_command,
         stubgen_prefix,
     )
 }
+
+pub fn new_project(
+    args: NewArgs,
+    stubgen_command: &str,
+    stubgen_prefix: &[&str],
+) -> anyhow::Result<NewProjectOutput> {
+    scaffold::new_project(args, stubgen_command, stubgen_prefix)
+        .context("Failed to create the project")
+}
+
+pub fn completions(args: CompletionsArgs) {
+    completions::write_completions(Cli::command(), args.shell, &mut std::io::stdout());
+}
+
+pub fn mangen(args: MangenArgs) -> anyhow::Result<ManPagesOutput> {
+    let pages = completions::write_man_pages(Cli::command(), &args.out_dir)?;
+    for page in &pages {
+        info!("Written {}", page.to_string_lossy());
+    }
+    Ok(ManPagesOutput { pages })
+}
+
+pub fn self_update(args: SelfUpdateArgs) -> anyhow::Result<SelfUpdateOutput> {
+    let current = update::current_version();
+    let explicit = args.target_version.is_some();
+    let target = match args.target_version {
+        Some(version) => version,
+        None => update::latest_version().context("Failed to look up the latest version")?,
+    };
+
+    let updated = if args.check {
+        if target > current {
+            info!("A newer version is available: {current} -> {target}");
+        } else {
+            info!("The installed version {current} is up to date");
+        }
+        false
+    } else if target == current {
+        info!("Version {current} is already installed");
+        false
+    } else if !update::should_install(&current, &target, explicit) {
+        info!("The installed version {current} is newer than the latest release {target}");
+        false
+    } else {
+        info!("Installing version {target} over {current}");
+        update::install(&target).context(format!("Failed to install version {target}"))?;
+        true
+    };
+
+    Ok(SelfUpdateOutput {
+        current_version: current.to_string(),
+        latest_version: target.to_string(),
+        updated,
+    })
+}
@@ -0,0 +1,147 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::progress::enable_progress_bars;
+use std::fmt;
+use std::io::IsTerminal;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{FmtSpan, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+/// The verbosity flags shared by the command line tools
+#[derive(clap::Args, Debug, Clone, Copy, Default)]
+pub struct Verbosity {
+    /// Print more details: `-v` for debug messages and the duration of the generate, compile and compose steps,
+    /// `-vv` for tracing the dependencies too
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Print only warnings and errors
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+impl Verbosity {
+    fn filter(&self) -> &'static str {
+        match (self.quiet, self.verbose) {
+            (true, _) => "warn",
+            (false, 0) => "warn,golem_wasm_rpc_stubgen=info",
+            (false, 1) => "warn,golem_wasm_rpc_stubgen=debug",
+            (false, _) => "trace",
+        }
+    }
+}
+
+/// Sets up the global logger writing to the standard error. The `RUST_LOG` environment variable overrides the
+/// level selected by the verbosity flags. Without either, steps are shown on a status line on terminals.
+pub fn init_logging(verbosity: Verbosity) {
+    let env_filter = EnvFilter::try_from_default_env().ok();
+    // The status line would be interleaved with the messages of a more detailed log
+    enable_progress_bars(env_filter.is_none() && !verbosity.quiet && verbosity.verbose == 0);
+    let filter = env_filter.unwrap_or_else(|| EnvFilter::new(verbosity.filter()));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+
+    let result = if verbosity.verbose > 0 {
+        builder
+            .with_span_events(FmtSpan::CLOSE)
+            .with_timer(tracing_subscriber::fmt::time::uptime())
+            .try_init()
+    } else {
+        builder.event_format(PlainFormat).try_init()
+    };
+    if let Err(err) = result {
+        eprintln!("Warning: failed to initialize logging: {err}");
+    }
+}
+
+/// Prints only the messages, prefixing warnings and errors, like the tool's output before it used a logger
+struct PlainFormat;
+
+impl<S, N> FormatEvent<S, N> for PlainFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            _ => {}
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::Verbosity;
+    use crate::{Cli, OutputFormat};
+    use clap::Parser;
+
+    fn verbosity(quiet: bool, verbose: u8) -> Verbosity {
+        Verbosity { verbose, quiet }
+    }
+
+    #[test]
+    fn verbosity_flags_select_the_log_filter() {
+        assert_eq!(verbosity(true, 0).filter(), "warn");
+        assert_eq!(
+            verbosity(false, 0).filter(),
+            "warn,golem_wasm_rpc_stubgen=info"
+        );
+        assert_eq!(
+            verbosity(false, 1).filter(),
+            "warn,golem_wasm_rpc_stubgen=debug"
+        );
+        assert_eq!(verbosity(false, 2).filter(), "trace");
+        assert_eq!(verbosity(false, 5).filter(), "trace");
+    }
+
+    #[test]
+    fn quiet_can_be_combined_with_the_json_output() {
+        let cli = Cli::try_parse_from([
+            "wasm-rpc-stubgen",
+            "--format",
+            "json",
+            "-q",
+            "check-toolchain",
+        ])
+        .unwrap();
+        assert_eq!(cli.format, OutputFormat::Json);
+        assert!(cli.verbosity.quiet);
+
+        let cli = Cli::try_parse_from([
+            "wasm-rpc-stubgen",
+            "check-toolchain",
+            "--format",
+            "json",
+            "-vv",
+        ])
+        .unwrap();
+        assert_eq!(cli.format, OutputFormat::Json);
+        assert_eq!(cli.verbosity.verbose, 2);
+
+        assert!(Cli::try_parse_from(["wasm-rpc-stubgen", "-q", "-v", "check-toolchain"]).is_err());
+    }
+}
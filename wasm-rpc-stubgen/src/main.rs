@@ -17,37 +17,93 @@ use golem_wasm_rpc_stubgen::*;
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
+    let cli = Cli::parse();
+    init_logging(cli.verbosity);
+    set_output_format(cli.format);
+    let format = cli.format;
+    let check_for_update = !matches!(cli.command, Command::SelfUpdate(_));
 
-    match Command::parse() {
-        Command::Generate(generate_args) => {
-            let _ = render_error(generate(generate_args));
+    let exit_code = match cli.command {
+        Command::Generate(generate_args) => render(format, "generate", generate(generate_args)),
+        Command::Build(build_args) => render(format, "build", build(build_args).await),
+        Command::AddStubDependency(add_stub_dependency_args) => render(
+            format,
+            "add-stub-dependency",
+            add_stub_dependency(add_stub_dependency_args),
+        ),
+        Command::UpdateStubDependency(update_stub_dependency_args) => render(
+            format,
+            "update-stub-dependency",
+            update_stub_dependency(update_stub_dependency_args),
+        ),
+        Command::Clean(clean_args) => render(format, "clean", clean(clean_args)),
+        Command::Restore(restore_args) => render(format, "restore", restore(restore_args)),
+        Command::Compose(compose_args) => render(format, "compose", compose(compose_args)),
+        Command::ComposeChain(compose_chain_args) => {
+            render(format, "compose-chain", compose_chain(compose_chain_args))
         }
-        Command::Build(build_args) => {
-            let _ = render_error(build(build_args).await);
+        Command::Sign(sign_args) => render(format, "sign", sign(sign_args)),
+        Command::GenerateKey(generate_key_args) => {
+            render(format, "generate-key", generate_key(generate_key_args))
         }
-        Command::AddStubDependency(add_stub_dependency_args) => {
-            let _ = render_error(add_stub_dependency(add_stub_dependency_args));
+        Command::Optimize(optimize_args) => render(format, "optimize", optimize(optimize_args)),
+        Command::SizeReport(size_report_args) => {
+            render(format, "size-report", size_report(size_report_args))
         }
-        Command::Compose(compose_args) => {
-            let _ = render_error(compose(compose_args));
+        Command::Inspect(inspect_args) => render(format, "inspect", inspect(inspect_args)),
+        Command::Compat(compat_args) => render(format, "compat", compat(compat_args)),
+        Command::Invoke(invoke_args) => render(format, "invoke", invoke(invoke_args).await),
+        Command::Validate(validate_args) => render(format, "validate", validate(validate_args)),
+        Command::Graph(graph_args) => render(format, "graph", graph(graph_args)),
+        Command::Fetch(fetch_args) => render(format, "fetch", fetch(fetch_args)),
+        Command::Publish(publish_args) => render(format, "publish", publish(publish_args)),
+        Command::CheckToolchain(check_toolchain_args) => render(
+            format,
+            "check-toolchain",
+            check_toolchain_requirements(check_toolchain_args),
+        ),
+        Command::InitializeWorkspace(init_workspace_args) => render(
+            format,
+            "initialize-workspace",
+            initialize_workspace(init_workspace_args, "wasm-rpc-stubgen", &[]),
+        ),
+        Command::New(new_args) => render(
+            format,
+            "new",
+            new_project(new_args, "wasm-rpc-stubgen", &[]),
+        ),
+        Command::Completions(completions_args) => {
+            completions(completions_args);
+            0
         }
-        Command::InitializeWorkspace(init_workspace_args) => {
-            let _ = render_error(initialize_workspace(
-                init_workspace_args,
-                "wasm-rpc-stubgen",
-                &[],
-            ));
+        Command::Mangen(mangen_args) => render(format, "mangen", mangen(mangen_args)),
+        Command::SelfUpdate(self_update_args) => {
+            render(format, "self-update", self_update(self_update_args))
         }
+    };
+    if check_for_update {
+        notify_newer_version();
     }
+    std::process::exit(exit_code);
 }
 
-fn render_error<T>(result: anyhow::Result<T>) -> Option<T> {
-    match result {
-        Ok(value) => Some(value),
-        Err(err) => {
-            eprintln!("Error: {:?}", err);
-            None
+/// Prints the outcome of the command, returning the process exit code
+fn render<T: serde::Serialize>(
+    format: OutputFormat,
+    command: &str,
+    result: anyhow::Result<T>,
+) -> i32 {
+    let exit_code = match &result {
+        Ok(_) => 0,
+        Err(err) => ErrorKind::of(err).exit_code(),
+    };
+    match format {
+        OutputFormat::Text => {
+            if let Err(err) = result {
+                eprintln!("Error: {:?}", err);
+            }
         }
+        OutputFormat::Json => println!("{}", json_output(command, &result)),
     }
+    exit_code
 }
@@ -12,353 +12,1054 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{cargo, GenerateArgs};
+use crate::cargo::DependencySource;
+use crate::error::{CommandError, ErrorKind};
+use crate::stub::StubPackageVersion;
+use crate::{cargo, GenerateArgs, InitializeWorkspaceOutput, ManagedTaskChanges};
+use anyhow::{anyhow, bail, Context};
 use heck::ToSnakeCase;
 use std::fs;
 use std::process::Command;
 use toml::map::Map;
 use toml::Value;
+use tracing::{info, warn};
 
+/// The task runner the workspace's build configuration is generated for
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BuildTool {
+    /// A `Makefile.toml` for cargo-make
+    #[default]
+    CargoMake,
+    /// A `justfile` for just
+    Just,
+    /// A `Makefile` for GNU Make
+    Make,
+    /// A `Taskfile.yml` for Task
+    Task,
+}
+
+impl BuildTool {
+    /// The name of the generated file in the workspace root
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            BuildTool::CargoMake => "Makefile.toml",
+            BuildTool::Just => "justfile",
+            BuildTool::Make => "Makefile",
+            BuildTool::Task => "Taskfile.yml",
+        }
+    }
+
+    /// The beginning of a new file, before the managed tasks
+    fn file_header(&self) -> &'static str {
+        match self {
+            BuildTool::Task => "version: '3'\n\ntasks:\n",
+            _ => "",
+        }
+    }
+
+    fn is_installed(&self) -> bool {
+        let executable = match self {
+            BuildTool::CargoMake => "cargo-make",
+            BuildTool::Just => "just",
+            BuildTool::Make => "make",
+            BuildTool::Task => "task",
+        };
+        Command::new(executable)
+            .args(["--version"])
+            .output()
+            .is_ok()
+    }
+
+    fn install_hint(&self) -> &'static str {
+        match self {
+            BuildTool::CargoMake => {
+                "cargo-make is not installed. Please install it with `cargo install cargo-make`"
+            }
+            BuildTool::Just => "just is not installed. Please install it with `cargo install just`",
+            BuildTool::Make => "make is not installed. Please install GNU Make",
+            BuildTool::Task => {
+                "task is not installed. Please install it following https://taskfile.dev/installation"
+            }
+        }
+    }
+}
+
+/// A task of the generated build configuration: an optional command, run after the dependencies in order
+struct Task {
+    name: String,
+    command: Vec<String>,
+    dependencies: Vec<String>,
+    /// Environment variables set for the command
+    env: Vec<(String, String)>,
+    /// Whether the command must run in the workspace root instead of the current member's directory
+    in_workspace_root: bool,
+}
+
+impl Task {
+    fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            command: Vec::new(),
+            dependencies: Vec::new(),
+            env: Vec::new(),
+            in_workspace_root: false,
+        }
+    }
+
+    fn command(mut self, command: &[&str]) -> Self {
+        self.command = command.iter().map(|arg| arg.to_string()).collect();
+        self
+    }
+
+    fn env(mut self, name: &str, value: &str) -> Self {
+        self.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn dependencies<S: AsRef<str>>(mut self, dependencies: &[S]) -> Self {
+        self.dependencies = dependencies
+            .iter()
+            .map(|dependency| dependency.as_ref().to_string())
+            .collect();
+        self
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn initialize_workspace(
     targets: &[String],
     callers: &[String],
     wasm_rpc_path_override: Option<String>,
+    wasm_rpc_source: Option<DependencySource>,
+    wit_bindgen_source: Option<DependencySource>,
+    build_tool: BuildTool,
+    gitignore: bool,
     stubgen_command: &str,
     stubgen_prefix: &[&str],
-) -> anyhow::Result<()> {
+) -> anyhow::Result<InitializeWorkspaceOutput> {
     let cwd = std::env::current_dir()?;
     let workspace_cargo = cwd.join("Cargo.toml");
     if cargo::is_cargo_workspace_toml(&workspace_cargo)? {
-        let makefile_path = cwd.join("Makefile.toml");
-        if makefile_path.exists() && makefile_path.is_file() {
-            Err(anyhow::anyhow!("Makefile.toml already exists. Modifying existing cargo-make projects is currently not supported."))
-        } else if has_cargo_make() {
-            let makefile = generate_makefile(
+        let makefile_path = cwd.join(build_tool.file_name());
+        if build_tool.is_installed() {
+            let existing = if makefile_path.is_file() {
+                Some(fs::read_to_string(&makefile_path)?)
+            } else {
+                None
+            };
+            let (makefile, tasks) = generate_makefile(
                 targets,
                 callers,
                 wasm_rpc_path_override.clone(),
+                &wasm_rpc_source,
+                &wit_bindgen_source,
+                build_tool,
                 stubgen_command,
                 stubgen_prefix,
-            )?;
-            println!("Writing cargo-make Makefile to {:?}", makefile_path);
-            fs::write(makefile_path, makefile)?;
+                existing.as_deref(),
+            )
+            .context(format!("Failed to update {}", build_tool.file_name()))?;
+            for (change, names) in [
+                ("Added", &tasks.added),
+                ("Updated", &tasks.updated),
+                ("Removed", &tasks.removed),
+            ] {
+                if !names.is_empty() {
+                    info!("{change} tasks: {}", names.join(", "));
+                }
+            }
+            if !tasks.kept.is_empty() {
+                warn!(
+                    "Kept the customized tasks defined outside the managed section: {}",
+                    tasks.kept.join(", ")
+                );
+            }
+            info!("Writing {} to {:?}", build_tool.file_name(), makefile_path);
+            fs::write(&makefile_path, makefile)?;
 
             let mut new_members = Vec::new();
             for target in targets {
-                println!("Generating initial stub for {target}");
+                info!("Generating initial stub for {target}");
 
                 let stub_name = format!("{target}-stub");
                 crate::generate(GenerateArgs {
-                    source_wit_root: cwd.join(format!("{target}/wit")),
+                    source_wit_root: cwd.join(target).join("wit"),
                     dest_crate_root: cwd.join(stub_name.clone()),
+                    package: None,
                     world: None,
                     stub_crate_version: "0.0.1".to_string(),
+                    stub_package_version: StubPackageVersion::Same,
                     wasm_rpc_path_override: wasm_rpc_path_override.clone(),
+                    wasm_rpc_source: wasm_rpc_source.clone(),
+                    wit_bindgen_source: wit_bindgen_source.clone(),
+                    shared_types: vec![],
+                    converted_types: vec![],
+                    force_manifest: false,
+                    with_integration_test: false,
+                    with_metadata: false,
+                    older_wit_root: None,
+                    conventions: None,
+                    include_unstable: false,
+                    deny_breaking: false,
+                    dry_run: false,
+                    keep_backup: false,
+                    no_gitignore: !gitignore,
                 })?;
 
                 new_members.push(stub_name);
             }
 
+            if gitignore {
+                crate::gitignore::add_managed_entries(&cwd, &composed_components(callers))?;
+            }
+
             cargo::add_workspace_members(&workspace_cargo, &new_members)?;
 
-            Ok(())
+            Ok(InitializeWorkspaceOutput {
+                makefile: makefile_path,
+                targets: targets.to_vec(),
+                callers: callers.to_vec(),
+                stub_crates: new_members,
+                tasks,
+            })
         } else {
-            Err(anyhow::anyhow!(
-                "cargo-make is not installed. Please install it with `cargo install cargo-make`"
-            ))
+            Err(anyhow::anyhow!(CommandError::new(
+                ErrorKind::MissingTools,
+                build_tool.install_hint()
+            )))
         }
     } else {
         Err(anyhow::anyhow!("Not in a cargo workspace"))
     }
 }
 
-fn generate_makefile(
+/// The `.gitignore` entries of the components composed by the build tasks of the given callers
+fn composed_components(callers: &[String]) -> Vec<String> {
+    ["debug", "release"]
+        .iter()
+        .flat_map(|profile| {
+            callers.iter().map(move |caller| {
+                format!(
+                    "/target/wasm32-wasi/{profile}/{}_composed.wasm",
+                    caller.to_snake_case()
+                )
+            })
+        })
+        .collect()
+}
+
+/// Generates the build configuration of the workspace for the given build tool, merging the managed tasks into the
+/// existing configuration if there is one
+#[allow(clippy::too_many_arguments)]
+pub fn generate_makefile(
     targets: &[String],
     callers: &[String],
     wasm_rpc_path_override: Option<String>,
+    wasm_rpc_source: &Option<DependencySource>,
+    wit_bindgen_source: &Option<DependencySource>,
+    build_tool: BuildTool,
     stubgen_command: &str,
     stubgen_prefix: &[&str],
-) -> anyhow::Result<String> {
-    let mut root = Map::default();
-
-    let mut config = Map::default();
-    config.insert("default_to_workspace".to_string(), Value::Boolean(false));
-    root.insert("config".to_string(), Value::Table(config));
-
-    let mut tasks = Map::default();
-
-    let mut default = Map::default();
-    default.insert("alias".to_string(), Value::String("build".to_string()));
-    tasks.insert("default".to_string(), Value::Table(default));
-
-    let mut clean = Map::default();
-    clean.insert(
-        "command".to_string(),
-        Value::String("cargo-component".to_string()),
-    );
-    clean.insert(
-        "args".to_string(),
-        Value::Array(vec![Value::String("clean".to_string())]),
-    );
-    tasks.insert("clean".to_string(), Value::Table(clean));
-
-    let mut build = Map::default();
-    build.insert(
-        "command".to_string(),
-        Value::String("cargo-component".to_string()),
+    existing: Option<&str>,
+) -> anyhow::Result<(String, ManagedTaskChanges)> {
+    let tasks = workspace_tasks(
+        targets,
+        callers,
+        wasm_rpc_path_override,
+        wasm_rpc_source,
+        wit_bindgen_source,
+        stubgen_command,
+        stubgen_prefix,
     );
-    build.insert(
-        "args".to_string(),
-        Value::Array(vec![Value::String("build".to_string())]),
-    );
-    build.insert(
-        "dependencies".to_string(),
-        Value::Array(vec![
-            Value::String("clean".to_string()),
-            Value::String("regenerate-stubs".to_string()),
-        ]),
-    );
-    tasks.insert("build".to_string(), Value::Table(build));
+    merge_build_file(build_tool, existing, &tasks)
+}
 
-    let mut build_release = Map::default();
-    build_release.insert(
-        "command".to_string(),
-        Value::String("cargo-component".to_string()),
-    );
-    build_release.insert(
-        "args".to_string(),
-        Value::Array(vec![
-            Value::String("build".to_string()),
-            Value::String("--release".to_string()),
-        ]),
-    );
-    build_release.insert(
-        "dependencies".to_string(),
-        Value::Array(vec![
-            Value::String("clean".to_string()),
-            Value::String("regenerate-stubs".to_string()),
-        ]),
-    );
-    tasks.insert("build-release".to_string(), Value::Table(build_release));
+fn workspace_tasks(
+    targets: &[String],
+    callers: &[String],
+    wasm_rpc_path_override: Option<String>,
+    wasm_rpc_source: &Option<DependencySource>,
+    wit_bindgen_source: &Option<DependencySource>,
+    stubgen_command: &str,
+    stubgen_prefix: &[&str],
+) -> Vec<Task> {
+    let stubgen = |args: Vec<String>| {
+        let mut command = vec![stubgen_command.to_string()];
+        command.extend(stubgen_prefix.iter().map(|s| s.to_string()));
+        command.extend(args);
+        Task {
+            command,
+            in_workspace_root: true,
+            ..Task::new("")
+        }
+    };
 
-    let mut test = Map::default();
-    test.insert(
-        "command".to_string(),
-        Value::String("cargo-component".to_string()),
-    );
-    test.insert(
-        "args".to_string(),
-        Value::Array(vec![Value::String("test".to_string())]),
-    );
-    test.insert(
-        "dependencies".to_string(),
-        Value::Array(vec![Value::String("clean".to_string())]),
-    );
-    tasks.insert("test".to_string(), Value::Table(test));
+    let mut tasks = vec![
+        Task::new("default").dependencies(&["build"]),
+        Task::new("clean").command(&["cargo-component", "clean"]),
+        Task::new("build")
+            .command(&["cargo-component", "build"])
+            .dependencies(&["clean", "regenerate-stubs"]),
+        // The release profile is optimized for size even if the workspace does not configure it
+        Task::new("build-release")
+            .command(&["cargo-component", "build", "--release"])
+            .env("CARGO_PROFILE_RELEASE_OPT_LEVEL", "s")
+            .env("CARGO_PROFILE_RELEASE_LTO", "true")
+            .dependencies(&["clean", "regenerate-stubs"]),
+        Task::new("test")
+            .command(&["cargo-component", "test"])
+            .dependencies(&["clean"]),
+    ];
 
+    let mut generate_stub_tasks = Vec::new();
     let mut regenerate_stub_tasks = Vec::new();
     for target in targets {
-        let mut generate_stub = Map::default();
-        generate_stub.insert("cwd".to_string(), Value::String(".".to_string()));
-        generate_stub.insert(
-            "command".to_string(),
-            Value::String(stubgen_command.to_string()),
-        );
-        let mut args = Vec::new();
-        args.extend(stubgen_prefix.iter().map(|s| Value::String(s.to_string())));
-        args.push(Value::String("generate".to_string()));
-        args.push(Value::String("-s".to_string()));
-        args.push(Value::String(format!("{}/wit", target)));
-        args.push(Value::String("-d".to_string()));
-        args.push(Value::String(format!("{}-stub", target)));
+        let mut args = vec![
+            "generate".to_string(),
+            "-s".to_string(),
+            format!("{}/wit", target),
+            "-d".to_string(),
+            format!("{}-stub", target),
+        ];
         if let Some(wasm_rpc_path_override) = wasm_rpc_path_override.as_ref() {
-            args.push(Value::String("--wasm-rpc-path-override".to_string()));
-            args.push(Value::String(wasm_rpc_path_override.to_string()));
+            args.push("--wasm-rpc-path-override".to_string());
+            args.push(wasm_rpc_path_override.to_string());
+        }
+        if let Some(wasm_rpc_source) = wasm_rpc_source {
+            args.push("--wasm-rpc-source".to_string());
+            args.push(wasm_rpc_source.to_string());
+        }
+        if let Some(wit_bindgen_source) = wit_bindgen_source {
+            args.push("--wit-bindgen-source".to_string());
+            args.push(wit_bindgen_source.to_string());
         }
-        generate_stub.insert("args".to_string(), Value::Array(args));
 
         let generate_stub_task_name = format!("generate-{}-stub", target);
-        tasks.insert(generate_stub_task_name.clone(), Value::Table(generate_stub));
+        tasks.push(Task {
+            name: generate_stub_task_name.clone(),
+            ..stubgen(args)
+        });
+        generate_stub_tasks.push(generate_stub_task_name.clone());
 
         for caller in callers {
-            let mut add_stub_dependency = Map::default();
-            add_stub_dependency.insert("cwd".to_string(), Value::String(".".to_string()));
-            add_stub_dependency.insert(
-                "command".to_string(),
-                Value::String(stubgen_command.to_string()),
-            );
-
-            let mut args = Vec::new();
-            args.extend(stubgen_prefix.iter().map(|s| Value::String(s.to_string())));
-            args.extend(vec![
-                Value::String("add-stub-dependency".to_string()),
-                Value::String("--stub-wit-root".to_string()),
-                Value::String(format!("{}-stub/wit", target)),
-                Value::String("--dest-wit-root".to_string()),
-                Value::String(format!("{}/wit", caller)),
-                Value::String("--overwrite".to_string()),
-                Value::String("--update-cargo-toml".to_string()),
-            ]);
-
-            add_stub_dependency.insert("args".to_string(), Value::Array(args));
-            add_stub_dependency.insert(
-                "dependencies".to_string(),
-                Value::Array(vec![Value::String(generate_stub_task_name.clone())]),
-            );
+            let args = vec![
+                "add-stub-dependency".to_string(),
+                "--stub-wit-root".to_string(),
+                format!("{}-stub/wit", target),
+                "--dest-wit-root".to_string(),
+                format!("{}/wit", caller),
+                "--overwrite".to_string(),
+                "--update-cargo-toml".to_string(),
+            ];
 
             let regenerate_task_name = format!("add-stub-dependency-{}-{}", target, caller);
-            tasks.insert(
-                regenerate_task_name.clone(),
-                Value::Table(add_stub_dependency),
-            );
+            tasks.push(Task {
+                name: regenerate_task_name.clone(),
+                ..stubgen(args).dependencies(&[&generate_stub_task_name])
+            });
             regenerate_stub_tasks.push(regenerate_task_name);
         }
     }
+    tasks.push(Task::new("regenerate-stubs").dependencies(&regenerate_stub_tasks));
 
-    let mut regenerate_stubs = Map::default();
-    regenerate_stubs.insert(
-        "dependencies".to_string(),
-        Value::Array(
-            regenerate_stub_tasks
-                .iter()
-                .map(|n| Value::String(n.to_string()))
-                .collect::<Vec<_>>(),
-        ),
-    );
-    tasks.insert(
-        "regenerate-stubs".to_string(),
-        Value::Table(regenerate_stubs),
-    );
+    let mut clean_stubs = vec!["cargo-component".to_string(), "clean".to_string()];
+    for target in targets {
+        clean_stubs.push("-p".to_string());
+        clean_stubs.push(format!("{}-stub", target));
+    }
+    tasks.push(Task {
+        command: clean_stubs,
+        ..Task::new("clean-stubs")
+    });
+    // Stubs of targets without callers in the workspace are only generated by this task, before adding the others
+    // to their callers
+    let mut regenerate_all_stubs = vec!["clean-stubs".to_string()];
+    regenerate_all_stubs.extend(generate_stub_tasks);
+    regenerate_all_stubs.push("regenerate-stubs".to_string());
+    tasks.push(Task::new("regenerate-all-stubs").dependencies(&regenerate_all_stubs));
 
     let mut compose_tasks = Vec::new();
     let mut compose_release_tasks = Vec::new();
+    let mut optimize_args = vec!["optimize".to_string()];
+    let mut size_report_args = vec!["size-report".to_string()];
     for caller in callers {
-        let mut compose = Map::default();
-        compose.insert("cwd".to_string(), Value::String(".".to_string()));
-        compose.insert(
-            "command".to_string(),
-            Value::String(stubgen_command.to_string()),
-        );
-        let mut args = Vec::new();
-        args.extend(stubgen_prefix.iter().map(|s| Value::String(s.to_string())));
-        args.push(Value::String("compose".to_string()));
-        args.push(Value::String("--source-wasm".to_string()));
-        args.push(Value::String(format!(
-            "target/wasm32-wasi/debug/{}.wasm",
-            caller.to_snake_case()
-        )));
-        for target in targets {
-            args.push(Value::String("--stub-wasm".to_string()));
-            args.push(Value::String(format!(
-                "target/wasm32-wasi/debug/{}_stub.wasm",
-                target.to_snake_case()
-            )));
+        for (profile, task_name, profile_tasks) in [
+            ("debug", format!("compose-{}", caller), &mut compose_tasks),
+            (
+                "release",
+                format!("compose-release-{}", caller),
+                &mut compose_release_tasks,
+            ),
+        ] {
+            let mut args = vec![
+                "compose".to_string(),
+                "--source-wasm".to_string(),
+                format!(
+                    "target/wasm32-wasi/{profile}/{}.wasm",
+                    caller.to_snake_case()
+                ),
+            ];
+            for target in targets {
+                args.push("--stub-wasm".to_string());
+                args.push(format!(
+                    "target/wasm32-wasi/{profile}/{}_stub.wasm",
+                    target.to_snake_case()
+                ));
+            }
+            let composed = format!(
+                "target/wasm32-wasi/{profile}/{}_composed.wasm",
+                caller.to_snake_case()
+            );
+            args.push("--dest-wasm".to_string());
+            args.push(composed.clone());
+            if profile == "release" {
+                optimize_args.push("--wasm".to_string());
+                optimize_args.push(composed.clone());
+            }
+            size_report_args.push("--wasm".to_string());
+            size_report_args.push(composed);
+
+            tasks.push(Task {
+                name: task_name.clone(),
+                ..stubgen(args)
+            });
+            profile_tasks.push(task_name);
         }
-        args.push(Value::String("--dest-wasm".to_string()));
-        args.push(Value::String(format!(
-            "target/wasm32-wasi/debug/{}_composed.wasm",
-            caller.to_snake_case()
-        )));
-
-        compose.insert("args".to_string(), Value::Array(args));
-
-        let task_name = format!("compose-{}", caller);
-        tasks.insert(task_name.clone(), Value::Table(compose));
-        compose_tasks.push(task_name);
-
-        let mut compose_release = Map::default();
-        compose_release.insert("cwd".to_string(), Value::String(".".to_string()));
-        compose_release.insert(
-            "command".to_string(),
-            Value::String(stubgen_command.to_string()),
-        );
-        let mut args = Vec::new();
-        args.extend(stubgen_prefix.iter().map(|s| Value::String(s.to_string())));
-        args.push(Value::String("compose".to_string()));
-        args.push(Value::String("--source-wasm".to_string()));
-        args.push(Value::String(format!(
-            "target/wasm32-wasi/release/{}.wasm",
-            caller.to_snake_case()
-        )));
-        for target in targets {
-            args.push(Value::String("--stub-wasm".to_string()));
-            args.push(Value::String(format!(
-                "target/wasm32-wasi/release/{}_stub.wasm",
-                target.to_snake_case()
-            )));
+    }
+
+    tasks.push(Task::new("post-build").dependencies(&compose_tasks));
+    tasks.push(Task::new("post-build-release").dependencies(&compose_release_tasks));
+    tasks.push(Task::new("build-flow").dependencies(&["build", "post-build"]));
+    // Without callers there are no composed components to optimize and report
+    for (task_name, args) in [
+        ("optimize-release", optimize_args),
+        ("size-report", size_report_args),
+    ] {
+        tasks.push(if callers.is_empty() {
+            Task::new(task_name)
+        } else {
+            Task {
+                name: task_name.to_string(),
+                ..stubgen(args)
+            }
+        });
+    }
+    tasks.push(Task::new("release-build-flow").dependencies(&[
+        "build-release",
+        "post-build-release",
+        "optimize-release",
+    ]));
+    // Builds both profiles, printing the sizes of the composed components at the end
+    tasks.push(Task::new("all-flow").dependencies(&[
+        "build-flow",
+        "release-build-flow",
+        "size-report",
+    ]));
+    // Runs the tests after building and composing, without the clean of the `test` task removing the results
+    tasks.push(
+        Task::new("test-components")
+            .command(&["cargo-component", "test"])
+            .env("CARGO_TARGET_WASM32_WASI_RUNNER", "wasmtime")
+            .dependencies(&["build-flow"]),
+    );
+
+    tasks
+}
+
+const BEGIN_GUARD: &str =
+    "# BEGIN wasm-rpc-stubgen managed tasks, regenerated by `wasm-rpc-stubgen initialize-workspace`";
+const END_GUARD: &str = "# END wasm-rpc-stubgen managed tasks";
+
+/// Merges the managed tasks into an existing build configuration, replacing the previously managed ones between the
+/// guard comments and keeping everything else. Tasks defined outside the guards take precedence over the managed
+/// ones with the same name. Without guards, a `Makefile.toml` generated by an earlier version is taken over: its
+/// tasks which are identical to the generated ones become managed, the customized ones are kept.
+fn merge_build_file(
+    build_tool: BuildTool,
+    existing: Option<&str>,
+    tasks: &[Task],
+) -> anyhow::Result<(String, ManagedTaskChanges)> {
+    let file_name = build_tool.file_name();
+    let (mut outside, old_block, after) = match existing.map(split_guards).transpose()? {
+        Some(Some((before, block, after))) => (before, block, Some(after)),
+        Some(None) => (
+            existing.unwrap_or_default().to_string(),
+            String::new(),
+            None,
+        ),
+        None => (build_tool.file_header().to_string(), String::new(), None),
+    };
+    if build_tool == BuildTool::Task && existing.is_some() && after.is_none() {
+        bail!(CommandError::new(
+            ErrorKind::Conflict,
+            format!(
+                "{file_name} has no managed tasks. Add the `{BEGIN_GUARD}` and `{END_GUARD}` lines to its `tasks` mapping to mark where they should be inserted."
+            )
+        ));
+    }
+
+    let mut old_snippets = task_snippets(build_tool, &old_block);
+    let mut has_config = false;
+    if build_tool == BuildTool::CargoMake {
+        let mut document = outside
+            .parse::<toml_edit::DocumentMut>()
+            .context(format!("Failed to parse {file_name}"))?;
+        if after.is_none() && existing.is_some() {
+            let outside_toml = toml::from_str::<toml::Table>(&outside)
+                .context(format!("Failed to parse {file_name}"))?;
+            for task in tasks {
+                let snippet = cargo_make_task(task)?;
+                let outside_task = outside_toml
+                    .get("tasks")
+                    .and_then(|tasks| tasks.get(&task.name));
+                if outside_task.is_some()
+                    && outside_task == toml_value(&snippet, &["tasks", &task.name]).as_ref()
+                {
+                    if let Some(outside_tasks) = document
+                        .get_mut("tasks")
+                        .and_then(|tasks| tasks.as_table_like_mut())
+                    {
+                        outside_tasks.remove(&task.name);
+                    }
+                    old_snippets.push((task.name.clone(), snippet));
+                }
+            }
+            let tasks_are_empty = document
+                .get("tasks")
+                .and_then(|tasks| tasks.as_table_like())
+                .map(|tasks| tasks.is_empty())
+                .unwrap_or(false);
+            if tasks_are_empty {
+                document.remove("tasks");
+            }
+            if outside_toml.get("config").is_some()
+                && outside_toml.get("config")
+                    == toml_value(&cargo_make_config(), &["config"]).as_ref()
+            {
+                document.remove("config");
+            }
+            outside = document.to_string();
+        }
+        has_config = document.contains_key("config");
+    }
+
+    let outside_names = match build_tool {
+        BuildTool::CargoMake => {
+            let outside_toml = toml::from_str::<toml::Table>(&format!(
+                "{outside}\n{}",
+                after.as_deref().unwrap_or_default()
+            ))
+            .context(format!("Failed to parse {file_name}"))?;
+            outside_toml
+                .get("tasks")
+                .and_then(|tasks| tasks.as_table())
+                .map(|tasks| tasks.keys().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
         }
-        args.push(Value::String("--dest-wasm".to_string()));
-        args.push(Value::String(format!(
-            "target/wasm32-wasi/release/{}_composed.wasm",
-            caller.to_snake_case()
-        )));
+        _ => task_snippets(
+            build_tool,
+            &format!("{outside}{}", after.as_deref().unwrap_or_default()),
+        )
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect(),
+    };
 
-        compose_release.insert("args".to_string(), Value::Array(args));
+    let managed = tasks
+        .iter()
+        .filter(|task| !outside_names.contains(&task.name))
+        .collect::<Vec<_>>();
+    let (block, new_snippets) = render_block(build_tool, &managed, !has_config)?;
 
-        let task_name = format!("compose-release-{}", caller);
-        tasks.insert(task_name.clone(), Value::Table(compose_release));
-        compose_release_tasks.push(task_name);
+    let mut changes = ManagedTaskChanges::default();
+    for (name, snippet) in &new_snippets {
+        match old_snippets.iter().find(|(old_name, _)| old_name == name) {
+            None => changes.added.push(name.clone()),
+            Some((_, old_snippet)) if old_snippet.trim() != snippet.trim() => {
+                changes.updated.push(name.clone())
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, _) in &old_snippets {
+        if !new_snippets.iter().any(|(new_name, _)| new_name == name)
+            && !outside_names.contains(name)
+        {
+            changes.removed.push(name.clone());
+        }
     }
+    changes.kept = tasks
+        .iter()
+        .filter(|task| outside_names.contains(&task.name))
+        .map(|task| task.name.clone())
+        .collect();
 
-    let mut post_build = Map::default();
-    post_build.insert(
-        "dependencies".to_string(),
-        Value::Array(
-            compose_tasks
-                .iter()
-                .map(|n| Value::String(n.to_string()))
-                .collect::<Vec<_>>(),
-        ),
-    );
-    tasks.insert("post-build".to_string(), Value::Table(post_build));
+    let result = match after {
+        Some(after) => format!("{outside}{block}{after}"),
+        None if outside.trim().is_empty() => block,
+        None if existing.is_none() => format!("{outside}{block}"),
+        None => format!("{}\n\n{block}", outside.trim()),
+    };
+    if build_tool == BuildTool::CargoMake {
+        toml::from_str::<toml::Table>(&result)
+            .context(format!("The merged {file_name} is not valid TOML"))?;
+    }
+    Ok((result, changes))
+}
+
+/// Splits the file to the text before the managed block, the managed block without the guards and the text after it
+fn split_guards(text: &str) -> anyhow::Result<Option<(String, String, String)>> {
+    let mut offset = 0;
+    let mut begin = None;
+    for line in text.split_inclusive('\n') {
+        let next = offset + line.len();
+        match line.trim() {
+            BEGIN_GUARD if begin.is_none() => begin = Some((offset, next)),
+            END_GUARD => {
+                return match begin {
+                    Some((begin_start, begin_end)) => Ok(Some((
+                        text[..begin_start].to_string(),
+                        text[begin_end..offset].to_string(),
+                        text[next..].to_string(),
+                    ))),
+                    None => Err(anyhow!(
+                        "The end of the managed tasks is marked before their beginning"
+                    )),
+                };
+            }
+            _ => {}
+        }
+        offset = next;
+    }
+    match begin {
+        Some(_) => Err(anyhow!("The end of the managed tasks is not marked")),
+        None => Ok(None),
+    }
+}
+
+/// The tasks defined in the text, with the lines defining them
+fn task_snippets(build_tool: BuildTool, text: &str) -> Vec<(String, String)> {
+    let mut snippets: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        match task_header(build_tool, line) {
+            Some(name) => snippets.push((name, format!("{line}\n"))),
+            None => {
+                if let Some((_, snippet)) = snippets.last_mut() {
+                    snippet.push_str(line);
+                    snippet.push('\n');
+                }
+            }
+        }
+    }
+    snippets
+}
+
+fn task_header(build_tool: BuildTool, line: &str) -> Option<String> {
+    let is_name = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    let name = match build_tool {
+        BuildTool::CargoMake => line
+            .trim()
+            .strip_prefix("[tasks.")?
+            .strip_suffix(']')?
+            .trim_matches('"'),
+        BuildTool::Just | BuildTool::Make => {
+            if line.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let (name, rest) = line.split_once(':')?;
+            if rest.starts_with('=') {
+                return None;
+            }
+            name.trim()
+        }
+        BuildTool::Task => line
+            .strip_prefix("  ")
+            .filter(|rest| !rest.starts_with(char::is_whitespace))?
+            .trim_end()
+            .strip_suffix(':')?,
+    };
+    is_name(name).then(|| name.to_string())
+}
 
-    let mut post_build_release = Map::default();
-    post_build_release.insert(
-        "dependencies".to_string(),
+fn toml_value(text: &str, path: &[&str]) -> Option<toml::Value> {
+    let mut value = toml::Value::Table(toml::from_str::<toml::Table>(text).ok()?);
+    for key in path {
+        value = value.get(key)?.clone();
+    }
+    Some(value)
+}
+
+fn render_block(
+    build_tool: BuildTool,
+    tasks: &[&Task],
+    with_config: bool,
+) -> anyhow::Result<(String, Vec<(String, String)>)> {
+    let indent = if build_tool == BuildTool::Task {
+        "  "
+    } else {
+        ""
+    };
+    let mut block = format!("{indent}{BEGIN_GUARD}\n");
+    match build_tool {
+        BuildTool::CargoMake if with_config => {
+            block.push_str(&cargo_make_config());
+            block.push('\n');
+        }
+        BuildTool::Make => {
+            block.push_str(".PHONY:");
+            for task in tasks {
+                block.push(' ');
+                block.push_str(&task.name);
+            }
+            block.push_str("\n\n");
+        }
+        _ => {}
+    }
+
+    let mut snippets = Vec::new();
+    for task in tasks {
+        let snippet = match build_tool {
+            BuildTool::CargoMake => cargo_make_task(task)?,
+            BuildTool::Just => recipe(task, "    ", |command| command),
+            BuildTool::Make => recipe(task, "\t", |command| command.replace('$', "$$")),
+            BuildTool::Task => taskfile_task(task),
+        };
+        block.push_str(&snippet);
+        if build_tool != BuildTool::Task {
+            block.push('\n');
+        }
+        snippets.push((task.name.clone(), snippet));
+    }
+    block.push_str(&format!("{indent}{END_GUARD}\n"));
+    Ok((block, snippets))
+}
+
+fn cargo_make_config() -> String {
+    "[config]\ndefault_to_workspace = false\n".to_string()
+}
+
+fn cargo_make_task(task: &Task) -> anyhow::Result<String> {
+    let strings = |values: &[String]| {
         Value::Array(
-            compose_release_tasks
+            values
                 .iter()
-                .map(|n| Value::String(n.to_string()))
-                .collect::<Vec<_>>(),
-        ),
-    );
-    tasks.insert(
-        "post-build-release".to_string(),
-        Value::Table(post_build_release),
-    );
+                .map(|value| Value::String(value.to_string()))
+                .collect(),
+        )
+    };
 
-    let mut build_flow = Map::default();
-    build_flow.insert(
-        "dependencies".to_string(),
-        Value::Array(vec![
-            Value::String("build".to_string()),
-            Value::String("post-build".to_string()),
-        ]),
-    );
-    tasks.insert("build-flow".to_string(), Value::Table(build_flow));
-
-    let mut release_build_flow = Map::default();
-    release_build_flow.insert(
-        "dependencies".to_string(),
-        Value::Array(vec![
-            Value::String("build-release".to_string()),
-            Value::String("post-build-release".to_string()),
-        ]),
-    );
-    tasks.insert(
-        "release-build-flow".to_string(),
-        Value::Table(release_build_flow),
-    );
+    let mut table = Map::default();
+    if task.name == "default" {
+        // cargo-make runs the `default` task without arguments, aliasing it keeps the build's own dependencies
+        table.insert("alias".to_string(), Value::String("build".to_string()));
+    } else {
+        if task.in_workspace_root {
+            table.insert("cwd".to_string(), Value::String(".".to_string()));
+        }
+        if !task.env.is_empty() {
+            let env = task
+                .env
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                .collect::<Map<_, _>>();
+            table.insert("env".to_string(), Value::Table(env));
+        }
+        if let Some((command, args)) = task.command.split_first() {
+            table.insert("command".to_string(), Value::String(command.to_string()));
+            table.insert("args".to_string(), strings(args));
+        }
+        if !task.dependencies.is_empty() || task.command.is_empty() {
+            table.insert("dependencies".to_string(), strings(&task.dependencies));
+        }
+    }
 
+    let mut tasks = Map::default();
+    tasks.insert(task.name.clone(), Value::Table(table));
+    let mut root = Map::default();
     root.insert("tasks".to_string(), Value::Table(tasks));
+    Ok(toml::to_string(&root)?)
+}
+
+fn recipe(task: &Task, indent: &str, escape: impl Fn(String) -> String) -> String {
+    let mut result = task.name.clone();
+    result.push(':');
+    for dependency in &task.dependencies {
+        result.push(' ');
+        result.push_str(dependency);
+    }
+    result.push('\n');
+    if !task.command.is_empty() {
+        result.push_str(indent);
+        for (name, value) in &task.env {
+            result.push_str(&escape(format!(
+                "{name}={} ",
+                shell_command(std::slice::from_ref(value))
+            )));
+        }
+        result.push_str(&escape(shell_command(&task.command)));
+        result.push('\n');
+    }
+    result
+}
+
+fn taskfile_task(task: &Task) -> String {
+    let mut result = format!("  {}:\n", task.name);
+    if !task.env.is_empty() {
+        result.push_str("    env:\n");
+        for (name, value) in &task.env {
+            result.push_str(&format!(
+                "      {name}: {}\n",
+                serde_json::Value::String(value.clone())
+            ));
+        }
+    }
+    // Dependencies listed in `deps` would run in parallel, calling them from `cmds` keeps their order
+    result.push_str("    cmds:\n");
+    for dependency in &task.dependencies {
+        result.push_str(&format!("      - task: {dependency}\n"));
+    }
+    if !task.command.is_empty() {
+        result.push_str(&format!(
+            "      - {}\n",
+            serde_json::Value::String(shell_command(&task.command))
+        ));
+    }
+    result
+}
 
-    let result = toml::to_string(&root)?;
-    Ok(result)
+fn shell_command(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| {
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:@=+,%".contains(c))
+            {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-fn has_cargo_make() -> bool {
-    Command::new("cargo-make")
-        .args(["--version"])
-        .output()
-        .is_ok()
+#[cfg(test)]
+mod tests {
+    use crate::make::{generate_makefile, BuildTool};
+    use crate::ManagedTaskChanges;
+
+    fn generate(
+        build_tool: BuildTool,
+        callers: &[&str],
+        existing: Option<&str>,
+    ) -> (String, ManagedTaskChanges) {
+        generate_makefile(
+            &["counter".to_string()],
+            &callers
+                .iter()
+                .map(|caller| caller.to_string())
+                .collect::<Vec<_>>(),
+            None,
+            &None,
+            &None,
+            build_tool,
+            "wasm-rpc-stubgen",
+            &[],
+            existing,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn cargo_make_tasks_are_generated() {
+        let (makefile, changes) = generate(BuildTool::CargoMake, &["caller"], None);
+        let makefile: toml::Value = toml::from_str(&makefile).unwrap();
+        let tasks = &makefile["tasks"];
+
+        assert_eq!(tasks["default"]["alias"].as_str(), Some("build"));
+        assert_eq!(
+            tasks["build"]["dependencies"],
+            toml::Value::Array(vec!["clean".into(), "regenerate-stubs".into()])
+        );
+        assert_eq!(
+            tasks["regenerate-stubs"]["dependencies"],
+            toml::Value::Array(vec!["add-stub-dependency-counter-caller".into()])
+        );
+        assert_eq!(tasks["compose-caller"]["cwd"].as_str(), Some("."));
+        assert_eq!(
+            tasks["regenerate-all-stubs"]["dependencies"],
+            toml::Value::Array(vec![
+                "clean-stubs".into(),
+                "generate-counter-stub".into(),
+                "regenerate-stubs".into()
+            ])
+        );
+        assert_eq!(
+            tasks["test-components"]["env"]["CARGO_TARGET_WASM32_WASI_RUNNER"].as_str(),
+            Some("wasmtime")
+        );
+        assert_eq!(
+            tasks["build-release"]["env"]["CARGO_PROFILE_RELEASE_OPT_LEVEL"].as_str(),
+            Some("s")
+        );
+        assert_eq!(
+            tasks["optimize-release"]["args"],
+            toml::Value::Array(vec![
+                "optimize".into(),
+                "--wasm".into(),
+                "target/wasm32-wasi/release/caller_composed.wasm".into()
+            ])
+        );
+        assert_eq!(
+            tasks["all-flow"]["dependencies"],
+            toml::Value::Array(vec![
+                "build-flow".into(),
+                "release-build-flow".into(),
+                "size-report".into()
+            ])
+        );
+        assert!(changes.added.contains(&"build-flow".to_string()));
+    }
+
+    #[test]
+    fn other_build_tools_have_the_same_recipes() {
+        let (justfile, _) = generate(BuildTool::Just, &["caller"], None);
+        assert!(justfile.contains("\ndefault: build\n"));
+        assert!(justfile.contains("build: clean regenerate-stubs\n    cargo-component build\n"));
+        assert!(justfile.contains(
+            "generate-counter-stub:\n    wasm-rpc-stubgen generate -s counter/wit -d counter-stub\n"
+        ));
+
+        let (makefile, _) = generate(BuildTool::Make, &["caller"], None);
+        assert!(makefile.contains("\n.PHONY: default clean build"));
+        assert!(makefile.contains("build-flow: build post-build\n"));
+        assert!(makefile.contains("\twasm-rpc-stubgen compose --source-wasm"));
+        assert!(makefile.contains(
+            "test-components: build-flow\n\tCARGO_TARGET_WASM32_WASI_RUNNER=wasmtime cargo-component test\n"
+        ));
+
+        let (taskfile, _) = generate(BuildTool::Task, &["caller"], None);
+        assert!(taskfile.starts_with("version: '3'\n\ntasks:\n"));
+        assert!(taskfile.contains(
+            "  build:\n    cmds:\n      - task: clean\n      - task: regenerate-stubs\n      - \"cargo-component build\"\n"
+        ));
+    }
+
+    #[test]
+    fn stubs_of_targets_without_callers_are_regenerated() {
+        let (makefile, _) = generate(BuildTool::CargoMake, &[], None);
+        let makefile: toml::Value = toml::from_str(&makefile).unwrap();
+        let tasks = &makefile["tasks"];
+
+        assert_eq!(
+            tasks["regenerate-stubs"]["dependencies"],
+            toml::Value::Array(vec![])
+        );
+        assert_eq!(
+            tasks["regenerate-all-stubs"]["dependencies"],
+            toml::Value::Array(vec![
+                "clean-stubs".into(),
+                "generate-counter-stub".into(),
+                "regenerate-stubs".into()
+            ])
+        );
+        assert_eq!(
+            tasks["clean-stubs"]["command"].as_str(),
+            Some("cargo-component")
+        );
+        assert_eq!(
+            tasks["clean-stubs"]["args"],
+            toml::Value::Array(vec!["clean".into(), "-p".into(), "counter-stub".into()])
+        );
+
+        let (justfile, _) = generate(BuildTool::Just, &[], None);
+        assert!(justfile.contains("clean-stubs:\n    cargo-component clean -p counter-stub\n"));
+        assert!(justfile.contains(
+            "regenerate-all-stubs: clean-stubs generate-counter-stub regenerate-stubs\n"
+        ));
+
+        let (taskfile, _) = generate(BuildTool::Task, &[], None);
+        assert!(taskfile.contains(
+            "  test-components:\n    env:\n      CARGO_TARGET_WASM32_WASI_RUNNER: \"wasmtime\"\n    cmds:\n      - task: build-flow\n"
+        ));
+    }
+
+    #[test]
+    fn rerun_updates_only_the_managed_tasks() {
+        let (makefile, _) = generate(BuildTool::CargoMake, &["caller"], None);
+        let customized = format!(
+            "[env]\nRUST_LOG = \"info\"\n\n{makefile}\n[tasks.deploy]\ncommand = \"golem\"\n\n[tasks.test]\ncommand = \"cargo\"\nargs = [\"test\"]\n"
+        );
+
+        let (makefile, changes) = generate(
+            BuildTool::CargoMake,
+            &["caller", "other-caller"],
+            Some(&customized),
+        );
+
+        assert!(makefile.starts_with("[env]\nRUST_LOG = \"info\"\n"));
+        assert!(makefile.contains("[tasks.deploy]\ncommand = \"golem\"\n"));
+        assert!(changes
+            .added
+            .contains(&"add-stub-dependency-counter-other-caller".to_string()));
+        assert!(changes.added.contains(&"compose-other-caller".to_string()));
+        assert_eq!(
+            changes.updated,
+            vec![
+                "regenerate-stubs",
+                "post-build",
+                "post-build-release",
+                "optimize-release",
+                "size-report"
+            ]
+        );
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.kept, vec!["test"]);
+
+        let makefile: toml::Value = toml::from_str(&makefile).unwrap();
+        assert_eq!(makefile["tasks"]["test"]["command"].as_str(), Some("cargo"));
+        assert_eq!(makefile["env"]["RUST_LOG"].as_str(), Some("info"));
+    }
+
+    #[test]
+    fn makefile_of_earlier_versions_is_taken_over() {
+        let legacy = r#"[config]
+default_to_workspace = false
+
+[tasks.default]
+alias = "build"
+
+[tasks.clean]
+command = "cargo-component"
+args = ["clean"]
+
+[tasks.build]
+command = "cargo-component"
+args = ["build", "--verbose"]
+dependencies = ["clean", "regenerate-stubs"]
+"#;
+
+        let (makefile, changes) = generate(BuildTool::CargoMake, &["caller"], Some(legacy));
+
+        assert!(!changes.added.contains(&"clean".to_string()));
+        assert!(changes.added.contains(&"build-flow".to_string()));
+        assert_eq!(changes.kept, vec!["build"]);
+        assert!(makefile.starts_with("[tasks.build]\n"));
+        let makefile: toml::Value = toml::from_str(&makefile).unwrap();
+        assert_eq!(
+            makefile["tasks"]["build"]["args"],
+            toml::Value::Array(vec!["build".into(), "--verbose".into()])
+        );
+        assert_eq!(
+            makefile["config"]["default_to_workspace"].as_bool(),
+            Some(false)
+        );
+    }
 }
@@ -0,0 +1,388 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::{CommandError, ErrorKind};
+use anyhow::{anyhow, bail, Context};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempdir::TempDir;
+use wasmparser::{Chunk, Parser, Payload};
+
+const MODULE_SECTION_ID: u8 = 1;
+const COMPONENT_SECTION_ID: u8 = 4;
+
+#[derive(Debug, Serialize)]
+pub struct OptimizedComponent {
+    pub path: PathBuf,
+    pub original_size: u64,
+    pub optimized_size: u64,
+}
+
+pub fn is_wasm_opt_installed() -> bool {
+    Command::new("wasm-opt")
+        .args(["--version"])
+        .output()
+        .is_ok()
+}
+
+/// Optimizes the core modules embedded in a component with `wasm-opt`, which does not support components itself,
+/// and overwrites the component with the result
+pub fn optimize_component(
+    path: &Path,
+    wasm_opt_args: &[String],
+) -> anyhow::Result<OptimizedComponent> {
+    let original = fs::read(path).context(format!("Failed to read {path:?}"))?;
+    let optimized =
+        optimize_wasm(&original, wasm_opt_args).context(format!("Failed to optimize {path:?}"))?;
+    fs::write(path, &optimized).context(format!("Failed to write {path:?}"))?;
+
+    Ok(OptimizedComponent {
+        path: path.to_path_buf(),
+        original_size: original.len() as u64,
+        optimized_size: optimized.len() as u64,
+    })
+}
+
+/// Optimizes a component or a core module with `wasm-opt`, returning the result
+pub fn optimize_wasm(wasm: &[u8], wasm_opt_args: &[String]) -> anyhow::Result<Vec<u8>> {
+    let temp_dir = TempDir::new("wasm-opt")?;
+    let mut index = 0;
+    let mut run_wasm_opt = |module: &[u8]| {
+        index += 1;
+        let input = temp_dir.path().join(format!("module-{index}.wasm"));
+        let output = temp_dir.path().join(format!("module-{index}.opt.wasm"));
+        fs::write(&input, module)?;
+        let result = Command::new("wasm-opt")
+            .args(wasm_opt_args)
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .output()
+            .map_err(|err| {
+                anyhow!(CommandError::new(
+                    ErrorKind::MissingTools,
+                    format!("Failed to run wasm-opt: {err}")
+                ))
+            })?;
+        if !result.status.success() {
+            bail!(
+                "wasm-opt failed with {}: {}",
+                result.status,
+                String::from_utf8_lossy(&result.stderr).trim()
+            );
+        }
+        Ok(fs::read(&output)?)
+    };
+
+    if Parser::is_component(wasm) {
+        map_modules(wasm, &mut run_wasm_opt)
+    } else {
+        run_wasm_opt(wasm)
+    }
+}
+
+/// Re-encodes a component, replacing each of its core modules, including the ones of nested components, with the
+/// result of `f`. All the other sections are copied as they are.
+fn map_modules(
+    component: &[u8],
+    f: &mut dyn FnMut(&[u8]) -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut parser = Parser::new(0);
+    let mut result = Vec::with_capacity(component.len());
+    let mut offset = 0;
+    loop {
+        let (payload, consumed) = match parser.parse(&component[offset..], true)? {
+            Chunk::Parsed { payload, consumed } => (payload, consumed),
+            Chunk::NeedMoreData(_) => bail!("Unexpected end of the component"),
+        };
+        match payload {
+            Payload::ModuleSection { range, .. } => {
+                let module = f(&component[range.clone()])?;
+                write_section(&mut result, MODULE_SECTION_ID, &module);
+                offset += consumed + range.len();
+            }
+            Payload::ComponentSection { range, .. } => {
+                let nested = map_modules(&component[range.clone()], f)?;
+                write_section(&mut result, COMPONENT_SECTION_ID, &nested);
+                offset += consumed + range.len();
+            }
+            Payload::End(_) => break,
+            _ => {
+                result.extend_from_slice(&component[offset..offset + consumed]);
+                offset += consumed;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Whether a custom section holds debug information, like the DWARF sections and the names of the functions
+pub fn is_debug_section(name: &str) -> bool {
+    name.starts_with(".debug_")
+        || matches!(
+            name,
+            "name" | "component-name" | "sourceMappingURL" | "external_debug_info"
+        )
+}
+
+/// Removes the custom sections for which `remove` returns true from a component or a core module, including the
+/// modules and components nested in it
+pub fn strip_sections(wasm: &[u8], remove: &dyn Fn(&str) -> bool) -> anyhow::Result<Vec<u8>> {
+    let mut parser = Parser::new(0);
+    let mut result = Vec::with_capacity(wasm.len());
+    let mut offset = 0;
+    loop {
+        let (payload, consumed) = match parser.parse(&wasm[offset..], true)? {
+            Chunk::Parsed { payload, consumed } => (payload, consumed),
+            Chunk::NeedMoreData(_) => bail!("Unexpected end of the WASM file"),
+        };
+        match payload {
+            Payload::ModuleSection { range, .. } => {
+                let module = strip_sections(&wasm[range.clone()], remove)?;
+                write_section(&mut result, MODULE_SECTION_ID, &module);
+                offset += consumed + range.len();
+            }
+            Payload::ComponentSection { range, .. } => {
+                let nested = strip_sections(&wasm[range.clone()], remove)?;
+                write_section(&mut result, COMPONENT_SECTION_ID, &nested);
+                offset += consumed + range.len();
+            }
+            Payload::CustomSection(section) if remove(section.name()) => {
+                offset += consumed;
+            }
+            Payload::End(_) => break,
+            _ => {
+                result.extend_from_slice(&wasm[offset..offset + consumed]);
+                offset += consumed;
+            }
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn write_section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+    out.push(id);
+    write_leb128(out, content.len());
+    out.extend_from_slice(content);
+}
+
+/// Writes an unsigned integer in the LEB128 encoding of the lengths in WASM files
+pub(crate) fn write_leb128(out: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentSize {
+    pub path: PathBuf,
+    /// The file name of the component, without the extension
+    pub component: String,
+    /// The name of the directory containing the component, which is the profile for the files built by cargo
+    pub profile: String,
+    pub size: u64,
+}
+
+/// The sizes of a set of components, displayed as a table with a row per component and a column per profile
+#[derive(Debug, Serialize)]
+pub struct SizeReport {
+    pub components: Vec<ComponentSize>,
+}
+
+pub fn size_report(paths: &[PathBuf]) -> anyhow::Result<SizeReport> {
+    let mut components = Vec::new();
+    for path in paths {
+        let metadata = fs::metadata(path).map_err(|err| {
+            anyhow!(CommandError::new(
+                ErrorKind::MissingDependency,
+                format!("Failed to read {path:?}: {err}")
+            ))
+        })?;
+        let name = |path: Option<&Path>| {
+            path.and_then(|path| path.file_stem())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        components.push(ComponentSize {
+            path: path.clone(),
+            component: name(Some(path)),
+            profile: name(path.parent()),
+            size: metadata.len(),
+        });
+    }
+    Ok(SizeReport { components })
+}
+
+impl Display for SizeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut names = Vec::new();
+        let mut profiles = Vec::new();
+        for component in &self.components {
+            if !names.contains(&component.component) {
+                names.push(component.component.clone());
+            }
+            if !profiles.contains(&component.profile) {
+                profiles.push(component.profile.clone());
+            }
+        }
+
+        let mut rows = vec![std::iter::once("Component".to_string())
+            .chain(profiles.iter().cloned())
+            .collect::<Vec<_>>()];
+        for name in &names {
+            let mut row = vec![name.clone()];
+            for profile in &profiles {
+                let size = self
+                    .components
+                    .iter()
+                    .find(|c| &c.component == name && &c.profile == profile)
+                    .map(|c| format_size(c.size))
+                    .unwrap_or_else(|| "-".to_string());
+                row.push(size);
+            }
+            rows.push(row);
+        }
+
+        let widths = (0..=profiles.len())
+            .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        for row in rows {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    if column == 0 {
+                        format!("{cell:<width$}")
+                    } else {
+                        format!("{cell:>width$}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn format_size(size: u64) -> String {
+    const KIB: f64 = 1024.0;
+    let size_f = size as f64;
+    if size_f < KIB {
+        format!("{size} B")
+    } else if size_f < KIB * KIB {
+        format!("{:.1} KiB", size_f / KIB)
+    } else {
+        format!("{:.1} MiB", size_f / KIB / KIB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimize::{is_debug_section, map_modules, size_report, strip_sections};
+    use tempdir::TempDir;
+    use wasmparser::{Parser, Payload};
+
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+    const COMPONENT_HEADER: &[u8] = b"\0asm\x0d\0\x01\0";
+
+    fn section(id: u8, content: &[u8]) -> Vec<u8> {
+        let mut section = vec![id, content.len() as u8];
+        section.extend_from_slice(content);
+        section
+    }
+
+    fn modules(component: &[u8]) -> Vec<Vec<u8>> {
+        Parser::new(0)
+            .parse_all(component)
+            .filter_map(|payload| match payload.unwrap() {
+                Payload::ModuleSection { range, .. } => Some(component[range].to_vec()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn modules_of_nested_components_are_replaced() {
+        let mut nested = COMPONENT_HEADER.to_vec();
+        nested.extend(section(1, MODULE));
+        let mut component = COMPONENT_HEADER.to_vec();
+        component.extend(section(1, MODULE));
+        component.extend(section(4, &nested));
+        component.extend(section(0, b"\x04name"));
+
+        let result = map_modules(&component, &mut |module| {
+            let mut module = module.to_vec();
+            module.extend(section(0, b"\x03opt"));
+            Ok(module)
+        })
+        .unwrap();
+
+        let mut optimized = MODULE.to_vec();
+        optimized.extend(section(0, b"\x03opt"));
+        assert_eq!(modules(&result), vec![optimized.clone(), optimized]);
+        assert!(result.ends_with(&section(0, b"\x04name")));
+        assert_eq!(result.len(), component.len() + 2 * 6);
+    }
+
+    #[test]
+    fn debug_sections_of_nested_modules_are_stripped() {
+        let mut module = MODULE.to_vec();
+        module.extend(section(0, b"\x0b.debug_info"));
+        module.extend(section(0, b"\x04name"));
+        module.extend(section(0, b"\x03opt"));
+        let mut component = COMPONENT_HEADER.to_vec();
+        component.extend(section(1, &module));
+        component.extend(section(0, b"\x0ecomponent-name"));
+
+        let result = strip_sections(&component, &is_debug_section).unwrap();
+
+        let mut stripped = MODULE.to_vec();
+        stripped.extend(section(0, b"\x03opt"));
+        assert_eq!(modules(&result), vec![stripped.clone()]);
+        let mut expected = COMPONENT_HEADER.to_vec();
+        expected.extend(section(1, &stripped));
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn sizes_are_reported_per_component_and_profile() {
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let mut paths = Vec::new();
+        for (profile, size) in [("debug", 3000), ("release", 100)] {
+            let dir = temp_dir.path().join(profile);
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("caller_composed.wasm");
+            std::fs::write(&path, vec![0; size]).unwrap();
+            paths.push(path);
+        }
+
+        let report = size_report(&paths).unwrap();
+        assert_eq!(
+            report.to_string(),
+            "Component          debug  release\n\
+             caller_composed  2.9 KiB    100 B\n"
+        );
+    }
+}
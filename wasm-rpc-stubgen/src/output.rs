@@ -0,0 +1,125 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::ErrorKind;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// JSON, for tools and CI pipelines
+    Json,
+}
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Selects the output format of the commands. In JSON mode the standard output is reserved for the
+/// command's result.
+pub fn set_output_format(format: OutputFormat) {
+    JSON_OUTPUT.store(format == OutputFormat::Json, Ordering::Relaxed);
+}
+
+pub(crate) fn is_json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// An error carrying structured details for the JSON output, such as the issues found by `validate`
+#[derive(Debug)]
+pub struct DetailedError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub details: serde_json::Value,
+}
+
+impl Display for DetailedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for DetailedError {}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+enum CommandOutput<'a, T: Serialize> {
+    Success {
+        command: &'a str,
+        result: &'a T,
+    },
+    Error {
+        command: &'a str,
+        error: ErrorOutput,
+    },
+}
+
+#[derive(Serialize)]
+struct ErrorOutput {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+}
+
+/// Renders the outcome of a command as a JSON document
+pub fn json_output<T: Serialize>(command: &str, result: &anyhow::Result<T>) -> String {
+    let output = match result {
+        Ok(result) => CommandOutput::Success { command, result },
+        Err(err) => CommandOutput::Error {
+            command,
+            error: ErrorOutput {
+                code: ErrorKind::of(err).code(),
+                message: format!("{err:#}"),
+                details: err
+                    .downcast_ref::<DetailedError>()
+                    .map(|err| err.details.clone()),
+            },
+        },
+    };
+    serde_json::to_string_pretty(&output).expect("Failed to serialize the command output")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api;
+    use crate::output::json_output;
+    use anyhow::{anyhow, Context};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn results_and_errors_are_rendered_as_json() {
+        let success: anyhow::Result<Vec<&str>> = Ok(vec!["a.wit"]);
+        assert_eq!(
+            serde_json::from_str::<Value>(&json_output("generate", &success)).unwrap(),
+            json!({"status": "success", "command": "generate", "result": ["a.wit"]})
+        );
+
+        let failure: anyhow::Result<()> =
+            Err(anyhow!(api::Error::InvalidWit("no package".to_string())))
+                .context("Failed to generate");
+        assert_eq!(
+            serde_json::from_str::<Value>(&json_output("generate", &failure)).unwrap(),
+            json!({
+                "status": "error",
+                "command": "generate",
+                "error": {
+                    "code": "invalid-wit",
+                    "message": "Failed to generate: Invalid source WIT: no package"
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Paths of at least this length only work on Windows in their verbatim `\\?\` form
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Canonicalizes the path like [std::fs::canonicalize], but without the verbatim `\\?\` prefix it adds on Windows
+/// where the path works without it. Verbatim paths are not understood by many tools, and are never equal to or
+/// relative to the same paths written the usual way.
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    let canonical = path.canonicalize()?;
+    Ok(match canonical.to_str().and_then(strip_verbatim_prefix) {
+        Some(simplified) => PathBuf::from(simplified),
+        None => canonical,
+    })
+}
+
+/// Strips the `\\?\` prefix of verbatim drive paths, and turns verbatim UNC paths (`\\?\UNC\server\share\...`) into
+/// `\\server\share\...`, unless the path is too long for the shorter form
+fn strip_verbatim_prefix(path: &str) -> Option<String> {
+    if path.len() >= WINDOWS_MAX_PATH {
+        return None;
+    }
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        return Some(format!(r"\\{unc}"));
+    }
+    let disk = path.strip_prefix(r"\\?\")?;
+    let mut chars = disk.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(drive), Some(':'), Some('\\') | None) if drive.is_ascii_alphabetic() => {
+            Some(disk.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Formats a relative path to be written into a manifest, like a `Cargo.toml` or a `deps.toml`, with `/` separators
+/// on all platforms, so the manifests generated on Windows and elsewhere are the same. Absolute paths are kept as they
+/// are.
+pub fn manifest_path(path: &Path) -> String {
+    if path.has_root() || path.components().any(|c| matches!(c, Component::Prefix(_))) {
+        return path.to_string_lossy().to_string();
+    }
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paths::{canonicalize, manifest_path, strip_verbatim_prefix};
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    fn verbatim_prefixes_are_stripped() {
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\C:\Users\golem\wit"),
+            Some(r"C:\Users\golem\wit".to_string())
+        );
+        assert_eq!(strip_verbatim_prefix(r"\\?\D:"), Some("D:".to_string()));
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\wit"),
+            Some(r"\\server\share\wit".to_string())
+        );
+        assert_eq!(strip_verbatim_prefix(r"\\?\Volume{1234}\wit"), None);
+        assert_eq!(strip_verbatim_prefix(r"C:\Users\golem\wit"), None);
+        assert_eq!(strip_verbatim_prefix("/home/golem/wit"), None);
+
+        let long = format!(r"\\?\C:\{}", "a".repeat(300));
+        assert_eq!(strip_verbatim_prefix(&long), None);
+    }
+
+    #[test]
+    fn manifest_paths_use_forward_slashes() {
+        let path = PathBuf::from("..").join("counter").join("wit").join("deps");
+        assert_eq!(manifest_path(&path), "../counter/wit/deps");
+        assert_eq!(manifest_path(&Path::new(".").join("wit")), "wit");
+        assert_eq!(manifest_path(Path::new("")), "");
+    }
+
+    #[test]
+    fn canonical_paths_are_usable_without_prefixes() {
+        let dir = TempDir::new("stubgen-test").unwrap();
+        let canonical = canonicalize(dir.path()).unwrap();
+
+        assert!(canonical.is_absolute());
+        assert!(!canonical.to_string_lossy().starts_with(r"\\?\"));
+        assert_eq!(canonicalize(&canonical.join(".")).unwrap(), canonical);
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::output::is_json_output;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::cell::{Cell, RefCell};
+use std::fmt::{Display, Formatter};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::info;
+
+/// A step performed while generating, building or composing stubs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A file of the stub crate is being generated
+    Generating { description: String, path: PathBuf },
+    /// A generated manifest is being merged into an existing one
+    Merging { path: PathBuf },
+    /// A WIT package is being copied into the stub's WIT root
+    CopyingPackage { name: String },
+    /// A file of the package being copied
+    CopyingFile { source: PathBuf, dest: PathBuf },
+    /// The generated stub crate is being compiled
+    Compiling { crate_root: PathBuf },
+    /// An interface of the caller component is being composed with the stub implementing it
+    Composing {
+        interface: String,
+        stub_wasm: PathBuf,
+    },
+    /// A result is being written to its destination
+    Writing { description: String, path: PathBuf },
+}
+
+impl Display for ProgressEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgressEvent::Generating { description, path } => {
+                write!(f, "Generating {description} to {}", path.to_string_lossy())
+            }
+            ProgressEvent::Merging { path } => {
+                write!(
+                    f,
+                    "Merging generated Cargo.toml into {}",
+                    path.to_string_lossy()
+                )
+            }
+            ProgressEvent::CopyingPackage { name } => write!(f, "Copying package {name}"),
+            ProgressEvent::CopyingFile { source, dest } => write!(
+                f,
+                "  .. {} to {}",
+                source.to_string_lossy(),
+                dest.to_string_lossy()
+            ),
+            ProgressEvent::Compiling { crate_root } => {
+                write!(
+                    f,
+                    "Compiling the stub crate in {}",
+                    crate_root.to_string_lossy()
+                )
+            }
+            ProgressEvent::Composing {
+                interface,
+                stub_wasm,
+            } => write!(
+                f,
+                "Composing {interface} from {}",
+                stub_wasm.to_string_lossy()
+            ),
+            ProgressEvent::Writing { description, path } => {
+                write!(f, "Writing {description} to {path:?}")
+            }
+        }
+    }
+}
+
+/// Receives the steps of long running operations
+pub trait Progress {
+    fn on_event(&self, event: &ProgressEvent);
+}
+
+impl<F: Fn(&ProgressEvent)> Progress for F {
+    fn on_event(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Ignores all progress events
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn on_event(&self, _event: &ProgressEvent) {}
+}
+
+/// Logs the progress events, used by the CLI
+pub(crate) struct PrintProgress;
+
+impl Progress for PrintProgress {
+    fn on_event(&self, event: &ProgressEvent) {
+        info!("{event}");
+    }
+}
+
+static PROGRESS_BARS: AtomicBool = AtomicBool::new(false);
+
+/// Allows the CLI to draw a status line, which only happens when the standard error is a terminal
+pub(crate) fn enable_progress_bars(enabled: bool) {
+    PROGRESS_BARS.store(enabled, Ordering::Relaxed);
+}
+
+/// The progress reporting of the CLI commands: a status line on terminals, log messages otherwise
+pub(crate) fn cli_progress() -> Box<dyn Progress> {
+    if draws_status_line(
+        PROGRESS_BARS.load(Ordering::Relaxed),
+        is_json_output(),
+        std::io::stderr().is_terminal(),
+    ) {
+        Box::new(TerminalProgress::default())
+    } else {
+        Box::new(PrintProgress)
+    }
+}
+
+/// The status line is only drawn on terminals, and never along the JSON output, which is read by tools capturing
+/// the standard error too
+fn draws_status_line(enabled: bool, json_output: bool, terminal: bool) -> bool {
+    enabled && !json_output && terminal
+}
+
+/// Logs the steps above a spinner showing the elapsed time and the number of copied WIT files
+#[derive(Default)]
+struct TerminalProgress {
+    bar: RefCell<Option<ProgressBar>>,
+    copied_files: Cell<usize>,
+}
+
+impl TerminalProgress {
+    fn bar(&self) -> ProgressBar {
+        self.bar
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let bar = ProgressBar::new_spinner();
+                if let Ok(style) = ProgressStyle::with_template("{spinner} [{elapsed}] {msg}") {
+                    bar.set_style(style);
+                }
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar
+            })
+            .clone()
+    }
+
+    fn clear(&self) {
+        if let Some(bar) = self.bar.borrow_mut().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn on_event(&self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::CopyingFile { .. } => {
+                let copied = self.copied_files.get() + 1;
+                self.copied_files.set(copied);
+                self.bar().set_message(format!("Copied {copied} WIT files"));
+            }
+            ProgressEvent::Compiling { .. } => {
+                // Cargo draws its own progress bar while compiling
+                self.clear();
+                info!("{event}");
+            }
+            _ => {
+                let bar = self.bar();
+                bar.suspend(|| info!("{event}"));
+                bar.set_message(event.to_string());
+            }
+        }
+    }
+}
+
+impl Drop for TerminalProgress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::{draws_status_line, Progress, ProgressEvent, TerminalProgress};
+    use std::path::PathBuf;
+
+    #[test]
+    fn status_line_is_drawn_only_on_terminals_without_json_output() {
+        assert!(draws_status_line(true, false, true));
+        assert!(!draws_status_line(true, false, false));
+        assert!(!draws_status_line(true, true, true));
+        assert!(!draws_status_line(false, false, true));
+    }
+
+    #[test]
+    fn status_line_counts_the_copied_files_until_compiling() {
+        let progress = TerminalProgress::default();
+        let copying = ProgressEvent::CopyingFile {
+            source: PathBuf::from("wit/api.wit"),
+            dest: PathBuf::from("stub/wit/api.wit"),
+        };
+        progress.on_event(&copying);
+        progress.on_event(&copying);
+        assert_eq!(progress.bar().message(), "Copied 2 WIT files");
+
+        progress.on_event(&ProgressEvent::Compiling {
+            crate_root: PathBuf::from("stub"),
+        });
+        assert!(progress.bar.borrow().is_none());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::wit;
+use anyhow::{anyhow, bail, Context};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempdir::TempDir;
+use tracing::info;
+
+/// Downloads a WIT package from a registry into `dest_wit_root`, together with its dependencies
+pub fn fetch(package: &str, dest_wit_root: &Path, registry: &Option<String>) -> anyhow::Result<()> {
+    ensure_wkg()?;
+
+    let output = fetched_wit_path(package, dest_wit_root)?;
+    fs::create_dir_all(dest_wit_root).context("Failed to create the destination WIT root")?;
+
+    info!("Fetching {package} to {:?}", output);
+    run_wkg(get_args(package, &output, registry))?;
+
+    info!("Fetching the dependencies of {package}");
+    run_wkg(fetch_dependencies_args(dest_wit_root, registry))
+}
+
+/// The file a fetched package is written to, named after the package like the directories of the dependencies
+fn fetched_wit_path(package: &str, dest_wit_root: &Path) -> anyhow::Result<PathBuf> {
+    let (name, _) = package.split_once('@').unwrap_or((package, ""));
+    let (namespace, name) = name.split_once(':').ok_or(anyhow!(
+        "Invalid package name {package}, expected <namespace>:<name>[@<version>]"
+    ))?;
+    Ok(dest_wit_root.join(format!("{namespace}_{name}.wit")))
+}
+
+fn get_args(package: &str, output: &Path, registry: &Option<String>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "get".into(),
+        package.into(),
+        "--format".into(),
+        "wit".into(),
+        "--output".into(),
+        output.as_os_str().to_os_string(),
+    ];
+    add_registry(&mut args, registry);
+    args
+}
+
+fn fetch_dependencies_args(dest_wit_root: &Path, registry: &Option<String>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "wit".into(),
+        "fetch".into(),
+        "--wit-dir".into(),
+        dest_wit_root.as_os_str().to_os_string(),
+    ];
+    add_registry(&mut args, registry);
+    args
+}
+
+/// Publishes a generated stub's WIT package, and optionally the compiled stub WASM, to a registry
+pub fn publish(
+    stub_wit_root: &Path,
+    stub_wasm: &Option<PathBuf>,
+    stub_wasm_package: &Option<String>,
+    version: &str,
+    registry: &Option<String>,
+) -> anyhow::Result<Vec<String>> {
+    ensure_wkg()?;
+
+    let stub_package = wit::get_package_name(&stub_wit_root.join("_stub.wit"))?;
+    let stub_package = format!("{}:{}", stub_package.namespace, stub_package.name);
+
+    let temp_dir = TempDir::new("wasm-rpc-stubgen")?;
+    let wit_wasm = temp_dir.path().join("stub-wit.wasm");
+
+    info!("Encoding the WIT package {stub_package}");
+    run_wkg(vec![
+        "wit".into(),
+        "build".into(),
+        "--wit-dir".into(),
+        stub_wit_root.as_os_str().to_os_string(),
+        "--output".into(),
+        wit_wasm.as_os_str().to_os_string(),
+    ])?;
+
+    let mut published = Vec::new();
+    for (file, package) in published_packages(
+        &wit_wasm,
+        &stub_package,
+        stub_wasm,
+        stub_wasm_package,
+        version,
+    ) {
+        info!("Publishing {package}");
+        run_wkg(publish_args(file, &package, registry))?;
+        published.push(package);
+    }
+
+    Ok(published)
+}
+
+/// The files to publish with their versioned package names: the encoded WIT package, and the stub WASM, which is
+/// published as `<stub package>-wasm` unless named otherwise
+fn published_packages<'a>(
+    wit_wasm: &'a Path,
+    stub_package: &str,
+    stub_wasm: &'a Option<PathBuf>,
+    stub_wasm_package: &Option<String>,
+    version: &str,
+) -> Vec<(&'a Path, String)> {
+    let mut packages = vec![(wit_wasm, format!("{stub_package}@{version}"))];
+    if let Some(stub_wasm) = stub_wasm {
+        let package = stub_wasm_package
+            .clone()
+            .unwrap_or(format!("{stub_package}-wasm"));
+        packages.push((stub_wasm.as_path(), format!("{package}@{version}")));
+    }
+    packages
+}
+
+fn publish_args(file: &Path, package: &str, registry: &Option<String>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "publish".into(),
+        file.as_os_str().to_os_string(),
+        "--package".into(),
+        package.into(),
+    ];
+    add_registry(&mut args, registry);
+    args
+}
+
+fn add_registry(args: &mut Vec<OsString>, registry: &Option<String>) {
+    if let Some(registry) = registry {
+        args.push("--registry".into());
+        args.push(registry.into());
+    }
+}
+
+// Registry access is delegated to the wkg CLI (https://github.com/bytecodealliance/wasm-pkg-tools),
+// which supports Warg registries such as wa.dev
+fn ensure_wkg() -> anyhow::Result<()> {
+    if Command::new("wkg").args(["--version"]).output().is_ok() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "wkg is not installed. Please install it with `cargo install wkg`"
+        ))
+    }
+}
+
+fn run_wkg(args: Vec<OsString>) -> anyhow::Result<()> {
+    let status = Command::new("wkg")
+        .args(&args)
+        .status()
+        .context("Failed to run wkg")?;
+    if !status.success() {
+        bail!(
+            "wkg {} failed with {status}",
+            args.iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{
+        fetch_dependencies_args, fetched_wit_path, get_args, publish_args, published_packages,
+    };
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn fetched_packages_are_written_next_to_the_dependencies() {
+        let dest_wit_root = Path::new("caller/wit");
+        let output = fetched_wit_path("rpc:counter@0.1.0", dest_wit_root).unwrap();
+
+        assert_eq!(output, Path::new("caller/wit/rpc_counter.wit"));
+        assert_eq!(
+            strings(get_args(
+                "rpc:counter@0.1.0",
+                &output,
+                &Some("wa.dev".to_string())
+            )),
+            vec![
+                "get",
+                "rpc:counter@0.1.0",
+                "--format",
+                "wit",
+                "--output",
+                "caller/wit/rpc_counter.wit",
+                "--registry",
+                "wa.dev"
+            ]
+        );
+        assert_eq!(
+            strings(fetch_dependencies_args(dest_wit_root, &None)),
+            vec!["wit", "fetch", "--wit-dir", "caller/wit"]
+        );
+    }
+
+    #[test]
+    fn packages_without_namespace_cannot_be_fetched() {
+        let err = fetched_wit_path("counter@0.1.0", Path::new("wit")).unwrap_err();
+
+        assert!(
+            err.to_string().contains("expected <namespace>:<name>"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn stub_wasm_is_published_next_to_the_wit_package() {
+        let wit_wasm = Path::new("stub-wit.wasm");
+        let stub_wasm = Some(PathBuf::from("target/stub.wasm"));
+
+        assert_eq!(
+            published_packages(wit_wasm, "rpc:counter-stub", &None, &None, "0.1.0"),
+            vec![(wit_wasm, "rpc:counter-stub@0.1.0".to_string())]
+        );
+        assert_eq!(
+            published_packages(wit_wasm, "rpc:counter-stub", &stub_wasm, &None, "0.1.0"),
+            vec![
+                (wit_wasm, "rpc:counter-stub@0.1.0".to_string()),
+                (
+                    Path::new("target/stub.wasm"),
+                    "rpc:counter-stub-wasm@0.1.0".to_string()
+                )
+            ]
+        );
+        assert_eq!(
+            published_packages(
+                wit_wasm,
+                "rpc:counter-stub",
+                &stub_wasm,
+                &Some("rpc:counter-client".to_string()),
+                "0.1.0"
+            )[1]
+            .1,
+            "rpc:counter-client@0.1.0"
+        );
+        assert_eq!(
+            strings(publish_args(
+                wit_wasm,
+                "rpc:counter-stub@0.1.0",
+                &Some("wa.dev".to_string())
+            )),
+            vec![
+                "publish",
+                "stub-wit.wasm",
+                "--package",
+                "rpc:counter-stub@0.1.0",
+                "--registry",
+                "wa.dev"
+            ]
+        );
+    }
+}
@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::stub::{FunctionResultStub, FunctionStub, StubDefinition};
+use crate::compat::Downgrade;
+use crate::progress::{Progress, ProgressEvent};
+use crate::stub::{
+    Flatten, FunctionConvention, FunctionResultStub, FunctionStub, InterfaceStub, RpcErrors,
+    StubDefinition, METADATA_SETTER, TARGET_VERSION_SETTER,
+};
 use anyhow::anyhow;
 use heck::{ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
 use proc_macro2::{Ident, Span, TokenStream};
@@ -20,11 +25,10 @@ use quote::quote;
 use std::fs;
 use wit_bindgen_rust::to_rust_ident;
 use wit_parser::{
-    Enum, Flags, Handle, Record, Resolve, Result_, Tuple, Type, TypeDefKind, TypeId, TypeOwner,
-    Variant,
+    Enum, Flags, Handle, Record, Result_, Tuple, Type, TypeDefKind, TypeId, TypeOwner, Variant,
 };
 
-pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
+pub fn generate_stub_source(def: &StubDefinition, progress: &dyn Progress) -> anyhow::Result<()> {
     let root_ns = Ident::new(
         &def.root_package_name.namespace.to_snake_case(),
         Span::call_site(),
@@ -34,26 +38,38 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
         Span::call_site(),
     );
 
-    let mut struct_defs = Vec::new();
+    let mut modules: Vec<(String, Vec<TokenStream>)> = Vec::new();
     for interface in &def.interfaces {
         let interface_ident = to_rust_ident(&interface.name).to_upper_camel_case();
         let interface_name = Ident::new(&interface_ident, Span::call_site());
 
-        let additional_fields = if interface.is_resource() {
+        let mut additional_fields = if interface.is_resource() {
             vec![quote! {
                 id: u64,
-                uri: golem_wasm_rpc::Uri
+                uri: golem_wasm_rpc_guest::Uri
             }]
         } else {
             vec![]
         };
+        if def.metadata {
+            additional_fields.push(quote! {
+                metadata: std::cell::RefCell<Vec<(String, String)>>
+            });
+        }
+        if def.older_target.is_some() {
+            additional_fields.push(quote! {
+                older_target: std::cell::Cell<bool>
+            });
+        }
+        let field_inits = field_inits(def);
         let struct_fns: Vec<TokenStream> = if interface.is_resource() {
             vec![quote! {
-                pub fn from_remote_handle(uri: golem_wasm_rpc::Uri, id: u64) -> Self {
+                pub fn from_remote_handle(uri: golem_wasm_rpc_guest::Uri, id: u64) -> Self {
                     Self {
                         rpc: WasmRpc::new(&uri),
                         id,
                         uri,
+                        #field_inits
                     }
                 }
             }]
@@ -61,7 +77,7 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
             vec![]
         };
 
-        struct_defs.push(quote! {
+        module_items(&mut modules, interface).push(quote! {
            pub struct #interface_name {
                 rpc: WasmRpc,
                 #(#additional_fields),*
@@ -73,12 +89,13 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
         });
     }
 
-    let mut interface_impls = Vec::new();
     for interface in &def.interfaces {
+        let feature = interface.feature_name();
         let interface_ident = to_rust_ident(&interface.name).to_upper_camel_case();
         let interface_name = Ident::new(&interface_ident, Span::call_site());
         let guest_interface_name =
             Ident::new(&format!("Guest{}", interface_ident), Span::call_site());
+        let field_inits = field_inits(def);
 
         let mut fn_impls = Vec::new();
         for function in &interface.functions {
@@ -98,6 +115,9 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
                 } else {
                     FunctionMode::Global
                 },
+                &feature,
+                def.convention(interface, function),
+                def.downgrade(interface, function, false),
             )?);
         }
 
@@ -114,6 +134,9 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
                     }
                 },
                 FunctionMode::Static,
+                &feature,
+                def.convention(interface, function),
+                def.downgrade(interface, function, true),
             )?);
         }
 
@@ -126,6 +149,7 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
         let constructor = if interface.is_resource() {
             let constructor_stub = FunctionStub {
                 name: "new".to_string(),
+                docs: interface.constructor_docs.clone(),
                 params: interface.constructor_params.clone().unwrap_or_default(),
                 results: FunctionResultStub::SelfType,
             };
@@ -138,22 +162,65 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
                     &interface.name
                 )),
                 FunctionMode::Constructor,
+                &feature,
+                FunctionConvention::default(),
+                None,
             )?
         } else {
             quote! {
                 fn new(location: crate::bindings::golem::rpc::types::Uri) -> Self {
-                    let location = golem_wasm_rpc::Uri { value: location.value };
+                    let location = golem_wasm_rpc_guest::Uri { value: location.value };
                     Self {
-                        rpc: WasmRpc::new(&location)
+                        rpc: WasmRpc::new(&location),
+                        #field_inits
                     }
                 }
             }
         };
 
-        interface_impls.push(quote! {
+        let worker_constructor = if interface.has_worker_constructor() {
+            quote! {
+                fn for_worker(component_id: String, worker_name: String) -> wit_bindgen::rt::Resource<#interface_name> {
+                    let location = golem_wasm_rpc_guest::Uri::worker(component_id, worker_name);
+                    wit_bindgen::rt::Resource::new(Self {
+                        rpc: WasmRpc::new(&location),
+                        #field_inits
+                    })
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let metadata_setter = if def.metadata && !interface.defines_function(METADATA_SETTER) {
+            quote! {
+                fn set_metadata(&self, metadata: Vec<(String, String)>) {
+                    *self.metadata.borrow_mut() = metadata;
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let target_version_setter = match def.older_target_version() {
+            Some(version) if !interface.defines_function(TARGET_VERSION_SETTER) => quote! {
+                fn set_target_version(&self, version: String) {
+                    self.older_target.set(version == #version);
+                }
+            },
+            _ => quote! {},
+        };
+
+        module_items(&mut modules, interface).push(quote! {
             impl crate::bindings::exports::#root_ns::#root_name::#stub_interface_name::#guest_interface_name for #interface_name {
                 #constructor
 
+                #worker_constructor
+
+                #metadata_setter
+
+                #target_version_setter
+
                 #(#fn_impls)*
             }
         });
@@ -168,14 +235,18 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
                     &interface.name
                 )),
             );
-            interface_impls.push(quote! {
+            let invoke = invoke_method(def);
+            let metadata = metadata_arg(def, quote! { self });
+            module_items(&mut modules, interface).push(quote! {
                 impl Drop for #interface_name {
                     fn drop(&mut self) {
-                        self.rpc.invoke_and_await(
+                        #[cfg(feature = #feature)]
+                        self.rpc.#invoke(
                             #remote_function_name,
                             &[
                                 WitValue::builder().handle(self.uri.clone(), self.id)
-                            ]
+                            ],
+                            #metadata
                         ).expect("Failed to invoke remote drop");
                     }
                 }
@@ -183,31 +254,117 @@ pub fn generate_stub_source(def: &StubDefinition) -> anyhow::Result<()> {
         }
     }
 
+    let conversions = generate_conversions(def)?;
+
+    let module_names = modules
+        .iter()
+        .map(|(name, _)| Ident::new(name, Span::call_site()))
+        .collect::<Vec<_>>();
+    let module_defs = modules.iter().map(|(name, items)| {
+        let name = Ident::new(name, Span::call_site());
+        quote! {
+            pub mod #name {
+                use super::*;
+
+                #(#items)*
+            }
+        }
+    });
+
     let lib = quote! {
         #![allow(warnings)]
 
-        use golem_wasm_rpc::*;
+        use golem_wasm_rpc_guest::*;
 
         #[allow(dead_code)]
         mod bindings;
 
-        #(#struct_defs)*
+        #(#module_defs)*
+
+        #(#conversions)*
 
-        #(#interface_impls)*
+        /// The stubs of all the interfaces
+        pub mod prelude {
+            #(pub use super::#module_names::*;)*
+        }
+
+        pub use prelude::*;
     };
 
     let syntax_tree = syn::parse2(lib)?;
     let src = prettyplease::unparse(&syntax_tree);
 
-    println!(
-        "Generating stub source to {}",
-        def.target_rust_path().to_string_lossy()
-    );
+    progress.on_event(&ProgressEvent::Generating {
+        description: "stub source".to_string(),
+        path: def.target_rust_path(),
+    });
     fs::create_dir_all(def.target_rust_path().parent().unwrap())?;
     fs::write(def.target_rust_path(), src)?;
     Ok(())
 }
 
+/// Initializes the optional fields of the stubs, like `metadata` when generated with metadata
+fn field_inits(def: &StubDefinition) -> TokenStream {
+    let mut inits = Vec::new();
+    if def.metadata {
+        inits.push(quote! { metadata: std::cell::RefCell::new(Vec::new()) });
+    }
+    if def.older_target.is_some() {
+        inits.push(quote! { older_target: std::cell::Cell::new(false) });
+    }
+    quote! { #(#inits,)* }
+}
+
+/// The method of `WasmRpc` invoking the remote functions
+fn invoke_method(def: &StubDefinition) -> Ident {
+    let name = if def.metadata {
+        "invoke_and_await_with_metadata"
+    } else {
+        "invoke_and_await"
+    };
+    Ident::new(name, Span::call_site())
+}
+
+/// The metadata argument of the invocations through the given stub, when generated with metadata
+fn metadata_arg(def: &StubDefinition, stub: TokenStream) -> TokenStream {
+    if def.metadata {
+        quote! { &#stub.metadata.borrow() }
+    } else {
+        quote! {}
+    }
+}
+
+/// The items of the module of an interface's stub, created on the first use. Resources belong to the module of the
+/// interface defining them.
+fn module_items<'a>(
+    modules: &'a mut Vec<(String, Vec<TokenStream>)>,
+    interface: &InterfaceStub,
+) -> &'a mut Vec<TokenStream> {
+    let name = interface_module_name(
+        interface
+            .owner_interface
+            .as_ref()
+            .unwrap_or(&interface.name),
+    );
+    let idx = match modules.iter().position(|(existing, _)| *existing == name) {
+        Some(idx) => idx,
+        None => {
+            modules.push((name, Vec::new()));
+            modules.len() - 1
+        }
+    };
+    &mut modules[idx].1
+}
+
+/// The name of the Rust module of an interface's stub, not clashing with the other modules of the stub crate
+fn interface_module_name(interface_name: &str) -> String {
+    let name = to_rust_ident(&interface_name.to_snake_case());
+    match name.as_str() {
+        "bindings" | "prelude" => format!("{name}_interface"),
+        _ => name,
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum FunctionMode {
     Global,
@@ -221,10 +378,14 @@ fn generate_function_stub_source(
     function: &FunctionStub,
     interface_name: Option<String>,
     mode: FunctionMode,
+    feature: &str,
+    convention: FunctionConvention,
+    downgrade: Option<Downgrade>,
 ) -> anyhow::Result<TokenStream> {
     let function_name = Ident::new(&to_rust_ident(&function.name), Span::call_site());
     let mut params = Vec::new();
     let mut input_values = Vec::new();
+    let mut param_values = Vec::new();
     let mut output_values = Vec::new();
 
     if mode != FunctionMode::Static && mode != FunctionMode::Constructor {
@@ -241,34 +402,46 @@ fn generate_function_stub_source(
         });
     }
 
+    let mut param_names = Vec::new();
     for param in &function.params {
         let param_name = Ident::new(&to_rust_ident(&param.name), Span::call_site());
-        let param_typ = type_to_rust_ident(&param.typ, &def.resolve)?;
+        let param_typ = type_to_rust_ident(&param.typ, def)?;
         params.push(quote! {
             #param_name: #param_typ
         });
+        param_names.push(param_name.clone());
         let param_name_access = quote! { #param_name };
 
-        input_values.push(wit_value_builder(
+        param_values.push(wit_value_builder(
             &param.typ,
             &param_name_access,
-            &def.resolve,
+            def,
             quote! { WitValue::builder() },
         )?);
     }
+    let handle_values = input_values.clone();
+    input_values.extend(param_values.iter().cloned());
 
-    let result_type = match &function.results {
-        FunctionResultStub::Single(typ) => {
-            let typ = type_to_rust_ident(typ, &def.resolve)?;
+    let remote_function_name =
+        get_remote_function_name(def, &function.name, interface_name.as_ref());
+    let flattened = convention.flatten(&def.resolve, &function.results)?;
+
+    let result_type = match (&function.results, &flattened) {
+        (_, Some(flattened)) => match &flattened.typ {
+            Some(typ) => type_to_rust_ident(typ, def)?,
+            None => quote! { () },
+        },
+        (FunctionResultStub::Single(typ), None) => {
+            let typ = type_to_rust_ident(typ, def)?;
             quote! {
                 #typ
             }
         }
-        FunctionResultStub::Multi(params) => {
+        (FunctionResultStub::Multi(params), None) => {
             let mut results = Vec::new();
             for param in params {
                 let param_name = Ident::new(&to_rust_ident(&param.name), Span::call_site());
-                let param_typ = type_to_rust_ident(&param.typ, &def.resolve)?;
+                let param_typ = type_to_rust_ident(&param.typ, def)?;
                 results.push(quote! {
                     #param_name: #param_typ
                 });
@@ -283,34 +456,56 @@ fn generate_function_stub_source(
                 }
             }
         }
-        FunctionResultStub::SelfType => quote! { Self },
+        (FunctionResultStub::SelfType, None) => quote! { Self },
+    };
+    let result_type = match convention.rpc_errors {
+        RpcErrors::Panic => result_type,
+        RpcErrors::Result => {
+            quote! { Result<#result_type, crate::bindings::golem::rpc::types::RpcError> }
+        }
     };
 
     match &function.results {
         FunctionResultStub::Single(typ) => {
-            output_values.push(extract_from_wit_value(
+            let mut output_value = extract_from_wit_value(
                 typ,
-                &def.resolve,
+                def,
                 quote! { result.tuple_element(0).expect("tuple not found") },
-            )?);
+            )?;
+            for step in flattened.iter().flat_map(|flattened| &flattened.steps) {
+                output_value = match step {
+                    Flatten::Result => quote! {
+                        match #output_value {
+                            Ok(value) => value,
+                            Err(err) => panic!("Remote {} returned an error: {:?}", #remote_function_name, err),
+                        }
+                    },
+                    Flatten::Option => quote! {
+                        #output_value.unwrap_or_else(|| panic!("Remote {} returned none", #remote_function_name))
+                    },
+                };
+            }
+            output_values.push(output_value);
         }
         FunctionResultStub::Multi(params) => {
             for (n, param) in params.iter().enumerate() {
                 output_values.push(extract_from_wit_value(
                     &param.typ,
-                    &def.resolve,
+                    def,
                     quote! { result.tuple_element(#n).expect("tuple not found") },
                 )?);
             }
         }
         FunctionResultStub::SelfType if mode == FunctionMode::Constructor => {
+            let field_inits = field_inits(def);
             output_values.push(quote! {
                 {
                     let (uri, id) = result.tuple_element(0).expect("tuple not found").handle().expect("handle not found");
                     Self {
                         rpc,
                         id,
-                        uri
+                        uri,
+                        #field_inits
                     }
                 }
             });
@@ -322,10 +517,8 @@ fn generate_function_stub_source(
         }
     }
 
-    let remote_function_name =
-        get_remote_function_name(def, &function.name, interface_name.as_ref());
-
-    let rpc = match mode {
+    // The stub the function is called through, if any
+    let stub = match mode {
         FunctionMode::Static => {
             let first_param = function
                 .params
@@ -333,35 +526,114 @@ fn generate_function_stub_source(
                 .ok_or(anyhow!("static function has no params"))?;
             let first_param_ident =
                 Ident::new(&to_rust_ident(&first_param.name), Span::call_site());
-            quote! { #first_param_ident.rpc }
-        }
-        FunctionMode::Constructor => {
-            quote! { rpc }
+            Some(quote! { #first_param_ident })
         }
-        _ => {
-            quote! { self.rpc }
+        FunctionMode::Constructor => None,
+        _ => Some(quote! { self }),
+    };
+    let (rpc, metadata) = match &stub {
+        Some(stub) => (quote! { #stub.rpc }, metadata_arg(def, stub.clone())),
+        None if def.metadata => (quote! { rpc }, quote! { &[] }),
+        None => (quote! { rpc }, quote! {}),
+    };
+    let invoke = invoke_method(def);
+    let invocation = |values: &[TokenStream]| {
+        quote! {
+            #rpc.#invoke(
+                #remote_function_name,
+                &[
+                    #(#values),*
+                ],
+                #metadata
+            )
         }
     };
+    let mut call = invocation(&input_values);
+    if let (Some(stub), Some(downgrade)) = (&stub, downgrade) {
+        let older_call = match downgrade {
+            Downgrade::Unchanged => None,
+            Downgrade::Params(params) => {
+                let mut older_values = handle_values;
+                older_values.extend(params.iter().map(|param| match param {
+                    Some(idx) => param_values[*idx].clone(),
+                    None => quote! { WitValue::builder().option_none() },
+                }));
+                Some(invocation(&older_values))
+            }
+            Downgrade::Unsupported(reason) => {
+                let message = format!(
+                    "Calling {remote_function_name} is not supported by version {} of the target: {reason}",
+                    def.older_target_version().unwrap_or_default()
+                );
+                Some(quote! { panic!(#message) })
+            }
+        };
+        if let Some(older_call) = older_call {
+            call = quote! {
+                if #stub.older_target.get() {
+                    #older_call
+                } else {
+                    #call
+                }
+            };
+        }
+    }
 
     let init = if mode == FunctionMode::Constructor {
         quote! {
-            let location = golem_wasm_rpc::Uri { value: location.value };
+            let location = golem_wasm_rpc_guest::Uri { value: location.value };
             let rpc = WasmRpc::new(&location);
         }
     } else {
         quote! {}
     };
 
+    let output = match output_values.as_slice() {
+        [output_value] => output_value.clone(),
+        _ => quote! { (#(#output_values),*) },
+    };
+    let body = match convention.rpc_errors {
+        RpcErrors::Panic => quote! {
+            let result = #call.expect(&format!("Failed to invoke remote {}", #remote_function_name));
+            (#(#output_values),*)
+        },
+        RpcErrors::Result => quote! {
+            let result = #call;
+            let result = match result {
+                Ok(result) => result,
+                Err(err) => return Err(match err {
+                    golem_wasm_rpc_guest::RpcError::ProtocolError(message) => crate::bindings::golem::rpc::types::RpcError::ProtocolError(message),
+                    golem_wasm_rpc_guest::RpcError::Denied(message) => crate::bindings::golem::rpc::types::RpcError::Denied(message),
+                    golem_wasm_rpc_guest::RpcError::NotFound(message) => crate::bindings::golem::rpc::types::RpcError::NotFound(message),
+                    golem_wasm_rpc_guest::RpcError::RemoteInternalError(message) => crate::bindings::golem::rpc::types::RpcError::RemoteInternalError(message),
+                }),
+            };
+            Ok(#output)
+        },
+    };
+
+    let disabled_message = format!(
+        "Calling {remote_function_name} requires the stub to be compiled with the `{feature}` feature"
+    );
+    // Without the feature the parameters are only used to avoid unused variable warnings
+    let unused_params = if param_names.is_empty() {
+        quote! {}
+    } else {
+        quote! { let _ = (#(#param_names,)*); }
+    };
+
     Ok(quote! {
         fn #function_name(#(#params),*) -> #result_type {
-            #init
-            let result = #rpc.invoke_and_await(
-                #remote_function_name,
-                &[
-                    #(#input_values),*
-                ],
-            ).expect(&format!("Failed to invoke remote {}", #remote_function_name));
-            (#(#output_values),*)
+            #[cfg(feature = #feature)]
+            {
+                #init
+                #body
+            }
+            #[cfg(not(feature = #feature))]
+            {
+                #unused_params
+                panic!(#disabled_message)
+            }
         }
     })
 }
@@ -386,7 +658,7 @@ fn get_remote_function_name(
     }
 }
 
-fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStream> {
+fn type_to_rust_ident(typ: &Type, def: &StubDefinition) -> anyhow::Result<TokenStream> {
     match typ {
         Type::Bool => Ok(quote! { bool }),
         Type::U8 => Ok(quote! { u8 }),
@@ -402,35 +674,36 @@ fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStre
         Type::Char => Ok(quote! { char }),
         Type::String => Ok(quote! { String }),
         Type::Id(type_id) => {
-            let typedef = resolve
+            let typedef = def
+                .resolve
                 .types
                 .get(*type_id)
                 .ok_or(anyhow!("type not found"))?;
 
             match &typedef.kind {
                 TypeDefKind::Option(inner) => {
-                    let inner = type_to_rust_ident(inner, resolve)?;
+                    let inner = type_to_rust_ident(inner, def)?;
                     Ok(quote! { Option<#inner> })
                 }
                 TypeDefKind::List(inner) => {
-                    let inner = type_to_rust_ident(inner, resolve)?;
+                    let inner = type_to_rust_ident(inner, def)?;
                     Ok(quote! { Vec<#inner> })
                 }
                 TypeDefKind::Tuple(tuple) => {
                     let types = tuple
                         .types
                         .iter()
-                        .map(|t| type_to_rust_ident(t, resolve))
+                        .map(|t| type_to_rust_ident(t, def))
                         .collect::<anyhow::Result<Vec<_>>>()?;
                     Ok(quote! { (#(#types),*) })
                 }
                 TypeDefKind::Result(result) => {
                     let ok = match &result.ok {
-                        Some(ok) => type_to_rust_ident(ok, resolve)?,
+                        Some(ok) => type_to_rust_ident(ok, def)?,
                         None => quote! { () },
                     };
                     let err = match &result.err {
-                        Some(err) => type_to_rust_ident(err, resolve)?,
+                        Some(err) => type_to_rust_ident(err, def)?,
                         None => quote! { () },
                     };
                     Ok(quote! { Result<#ok, #err> })
@@ -441,7 +714,7 @@ fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStre
                         Handle::Borrow(type_id) => (type_id, true),
                     };
 
-                    let ident = resource_type_ident(type_id, resolve)?;
+                    let ident = resource_type_ident(type_id, def)?;
                     if is_ref {
                         Ok(quote! { &#ident })
                     } else {
@@ -459,13 +732,15 @@ fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStre
                     path.push(quote! { bindings });
                     match &typedef.owner {
                         TypeOwner::World(world_id) => {
-                            let world = resolve
+                            let world = def
+                                .resolve
                                 .worlds
                                 .get(*world_id)
                                 .ok_or(anyhow!("type's owner world not found"))?;
                             let package_id =
                                 world.package.ok_or(anyhow!("world has no package"))?;
-                            let package = resolve
+                            let package = def
+                                .resolve
                                 .packages
                                 .get(package_id)
                                 .ok_or(anyhow!("package not found"))?;
@@ -479,7 +754,12 @@ fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStre
                             path.push(quote! { #name_ident });
                         }
                         TypeOwner::Interface(interface_id) => {
-                            let interface = resolve
+                            if let Some(module) = def.shared_types_module(*interface_id) {
+                                let module = syn::parse_str::<syn::Path>(module)?;
+                                return Ok(quote! { #module::#typ });
+                            }
+                            let interface = def
+                                .resolve
                                 .interfaces
                                 .get(*interface_id)
                                 .ok_or(anyhow!("type's owner interface not found"))?;
@@ -487,7 +767,8 @@ fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStre
                             let package_id = interface
                                 .package
                                 .ok_or(anyhow!("interface has no package"))?;
-                            let package = resolve
+                            let package = def
+                                .resolve
                                 .packages
                                 .get(package_id)
                                 .ok_or(anyhow!("package not found"))?;
@@ -516,8 +797,10 @@ fn type_to_rust_ident(typ: &Type, resolve: &Resolve) -> anyhow::Result<TokenStre
     }
 }
 
-fn resource_type_ident(type_id: &TypeId, resolve: &Resolve) -> anyhow::Result<Ident> {
-    let typedef = resolve
+/// The path of the stub of a resource, re-exported from the root of the stub crate
+fn resource_type_ident(type_id: &TypeId, def: &StubDefinition) -> anyhow::Result<TokenStream> {
+    let typedef = def
+        .resolve
         .types
         .get(*type_id)
         .ok_or(anyhow!("type not found"))?;
@@ -532,13 +815,295 @@ fn resource_type_ident(type_id: &TypeId, resolve: &Resolve) -> anyhow::Result<Id
         .to_upper_camel_case(),
         Span::call_site(),
     );
-    Ok(ident)
+    Ok(quote! { crate::#ident })
+}
+
+/// `From` conversions in both directions between the stub's types of the interfaces configured to be converted and the
+/// types of the same interfaces in another crate, like the bindings of the target component. The records, variants,
+/// enums and flags are converted, except the ones holding resource handles.
+fn generate_conversions(def: &StubDefinition) -> anyhow::Result<Vec<TokenStream>> {
+    let mut conversions = Vec::new();
+    for (interface_id, _) in def.converted_interfaces()? {
+        if def.shared_types_module(interface_id).is_some() {
+            // The stub already uses the other crate's types
+            continue;
+        }
+        let interface = def
+            .resolve
+            .interfaces
+            .get(interface_id)
+            .ok_or(anyhow!("interface not found"))?;
+        for type_id in interface.types.values() {
+            let typedef = def
+                .resolve
+                .types
+                .get(*type_id)
+                .ok_or(anyhow!("type not found"))?;
+            let convertible = matches!(
+                typedef.kind,
+                TypeDefKind::Record(_)
+                    | TypeDefKind::Variant(_)
+                    | TypeDefKind::Enum(_)
+                    | TypeDefKind::Flags(_)
+            );
+            if !convertible || has_handle(&Type::Id(*type_id), def)? {
+                continue;
+            }
+
+            let stub_type = converted_type_path(type_id, def, false)?;
+            let native_type = converted_type_path(type_id, def, true)?;
+            for (from, to, to_native) in [
+                (&stub_type, &native_type, true),
+                (&native_type, &stub_type, false),
+            ] {
+                let body = match &typedef.kind {
+                    TypeDefKind::Record(record) => {
+                        let mut fields = Vec::new();
+                        for field in &record.fields {
+                            let field_name =
+                                Ident::new(&to_rust_ident(&field.name), Span::call_site());
+                            let field_expr = convert_value(
+                                &field.ty,
+                                def,
+                                quote! { value.#field_name },
+                                to_native,
+                            )?;
+                            fields.push(quote! { #field_name: #field_expr });
+                        }
+                        quote! {
+                            #to {
+                                #(#fields),*
+                            }
+                        }
+                    }
+                    TypeDefKind::Variant(variant) => {
+                        let mut arms = Vec::new();
+                        for case in &variant.cases {
+                            let case_name = Ident::new(
+                                &to_rust_ident(&case.name).to_upper_camel_case(),
+                                Span::call_site(),
+                            );
+                            match &case.ty {
+                                Some(ty) => {
+                                    let case_expr =
+                                        convert_value(ty, def, quote! { inner }, to_native)?;
+                                    arms.push(quote! {
+                                        #from::#case_name(inner) => #to::#case_name(#case_expr)
+                                    });
+                                }
+                                None => arms.push(quote! {
+                                    #from::#case_name => #to::#case_name
+                                }),
+                            }
+                        }
+                        quote! {
+                            match value {
+                                #(#arms),*
+                            }
+                        }
+                    }
+                    TypeDefKind::Enum(enum_def) => {
+                        let arms = enum_def.cases.iter().map(|case| {
+                            let case_name =
+                                Ident::new(&case.name.to_upper_camel_case(), Span::call_site());
+                            quote! { #from::#case_name => #to::#case_name }
+                        });
+                        quote! {
+                            match value {
+                                #(#arms),*
+                            }
+                        }
+                    }
+                    _ => quote! { #to::from_bits_retain(value.bits()) },
+                };
+                conversions.push(quote! {
+                    impl From<#from> for #to {
+                        fn from(value: #from) -> Self {
+                            #body
+                        }
+                    }
+                });
+            }
+        }
+    }
+    Ok(conversions)
+}
+
+/// The path of a converted named type, either the stub's or the other crate's one. The types of other interfaces used by
+/// the converted types have to be converted or shared as well.
+fn converted_type_path(
+    type_id: &TypeId,
+    def: &StubDefinition,
+    native: bool,
+) -> anyhow::Result<TokenStream> {
+    if !native {
+        return type_to_rust_ident(&Type::Id(*type_id), def);
+    }
+    let typedef = def
+        .resolve
+        .types
+        .get(*type_id)
+        .ok_or(anyhow!("type not found"))?;
+    let name = typedef.name.as_ref().ok_or(anyhow!("type has no name"))?;
+    let module = match &typedef.owner {
+        TypeOwner::Interface(interface_id) => def
+            .converted_types_module(*interface_id)
+            .or(def.shared_types_module(*interface_id)),
+        _ => None,
+    }
+    .ok_or(anyhow!(
+        "Type {name} is used by converted types, but it is not converted or shared"
+    ))?;
+    let module = syn::parse_str::<syn::Path>(module)?;
+    let ident = Ident::new(
+        &to_rust_ident(name).to_upper_camel_case(),
+        Span::call_site(),
+    );
+    Ok(quote! { #module::#ident })
+}
+
+/// Converts a value of a WIT type between the stub's and the other crate's types, converting the named types with
+/// their `From` implementations
+fn convert_value(
+    typ: &Type,
+    def: &StubDefinition,
+    expr: TokenStream,
+    to_native: bool,
+) -> anyhow::Result<TokenStream> {
+    if !has_named_types(typ, def)? {
+        return Ok(expr);
+    }
+    let type_id = match typ {
+        Type::Id(type_id) => type_id,
+        _ => return Ok(expr),
+    };
+    let typedef = def
+        .resolve
+        .types
+        .get(*type_id)
+        .ok_or(anyhow!("type not found"))?;
+    match &typedef.kind {
+        TypeDefKind::Type(inner) => convert_value(inner, def, expr, to_native),
+        TypeDefKind::Option(inner) => {
+            let inner = convert_value(inner, def, quote! { inner }, to_native)?;
+            Ok(quote! { #expr.map(|inner| #inner) })
+        }
+        TypeDefKind::List(inner) => {
+            let inner = convert_value(inner, def, quote! { inner }, to_native)?;
+            Ok(quote! { #expr.into_iter().map(|inner| #inner).collect::<Vec<_>>() })
+        }
+        TypeDefKind::Tuple(tuple) => {
+            let mut names = Vec::new();
+            let mut elems = Vec::new();
+            for (idx, typ) in tuple.types.iter().enumerate() {
+                let name = Ident::new(&format!("elem{idx}"), Span::call_site());
+                elems.push(convert_value(typ, def, quote! { #name }, to_native)?);
+                names.push(name);
+            }
+            Ok(quote! {
+                {
+                    let (#(#names,)*) = #expr;
+                    (#(#elems,)*)
+                }
+            })
+        }
+        TypeDefKind::Result(result) => {
+            let mut converted = expr;
+            if let Some(ok) = &result.ok {
+                if has_named_types(ok, def)? {
+                    let ok = convert_value(ok, def, quote! { ok }, to_native)?;
+                    converted = quote! { #converted.map(|ok| #ok) };
+                }
+            }
+            if let Some(err) = &result.err {
+                if has_named_types(err, def)? {
+                    let err = convert_value(err, def, quote! { err }, to_native)?;
+                    converted = quote! { #converted.map_err(|err| #err) };
+                }
+            }
+            Ok(converted)
+        }
+        _ => {
+            let target = converted_type_path(type_id, def, to_native)?;
+            Ok(quote! { #target::from(#expr) })
+        }
+    }
+}
+
+/// Whether a WIT type is or contains records, variants, enums or flags, which are different Rust types in the stub
+/// and in the other crate
+fn has_named_types(typ: &Type, def: &StubDefinition) -> anyhow::Result<bool> {
+    let type_id = match typ {
+        Type::Id(type_id) => type_id,
+        _ => return Ok(false),
+    };
+    let typedef = def
+        .resolve
+        .types
+        .get(*type_id)
+        .ok_or(anyhow!("type not found"))?;
+    match &typedef.kind {
+        TypeDefKind::Type(inner) | TypeDefKind::Option(inner) | TypeDefKind::List(inner) => {
+            has_named_types(inner, def)
+        }
+        TypeDefKind::Tuple(tuple) => {
+            for typ in &tuple.types {
+                if has_named_types(typ, def)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        TypeDefKind::Result(result) => {
+            for typ in result.ok.iter().chain(result.err.iter()) {
+                if has_named_types(typ, def)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Whether a WIT type holds resource handles, which cannot be converted between the stub and the other crate
+fn has_handle(typ: &Type, def: &StubDefinition) -> anyhow::Result<bool> {
+    let type_id = match typ {
+        Type::Id(type_id) => type_id,
+        _ => return Ok(false),
+    };
+    let typedef = def
+        .resolve
+        .types
+        .get(*type_id)
+        .ok_or(anyhow!("type not found"))?;
+    let nested: Vec<&Type> = match &typedef.kind {
+        TypeDefKind::Handle(_) | TypeDefKind::Resource => return Ok(true),
+        TypeDefKind::Type(inner) | TypeDefKind::Option(inner) | TypeDefKind::List(inner) => {
+            vec![inner]
+        }
+        TypeDefKind::Tuple(tuple) => tuple.types.iter().collect(),
+        TypeDefKind::Result(result) => result.ok.iter().chain(result.err.iter()).collect(),
+        TypeDefKind::Record(record) => record.fields.iter().map(|field| &field.ty).collect(),
+        TypeDefKind::Variant(variant) => variant
+            .cases
+            .iter()
+            .filter_map(|case| case.ty.as_ref())
+            .collect(),
+        _ => vec![],
+    };
+    for typ in nested {
+        if has_handle(typ, def)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
 
 fn wit_value_builder(
     typ: &Type,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     match typ {
@@ -582,13 +1147,14 @@ fn wit_value_builder(
             #builder_expr.string(&#name)
         }),
         Type::Id(type_id) => {
-            let typedef = resolve
+            let typedef = def
+                .resolve
                 .types
                 .get(*type_id)
                 .ok_or(anyhow!("type not found"))?;
             match &typedef.kind {
                 TypeDefKind::Record(record) => {
-                    wit_record_value_builder(record, name, resolve, builder_expr)
+                    wit_record_value_builder(record, name, def, builder_expr)
                 }
                 TypeDefKind::Resource => Err(anyhow!("Resource cannot directly appear in a function signature, just through a Handle")),
                 TypeDefKind::Handle(_) => {
@@ -597,29 +1163,29 @@ fn wit_value_builder(
                     })
                 }
                 TypeDefKind::Flags(flags) => {
-                    wit_flags_value_builder(flags, typ, name, resolve, builder_expr)
+                    wit_flags_value_builder(flags, typ, name, def, builder_expr)
                 }
                 TypeDefKind::Tuple(tuple) => {
-                    wit_tuple_value_builder(tuple, name, resolve, builder_expr)
+                    wit_tuple_value_builder(tuple, name, def, builder_expr)
                 }
                 TypeDefKind::Variant(variant) => {
-                    wit_variant_value_builder(variant, typ, name, resolve, builder_expr)
+                    wit_variant_value_builder(variant, typ, name, def, builder_expr)
                 }
                 TypeDefKind::Enum(enum_def) => {
-                    wit_enum_value_builder(enum_def, typ, name, resolve, builder_expr)
+                    wit_enum_value_builder(enum_def, typ, name, def, builder_expr)
                 }
                 TypeDefKind::Option(inner) => {
-                    wit_option_value_builder(inner, name, resolve, builder_expr)
+                    wit_option_value_builder(inner, name, def, builder_expr)
                 }
                 TypeDefKind::Result(result) => {
-                    wit_result_value_builder(result, name, resolve, builder_expr)
+                    wit_result_value_builder(result, name, def, builder_expr)
                 }
                 TypeDefKind::List(elem) => {
-                    wit_list_value_builder(elem, name, resolve, builder_expr)
+                    wit_list_value_builder(elem, name, def, builder_expr)
                 }
                 TypeDefKind::Future(_) => Ok(quote!(todo!("future"))),
                 TypeDefKind::Stream(_) => Ok(quote!(todo!("stream"))),
-                TypeDefKind::Type(typ) => wit_value_builder(typ, name, resolve, builder_expr),
+                TypeDefKind::Type(typ) => wit_value_builder(typ, name, def, builder_expr),
                 TypeDefKind::Unknown => Ok(quote!(todo!("unknown"))),
             }
         }
@@ -629,7 +1195,7 @@ fn wit_value_builder(
 fn wit_record_value_builder(
     record: &Record,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     mut builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     builder_expr = quote! { #builder_expr.record() };
@@ -640,7 +1206,7 @@ fn wit_record_value_builder(
         builder_expr = wit_value_builder(
             &field.ty,
             &field_access,
-            resolve,
+            def,
             quote! { #builder_expr.item() },
         )?;
     }
@@ -652,10 +1218,10 @@ fn wit_flags_value_builder(
     flags: &Flags,
     typ: &Type,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let flags_type = type_to_rust_ident(typ, resolve)?;
+    let flags_type = type_to_rust_ident(typ, def)?;
 
     let mut flags_vec_values = Vec::new();
     for flag in &flags.flags {
@@ -671,7 +1237,7 @@ fn wit_flags_value_builder(
 fn wit_tuple_value_builder(
     tuple: &Tuple,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     mut builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     builder_expr = quote! { #builder_expr.tuple() };
@@ -679,8 +1245,7 @@ fn wit_tuple_value_builder(
     for (n, typ) in tuple.types.iter().enumerate() {
         let field_name = syn::Index::from(n);
         let field_access = quote! { #name.#field_name };
-        builder_expr =
-            wit_value_builder(typ, &field_access, resolve, quote! { #builder_expr.item() })?;
+        builder_expr = wit_value_builder(typ, &field_access, def, quote! { #builder_expr.item() })?;
     }
 
     Ok(quote! { #builder_expr.finish() })
@@ -690,10 +1255,10 @@ fn wit_variant_value_builder(
     variant: &Variant,
     typ: &Type,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let variant_type = type_to_rust_ident(typ, resolve)?;
+    let variant_type = type_to_rust_ident(typ, def)?;
 
     let mut case_idx_patterns = Vec::new();
     let mut is_unit_patterns = Vec::new();
@@ -721,12 +1286,8 @@ fn wit_variant_value_builder(
                 });
             }
             Some(inner_ty) => {
-                let inner_builder_expr = wit_value_builder(
-                    inner_ty,
-                    &quote! { inner },
-                    resolve,
-                    quote! { case_builder },
-                )?;
+                let inner_builder_expr =
+                    wit_value_builder(inner_ty, &quote! { inner }, def, quote! { case_builder })?;
 
                 case_idx_patterns.push(quote! {
                     #variant_type::#case_name(_) => #case_idx
@@ -762,10 +1323,10 @@ fn wit_enum_value_builder(
     enum_def: &Enum,
     typ: &Type,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let enum_type = type_to_rust_ident(typ, resolve)?;
+    let enum_type = type_to_rust_ident(typ, def)?;
 
     let mut cases = Vec::new();
     for (n, case) in enum_def.cases.iter().enumerate() {
@@ -786,13 +1347,13 @@ fn wit_enum_value_builder(
 fn wit_option_value_builder(
     inner: &Type,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     let inner_builder_expr = wit_value_builder(
         inner,
         &quote! { #name.as_ref().unwrap() },
-        resolve,
+        def,
         quote! { some_builder },
     )?;
 
@@ -806,22 +1367,15 @@ fn wit_option_value_builder(
 fn wit_result_value_builder(
     result: &Result_,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     let ok_expr = match &result.ok {
-        Some(ok) => {
-            wit_value_builder(ok, &quote! { ok_value }, resolve, quote! { result_builder })?
-        }
+        Some(ok) => wit_value_builder(ok, &quote! { ok_value }, def, quote! { result_builder })?,
         None => quote! { unreachable!() },
     };
     let err_expr = match &result.err {
-        Some(err) => wit_value_builder(
-            err,
-            &quote! { err_value },
-            resolve,
-            quote! { result_builder },
-        )?,
+        Some(err) => wit_value_builder(err, &quote! { err_value }, def, quote! { result_builder })?,
         None => quote! { unreachable!() },
     };
 
@@ -844,11 +1398,17 @@ fn wit_result_value_builder(
 fn wit_list_value_builder(
     inner: &Type,
     name: &TokenStream,
-    resolve: &Resolve,
+    def: &StubDefinition,
     builder_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
+    if let Type::U8 = inner {
+        // The lists of bytes are stored as a single node
+        return Ok(quote! {
+            #builder_expr.bytes(&#name)
+        });
+    }
     let inner_builder_expr =
-        wit_value_builder(inner, &quote! { item }, resolve, quote! { item_builder })?;
+        wit_value_builder(inner, &quote! { item }, def, quote! { item_builder })?;
 
     Ok(quote! {
         #builder_expr.list_fn(&#name, |item, item_builder| {
@@ -859,7 +1419,7 @@ fn wit_list_value_builder(
 
 fn extract_from_wit_value(
     typ: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     match typ {
@@ -903,34 +1463,35 @@ fn extract_from_wit_value(
             #base_expr.string().expect("string not found").to_string()
         }),
         Type::Id(type_id) => {
-            let typedef = resolve
+            let typedef = def
+                .resolve
                 .types
                 .get(*type_id)
                 .ok_or(anyhow!("type not found"))?;
             match &typedef.kind {
                 TypeDefKind::Record(record) => {
-                    extract_from_record_value(record, typ, resolve, base_expr)
+                    extract_from_record_value(record, typ, def, base_expr)
                 }
                 TypeDefKind::Resource => Err(anyhow!("Resource cannot directly appear in a function signature, just through a Handle")),
-                TypeDefKind::Handle(handle) => extract_from_handle_value(handle, resolve, base_expr),
+                TypeDefKind::Handle(handle) => extract_from_handle_value(handle, def, base_expr),
                 TypeDefKind::Flags(flags) => {
-                    extract_from_flags_value(flags, typ, resolve, base_expr)
+                    extract_from_flags_value(flags, typ, def, base_expr)
                 }
-                TypeDefKind::Tuple(tuple) => extract_from_tuple_value(tuple, resolve, base_expr),
+                TypeDefKind::Tuple(tuple) => extract_from_tuple_value(tuple, def, base_expr),
                 TypeDefKind::Variant(variant) => {
-                    extract_from_variant_value(variant, typ, resolve, base_expr)
+                    extract_from_variant_value(variant, typ, def, base_expr)
                 }
                 TypeDefKind::Enum(enum_def) => {
-                    extract_from_enum_value(enum_def, typ, resolve, base_expr)
+                    extract_from_enum_value(enum_def, typ, def, base_expr)
                 }
-                TypeDefKind::Option(inner) => extract_from_option_value(inner, resolve, base_expr),
+                TypeDefKind::Option(inner) => extract_from_option_value(inner, def, base_expr),
                 TypeDefKind::Result(result) => {
-                    extract_from_result_value(result, resolve, base_expr)
+                    extract_from_result_value(result, def, base_expr)
                 }
-                TypeDefKind::List(elem) => extract_from_list_value(elem, resolve, base_expr),
+                TypeDefKind::List(elem) => extract_from_list_value(elem, def, base_expr),
                 TypeDefKind::Future(_) => Ok(quote!(todo!("future"))),
                 TypeDefKind::Stream(_) => Ok(quote!(todo!("stream"))),
-                TypeDefKind::Type(typ) => extract_from_wit_value(typ, resolve, base_expr),
+                TypeDefKind::Type(typ) => extract_from_wit_value(typ, def, base_expr),
                 TypeDefKind::Unknown => Ok(quote!(todo!("unknown"))),
             }
         }
@@ -940,7 +1501,7 @@ fn extract_from_wit_value(
 fn extract_from_record_value(
     record: &Record,
     record_type: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     let mut field_extractors = Vec::new();
@@ -948,7 +1509,7 @@ fn extract_from_record_value(
         let field_name = Ident::new(&to_rust_ident(&field.name), Span::call_site());
         let field_expr = extract_from_wit_value(
             &field.ty,
-            resolve,
+            def,
             quote! { record.field(#field_idx).expect("record field not found") },
         )?;
         field_extractors.push(quote! {
@@ -956,7 +1517,7 @@ fn extract_from_record_value(
         });
     }
 
-    let record_type = type_to_rust_ident(record_type, resolve)?;
+    let record_type = type_to_rust_ident(record_type, def)?;
 
     Ok(quote! {
         {
@@ -971,10 +1532,10 @@ fn extract_from_record_value(
 fn extract_from_flags_value(
     flags: &Flags,
     flags_type: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let flags_type = type_to_rust_ident(flags_type, resolve)?;
+    let flags_type = type_to_rust_ident(flags_type, def)?;
     let mut flag_exprs = Vec::new();
     for (flag_idx, flag) in flags.flags.iter().enumerate() {
         let flag_name = Ident::new(&flag.name.to_shouty_snake_case(), Span::call_site());
@@ -997,14 +1558,14 @@ fn extract_from_flags_value(
 
 fn extract_from_tuple_value(
     tuple: &Tuple,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     let mut elem_extractors = Vec::new();
     for (field_idx, typ) in tuple.types.iter().enumerate() {
         let elem_expr = extract_from_wit_value(
             typ,
-            resolve,
+            def,
             quote! { tuple.tuple_element(#field_idx).expect("tuple element not found") },
         )?;
         elem_extractors.push(elem_expr);
@@ -1021,10 +1582,10 @@ fn extract_from_tuple_value(
 fn extract_from_variant_value(
     variant: &Variant,
     variant_type: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let variant_type = type_to_rust_ident(variant_type, resolve)?;
+    let variant_type = type_to_rust_ident(variant_type, def)?;
 
     let mut case_extractors = Vec::new();
     for (n, case) in variant.cases.iter().enumerate() {
@@ -1038,7 +1599,7 @@ fn extract_from_variant_value(
             Some(ty) => {
                 let case_expr = extract_from_wit_value(
                     ty,
-                    resolve,
+                    def,
                     quote! { inner.expect("variant case not found") },
                 )?;
                 case_extractors.push(quote! {
@@ -1067,10 +1628,10 @@ fn extract_from_variant_value(
 fn extract_from_enum_value(
     enum_def: &Enum,
     enum_type: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let enum_type = type_to_rust_ident(enum_type, resolve)?;
+    let enum_type = type_to_rust_ident(enum_type, def)?;
 
     let mut case_extractors = Vec::new();
     for (n, case) in enum_def.cases.iter().enumerate() {
@@ -1094,10 +1655,10 @@ fn extract_from_enum_value(
 
 fn extract_from_option_value(
     inner: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let inner_expr = extract_from_wit_value(inner, resolve, quote! { inner })?;
+    let inner_expr = extract_from_wit_value(inner, def, quote! { inner })?;
 
     Ok(quote! {
         #base_expr.option().expect("option not found").map(|inner| #inner_expr)
@@ -1106,13 +1667,13 @@ fn extract_from_option_value(
 
 fn extract_from_result_value(
     result: &Result_,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     let ok_expr = match &result.ok {
         Some(ok) => extract_from_wit_value(
             ok,
-            resolve,
+            def,
             quote! { ok_value.expect("result ok value not found") },
         )?,
         None => quote! { () },
@@ -1120,7 +1681,7 @@ fn extract_from_result_value(
     let err_expr = match &result.err {
         Some(err) => extract_from_wit_value(
             err,
-            resolve,
+            def,
             quote! { err_value.expect("result err value not found") },
         )?,
         None => quote! { () },
@@ -1139,10 +1700,24 @@ fn extract_from_result_value(
 
 fn extract_from_list_value(
     inner: &Type,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
-    let inner_expr = extract_from_wit_value(inner, resolve, quote! { item })?;
+    if let Type::U8 = inner {
+        // Lists of bytes are read from their single node, unless the other side sent a node per byte
+        return Ok(quote! {
+            {
+                let list = #base_expr;
+                match list.bytes() {
+                    Some(bytes) => bytes.to_vec(),
+                    None => list
+                        .list_elements(|item| item.u8().expect("u8 not found"))
+                        .expect("list not found"),
+                }
+            }
+        });
+    }
+    let inner_expr = extract_from_wit_value(inner, def, quote! { item })?;
 
     Ok(quote! {
         #base_expr.list_elements(|item| #inner_expr).expect("list not found")
@@ -1151,12 +1726,12 @@ fn extract_from_list_value(
 
 fn extract_from_handle_value(
     handle: &Handle,
-    resolve: &Resolve,
+    def: &StubDefinition,
     base_expr: TokenStream,
 ) -> anyhow::Result<TokenStream> {
     match handle {
         Handle::Own(type_id) => {
-            let ident = resource_type_ident(type_id, resolve)?;
+            let ident = resource_type_ident(type_id, def)?;
             Ok(quote! {
                 {
                     let (uri, id) = #base_expr.handle().expect("handle not found");
@@ -1165,7 +1740,7 @@ fn extract_from_handle_value(
             })
         }
         Handle::Borrow(type_id) => {
-            let ident = resource_type_ident(type_id, resolve)?;
+            let ident = resource_type_ident(type_id, def)?;
             Ok(quote! {
                 {
                     let (uri, id) = #base_expr.handle().expect("handle not found");
@@ -0,0 +1,344 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::content_hash;
+use crate::backup::utc_date_time;
+use crate::paths::manifest_path;
+use anyhow::Context;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Software bill of materials of a built stub or a composed component in the CycloneDX JSON format, listing what the
+/// component was made from and the version of the stub generator that made it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bom {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: Metadata,
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Metadata {
+    timestamp: String,
+    tools: Tools,
+    component: Component,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Tools {
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Component {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<Hash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Hash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Property {
+    name: String,
+    value: String,
+}
+
+impl Component {
+    fn new(kind: &'static str, name: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            version: None,
+            purl: None,
+            hashes: Vec::new(),
+            properties: Vec::new(),
+        }
+    }
+
+    fn with_hash(mut self, content: &[u8]) -> Self {
+        self.hashes.push(Hash {
+            alg: "SHA-256",
+            content: content_hash(content),
+        });
+        self
+    }
+
+    fn with_property(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.properties.push(Property {
+            name: name.to_string(),
+            value: value.into(),
+        });
+        self
+    }
+}
+
+impl Bom {
+    fn new(wasm: &Path, content: &[u8], components: Vec<Component>) -> Self {
+        let name = wasm
+            .file_name()
+            .unwrap_or(wasm.as_os_str())
+            .to_string_lossy();
+        let mut tool = Component::new("application", env!("CARGO_PKG_NAME"));
+        tool.version = Some(env!("CARGO_PKG_VERSION").to_string());
+        Bom {
+            bom_format: "CycloneDX",
+            spec_version: "1.5",
+            version: 1,
+            metadata: Metadata {
+                timestamp: timestamp(),
+                tools: Tools {
+                    components: vec![tool],
+                },
+                component: Component::new("application", name).with_hash(content),
+            },
+            components,
+        }
+    }
+
+    /// The bill of materials of a stub WASM compiled from the given crate: the WIT files of the source WIT root and
+    /// the crates locked in the crate's `Cargo.lock`
+    pub fn stub(
+        wasm: &Path,
+        content: &[u8],
+        source_wit_root: &Path,
+        crate_root: &Path,
+        crate_name: &str,
+        cache_key: &str,
+    ) -> anyhow::Result<Self> {
+        let mut components = wit_components(source_wit_root)?;
+        components.extend(locked_crates(&crate_root.join("Cargo.lock"), crate_name)?);
+        let mut bom = Bom::new(wasm, content, components);
+        bom.metadata.component = bom
+            .metadata
+            .component
+            .with_property("wasm-rpc:cache-key", cache_key);
+        Ok(bom)
+    }
+
+    /// The bill of materials of a composed component: the components it was composed of
+    pub fn composition(wasm: &Path, content: &[u8], parts: &[PathBuf]) -> anyhow::Result<Self> {
+        let components = parts
+            .iter()
+            .map(|part| {
+                let part_content = fs::read(part).context(format!("Failed to read {part:?}"))?;
+                Ok(Component::new("file", manifest_path(part)).with_hash(&part_content))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Bom::new(wasm, content, components))
+    }
+
+    /// Writes the bill of materials next to the WASM file it describes, returning its path, see [sbom_path]
+    pub fn write(&self, wasm: &Path) -> anyhow::Result<PathBuf> {
+        let path = sbom_path(wasm);
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&path, json + "\n").context(format!("Failed to write {path:?}"))?;
+        Ok(path)
+    }
+}
+
+/// The path of the bill of materials of a WASM file, `<wasm>.cdx.json`
+pub fn sbom_path(wasm: &Path) -> PathBuf {
+    let mut path = wasm.as_os_str().to_os_string();
+    path.push(".cdx.json");
+    PathBuf::from(path)
+}
+
+/// The current time in RFC 3339 format, or the one of `SOURCE_DATE_EPOCH` for reproducible builds
+fn timestamp() -> String {
+    let time = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .unwrap_or_else(SystemTime::now);
+    let [year, month, day, hour, minute, second] = utc_date_time(time);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// The WIT files of a WIT root with their hashes, by their paths relative to the root
+fn wit_components(wit_root: &Path) -> anyhow::Result<Vec<Component>> {
+    let mut files = Vec::new();
+    wit_files(wit_root, &mut files)?;
+    files.sort();
+    files
+        .into_iter()
+        .map(|file| {
+            let content = fs::read(&file).context(format!("Failed to read {file:?}"))?;
+            let name = manifest_path(file.strip_prefix(wit_root).unwrap_or(&file));
+            Ok(Component::new("file", name).with_hash(&content))
+        })
+        .collect()
+}
+
+fn wit_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).context(format!("Failed to read {dir:?}"))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            wit_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "wit") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The crates of a `Cargo.lock`, except the crate it was generated for
+fn locked_crates(lock_file: &Path, crate_name: &str) -> anyhow::Result<Vec<Component>> {
+    if !lock_file.exists() {
+        return Ok(Vec::new());
+    }
+    let lock: toml::Value = toml::from_str(
+        &fs::read_to_string(lock_file).context(format!("Failed to read {lock_file:?}"))?,
+    )
+    .context(format!("Failed to parse {lock_file:?}"))?;
+    let packages = lock
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .cloned()
+        .unwrap_or_default();
+    Ok(packages
+        .iter()
+        .filter_map(|package| {
+            let name = package.get("name")?.as_str()?;
+            let version = package.get("version")?.as_str()?;
+            if name == crate_name {
+                return None;
+            }
+            let mut component = Component::new("library", name);
+            component.version = Some(version.to_string());
+            component.purl = Some(format!("pkg:cargo/{name}@{version}"));
+            if let Some(checksum) = package.get("checksum").and_then(|c| c.as_str()) {
+                component.hashes.push(Hash {
+                    alg: "SHA-256",
+                    content: checksum.to_string(),
+                });
+            }
+            if let Some(source) = package.get("source").and_then(|s| s.as_str()) {
+                component = component.with_property("cargo:source", source);
+            }
+            Some(component)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sbom::{sbom_path, Bom};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+
+    #[test]
+    fn stub_sboms_list_the_source_wit_files_and_the_locked_crates() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let wit_root = root.path().join("wit");
+        fs::create_dir_all(wit_root.join("deps/io")).unwrap();
+        fs::write(wit_root.join("api.wit"), "package test:api;\n").unwrap();
+        fs::write(wit_root.join("deps/io/io.wit"), "package wasi:io;\n").unwrap();
+        fs::write(wit_root.join("README.md"), "not a WIT file").unwrap();
+        fs::write(
+            root.path().join("Cargo.lock"),
+            r#"version = 3
+
+[[package]]
+name = "api-stub"
+version = "0.0.1"
+
+[[package]]
+name = "anyhow"
+version = "1.0.79"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "080e9890a082662b09c1ad45f567faeeb47f22b5fb23895fbe1e651e718e25ca"
+"#,
+        )
+        .unwrap();
+
+        let bom = Bom::stub(
+            Path::new("target/api_stub.wasm"),
+            b"\0asm",
+            &wit_root,
+            root.path(),
+            "api-stub",
+            "key",
+        )
+        .unwrap();
+        let json = serde_json::to_value(&bom).unwrap();
+
+        assert_eq!(json["bomFormat"], "CycloneDX");
+        assert_eq!(json["metadata"]["component"]["name"], "api_stub.wasm");
+        assert_eq!(
+            json["metadata"]["component"]["properties"][0]["value"],
+            "key"
+        );
+        assert_eq!(
+            json["metadata"]["tools"]["components"][0]["version"],
+            env!("CARGO_PKG_VERSION")
+        );
+        let names: Vec<_> = json["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|component| component["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["api.wit", "deps/io/io.wit", "anyhow"]);
+        assert_eq!(json["components"][2]["purl"], "pkg:cargo/anyhow@1.0.79");
+    }
+
+    #[test]
+    fn composition_sboms_hash_the_composed_components() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let parts: Vec<PathBuf> = ["caller.wasm", "stub.wasm"]
+            .iter()
+            .map(|name| root.path().join(name))
+            .collect();
+        for part in &parts {
+            fs::write(part, part.to_string_lossy().as_bytes()).unwrap();
+        }
+        let composed = root.path().join("composed.wasm");
+
+        let bom = Bom::composition(&composed, b"composed", &parts).unwrap();
+        let path = bom.write(&composed).unwrap();
+
+        assert_eq!(path, sbom_path(&composed));
+        assert!(path.to_string_lossy().ends_with("composed.wasm.cdx.json"));
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(json["components"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            json["components"][1]["hashes"][0]["content"],
+            crate::api::content_hash(parts[1].to_string_lossy().as_bytes())
+        );
+        assert_eq!(
+            json["metadata"]["component"]["hashes"][0]["content"],
+            crate::api::content_hash(b"composed")
+        );
+    }
+}
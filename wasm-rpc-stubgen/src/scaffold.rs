@@ -0,0 +1,399 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::{CommandError, ErrorKind};
+use crate::make::BuildTool;
+use crate::stub::StubPackageVersion;
+use crate::{
+    cargo, make, AddStubDependencyArgs, ConflictPolicy, GenerateArgs, NewArgs, NewProjectOutput,
+};
+use anyhow::{anyhow, bail, Context};
+use heck::{ToKebabCase, ToSnakeCase};
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// The components a new project is scaffolded with
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectTemplate {
+    /// A component calling a target component of the same workspace through its stub
+    Caller,
+    /// A counter component to be called through RPC, with its generated stub
+    Target,
+    /// Both the target and the caller components, ready to build and compose
+    Pair,
+}
+
+impl ProjectTemplate {
+    fn has_target(&self) -> bool {
+        matches!(self, ProjectTemplate::Target | ProjectTemplate::Pair)
+    }
+
+    fn has_caller(&self) -> bool {
+        matches!(self, ProjectTemplate::Caller | ProjectTemplate::Pair)
+    }
+}
+
+pub fn new_project(
+    args: NewArgs,
+    stubgen_command: &str,
+    stubgen_prefix: &[&str],
+) -> anyhow::Result<NewProjectOutput> {
+    let root = &args.path;
+    let namespace = args.namespace.to_kebab_case();
+    let target = args.target_name.to_kebab_case();
+    let caller = args.caller_name.to_kebab_case();
+    if args.template == ProjectTemplate::Pair && target == caller {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            "The target and the caller components must have different names"
+        ));
+    }
+
+    fs::create_dir_all(root).context(format!("Failed to create {root:?}"))?;
+    let workspace_cargo = root.join("Cargo.toml");
+    let existing_workspace = workspace_cargo.exists();
+    if existing_workspace && !cargo::is_cargo_workspace_toml(&workspace_cargo)? {
+        bail!(CommandError::new(
+            ErrorKind::InvalidArguments,
+            format!("{workspace_cargo:?} exists, but it is not a cargo workspace")
+        ));
+    }
+
+    let mut components = Vec::new();
+    if args.template.has_target() {
+        write_target(root, &namespace, &target)?;
+        components.push(target.clone());
+    }
+    if args.template.has_caller() {
+        write_caller(root, &namespace, &caller, &target)?;
+        components.push(caller.clone());
+    }
+
+    if existing_workspace {
+        cargo::add_workspace_members(&workspace_cargo, &components)?;
+    } else {
+        info!("Writing workspace Cargo.toml to {:?}", workspace_cargo);
+        fs::write(&workspace_cargo, workspace_cargo_toml(&components))?;
+    }
+
+    let stub_name = format!("{target}-stub");
+    let mut stub_crates = Vec::new();
+    if args.template.has_target() || !root.join(&stub_name).exists() {
+        let target_wit_root = root.join(&target).join("wit");
+        if !target_wit_root.exists() {
+            bail!(CommandError::new(
+                ErrorKind::MissingDependency,
+                format!(
+                    "The target component {target} does not exist in {root:?}. Create it first with `--template target`."
+                )
+            ));
+        }
+
+        info!("Generating initial stub for {target}");
+        crate::generate(GenerateArgs {
+            source_wit_root: target_wit_root,
+            dest_crate_root: root.join(&stub_name),
+            package: None,
+            world: None,
+            stub_crate_version: "0.0.1".to_string(),
+            stub_package_version: StubPackageVersion::Same,
+            wasm_rpc_path_override: None,
+            wasm_rpc_source: args.wasm_rpc_source.clone(),
+            wit_bindgen_source: args.wit_bindgen_source.clone(),
+            shared_types: vec![],
+            converted_types: vec![],
+            force_manifest: false,
+            with_integration_test: false,
+            with_metadata: false,
+            older_wit_root: None,
+            conventions: None,
+            include_unstable: false,
+            deny_breaking: false,
+            dry_run: false,
+            keep_backup: false,
+            no_gitignore: false,
+        })?;
+        cargo::add_workspace_members(&workspace_cargo, std::slice::from_ref(&stub_name))?;
+        stub_crates.push(stub_name.clone());
+    }
+
+    if args.template.has_caller() {
+        crate::add_stub_dependency(AddStubDependencyArgs {
+            stub_wit_root: root.join(&stub_name).join("wit"),
+            dest_wit_root: root.join(&caller).join("wit"),
+            overwrite: false,
+            on_conflict: ConflictPolicy::Fail,
+            update_cargo_toml: true,
+            link: false,
+            dry_run: false,
+            keep_backup: false,
+        })?;
+    }
+
+    let makefile_path = root.join("Makefile.toml");
+    let makefile = if makefile_path.exists() {
+        warn!("{makefile_path:?} already exists, update it by running `initialize-workspace` with all the targets and callers");
+        None
+    } else {
+        let callers = if args.template.has_caller() {
+            vec![caller.clone()]
+        } else {
+            vec![]
+        };
+        let (makefile, _) = make::generate_makefile(
+            std::slice::from_ref(&target),
+            &callers,
+            None,
+            &args.wasm_rpc_source,
+            &args.wit_bindgen_source,
+            BuildTool::CargoMake,
+            stubgen_command,
+            stubgen_prefix,
+            None,
+        )?;
+        info!("Writing cargo-make Makefile to {:?}", makefile_path);
+        fs::write(&makefile_path, makefile)?;
+        Some(makefile_path)
+    };
+
+    Ok(NewProjectOutput {
+        workspace: root.clone(),
+        components,
+        stub_crates,
+        makefile,
+    })
+}
+
+fn write_target(root: &Path, namespace: &str, name: &str) -> anyhow::Result<()> {
+    let wit = format!(
+        r#"package {namespace}:{name};
+
+interface api {{
+  add: func(value: u64);
+  get: func() -> u64;
+}}
+
+world {name} {{
+  export api;
+}}
+"#
+    );
+    let lib = format!(
+        r#"mod bindings;
+
+use crate::bindings::exports::{namespace}::{name}::api::Guest;
+use std::cell::RefCell;
+
+struct Component;
+
+thread_local! {{
+    static COUNTER: RefCell<u64> = const {{ RefCell::new(0) }};
+}}
+
+impl Guest for Component {{
+    fn add(value: u64) {{
+        COUNTER.with_borrow_mut(|counter| *counter += value);
+    }}
+
+    fn get() -> u64 {{
+        COUNTER.with_borrow(|counter| *counter)
+    }}
+}}
+"#,
+        namespace = namespace.to_snake_case(),
+        name = name.to_snake_case(),
+    );
+    write_component(root, namespace, name, &wit, &lib)
+}
+
+fn write_caller(root: &Path, namespace: &str, name: &str, target: &str) -> anyhow::Result<()> {
+    let wit = format!(
+        r#"package {namespace}:{name};
+
+interface api {{
+  run: func(counter-uri: string) -> u64;
+}}
+
+world {name} {{
+  import {namespace}:{target}-stub/stub-{target};
+  export api;
+}}
+"#
+    );
+    let lib = format!(
+        r#"mod bindings;
+
+use crate::bindings::exports::{namespace}::{name}::api::Guest;
+use crate::bindings::golem::rpc::types::Uri;
+use crate::bindings::{namespace}::{target}_stub::stub_{target}::Api;
+
+struct Component;
+
+impl Guest for Component {{
+    /// Increments the counter of the worker at the given `worker://<component-id>/<worker-name>` URI through RPC
+    fn run(counter_uri: String) -> u64 {{
+        let counter = Api::new(&Uri {{ value: counter_uri }});
+        counter.add(1);
+        counter.get()
+    }}
+}}
+"#,
+        namespace = namespace.to_snake_case(),
+        name = name.to_snake_case(),
+        target = target.to_snake_case(),
+    );
+    write_component(root, namespace, name, &wit, &lib)
+}
+
+fn write_component(
+    root: &Path,
+    namespace: &str,
+    name: &str,
+    wit: &str,
+    lib: &str,
+) -> anyhow::Result<()> {
+    let component_root = root.join(name);
+    if component_root.exists() {
+        return Err(anyhow!(CommandError::new(
+            ErrorKind::Conflict,
+            format!("{component_root:?} already exists")
+        )));
+    }
+
+    info!("Creating component {name} in {component_root:?}");
+    fs::create_dir_all(component_root.join("wit"))?;
+    fs::create_dir_all(component_root.join("src"))?;
+    fs::write(
+        component_root.join("Cargo.toml"),
+        component_cargo_toml(namespace, name),
+    )?;
+    fs::write(component_root.join("wit").join(format!("{name}.wit")), wit)?;
+    fs::write(component_root.join("src").join("lib.rs"), lib)?;
+    Ok(())
+}
+
+fn component_cargo_toml(namespace: &str, name: &str) -> String {
+    format!(
+        r#"[package]
+name = "{name}"
+version = "0.0.1"
+edition = "2021"
+
+[lib]
+crate-type = ["cdylib"]
+
+[dependencies]
+wit-bindgen = {{ version = "0.17.0", default-features = false, features = ["realloc"] }}
+
+[package.metadata.component]
+package = "{namespace}:{name}"
+
+[package.metadata.component.target]
+path = "wit"
+"#
+    )
+}
+
+fn workspace_cargo_toml(members: &[String]) -> String {
+    let members = members
+        .iter()
+        .map(|member| format!("    \"{member}\",\n"))
+        .collect::<String>();
+    format!(
+        r#"[workspace]
+resolver = "2"
+members = [
+{members}]
+
+[profile.release]
+opt-level = "s"
+lto = true
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cargo::get_workspace_members;
+    use crate::scaffold::{new_project, ProjectTemplate};
+    use crate::NewArgs;
+    use tempdir::TempDir;
+
+    fn new_args(path: &std::path::Path, template: ProjectTemplate) -> NewArgs {
+        NewArgs {
+            path: path.to_path_buf(),
+            template,
+            namespace: "rpc".to_string(),
+            target_name: "counter".to_string(),
+            caller_name: "caller".to_string(),
+            wasm_rpc_source: None,
+            wit_bindgen_source: None,
+        }
+    }
+
+    #[test]
+    fn pair_is_scaffolded_with_stub_dependency() {
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let root = temp_dir.path().join("app");
+
+        let output = new_project(
+            new_args(&root, ProjectTemplate::Pair),
+            "wasm-rpc-stubgen",
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(output.components, vec!["counter", "caller"]);
+        assert_eq!(output.stub_crates, vec!["counter-stub"]);
+        assert_eq!(
+            get_workspace_members(&root.join("Cargo.toml")).unwrap(),
+            vec!["counter", "caller", "counter-stub"]
+        );
+        assert!(root
+            .join("caller/wit/deps/rpc_counter-stub/_stub.wit")
+            .is_file());
+        assert!(std::fs::read_to_string(root.join("caller/Cargo.toml"))
+            .unwrap()
+            .contains("rpc:counter-stub"));
+        assert!(std::fs::read_to_string(root.join("Makefile.toml"))
+            .unwrap()
+            .contains("compose-caller"));
+    }
+
+    #[test]
+    fn caller_is_added_to_a_target_workspace() {
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let root = temp_dir.path().join("app");
+
+        new_project(
+            new_args(&root, ProjectTemplate::Target),
+            "wasm-rpc-stubgen",
+            &[],
+        )
+        .unwrap();
+        let output = new_project(
+            new_args(&root, ProjectTemplate::Caller),
+            "wasm-rpc-stubgen",
+            &[],
+        )
+        .unwrap();
+
+        assert!(output.stub_crates.is_empty());
+        assert!(output.makefile.is_none());
+        assert!(root
+            .join("caller/wit/deps/rpc_counter-stub/_stub.wit")
+            .is_file());
+    }
+}
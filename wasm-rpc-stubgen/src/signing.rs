@@ -0,0 +1,400 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::{CommandError, ErrorKind};
+use crate::optimize::{write_leb128, write_section};
+use anyhow::{anyhow, bail, Context};
+use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Debug;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use wasmparser::{Chunk, Parser, Payload};
+
+/// The custom section holding the signature of a component
+pub const SIGNATURE_SECTION: &str = "wasm-rpc-signature";
+
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// The signature embedded in a component, made over the SHA-256 digest of the component without its signature
+/// section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentSignature {
+    pub algorithm: String,
+    /// Identifies the key the component was signed with, the hexadecimal public key for `ed25519`
+    pub key_id: String,
+    /// The signature in hexadecimal
+    pub signature: String,
+    /// Whether the signature was checked by a [Verifier]
+    #[serde(default, skip_deserializing)]
+    pub verified: bool,
+}
+
+/// Signs the digests of components, for example with a key or through a key management service
+pub trait Signer: Debug + Send + Sync {
+    fn algorithm(&self) -> String;
+    fn key_id(&self) -> String;
+    fn sign(&self, digest: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Checks the signatures made by a [Signer], failing if a signature is not valid
+pub trait Verifier: Debug + Send + Sync {
+    fn verify(&self, signature: &ComponentSignature, digest: &[u8]) -> anyhow::Result<()>;
+}
+
+/// Signs with an Ed25519 secret key
+#[derive(Debug)]
+pub struct Ed25519Signer {
+    key: SigningKey,
+}
+
+impl Ed25519Signer {
+    /// Reads the secret key from a file containing it in hexadecimal, as written by [generate_key_pair]
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            key: SigningKey::from_bytes(&read_key(path)?),
+        })
+    }
+}
+
+impl Signer for Ed25519Signer {
+    fn algorithm(&self) -> String {
+        "ed25519".to_string()
+    }
+
+    fn key_id(&self) -> String {
+        hex::encode(self.key.verifying_key().as_bytes())
+    }
+
+    fn sign(&self, digest: &[u8]) -> anyhow::Result<Vec<u8>> {
+        use ed25519_dalek::Signer;
+        Ok(self.key.sign(digest).to_bytes().to_vec())
+    }
+}
+
+/// Verifies the signatures made with the secret key of an Ed25519 public key
+#[derive(Debug)]
+pub struct Ed25519Verifier {
+    key: VerifyingKey,
+}
+
+impl Ed25519Verifier {
+    /// Reads the public key from a file containing it in hexadecimal, as written by [generate_key_pair]
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let key = VerifyingKey::from_bytes(&read_key(path)?)
+            .context(format!("{path:?} does not contain an Ed25519 public key"))?;
+        Ok(Self { key })
+    }
+}
+
+impl Verifier for Ed25519Verifier {
+    fn verify(&self, signature: &ComponentSignature, digest: &[u8]) -> anyhow::Result<()> {
+        if signature.algorithm != "ed25519" {
+            bail!(
+                "The component is signed with {}, not ed25519",
+                signature.algorithm
+            );
+        }
+        if signature.key_id != hex::encode(self.key.as_bytes()) {
+            bail!(
+                "The component is signed with another key, {}",
+                signature.key_id
+            );
+        }
+        let bytes = hex::decode(&signature.signature).context("Invalid signature")?;
+        let signature = Signature::from_slice(&bytes).context("Invalid signature")?;
+        self.key
+            .verify_strict(digest, &signature)
+            .map_err(|_| anyhow!("The signature does not match the component"))
+    }
+}
+
+/// Signs by running a program, like a wrapper of a key management service. The program gets the hexadecimal digest
+/// on its standard input, and prints the hexadecimal signature to its standard output.
+#[derive(Debug, Clone)]
+pub struct CommandSigner {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub key_id: String,
+}
+
+impl Signer for CommandSigner {
+    fn algorithm(&self) -> String {
+        "external".to_string()
+    }
+
+    fn key_id(&self) -> String {
+        self.key_id.clone()
+    }
+
+    fn sign(&self, digest: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let output = run_with_digest(
+            Command::new(&self.program).args(&self.args),
+            &self.program,
+            digest,
+        )?;
+        hex::decode(output.trim()).context(format!(
+            "{:?} did not print a hexadecimal signature",
+            self.program
+        ))
+    }
+}
+
+/// Verifies by running a program, which gets the hexadecimal digest on its standard input and the signature in the
+/// `WASM_RPC_SIGNATURE`, `WASM_RPC_SIGNATURE_ALGORITHM` and `WASM_RPC_KEY_ID` environment variables, and exits
+/// successfully if the signature is valid
+#[derive(Debug, Clone)]
+pub struct CommandVerifier {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+}
+
+impl Verifier for CommandVerifier {
+    fn verify(&self, signature: &ComponentSignature, digest: &[u8]) -> anyhow::Result<()> {
+        run_with_digest(
+            Command::new(&self.program)
+                .args(&self.args)
+                .env("WASM_RPC_SIGNATURE", &signature.signature)
+                .env("WASM_RPC_SIGNATURE_ALGORITHM", &signature.algorithm)
+                .env("WASM_RPC_KEY_ID", &signature.key_id),
+            &self.program,
+            digest,
+        )
+        .map(|_| ())
+    }
+}
+
+fn run_with_digest(command: &mut Command, program: &Path, digest: &[u8]) -> anyhow::Result<String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to run {program:?}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", hex::encode(digest))?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{program:?} failed with {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Generates an Ed25519 key pair, writing the secret and the public key in hexadecimal to the given files. Returns
+/// the public key, which is the key id of the signatures.
+pub fn generate_key_pair(secret_key: &Path, public_key: &Path) -> anyhow::Result<String> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|err| anyhow!("Failed to generate a key: {err}"))?;
+    let key = SigningKey::from_bytes(&seed);
+    let public = hex::encode(key.verifying_key().as_bytes());
+    write_secret(secret_key, &hex::encode(key.to_bytes()))
+        .context(format!("Failed to write {secret_key:?}"))?;
+    fs::write(public_key, format!("{public}\n"))
+        .context(format!("Failed to write {public_key:?}"))?;
+    Ok(public)
+}
+
+#[cfg(unix)]
+fn write_secret(path: &Path, key: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{key}")
+}
+
+#[cfg(not(unix))]
+fn write_secret(path: &Path, key: &str) -> std::io::Result<()> {
+    fs::write(path, format!("{key}\n"))
+}
+
+fn read_key(path: &Path) -> anyhow::Result<[u8; 32]> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        anyhow!(CommandError::new(
+            ErrorKind::MissingDependency,
+            format!("Failed to read the key {path:?}: {err}")
+        ))
+    })?;
+    hex::decode(content.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(anyhow!(
+            "{path:?} does not contain a hexadecimal 32 byte key"
+        ))
+}
+
+/// Signs a component, replacing its previous signature. The signature is appended as the last custom section.
+pub fn sign(wasm: &[u8], signer: &dyn Signer) -> anyhow::Result<(Vec<u8>, ComponentSignature)> {
+    let (mut unsigned, _) = split_signature(wasm)?;
+    let digest = Sha256::digest(&unsigned);
+    let signature = ComponentSignature {
+        algorithm: signer.algorithm(),
+        key_id: signer.key_id(),
+        signature: hex::encode(signer.sign(&digest)?),
+        verified: false,
+    };
+
+    let mut content = Vec::new();
+    write_leb128(&mut content, SIGNATURE_SECTION.len());
+    content.extend_from_slice(SIGNATURE_SECTION.as_bytes());
+    content.extend_from_slice(&serde_json::to_vec(&signature)?);
+    write_section(&mut unsigned, CUSTOM_SECTION_ID, &content);
+    Ok((unsigned, signature))
+}
+
+/// Reads the signature of a component, checking it with the verifier if one is given
+pub fn read_signature(
+    wasm: &[u8],
+    verifier: Option<&dyn Verifier>,
+) -> anyhow::Result<Option<ComponentSignature>> {
+    let (unsigned, signature) = split_signature(wasm)?;
+    match (signature, verifier) {
+        (Some(mut signature), Some(verifier)) => {
+            verifier
+                .verify(&signature, &Sha256::digest(&unsigned))
+                .map_err(|err| {
+                    anyhow!(CommandError::new(
+                        ErrorKind::CheckFailed,
+                        format!("The signature of the component is not valid: {err:#}")
+                    ))
+                })?;
+            signature.verified = true;
+            Ok(Some(signature))
+        }
+        (None, Some(_)) => Err(anyhow!(CommandError::new(
+            ErrorKind::CheckFailed,
+            "The component is not signed"
+        ))),
+        (signature, None) => Ok(signature),
+    }
+}
+
+/// Splits a component to its content without the top level signature sections, and the last signature
+fn split_signature(wasm: &[u8]) -> anyhow::Result<(Vec<u8>, Option<ComponentSignature>)> {
+    let mut parser = Parser::new(0);
+    let mut unsigned = Vec::with_capacity(wasm.len());
+    let mut signature = None;
+    let mut offset = 0;
+    loop {
+        let (payload, consumed) = match parser.parse(&wasm[offset..], true)? {
+            Chunk::Parsed { payload, consumed } => (payload, consumed),
+            Chunk::NeedMoreData(_) => bail!("Unexpected end of the WASM file"),
+        };
+        match payload {
+            Payload::ModuleSection { range, .. } | Payload::ComponentSection { range, .. } => {
+                let end = offset + consumed + range.len();
+                unsigned.extend_from_slice(&wasm[offset..end]);
+                offset = end;
+            }
+            Payload::CustomSection(section) if section.name() == SIGNATURE_SECTION => {
+                signature = Some(
+                    serde_json::from_slice(section.data()).context("Invalid signature section")?,
+                );
+                offset += consumed;
+            }
+            Payload::End(_) => break,
+            _ => {
+                unsigned.extend_from_slice(&wasm[offset..offset + consumed]);
+                offset += consumed;
+            }
+        }
+    }
+    Ok((unsigned, signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signing::{
+        generate_key_pair, read_signature, sign, CommandSigner, CommandVerifier, Ed25519Signer,
+        Ed25519Verifier,
+    };
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    // An empty core module with a custom section
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0\0\x09\x04testabcd";
+
+    #[test]
+    fn signed_components_are_verified_with_the_public_key() {
+        let dir = TempDir::new("stubgen-test").unwrap();
+        let (secret, public) = (dir.path().join("key"), dir.path().join("key.pub"));
+        let key_id = generate_key_pair(&secret, &public).unwrap();
+        let signer = Ed25519Signer::from_file(&secret).unwrap();
+        let verifier = Ed25519Verifier::from_file(&public).unwrap();
+
+        let (signed, signature) = sign(MODULE, &signer).unwrap();
+        assert_eq!(signature.key_id, key_id);
+        assert!(signed.starts_with(MODULE));
+        wasmparser::validate(&signed).unwrap();
+
+        let verified = read_signature(&signed, Some(&verifier)).unwrap().unwrap();
+        assert!(verified.verified);
+        assert_eq!(read_signature(&signed, None).unwrap(), Some(signature));
+        assert_eq!(read_signature(MODULE, None).unwrap(), None);
+        assert!(read_signature(MODULE, Some(&verifier)).is_err());
+
+        // Re-signing replaces the signature instead of signing the previous one
+        let (resigned, _) = sign(&signed, &signer).unwrap();
+        assert_eq!(resigned, signed);
+
+        let mut tampered = signed.clone();
+        tampered[MODULE.len() - 1] = b'x';
+        assert!(read_signature(&tampered, Some(&verifier)).is_err());
+
+        let other = dir.path().join("other");
+        generate_key_pair(&other, &dir.path().join("other.pub")).unwrap();
+        let (other_signed, _) = sign(MODULE, &Ed25519Signer::from_file(&other).unwrap()).unwrap();
+        assert!(read_signature(&other_signed, Some(&verifier)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signing_can_be_delegated_to_programs() {
+        let signer = CommandSigner {
+            program: PathBuf::from("sh"),
+            args: vec![
+                "-c".to_string(),
+                "read digest; echo ${digest}00".to_string(),
+            ],
+            key_id: "kms-key".to_string(),
+        };
+        let verifier = CommandVerifier {
+            program: PathBuf::from("sh"),
+            args: vec![
+                "-c".to_string(),
+                "read digest; test \"${digest}00\" = \"$WASM_RPC_SIGNATURE\" && test \"$WASM_RPC_KEY_ID\" = kms-key"
+                    .to_string(),
+            ],
+        };
+
+        let (signed, signature) = sign(MODULE, &signer).unwrap();
+        assert_eq!(signature.algorithm, "external");
+        assert!(
+            read_signature(&signed, Some(&verifier))
+                .unwrap()
+                .unwrap()
+                .verified
+        );
+
+        let mut tampered = signed.clone();
+        tampered[MODULE.len() - 1] = b'x';
+        assert!(read_signature(&tampered, Some(&verifier)).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, bail, Context};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wit_parser::{SourceMap, UnresolvedPackage};
+
+/// The feature gate of a WIT item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gate {
+    /// The item is stable since the given version of its package
+    Since(Version),
+    /// The item is unstable, available with the given feature
+    Unstable(String),
+}
+
+/// The feature gates of the items of a WIT package, by their paths like `api`, `api.get` or `api.counter.inc`. The
+/// functions of a world are under the world's name, while the interfaces exported by a world are gated by their own
+/// names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stability {
+    gates: BTreeMap<String, Vec<Gate>>,
+}
+
+impl Stability {
+    /// Collects the gates of the items of an already parsed package from its source files
+    pub fn of_package(package: &UnresolvedPackage) -> anyhow::Result<Self> {
+        let mut stability = Stability::default();
+        for source in package.source_files() {
+            if is_wit_file(source) {
+                let (_, gates) = strip_gates(&fs::read_to_string(source)?)
+                    .context(format!("Failed to read the feature gates of {source:?}"))?;
+                stability.gates.extend(gates.gates);
+            }
+        }
+        Ok(stability)
+    }
+
+    pub fn is_unstable(&self, path: &str) -> bool {
+        self.gates(path)
+            .any(|gate| matches!(gate, Gate::Unstable(_)))
+    }
+
+    /// The latest version an item is stable since, by all its gates
+    pub fn since(&self, path: &str) -> Option<&Version> {
+        self.gates(path)
+            .filter_map(|gate| match gate {
+                Gate::Since(version) => Some(version),
+                Gate::Unstable(_) => None,
+            })
+            .max()
+    }
+
+    fn gates(&self, path: &str) -> impl Iterator<Item = &Gate> {
+        self.gates.get(path).into_iter().flatten()
+    }
+}
+
+/// Parses the WIT files of a directory as a package, like [UnresolvedPackage::parse_dir], supporting feature gates
+pub fn parse_wit_dir(path: &Path) -> anyhow::Result<UnresolvedPackage> {
+    let mut map = SourceMap::default();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).context(format!("failed to read directory {path:?}"))? {
+        let path = entry?.path();
+        if path.is_file() && (is_wit_file(&path) || is_wit_markdown_file(&path)) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    for file in files {
+        push_wit_file(&mut map, &file)?;
+    }
+    map.parse()
+}
+
+/// Parses a WIT file or directory as a package, like [UnresolvedPackage::parse_path], supporting feature gates
+pub fn parse_wit_path(path: &Path) -> anyhow::Result<UnresolvedPackage> {
+    if path.is_dir() {
+        parse_wit_dir(path)
+    } else {
+        let mut map = SourceMap::default();
+        push_wit_file(&mut map, path)?;
+        map.parse()
+    }
+}
+
+/// Adds a WIT file to a source map, with its feature gates removed
+pub fn push_wit_file(map: &mut SourceMap, path: &Path) -> anyhow::Result<()> {
+    if is_wit_file(path) {
+        let (stripped, _) = strip_gates(&fs::read_to_string(path)?)
+            .context(format!("Failed to read the feature gates of {path:?}"))?;
+        map.push(path, stripped);
+        Ok(())
+    } else {
+        map.push_file(path)
+    }
+}
+
+/// Copies a WIT file, with its feature gates removed
+pub fn copy_wit_file(source: &Path, dest: &PathBuf) -> anyhow::Result<()> {
+    if is_wit_file(source) {
+        let (stripped, _) = strip_gates(&fs::read_to_string(source)?)
+            .context(format!("Failed to read the feature gates of {source:?}"))?;
+        fs::write(dest, stripped)?;
+    } else {
+        fs::copy(source, dest)?;
+    }
+    Ok(())
+}
+
+fn is_wit_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "wit")
+}
+
+fn is_wit_markdown_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".wit.md"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Punct(u8),
+    Gate(Option<Gate>),
+}
+
+/// Removes the `@since`, `@unstable` and `@deprecated` annotations from a WIT source, which the WIT parser does not
+/// support, replacing them with spaces to keep the positions of the rest. Returns the gates of the annotated items.
+pub fn strip_gates(source: &str) -> anyhow::Result<(String, Stability)> {
+    let bytes = source.as_bytes();
+    let mut stripped = bytes.to_vec();
+    let mut tokens = Vec::new();
+
+    let is_word =
+        |byte: u8| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || byte == b'%';
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &source[i..];
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+        } else if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += rest.find("*/").map(|end| end + 2).unwrap_or(rest.len());
+        } else if bytes[i] == b'@' && bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic) {
+            let open = rest
+                .find('(')
+                .ok_or(anyhow!("annotation without arguments at offset {i}"))?;
+            let close = rest
+                .find(')')
+                .ok_or(anyhow!("unclosed annotation at offset {i}"))?;
+            let gate = parse_gate(rest[1..open].trim(), &rest[open + 1..close])?;
+            stripped[i..=i + close].fill(b' ');
+            tokens.push(Token::Gate(gate));
+            i += close + 1;
+        } else if is_word(bytes[i]) {
+            let end = rest
+                .find(|c: char| !c.is_ascii() || !is_word(c as u8))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].trim_start_matches('%')));
+            i += end;
+        } else {
+            tokens.push(Token::Punct(bytes[i]));
+            i += 1;
+        }
+    }
+
+    let mut stability = Stability::default();
+    let mut scopes: Vec<Option<&str>> = Vec::new();
+    let mut pending_scope = None;
+    let mut gates = Vec::new();
+    let mut record = |scopes: &[Option<&str>], name: &str, gates: &mut Vec<Gate>| {
+        if !gates.is_empty() {
+            let path = scopes
+                .iter()
+                .flatten()
+                .copied()
+                .chain([name])
+                .collect::<Vec<_>>()
+                .join(".");
+            stability.gates.entry(path).or_default().append(gates);
+        }
+    };
+    let mut k = 0;
+    while k < tokens.len() {
+        let next = tokens.get(k + 1);
+        match (&tokens[k], next) {
+            (Token::Gate(gate), _) => gates.extend(gate.clone()),
+            (Token::Word("interface" | "world" | "resource"), Some(Token::Word(name))) => {
+                pending_scope = Some(*name);
+                record(&scopes, name, &mut gates);
+                k += 1;
+            }
+            (
+                Token::Word("record" | "variant" | "enum" | "flags" | "type"),
+                Some(Token::Word(name)),
+            ) => {
+                record(&scopes, name, &mut gates);
+                k += 1;
+            }
+            (Token::Word("export" | "import"), Some(Token::Word(name))) => {
+                if tokens.get(k + 2) == Some(&Token::Punct(b':')) {
+                    if tokens.get(k + 3) == Some(&Token::Word("interface")) {
+                        pending_scope = Some(*name);
+                    }
+                    record(&scopes, name, &mut gates);
+                } else {
+                    // Exporting or importing an interface gates the interface itself
+                    record(&[], name, &mut gates);
+                }
+                k += 1;
+            }
+            (Token::Word("use" | "package" | "include"), _) => gates.clear(),
+            (Token::Word(name), Some(Token::Punct(b':'))) => record(&scopes, name, &mut gates),
+            (Token::Word("constructor"), _) => record(&scopes, "constructor", &mut gates),
+            (Token::Punct(b'{'), _) => scopes.push(pending_scope.take()),
+            (Token::Punct(b'}'), _) => {
+                scopes.pop();
+            }
+            (Token::Punct(b';'), _) => pending_scope = None,
+            _ => {}
+        }
+        k += 1;
+    }
+
+    let stripped =
+        String::from_utf8(stripped).map_err(|err| anyhow!("Invalid UTF-8 in WIT source: {err}"))?;
+    Ok((stripped, stability))
+}
+
+/// Parses the arguments of an annotation, returning the gate it defines, if any
+fn parse_gate(name: &str, args: &str) -> anyhow::Result<Option<Gate>> {
+    let mut values = BTreeMap::new();
+    for arg in args.split(',').filter(|arg| !arg.trim().is_empty()) {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or(anyhow!("invalid argument of @{name}: {arg}"))?;
+        values.insert(key.trim(), value.trim());
+    }
+    let value = |key: &str| {
+        values
+            .get(key)
+            .copied()
+            .ok_or(anyhow!("@{name} has no {key}"))
+    };
+    match name {
+        "since" => Ok(Some(Gate::Since(
+            Version::parse(value("version")?)
+                .map_err(|err| anyhow!("invalid version of @since: {err}"))?,
+        ))),
+        "unstable" => Ok(Some(Gate::Unstable(value("feature")?.to_string()))),
+        "deprecated" => Ok(None),
+        _ => bail!("unsupported annotation @{name}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stability::{strip_gates, Gate};
+    use semver::Version;
+
+    const GATED_WIT: &str = r#"
+package test:gated@0.2.0;
+
+/// The API
+@since(version = 0.1.0)
+interface api {
+  @since(version = 0.1.0)
+  get: func(name: string) -> u32;
+
+  @unstable(feature = bulk)
+  get-all: func() -> list<u32>;
+
+  @since(version = 0.2.0)
+  resource counter {
+    constructor(name: string);
+    @unstable(feature = reset) reset: func();
+  }
+}
+
+@unstable(feature = admin)
+interface admin {
+  wipe: func();
+}
+
+world gated {
+  @since(version = 0.2.0)
+  export api;
+  export admin;
+  @unstable(feature = ping)
+  export ping: func();
+}
+"#;
+
+    #[test]
+    fn gates_are_collected_by_item_paths() {
+        let (_, stability) = strip_gates(GATED_WIT).unwrap();
+
+        assert_eq!(stability.since("api"), Some(&Version::new(0, 2, 0)));
+        assert_eq!(stability.since("api.get"), Some(&Version::new(0, 1, 0)));
+        assert!(stability.is_unstable("api.get-all"));
+        assert!(!stability.is_unstable("api.get"));
+        assert_eq!(stability.since("api.counter"), Some(&Version::new(0, 2, 0)));
+        assert!(stability.is_unstable("api.counter.reset"));
+        assert!(stability.is_unstable("admin"));
+        assert!(stability.is_unstable("gated.ping"));
+        assert_eq!(
+            stability.gates.get("api.get-all"),
+            Some(&vec![Gate::Unstable("bulk".to_string())])
+        );
+    }
+
+    #[test]
+    fn stripping_keeps_the_positions_of_the_items() {
+        let (stripped, _) = strip_gates(GATED_WIT).unwrap();
+
+        assert_eq!(stripped.len(), GATED_WIT.len());
+        assert!(!stripped.contains("@since") && !stripped.contains("@unstable"));
+        assert_eq!(
+            stripped.find("get-all: func"),
+            GATED_WIT.find("get-all: func")
+        );
+        wit_parser::UnresolvedPackage::parse("gated.wit".as_ref(), &stripped).unwrap();
+    }
+}
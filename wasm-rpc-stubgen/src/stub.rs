@@ -12,20 +12,75 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::cargo::{SharedTypes, StubCrateDependencies};
+use crate::compat::{downgrade, Downgrade};
+use crate::stability::{parse_wit_dir, parse_wit_path, push_wit_file, Stability};
 use anyhow::{anyhow, bail};
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
+use semver::Version;
+use serde::Deserialize;
 use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use wit_parser::{
-    Function, FunctionKind, PackageName, Resolve, Results, Type, TypeDefKind, TypeId, TypeOwner,
-    UnresolvedPackage, World, WorldId, WorldItem,
+    Function, FunctionKind, InterfaceId, PackageName, Resolve, Results, SourceMap, Type,
+    TypeDefKind, TypeId, TypeOwner, UnresolvedPackage, World, WorldId, WorldItem,
 };
 
+/// Determines the version of the generated stub WIT package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StubPackageVersion {
+    /// The stub package has the same version as the source package
+    Same,
+    /// The stub package has no version
+    Unversioned,
+    /// The stub package has the given version, regardless of the source package's version
+    Fixed(Version),
+}
+
+impl StubPackageVersion {
+    pub fn derive(&self, source_version: &Option<Version>) -> Option<Version> {
+        match self {
+            StubPackageVersion::Same => source_version.clone(),
+            StubPackageVersion::Unversioned => None,
+            StubPackageVersion::Fixed(version) => Some(version.clone()),
+        }
+    }
+}
+
+impl FromStr for StubPackageVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "same" => Ok(StubPackageVersion::Same),
+            "none" => Ok(StubPackageVersion::Unversioned),
+            _ => Version::parse(s)
+                .map(StubPackageVersion::Fixed)
+                .map_err(|err| {
+                    format!("expected `same`, `none` or a semantic version, got {s}: {err}")
+                }),
+        }
+    }
+}
+
+impl Display for StubPackageVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StubPackageVersion::Same => write!(f, "same"),
+            StubPackageVersion::Unversioned => write!(f, "none"),
+            StubPackageVersion::Fixed(version) => write!(f, "{version}"),
+        }
+    }
+}
+
 /// All the gathered information for generating the stub crate.
 pub struct StubDefinition {
     pub resolve: Resolve,
     pub root_package_name: PackageName,
+    pub stub_package_version: Option<Version>,
     pub world_id: WorldId,
     pub source_wit_root: PathBuf,
     pub target_root: PathBuf,
@@ -33,18 +88,30 @@ pub struct StubDefinition {
     pub interfaces: Vec<InterfaceStub>,
     pub unresolved_root: UnresolvedPackage,
     pub unresolved_deps: Vec<UnresolvedPackage>,
-    pub wasm_rpc_path_override: Option<String>,
+    pub dependencies: StubCrateDependencies,
+    /// Whether the stubs pass the metadata set by their `set-metadata` method with every invocation
+    pub metadata: bool,
+    /// The older version of the target, the stubs down-convert their calls to after their `set-target-version` method
+    /// is called with its version
+    pub older_target: Option<Box<StubDefinition>>,
+    /// How the stub functions surface the results of the target functions, by `<resource>.<function>` as named in
+    /// the stub WIT
+    pub conventions: BTreeMap<String, FunctionConvention>,
+    /// The feature gates of the items of the source package
+    pub stability: Stability,
 }
 
 impl StubDefinition {
     pub fn new(
         source_wit_root: &Path,
         target_root: &Path,
+        selected_package: &Option<String>,
         selected_world: &Option<String>,
         stub_crate_version: &str,
-        wasm_rpc_path_override: &Option<String>,
+        stub_package_version: &StubPackageVersion,
+        dependencies: &StubCrateDependencies,
     ) -> anyhow::Result<Self> {
-        let (root, deps) = get_unresolved_packages(source_wit_root)?;
+        let (root, deps) = get_unresolved_packages(source_wit_root, selected_package)?;
         let root_package = root.name.clone();
 
         let mut resolve = Resolve::new();
@@ -60,9 +127,13 @@ impl StubDefinition {
             .ok_or(anyhow!("world {world_id:?} not found"))?;
         let interfaces = collect_stub_interfaces(&resolve, world)?;
 
+        let stub_package_version = stub_package_version.derive(&root_package.version);
+        let stability = Stability::of_package(&root)?;
+
         Ok(Self {
             resolve,
             root_package_name: root_package,
+            stub_package_version,
             world_id,
             source_wit_root: source_wit_root.to_path_buf(),
             target_root: target_root.to_path_buf(),
@@ -70,10 +141,150 @@ impl StubDefinition {
             interfaces,
             unresolved_root: root,
             unresolved_deps: deps,
-            wasm_rpc_path_override: wasm_rpc_path_override.clone(),
+            dependencies: dependencies.clone(),
+            metadata: false,
+            older_target: None,
+            conventions: BTreeMap::new(),
+            stability,
         })
     }
 
+    /// Removes the interfaces and functions marked `@unstable` in the source package from the stub
+    pub fn exclude_unstable(&mut self) {
+        let stability = &self.stability;
+        self.interfaces.retain(|interface| {
+            !stability.is_unstable(&interface.gate_path())
+                && !interface
+                    .owner_interface
+                    .as_ref()
+                    .is_some_and(|owner| stability.is_unstable(owner))
+        });
+        for interface in &mut self.interfaces {
+            let path = interface.gate_path();
+            let is_stable = |function: &FunctionStub| {
+                !stability.is_unstable(&format!("{path}.{}", function.name))
+            };
+            interface.functions.retain(is_stable);
+            interface.static_functions.retain(is_stable);
+        }
+    }
+
+    /// The minimum version of the target the stub works with, the latest `@since` version of the items it calls
+    pub fn min_target_version(&self) -> Option<Version> {
+        self.interfaces
+            .iter()
+            .flat_map(|interface| {
+                let path = interface.gate_path();
+                let functions = interface
+                    .functions
+                    .iter()
+                    .chain(&interface.static_functions)
+                    .map(|function| format!("{path}.{}", function.name))
+                    .collect::<Vec<_>>();
+                interface
+                    .owner_interface
+                    .clone()
+                    .into_iter()
+                    .chain([path])
+                    .chain(functions)
+            })
+            .filter_map(|path| self.stability.since(&path).cloned())
+            .max()
+    }
+
+    /// The convention of a function of the stub of an interface
+    pub fn convention(
+        &self,
+        interface: &InterfaceStub,
+        function: &FunctionStub,
+    ) -> FunctionConvention {
+        self.conventions
+            .get(&format!("{}.{}", interface.name, function.name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether any of the stub functions return the RPC failures as errors
+    pub fn returns_rpc_errors(&self) -> bool {
+        self.conventions
+            .values()
+            .any(|convention| convention.rpc_errors == RpcErrors::Result)
+    }
+
+    /// Checks that all the configured conventions belong to stub functions, and can be applied to their results
+    pub fn check_conventions(&self) -> anyhow::Result<()> {
+        for (name, convention) in &self.conventions {
+            let function = self
+                .interfaces
+                .iter()
+                .flat_map(|interface| {
+                    interface
+                        .functions
+                        .iter()
+                        .chain(&interface.static_functions)
+                        .map(move |function| (interface, function))
+                })
+                .find(|(interface, function)| {
+                    format!("{}.{}", interface.name, function.name) == *name
+                })
+                .map(|(_, function)| function)
+                .ok_or(anyhow!(
+                    "The convention of {name} does not belong to a stub function"
+                ))?;
+            let flattened = convention
+                .flatten(&self.resolve, &function.results)
+                .map_err(|err| anyhow!("The convention of {name} cannot be applied: {err}"))?;
+            if convention.rpc_errors == RpcErrors::Result
+                && flattened.is_none()
+                && matches!(&function.results, FunctionResultStub::Multi(results) if !results.is_empty())
+            {
+                bail!("The convention of {name} cannot be applied: named results cannot be returned with the RPC errors");
+            }
+        }
+        Ok(())
+    }
+
+    /// The package version of the older target, if the stubs are generated with one
+    pub fn older_target_version(&self) -> Option<String> {
+        let older = self.older_target.as_ref()?;
+        older
+            .root_package_name
+            .version
+            .as_ref()
+            .map(|version| version.to_string())
+    }
+
+    /// How the calls of a function of an interface are passed to the older target, if the stubs are generated with
+    /// one
+    pub fn downgrade(
+        &self,
+        interface: &InterfaceStub,
+        function: &FunctionStub,
+        is_static: bool,
+    ) -> Option<Downgrade> {
+        let older = self.older_target.as_ref()?;
+        let older_function = older
+            .interfaces
+            .iter()
+            .find(|older| {
+                older.name == interface.name && older.owner_interface == interface.owner_interface
+            })
+            .and_then(|older| {
+                let functions = if is_static {
+                    &older.static_functions
+                } else {
+                    &older.functions
+                };
+                functions.iter().find(|older| older.name == function.name)
+            });
+        Some(downgrade(
+            &self.resolve,
+            function,
+            &older.resolve,
+            older_function,
+        ))
+    }
+
     pub fn source_world(&self) -> anyhow::Result<&World> {
         self.resolve
             .worlds
@@ -85,6 +296,14 @@ impl StubDefinition {
         Ok(self.source_world()?.name.clone())
     }
 
+    pub fn stub_package_name(&self) -> PackageName {
+        PackageName {
+            namespace: self.root_package_name.namespace.clone(),
+            name: format!("{}-stub", self.root_package_name.name),
+            version: self.stub_package_version.clone(),
+        }
+    }
+
     pub fn target_cargo_path(&self) -> PathBuf {
         self.target_root.join("Cargo.toml")
     }
@@ -97,10 +316,87 @@ impl StubDefinition {
         self.target_root.join("src/lib.rs")
     }
 
+    pub fn target_integration_test_path(&self) -> PathBuf {
+        self.target_root.join("tests/stub_integration.rs")
+    }
+
     pub fn target_world_name(&self) -> anyhow::Result<String> {
         Ok(format!("wasm-rpc-stub-{}", self.source_world_name()?))
     }
 
+    /// Whether the package is defined directly in the source WIT root (like the selected root package)
+    /// instead of in its `deps` directory
+    pub fn is_source_root_package(&self, pkg: &UnresolvedPackage) -> bool {
+        pkg.source_files()
+            .all(|source| source.parent() == Some(self.source_wit_root.as_path()))
+    }
+
+    /// The Cargo features of the stub crate, one for each exported interface of the source world
+    pub fn interface_features(&self) -> IndexSet<String> {
+        self.interfaces
+            .iter()
+            .map(|interface| interface.feature_name())
+            .collect()
+    }
+
+    /// The Rust module of another crate providing the types of an interface, configured by its name with or without
+    /// the version of its package
+    pub fn shared_types_module(&self, interface_id: InterfaceId) -> Option<&str> {
+        self.mapped_module(interface_id, &self.dependencies.shared_types)
+    }
+
+    /// The Rust module of another crate having the types of an interface the stub's types are converted from and to
+    pub fn converted_types_module(&self, interface_id: InterfaceId) -> Option<&str> {
+        self.mapped_module(interface_id, &self.dependencies.converted_types)
+    }
+
+    /// The interfaces configured to have their types converted, with the Rust module of the other crate's types
+    pub fn converted_interfaces(&self) -> anyhow::Result<Vec<(InterfaceId, &str)>> {
+        self.dependencies
+            .converted_types
+            .iter()
+            .map(|converted| {
+                self.resolve
+                    .interfaces
+                    .iter()
+                    .find(|(id, _)| self.has_interface_name(*id, &converted.interface))
+                    .map(|(id, _)| (id, converted.module.as_str()))
+                    .ok_or(anyhow!(
+                        "Interface {} to convert the types of is not found",
+                        converted.interface
+                    ))
+            })
+            .collect()
+    }
+
+    fn mapped_module<'a>(
+        &self,
+        interface_id: InterfaceId,
+        mappings: &'a [SharedTypes],
+    ) -> Option<&'a str> {
+        mappings
+            .iter()
+            .find(|mapping| self.has_interface_name(interface_id, &mapping.interface))
+            .map(|mapping| mapping.module.as_str())
+    }
+
+    /// Whether the name of an interface is the given one, with or without the version of its package
+    fn has_interface_name(&self, interface_id: InterfaceId, name: &str) -> bool {
+        self.unversioned_interface_name(interface_id).as_deref() == Some(name)
+            || self.resolve.id_of(interface_id).as_deref() == Some(name)
+    }
+
+    fn unversioned_interface_name(&self, interface_id: InterfaceId) -> Option<String> {
+        let interface = self.resolve.interfaces.get(interface_id)?;
+        let package = self.resolve.packages.get(interface.package?)?;
+        Some(format!(
+            "{}:{}/{}",
+            package.name.namespace,
+            package.name.name,
+            interface.name.as_ref()?
+        ))
+    }
+
     pub fn target_wit_root(&self) -> PathBuf {
         self.target_root.join("wit")
     }
@@ -110,7 +406,7 @@ impl StubDefinition {
     }
 
     pub fn verify_target_wits(&self) -> anyhow::Result<()> {
-        let (final_root, final_deps) = get_unresolved_packages(&self.target_wit_root())?;
+        let (final_root, final_deps) = get_unresolved_packages(&self.target_wit_root(), &None)?;
 
         let mut final_resolve = Resolve::new();
         for unresolved in final_deps.iter().cloned() {
@@ -121,10 +417,21 @@ impl StubDefinition {
     }
 }
 
+/// The name of the static function creating a stub for a worker, identified by its component id and name
+pub const WORKER_CONSTRUCTOR: &str = "for-worker";
+
+/// The name of the method setting the metadata passed with the invocations of a stub
+pub const METADATA_SETTER: &str = "set-metadata";
+
+/// The name of the method setting the version of the target of a stub generated with an older target
+pub const TARGET_VERSION_SETTER: &str = "set-target-version";
+
 #[derive(Debug, Clone)]
 pub struct InterfaceStub {
     pub name: String,
+    pub docs: Option<String>,
     pub constructor_params: Option<Vec<FunctionParamStub>>,
+    pub constructor_docs: Option<String>,
     pub functions: Vec<FunctionStub>,
     pub static_functions: Vec<FunctionStub>,
     pub imports: Vec<InterfaceStubImport>,
@@ -133,9 +440,45 @@ pub struct InterfaceStub {
 }
 
 impl InterfaceStub {
+    /// The path of the interface or resource in the feature gates of the source package
+    pub fn gate_path(&self) -> String {
+        match &self.owner_interface {
+            Some(owner) => format!("{owner}.{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
     pub fn is_resource(&self) -> bool {
         self.constructor_params.is_some()
     }
+
+    /// Whether the stub of this interface gets a `for-worker` static function, creating the stub from the component id
+    /// and the name of the target worker. Resources are always created through their constructors, and interfaces
+    /// defining their own `for-worker` function keep it.
+    pub fn has_worker_constructor(&self) -> bool {
+        !self.is_resource()
+            && !self
+                .functions
+                .iter()
+                .any(|function| function.name == WORKER_CONSTRUCTOR)
+    }
+
+    /// Whether the interface defines a function with the given name. The generated methods of the stubs, like
+    /// `set-metadata`, are skipped for the interfaces defining their own function with the same name.
+    pub fn defines_function(&self, name: &str) -> bool {
+        self.functions
+            .iter()
+            .chain(&self.static_functions)
+            .any(|function| function.name == name)
+    }
+
+    /// The Cargo feature of the stub crate enabling the calls to this interface. Resources belong to the
+    /// feature of the interface defining them. The features are prefixed, so interfaces named like the features
+    /// Cargo reserves, such as `default`, do not clash with them.
+    pub fn feature_name(&self) -> String {
+        let interface = self.owner_interface.as_ref().unwrap_or(&self.name);
+        format!("iface-{interface}")
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -147,6 +490,7 @@ pub struct InterfaceStubImport {
 #[derive(Debug, Clone)]
 pub struct FunctionStub {
     pub name: String,
+    pub docs: Option<String>,
     pub params: Vec<FunctionParamStub>,
     pub results: FunctionResultStub,
 }
@@ -160,6 +504,7 @@ impl FunctionStub {
             } else {
                 Some(FunctionStub {
                     name: parts[1].to_string(),
+                    docs: self.docs.clone(),
                     params: self
                         .params
                         .iter()
@@ -180,6 +525,7 @@ impl FunctionStub {
             } else {
                 Some(FunctionStub {
                     name: parts[1].to_string(),
+                    docs: self.docs.clone(),
                     params: self
                         .params
                         .iter()
@@ -216,6 +562,106 @@ impl FunctionResultStub {
     }
 }
 
+/// How a stub function surfaces the results of the target function, configured per function
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FunctionConvention {
+    /// Return the ok value of the returned `result`, panicking on its error
+    #[serde(default)]
+    pub flatten_result: bool,
+    /// Return the value of the returned `option`, panicking on `none`. Applied after `flatten_result`.
+    #[serde(default)]
+    pub flatten_option: bool,
+    /// How the failures of the invocations are surfaced
+    #[serde(default)]
+    pub rpc_errors: RpcErrors,
+}
+
+/// How a stub function surfaces the failures of its invocations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RpcErrors {
+    /// Panic with the error
+    #[default]
+    Panic,
+    /// Return a `result` with the `rpc-error` of the failure
+    Result,
+}
+
+/// A step of flattening the result of a target function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flatten {
+    Result,
+    Option,
+}
+
+/// The result of a target function as surfaced by a stub function flattening it
+#[derive(Debug, Clone)]
+pub struct FlattenedResult {
+    pub steps: Vec<Flatten>,
+    /// The flattened type, or none if the stub function has no result
+    pub typ: Option<Type>,
+}
+
+impl FunctionConvention {
+    /// How the results of a function are flattened, or none if they are returned as they are
+    pub fn flatten(
+        &self,
+        resolve: &Resolve,
+        results: &FunctionResultStub,
+    ) -> anyhow::Result<Option<FlattenedResult>> {
+        if !self.flatten_result && !self.flatten_option {
+            return Ok(None);
+        }
+        let FunctionResultStub::Single(typ) = results else {
+            bail!("only a single result can be flattened");
+        };
+
+        let mut flattened = FlattenedResult {
+            steps: Vec::new(),
+            typ: Some(*typ),
+        };
+        if self.flatten_result {
+            match flattened
+                .typ
+                .as_ref()
+                .and_then(|typ| type_kind(resolve, typ))
+            {
+                Some(TypeDefKind::Result(result)) => {
+                    flattened.steps.push(Flatten::Result);
+                    flattened.typ = result.ok;
+                }
+                _ => bail!("the function does not return a result"),
+            }
+        }
+        if self.flatten_option {
+            match flattened
+                .typ
+                .as_ref()
+                .and_then(|typ| type_kind(resolve, typ))
+            {
+                Some(TypeDefKind::Option(inner)) => {
+                    flattened.steps.push(Flatten::Option);
+                    flattened.typ = Some(*inner);
+                }
+                _ => bail!("the function does not return an option"),
+            }
+        }
+        Ok(Some(flattened))
+    }
+}
+
+/// The kind of a defined type, looking through the type aliases
+fn type_kind<'a>(resolve: &'a Resolve, typ: &Type) -> Option<&'a TypeDefKind> {
+    match typ {
+        Type::Id(id) => match &resolve.types[*id].kind {
+            TypeDefKind::Type(typ) => type_kind(resolve, typ),
+            kind => Some(kind),
+        },
+        _ => None,
+    }
+}
+
 fn collect_stub_imports<'a>(
     types: impl Iterator<Item = (&'a String, &'a TypeId)>,
     resolve: &Resolve,
@@ -298,10 +744,12 @@ fn collect_stub_interfaces(resolve: &Resolve, world: &World) -> anyhow::Result<V
 
             interfaces.push(InterfaceStub {
                 name,
+                docs: interface.docs.contents.clone(),
                 functions,
                 imports,
                 global: false,
                 constructor_params: None,
+                constructor_docs: None,
                 static_functions: vec![],
                 owner_interface: None,
             });
@@ -313,6 +761,7 @@ fn collect_stub_interfaces(resolve: &Resolve, world: &World) -> anyhow::Result<V
     if !top_level_functions.is_empty() {
         interfaces.push(InterfaceStub {
             name: world.name.clone(),
+            docs: world.docs.contents.clone(),
             functions: collect_stub_functions(
                 top_level_functions
                     .into_iter()
@@ -321,6 +770,7 @@ fn collect_stub_interfaces(resolve: &Resolve, world: &World) -> anyhow::Result<V
             imports: collect_stub_imports(top_level_types.iter().map(|(k, v)| (k, *v)), resolve)?,
             global: true,
             constructor_params: None,
+            constructor_docs: None,
             static_functions: vec![],
             owner_interface: None,
         });
@@ -358,6 +808,7 @@ fn collect_stub_functions<'a>(
 
             FunctionStub {
                 name: f.name.clone(),
+                docs: f.docs.contents.clone(),
                 params,
                 results,
             }
@@ -434,12 +885,17 @@ fn collect_stub_resources<'a>(
                         .ok_or(anyhow!("Resource type has no name"))?
                         .clone();
 
+                    let constructor_docs =
+                        constructors.first().and_then(|c| c.docs.contents.clone());
+
                     interfaces.push(InterfaceStub {
                         name: resource_name,
+                        docs: typ.docs.contents.clone(),
                         functions,
                         imports,
                         global: false,
                         constructor_params,
+                        constructor_docs,
                         static_functions,
                         owner_interface: Some(owner_interface.to_string()),
                     });
@@ -479,15 +935,22 @@ fn visit<'a>(
 // Copied and modified from `wit-parser` crate
 fn get_unresolved_packages(
     root_path: &Path,
+    selected_package: &Option<String>,
 ) -> anyhow::Result<(UnresolvedPackage, Vec<UnresolvedPackage>)> {
-    let root = UnresolvedPackage::parse_dir(root_path)?;
+    let mut root_packages = parse_root_packages(root_path)?;
+    let root_index = select_root_package(&root_packages, selected_package)?;
+    let root = root_packages.remove(root_index);
 
     let mut deps = BTreeMap::new();
+    // The other top-level packages of the root are available as dependencies of the selected one
+    for pkg in root_packages {
+        deps.insert(pkg.name.clone(), pkg);
+    }
     let deps_path = root_path.join(Path::new("deps"));
     if deps_path.exists() {
         for dep_entry in fs::read_dir(deps_path)? {
             let dep_entry = dep_entry?;
-            let dep = UnresolvedPackage::parse_path(&dep_entry.path())?;
+            let dep = parse_wit_path(&dep_entry.path())?;
             deps.insert(dep.name.clone(), dep);
         }
     }
@@ -510,3 +973,120 @@ fn get_unresolved_packages(
 
     Ok((root, ordered_deps))
 }
+
+/// Parses the top-level WIT files of a root directory, which may define more than one package
+pub(crate) fn parse_root_packages(root_path: &Path) -> anyhow::Result<Vec<UnresolvedPackage>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(root_path)? {
+        let path = entry?.path();
+        let is_wit = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.ends_with(".wit") || name.ends_with(".wit.md"))
+            .unwrap_or(false);
+        if is_wit && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let mut groups = IndexMap::<Option<String>, Vec<PathBuf>>::new();
+    for file in files {
+        let name = declared_package_name(&fs::read_to_string(&file)?);
+        groups.entry(name).or_default().push(file);
+    }
+
+    if let Some(unnamed) = groups.shift_remove(&None) {
+        let package_count = groups.len();
+        match groups.values_mut().next() {
+            Some(files) if package_count == 1 => files.extend(unnamed),
+            None => {
+                groups.insert(None, unnamed);
+            }
+            Some(_) => bail!(
+                "Files {} in {root_path:?} do not declare a package, but the root contains multiple packages",
+                unnamed
+                    .iter()
+                    .map(|file| format!("{file:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    if groups.len() <= 1 {
+        return Ok(vec![parse_wit_dir(root_path)?]);
+    }
+
+    groups
+        .into_values()
+        .map(|files| {
+            let mut map = SourceMap::new();
+            for file in files {
+                push_wit_file(&mut map, &file)?;
+            }
+            map.parse()
+        })
+        .collect()
+}
+
+pub(crate) fn select_root_package(
+    packages: &[UnresolvedPackage],
+    selected_package: &Option<String>,
+) -> anyhow::Result<usize> {
+    let available = || {
+        packages
+            .iter()
+            .map(|pkg| pkg.name.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match selected_package {
+        Some(selected) => packages
+            .iter()
+            .position(|pkg| {
+                pkg.name.to_string() == *selected
+                    || format!("{}:{}", pkg.name.namespace, pkg.name.name) == *selected
+            })
+            .ok_or(anyhow!(
+                "Package {selected} not found in the source WIT root, available packages: {}",
+                available()
+            )),
+        None if packages.len() == 1 => Ok(0),
+        None => bail!(
+            "The source WIT root contains multiple packages ({}), select one with --package",
+            available()
+        ),
+    }
+}
+
+/// Extracts the name from a WIT file's `package` statement without fully parsing the file
+fn declared_package_name(contents: &str) -> Option<String> {
+    let mut in_block_comment = false;
+    for line in contents.lines() {
+        let mut line = line.trim();
+        if in_block_comment {
+            match line.find("*/") {
+                Some(end) => {
+                    in_block_comment = false;
+                    line = line[end + 2..].trim();
+                }
+                None => continue,
+            }
+        }
+        if line.starts_with("/*") {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        return line
+            .strip_prefix("package ")
+            .and_then(|rest| rest.split(';').next())
+            .map(|name| name.trim().to_string());
+    }
+    None
+}
@@ -0,0 +1,143 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::{CommandError, ErrorKind};
+use anyhow::{anyhow, bail, Context};
+use semver::Version;
+use std::process::Command;
+use tracing::{debug, info};
+
+/// The crate the stub generator's binaries are installed from
+pub const CRATE_NAME: &str = "golem-wasm-rpc-stubgen";
+
+/// Setting this environment variable to `1` makes every command check for a newer release
+pub const UPDATE_CHECK_ENV_VAR: &str = "WASM_RPC_STUBGEN_UPDATE_CHECK";
+
+/// The version of the running binary. Local builds have the version `0.0.0`, the released ones are versioned by CI.
+pub fn current_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("Invalid package version")
+}
+
+/// The latest version of the stub generator published to crates.io
+pub fn latest_version() -> anyhow::Result<Version> {
+    // Releases are looked up and installed with cargo, which already knows the configured proxy and registry settings
+    let output = Command::new("cargo")
+        .args(["search", CRATE_NAME, "--limit", "1"])
+        .output()
+        .map_err(|err| {
+            anyhow!(CommandError::new(
+                ErrorKind::MissingTools,
+                format!("Failed to run cargo: {err}")
+            ))
+        })?;
+    if !output.status.success() {
+        bail!(
+            "cargo search failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_search_output(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| anyhow!("{CRATE_NAME} was not found on crates.io"))
+}
+
+/// Replaces the installed binaries with the given version, using `cargo install`
+pub fn install(version: &Version) -> anyhow::Result<()> {
+    let status = Command::new("cargo")
+        .args(["install", CRATE_NAME, "--locked", "--force", "--version"])
+        .arg(version.to_string())
+        .status()
+        .map_err(|err| {
+            anyhow!(CommandError::new(
+                ErrorKind::MissingTools,
+                format!("Failed to run cargo: {err}")
+            ))
+        })?;
+    if !status.success() {
+        bail!("cargo install {CRATE_NAME}@{version} failed with {status}");
+    }
+    Ok(())
+}
+
+/// Whether `self-update` installs the target version over the current one. The latest release is only installed if it
+/// is newer, so pre-release and development builds are not downgraded, while an explicitly requested version is
+/// installed unless it is the current one.
+pub fn should_install(current: &Version, target: &Version, explicit: bool) -> bool {
+    if explicit {
+        target != current
+    } else {
+        target > current
+    }
+}
+
+/// Logs a notice when a newer release is available, if enabled by the [UPDATE_CHECK_ENV_VAR] environment variable.
+/// Failing to check is not an error, as the notice is only a courtesy.
+pub fn notify_newer_version() {
+    if std::env::var(UPDATE_CHECK_ENV_VAR).as_deref() != Ok("1") {
+        return;
+    }
+    let current = current_version();
+    if current == Version::new(0, 0, 0) {
+        debug!("Skipping the update check of a local build");
+        return;
+    }
+    match latest_version().context("Failed to check for a newer version") {
+        Ok(latest) if latest > current => info!(
+            "A newer version of wasm-rpc-stubgen is available: {current} -> {latest}. Run `wasm-rpc-stubgen self-update` to install it."
+        ),
+        Ok(_) => {}
+        Err(err) => debug!("{err:#}"),
+    }
+}
+
+/// Finds the version of the crate in the output of `cargo search`, which lists matches like
+/// `golem-wasm-rpc-stubgen = "0.0.26"    # Golem WASM RPC stub generator`
+fn parse_search_output(output: &str) -> Option<Version> {
+    output.lines().find_map(|line| {
+        let (name, rest) = line.split_once('=')?;
+        if name.trim() != CRATE_NAME {
+            return None;
+        }
+        let version = rest.trim().strip_prefix('"')?.split('"').next()?;
+        Version::parse(version).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::update::{parse_search_output, should_install};
+    use semver::Version;
+
+    #[test]
+    fn version_is_found_in_search_output() {
+        let output = "golem-wasm-rpc-stubgen = \"0.0.26\"    # Golem WASM RPC stub generator\n\
+                      ... and 3 crates more (use --limit N to see more)\n";
+        assert_eq!(parse_search_output(output), Some(Version::new(0, 0, 26)));
+
+        let output = "golem-wasm-rpc = \"0.0.26\"    # Golem WASM RPC support library\n";
+        assert_eq!(parse_search_output(output), None);
+    }
+
+    #[test]
+    fn only_newer_releases_are_installed_unless_requested() {
+        let current = Version::parse("0.0.27-rc.1").unwrap();
+        let latest = Version::new(0, 0, 26);
+
+        assert!(!should_install(&current, &latest, false));
+        assert!(!should_install(&current, &current, false));
+        assert!(should_install(&current, &Version::new(0, 0, 27), false));
+        assert!(should_install(&current, &latest, true));
+        assert!(!should_install(&current, &current, true));
+    }
+}
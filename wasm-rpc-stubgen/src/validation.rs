@@ -0,0 +1,813 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::stability::parse_wit_path;
+use crate::stub::{parse_root_packages, select_root_package};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wit_parser::{
+    Function, PackageName, Resolve, Results, Type, TypeDefKind, TypeId, TypeOwner,
+    UnresolvedPackage, WorldItem,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in a WIT root, with a suggestion on how to fix it
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub message: String,
+    pub fix: String,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        writeln!(f, "{severity}: {}", self.message)?;
+        write!(f, "  fix: {}", self.fix)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn error_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+            .count()
+    }
+
+    fn error(&mut self, message: impl Into<String>, fix: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            severity: Severity::Error,
+            message: message.into(),
+            fix: fix.into(),
+        });
+    }
+
+    fn warning(&mut self, message: impl Into<String>, fix: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            severity: Severity::Warning,
+            message: message.into(),
+            fix: fix.into(),
+        });
+    }
+}
+
+/// Checks whether a WIT root can be used as the source of stub generation, collecting all the
+/// problems instead of stopping at the first one.
+pub fn validate_wit_root(
+    wit_root: &Path,
+    selected_package: &Option<String>,
+    selected_world: &Option<String>,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    if !wit_root.is_dir() {
+        report.error(
+            format!("WIT root {wit_root:?} does not exist or is not a directory"),
+            "point --wit-root to the directory containing the component's WIT files",
+        );
+        return report;
+    }
+
+    let mut root_packages = match parse_root_packages(wit_root) {
+        Ok(root_packages) => root_packages,
+        Err(err) => {
+            report.error(
+                format!("failed to parse the root package in {wit_root:?}: {err:#}"),
+                "fix the syntax error above; the .wit files directly in the WIT root without a package declaration must belong to its only package",
+            );
+            return report;
+        }
+    };
+    let root = match select_root_package(&root_packages, selected_package) {
+        Ok(index) => root_packages.remove(index),
+        Err(err) => {
+            report.error(
+                format!("{err:#}"),
+                "select the package to generate the stub for with --package",
+            );
+            return report;
+        }
+    };
+
+    let mut deps = parse_deps(wit_root, &root.name, &mut report);
+    // The other top-level packages of the root are available as dependencies of the selected one
+    for pkg in root_packages {
+        if let Some((existing, _)) = deps.get(&pkg.name) {
+            report.error(
+                format!(
+                    "package {} is defined by both {existing:?} and the WIT root",
+                    pkg.name
+                ),
+                format!("remove {existing:?}"),
+            );
+        } else {
+            deps.insert(pkg.name.clone(), (wit_root.to_path_buf(), pkg));
+        }
+    }
+
+    for pkg in deps.values().map(|(_, pkg)| pkg).chain([&root]) {
+        for dep in pkg.foreign_deps.keys() {
+            if dep != &root.name && !deps.contains_key(dep) {
+                report.error(
+                    format!(
+                        "package {} depends on {dep}, which is not found in the deps directory",
+                        pkg.name
+                    ),
+                    format!(
+                        "copy the WIT files of {dep} into {:?}",
+                        wit_root
+                            .join("deps")
+                            .join(format!("{}_{}", dep.namespace, dep.name))
+                    ),
+                );
+            }
+        }
+    }
+
+    if report.error_count() > 0 {
+        return report;
+    }
+
+    let order = match dependency_order(&root, &deps) {
+        Ok(order) => order,
+        Err(cycle) => {
+            report.error(
+                format!("circular dependency between WIT packages involving {cycle}"),
+                "break the cycle by moving the shared types into a separate package",
+            );
+            return report;
+        }
+    };
+
+    let mut resolve = Resolve::new();
+    for name in order {
+        if let Some((path, pkg)) = deps.get(&name) {
+            if let Err(err) = resolve.push(pkg.clone()) {
+                report.error(
+                    format!("failed to resolve package {name} in {path:?}: {err:#}"),
+                    "make sure the package is compatible with the versions of its dependencies found in the deps directory",
+                );
+                return report;
+            }
+        }
+    }
+    let root_id = match resolve.push(root.clone()) {
+        Ok(id) => id,
+        Err(err) => {
+            report.error(
+                format!("failed to resolve the root package {}: {err:#}", root.name),
+                "check that every `use` and `import` refers to an existing interface and type",
+            );
+            return report;
+        }
+    };
+
+    let worlds = &resolve.packages[root_id].worlds;
+    if worlds.is_empty() {
+        report.error(
+            format!("package {} does not define any worlds", root.name),
+            "add a world exporting the interfaces to be called via RPC",
+        );
+        return report;
+    }
+
+    let world_id = match selected_world {
+        Some(name) => match worlds.get(name) {
+            Some(id) => *id,
+            None => {
+                report.error(
+                    format!("world {name} not found in package {}", root.name),
+                    format!(
+                        "use one of the available worlds: {}",
+                        worlds.keys().cloned().collect::<Vec<_>>().join(", ")
+                    ),
+                );
+                return report;
+            }
+        },
+        None if worlds.len() > 1 => {
+            report.error(
+                format!("package {} defines multiple worlds", root.name),
+                format!(
+                    "select one with --world; the available worlds are: {}",
+                    worlds.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            );
+            return report;
+        }
+        None => *worlds.values().next().unwrap(),
+    };
+
+    let world = &resolve.worlds[world_id];
+    if world.exports.is_empty() {
+        report.warning(
+            format!("world {} does not export anything", world.name),
+            "export the interfaces or functions to be called via RPC, otherwise the generated stub will be empty",
+        );
+    }
+
+    let mut visited = HashSet::new();
+    for (key, item) in &world.exports {
+        match item {
+            WorldItem::Interface(interface_id) => {
+                let interface = &resolve.interfaces[*interface_id];
+                let interface_name = interface
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| resolve.name_world_key(key));
+                for type_id in interface.types.values() {
+                    check_type(
+                        &resolve,
+                        &Type::Id(*type_id),
+                        &interface_name,
+                        &mut visited,
+                        &mut report,
+                    );
+                }
+                for function in interface.functions.values() {
+                    check_function(
+                        &resolve,
+                        function,
+                        &interface_name,
+                        &mut visited,
+                        &mut report,
+                    );
+                }
+            }
+            WorldItem::Function(function) => {
+                check_function(&resolve, function, &world.name, &mut visited, &mut report);
+            }
+            WorldItem::Type(_) => {}
+        }
+    }
+
+    // The types defined in a world are listed among its imports
+    for item in world.imports.values() {
+        if let WorldItem::Type(type_id) = item {
+            let typ = &resolve.types[*type_id];
+            if typ.kind == TypeDefKind::Resource && matches!(typ.owner, TypeOwner::World(_)) {
+                report.warning(
+                    format!(
+                        "resource {} is defined directly in world {}, which is not supported by the stub generator and will be skipped",
+                        typ.name.clone().unwrap_or_default(),
+                        world.name
+                    ),
+                    "move the resource into an exported interface",
+                );
+            }
+        }
+    }
+
+    report
+}
+
+fn parse_deps(
+    wit_root: &Path,
+    root_name: &PackageName,
+    report: &mut ValidationReport,
+) -> BTreeMap<PackageName, (PathBuf, UnresolvedPackage)> {
+    let mut deps = BTreeMap::new();
+    let deps_path = wit_root.join("deps");
+    if !deps_path.exists() {
+        return deps;
+    }
+
+    let entries = match fs::read_dir(&deps_path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            report.error(
+                format!("failed to read the deps directory {deps_path:?}: {err}"),
+                "make sure the deps directory is readable",
+            );
+            return deps;
+        }
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        match parse_wit_path(&path) {
+            Ok(pkg) => {
+                if &pkg.name == root_name {
+                    report.error(
+                        format!(
+                            "dependency {path:?} defines the same package {} as the WIT root",
+                            pkg.name
+                        ),
+                        format!("remove {path:?}"),
+                    );
+                } else if let Some((existing, _)) = deps.get(&pkg.name) {
+                    report.error(
+                        format!(
+                            "package {} is defined by both {existing:?} and {path:?}",
+                            pkg.name
+                        ),
+                        "remove one of the duplicate dependency directories",
+                    );
+                } else {
+                    deps.insert(pkg.name.clone(), (path, pkg));
+                }
+            }
+            Err(err) => {
+                report.error(
+                    format!("failed to parse the dependency {path:?}: {err:#}"),
+                    "fix the syntax error above, or remove the dependency if it is not needed",
+                );
+            }
+        }
+    }
+
+    deps
+}
+
+fn dependency_order(
+    root: &UnresolvedPackage,
+    deps: &BTreeMap<PackageName, (PathBuf, UnresolvedPackage)>,
+) -> Result<Vec<PackageName>, PackageName> {
+    fn visit<'a>(
+        pkg: &'a UnresolvedPackage,
+        deps: &'a BTreeMap<PackageName, (PathBuf, UnresolvedPackage)>,
+        order: &mut Vec<PackageName>,
+        visiting: &mut HashSet<&'a PackageName>,
+    ) -> Result<(), PackageName> {
+        if order.contains(&pkg.name) {
+            return Ok(());
+        }
+        if !visiting.insert(&pkg.name) {
+            return Err(pkg.name.clone());
+        }
+        for dep in pkg.foreign_deps.keys() {
+            if let Some((_, dep)) = deps.get(dep) {
+                visit(dep, deps, order, visiting)?;
+            }
+        }
+        visiting.remove(&pkg.name);
+        order.push(pkg.name.clone());
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    let mut visiting = HashSet::new();
+    for pkg in deps.values().map(|(_, pkg)| pkg).chain([root]) {
+        visit(pkg, deps, &mut order, &mut visiting)?;
+    }
+    Ok(order)
+}
+
+fn check_function(
+    resolve: &Resolve,
+    function: &Function,
+    owner: &str,
+    visited: &mut HashSet<TypeId>,
+    report: &mut ValidationReport,
+) {
+    let context = format!("{owner}.{}", function.name);
+    for (_, typ) in &function.params {
+        check_type(resolve, typ, &context, visited, report);
+    }
+    match &function.results {
+        Results::Named(results) => {
+            for (_, typ) in results {
+                check_type(resolve, typ, &context, visited, report);
+            }
+        }
+        Results::Anon(typ) => check_type(resolve, typ, &context, visited, report),
+    }
+}
+
+fn check_type(
+    resolve: &Resolve,
+    typ: &Type,
+    context: &str,
+    visited: &mut HashSet<TypeId>,
+    report: &mut ValidationReport,
+) {
+    let Type::Id(type_id) = typ else {
+        return;
+    };
+    if !visited.insert(*type_id) {
+        return;
+    }
+
+    let typedef = &resolve.types[*type_id];
+    match &typedef.kind {
+        TypeDefKind::Record(record) => {
+            for field in &record.fields {
+                check_type(resolve, &field.ty, context, visited, report);
+            }
+        }
+        TypeDefKind::Tuple(tuple) => {
+            for typ in &tuple.types {
+                check_type(resolve, typ, context, visited, report);
+            }
+        }
+        TypeDefKind::Variant(variant) => {
+            for case in &variant.cases {
+                if let Some(typ) = &case.ty {
+                    check_type(resolve, typ, context, visited, report);
+                }
+            }
+        }
+        TypeDefKind::Option(inner) | TypeDefKind::List(inner) | TypeDefKind::Type(inner) => {
+            check_type(resolve, inner, context, visited, report)
+        }
+        TypeDefKind::Result(result) => {
+            if let Some(ok) = &result.ok {
+                check_type(resolve, ok, context, visited, report);
+            }
+            if let Some(err) = &result.err {
+                check_type(resolve, err, context, visited, report);
+            }
+        }
+        TypeDefKind::Future(_) => report.error(
+            format!("{context} uses a future type, which is not supported by WASM RPC"),
+            "replace the future with a regular value or a resource",
+        ),
+        TypeDefKind::Stream(_) => report.error(
+            format!("{context} uses a stream type, which is not supported by WASM RPC"),
+            "replace the stream with a list or a resource with a method returning the next chunk",
+        ),
+        TypeDefKind::Unknown => report.error(
+            format!("{context} uses a type with unknown structure"),
+            "make sure all the used types are defined in the root package or its dependencies",
+        ),
+        TypeDefKind::Resource
+        | TypeDefKind::Handle(_)
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Enum(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::write;
+    use crate::validation::{validate_wit_root, Severity, ValidationReport};
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    const API: &str = "package test:api;\n\ninterface api {\n  get: func() -> u32;\n}\n\nworld api-world {\n  export api;\n}\n";
+
+    fn validate(files: &[(&str, &str)], world: Option<&str>) -> ValidationReport {
+        let root = TempDir::new("stubgen-test").unwrap();
+        for (path, content) in files {
+            write(&root.path().join(path), content);
+        }
+        validate_wit_root(root.path(), &None, &world.map(|world| world.to_string()))
+    }
+
+    fn assert_issue(report: &ValidationReport, severity: Severity, message: &str) {
+        assert!(
+            report
+                .issues
+                .iter()
+                .any(|issue| issue.severity == severity && issue.message.contains(message)),
+            "{message} not reported in {:?}",
+            report.issues
+        );
+    }
+
+    fn assert_valid(report: &ValidationReport) {
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+    }
+
+    #[test]
+    fn wit_root_must_be_a_directory() {
+        let report = validate_wit_root(Path::new("does/not/exist"), &None, &None);
+        assert_issue(
+            &report,
+            Severity::Error,
+            "does not exist or is not a directory",
+        );
+
+        assert_valid(&validate(&[("api.wit", API)], None));
+    }
+
+    #[test]
+    fn root_package_must_parse() {
+        let report = validate(
+            &[("api.wit", "package test:api;\n\ninterface api {\n")],
+            None,
+        );
+        assert_issue(&report, Severity::Error, "failed to parse the root package");
+
+        assert_valid(&validate(&[("api.wit", API)], None));
+    }
+
+    #[test]
+    fn dependencies_must_be_found_in_deps() {
+        let root = "package test:api;\n\ninterface api {\n  use test:types/types.{point};\n  get: func() -> point;\n}\n\nworld api-world {\n  export api;\n}\n";
+        let types = "package test:types;\n\ninterface types {\n  record point { x: s32 }\n}\n";
+
+        let report = validate(&[("api.wit", root)], None);
+        assert_issue(&report, Severity::Error, "depends on test:types");
+
+        assert_valid(&validate(
+            &[("api.wit", root), ("deps/types/types.wit", types)],
+            None,
+        ));
+    }
+
+    #[test]
+    fn dependencies_must_parse() {
+        let report = validate(
+            &[
+                ("api.wit", API),
+                ("deps/broken/broken.wit", "package test:broken;\ninterface"),
+            ],
+            None,
+        );
+        assert_issue(&report, Severity::Error, "failed to parse the dependency");
+
+        assert_valid(&validate(
+            &[
+                ("api.wit", API),
+                (
+                    "deps/other/other.wit",
+                    "package test:other;\n\ninterface other {}\n",
+                ),
+            ],
+            None,
+        ));
+    }
+
+    #[test]
+    fn dependencies_must_not_redefine_the_root_package() {
+        let report = validate(&[("api.wit", API), ("deps/api/api.wit", API)], None);
+        assert_issue(
+            &report,
+            Severity::Error,
+            "defines the same package test:api",
+        );
+
+        assert_valid(&validate(&[("api.wit", API)], None));
+    }
+
+    #[test]
+    fn dependencies_must_not_be_duplicated() {
+        let other = "package test:other;\n\ninterface other {}\n";
+
+        let report = validate(
+            &[
+                ("api.wit", API),
+                ("deps/a/other.wit", other),
+                ("deps/b/other.wit", other),
+            ],
+            None,
+        );
+        assert_issue(
+            &report,
+            Severity::Error,
+            "package test:other is defined by both",
+        );
+
+        assert_valid(&validate(
+            &[("api.wit", API), ("deps/a/other.wit", other)],
+            None,
+        ));
+    }
+
+    #[test]
+    fn dependencies_must_not_be_circular() {
+        let api = "package test:api;\n\ninterface api {\n  use test:a/a.{x};\n  get: func() -> x;\n}\n\nworld api-world {\n  export api;\n}\n";
+        let a = |use_b: &str| {
+            format!("package test:a;\n\ninterface a {{\n{use_b}  type x = u32;\n}}\n")
+        };
+        let b = "package test:b;\n\ninterface b {\n  use test:a/a.{x};\n  type y = x;\n}\n";
+
+        let cyclic = a("  use test:b/b.{y};\n");
+        let report = validate(
+            &[
+                ("api.wit", api),
+                ("deps/a/a.wit", &cyclic),
+                ("deps/b/b.wit", b),
+            ],
+            None,
+        );
+        assert_issue(&report, Severity::Error, "circular dependency");
+
+        assert_valid(&validate(
+            &[
+                ("api.wit", api),
+                ("deps/a/a.wit", &a("")),
+                ("deps/b/b.wit", b),
+            ],
+            None,
+        ));
+    }
+
+    #[test]
+    fn deps_must_be_a_readable_directory() {
+        let report = validate(&[("api.wit", API), ("deps", "")], None);
+        assert_issue(
+            &report,
+            Severity::Error,
+            "failed to read the deps directory",
+        );
+
+        assert_valid(&validate(
+            &[
+                ("api.wit", API),
+                (
+                    "deps/other/other.wit",
+                    "package test:other;\n\ninterface other {}\n",
+                ),
+            ],
+            None,
+        ));
+    }
+
+    #[test]
+    fn dependencies_must_resolve() {
+        let api = "package test:api;\n\ninterface api {\n  use test:a/a.{x};\n  get: func() -> x;\n}\n\nworld api-world {\n  export api;\n}\n";
+        let a = "package test:a;\n\ninterface a {\n  use test:b/b.{y};\n  type x = y;\n}\n";
+        let b =
+            |name: &str| format!("package test:b;\n\ninterface b {{\n  type {name} = u32;\n}}\n");
+
+        let report = validate(
+            &[
+                ("api.wit", api),
+                ("deps/a/a.wit", a),
+                ("deps/b/b.wit", &b("z")),
+            ],
+            None,
+        );
+        assert_issue(&report, Severity::Error, "failed to resolve package test:a");
+
+        assert_valid(&validate(
+            &[
+                ("api.wit", api),
+                ("deps/a/a.wit", a),
+                ("deps/b/b.wit", &b("y")),
+            ],
+            None,
+        ));
+    }
+
+    #[test]
+    fn root_package_must_resolve() {
+        let root = "package test:api;\n\ninterface api {\n  use test:types/types.{point};\n  get: func() -> point;\n}\n\nworld api-world {\n  export api;\n}\n";
+        let types = |name: &str| {
+            format!("package test:types;\n\ninterface types {{\n  record {name} {{ x: s32 }}\n}}\n")
+        };
+
+        let report = validate(
+            &[("api.wit", root), ("deps/types/types.wit", &types("size"))],
+            None,
+        );
+        assert_issue(
+            &report,
+            Severity::Error,
+            "failed to resolve the root package",
+        );
+
+        assert_valid(&validate(
+            &[("api.wit", root), ("deps/types/types.wit", &types("point"))],
+            None,
+        ));
+    }
+
+    #[test]
+    fn root_package_must_define_a_world() {
+        let report = validate(
+            &[(
+                "api.wit",
+                "package test:api;\n\ninterface api {\n  get: func() -> u32;\n}\n",
+            )],
+            None,
+        );
+        assert_issue(&report, Severity::Error, "does not define any worlds");
+
+        assert_valid(&validate(&[("api.wit", API)], None));
+    }
+
+    #[test]
+    fn one_of_multiple_worlds_must_be_selected() {
+        let worlds = format!("{API}\nworld other-world {{\n  export api;\n}}\n");
+
+        let report = validate(&[("api.wit", &worlds)], None);
+        assert_issue(&report, Severity::Error, "defines multiple worlds");
+
+        assert_valid(&validate(&[("api.wit", &worlds)], Some("other-world")));
+    }
+
+    #[test]
+    fn selected_world_must_exist() {
+        let report = validate(&[("api.wit", API)], Some("missing"));
+        assert_issue(&report, Severity::Error, "world missing not found");
+
+        assert_valid(&validate(&[("api.wit", API)], Some("api-world")));
+    }
+
+    #[test]
+    fn world_should_export_something() {
+        let report = validate(
+            &[("api.wit", "package test:api;\n\nworld api-world {\n}\n")],
+            None,
+        );
+        assert_issue(&report, Severity::Warning, "does not export anything");
+        assert_eq!(report.error_count(), 0);
+
+        assert_valid(&validate(&[("api.wit", API)], None));
+    }
+
+    #[test]
+    fn exported_functions_must_not_use_futures_or_streams() {
+        let report = validate(
+            &[(
+                "api.wit",
+                "package test:api;\n\ninterface api {\n  get: func() -> future<u32>;\n  \
+                 list-all: func() -> stream<u32>;\n}\n\nworld api-world {\n  export api;\n}\n",
+            )],
+            None,
+        );
+        assert_issue(&report, Severity::Error, "api.get uses a future type");
+        assert_issue(&report, Severity::Error, "api.list-all uses a stream type");
+
+        assert_valid(&validate(&[("api.wit", API)], None));
+    }
+
+    #[test]
+    fn resources_should_be_exported_from_interfaces() {
+        let report = validate(
+            &[(
+                "api.wit",
+                "package test:api;\n\nworld api-world {\n  resource counter {\n    get: func() -> u32;\n  }\n  \
+                 export get-counter: func() -> counter;\n}\n",
+            )],
+            None,
+        );
+        assert_issue(
+            &report,
+            Severity::Warning,
+            "resource counter is defined directly",
+        );
+
+        assert_valid(&validate(
+            &[(
+                "api.wit",
+                "package test:api;\n\ninterface api {\n  resource counter {\n    get: func() -> u32;\n  }\n}\n\n\
+                 world api-world {\n  export api;\n}\n",
+            )],
+            None,
+        ));
+    }
+
+    #[test]
+    fn multi_package_roots_validate_with_a_selected_package() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let api = "package test:api;\n\ninterface api {\n  use test:types/types.{point};\n  get: func() -> point;\n}\n\nworld api-world {\n  export api;\n}\n";
+        write(&root.path().join("api.wit"), api);
+        write(
+            &root.path().join("types.wit"),
+            "package test:types;\n\ninterface types {\n  record point { x: s32 }\n}\n",
+        );
+
+        let report = validate_wit_root(root.path(), &None, &None);
+        assert_issue(&report, Severity::Error, "multiple packages");
+
+        assert_valid(&validate_wit_root(
+            root.path(),
+            &Some("test:api".to_string()),
+            &None,
+        ));
+    }
+}
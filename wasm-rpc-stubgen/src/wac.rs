@@ -0,0 +1,140 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use wac_graph::types::Package;
+use wac_graph::{CompositionGraph, EncodeOptions, NodeId};
+use wasm_compose::config::Config;
+
+/// Composes a component with the `wac` composition graph, using the dependencies and options of a `wasm-compose`
+/// configuration. Each dependency is instantiated once, and its exports satisfy the imports of the source component
+/// with the same names. The imports not satisfied by any of the dependencies are left as imports of the result.
+pub fn compose(source_wasm: &Path, config: &Config) -> anyhow::Result<Vec<u8>> {
+    if !config.definitions.is_empty() {
+        bail!("Definitions are not supported by the wac engine");
+    }
+    if !config.instantiations.is_empty() {
+        bail!("The instantiations of the wasm-compose configuration are not supported by the wac engine, only its dependencies");
+    }
+
+    let mut graph = CompositionGraph::new();
+    let source = Package::from_file("stubgen:source", None, source_wasm, graph.types_mut())
+        .context(format!("Failed to read {source_wasm:?}"))?;
+    let source_world = source.ty();
+    let source = graph.register_package(source)?;
+    let source_instance = graph.instantiate(source);
+
+    let imports = graph.types()[source_world]
+        .imports
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut instances = HashMap::<PathBuf, NodeId>::new();
+    for import in imports {
+        let Some(path) = find_dependency(config, &import) else {
+            if config.disallow_imports {
+                bail!("None of the dependencies satisfy the import {import}");
+            }
+            continue;
+        };
+        debug!("Satisfying {import} with {path:?}");
+        let instance = match instances.get(&path) {
+            Some(instance) => *instance,
+            None => {
+                let name = format!("stubgen:dependency-{}", instances.len() + 1);
+                let package = Package::from_file(&name, None, &path, graph.types_mut())
+                    .context(format!("Failed to read {path:?}"))?;
+                let package = graph.register_package(package)?;
+                let instance = graph.instantiate(package);
+                instances.insert(path, instance);
+                instance
+            }
+        };
+        let export = graph.alias_instance_export(instance, &import)?;
+        graph.set_instantiation_argument(source_instance, &import, export)?;
+    }
+
+    let exports = graph.types()[source_world]
+        .exports
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+    for name in exports {
+        let export = graph.alias_instance_export(source_instance, &name)?;
+        graph.export(export, &name)?;
+    }
+
+    Ok(graph.encode(EncodeOptions {
+        define_components: !config.import_components,
+        validate: !config.skip_validation,
+        ..Default::default()
+    })?)
+}
+
+/// The component satisfying an import, looked up like `wasm-compose` does: first among the dependencies of the
+/// configuration, then in the search paths by the name of the import
+fn find_dependency(config: &Config, import: &str) -> Option<PathBuf> {
+    match config.dependencies.get(import) {
+        Some(dependency) => Some(config.dir.join(&dependency.path)),
+        None => config
+            .search_paths
+            .iter()
+            .map(|search_path| config.dir.join(search_path).join(format!("{import}.wasm")))
+            .find(|path| path.is_file()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wac::{compose, find_dependency};
+    use std::path::Path;
+    use tempdir::TempDir;
+    use wasm_compose::config::{Config, Dependency};
+
+    #[test]
+    fn dependencies_are_found_in_config_then_search_paths() {
+        let temp_dir = TempDir::new("stubgen-test").unwrap();
+        let search_path = temp_dir.path().join("components");
+        std::fs::create_dir_all(&search_path).unwrap();
+        std::fs::write(search_path.join("logger.wasm"), b"").unwrap();
+
+        let mut config = Config {
+            dir: temp_dir.path().to_path_buf(),
+            search_paths: vec!["components".into()],
+            ..Config::default()
+        };
+        config.dependencies.insert(
+            "rpc:counter-stub/stub-counter".to_string(),
+            Dependency {
+                path: "counter_stub.wasm".into(),
+            },
+        );
+
+        assert_eq!(
+            find_dependency(&config, "rpc:counter-stub/stub-counter"),
+            Some(temp_dir.path().join("counter_stub.wasm"))
+        );
+        assert_eq!(
+            find_dependency(&config, "logger"),
+            Some(search_path.join("logger.wasm"))
+        );
+        assert_eq!(find_dependency(&config, "wasi:io/streams"), None);
+
+        config.definitions.push("extra.wasm".into());
+        assert!(compose(Path::new("caller.wasm"), &config).is_err());
+    }
+}
@@ -12,187 +12,364 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::stub::{FunctionParamStub, FunctionResultStub, StubDefinition};
+use crate::changes::{copied_files, file_change, ChangeKind, FileChange};
+use crate::compat::{package_interfaces, InterfaceShape};
+use crate::conflict::Conflict;
+use crate::paths::canonicalize;
+use crate::progress::{Progress, ProgressEvent};
+use crate::stability::{copy_wit_file, parse_wit_path};
+use crate::stub::{
+    FunctionParamStub, FunctionResultStub, FunctionStub, InterfaceStub, RpcErrors, StubDefinition,
+    METADATA_SETTER, TARGET_VERSION_SETTER, WORKER_CONSTRUCTOR,
+};
 use anyhow::{anyhow, bail, Context};
-use indexmap::IndexSet;
-use std::fmt::{Display, Formatter, Write};
+use indexmap::{IndexMap, IndexSet};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
 use std::fs;
 use std::path::{Path, PathBuf};
+use tracing::info;
 use wit_parser::{Handle, PackageName, Resolve, Type, TypeDefKind, UnresolvedPackage};
 
-pub fn generate_stub_wit(def: &StubDefinition) -> anyhow::Result<()> {
-    let world = def.resolve.worlds.get(def.world_id).unwrap();
+pub fn generate_stub_wit(def: &StubDefinition, progress: &dyn Progress) -> anyhow::Result<()> {
+    let out = render_stub_wit(def)?;
 
-    let mut out = String::new();
+    progress.on_event(&ProgressEvent::Generating {
+        description: "stub WIT".to_string(),
+        path: def.target_wit_path(),
+    });
+    fs::create_dir_all(def.target_wit_root())?;
+    fs::write(def.target_wit_path(), out)?;
+    Ok(())
+}
 
-    writeln!(out, "package {}-stub;", def.root_package_name)?;
-    writeln!(out)?;
-    writeln!(out, "interface stub-{} {{", world.name)?;
+/// The shapes of the interfaces of the stub WIT to be generated, resolved against the source packages
+pub fn stub_interfaces(def: &StubDefinition) -> anyhow::Result<BTreeMap<String, InterfaceShape>> {
+    let mut resolve = def.resolve.clone();
+    let wasm_rpc =
+        UnresolvedPackage::parse(Path::new("wasm-rpc.wit"), golem_wasm_rpc::WASM_RPC_WIT)?;
+    if !resolve.package_names.contains_key(&wasm_rpc.name) {
+        resolve.push(wasm_rpc)?;
+    }
+    let stub = UnresolvedPackage::parse(&def.target_wit_path(), &render_stub_wit(def)?)?;
+    let package = resolve.push(stub)?;
+    Ok(package_interfaces(&resolve, package))
+}
 
-    let all_imports = def
-        .interfaces
-        .iter()
-        .flat_map(|i| i.imports.iter())
-        .collect::<IndexSet<_>>();
+/// The shapes of the interfaces of a previously generated stub WIT in the target, if there is one
+pub fn previous_stub_interfaces(
+    def: &StubDefinition,
+) -> anyhow::Result<Option<BTreeMap<String, InterfaceShape>>> {
+    if !def.target_wit_path().exists() {
+        return Ok(None);
+    }
+    let mut resolve = Resolve::new();
+    let (package, _) = resolve
+        .push_dir(&def.target_wit_root())
+        .context("Failed to resolve the previously generated stub WIT")?;
+    Ok(Some(package_interfaces(&resolve, package)))
+}
+
+fn render_stub_wit(def: &StubDefinition) -> anyhow::Result<String> {
+    let world = def.source_world()?;
+
+    let mut out = WitPrinter::default();
+
+    out.line(format!("package {};", def.stub_package_name()));
+    out.blank();
 
-    writeln!(out, "  use golem:rpc/types@0.1.0.{{uri}};")?;
-    for import in all_imports {
-        writeln!(out, "  use {}.{{{}}};", import.path, import.name)?;
+    out.docs(&world.docs.contents);
+    out.open(format!("interface stub-{}", escape_id(&world.name)));
+
+    let mut all_imports = IndexMap::<&str, IndexSet<&str>>::new();
+    for import in def.interfaces.iter().flat_map(|i| i.imports.iter()) {
+        all_imports
+            .entry(&import.path)
+            .or_default()
+            .insert(&import.name);
+    }
+
+    if def.returns_rpc_errors() {
+        out.line("use golem:rpc/types@0.2.0.{uri, rpc-error};");
+    } else {
+        out.line("use golem:rpc/types@0.2.0.{uri};");
+    }
+    for (path, names) in all_imports {
+        let names = names.into_iter().map(escape_id).collect::<Vec<_>>();
+        out.line(format!("use {path}.{{{}}};", names.join(", ")));
     }
-    writeln!(out)?;
 
     for interface in &def.interfaces {
-        writeln!(out, "  resource {} {{", &interface.name)?;
-        match &interface.constructor_params {
-            None => {
-                writeln!(out, "    constructor(location: uri);")?;
-            }
-            Some(params) => {
-                write!(out, "    constructor(location: uri")?;
-                if !params.is_empty() {
-                    write!(out, ", ")?;
-                }
-                write_param_list(&mut out, def, params)?;
-                writeln!(out, ");")?;
-            }
-        }
+        out.blank();
+        out.docs(&interface.docs);
+        out.open(format!("resource {}", escape_id(&interface.name)));
+
+        out.docs(&interface.constructor_docs);
+        let mut constructor_params = vec!["location: uri".to_string()];
+        constructor_params.extend(param_list(
+            def,
+            interface.constructor_params.iter().flatten(),
+        )?);
+        out.line(format!("constructor({});", constructor_params.join(", ")));
+
         for function in &interface.functions {
-            write!(out, "    {}: func(", function.name)?;
-            write_param_list(&mut out, def, &function.params)?;
-            write!(out, ")")?;
-            if !function.results.is_empty() {
-                write!(out, " -> ")?;
-                match &function.results {
-                    FunctionResultStub::Single(typ) => {
-                        write!(out, "{}", typ.wit_type_string(&def.resolve)?)?;
-                    }
-                    FunctionResultStub::Multi(params) => {
-                        write!(out, "(")?;
-                        write_param_list(&mut out, def, params)?;
-                        write!(out, ")")?;
-                    }
-                    FunctionResultStub::SelfType => {
-                        return Err(anyhow!("Unexpected return type in wit generator"));
-                    }
-                }
-            }
-            writeln!(out, ";")?;
+            out.docs(&function.docs);
+            out.line(format!(
+                "{}: func{};",
+                escape_id(&function.name),
+                stub_function_signature(def, interface, function)?
+            ));
         }
         for function in &interface.static_functions {
-            write!(out, "    {}: static func(", function.name)?;
-            write_param_list(&mut out, def, &function.params)?;
-            write!(out, ")")?;
-            if !function.results.is_empty() {
-                write!(out, " -> ")?;
-                match &function.results {
-                    FunctionResultStub::Single(typ) => {
-                        write!(out, "{}", typ.wit_type_string(&def.resolve)?)?;
-                    }
-                    FunctionResultStub::Multi(params) => {
-                        write!(out, "(")?;
-                        write_param_list(&mut out, def, params)?;
-                        write!(out, ")")?;
-                    }
-                    FunctionResultStub::SelfType => {
-                        return Err(anyhow!("Unexpected return type in wit generator"));
-                    }
-                }
+            out.docs(&function.docs);
+            out.line(format!(
+                "{}: static func{};",
+                escape_id(&function.name),
+                stub_function_signature(def, interface, function)?
+            ));
+        }
+        if interface.has_worker_constructor() {
+            out.line(
+                "/// Creates the stub for the worker of the given component with the given name",
+            );
+            out.line(format!(
+                "{WORKER_CONSTRUCTOR}: static func(component-id: string, worker-name: string) -> {};",
+                escape_id(&interface.name)
+            ));
+        }
+        if def.metadata && !interface.defines_function(METADATA_SETTER) {
+            out.line("/// Sets the metadata passed with the invocations of this stub, like the tracing context, auth tokens or tenancy");
+            out.line(format!(
+                "{METADATA_SETTER}: func(metadata: list<tuple<string, string>>);"
+            ));
+        }
+        if let Some(version) = def.older_target_version() {
+            if !interface.defines_function(TARGET_VERSION_SETTER) {
+                out.line(format!("/// Sets the version of the target, calls to version {version} are down-converted to its API"));
+                out.line(format!("{TARGET_VERSION_SETTER}: func(version: string);"));
             }
-            writeln!(out, ";")?;
         }
-        writeln!(out, "  }}")?;
-        writeln!(out)?;
+        out.close();
     }
 
-    writeln!(out, "}}")?;
-    writeln!(out)?;
+    out.close();
+    out.blank();
 
-    writeln!(out, "world {} {{", def.target_world_name()?)?;
-    writeln!(out, "  export stub-{};", world.name)?;
-    writeln!(out, "}}")?;
+    out.open(format!("world {}", def.target_world_name()?));
+    out.line(format!("export stub-{};", escape_id(&world.name)));
+    out.close();
 
-    println!(
-        "Generating stub WIT to {}",
-        def.target_wit_path().to_string_lossy()
-    );
-    fs::create_dir_all(def.target_wit_root())?;
-    fs::write(def.target_wit_path(), out)?;
-    Ok(())
+    Ok(out.finish())
 }
 
-fn write_param_list(
-    out: &mut String,
+fn param_list<'a>(
     def: &StubDefinition,
-    params: &[FunctionParamStub],
-) -> anyhow::Result<()> {
-    for (idx, param) in params.iter().enumerate() {
-        write!(
-            out,
-            "{}: {}",
-            param.name,
-            param.typ.wit_type_string(&def.resolve)?
-        )?;
-        if idx < params.len() - 1 {
-            write!(out, ", ")?;
+    params: impl IntoIterator<Item = &'a FunctionParamStub>,
+) -> anyhow::Result<Vec<String>> {
+    params
+        .into_iter()
+        .map(|param| {
+            Ok(format!(
+                "{}: {}",
+                escape_id(&param.name),
+                param.typ.wit_type_string(&def.resolve)?
+            ))
+        })
+        .collect()
+}
+
+fn stub_function_signature(
+    def: &StubDefinition,
+    interface: &InterfaceStub,
+    function: &FunctionStub,
+) -> anyhow::Result<String> {
+    let params = param_list(def, &function.params)?.join(", ");
+    let convention = def.convention(interface, function);
+    let result = match convention.flatten(&def.resolve, &function.results)? {
+        Some(flattened) => match flattened.typ {
+            Some(typ) => Some(typ.wit_type_string(&def.resolve)?),
+            None => None,
+        },
+        None => match &function.results {
+            _ if function.results.is_empty() => None,
+            FunctionResultStub::Single(typ) => Some(typ.wit_type_string(&def.resolve)?),
+            FunctionResultStub::Multi(results) => {
+                Some(format!("({})", param_list(def, results)?.join(", ")))
+            }
+            FunctionResultStub::SelfType => {
+                return Err(anyhow!("Unexpected return type in wit generator"));
+            }
+        },
+    };
+    let results = match (result, convention.rpc_errors) {
+        (None, RpcErrors::Panic) => String::new(),
+        (Some(result), RpcErrors::Panic) => format!(" -> {result}"),
+        (None, RpcErrors::Result) => " -> result<_, rpc-error>".to_string(),
+        (Some(result), RpcErrors::Result) => format!(" -> result<{result}, rpc-error>"),
+    };
+    Ok(format!("({params}){results}"))
+}
+
+/// Builds WIT source with consistent indentation, doc comments and blank lines between items
+#[derive(Default)]
+struct WitPrinter {
+    out: String,
+    indent: usize,
+}
+
+impl WitPrinter {
+    fn line(&mut self, line: impl AsRef<str>) {
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
         }
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+
+    /// Emits a blank line, unless it would follow another blank line or an opening brace
+    fn blank(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn docs(&mut self, docs: &Option<String>) {
+        if let Some(docs) = docs {
+            for line in docs.lines() {
+                if line.is_empty() {
+                    self.line("///");
+                } else {
+                    self.line(format!("/// {line}"));
+                }
+            }
+        }
+    }
+
+    fn open(&mut self, header: impl AsRef<str>) {
+        self.line(format!("{} {{", header.as_ref()));
+        self.indent += 1;
+    }
+
+    fn close(&mut self) {
+        while self.out.ends_with("\n\n") {
+            self.out.pop();
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn finish(self) -> String {
+        self.out
     }
-    Ok(())
 }
 
-pub fn copy_wit_files(def: &StubDefinition) -> anyhow::Result<()> {
+/// Escapes identifiers colliding with WIT keywords with a `%` prefix
+fn escape_id(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as",
+        "bool",
+        "borrow",
+        "char",
+        "constructor",
+        "enum",
+        "export",
+        "f32",
+        "f64",
+        "flags",
+        "float32",
+        "float64",
+        "from",
+        "func",
+        "future",
+        "import",
+        "include",
+        "interface",
+        "list",
+        "option",
+        "own",
+        "package",
+        "record",
+        "resource",
+        "result",
+        "s16",
+        "s32",
+        "s64",
+        "s8",
+        "static",
+        "stream",
+        "string",
+        "tuple",
+        "type",
+        "u16",
+        "u32",
+        "u64",
+        "u8",
+        "use",
+        "variant",
+        "with",
+        "world",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("%{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn copy_wit_files(def: &StubDefinition, progress: &dyn Progress) -> anyhow::Result<()> {
     let mut all = def.unresolved_deps.clone();
     all.push(def.unresolved_root.clone());
 
     let dest_wit_root = def.target_wit_root();
-    fs::create_dir_all(&dest_wit_root)?;
+    fs::create_dir_all(&dest_wit_root).context(format!("Failed to create {dest_wit_root:?}"))?;
 
+    let mut packages = Vec::new();
     for unresolved in all {
-        if unresolved.name == def.root_package_name {
-            println!("Copying root package {}", unresolved.name);
-
-            let dep_dir = dest_wit_root
-                .clone()
-                .join(Path::new("deps"))
-                .join(Path::new(&format!(
-                    "{}_{}",
-                    def.root_package_name.namespace, def.root_package_name.name
-                )));
-
-            fs::create_dir_all(&dep_dir)?;
+        let mut files = Vec::new();
+        if def.is_source_root_package(&unresolved) {
+            let dep_dir = dest_wit_root.join("deps").join(format!(
+                "{}_{}",
+                unresolved.name.namespace, unresolved.name.name
+            ));
             for source in unresolved.source_files() {
-                let dest = dep_dir.join(source.file_name().unwrap());
-                println!(
-                    "  .. {} to {}",
-                    source.to_string_lossy(),
-                    dest.to_string_lossy()
-                );
-
-                fs::create_dir_all(dest.parent().unwrap())?;
-                fs::copy(source, &dest)?;
+                let file_name = source
+                    .file_name()
+                    .ok_or(anyhow!("Package source {source:?} has no file name"))?;
+                files.push((source.to_path_buf(), dep_dir.join(file_name)));
             }
         } else {
-            println!("Copying package {}", unresolved.name);
-
             for source in unresolved.source_files() {
                 let relative = source.strip_prefix(&def.source_wit_root)?;
-                let dest = dest_wit_root.clone().join(relative);
-                println!(
-                    "  .. {} to {}",
-                    source.to_string_lossy(),
-                    dest.to_string_lossy()
-                );
-                fs::create_dir_all(dest.parent().unwrap())?;
-                fs::copy(source, &dest)?;
+                files.push((source.to_path_buf(), dest_wit_root.join(relative)));
             }
         }
+        packages.push((unresolved.name.to_string(), files));
     }
-    let wasm_rpc_root = dest_wit_root.join(Path::new("deps/wasm-rpc"));
-    fs::create_dir_all(&wasm_rpc_root).unwrap();
+    check_case_collisions(packages.iter().flat_map(|(_, files)| files))?;
 
-    println!(
-        "Writing wasm-rpc.wit to {}",
-        wasm_rpc_root.to_string_lossy()
-    );
+    for (name, files) in packages {
+        progress.on_event(&ProgressEvent::CopyingPackage { name });
+        for (source, dest) in files {
+            progress.on_event(&ProgressEvent::CopyingFile {
+                source: source.clone(),
+                dest: dest.clone(),
+            });
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).context(format!("Failed to create {parent:?}"))?;
+            }
+            copy_wit_file(&source, &dest)
+                .context(format!("Failed to copy {source:?} to {dest:?}"))?;
+        }
+    }
+
+    let wasm_rpc_root = dest_wit_root.join("deps").join("wasm-rpc");
+    fs::create_dir_all(&wasm_rpc_root).context(format!("Failed to create {wasm_rpc_root:?}"))?;
+
+    progress.on_event(&ProgressEvent::Generating {
+        description: "wasm-rpc.wit".to_string(),
+        path: wasm_rpc_root.clone(),
+    });
     fs::write(
         wasm_rpc_root.join(Path::new("wasm-rpc.wit")),
         golem_wasm_rpc::WASM_RPC_WIT,
@@ -293,8 +470,68 @@ pub fn get_dep_dirs(wit_root: &Path) -> anyhow::Result<Vec<PathBuf>> {
     Ok(result)
 }
 
+/// The dependency directories of a previously added stub which are no longer needed after replacing it by a version
+/// depending on the given packages: the packages only the previous stub depended on, directly or transitively, and
+/// neither the destination's own package nor its other dependencies use.
+pub fn stale_dep_dirs(
+    dest_wit_root: &Path,
+    stub_package: &PackageName,
+    new_packages: &[PackageName],
+) -> anyhow::Result<Vec<(PathBuf, PackageName)>> {
+    // Packages are matched without their versions, so differently versioned references keep a dependency
+    let key = |name: &PackageName| (name.namespace.clone(), name.name.clone());
+
+    let mut deps = HashMap::new();
+    for dep_dir in get_dep_dirs(dest_wit_root)? {
+        // Unparseable directories are never considered stale
+        let Ok(pkg) = parse_wit_path(&dep_dir) else {
+            continue;
+        };
+        let foreign_deps = pkg.foreign_deps.keys().map(key).collect::<Vec<_>>();
+        deps.insert(key(&pkg.name), (dep_dir, pkg.name, foreign_deps));
+    }
+    let reachable = |from: Vec<(String, String)>| {
+        let mut reached = IndexSet::new();
+        let mut pending = from;
+        while let Some(name) = pending.pop() {
+            if reached.insert(name.clone()) {
+                if let Some((_, _, foreign_deps)) = deps.get(&name) {
+                    pending.extend(foreign_deps.iter().cloned());
+                }
+            }
+        }
+        reached
+    };
+
+    let stub_key = key(stub_package);
+    let previous = reachable(vec![stub_key.clone()]);
+    let mut used = reachable(
+        parse_wit_path(dest_wit_root)?
+            .foreign_deps
+            .keys()
+            .map(key)
+            .chain(
+                deps.keys()
+                    .filter(|name| !previous.contains(*name))
+                    .cloned(),
+            )
+            .filter(|name| *name != stub_key)
+            .collect(),
+    );
+    used.extend(new_packages.iter().map(key));
+
+    let mut stale = previous
+        .iter()
+        .filter(|name| !used.contains(*name))
+        .filter_map(|name| deps.get(name))
+        .map(|(dep_dir, name, _)| (dep_dir.clone(), name.clone()))
+        .collect::<Vec<_>>();
+    stale.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(stale)
+}
+
 pub fn get_package_name(wit: &Path) -> anyhow::Result<PackageName> {
-    let pkg = UnresolvedPackage::parse_path(wit)?;
+    let pkg = parse_wit_path(wit)?;
     Ok(pkg.name)
 }
 
@@ -335,6 +572,7 @@ impl Display for WitAction {
 
 impl WitAction {
     pub fn perform(&self, target_wit_root: &Path) -> anyhow::Result<()> {
+        self.check_collisions(target_wit_root)?;
         match self {
             WitAction::CopyDepDir { source_dir } => {
                 let dep_name = source_dir
@@ -344,7 +582,7 @@ impl WitAction {
                 if !target_path.exists() {
                     fs::create_dir_all(&target_path).context("Create target directory")?;
                 }
-                println!("Copying {source_dir:?} to {target_path:?}");
+                info!("Copying {source_dir:?} to {target_path:?}");
                 fs_extra::dir::copy(
                     source_dir,
                     &target_path,
@@ -352,7 +590,9 @@ impl WitAction {
                         .content_only(true)
                         .overwrite(true),
                 )
-                .context("Failed to copy the dependency directory")?;
+                .context(format!(
+                    "Failed to copy the dependency directory {source_dir:?} to {target_path:?}"
+                ))?;
             }
             WitAction::CopyDepWit {
                 source_wit,
@@ -362,26 +602,275 @@ impl WitAction {
                 if !target_dir.exists() {
                     fs::create_dir_all(&target_dir).context("Create target directory")?;
                 }
-                fs::copy(source_wit, target_dir.join(source_wit.file_name().unwrap()))
-                    .context("Copy the WIT file")?;
+                let target_wit =
+                    target_dir.join(source_wit.file_name().context("Get source wit file name")?);
+                fs::copy(source_wit, &target_wit)
+                    .context(format!("Failed to copy {source_wit:?} to {target_wit:?}"))?;
             }
         }
 
         Ok(())
     }
 
+    /// Performs the action by creating relative symbolic links pointing to the source files instead of copying them,
+    /// so regenerating the source stub automatically updates the target.
+    pub fn link(&self, target_wit_root: &Path) -> anyhow::Result<()> {
+        self.check_collisions(target_wit_root)?;
+        match self {
+            WitAction::CopyDepDir { source_dir } => {
+                let dep_name = source_dir
+                    .file_name()
+                    .context("Get wit dependency directory name")?;
+                let deps_dir = target_wit_root.join("deps");
+                fs::create_dir_all(&deps_dir).context("Create target directory")?;
+                let target_path = deps_dir.join(dep_name);
+                remove_existing(&target_path)?;
+                let link = relative_link_target(source_dir, &deps_dir)?;
+                info!("Linking {target_path:?} to {link:?}");
+                symlink_dir(&link, &target_path).context("Failed to create symbolic link")?;
+            }
+            WitAction::CopyDepWit {
+                source_wit,
+                dir_name,
+            } => {
+                let target_dir = target_wit_root.join("deps").join(dir_name);
+                if target_dir.is_symlink() {
+                    fs::remove_file(&target_dir)?;
+                }
+                fs::create_dir_all(&target_dir).context("Create target directory")?;
+                let target_wit =
+                    target_dir.join(source_wit.file_name().context("Get source wit file name")?);
+                remove_existing(&target_wit)?;
+                let link = relative_link_target(source_wit, &target_dir)?;
+                info!("Linking {target_wit:?} to {link:?}");
+                symlink_file(&link, &target_wit).context("Failed to create symbolic link")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The changes performing the action would make, without touching the target
+    pub fn planned_changes(
+        &self,
+        target_wit_root: &Path,
+        link: bool,
+    ) -> anyhow::Result<Vec<FileChange>> {
+        self.check_collisions(target_wit_root)?;
+        if link {
+            let target = self.target_path(target_wit_root)?;
+            let kind = if target.is_symlink() || target.exists() {
+                ChangeKind::Overwrite
+            } else {
+                ChangeKind::Create
+            };
+            Ok(vec![FileChange {
+                path: target,
+                kind,
+                edits: Vec::new(),
+            }])
+        } else {
+            let mut changes = Vec::new();
+            for (source, target) in self.copied_files(target_wit_root)? {
+                changes.extend(file_change(&target, &fs::read(&source)?));
+            }
+            Ok(changes)
+        }
+    }
+
+    /// The existing files in the target which performing the action would overwrite with a different content
+    pub fn conflicts(&self, target_wit_root: &Path) -> anyhow::Result<Vec<Conflict>> {
+        let mut conflicts = Vec::new();
+        for (source, target) in self.copied_files(target_wit_root)? {
+            if target.is_file() {
+                let existing =
+                    fs::read_to_string(&target).context(format!("Failed to read {target:?}"))?;
+                let incoming =
+                    fs::read_to_string(&source).context(format!("Failed to read {source:?}"))?;
+                if existing != incoming {
+                    conflicts.push(Conflict {
+                        path: target,
+                        existing,
+                        incoming,
+                    });
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Fails if the files the action creates would collide, on case-insensitive filesystems like the default ones of
+    /// macOS and Windows, with each other or with the existing entries of the target differing only in case
+    fn check_collisions(&self, target_wit_root: &Path) -> anyhow::Result<()> {
+        let target = self.target_path(target_wit_root)?;
+        check_existing_case_collision(&target)?;
+        if let WitAction::CopyDepWit { .. } = self {
+            if let Some(dep_dir) = target.parent() {
+                check_existing_case_collision(dep_dir)?;
+            }
+        }
+        check_case_collisions(&self.copied_files(target_wit_root)?)
+    }
+
+    /// The source and target paths of each file the action copies
+    fn copied_files(&self, target_wit_root: &Path) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+        let target = self.target_path(target_wit_root)?;
+        match self {
+            WitAction::CopyDepDir { source_dir } => copied_files(source_dir, &target),
+            WitAction::CopyDepWit { source_wit, .. } => Ok(vec![(source_wit.clone(), target)]),
+        }
+    }
+
+    /// The dependency directory in the target's `deps` the action creates or changes
+    pub fn target_dep_dir(&self, target_wit_root: &Path) -> anyhow::Result<PathBuf> {
+        match self {
+            WitAction::CopyDepDir { .. } => self.target_path(target_wit_root),
+            WitAction::CopyDepWit { dir_name, .. } => {
+                Ok(target_wit_root.join("deps").join(dir_name))
+            }
+        }
+    }
+
+    /// The directory or file the action creates in the target
+    fn target_path(&self, target_wit_root: &Path) -> anyhow::Result<PathBuf> {
+        match self {
+            WitAction::CopyDepDir { source_dir } => {
+                let dep_name = source_dir
+                    .file_name()
+                    .context("Get wit dependency directory name")?;
+                Ok(target_wit_root.join("deps").join(dep_name))
+            }
+            WitAction::CopyDepWit {
+                source_wit,
+                dir_name,
+            } => Ok(target_wit_root
+                .join("deps")
+                .join(dir_name)
+                .join(source_wit.file_name().context("Get source wit file name")?)),
+        }
+    }
+
+    pub fn get_package_name(&self) -> anyhow::Result<PackageName> {
+        match self {
+            WitAction::CopyDepDir { source_dir } => get_package_name(source_dir),
+            WitAction::CopyDepWit { source_wit, .. } => get_package_name(source_wit),
+        }
+    }
+
+    /// The name of the dependency directory created in the target's `deps`, as registered in the manifests
     pub fn get_dep_dir_name(&self) -> anyhow::Result<String> {
         match self {
-            WitAction::CopyDepDir { source_dir } => Ok(source_dir
-                .file_name()
-                .context("Get wit dependency directory name")?
-                .to_string_lossy()
-                .to_string()),
+            WitAction::CopyDepDir { source_dir } => {
+                let dir_name = source_dir
+                    .file_name()
+                    .context("Get wit dependency directory name")?;
+                Ok(utf8_name(dir_name, source_dir)?.to_string())
+            }
             WitAction::CopyDepWit { dir_name, .. } => Ok(dir_name.clone()),
         }
     }
 }
 
+/// The file name as UTF-8, failing with the full path otherwise, as the name has to be written to WIT files and
+/// manifests
+fn utf8_name<'a>(name: &'a OsStr, path: &Path) -> anyhow::Result<&'a str> {
+    name.to_str()
+        .ok_or(anyhow!("The name of {path:?} is not valid UTF-8"))
+}
+
+/// Fails if two of the target paths differ only in case, for different sources, so one would silently overwrite the
+/// other on case-insensitive filesystems, like the default ones of macOS and Windows
+fn check_case_collisions<'a>(
+    files: impl IntoIterator<Item = &'a (PathBuf, PathBuf)>,
+) -> anyhow::Result<()> {
+    let mut targets = HashMap::<String, &(PathBuf, PathBuf)>::new();
+    for file in files {
+        let (source, target) = file;
+        // Non UTF-8 paths cannot be case folded, they only collide when equal, like on any filesystem
+        let key = match target.to_str() {
+            Some(target) => target.to_lowercase(),
+            None => continue,
+        };
+        if let Some((other_source, other_target)) = targets.insert(key, file) {
+            if other_source != source {
+                bail!(
+                    "{source:?} and {other_source:?} would both be copied to {target:?}{}",
+                    if other_target == target {
+                        String::new()
+                    } else {
+                        format!(" and {other_target:?}, which are the same on case-insensitive filesystems")
+                    }
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails if the directory of the path has an entry differing from the path's name only in case, which is a different
+/// entry on case-sensitive filesystems but the same on case-insensitive ones
+fn check_existing_case_collision(path: &Path) -> anyhow::Result<()> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(());
+    };
+    let Some(name) = name.to_str() else {
+        return Ok(());
+    };
+    if !parent.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(parent).context(format!("Failed to read {parent:?}"))? {
+        let existing = entry?.file_name();
+        if let Some(existing) = existing.to_str() {
+            if existing != name && existing.to_lowercase() == name.to_lowercase() {
+                bail!(
+                    "{path:?} differs from the existing {:?} only in case, which is the same on case-insensitive filesystems",
+                    parent.join(existing)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove_existing(path: &Path) -> anyhow::Result<()> {
+    if path.is_symlink() || path.is_file() {
+        fs::remove_file(path).context(format!("Failed to remove {path:?}"))?;
+    } else if path.is_dir() {
+        fs::remove_dir_all(path).context(format!("Failed to remove {path:?}"))?;
+    }
+    Ok(())
+}
+
+fn relative_link_target(source: &Path, link_parent: &Path) -> anyhow::Result<PathBuf> {
+    let source = canonicalize(source).context(format!("Failed to resolve {source:?}"))?;
+    let link_parent =
+        canonicalize(link_parent).context(format!("Failed to resolve {link_parent:?}"))?;
+    pathdiff::diff_paths(&source, &link_parent).ok_or(anyhow!(
+        "Cannot express {source:?} relative to {link_parent:?}"
+    ))
+}
+
+#[cfg(unix)]
+fn symlink_dir(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(unix)]
+fn symlink_file(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
 pub fn verify_action(
     action: &WitAction,
     target_wit_root: &Path,
@@ -397,7 +886,7 @@ pub fn verify_action(
                 if !dir_diff::is_different(source_dir, &target_path)? {
                     Ok(true)
                 } else if overwrite {
-                    println!("Overwriting {}", target_path.to_string_lossy());
+                    info!("Overwriting {target_path:?}");
                     Ok(true)
                 } else {
                     Ok(false)
@@ -416,22 +905,18 @@ pub fn verify_action(
             if target_dir.exists() && target_dir.is_dir() {
                 let mut existing_entries = Vec::new();
                 for entry in fs::read_dir(&target_dir)? {
-                    let entry = entry?;
-                    let name = entry
-                        .path()
-                        .file_name()
-                        .context("Get existing wit directory's name")?
-                        .to_string_lossy()
-                        .to_string();
-                    existing_entries.push(name);
+                    existing_entries.push(entry?.file_name());
                 }
-                if existing_entries.contains(&source_file_name.to_string_lossy().to_string()) {
+                if existing_entries
+                    .iter()
+                    .any(|name| name.as_os_str() == source_file_name)
+                {
                     let source_contents = fs::read_to_string(source_wit)?;
                     let target_contents = fs::read_to_string(&target_wit)?;
                     if source_contents == target_contents {
                         Ok(true)
                     } else if overwrite {
-                        println!("Overwriting {}", target_wit.to_string_lossy());
+                        info!("Overwriting {target_wit:?}");
                         Ok(true)
                     } else {
                         Ok(false)
@@ -445,3 +930,440 @@ pub fn verify_action(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cargo::StubCrateDependencies;
+    use crate::progress::NoProgress;
+    use crate::stub::{StubDefinition, StubPackageVersion};
+    use crate::test_utils::write;
+    use crate::wit::{
+        check_case_collisions, copy_wit_files, generate_stub_wit, stale_dep_dirs, WitAction,
+    };
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+    use wit_parser::PackageName;
+    use wit_parser::{Resolve, TypeDefKind};
+
+    const DOCUMENTED_WIT: &str = r#"
+package test:docs;
+
+/// Account operations
+interface api {
+  /// A shopping cart
+  resource cart {
+    /// Creates a cart for the given user
+    constructor(user-id: string);
+    /// Adds an item
+    ///
+    /// Returns the number of items
+    add-item: func(name: string) -> u32;
+    %list: func() -> list<string>;
+  }
+
+  /// Adds two numbers
+  add: func(x: s32, %type: s32) -> s64;
+  %result: func() -> string;
+}
+
+/// The API world
+world api-world {
+  export api;
+}
+"#;
+
+    const VERSIONED_WIT: &str = "package test:api@1.2.3;\n\ninterface api {\n  get: func() -> u32;\n}\n\nworld api-world {\n  export api;\n}\n";
+
+    fn generate(source_wit_root: &Path, target_root: &Path) -> (StubDefinition, Resolve) {
+        generate_with_version(source_wit_root, target_root, &StubPackageVersion::Same)
+    }
+
+    fn generate_with_version(
+        source_wit_root: &Path,
+        target_root: &Path,
+        stub_package_version: &StubPackageVersion,
+    ) -> (StubDefinition, Resolve) {
+        let def = StubDefinition::new(
+            source_wit_root,
+            target_root,
+            &None,
+            &None,
+            "0.0.1",
+            stub_package_version,
+            &StubCrateDependencies::default(),
+        )
+        .unwrap();
+        generate_stub_wit(&def, &NoProgress).unwrap();
+        copy_wit_files(&def, &NoProgress).unwrap();
+
+        let mut resolve = Resolve::new();
+        resolve.push_dir(&def.target_wit_root()).unwrap();
+        (def, resolve)
+    }
+
+    fn documented_source(root: &Path) -> std::path::PathBuf {
+        let source_wit_root = root.join("source").join("wit");
+        write(&source_wit_root.join("api.wit"), DOCUMENTED_WIT);
+        source_wit_root
+    }
+
+    fn docs_of(resolve: &Resolve, interface: &str, function: &str) -> Option<String> {
+        let (_, interface) = resolve
+            .interfaces
+            .iter()
+            .find(|(_, i)| i.name.as_deref() == Some(interface))
+            .unwrap();
+        interface.functions[function].docs.contents.clone()
+    }
+
+    #[test]
+    fn example_stub_wit_roundtrips() {
+        let target = TempDir::new("stubgen-test").unwrap();
+        let (def, resolve) = generate(Path::new("example"), target.path());
+
+        let (_, stub_interface) = resolve
+            .interfaces
+            .iter()
+            .find(|(_, i)| i.name.as_deref() == Some("stub-api"))
+            .unwrap();
+
+        for interface in &def.interfaces {
+            let type_id = stub_interface.types[&interface.name];
+            assert!(matches!(resolve.types[type_id].kind, TypeDefKind::Resource));
+            assert!(stub_interface
+                .functions
+                .contains_key(&format!("[constructor]{}", interface.name)));
+            for function in &interface.functions {
+                assert!(stub_interface
+                    .functions
+                    .contains_key(&format!("[method]{}.{}", interface.name, function.name)));
+            }
+            for function in &interface.static_functions {
+                assert!(stub_interface
+                    .functions
+                    .contains_key(&format!("[static]{}.{}", interface.name, function.name)));
+            }
+            assert_eq!(
+                stub_interface
+                    .functions
+                    .contains_key(&format!("[static]{}.for-worker", interface.name)),
+                !interface.is_resource()
+            );
+        }
+    }
+
+    #[test]
+    fn doc_comments_are_preserved() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let source_wit_root = documented_source(root.path());
+        let (_, resolve) = generate(&source_wit_root, &root.path().join("stub"));
+
+        let (_, stub_interface) = resolve
+            .interfaces
+            .iter()
+            .find(|(_, i)| i.name.as_deref() == Some("stub-api-world"))
+            .unwrap();
+        assert_eq!(
+            stub_interface.docs.contents.as_deref(),
+            Some("The API world")
+        );
+        assert_eq!(
+            resolve.types[stub_interface.types["api"]]
+                .docs
+                .contents
+                .as_deref(),
+            Some("Account operations")
+        );
+        assert_eq!(
+            resolve.types[stub_interface.types["cart"]]
+                .docs
+                .contents
+                .as_deref(),
+            Some("A shopping cart")
+        );
+
+        assert_eq!(
+            docs_of(&resolve, "stub-api-world", "[constructor]cart").as_deref(),
+            Some("Creates a cart for the given user")
+        );
+        assert_eq!(
+            docs_of(&resolve, "stub-api-world", "[method]cart.add-item").as_deref(),
+            Some("Adds an item\n\nReturns the number of items")
+        );
+        assert_eq!(
+            docs_of(&resolve, "stub-api-world", "[method]api.add").as_deref(),
+            Some("Adds two numbers")
+        );
+        assert_eq!(
+            docs_of(&resolve, "stub-api-world", "[method]api.result"),
+            None
+        );
+    }
+
+    #[test]
+    fn keywords_are_escaped() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let source_wit_root = documented_source(root.path());
+        let (def, resolve) = generate(&source_wit_root, &root.path().join("stub"));
+
+        let stub_wit = fs::read_to_string(def.target_wit_path()).unwrap();
+        assert!(stub_wit.contains("%list: func() -> list<string>;"));
+        assert!(stub_wit.contains("%result: func() -> string;"));
+        assert!(stub_wit.contains("add: func(x: s32, %type: s32) -> s64;"));
+
+        let (_, stub_interface) = resolve
+            .interfaces
+            .iter()
+            .find(|(_, i)| i.name.as_deref() == Some("stub-api-world"))
+            .unwrap();
+        assert!(stub_interface.functions.contains_key("[method]cart.list"));
+        assert!(stub_interface.functions.contains_key("[method]api.result"));
+    }
+
+    #[test]
+    fn package_can_be_selected_from_multi_package_root() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let source_wit_root = root.path().join("source").join("wit");
+        fs::create_dir_all(&source_wit_root).unwrap();
+        fs::write(
+            source_wit_root.join("types.wit"),
+            "package test:types;\n\ninterface shared {\n  record point {\n    x: s32,\n    y: s32,\n  }\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            source_wit_root.join("api.wit"),
+            "// Main API\npackage test:api;\n\ninterface api {\n  use test:types/shared.{point};\n  \
+             origin: func() -> point;\n}\n\nworld api-world {\n  export api;\n}\n",
+        )
+        .unwrap();
+
+        let target_root = root.path().join("stub");
+        assert!(StubDefinition::new(
+            &source_wit_root,
+            &target_root,
+            &None,
+            &None,
+            "0.0.1",
+            &StubPackageVersion::Same,
+            &StubCrateDependencies::default(),
+        )
+        .is_err());
+
+        let def = StubDefinition::new(
+            &source_wit_root,
+            &target_root,
+            &Some("test:api".to_string()),
+            &None,
+            "0.0.1",
+            &StubPackageVersion::Same,
+            &StubCrateDependencies::default(),
+        )
+        .unwrap();
+        assert_eq!(def.stub_package_name().to_string(), "test:api-stub");
+        generate_stub_wit(&def, &NoProgress).unwrap();
+        copy_wit_files(&def, &NoProgress).unwrap();
+
+        let wit_root = def.target_wit_root();
+        assert!(wit_root
+            .join("deps")
+            .join("test_types")
+            .join("types.wit")
+            .is_file());
+        assert!(!wit_root.join("types.wit").exists());
+
+        let mut resolve = Resolve::new();
+        resolve.push_dir(&wit_root).unwrap();
+    }
+
+    #[test]
+    fn stub_package_version_is_derived_from_the_source_package() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let source_wit_root = root.path().join("source").join("wit");
+        write(&source_wit_root.join("api.wit"), VERSIONED_WIT);
+
+        for (version, expected) in [
+            ("same", "test:api-stub@1.2.3"),
+            ("none", "test:api-stub"),
+            ("2.0.0-rc.1", "test:api-stub@2.0.0-rc.1"),
+        ] {
+            let target = TempDir::new("stubgen-test").unwrap();
+            let (def, resolve) =
+                generate_with_version(&source_wit_root, target.path(), &version.parse().unwrap());
+
+            assert_eq!(def.stub_package_name().to_string(), expected);
+            assert!(resolve
+                .package_names
+                .keys()
+                .any(|name| name.to_string() == expected));
+        }
+        assert!("1.2".parse::<StubPackageVersion>().is_err());
+    }
+
+    #[test]
+    fn targets_differing_only_in_case_collide() {
+        let files = [
+            (PathBuf::from("a/API.wit"), PathBuf::from("deps/x/API.wit")),
+            (PathBuf::from("b/api.wit"), PathBuf::from("deps/x/api.wit")),
+        ];
+        let err = check_case_collisions(&files).unwrap_err().to_string();
+        assert!(err.contains("case-insensitive"), "{err}");
+
+        let same_source = [
+            (PathBuf::from("a/api.wit"), PathBuf::from("deps/x/api.wit")),
+            (PathBuf::from("a/api.wit"), PathBuf::from("deps/x/api.wit")),
+        ];
+        check_case_collisions(&same_source).unwrap();
+        check_case_collisions(&files[..1]).unwrap();
+    }
+
+    #[test]
+    fn dependency_directories_differing_only_in_case_are_rejected() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        let source_dir = source.path().join("rpc_counter-stub");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("_stub.wit"), "package rpc:counter-stub;\n").unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+        fs::create_dir_all(target.path().join("deps").join("RPC_counter-stub")).unwrap();
+
+        let action = WitAction::CopyDepDir { source_dir };
+        let err = action.perform(target.path()).unwrap_err().to_string();
+
+        assert!(err.contains("only in case"), "{err}");
+        assert!(action.planned_changes(target.path(), false).is_err());
+    }
+
+    #[test]
+    fn deep_dependency_trees_are_copied() {
+        let source = TempDir::new("stubgen-test").unwrap();
+        let source_dir = source.path().join("deep");
+        let nested = (0..24).fold(source_dir.clone(), |dir, level| {
+            dir.join(format!("level-{level}"))
+        });
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("types.wit"), "package test:deep;\n").unwrap();
+        let target = TempDir::new("stubgen-test").unwrap();
+
+        WitAction::CopyDepDir { source_dir }
+            .perform(target.path())
+            .unwrap();
+
+        let copied = nested
+            .strip_prefix(source.path())
+            .map(|relative| target.path().join("deps").join(relative))
+            .unwrap();
+        assert!(copied.join("types.wit").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_dependencies_are_relative_symlinks() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let source_dir = root
+            .path()
+            .join("stub")
+            .join("wit")
+            .join("deps")
+            .join("test_dep");
+        write(&source_dir.join("dep.wit"), "package test:dep;\n");
+        let source_wit = root.path().join("stub").join("wit").join("_stub.wit");
+        write(&source_wit, "package test:main-stub;\n");
+        let target = root.path().join("caller").join("wit");
+        write(
+            &target.join("deps").join("test_main-stub").join("_stub.wit"),
+            "package test:old-stub;\n",
+        );
+
+        WitAction::CopyDepDir { source_dir }.link(&target).unwrap();
+        WitAction::CopyDepWit {
+            source_wit,
+            dir_name: "test_main-stub".to_string(),
+        }
+        .link(&target)
+        .unwrap();
+
+        let linked_dir = target.join("deps").join("test_dep");
+        assert!(linked_dir.is_symlink());
+        assert_eq!(
+            fs::read_link(&linked_dir).unwrap(),
+            Path::new("../../../stub/wit/deps/test_dep")
+        );
+        let linked_wit = target.join("deps").join("test_main-stub").join("_stub.wit");
+        assert!(linked_wit.is_symlink());
+        assert_eq!(
+            fs::read_link(&linked_wit).unwrap(),
+            Path::new("../../../../stub/wit/_stub.wit")
+        );
+        assert_eq!(
+            fs::read_to_string(linked_wit).unwrap(),
+            "package test:main-stub;\n"
+        );
+        assert_eq!(
+            fs::read_to_string(linked_dir.join("dep.wit")).unwrap(),
+            "package test:dep;\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_dependency_names_are_reported() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let action = WitAction::CopyDepDir {
+            source_dir: Path::new("wit/deps").join(OsStr::from_bytes(b"counter\xff")),
+        };
+        let err = action.get_dep_dir_name().unwrap_err().to_string();
+
+        assert!(err.contains("not valid UTF-8"), "{err}");
+    }
+
+    #[test]
+    fn dependencies_only_used_by_the_previous_stub_are_stale() {
+        let dest = TempDir::new("stubgen-test").unwrap();
+        let write = |dir: &str, wit: &str| {
+            let dir = dest.path().join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("package.wit"), wit).unwrap();
+        };
+        write(
+            "",
+            "package rpc:caller;\n\nworld caller {\n  import rpc:counter-stub/stub-counter;\n  import rpc:shared/types;\n}\n",
+        );
+        write(
+            "deps/rpc_counter-stub",
+            "package rpc:counter-stub;\n\ninterface stub-counter {\n  use rpc:counter/api.{state};\n  use rpc:legacy/api.{old};\n}\n",
+        );
+        write(
+            "deps/rpc_counter",
+            "package rpc:counter;\n\ninterface api {\n  type state = u64;\n}\n",
+        );
+        write("deps/rpc_legacy", "package rpc:legacy;\n\ninterface api {\n  use rpc:shared/types.{id};\n  type old = id;\n}\n");
+        write(
+            "deps/rpc_shared",
+            "package rpc:shared;\n\ninterface types {\n  type id = u64;\n}\n",
+        );
+
+        let stub = PackageName {
+            namespace: "rpc".to_string(),
+            name: "counter-stub".to_string(),
+            version: None,
+        };
+        let counter = PackageName {
+            name: "counter".to_string(),
+            ..stub.clone()
+        };
+        let stale = stale_dep_dirs(dest.path(), &stub, &[stub.clone(), counter]).unwrap();
+
+        assert_eq!(
+            stale,
+            vec![(
+                dest.path().join("deps").join("rpc_legacy"),
+                PackageName {
+                    name: "legacy".to_string(),
+                    ..stub
+                }
+            )]
+        );
+    }
+}
@@ -0,0 +1,607 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::{self, BuildOptions, ComposeOptions, GenerateOptions};
+use crate::cargo::{find_workspace_root, get_workspace_members, StubCrateDependencies};
+use crate::error::{CommandError, ErrorKind};
+use crate::progress::cli_progress;
+use crate::stability::parse_wit_dir;
+use crate::stub::FunctionConvention;
+use crate::{wit, AddStubDependencyArgs, ConflictPolicy};
+use anyhow::{anyhow, Context};
+use cargo_toml::Manifest;
+use heck::ToSnakeCase;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Default)]
+struct MetadataRoot {
+    #[serde(rename = "wasm-rpc")]
+    wasm_rpc: Option<WasmRpcMetadata>,
+}
+
+/// The `[package.metadata.wasm-rpc]` and `[workspace.metadata.wasm-rpc]` tables
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct WasmRpcMetadata {
+    #[serde(default)]
+    calls: Vec<String>,
+    wit: Option<String>,
+    world: Option<String>,
+    stub_crate: Option<String>,
+    wasm_rpc_source: Option<String>,
+    wit_bindgen_source: Option<String>,
+    #[serde(default)]
+    functions: BTreeMap<String, FunctionConvention>,
+    target_dir: Option<String>,
+    rustc_wrapper: Option<String>,
+    #[serde(default)]
+    sbom: bool,
+}
+
+/// The components of a Cargo workspace taking part in WASM RPC, as configured in their manifests
+#[derive(Debug, Clone)]
+pub struct WorkspaceConfig {
+    pub root: PathBuf,
+    pub targets: Vec<RpcTarget>,
+    pub callers: Vec<RpcCaller>,
+    pub dependencies: StubCrateDependencies,
+    /// The cargo target directory shared by the builds of all the stubs
+    pub stub_target_dir: Option<PathBuf>,
+    /// The program rustc is run through when compiling the stubs, like `sccache`
+    pub stub_rustc_wrapper: Option<PathBuf>,
+    /// Write software bills of materials next to the compiled stubs and the composed callers
+    pub sbom: bool,
+}
+
+/// A workspace member called by other members, having a stub crate generated for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcTarget {
+    pub name: String,
+    pub wit_root: PathBuf,
+    pub world: Option<String>,
+    pub stub_crate_root: PathBuf,
+    /// How the stub functions surface the results of the target functions
+    pub conventions: BTreeMap<String, FunctionConvention>,
+}
+
+/// A workspace member calling other members through their stubs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcCaller {
+    pub name: String,
+    pub wit_root: PathBuf,
+    pub calls: Vec<String>,
+}
+
+struct Member {
+    name: String,
+    dir: PathBuf,
+    metadata: WasmRpcMetadata,
+}
+
+/// Finds the workspace containing the given manifest, or the current directory, and reads its WASM RPC configuration
+pub fn discover_workspace(manifest_path: &Option<PathBuf>) -> anyhow::Result<WorkspaceConfig> {
+    let manifest_path = match manifest_path {
+        Some(path) => path.clone(),
+        None => {
+            let cwd = std::env::current_dir()?;
+            cwd.ancestors()
+                .map(|dir| dir.join("Cargo.toml"))
+                .find(|path| path.is_file())
+                .ok_or(anyhow!(
+                    "Could not find Cargo.toml in {cwd:?} or any parent directory"
+                ))?
+        }
+    };
+
+    let workspace_cargo = find_workspace_root(&manifest_path)?.ok_or(anyhow!(
+        "{manifest_path:?} is not part of a Cargo workspace"
+    ))?;
+    load_workspace_config(&workspace_cargo)
+}
+
+/// Reads the WASM RPC configuration of the members of a workspace.
+///
+/// Members listing other members in `calls` are callers, and the members they call are the targets.
+pub fn load_workspace_config(workspace_cargo: &Path) -> anyhow::Result<WorkspaceConfig> {
+    let root = workspace_cargo
+        .parent()
+        .ok_or(anyhow!("{workspace_cargo:?} has no parent directory"))?
+        .to_path_buf();
+
+    let workspace_metadata = Manifest::<MetadataRoot>::from_path_with_metadata(workspace_cargo)
+        .context(format!("Failed to read {workspace_cargo:?}"))?
+        .workspace
+        .and_then(|workspace| workspace.metadata)
+        .and_then(|metadata| metadata.wasm_rpc)
+        .unwrap_or_default();
+    let defaults = StubCrateDependencies::default();
+    let dependencies = StubCrateDependencies {
+        wasm_rpc: match workspace_metadata.wasm_rpc_source {
+            Some(source) => source.parse().map_err(|err: String| anyhow!(err))?,
+            None => defaults.wasm_rpc,
+        },
+        wit_bindgen: match workspace_metadata.wit_bindgen_source {
+            Some(source) => source.parse().map_err(|err: String| anyhow!(err))?,
+            None => defaults.wit_bindgen,
+        },
+        shared_types: defaults.shared_types,
+        converted_types: defaults.converted_types,
+    };
+
+    let stub_target_dir = workspace_metadata
+        .target_dir
+        .as_ref()
+        .map(|target_dir| root.join(target_dir));
+
+    let members = member_dirs(&root, &get_workspace_members(workspace_cargo)?)?
+        .into_iter()
+        .map(|dir| read_member(&root, dir))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut targets = Vec::new();
+    let mut callers = Vec::new();
+    for member in &members {
+        if member.metadata.calls.is_empty() {
+            continue;
+        }
+
+        for called in &member.metadata.calls {
+            let target = members
+                .iter()
+                .find(|candidate| &candidate.name == called)
+                .ok_or(anyhow!(
+                    "{called} is called by {} but is not a member of the workspace",
+                    member.name
+                ))?;
+            if !targets.iter().any(|t: &RpcTarget| &t.name == called) {
+                let stub_crate = match &target.metadata.stub_crate {
+                    Some(stub_crate) => PathBuf::from(stub_crate),
+                    None => {
+                        let dir_name = target
+                            .dir
+                            .file_name()
+                            .ok_or(anyhow!("Invalid member directory {:?}", target.dir))?;
+                        target
+                            .dir
+                            .with_file_name(format!("{}-stub", dir_name.to_string_lossy()))
+                    }
+                };
+                targets.push(RpcTarget {
+                    name: target.name.clone(),
+                    wit_root: root.join(&target.dir).join(wit_dir(&target.metadata)),
+                    world: target.metadata.world.clone(),
+                    stub_crate_root: root.join(stub_crate),
+                    conventions: target.metadata.functions.clone(),
+                });
+            }
+        }
+
+        callers.push(RpcCaller {
+            name: member.name.clone(),
+            wit_root: root.join(&member.dir).join(wit_dir(&member.metadata)),
+            calls: member.metadata.calls.clone(),
+        });
+    }
+
+    Ok(WorkspaceConfig {
+        root,
+        targets,
+        callers,
+        dependencies,
+        stub_target_dir,
+        stub_rustc_wrapper: workspace_metadata.rustc_wrapper.map(PathBuf::from),
+        sbom: workspace_metadata.sbom,
+    })
+}
+
+fn wit_dir(metadata: &WasmRpcMetadata) -> &str {
+    metadata.wit.as_deref().unwrap_or("wit")
+}
+
+/// Expands the workspace `members` entries, supporting plain paths and a trailing `*` wildcard
+pub(crate) fn member_dirs(root: &Path, patterns: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for pattern in patterns {
+        let pattern = Path::new(pattern.trim_end_matches('/'));
+        match (
+            pattern.file_name().and_then(|name| name.to_str()),
+            pattern.parent(),
+        ) {
+            (Some("*"), Some(parent)) => {
+                let mut matches = Vec::new();
+                for entry in fs::read_dir(root.join(parent))? {
+                    let path = entry?.path();
+                    if path.join("Cargo.toml").is_file() {
+                        matches.push(parent.join(path.file_name().unwrap()));
+                    }
+                }
+                matches.sort();
+                dirs.extend(matches);
+            }
+            _ => dirs.push(pattern.to_path_buf()),
+        }
+    }
+    Ok(dirs)
+}
+
+fn read_member(root: &Path, dir: PathBuf) -> anyhow::Result<Member> {
+    let cargo_path = root.join(&dir).join("Cargo.toml");
+    let manifest = Manifest::<MetadataRoot>::from_path_with_metadata(&cargo_path)
+        .context(format!("Failed to read {cargo_path:?}"))?;
+    let package = manifest
+        .package
+        .ok_or(anyhow!("{cargo_path:?} has no [package] section"))?;
+    Ok(Member {
+        name: package.name,
+        dir,
+        metadata: package
+            .metadata
+            .and_then(|metadata| metadata.wasm_rpc)
+            .unwrap_or_default(),
+    })
+}
+
+/// The conventions of the stub functions configured in the `[package.metadata.wasm-rpc.functions]` table of a manifest
+pub fn function_conventions(
+    cargo_path: &Path,
+) -> anyhow::Result<BTreeMap<String, FunctionConvention>> {
+    let manifest = Manifest::<MetadataRoot>::from_path_with_metadata(cargo_path)
+        .context(format!("Failed to read {cargo_path:?}"))?;
+    Ok(manifest
+        .package
+        .and_then(|package| package.metadata)
+        .and_then(|metadata| metadata.wasm_rpc)
+        .map(|metadata| metadata.functions)
+        .unwrap_or_default())
+}
+
+/// The name of the crate defined in a manifest, which determines the name of its WASM output
+fn crate_name(crate_root: &Path) -> anyhow::Result<String> {
+    let cargo_path = crate_root.join("Cargo.toml");
+    Manifest::from_path(&cargo_path)
+        .context(format!("Failed to read {cargo_path:?}"))?
+        .package
+        .map(|package| package.name)
+        .ok_or(anyhow!("{cargo_path:?} has no [package] section"))
+}
+
+fn wasm_path(config: &WorkspaceConfig, profile: &str, crate_name: &str) -> PathBuf {
+    config
+        .root
+        .join("target")
+        .join("wasm32-wasi")
+        .join(profile)
+        .join(format!("{}.wasm", crate_name.to_snake_case()))
+}
+
+/// Generates the stub crates of all the targets and adds them as WIT dependencies to their callers
+pub fn generate_stubs(config: &WorkspaceConfig) -> anyhow::Result<()> {
+    for target in &config.targets {
+        let options = GenerateOptions {
+            world: target.world.clone(),
+            dependencies: config.dependencies.clone(),
+            conventions: target.conventions.clone(),
+            ..GenerateOptions::new(&target.wit_root, &target.stub_crate_root)
+        };
+        api::generate_stub(&options, cli_progress().as_ref())
+            .context(format!("Failed to generate the stub of {}", target.name))?;
+    }
+
+    for caller in &config.callers {
+        for called in &caller.calls {
+            let target = find_target(config, called)?;
+            crate::add_stub_dependency(AddStubDependencyArgs {
+                stub_wit_root: target.stub_crate_root.join("wit"),
+                dest_wit_root: caller.wit_root.clone(),
+                overwrite: true,
+                on_conflict: ConflictPolicy::Overwrite,
+                update_cargo_toml: true,
+                link: false,
+                dry_run: false,
+                keep_backup: false,
+            })
+            .context(format!(
+                "Failed to add the stub of {called} to {}",
+                caller.name
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Regenerates the stubs and compiles them to the workspace's release target directory
+pub async fn build_stubs(config: &WorkspaceConfig) -> anyhow::Result<()> {
+    generate_stubs(config)?;
+
+    for target in &config.targets {
+        let options = BuildOptions {
+            world: target.world.clone(),
+            dependencies: config.dependencies.clone(),
+            conventions: target.conventions.clone(),
+            target_dir: config.stub_target_dir.clone(),
+            rustc_wrapper: config.stub_rustc_wrapper.clone(),
+            sbom: config.sbom,
+            ..BuildOptions::new(
+                &target.wit_root,
+                wasm_path(config, "release", &crate_name(&target.stub_crate_root)?),
+                target.stub_crate_root.join("wit"),
+            )
+        };
+        api::build_stub(&options, cli_progress().as_ref())
+            .await
+            .context(format!("Failed to build the stub of {}", target.name))?;
+    }
+
+    Ok(())
+}
+
+/// Composes the compiled callers with the stubs of the targets they call
+pub fn compose_callers(config: &WorkspaceConfig, release: bool) -> anyhow::Result<()> {
+    let profile = if release { "release" } else { "debug" };
+
+    for caller in &config.callers {
+        let mut stub_wasm = Vec::new();
+        for called in &caller.calls {
+            let target = find_target(config, called)?;
+            stub_wasm.push(wasm_path(
+                config,
+                profile,
+                &crate_name(&target.stub_crate_root)?,
+            ));
+        }
+
+        let options = ComposeOptions {
+            sbom: config.sbom,
+            ..ComposeOptions::new(
+                wasm_path(config, profile, &caller.name),
+                stub_wasm,
+                wasm_path(config, profile, &format!("{}_composed", caller.name)),
+            )
+        };
+        api::compose(&options, cli_progress().as_ref())
+            .context(format!("Failed to compose {}", caller.name))?;
+    }
+
+    Ok(())
+}
+
+/// The inputs and the output of composing a caller crate, in the build output directory of its workspace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallerComposition {
+    pub source_wasm: PathBuf,
+    pub stub_wasm: Vec<PathBuf>,
+    pub dest_wasm: PathBuf,
+}
+
+/// Locates the WASM files for composing a caller crate. The stubs are the packages with generated stub worlds in its
+/// `wit/deps` directory, expected to be built by `cargo component` into the same output directory as the caller,
+/// `<target-dir>/wasm32-wasi/<profile>`. Without an explicit target directory, the `target` directory of the
+/// workspace of the caller is used.
+pub fn discover_caller_composition(
+    caller_root: &Path,
+    profile: &str,
+    target_dir: Option<&Path>,
+) -> anyhow::Result<CallerComposition> {
+    let caller_cargo = caller_root.join("Cargo.toml");
+    let caller_name = crate_name(caller_root)?;
+    let target_dir = match target_dir {
+        Some(target_dir) => target_dir.to_path_buf(),
+        None => match find_workspace_root(&caller_cargo)? {
+            Some(workspace_cargo) => workspace_cargo
+                .parent()
+                .ok_or(anyhow!("{workspace_cargo:?} has no parent directory"))?
+                .join("target"),
+            None => caller_root.join("target"),
+        },
+    };
+    let out_dir = target_dir.join("wasm32-wasi").join(profile);
+
+    let mut stub_wasm = Vec::new();
+    for dep_dir in wit::get_dep_dirs(&caller_root.join("wit"))? {
+        let pkg = parse_wit_dir(&dep_dir)
+            .context(format!("Failed to parse the WIT package in {dep_dir:?}"))?;
+        if !pkg.name.name.ends_with("-stub") {
+            continue;
+        }
+        // The stub crate is named after the stub's source world, see `StubDefinition::target_crate_name`
+        let Some(source_world) = pkg
+            .worlds
+            .iter()
+            .find_map(|(_, world)| world.name.strip_prefix("wasm-rpc-stub-"))
+        else {
+            continue;
+        };
+        let wasm = out_dir.join(format!("{}_stub.wasm", source_world.to_snake_case()));
+        if !wasm.is_file() {
+            return Err(anyhow!(CommandError::new(
+                ErrorKind::MissingDependency,
+                format!(
+                    "The WASM of the stub {} was not found at {wasm:?}. Build the stub crate first.",
+                    pkg.name
+                )
+            )));
+        }
+        stub_wasm.push(wasm);
+    }
+    if stub_wasm.is_empty() {
+        return Err(anyhow!(CommandError::new(
+            ErrorKind::MissingDependency,
+            format!("No stubs found in the WIT dependencies of {caller_name}. Add them with `add-stub-dependency` first.")
+        )));
+    }
+    stub_wasm.sort();
+
+    Ok(CallerComposition {
+        source_wasm: out_dir.join(format!("{}.wasm", caller_name.to_snake_case())),
+        stub_wasm,
+        dest_wasm: out_dir.join(format!("{}_composed.wasm", caller_name.to_snake_case())),
+    })
+}
+
+fn find_target<'a>(config: &'a WorkspaceConfig, name: &str) -> anyhow::Result<&'a RpcTarget> {
+    config
+        .targets
+        .iter()
+        .find(|target| target.name == name)
+        .ok_or(anyhow!("{name} is not an RPC target of the workspace"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stub::{FunctionConvention, RpcErrors};
+    use crate::test_utils::write;
+    use crate::workspace::{
+        discover_caller_composition, load_workspace_config, CallerComposition, RpcCaller, RpcTarget,
+    };
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    fn targets_and_callers_are_discovered_from_metadata() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        write(
+            &root.path().join("Cargo.toml"),
+            r#"
+[workspace]
+members = ["components/*", "other"]
+
+[workspace.metadata.wasm-rpc]
+wasm-rpc-source = "path:../wasm-rpc"
+target-dir = "target/wasm-rpc-stubs"
+rustc-wrapper = "sccache"
+sbom = true
+"#,
+        );
+        write(
+            &root.path().join("components/counter/Cargo.toml"),
+            r#"
+[package]
+name = "counter"
+version = "0.1.0"
+
+[package.metadata.wasm-rpc]
+world = "counter-api"
+
+[package.metadata.wasm-rpc.functions."api.get"]
+flatten-result = true
+rpc-errors = "result"
+"#,
+        );
+        write(
+            &root.path().join("components/caller/Cargo.toml"),
+            r#"
+[package]
+name = "caller"
+version = "0.1.0"
+
+[package.metadata.wasm-rpc]
+calls = ["counter"]
+"#,
+        );
+        write(
+            &root.path().join("other/Cargo.toml"),
+            "[package]\nname = \"other\"\nversion = \"0.1.0\"\n",
+        );
+
+        let config = load_workspace_config(&root.path().join("Cargo.toml")).unwrap();
+
+        assert_eq!(
+            config.targets,
+            vec![RpcTarget {
+                name: "counter".to_string(),
+                wit_root: root.path().join("components/counter/wit"),
+                world: Some("counter-api".to_string()),
+                stub_crate_root: root.path().join("components/counter-stub"),
+                conventions: BTreeMap::from([(
+                    "api.get".to_string(),
+                    FunctionConvention {
+                        flatten_result: true,
+                        flatten_option: false,
+                        rpc_errors: RpcErrors::Result,
+                    }
+                )]),
+            }]
+        );
+        assert_eq!(
+            config.callers,
+            vec![RpcCaller {
+                name: "caller".to_string(),
+                wit_root: root.path().join("components/caller/wit"),
+                calls: vec!["counter".to_string()],
+            }]
+        );
+        assert_eq!(config.dependencies.wasm_rpc.to_string(), "path:../wasm-rpc");
+        assert_eq!(
+            config.stub_target_dir,
+            Some(root.path().join("target/wasm-rpc-stubs"))
+        );
+        assert_eq!(config.stub_rustc_wrapper, Some(PathBuf::from("sccache")));
+        assert!(config.sbom);
+    }
+
+    #[test]
+    fn calling_unknown_members_is_rejected() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        write(
+            &root.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"caller\"]\n",
+        );
+        write(
+            &root.path().join("caller/Cargo.toml"),
+            "[package]\nname = \"caller\"\nversion = \"0.1.0\"\n\n[package.metadata.wasm-rpc]\ncalls = [\"counter\"]\n",
+        );
+
+        assert!(load_workspace_config(&root.path().join("Cargo.toml")).is_err());
+    }
+
+    #[test]
+    fn caller_composition_is_discovered_from_wit_deps() {
+        let root = TempDir::new("stubgen-test").unwrap();
+        let root = root.path().canonicalize().unwrap();
+        write(
+            &root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"caller\"]\n",
+        );
+        write(
+            &root.join("caller/Cargo.toml"),
+            "[package]\nname = \"caller\"\nversion = \"0.1.0\"\n",
+        );
+        write(
+            &root.join("caller/wit/deps/rpc_counter-stub/_stub.wit"),
+            "package rpc:counter-stub;\n\ninterface stub-counter {\n}\n\nworld wasm-rpc-stub-counter {\n  export stub-counter;\n}\n",
+        );
+        write(
+            &root.join("caller/wit/deps/wasi_io/streams.wit"),
+            "package wasi:io;\n\ninterface streams {\n}\n",
+        );
+
+        let out_dir = root.join("target/wasm32-wasi/release");
+        assert!(discover_caller_composition(&root.join("caller"), "release", None).is_err());
+
+        write(&out_dir.join("counter_stub.wasm"), "");
+        assert_eq!(
+            discover_caller_composition(&root.join("caller"), "release", None).unwrap(),
+            CallerComposition {
+                source_wasm: out_dir.join("caller.wasm"),
+                stub_wasm: vec![out_dir.join("counter_stub.wasm")],
+                dest_wasm: out_dir.join("caller_composed.wasm"),
+            }
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{criterion_group, BatchSize, Criterion};
+use golem_wasm_ast::analysis::{AnalysedFunctionParameter, AnalysedFunctionResult, AnalysedType};
+use golem_wasm_rpc::{json, protobuf, Value, WitValue};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A representative payload shape, with its type for the JSON conversions
+struct Payload {
+    name: &'static str,
+    value: Value,
+    typ: AnalysedType,
+}
+
+/// A record nested 32 levels deep
+fn deep_record() -> Payload {
+    let mut value = Value::Option(None);
+    let mut typ = AnalysedType::Option(Box::new(AnalysedType::U64));
+    for depth in 0..32 {
+        value = Value::Record(vec![
+            Value::U64(depth),
+            Value::String(format!("level-{depth}")),
+            value,
+        ]);
+        typ = AnalysedType::Record(vec![
+            ("depth".to_string(), AnalysedType::U64),
+            ("name".to_string(), AnalysedType::Str),
+            ("inner".to_string(), typ),
+        ]);
+    }
+    Payload {
+        name: "deep record",
+        value,
+        typ,
+    }
+}
+
+/// A list of 10000 numbers
+fn wide_list() -> Payload {
+    Payload {
+        name: "wide list",
+        value: Value::List((0..10_000).map(Value::S32).collect()),
+        typ: AnalysedType::List(Box::new(AnalysedType::S32)),
+    }
+}
+
+/// A string of 1 MiB
+fn long_string() -> Payload {
+    Payload {
+        name: "long string",
+        value: Value::String("x".repeat(1024 * 1024)),
+        typ: AnalysedType::Str,
+    }
+}
+
+fn conversions(c: &mut Criterion) {
+    for payload in [deep_record(), wide_list(), long_string()] {
+        let mut group = c.benchmark_group(payload.name);
+        let value = payload.value;
+        let wit_value = WitValue::from(value.clone());
+        let val = protobuf::Val::from(value.clone());
+        let results = [AnalysedFunctionResult {
+            name: None,
+            typ: payload.typ.clone(),
+        }];
+        let parameters = [AnalysedFunctionParameter {
+            name: "value".to_string(),
+            typ: payload.typ,
+        }];
+        let json = json::function_result(vec![value.clone()], &results).unwrap();
+        check_round_trips(&value, &wit_value, &val, &json, &parameters);
+
+        group.bench_function("Value to WitValue", |b| {
+            b.iter_batched(|| value.clone(), WitValue::from, BatchSize::LargeInput)
+        });
+        group.bench_function("WitValue to Value", |b| {
+            b.iter_batched(|| wit_value.clone(), Value::from, BatchSize::LargeInput)
+        });
+        group.bench_function("Value to protobuf", |b| {
+            b.iter_batched(|| value.clone(), protobuf::Val::from, BatchSize::LargeInput)
+        });
+        group.bench_function("protobuf to Value", |b| {
+            b.iter_batched(
+                || val.clone(),
+                |val| Value::try_from(val).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("Value to JSON", |b| {
+            b.iter_batched(
+                || vec![value.clone()],
+                |values| json::function_result(values, &results).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("JSON to Value", |b| {
+            b.iter(|| json::function_parameters(&json, &parameters).unwrap())
+        });
+        group.finish();
+    }
+}
+
+/// Fails if a payload does not survive the measured conversions, which would make its timings meaningless. As
+/// `cargo test --benches` runs every benchmark once, it checks the conversions of all the payloads.
+fn check_round_trips(
+    value: &Value,
+    wit_value: &WitValue,
+    val: &protobuf::Val,
+    json: &serde_json::Value,
+    parameters: &[AnalysedFunctionParameter],
+) {
+    assert_eq!(
+        &Value::from(wit_value.clone()),
+        value,
+        "WitValue round trip"
+    );
+    assert_eq!(
+        &Value::try_from(val.clone()).unwrap(),
+        value,
+        "protobuf round trip"
+    );
+    assert_eq!(
+        json::function_parameters(json, parameters).unwrap(),
+        vec![value.clone()],
+        "JSON round trip"
+    );
+}
+
+criterion_group!(benches, conversions);
+
+fn main() {
+    let started = SystemTime::now();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    check_regressions(started);
+}
+
+/// Fails if the mean time of a benchmark run by this process regressed by more than `BENCH_REGRESSION_THRESHOLD`,
+/// like `0.1` for 10%, compared to the previous run or to the baseline passed with `--baseline` or
+/// `--baseline-lenient`. Benchmarks without a baseline to compare to are not checked.
+fn check_regressions(started: SystemTime) {
+    let Ok(threshold) = std::env::var("BENCH_REGRESSION_THRESHOLD") else {
+        return;
+    };
+    let threshold: f64 = threshold
+        .parse()
+        .expect("BENCH_REGRESSION_THRESHOLD must be a number");
+
+    let criterion_home = std::env::var_os("CRITERION_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            // The temporary directory of the benchmarks is in the target directory
+            Path::new(env!("CARGO_TARGET_TMPDIR"))
+                .parent()
+                .unwrap()
+                .join("criterion")
+        });
+    let mut estimates = Vec::new();
+    change_estimates(&criterion_home, &mut estimates);
+
+    let mut regressions = Vec::new();
+    for path in estimates {
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+        if !matches!(modified, Ok(modified) if modified >= started) {
+            continue;
+        }
+        let content = fs::read(&path).expect("Failed to read the benchmark results");
+        let change: serde_json::Value =
+            serde_json::from_slice(&content).expect("Failed to parse the benchmark results");
+        let mean = change["mean"]["point_estimate"]
+            .as_f64()
+            .unwrap_or_default();
+        if mean > threshold {
+            let benchmark = path
+                .parent()
+                .and_then(|change| change.parent())
+                .and_then(|benchmark| benchmark.strip_prefix(&criterion_home).ok())
+                .unwrap_or(path.as_path());
+            regressions.push(format!("{}: {:+.1}%", benchmark.display(), mean * 100.0));
+        }
+    }
+
+    if !regressions.is_empty() {
+        eprintln!(
+            "Benchmarks regressed by more than {:.1}%:",
+            threshold * 100.0
+        );
+        for regression in regressions {
+            eprintln!("  {regression}");
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Collects the `change/estimates.json` files of the benchmarks compared to a previous run
+fn change_estimates(dir: &Path, estimates: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            change_estimates(&path, estimates);
+        } else if path.ends_with("change/estimates.json") {
+            estimates.push(path);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use golem_wasm_rpc::{protobuf, Value, WitValue};
+use prost::bytes::Bytes;
+use prost::Message;
+
+/// A list of records, like the result of a query
+fn payload() -> WitValue {
+    Value::List(
+        (0..1000)
+            .map(|idx| {
+                Value::Record(vec![
+                    Value::U64(idx),
+                    Value::String(format!("item-{idx}")),
+                    Value::Option(Some(Box::new(Value::F64(idx as f64 / 3.0)))),
+                    Value::List((0..8).map(Value::U32).collect()),
+                ])
+            })
+            .collect(),
+    )
+    .into()
+}
+
+fn encode(c: &mut Criterion) {
+    let value = payload();
+    let mut group = c.benchmark_group("protobuf encode");
+    group.bench_function("message", |b| {
+        b.iter(|| protobuf::WitValue::from(black_box(value.clone())).encode_to_vec())
+    });
+    let mut buf = Vec::new();
+    group.bench_function("encode_into", |b| {
+        b.iter(|| {
+            buf.clear();
+            protobuf::encode_into(black_box(&value), &mut buf);
+        })
+    });
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut buf = Vec::new();
+    protobuf::encode_into(&payload(), &mut buf);
+    let bytes = Bytes::from(buf);
+    let mut group = c.benchmark_group("protobuf decode");
+    group.bench_function("message", |b| {
+        b.iter(|| {
+            let message = protobuf::WitValue::decode(black_box(bytes.clone())).unwrap();
+            WitValue::try_from(message).unwrap()
+        })
+    });
+    group.bench_function("decode", |b| {
+        b.iter(|| protobuf::decode(black_box(bytes.clone())).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
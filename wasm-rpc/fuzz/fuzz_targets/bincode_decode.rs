@@ -0,0 +1,49 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use golem_wasm_rpc::{Value, WitValue};
+use libfuzzer_sys::fuzz_target;
+
+/// Either raw bytes, or the encoding of an arbitrary value with some of its bytes corrupted, to reach deeper into the
+/// decoder than random bytes do
+#[derive(Arbitrary, Debug)]
+enum Input {
+    Raw(Vec<u8>),
+    Corrupted {
+        value: Value,
+        flips: Vec<(usize, u8)>,
+    },
+}
+
+fuzz_target!(|input: Input| {
+    let config = bincode::config::standard();
+    let bytes = match input {
+        Input::Raw(bytes) => bytes,
+        Input::Corrupted { value, flips } => {
+            let mut bytes = bincode::encode_to_vec(WitValue::from(value), config).unwrap();
+            let len = bytes.len();
+            for (index, mask) in flips {
+                bytes[index % len] ^= mask;
+            }
+            bytes
+        }
+    };
+
+    if let Ok((wit_value, _)) = bincode::decode_from_slice::<WitValue, _>(&bytes, config) {
+        let _ = Value::try_from_wit_value(wit_value);
+    }
+});
@@ -0,0 +1,82 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use golem_wasm_ast::analysis::{AnalysedFunctionParameter, AnalysedFunctionResult, AnalysedType};
+use golem_wasm_rpc::json;
+use libfuzzer_sys::fuzz_target;
+
+/// A type using every kind of value, nested in each other
+fn analysed_type() -> AnalysedType {
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    let leaf = AnalysedType::Tuple(vec![
+        AnalysedType::Bool,
+        AnalysedType::U8,
+        AnalysedType::U16,
+        AnalysedType::U32,
+        AnalysedType::U64,
+        AnalysedType::S8,
+        AnalysedType::S16,
+        AnalysedType::S32,
+        AnalysedType::S64,
+        AnalysedType::F32,
+        AnalysedType::F64,
+        AnalysedType::Chr,
+        AnalysedType::Str,
+        AnalysedType::Enum(names(&["a", "b", "c"])),
+        AnalysedType::Flags(names(&["x", "y", "z"])),
+    ]);
+    AnalysedType::Record(vec![
+        (
+            "items".to_string(),
+            AnalysedType::List(Box::new(AnalysedType::Option(Box::new(leaf.clone())))),
+        ),
+        (
+            "outcome".to_string(),
+            AnalysedType::Result {
+                ok: Some(Box::new(leaf)),
+                error: Some(Box::new(AnalysedType::Str)),
+            },
+        ),
+        (
+            "choice".to_string(),
+            AnalysedType::Variant(vec![
+                ("none".to_string(), None),
+                (
+                    "nested".to_string(),
+                    Some(AnalysedType::List(Box::new(AnalysedType::List(Box::new(
+                        AnalysedType::S32,
+                    ))))),
+                ),
+            ]),
+        ),
+    ])
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = serde_json::from_slice::<serde_json::Value>(data) else {
+        return;
+    };
+    let typ = analysed_type();
+    let parameters = [AnalysedFunctionParameter {
+        name: "value".to_string(),
+        typ: typ.clone(),
+    }];
+    if let Ok(values) = json::function_parameters(&json, &parameters) {
+        let results = [AnalysedFunctionResult { name: None, typ }];
+        // Floats out of the range of f32 become infinite, which JSON can not represent
+        let _ = json::function_result(values, &results);
+    }
+});
@@ -0,0 +1,68 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use golem_wasm_rpc::{protobuf, Value, WitValue};
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+/// Either raw bytes, or the encoding of an arbitrary value with some of its bytes corrupted, to reach deeper into the
+/// decoders than random bytes do
+#[derive(Arbitrary, Debug)]
+enum Input {
+    Raw(Vec<u8>),
+    Corrupted {
+        value: Value,
+        flips: Vec<(usize, u8)>,
+    },
+}
+
+impl Input {
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Input::Raw(bytes) => bytes,
+            Input::Corrupted { value, flips } => {
+                let mut bytes = Vec::new();
+                protobuf::encode_into(&WitValue::from(value), &mut bytes);
+                if !bytes.is_empty() {
+                    let len = bytes.len();
+                    for (index, mask) in flips {
+                        bytes[index % len] ^= mask;
+                    }
+                }
+                bytes
+            }
+        }
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let bytes = input.into_bytes();
+
+    if let Ok(wit_value) = protobuf::decode(bytes.as_slice()) {
+        let _ = Value::try_from_wit_value(wit_value);
+    }
+
+    if let Ok(message) = protobuf::WitValue::decode(bytes.as_slice()) {
+        if let Ok(wit_value) = WitValue::try_from(message) {
+            let _ = Value::try_from_wit_value(wit_value);
+        }
+    }
+
+    if let Ok(val) = protobuf::Val::decode(bytes.as_slice()) {
+        let _ = Value::try_from(val);
+    }
+});
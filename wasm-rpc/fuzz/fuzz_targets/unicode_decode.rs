@@ -0,0 +1,87 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use golem_wasm_ast::analysis::AnalysedType;
+use golem_wasm_rpc::{json, protobuf, Value, WitValue};
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+/// Code points and string bytes, valid or not, to decode as the chars and strings of every codec
+#[derive(Arbitrary, Debug)]
+struct Input {
+    code_points: Vec<u32>,
+    strings: Vec<Vec<u8>>,
+}
+
+fuzz_target!(|input: Input| {
+    for code_point in input.code_points {
+        let valid = char::from_u32(code_point).is_some();
+
+        let node = protobuf::WitValue {
+            nodes: vec![protobuf::WitNode {
+                value: Some(protobuf::wit_node::Value::Char(protobuf::WitPrimCharNode {
+                    value: code_point,
+                })),
+            }],
+        };
+        assert_eq!(
+            protobuf::decode(node.encode_to_vec().as_slice()).is_ok(),
+            valid
+        );
+        assert_eq!(WitValue::try_from(node).is_ok(), valid);
+
+        let val = protobuf::Val {
+            val: Some(protobuf::val::Val::Char(code_point as i32)),
+        };
+        assert_eq!(Value::try_from(val).is_ok(), valid);
+
+        let parameter = serde_json::Value::from(code_point);
+        let parameters = [golem_wasm_ast::analysis::AnalysedFunctionParameter {
+            name: "value".to_string(),
+            typ: AnalysedType::Chr,
+        }];
+        assert_eq!(
+            json::function_parameters(&serde_json::Value::Array(vec![parameter]), &parameters)
+                .is_ok(),
+            valid
+        );
+    }
+
+    for bytes in input.strings {
+        let valid = std::str::from_utf8(&bytes).is_ok();
+
+        // A WitValue of a single string node, with the bytes as the string
+        let field = |tag, value: &[u8]| {
+            let mut buf = Vec::new();
+            prost::encoding::bytes::encode(tag, &value.to_vec(), &mut buf);
+            buf
+        };
+        let encoded = field(1, &field(21, &field(1, &bytes)));
+        assert_eq!(protobuf::decode(encoded.as_slice()).is_ok(), valid);
+        assert_eq!(
+            protobuf::WitValue::decode(encoded.as_slice()).is_ok(),
+            valid
+        );
+
+        let encoded = bincode::encode_to_vec((1u64, 20u8, bytes), bincode::config::standard());
+        let decoded = bincode::decode_from_slice::<WitValue, _>(
+            &encoded.unwrap(),
+            bincode::config::standard(),
+        );
+        assert_eq!(decoded.is_ok(), valid);
+    }
+});
@@ -0,0 +1,42 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use golem_wasm_rpc::{protobuf, Value, WitValue};
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+fuzz_target!(|value: Value| {
+    // NaN floats are not equal to themselves
+    if value != value {
+        return;
+    }
+    let wit_value = WitValue::from(value.clone());
+
+    let config = bincode::config::standard();
+    let encoded = bincode::encode_to_vec(&wit_value, config).unwrap();
+    let (decoded, _): (WitValue, usize) = bincode::decode_from_slice(&encoded, config).unwrap();
+    assert_eq!(Value::try_from_wit_value(decoded).as_ref(), Ok(&value));
+
+    let mut encoded = Vec::new();
+    protobuf::encode_into(&wit_value, &mut encoded);
+    assert_eq!(encoded.len(), protobuf::encoded_len(&wit_value));
+    let decoded = protobuf::decode(encoded.as_slice()).unwrap();
+    assert_eq!(Value::try_from_wit_value(decoded).as_ref(), Ok(&value));
+
+    let encoded = protobuf::Val::from(value.clone()).encode_to_vec();
+    let decoded = protobuf::Val::decode(encoded.as_slice()).unwrap();
+    assert_eq!(Value::try_from(decoded).as_ref(), Ok(&value));
+});
@@ -0,0 +1,87 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use golem_wasm_rpc::{Uri, Value, WitNode, WitValue};
+use libfuzzer_sys::fuzz_target;
+
+/// Mirrors `WitNode`, to generate node lists with dangling, shared and cyclic indices
+#[derive(Arbitrary, Debug)]
+enum Node {
+    Record(Vec<i32>),
+    Variant(u32, Option<i32>),
+    Enum(u32),
+    Flags(Vec<bool>),
+    Tuple(Vec<i32>),
+    List(Vec<i32>),
+    Option(Option<i32>),
+    Result(Result<Option<i32>, Option<i32>>),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    S8(i8),
+    S16(i16),
+    S32(i32),
+    S64(i64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Bool(bool),
+    String(String),
+    Handle(String, u64),
+    Bytes(Vec<u8>),
+}
+
+fn wit_node(node: Node) -> WitNode {
+    match node {
+        Node::Record(fields) => WitNode::RecordValue(fields),
+        Node::Variant(case, value) => WitNode::VariantValue((case, value)),
+        Node::Enum(value) => WitNode::EnumValue(value),
+        Node::Flags(values) => WitNode::FlagsValue(values),
+        Node::Tuple(values) => WitNode::TupleValue(values),
+        Node::List(values) => WitNode::ListValue(values),
+        Node::Option(value) => WitNode::OptionValue(value),
+        Node::Result(value) => WitNode::ResultValue(value),
+        Node::U8(value) => WitNode::PrimU8(value),
+        Node::U16(value) => WitNode::PrimU16(value),
+        Node::U32(value) => WitNode::PrimU32(value),
+        Node::U64(value) => WitNode::PrimU64(value),
+        Node::S8(value) => WitNode::PrimS8(value),
+        Node::S16(value) => WitNode::PrimS16(value),
+        Node::S32(value) => WitNode::PrimS32(value),
+        Node::S64(value) => WitNode::PrimS64(value),
+        Node::F32(value) => WitNode::PrimFloat32(value),
+        Node::F64(value) => WitNode::PrimFloat64(value),
+        Node::Char(value) => WitNode::PrimChar(value),
+        Node::Bool(value) => WitNode::PrimBool(value),
+        Node::String(value) => WitNode::PrimString(value),
+        Node::Handle(uri, value) => WitNode::Handle((Uri { value: uri }, value)),
+        Node::Bytes(value) => WitNode::PrimBytes(value),
+    }
+}
+
+fuzz_target!(|nodes: Vec<Node>| {
+    let wit_value = WitValue {
+        nodes: nodes.into_iter().map(wit_node).collect(),
+    };
+    if let Ok(value) = Value::try_from_wit_value(wit_value) {
+        if value == value {
+            let round_trip = Value::try_from_wit_value(WitValue::from(value.clone()));
+            assert_eq!(round_trip, Ok(value));
+        }
+    }
+});
@@ -1,4 +1,5 @@
 use crate::{Uri, WitNode, WitValue};
+use bincode::de::read::Reader;
 use bincode::de::{BorrowDecoder, Decoder};
 use bincode::enc::Encoder;
 use bincode::error::{AllowedEnumVariants, DecodeError, EncodeError};
@@ -12,15 +13,14 @@ impl Encode for WitValue {
 
 impl Decode for WitValue {
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let nodes = Vec::<WitNode>::decode(decoder)?;
+        let nodes = decode_vec::<WitNode, _>(decoder)?;
         Ok(WitValue { nodes })
     }
 }
 
 impl<'de> BorrowDecode<'de> for WitValue {
     fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let nodes = Vec::<WitNode>::borrow_decode(decoder)?;
-        Ok(WitValue { nodes })
+        Self::decode(decoder)
     }
 }
 
@@ -117,6 +117,10 @@ impl Encode for WitNode {
                 uri.value.encode(encoder)?;
                 value.encode(encoder)
             }
+            WitNode::PrimBytes(value) => {
+                22u8.encode(encoder)?;
+                value.encode(encoder)
+            }
         }
     }
 }
@@ -126,7 +130,7 @@ impl Decode for WitNode {
         let tag: u8 = Decode::decode(decoder)?;
         match tag {
             0u8 => {
-                let field_indices = Vec::<i32>::decode(decoder)?;
+                let field_indices = decode_vec::<i32, _>(decoder)?;
                 Ok(WitNode::RecordValue(field_indices))
             }
             1u8 => {
@@ -139,15 +143,15 @@ impl Decode for WitNode {
                 Ok(WitNode::EnumValue(value))
             }
             3u8 => {
-                let values = Vec::<bool>::decode(decoder)?;
+                let values = decode_vec::<bool, _>(decoder)?;
                 Ok(WitNode::FlagsValue(values))
             }
             4u8 => {
-                let value_indices = Vec::<i32>::decode(decoder)?;
+                let value_indices = decode_vec::<i32, _>(decoder)?;
                 Ok(WitNode::TupleValue(value_indices))
             }
             5u8 => {
-                let value_indices = Vec::<i32>::decode(decoder)?;
+                let value_indices = decode_vec::<i32, _>(decoder)?;
                 Ok(WitNode::ListValue(value_indices))
             }
             6u8 => {
@@ -207,18 +211,22 @@ impl Decode for WitNode {
                 Ok(WitNode::PrimBool(value))
             }
             20u8 => {
-                let value = String::decode(decoder)?;
+                let value = decode_string(decoder)?;
                 Ok(WitNode::PrimString(value))
             }
             21u8 => {
-                let uri = String::decode(decoder)?;
+                let uri = decode_string(decoder)?;
                 let value = u64::decode(decoder)?;
                 Ok(WitNode::Handle((Uri { value: uri }, value)))
             }
+            22u8 => {
+                let value = decode_bytes(decoder)?;
+                Ok(WitNode::PrimBytes(value))
+            }
             _ => Err(DecodeError::UnexpectedVariant {
                 found: tag as u32,
                 type_name: "WitNode",
-                allowed: &AllowedEnumVariants::Range { min: 0, max: 21 },
+                allowed: &AllowedEnumVariants::Range { min: 0, max: 22 },
             }),
         }
     }
@@ -226,105 +234,49 @@ impl Decode for WitNode {
 
 impl<'de> BorrowDecode<'de> for WitNode {
     fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let tag: u8 = BorrowDecode::borrow_decode(decoder)?;
-        match tag {
-            0u8 => {
-                let field_indices = Vec::<i32>::borrow_decode(decoder)?;
-                Ok(WitNode::RecordValue(field_indices))
-            }
-            1u8 => {
-                let cons_idx = u32::borrow_decode(decoder)?;
-                let value_idx = Option::<i32>::borrow_decode(decoder)?;
-                Ok(WitNode::VariantValue((cons_idx, value_idx)))
-            }
-            2u8 => {
-                let value = u32::borrow_decode(decoder)?;
-                Ok(WitNode::EnumValue(value))
-            }
-            3u8 => {
-                let values = Vec::<bool>::borrow_decode(decoder)?;
-                Ok(WitNode::FlagsValue(values))
-            }
-            4u8 => {
-                let value_indices = Vec::<i32>::borrow_decode(decoder)?;
-                Ok(WitNode::TupleValue(value_indices))
-            }
-            5u8 => {
-                let value_indices = Vec::<i32>::borrow_decode(decoder)?;
-                Ok(WitNode::ListValue(value_indices))
-            }
-            6u8 => {
-                let opt_idx = Option::<i32>::borrow_decode(decoder)?;
-                Ok(WitNode::OptionValue(opt_idx))
-            }
-            7u8 => {
-                let res_idx = Result::<Option<i32>, Option<i32>>::borrow_decode(decoder)?;
-                Ok(WitNode::ResultValue(res_idx))
-            }
-            8u8 => {
-                let value = u8::borrow_decode(decoder)?;
-                Ok(WitNode::PrimU8(value))
-            }
-            9u8 => {
-                let value = u16::borrow_decode(decoder)?;
-                Ok(WitNode::PrimU16(value))
-            }
-            10u8 => {
-                let value = u32::borrow_decode(decoder)?;
-                Ok(WitNode::PrimU32(value))
-            }
-            11u8 => {
-                let value = u64::borrow_decode(decoder)?;
-                Ok(WitNode::PrimU64(value))
-            }
-            12u8 => {
-                let value = i8::borrow_decode(decoder)?;
-                Ok(WitNode::PrimS8(value))
-            }
-            13u8 => {
-                let value = i16::borrow_decode(decoder)?;
-                Ok(WitNode::PrimS16(value))
-            }
-            14u8 => {
-                let value = i32::borrow_decode(decoder)?;
-                Ok(WitNode::PrimS32(value))
-            }
-            15u8 => {
-                let value = i64::borrow_decode(decoder)?;
-                Ok(WitNode::PrimS64(value))
-            }
-            16u8 => {
-                let value = f32::borrow_decode(decoder)?;
-                Ok(WitNode::PrimFloat32(value))
-            }
-            17u8 => {
-                let value = f64::borrow_decode(decoder)?;
-                Ok(WitNode::PrimFloat64(value))
-            }
-            18u8 => {
-                let value = char::borrow_decode(decoder)?;
-                Ok(WitNode::PrimChar(value))
-            }
-            19u8 => {
-                let value = bool::borrow_decode(decoder)?;
-                Ok(WitNode::PrimBool(value))
-            }
-            20u8 => {
-                let value = String::borrow_decode(decoder)?;
-                Ok(WitNode::PrimString(value))
-            }
-            21u8 => {
-                let uri = String::borrow_decode(decoder)?;
-                let value = u64::borrow_decode(decoder)?;
-                Ok(WitNode::Handle((Uri { value: uri }, value)))
-            }
-            _ => Err(DecodeError::UnexpectedVariant {
-                found: tag as u32,
-                type_name: "WitNode",
-                allowed: &AllowedEnumVariants::Range { min: 0, max: 21 },
-            }),
-        }
+        Self::decode(decoder)
+    }
+}
+
+/// The number of elements preallocated when decoding a sequence, so a corrupted length can not exhaust the memory
+const MAX_PREALLOCATED: usize = 4096;
+
+fn decode_len<D: Decoder>(decoder: &mut D) -> Result<usize, DecodeError> {
+    let len = u64::decode(decoder)?;
+    usize::try_from(len).map_err(|_| DecodeError::OutsideUsizeRange(len))
+}
+
+/// Decodes a `Vec` like its `Decode` instance, but growing it as the elements are decoded
+fn decode_vec<T: Decode, D: Decoder>(decoder: &mut D) -> Result<Vec<T>, DecodeError> {
+    let len = decode_len(decoder)?;
+    decoder.claim_container_read::<T>(len)?;
+    let mut values = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+    for _ in 0..len {
+        decoder.unclaim_bytes_read(std::mem::size_of::<T>());
+        values.push(T::decode(decoder)?);
     }
+    Ok(values)
+}
+
+/// Decodes a `String` like its `Decode` instance, but reading it in chunks
+fn decode_string<D: Decoder>(decoder: &mut D) -> Result<String, DecodeError> {
+    let bytes = decode_bytes(decoder)?;
+    String::from_utf8(bytes).map_err(|err| DecodeError::Utf8 {
+        inner: err.utf8_error(),
+    })
+}
+
+/// Decodes a `Vec<u8>` like its `Decode` instance, but reading it in chunks
+fn decode_bytes<D: Decoder>(decoder: &mut D) -> Result<Vec<u8>, DecodeError> {
+    let len = decode_len(decoder)?;
+    decoder.claim_container_read::<u8>(len)?;
+    let mut bytes = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+    while bytes.len() < len {
+        let start = bytes.len();
+        bytes.resize(start + (len - start).min(MAX_PREALLOCATED), 0);
+        decoder.reader().read(&mut bytes[start..])?;
+    }
+    Ok(bytes)
 }
 
 #[cfg(test)]
@@ -350,4 +302,16 @@ mod tests {
             prop_assert_eq!(value, round_trip_value);
         }
     }
+
+    #[test]
+    fn corrupted_lengths_are_rejected_without_allocating() {
+        let huge = u64::MAX >> 8;
+        let config = bincode::config::standard();
+        let nodes = bincode::encode_to_vec(huge, config).unwrap();
+        let string = bincode::encode_to_vec((1u64, 20u8, huge), config).unwrap();
+        let bytes = bincode::encode_to_vec((1u64, 22u8, huge), config).unwrap();
+        for encoded in [nodes, string, bytes] {
+            assert!(bincode::decode_from_slice::<WitValue, _>(&encoded, config).is_err());
+        }
+    }
 }
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{NodeIndex, Uri, WitNode, WitValue};
+use crate::{CheckedNodeIndex, NodeIndex, Uri, WitNode, WitValue};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
 pub trait WitValueBuilderExtensions {
     fn builder() -> WitValueBuilder;
@@ -45,6 +48,8 @@ pub trait NodeBuilder: Sized {
     fn char(self, value: char) -> Self::Result;
     fn bool(self, value: bool) -> Self::Result;
     fn string(self, value: &str) -> Self::Result;
+    /// A `list<u8>` stored as a single node instead of a node per byte
+    fn bytes(self, value: &[u8]) -> Self::Result;
     fn enum_value(self, value: u32) -> Self::Result;
     fn flags(self, values: Vec<bool>) -> Self::Result;
 
@@ -130,16 +135,52 @@ pub trait NodeBuilder: Sized {
 
 pub struct WitValueBuilder {
     nodes: Vec<WitNode>,
+    /// The nodes of the strings added so far, when interning them
+    strings: Option<BTreeMap<String, NodeIndex>>,
+    /// Whether the lists of bytes of converted values are stored as single nodes
+    compact_bytes: bool,
 }
 
 impl WitValueBuilder {
     pub(crate) fn new() -> Self {
-        WitValueBuilder { nodes: Vec::new() }
+        WitValueBuilder {
+            nodes: Vec::new(),
+            strings: None,
+            compact_bytes: false,
+        }
+    }
+
+    /// Adds each distinct string once, and refers to the same node from every place it appears in, making the
+    /// payloads with many repeated strings, like lists of records with enum-like string fields, smaller
+    pub fn intern_strings(mut self) -> Self {
+        self.strings = Some(BTreeMap::new());
+        self
+    }
+
+    /// Stores the lists of bytes of the values converted with the builder as single `prim-bytes` nodes. The node is
+    /// part of `golem:rpc@0.2.0`, so only the values passed to peers supporting it can be compacted.
+    pub fn compact_bytes(mut self) -> Self {
+        self.compact_bytes = true;
+        self
+    }
+
+    pub(crate) fn compacts_bytes(&self) -> bool {
+        self.compact_bytes
     }
 
     fn add(&mut self, node: WitNode) -> NodeIndex {
+        let index =
+            CheckedNodeIndex::next(self.nodes.len()).unwrap_or_else(|error| panic!("{error}"));
         self.nodes.push(node);
-        self.nodes.len() as NodeIndex - 1
+        index.index()
+    }
+
+    /// The node previously added at the index, which only the methods of the builder hand out
+    fn node_mut(&mut self, index: NodeIndex) -> &mut WitNode {
+        let position = CheckedNodeIndex::new(index, &self.nodes)
+            .unwrap_or_else(|error| panic!("{error}"))
+            .position();
+        &mut self.nodes[position]
     }
 
     pub(crate) fn add_u8(&mut self, value: u8) -> NodeIndex {
@@ -191,7 +232,14 @@ impl WitValueBuilder {
     }
 
     pub(crate) fn add_string(&mut self, value: &str) -> NodeIndex {
-        self.add(WitNode::PrimString(value.to_string()))
+        if let Some(idx) = self.strings.as_ref().and_then(|strings| strings.get(value)) {
+            return *idx;
+        }
+        let idx = self.add(WitNode::PrimString(value.to_string()));
+        if let Some(strings) = &mut self.strings {
+            strings.insert(value.to_string(), idx);
+        }
+        idx
     }
 
     pub(crate) fn add_record(&mut self) -> NodeIndex {
@@ -210,6 +258,10 @@ impl WitValueBuilder {
         self.add(WitNode::EnumValue(value))
     }
 
+    pub(crate) fn add_bytes(&mut self, value: &[u8]) -> NodeIndex {
+        self.add(WitNode::PrimBytes(value.to_vec()))
+    }
+
     pub(crate) fn add_flags(&mut self, values: Vec<bool>) -> NodeIndex {
         self.add(WitNode::FlagsValue(values))
     }
@@ -251,7 +303,7 @@ impl WitValueBuilder {
     }
 
     pub(crate) fn finish_child(&mut self, child: NodeIndex, target_idx: NodeIndex) {
-        match &mut self.nodes[target_idx as usize] {
+        match self.node_mut(target_idx) {
             WitNode::OptionValue(ref mut result_item) => match result_item {
                 Some(idx) => *idx = child,
                 None => panic!("finish_child called on None option"),
@@ -275,7 +327,7 @@ impl WitValueBuilder {
     }
 
     pub(crate) fn finish_seq(&mut self, items: Vec<NodeIndex>, target_idx: NodeIndex) {
-        match &mut self.nodes[target_idx as usize] {
+        match self.node_mut(target_idx) {
             WitNode::RecordValue(ref mut result_items) => {
                 *result_items = items;
             }
@@ -368,6 +420,11 @@ impl NodeBuilder for WitValueBuilder {
         self.build()
     }
 
+    fn bytes(mut self, value: &[u8]) -> Self::Result {
+        let _ = self.add_bytes(value);
+        self.build()
+    }
+
     fn enum_value(mut self, value: u32) -> Self::Result {
         let _ = self.add_enum_value(value);
         self.build()
@@ -577,6 +634,12 @@ impl<ParentBuilder: NodeBuilder> NodeBuilder for WitValueItemBuilder<ParentBuild
         self.child_items_builder
     }
 
+    fn bytes(mut self, value: &[u8]) -> Self::Result {
+        let item_type_index = self.parent_builder().add_bytes(value);
+        self.child_items_builder.add_item(item_type_index);
+        self.child_items_builder
+    }
+
     fn enum_value(mut self, value: u32) -> Self::Result {
         let item_type_index = self.parent_builder().add_enum_value(value);
         self.child_items_builder.add_item(item_type_index);
@@ -784,6 +847,13 @@ impl<ParentBuilder: NodeBuilder> NodeBuilder for WitValueChildBuilder<ParentBuil
         self.builder
     }
 
+    fn bytes(mut self, value: &[u8]) -> Self::Result {
+        let child_index = self.parent_builder().add_bytes(value);
+        let target_idx = self.target_idx;
+        self.parent_builder().finish_child(child_index, target_idx);
+        self.builder
+    }
+
     fn enum_value(mut self, value: u32) -> Self::Result {
         let child_index = self.parent_builder().add_enum_value(value);
         let target_idx = self.target_idx;
@@ -901,7 +971,7 @@ impl<ParentBuilder: NodeBuilder> NodeBuilder for WitValueChildBuilder<ParentBuil
 
 #[cfg(test)]
 mod tests {
-    use crate::{NodeBuilder, Value, WitValue, WitValueBuilderExtensions};
+    use crate::{NodeBuilder, Value, WitNode, WitValue, WitValueBuilderExtensions};
 
     #[test]
     fn primitive() {
@@ -980,4 +1050,58 @@ mod tests {
             ))))))
         );
     }
+
+    #[test]
+    fn bytes() {
+        let wit_value = WitValue::builder()
+            .record()
+            .item()
+            .bytes(&[1, 2, 3])
+            .finish();
+        assert_eq!(wit_value.nodes.len(), 2);
+        let value: Value = wit_value.into();
+        assert_eq!(
+            value,
+            Value::Record(vec![Value::List(vec![
+                Value::U8(1),
+                Value::U8(2),
+                Value::U8(3)
+            ])])
+        );
+
+        let wit_value = value.clone().into_compact_wit_value();
+        assert_eq!(wit_value.nodes.len(), 2);
+        assert_eq!(Value::from(wit_value), value);
+
+        let wit_value: WitValue = value.clone().into();
+        assert_eq!(wit_value.nodes.len(), 5);
+        assert_eq!(Value::from(wit_value), value);
+
+        let empty = Value::List(Vec::new()).into_compact_wit_value();
+        assert!(matches!(empty.nodes[..], [WitNode::ListValue(_)]));
+    }
+
+    #[test]
+    fn interned_strings() {
+        let wit_value = WitValue::builder()
+            .intern_strings()
+            .list()
+            .item()
+            .string("a")
+            .item()
+            .string("b")
+            .item()
+            .string("a")
+            .finish();
+        assert_eq!(wit_value.nodes.len(), 3);
+        let value: Value = wit_value.into();
+        assert_eq!(
+            value,
+            Value::List(vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string()),
+                Value::String("a".to_string()),
+            ])
+        );
+    }
 }
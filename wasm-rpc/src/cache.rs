@@ -0,0 +1,343 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::metrics::RpcMetrics;
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Identifies the results of an invocation in an [RpcCache]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub uri: String,
+    pub function_name: String,
+    /// The encoded parameters, which are the same on every platform and in every version. They are compared in full,
+    /// so the invocations with different parameters never share a result.
+    pub params: Vec<u8>,
+}
+
+impl CacheKey {
+    pub fn new(uri: &Uri, function_name: &str, function_params: &[WitValue]) -> Self {
+        let params = bincode::encode_to_vec(function_params, bincode::config::standard())
+            .expect("Failed to encode the parameters");
+        Self {
+            uri: uri.value.clone(),
+            function_name: function_name.to_string(),
+            params,
+        }
+    }
+}
+
+/// Stores the results of the invocations of pure functions, to be reused for identical invocations
+#[async_trait]
+pub trait RpcCache: Send + Sync {
+    async fn get(&self, key: &CacheKey) -> Option<WitValue>;
+
+    /// Stores a result, to be returned by [Self::get] until the time to live passes
+    async fn put(&self, key: CacheKey, value: WitValue, ttl: Duration);
+}
+
+/// An [RpcCache] keeping the results in memory, up to a maximum number of entries. When full, the expired entries are
+/// removed first, then the ones expiring the soonest. Times to live too long to represent, like [Duration::MAX], never
+/// expire.
+pub struct InMemoryCache {
+    max_entries: usize,
+    entries: Mutex<HashMap<CacheKey, (WitValue, Option<Instant>)>>,
+}
+
+impl InMemoryCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries: max_entries.max(1),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl RpcCache for InMemoryCache {
+    async fn get(&self, key: &CacheKey) -> Option<WitValue> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((value, expires_at)) if !is_expired(*expires_at, Instant::now()) => {
+                Some(value.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn put(&self, key: CacheKey, value: WitValue, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let now = Instant::now();
+            entries.retain(|_, (_, expires_at)| !is_expired(*expires_at, now));
+            if entries.len() >= self.max_entries {
+                let soonest = entries
+                    .iter()
+                    .min_by_key(|(_, (_, expires_at))| (expires_at.is_none(), *expires_at))
+                    .map(|(key, _)| key.clone());
+                if let Some(soonest) = soonest {
+                    entries.remove(&soonest);
+                }
+            }
+        }
+        entries.insert(key, (value, Instant::now().checked_add(ttl)));
+    }
+}
+
+fn is_expired(expires_at: Option<Instant>, now: Instant) -> bool {
+    matches!(expires_at, Some(expires_at) if expires_at <= now)
+}
+
+/// Configures which functions have their results cached, and for how long. Only pure or read-only functions should be
+/// cached, as the cached invocations do not reach the workers.
+#[derive(Debug, Clone, Default)]
+pub struct Caching {
+    ttls: HashMap<String, Duration>,
+}
+
+impl Caching {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caches the results of a function, by its remote name like `rpc:counter/api.{get}`, for the given time
+    pub fn function(mut self, function_name: impl Into<String>, ttl: Duration) -> Self {
+        self.ttls.insert(function_name.into(), ttl);
+        self
+    }
+}
+
+/// Answers the repeated identical invocations of the cached functions from an [RpcCache]. Only the successful results
+/// are cached.
+pub struct CachingTransport<T> {
+    inner: T,
+    cache: Arc<dyn RpcCache>,
+    config: Caching,
+    metrics: Option<Arc<dyn RpcMetrics>>,
+}
+
+impl<T: RpcTransport> CachingTransport<T> {
+    pub fn new(inner: T, cache: Arc<dyn RpcCache>, config: Caching) -> Self {
+        Self {
+            inner,
+            cache,
+            config,
+            metrics: None,
+        }
+    }
+
+    /// Reports the cache hits and misses
+    pub fn with_metrics(mut self, metrics: Arc<dyn RpcMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for CachingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let Some(ttl) = self.config.ttls.get(function_name) else {
+            return self
+                .inner
+                .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+                .await;
+        };
+
+        let key = CacheKey::new(uri, function_name, &function_params);
+        let cached = self.cache.get(&key).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.cache_lookup(uri, function_name, cached.is_some());
+        }
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+        let result = self
+            .inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await;
+        if let Ok(value) = &result {
+            self.cache.put(key, value.clone(), *ttl).await;
+        }
+        result
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        self.inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{CacheKey, Caching, CachingTransport, InMemoryCache, RpcCache};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Returns the number of invocations so far
+    #[derive(Default)]
+    struct Counter {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Counter {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            Ok(Value::U32(self.calls.fetch_add(1, Ordering::SeqCst)).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn identical_invocations_of_cached_functions_are_answered_from_the_cache() {
+        let transport = CachingTransport::new(
+            Counter::default(),
+            Arc::new(InMemoryCache::new(16)),
+            Caching::new()
+                .function("rpc:counter/api.{get}", Duration::from_secs(60))
+                .function("rpc:counter/api.{peek}", Duration::ZERO),
+        );
+        let worker1 = Uri::worker("component", "worker-1");
+        let worker2 = Uri::worker("component", "worker-2");
+        let transport = &transport;
+        let invoke = |uri, function_name, param: u32| async move {
+            let result = transport
+                .invoke_and_await(uri, function_name, vec![Value::U32(param).into()])
+                .await
+                .unwrap();
+            Value::from(result)
+        };
+
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{get}", 1).await,
+            Value::U32(0)
+        );
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{get}", 1).await,
+            Value::U32(0)
+        );
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{get}", 2).await,
+            Value::U32(1)
+        );
+        assert_eq!(
+            invoke(&worker2, "rpc:counter/api.{get}", 1).await,
+            Value::U32(2)
+        );
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{add}", 1).await,
+            Value::U32(3)
+        );
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{add}", 1).await,
+            Value::U32(4)
+        );
+        // Expired immediately
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{peek}", 1).await,
+            Value::U32(5)
+        );
+        assert_eq!(
+            invoke(&worker1, "rpc:counter/api.{peek}", 1).await,
+            Value::U32(6)
+        );
+    }
+
+    #[tokio::test]
+    async fn results_can_be_cached_forever() {
+        let cache = InMemoryCache::new(1);
+        let uri = Uri::worker("component", "worker-1");
+        let key =
+            |param: u32| CacheKey::new(&uri, "rpc:counter/api.{get}", &[Value::U32(param).into()]);
+
+        cache
+            .put(key(1), Value::U32(10).into(), Duration::MAX)
+            .await;
+        assert_eq!(
+            cache.get(&key(1)).await.map(Value::from),
+            Some(Value::U32(10))
+        );
+        assert!(cache.get(&key(2)).await.is_none());
+
+        // The entries expiring are evicted before the ones which never do
+        let cache = InMemoryCache::new(2);
+        cache
+            .put(key(1), Value::U32(10).into(), Duration::MAX)
+            .await;
+        cache
+            .put(key(2), Value::U32(20).into(), Duration::from_secs(60))
+            .await;
+        cache
+            .put(key(3), Value::U32(30).into(), Duration::from_secs(60))
+            .await;
+        assert!(cache.get(&key(1)).await.is_some());
+        assert!(cache.get(&key(2)).await.is_none());
+    }
+}
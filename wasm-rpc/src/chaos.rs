@@ -0,0 +1,447 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::selector::fnv1a;
+use crate::transport::RpcTransport;
+use crate::uri::WorkerUri;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The distribution of the latency injected into the invocations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    Constant(Duration),
+    /// Uniformly distributed between the bounds
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Exponentially distributed with the given mean, like the waiting times of independent events
+    Exponential {
+        mean: Duration,
+    },
+}
+
+impl Latency {
+    fn sample(&self, rng: &mut SplitMix64) -> Duration {
+        match self {
+            Latency::Constant(latency) => *latency,
+            Latency::Uniform { min, max } if max > min => {
+                *min + (*max - *min).mul_f64(rng.next_f64())
+            }
+            Latency::Uniform { min, .. } => *min,
+            Latency::Exponential { mean } => mean.mul_f64(-(1.0 - rng.next_f64()).ln()),
+        }
+    }
+}
+
+/// The faults injected into the invocations of a target. The rates are between 0 and 1.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Faults {
+    latency: Option<Latency>,
+    error_rate: f64,
+    drop_rate: f64,
+    duplicate_rate: f64,
+}
+
+impl Faults {
+    /// No faults, until they are configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays the invocations before they reach the target
+    pub fn with_latency(mut self, latency: Latency) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// The ratio of the invocations failing with a protocol error without reaching the target, like a broken
+    /// connection
+    pub fn with_error_rate(mut self, rate: f64) -> Self {
+        self.error_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The ratio of the oneway invocations which are dropped: they succeed for the caller without reaching the target
+    pub fn with_drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The ratio of the invocations delivered to the target twice, like a request retried by the network. The caller
+    /// gets the result of the first delivery.
+    pub fn with_duplicate_rate(mut self, rate: f64) -> Self {
+        self.duplicate_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// Configures the faults injected into the invocations of each target, for exercising the retry and idempotency logic
+/// of the callers. The faults are drawn from a random number generator per target worker, seeded from the seed and the
+/// URI of the worker, so the same sequence of invocations of a worker gets the same faults in every run.
+#[derive(Debug, Clone, Default)]
+pub struct Chaos {
+    seed: u64,
+    workers: HashMap<WorkerUri, Faults>,
+    components: HashMap<String, Faults>,
+}
+
+impl Chaos {
+    /// No faults, until they are configured
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+
+    /// Injects faults into the invocations of a worker, replacing the faults of its component. The function of the URI,
+    /// if any, is ignored.
+    pub fn worker(mut self, uri: &WorkerUri, faults: Faults) -> Self {
+        self.workers.insert(uri.worker(), faults);
+        self
+    }
+
+    /// Injects faults into the invocations of the workers of a component which have no faults of their own
+    pub fn component(mut self, component_id: impl Into<String>, faults: Faults) -> Self {
+        self.components.insert(component_id.into(), faults);
+        self
+    }
+
+    fn faults(&self, worker: &WorkerUri) -> Option<&Faults> {
+        self.workers
+            .get(worker)
+            .or_else(|| self.components.get(&worker.component_id))
+    }
+}
+
+/// The faults drawn for an invocation
+#[derive(Debug, Default)]
+struct Injected {
+    latency: Duration,
+    error: bool,
+    drop: bool,
+    duplicate: bool,
+}
+
+/// Injects the faults configured by [Chaos] into the invocations performed through the wrapped transport, like a
+/// mock transport or a router of local component instances
+pub struct ChaosTransport<T> {
+    inner: T,
+    config: Chaos,
+    rngs: Mutex<HashMap<WorkerUri, SplitMix64>>,
+}
+
+impl<T: RpcTransport> ChaosTransport<T> {
+    pub fn new(inner: T, config: Chaos) -> Self {
+        Self {
+            inner,
+            config,
+            rngs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Draws the faults of an invocation of the target, and waits for the injected latency
+    async fn inject(&self, uri: &Uri) -> Injected {
+        let injected = self.draw(uri);
+        if !injected.latency.is_zero() {
+            tokio::time::sleep(injected.latency).await;
+        }
+        injected
+    }
+
+    fn draw(&self, uri: &Uri) -> Injected {
+        let Some((worker, faults)) = uri
+            .parse_worker()
+            .ok()
+            .and_then(|worker| Some((worker.worker(), self.config.faults(&worker)?)))
+        else {
+            return Injected::default();
+        };
+        let mut rngs = self.rngs.lock().unwrap();
+        let rng = rngs.entry(worker.clone()).or_insert_with(|| {
+            SplitMix64::new(self.config.seed ^ fnv1a(worker.to_string().as_bytes()))
+        });
+        Injected {
+            latency: faults
+                .latency
+                .map(|latency| latency.sample(rng))
+                .unwrap_or_default(),
+            error: rng.chance(faults.error_rate),
+            drop: rng.chance(faults.drop_rate),
+            duplicate: rng.chance(faults.duplicate_rate),
+        }
+    }
+}
+
+fn injected_error(uri: &Uri) -> RpcError {
+    RpcError::ProtocolError(format!("Injected fault: the invocation of {uri} failed"))
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for ChaosTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let injected = self.inject(uri).await;
+        if injected.error {
+            return Err(injected_error(uri));
+        }
+        if !injected.duplicate {
+            return self
+                .inner
+                .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+                .await;
+        }
+        let result = self
+            .inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params.clone(), metadata)
+            .await;
+        let _ = self
+            .inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await;
+        result
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        let injected = self.inject(uri).await;
+        if injected.error {
+            return Err(injected_error(uri));
+        }
+        if injected.drop {
+            return Ok(());
+        }
+        if injected.duplicate {
+            let _ = self
+                .inner
+                .invoke_with_metadata(uri, function_name, function_params.clone(), metadata)
+                .await;
+        }
+        self.inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+/// A small deterministic random number generator, so the injected faults can be reproduced from the seed
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && self.next_f64() < rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chaos::{Chaos, ChaosTransport, Faults, Latency, SplitMix64};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue, WorkerUri};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Counts the delivered invocations, returning their sequence number
+    #[derive(Default)]
+    struct Worker {
+        delivered: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Worker {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            let delivered = self.delivered.fetch_add(1, Ordering::SeqCst);
+            Ok(Value::U64(delivered as u64).into())
+        }
+    }
+
+    fn worker(name: &str) -> WorkerUri {
+        WorkerUri::new("counter", name)
+    }
+
+    /// Whether each of the invocations of a worker failed
+    async fn failures(chaos: Chaos, name: &str, count: usize) -> Vec<bool> {
+        let transport = ChaosTransport::new(Worker::default(), chaos);
+        let uri = Uri::from(worker(name));
+        let mut failures = Vec::new();
+        for _ in 0..count {
+            let result = transport.invoke_and_await(&uri, "get", vec![]).await;
+            failures.push(result.is_err());
+        }
+        failures
+    }
+
+    #[tokio::test]
+    async fn faults_are_reproducible_from_the_seed() {
+        let chaos =
+            |seed| Chaos::new(seed).component("counter", Faults::new().with_error_rate(0.5));
+
+        let first = failures(chaos(42), "worker-1", 200).await;
+        assert_eq!(first, failures(chaos(42), "worker-1", 200).await);
+        assert_ne!(first, failures(chaos(7), "worker-1", 200).await);
+        assert_ne!(first, failures(chaos(42), "worker-2", 200).await);
+        let failed = first.iter().filter(|failed| **failed).count();
+        assert!((60..140).contains(&failed), "{failed} failures");
+
+        let unaffected = Chaos::new(42).component("other", Faults::new().with_error_rate(1.0));
+        assert!(!failures(unaffected, "worker-1", 20).await.contains(&true));
+    }
+
+    #[tokio::test]
+    async fn worker_faults_replace_the_faults_of_their_component() {
+        let chaos = Chaos::new(0)
+            .component("counter", Faults::new().with_error_rate(1.0))
+            .worker(&worker("healthy").with_function("get"), Faults::new());
+
+        assert!(!failures(chaos.clone(), "healthy", 20).await.contains(&true));
+        assert!(!failures(chaos, "worker-1", 20).await.contains(&false));
+    }
+
+    #[tokio::test]
+    async fn duplicated_invocations_are_delivered_twice() {
+        let chaos = Chaos::new(0).component("counter", Faults::new().with_duplicate_rate(1.0));
+        let transport = ChaosTransport::new(Worker::default(), chaos);
+        let uri = Uri::from(worker("worker-1"));
+
+        let result = transport
+            .invoke_and_await(&uri, "add", vec![])
+            .await
+            .unwrap();
+        // The caller gets the result of the first delivery
+        assert_eq!(Value::from(result), Value::U64(0));
+        transport.invoke(&uri, "add", vec![]).await.unwrap();
+        assert_eq!(transport.inner.delivered.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn dropped_oneway_invocations_do_not_reach_the_target() {
+        let chaos = Chaos::new(0).component("counter", Faults::new().with_drop_rate(1.0));
+        let transport = ChaosTransport::new(Worker::default(), chaos);
+        let uri = Uri::from(worker("worker-1"));
+
+        transport.invoke(&uri, "add", vec![]).await.unwrap();
+        assert_eq!(transport.inner.delivered.load(Ordering::SeqCst), 0);
+        // Awaited invocations are not dropped
+        transport
+            .invoke_and_await(&uri, "get", vec![])
+            .await
+            .unwrap();
+        assert_eq!(transport.inner.delivered.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn latency_is_injected_before_the_delivery() {
+        let latency = Duration::from_millis(20);
+        let chaos = Chaos::new(0).worker(
+            &worker("slow"),
+            Faults::new().with_latency(Latency::Constant(latency)),
+        );
+        let transport = ChaosTransport::new(Worker::default(), chaos);
+
+        let started = Instant::now();
+        transport
+            .invoke_and_await(&Uri::from(worker("slow")), "get", vec![])
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= latency);
+    }
+
+    #[test]
+    fn latencies_follow_their_distribution() {
+        let mut rng = SplitMix64::new(1);
+        let (min, max) = (Duration::from_millis(10), Duration::from_millis(50));
+        let uniform = Latency::Uniform { min, max };
+        let samples = (0..1000)
+            .map(|_| uniform.sample(&mut rng))
+            .collect::<Vec<_>>();
+        assert!(samples.iter().all(|latency| (min..max).contains(latency)));
+
+        let mean = Duration::from_millis(20);
+        let exponential = Latency::Exponential { mean };
+        let total = (0..10000)
+            .map(|_| exponential.sample(&mut rng))
+            .sum::<Duration>();
+        let sampled_mean = total / 10000;
+        assert!(
+            sampled_mean > Duration::from_millis(18) && sampled_mean < Duration::from_millis(22),
+            "{sampled_mean:?}"
+        );
+    }
+}
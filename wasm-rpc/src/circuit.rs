@@ -0,0 +1,469 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::metrics::RpcMetrics;
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The state of the circuit breaker of a target worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The invocations are passed to the worker
+    Closed,
+    /// The worker failed too many times, the invocations are rejected
+    Open,
+    /// The worker is probed with a limited number of invocations, to decide whether to close the circuit again
+    HalfOpen,
+}
+
+/// Configures the circuit breakers of the target workers
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    open_duration: Duration,
+    half_open_probes: u32,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+            half_open_probes: 1,
+        }
+    }
+}
+
+impl CircuitBreakers {
+    /// Opens the circuit after 5 consecutive failures for 30 seconds, then probes the worker with a single invocation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of consecutive failed invocations opening the circuit, at least one
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// How long the circuit stays open before probing the worker again
+    pub fn with_open_duration(mut self, open_duration: Duration) -> Self {
+        self.open_duration = open_duration;
+        self
+    }
+
+    /// The number of invocations let through concurrently to probe a worker with a half-open circuit, at least one
+    pub fn with_half_open_probes(mut self, half_open_probes: u32) -> Self {
+        self.half_open_probes = half_open_probes.max(1);
+        self
+    }
+}
+
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Instant,
+    probes: u32,
+}
+
+/// Rejects the invocations of the workers failing repeatedly, so a worker which is down does not slow down all its
+/// callers. Only the failures to reach or run the worker count, not the ones caused by the caller, like `denied` and
+/// `not-found`.
+pub struct CircuitBreakingTransport<T> {
+    inner: T,
+    config: CircuitBreakers,
+    metrics: Option<Arc<dyn RpcMetrics>>,
+    /// The circuits of the workers which failed recently, the other ones are closed
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl<T: RpcTransport> CircuitBreakingTransport<T> {
+    pub fn new(inner: T, config: CircuitBreakers) -> Self {
+        Self {
+            inner,
+            config,
+            metrics: None,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reports the state changes and the rejected invocations
+    pub fn with_metrics(mut self, metrics: Arc<dyn RpcMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn state(&self, uri: &Uri) -> CircuitState {
+        self.circuits
+            .lock()
+            .unwrap()
+            .get(&uri.value)
+            .map(|circuit| circuit.state)
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// The permit of an invocation, failing if the circuit rejects it
+    fn permit<'a>(&'a self, uri: &'a Uri) -> Result<Permit<'a, T>, RpcError> {
+        let (permit, changed) = self.acquire(uri);
+        self.report(uri, changed);
+        permit.ok_or_else(|| {
+            if let Some(metrics) = &self.metrics {
+                metrics.circuit_rejected(uri);
+            }
+            RpcError::ProtocolError(format!(
+                "The circuit breaker of {} is open after repeated failures",
+                uri.value
+            ))
+        })
+    }
+
+    /// Decides whether the invocation can be passed to the worker, returning the permit recording its outcome, and
+    /// the new state of the circuit if it changed
+    fn acquire<'a>(&'a self, uri: &'a Uri) -> (Option<Permit<'a, T>>, Option<CircuitState>) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let permit = |probe| {
+            Some(Permit {
+                transport: self,
+                uri,
+                probe,
+                completed: false,
+            })
+        };
+        let Some(circuit) = circuits.get_mut(&uri.value) else {
+            return (permit(false), None);
+        };
+        let mut changed = None;
+        if circuit.state == CircuitState::Open
+            && circuit.opened_at.elapsed() >= self.config.open_duration
+        {
+            circuit.state = CircuitState::HalfOpen;
+            circuit.probes = 0;
+            changed = Some(CircuitState::HalfOpen);
+        }
+        let permit = match circuit.state {
+            CircuitState::Closed => permit(false),
+            CircuitState::Open => None,
+            CircuitState::HalfOpen if circuit.probes < self.config.half_open_probes => {
+                circuit.probes += 1;
+                permit(true)
+            }
+            CircuitState::HalfOpen => None,
+        };
+        (permit, changed)
+    }
+}
+
+impl<T> CircuitBreakingTransport<T> {
+    /// Records the outcome of an invocation, and returns the new state of the circuit if it changed
+    fn complete(&self, uri: &Uri, failed: bool) -> Option<CircuitState> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let state = circuits
+            .get(&uri.value)
+            .map(|circuit| circuit.state)
+            .unwrap_or(CircuitState::Closed);
+        match (state, failed) {
+            (CircuitState::Closed, false) => {
+                circuits.remove(&uri.value);
+                None
+            }
+            (CircuitState::HalfOpen, false) => {
+                circuits.remove(&uri.value);
+                Some(CircuitState::Closed)
+            }
+            (CircuitState::Closed, true) => {
+                let circuit = circuits.entry(uri.value.clone()).or_insert(Circuit {
+                    state: CircuitState::Closed,
+                    failures: 0,
+                    opened_at: Instant::now(),
+                    probes: 0,
+                });
+                circuit.failures += 1;
+                (circuit.failures >= self.config.failure_threshold).then(|| {
+                    circuit.state = CircuitState::Open;
+                    circuit.opened_at = Instant::now();
+                    CircuitState::Open
+                })
+            }
+            (CircuitState::HalfOpen, true) => {
+                let circuit = circuits.get_mut(&uri.value)?;
+                circuit.state = CircuitState::Open;
+                circuit.opened_at = Instant::now();
+                Some(CircuitState::Open)
+            }
+            // Invocations started before the circuit was opened
+            (CircuitState::Open, _) => None,
+        }
+    }
+
+    fn report(&self, uri: &Uri, changed: Option<CircuitState>) {
+        if let (Some(state), Some(metrics)) = (changed, &self.metrics) {
+            metrics.circuit_state_changed(uri, state);
+        }
+    }
+}
+
+/// An invocation let through the circuit, recording its outcome when it completes. A probe of a half-open circuit
+/// dropped before completing, like one cancelled by a timeout or losing a hedge, counts as failed, so the circuit does
+/// not wait for it forever.
+struct Permit<'a, T> {
+    transport: &'a CircuitBreakingTransport<T>,
+    uri: &'a Uri,
+    probe: bool,
+    completed: bool,
+}
+
+impl<T> Permit<'_, T> {
+    fn complete(mut self, failed: bool) {
+        self.completed = true;
+        let changed = self.transport.complete(self.uri, failed);
+        self.transport.report(self.uri, changed);
+    }
+}
+
+impl<T> Drop for Permit<'_, T> {
+    fn drop(&mut self) {
+        if !self.completed && self.probe {
+            let changed = self.transport.complete(self.uri, true);
+            self.transport.report(self.uri, changed);
+        }
+    }
+}
+
+fn is_failure<T>(result: &Result<T, RpcError>) -> bool {
+    matches!(
+        result,
+        Err(RpcError::ProtocolError(_)) | Err(RpcError::RemoteInternalError(_))
+    )
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for CircuitBreakingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let permit = self.permit(uri)?;
+        let result = self
+            .inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await;
+        permit.complete(is_failure(&result));
+        result
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    /// The circuit only sees whether the oneway invocations could be delivered
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        let permit = self.permit(uri)?;
+        let result = self
+            .inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await;
+        permit.complete(is_failure(&result));
+        result
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circuit::{CircuitBreakers, CircuitBreakingTransport, CircuitState};
+    use crate::metrics::RpcMetrics;
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Worker {
+        down: AtomicBool,
+        hanging: AtomicBool,
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Worker {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.hanging.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            if self.down.load(Ordering::SeqCst) {
+                Err(RpcError::ProtocolError("connection refused".to_string()))
+            } else {
+                Ok(Value::Tuple(vec![]).into())
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Metrics {
+        states: Mutex<Vec<CircuitState>>,
+        rejected: AtomicU32,
+    }
+
+    impl RpcMetrics for Metrics {
+        fn circuit_state_changed(&self, _uri: &Uri, state: CircuitState) {
+            self.states.lock().unwrap().push(state);
+        }
+
+        fn circuit_rejected(&self, _uri: &Uri) {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn circuits_open_and_close_per_worker() {
+        tokio::time::pause();
+        let worker = Arc::new(Worker::default());
+        let metrics = Arc::new(Metrics::default());
+        let transport = CircuitBreakingTransport::new(
+            worker.clone(),
+            CircuitBreakers::new()
+                .with_failure_threshold(2)
+                .with_open_duration(Duration::from_millis(50)),
+        )
+        .with_metrics(metrics.clone());
+        let failing = Uri::worker("component", "worker-1");
+        let other = Uri::worker("component", "worker-2");
+
+        worker.down.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            transport
+                .invoke_and_await(&failing, "run", vec![])
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(transport.state(&failing), CircuitState::Open);
+        assert_eq!(transport.state(&other), CircuitState::Closed);
+
+        transport
+            .invoke_and_await(&failing, "run", vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(worker.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(metrics.rejected.load(Ordering::SeqCst), 1);
+
+        // A failed probe opens the circuit again, a successful one closes it
+        tokio::time::advance(Duration::from_millis(60)).await;
+        transport
+            .invoke_and_await(&failing, "run", vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(transport.state(&failing), CircuitState::Open);
+        worker.down.store(false, Ordering::SeqCst);
+        tokio::time::advance(Duration::from_millis(60)).await;
+        transport
+            .invoke_and_await(&failing, "run", vec![])
+            .await
+            .unwrap();
+        assert_eq!(transport.state(&failing), CircuitState::Closed);
+
+        assert_eq!(
+            *metrics.states.lock().unwrap(),
+            vec![
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dropped_probes_open_the_circuit_again() {
+        tokio::time::pause();
+        let worker = Arc::new(Worker::default());
+        let transport = CircuitBreakingTransport::new(
+            worker.clone(),
+            CircuitBreakers::new()
+                .with_failure_threshold(1)
+                .with_open_duration(Duration::from_millis(50)),
+        );
+        let uri = Uri::worker("component", "worker-1");
+
+        worker.down.store(true, Ordering::SeqCst);
+        transport
+            .invoke_and_await(&uri, "run", vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(transport.state(&uri), CircuitState::Open);
+
+        // The probe is cancelled by a timeout while the worker hangs
+        worker.hanging.store(true, Ordering::SeqCst);
+        tokio::time::advance(Duration::from_millis(60)).await;
+        let probe = transport.invoke_and_await(&uri, "run", vec![]);
+        tokio::time::timeout(Duration::from_millis(10), probe)
+            .await
+            .unwrap_err();
+        assert_eq!(transport.state(&uri), CircuitState::Open);
+
+        worker.hanging.store(false, Ordering::SeqCst);
+        worker.down.store(false, Ordering::SeqCst);
+        tokio::time::advance(Duration::from_millis(60)).await;
+        transport
+            .invoke_and_await(&uri, "run", vec![])
+            .await
+            .unwrap();
+        assert_eq!(transport.state(&uri), CircuitState::Closed);
+    }
+}
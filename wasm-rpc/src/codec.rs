@@ -0,0 +1,861 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::transport::RpcTransport;
+use crate::{
+    NodeBuilder, RpcError, Uri, Value, WitNodePointer, WitValue, WitValueBuilderExtensions,
+};
+use async_trait::async_trait;
+use serde_json::{json, Map, Number};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// The function invoked by the sending side to negotiate the codec of a connection. The receiving side answers it
+/// itself, without dispatching it to the target worker.
+pub const NEGOTIATE_FUNCTION: &str = "golem:rpc/codec.{negotiate}";
+
+/// The metadata entry of the negotiation, listing the codecs supported by the sending side
+pub const ACCEPT_CODECS: &str = "accept-codecs";
+
+/// The metadata entry naming the codec of the encoded parameters of an invocation. Its result is encoded with the
+/// same codec.
+pub const CONTENT_CODEC: &str = "content-codec";
+
+/// The encodings of the payloads of the invocations, from the most to the least efficient one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    Bincode,
+    Protobuf,
+    /// A tagged JSON form of the values, readable without their types, for debugging
+    Json,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Bincode, Codec::Protobuf, Codec::Json];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Bincode => "bincode",
+            Codec::Protobuf => "protobuf",
+            Codec::Json => "json",
+        }
+    }
+
+    /// Whether the codec is compiled in. The protobuf codec needs the `protobuf` feature.
+    pub fn is_available(&self) -> bool {
+        cfg!(feature = "protobuf") || *self != Codec::Protobuf
+    }
+
+    pub fn encode(&self, value: &WitValue) -> Result<Vec<u8>, RpcError> {
+        match self {
+            Codec::Bincode => bincode::encode_to_vec(value, bincode::config::standard())
+                .map_err(|err| encoding_error(self, err)),
+            #[cfg(feature = "protobuf")]
+            Codec::Protobuf => {
+                let mut buf = Vec::new();
+                crate::protobuf::encode_into(value, &mut buf);
+                Ok(buf)
+            }
+            #[cfg(not(feature = "protobuf"))]
+            Codec::Protobuf => Err(unavailable(self)),
+            Codec::Json => {
+                let value = Value::try_from_wit_value(value.clone())
+                    .map_err(|err| encoding_error(self, err))?;
+                serde_json::to_vec(&to_json(&value)).map_err(|err| encoding_error(self, err))
+            }
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<WitValue, RpcError> {
+        match self {
+            Codec::Bincode => bincode::decode_from_slice(bytes, bincode::config::standard())
+                .map(|(value, _)| value)
+                .map_err(|err| decoding_error(self, err)),
+            #[cfg(feature = "protobuf")]
+            Codec::Protobuf => {
+                crate::protobuf::decode(bytes).map_err(|err| decoding_error(self, err))
+            }
+            #[cfg(not(feature = "protobuf"))]
+            Codec::Protobuf => Err(unavailable(self)),
+            Codec::Json => {
+                let json =
+                    serde_json::from_slice(bytes).map_err(|err| decoding_error(self, err))?;
+                from_json(&json)
+                    .map(WitValue::from)
+                    .map_err(|err| decoding_error(self, err))
+            }
+        }
+    }
+
+    /// Encodes the parameters of an invocation as a single `list<u8>` parameter
+    pub fn encode_params(&self, function_params: Vec<WitValue>) -> Result<WitValue, RpcError> {
+        let params = function_params
+            .into_iter()
+            .map(Value::try_from_wit_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| encoding_error(self, err))?;
+        let params = Value::Tuple(params);
+        Ok(WitValue::builder().bytes(&self.encode(&params.into())?))
+    }
+
+    pub fn decode_params(&self, encoded: Vec<WitValue>) -> Result<Vec<WitValue>, RpcError> {
+        let [encoded] = <[WitValue; 1]>::try_from(encoded).map_err(|_| invalid(self))?;
+        let params = self.decode(&payload_bytes(&encoded).ok_or_else(|| invalid(self))?)?;
+        match Value::try_from_wit_value(params) {
+            Ok(Value::Tuple(params)) => Ok(params.into_iter().map(WitValue::from).collect()),
+            _ => Err(invalid(self)),
+        }
+    }
+
+    /// Encodes the result of an invocation as a `list<u8>` value
+    pub fn encode_result(&self, result: &WitValue) -> Result<WitValue, RpcError> {
+        Ok(WitValue::builder().bytes(&self.encode(result)?))
+    }
+
+    pub fn decode_result(&self, encoded: WitValue) -> Result<WitValue, RpcError> {
+        self.decode(&payload_bytes(&encoded).ok_or_else(|| invalid(self))?)
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Codec::ALL
+            .into_iter()
+            .find(|codec| codec.name() == s.trim())
+            .ok_or_else(|| format!("Unknown payload codec: {s}"))
+    }
+}
+
+fn encoding_error(codec: &Codec, err: impl Display) -> RpcError {
+    RpcError::ProtocolError(format!("Failed to encode the payload with {codec}: {err}"))
+}
+
+fn decoding_error(codec: &Codec, err: impl Display) -> RpcError {
+    RpcError::ProtocolError(format!("Failed to decode the payload with {codec}: {err}"))
+}
+
+fn invalid(codec: &Codec) -> RpcError {
+    RpcError::ProtocolError(format!("Invalid {codec} encoded payload"))
+}
+
+#[cfg(not(feature = "protobuf"))]
+fn unavailable(codec: &Codec) -> RpcError {
+    RpcError::ProtocolError(format!("The {codec} payload codec is not available"))
+}
+
+fn payload_bytes(value: &WitValue) -> Option<Vec<u8>> {
+    list_bytes(WitNodePointer::root(value)?)
+}
+
+/// The bytes of a `list<u8>` payload, built as a single `prim-bytes` node with [NodeBuilder::bytes], or as a node per
+/// byte by the peers not compacting them
+pub(crate) fn list_bytes(value: WitNodePointer) -> Option<Vec<u8>> {
+    match value.bytes() {
+        Some(bytes) => Some(bytes.to_vec()),
+        None => value.list_iter()?.map(|byte| byte.u8()).collect(),
+    }
+}
+
+/// The codecs supported by one side of the connections. The sending side offers them in the negotiation, and the
+/// receiving side picks the most efficient one it supports too. Connections without a mutually supported codec, or
+/// to receivers not taking part in the negotiation, pass the values as they are.
+#[derive(Debug, Clone)]
+pub struct Codecs {
+    supported: Vec<Codec>,
+    forced: Option<Codec>,
+}
+
+impl Codecs {
+    /// Supports every available codec
+    pub fn new() -> Self {
+        Self {
+            supported: Codec::ALL
+                .into_iter()
+                .filter(|codec| codec.is_available())
+                .collect(),
+            forced: None,
+        }
+    }
+
+    /// Supports only the given codecs, still preferring the more efficient ones
+    pub fn supported(mut self, codecs: impl IntoIterator<Item = Codec>) -> Self {
+        let codecs = codecs.into_iter().collect::<Vec<_>>();
+        self.supported.retain(|codec| codecs.contains(codec));
+        self
+    }
+
+    /// Encodes every payload with the codec without negotiating it, like for debugging with [Codec::Json]. The
+    /// invocations fail if the receiving side does not support it.
+    pub fn forced(mut self, codec: Codec) -> Self {
+        self.forced = Some(codec);
+        self
+    }
+
+    /// The most efficient of the offered codecs supported by this side
+    pub fn select(&self, offered: &[Codec]) -> Option<Codec> {
+        self.supported
+            .iter()
+            .find(|codec| offered.contains(codec))
+            .copied()
+    }
+
+    /// Answers a negotiation of the sending side with a `tuple<option<string>>` of the selected codec
+    pub fn answer(&self, metadata: &[(String, String)]) -> WitValue {
+        let offered = metadata
+            .iter()
+            .filter(|(name, _)| name == ACCEPT_CODECS)
+            .flat_map(|(_, codecs)| codecs.split(','))
+            .filter_map(|name| name.parse().ok())
+            .collect::<Vec<_>>();
+        let selected = self
+            .select(&offered)
+            .map(|codec| Box::new(Value::String(codec.name().to_string())));
+        Value::Tuple(vec![Value::Option(selected)]).into()
+    }
+
+    /// The codec of the payloads of an incoming invocation, failing if it is not supported by this side
+    pub fn content_codec(&self, metadata: &[(String, String)]) -> Result<Option<Codec>, RpcError> {
+        let Some((_, name)) = metadata.iter().find(|(name, _)| name == CONTENT_CODEC) else {
+            return Ok(None);
+        };
+        match name.parse() {
+            Ok(codec) if self.supported.contains(&codec) => Ok(Some(codec)),
+            _ => Err(RpcError::ProtocolError(format!(
+                "Unsupported payload codec: {name}"
+            ))),
+        }
+    }
+
+    fn offer(&self) -> String {
+        self.supported
+            .iter()
+            .map(|codec| codec.name())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Default for Codecs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Negotiates the codec of the payloads with each target worker on the first invocation of the connection, and
+/// encodes the parameters and decodes the results of the invocations with it. The negotiation is repeated after the
+/// connection is reconnected, and on the next invocation when the target could not answer it.
+pub struct CodecTransport<T> {
+    inner: T,
+    config: Codecs,
+    negotiated: Mutex<HashMap<String, Option<Codec>>>,
+}
+
+impl<T: RpcTransport> CodecTransport<T> {
+    pub fn new(inner: T, config: Codecs) -> Self {
+        Self {
+            inner,
+            config,
+            negotiated: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The codec negotiated with the worker, if the connection was negotiated already
+    pub fn negotiated(&self, uri: &Uri) -> Option<Option<Codec>> {
+        self.negotiated.lock().unwrap().get(&uri.value).copied()
+    }
+
+    async fn codec(&self, uri: &Uri) -> Option<Codec> {
+        if self.config.forced.is_some() {
+            return self.config.forced;
+        }
+        if let Some(codec) = self.negotiated(uri) {
+            return codec;
+        }
+
+        let metadata = [(ACCEPT_CODECS.to_string(), self.config.offer())];
+        let codec = match self
+            .inner
+            .invoke_and_await_with_metadata(uri, NEGOTIATE_FUNCTION, vec![], &metadata)
+            .await
+        {
+            Ok(answer) => {
+                answered_codec(answer).filter(|codec| self.config.supported.contains(codec))
+            }
+            // The target does not take part in the negotiation
+            Err(RpcError::NotFound(message)) if message.contains(NEGOTIATE_FUNCTION) => None,
+            // The target could not answer, like when the connection failed or the worker is not running, so it is
+            // negotiated again on the next invocation
+            Err(_) => return None,
+        };
+        self.negotiated
+            .lock()
+            .unwrap()
+            .insert(uri.value.clone(), codec);
+        codec
+    }
+}
+
+/// The codec selected by the receiving side in its answer to the negotiation
+fn answered_codec(answer: WitValue) -> Option<Codec> {
+    let Ok(Value::Tuple(fields)) = Value::try_from_wit_value(answer) else {
+        return None;
+    };
+    match fields.as_slice() {
+        [Value::Option(Some(name))] => match name.as_ref() {
+            Value::String(name) => name.parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for CodecTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        match self.codec(uri).await {
+            Some(codec) => {
+                let encoded = codec.encode_params(function_params)?;
+                let mut metadata = metadata.to_vec();
+                metadata.push((CONTENT_CODEC.to_string(), codec.name().to_string()));
+                let result = self
+                    .inner
+                    .invoke_and_await_with_metadata(uri, function_name, vec![encoded], &metadata)
+                    .await?;
+                codec.decode_result(result)
+            }
+            None => {
+                self.inner
+                    .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+                    .await
+            }
+        }
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        match self.codec(uri).await {
+            Some(codec) => {
+                let encoded = codec.encode_params(function_params)?;
+                let mut metadata = metadata.to_vec();
+                metadata.push((CONTENT_CODEC.to_string(), codec.name().to_string()));
+                self.inner
+                    .invoke_with_metadata(uri, function_name, vec![encoded], &metadata)
+                    .await
+            }
+            None => {
+                self.inner
+                    .invoke_with_metadata(uri, function_name, function_params, metadata)
+                    .await
+            }
+        }
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.negotiated.lock().unwrap().remove(&uri.value);
+        self.inner.reconnect(uri).await
+    }
+}
+
+/// The JSON form of a value, tagging every node with its kind so it can be decoded without the type
+fn to_json(value: &Value) -> serde_json::Value {
+    let (tag, json) = match value {
+        Value::Bool(value) => ("bool", json!(value)),
+        Value::U8(value) => ("u8", json!(value)),
+        Value::U16(value) => ("u16", json!(value)),
+        Value::U32(value) => ("u32", json!(value)),
+        Value::U64(value) => ("u64", json!(value)),
+        Value::S8(value) => ("s8", json!(value)),
+        Value::S16(value) => ("s16", json!(value)),
+        Value::S32(value) => ("s32", json!(value)),
+        Value::S64(value) => ("s64", json!(value)),
+        Value::F32(value) => ("f32", float_to_json(*value as f64)),
+        Value::F64(value) => ("f64", float_to_json(*value)),
+        Value::Char(value) => ("char", json!(value)),
+        Value::String(value) => ("string", json!(value)),
+        Value::List(items) => ("list", items_to_json(items)),
+        Value::Tuple(items) => ("tuple", items_to_json(items)),
+        Value::Record(fields) => ("record", items_to_json(fields)),
+        Value::Variant {
+            case_idx,
+            case_value,
+        } => (
+            "variant",
+            json!({ "case": case_idx, "value": optional_to_json(case_value) }),
+        ),
+        Value::Enum(case_idx) => ("enum", json!(case_idx)),
+        Value::Flags(flags) => ("flags", json!(flags)),
+        Value::Option(value) => ("option", optional_to_json(value)),
+        Value::Result(Ok(value)) => ("ok", optional_to_json(value)),
+        Value::Result(Err(value)) => ("err", optional_to_json(value)),
+        Value::Handle { uri, resource_id } => (
+            "handle",
+            json!({ "uri": uri.value, "resource-id": resource_id }),
+        ),
+    };
+    let mut object = Map::new();
+    object.insert(tag.to_string(), json);
+    serde_json::Value::Object(object)
+}
+
+fn items_to_json(items: &[Value]) -> serde_json::Value {
+    serde_json::Value::Array(items.iter().map(to_json).collect())
+}
+
+fn optional_to_json(value: &Option<Box<Value>>) -> serde_json::Value {
+    value
+        .as_ref()
+        .map_or(serde_json::Value::Null, |value| to_json(value))
+}
+
+/// JSON numbers can not be NaN or infinite, so those are passed as strings
+fn float_to_json(value: f64) -> serde_json::Value {
+    match Number::from_f64(value) {
+        Some(number) => serde_json::Value::Number(number),
+        None => json!(value.to_string()),
+    }
+}
+
+fn from_json(json: &serde_json::Value) -> Result<Value, String> {
+    let invalid = || format!("Invalid JSON encoded value: {json}");
+    let Some((tag, json)) = json
+        .as_object()
+        .filter(|object| object.len() == 1)
+        .and_then(|object| object.iter().next())
+    else {
+        return Err(invalid());
+    };
+    let unsigned = || json.as_u64().ok_or_else(invalid);
+    let signed = || json.as_i64().ok_or_else(invalid);
+    let value = match tag.as_str() {
+        "bool" => Value::Bool(json.as_bool().ok_or_else(invalid)?),
+        "u8" => Value::U8(unsigned()?.try_into().map_err(|_| invalid())?),
+        "u16" => Value::U16(unsigned()?.try_into().map_err(|_| invalid())?),
+        "u32" => Value::U32(unsigned()?.try_into().map_err(|_| invalid())?),
+        "u64" => Value::U64(unsigned()?),
+        "s8" => Value::S8(signed()?.try_into().map_err(|_| invalid())?),
+        "s16" => Value::S16(signed()?.try_into().map_err(|_| invalid())?),
+        "s32" => Value::S32(signed()?.try_into().map_err(|_| invalid())?),
+        "s64" => Value::S64(signed()?),
+        "f32" => Value::F32(float_from_json(json).ok_or_else(invalid)? as f32),
+        "f64" => Value::F64(float_from_json(json).ok_or_else(invalid)?),
+        "char" => {
+            let mut chars = json.as_str().ok_or_else(invalid)?.chars();
+            match (chars.next(), chars.next()) {
+                (Some(char), None) => Value::Char(char),
+                _ => return Err(invalid()),
+            }
+        }
+        "string" => Value::String(json.as_str().ok_or_else(invalid)?.to_string()),
+        "list" => Value::List(items_from_json(json)?),
+        "tuple" => Value::Tuple(items_from_json(json)?),
+        "record" => Value::Record(items_from_json(json)?),
+        "variant" => Value::Variant {
+            case_idx: json["case"]
+                .as_u64()
+                .and_then(|case_idx| case_idx.try_into().ok())
+                .ok_or_else(invalid)?,
+            case_value: optional_from_json(&json["value"])?,
+        },
+        "enum" => Value::Enum(unsigned()?.try_into().map_err(|_| invalid())?),
+        "flags" => Value::Flags(
+            json.as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|flag| flag.as_bool().ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+        ),
+        "option" => Value::Option(optional_from_json(json)?),
+        "ok" => Value::Result(Ok(optional_from_json(json)?)),
+        "err" => Value::Result(Err(optional_from_json(json)?)),
+        "handle" => Value::Handle {
+            uri: Uri {
+                value: json["uri"].as_str().ok_or_else(invalid)?.to_string(),
+            },
+            resource_id: json["resource-id"].as_u64().ok_or_else(invalid)?,
+        },
+        _ => return Err(invalid()),
+    };
+    Ok(value)
+}
+
+fn items_from_json(json: &serde_json::Value) -> Result<Vec<Value>, String> {
+    json.as_array()
+        .ok_or_else(|| format!("Invalid JSON encoded value: {json}"))?
+        .iter()
+        .map(from_json)
+        .collect()
+}
+
+fn optional_from_json(json: &serde_json::Value) -> Result<Option<Box<Value>>, String> {
+    match json {
+        serde_json::Value::Null => Ok(None),
+        json => Ok(Some(Box::new(from_json(json)?))),
+    }
+}
+
+fn float_from_json(json: &serde_json::Value) -> Option<f64> {
+    json.as_f64().or_else(|| json.as_str()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::{Codec, CodecTransport, Codecs, CONTENT_CODEC};
+    use crate::intercept::IncomingCall;
+    use crate::router::{RpcHandler, RpcRouter};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    fn sample() -> Value {
+        Value::Record(vec![
+            Value::U64(u64::MAX),
+            Value::S64(i64::MIN),
+            Value::F32(1.5),
+            Value::F64(f64::INFINITY),
+            Value::Char('λ'),
+            Value::List(vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string()),
+            ]),
+            Value::Variant {
+                case_idx: 2,
+                case_value: Some(Box::new(Value::Flags(vec![true, false]))),
+            },
+            Value::Option(None),
+            Value::Result(Err(Some(Box::new(Value::Enum(1))))),
+            Value::Handle {
+                uri: Uri::worker("counter", "worker-1"),
+                resource_id: 7,
+            },
+        ])
+    }
+
+    #[test]
+    fn values_survive_the_available_codecs() {
+        for codec in Codec::ALL.into_iter().filter(Codec::is_available) {
+            let encoded = codec.encode(&sample().into()).unwrap();
+            assert_eq!(Value::from(codec.decode(&encoded).unwrap()), sample());
+
+            let params = codec
+                .encode_params(vec![sample().into(), Value::Bool(true).into()])
+                .unwrap();
+            let decoded = codec.decode_params(vec![params]).unwrap();
+            assert_eq!(
+                decoded.into_iter().map(Value::from).collect::<Vec<_>>(),
+                vec![sample(), Value::Bool(true)]
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_params_are_rejected() {
+        let malformed = WitValue {
+            nodes: vec![crate::WitNode::ListValue(vec![1])],
+        };
+        for codec in Codec::ALL.into_iter().filter(Codec::is_available) {
+            let result = codec.encode_params(vec![malformed.clone()]);
+            assert!(matches!(result, Err(RpcError::ProtocolError(_))));
+        }
+        let result = Codec::Json.encode(&malformed);
+        assert!(matches!(result, Err(RpcError::ProtocolError(_))));
+    }
+
+    /// Returns the parameters and the metadata of the invocation
+    struct Echo;
+
+    #[async_trait]
+    impl RpcHandler for Echo {
+        async fn handle(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+            let metadata = call
+                .metadata
+                .iter()
+                .map(|(name, value)| Value::String(format!("{name}={value}")))
+                .collect();
+            let params = call.function_params.iter().cloned().map(Value::from);
+            Ok(Value::Tuple(params.chain([Value::List(metadata)]).collect()).into())
+        }
+    }
+
+    fn router(codecs: Codecs) -> RpcRouter {
+        let router = RpcRouter::new().codecs(codecs);
+        router.register_component("counter", Echo);
+        router
+    }
+
+    async fn echo(transport: &impl RpcTransport, uri: &Uri) -> Result<Value, RpcError> {
+        let metadata = vec![("tenant".to_string(), "acme".to_string())];
+        transport
+            .invoke_and_await_with_metadata(
+                uri,
+                "rpc:counter/api.{add}",
+                vec![sample().into()],
+                &metadata,
+            )
+            .await
+            .map(Value::from)
+    }
+
+    fn echoed() -> Value {
+        Value::Tuple(vec![
+            sample(),
+            Value::List(vec![Value::String("tenant=acme".to_string())]),
+        ])
+    }
+
+    #[tokio::test]
+    async fn the_most_efficient_mutual_codec_is_negotiated() {
+        let uri = Uri::worker("counter", "worker-1");
+        let transport = CodecTransport::new(router(Codecs::new()), Codecs::new());
+        assert_eq!(transport.negotiated(&uri), None);
+        assert_eq!(echo(&transport, &uri).await.unwrap(), echoed());
+        assert_eq!(transport.negotiated(&uri), Some(Some(Codec::ALL[0])));
+
+        let receiver = router(Codecs::new().supported([Codec::Json]));
+        let transport = CodecTransport::new(receiver, Codecs::new());
+        assert_eq!(echo(&transport, &uri).await.unwrap(), echoed());
+        assert_eq!(transport.negotiated(&uri), Some(Some(Codec::Json)));
+
+        let receiver = router(Codecs::new().supported([Codec::Json]));
+        let transport = CodecTransport::new(receiver, Codecs::new().supported([Codec::Bincode]));
+        assert_eq!(echo(&transport, &uri).await.unwrap(), echoed());
+        assert_eq!(transport.negotiated(&uri), Some(None));
+    }
+
+    #[tokio::test]
+    async fn forced_codecs_are_not_negotiated() {
+        let uri = Uri::worker("counter", "worker-1");
+        let transport =
+            CodecTransport::new(router(Codecs::new()), Codecs::new().forced(Codec::Json));
+        assert_eq!(echo(&transport, &uri).await.unwrap(), echoed());
+        assert_eq!(transport.negotiated(&uri), None);
+
+        let receiver = router(Codecs::new().supported([Codec::Bincode]));
+        let transport = CodecTransport::new(receiver, Codecs::new().forced(Codec::Json));
+        let result = echo(&transport, &uri).await;
+        assert!(matches!(result, Err(RpcError::ProtocolError(_))));
+    }
+
+    /// A receiver not taking part in the negotiation, counting its invocations
+    #[derive(Default)]
+    struct Plain {
+        functions: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Plain {
+        async fn invoke_and_await(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+                .await
+        }
+
+        async fn invoke_and_await_with_metadata(
+            &self,
+            _uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+            _metadata: &[(String, String)],
+        ) -> Result<WitValue, RpcError> {
+            self.functions
+                .lock()
+                .unwrap()
+                .push(function_name.to_string());
+            if function_name == "rpc:counter/api.{add}" {
+                Ok(Value::Tuple(function_params.into_iter().map(Value::from).collect()).into())
+            } else {
+                Err(RpcError::NotFound(function_name.to_string()))
+            }
+        }
+    }
+
+    /// Forwards to a receiver, recording the fire-and-forget invocations, and failing the first negotiations like a
+    /// worker which is not running yet
+    struct Forwarding {
+        inner: RpcRouter,
+        failures: Mutex<usize>,
+        invoked: Mutex<Vec<IncomingCall>>,
+    }
+
+    impl Forwarding {
+        fn new(inner: RpcRouter, failures: usize) -> Self {
+            Self {
+                inner,
+                failures: Mutex::new(failures),
+                invoked: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RpcTransport for Forwarding {
+        async fn invoke_and_await(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+                .await
+        }
+
+        async fn invoke_and_await_with_metadata(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+            metadata: &[(String, String)],
+        ) -> Result<WitValue, RpcError> {
+            {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(RpcError::RemoteInternalError("not running".to_string()));
+                }
+            }
+            self.inner
+                .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+                .await
+        }
+
+        async fn invoke_with_metadata(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+            metadata: &[(String, String)],
+        ) -> Result<(), RpcError> {
+            self.invoked.lock().unwrap().push(
+                IncomingCall::new(uri.clone(), function_name, function_params.clone())
+                    .with_metadata(metadata.to_vec()),
+            );
+            self.inner
+                .invoke_with_metadata(uri, function_name, function_params, metadata)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn receivers_without_negotiation_get_the_plain_values() {
+        let uri = Uri::worker("counter", "worker-1");
+        let transport = CodecTransport::new(Plain::default(), Codecs::new());
+        for _ in 0..2 {
+            let result = transport
+                .invoke_and_await(&uri, "rpc:counter/api.{add}", vec![sample().into()])
+                .await
+                .unwrap();
+            assert_eq!(Value::from(result), Value::Tuple(vec![sample()]));
+        }
+        assert_eq!(transport.negotiated(&uri), Some(None));
+        assert_eq!(transport.inner.functions.lock().unwrap().len(), 3);
+
+        transport.reconnect(&uri).await.unwrap();
+        assert_eq!(transport.negotiated(&uri), None);
+    }
+
+    #[tokio::test]
+    async fn failed_negotiations_are_retried() {
+        let uri = Uri::worker("counter", "worker-1");
+        let transport =
+            CodecTransport::new(Forwarding::new(router(Codecs::new()), 1), Codecs::new());
+        let result = echo(&transport, &uri).await.unwrap();
+        assert_eq!(result, echoed());
+        assert_eq!(transport.negotiated(&uri), None);
+
+        assert_eq!(echo(&transport, &uri).await.unwrap(), echoed());
+        assert_eq!(transport.negotiated(&uri), Some(Some(Codec::ALL[0])));
+    }
+
+    #[tokio::test]
+    async fn fire_and_forget_payloads_are_encoded() {
+        let uri = Uri::worker("counter", "worker-1");
+        let transport =
+            CodecTransport::new(Forwarding::new(router(Codecs::new()), 0), Codecs::new());
+        let metadata = vec![("tenant".to_string(), "acme".to_string())];
+        transport
+            .invoke_with_metadata(
+                &uri,
+                "rpc:counter/api.{add}",
+                vec![sample().into()],
+                &metadata,
+            )
+            .await
+            .unwrap();
+
+        let invoked = transport.inner.invoked.lock().unwrap()[0].clone();
+        assert_eq!(
+            invoked.metadata,
+            vec![
+                ("tenant".to_string(), "acme".to_string()),
+                (CONTENT_CODEC.to_string(), Codec::ALL[0].name().to_string())
+            ]
+        );
+        let params = Codec::ALL[0]
+            .decode_params(invoked.function_params)
+            .unwrap();
+        assert_eq!(
+            params.into_iter().map(Value::from).collect::<Vec<_>>(),
+            vec![sample()]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Value;
+use golem_wasm_ast::analysis::AnalysedType;
+
+/// Rust types converted to values of a WIT type
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+
+    /// The WIT type of the values
+    fn get_type() -> AnalysedType;
+}
+
+/// Rust types converted from values. The type of the value guides the conversion of the nested values of the
+/// containers, like the elements of a list.
+pub trait FromValueAndType: Sized {
+    fn from_value_and_type(value: Value, typ: &AnalysedType) -> Result<Self, String>;
+}
+
+impl FromValueAndType for Value {
+    fn from_value_and_type(value: Value, _typ: &AnalysedType) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+macro_rules! primitive {
+    ($ty:ty, $variant:ident, $typ:ident) => {
+        impl IntoValue for $ty {
+            fn into_value(self) -> Value {
+                Value::$variant(self)
+            }
+
+            fn get_type() -> AnalysedType {
+                AnalysedType::$typ
+            }
+        }
+
+        impl FromValueAndType for $ty {
+            fn from_value_and_type(value: Value, _typ: &AnalysedType) -> Result<Self, String> {
+                match value {
+                    Value::$variant(value) => Ok(value),
+                    other => Err(unexpected(stringify!($typ), &other)),
+                }
+            }
+        }
+    };
+}
+
+primitive!(bool, Bool, Bool);
+primitive!(u8, U8, U8);
+primitive!(u16, U16, U16);
+primitive!(u32, U32, U32);
+primitive!(u64, U64, U64);
+primitive!(i8, S8, S8);
+primitive!(i16, S16, S16);
+primitive!(i32, S32, S32);
+primitive!(i64, S64, S64);
+primitive!(f32, F32, F32);
+primitive!(f64, F64, F64);
+primitive!(char, Char, Chr);
+primitive!(String, String, Str);
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+
+    fn get_type() -> AnalysedType {
+        AnalysedType::Str
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+
+    fn get_type() -> AnalysedType {
+        AnalysedType::List(Box::new(T::get_type()))
+    }
+}
+
+impl<T: FromValueAndType> FromValueAndType for Vec<T> {
+    fn from_value_and_type(value: Value, typ: &AnalysedType) -> Result<Self, String> {
+        match (value, typ) {
+            (Value::List(values), AnalysedType::List(element)) => values
+                .into_iter()
+                .map(|value| T::from_value_and_type(value, element))
+                .collect(),
+            (other, _) => Err(unexpected("List", &other)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        Value::Option(self.map(|value| Box::new(value.into_value())))
+    }
+
+    fn get_type() -> AnalysedType {
+        AnalysedType::Option(Box::new(T::get_type()))
+    }
+}
+
+impl<T: FromValueAndType> FromValueAndType for Option<T> {
+    fn from_value_and_type(value: Value, typ: &AnalysedType) -> Result<Self, String> {
+        match (value, typ) {
+            (Value::Option(None), _) => Ok(None),
+            (Value::Option(Some(value)), AnalysedType::Option(inner)) => {
+                T::from_value_and_type(*value, inner).map(Some)
+            }
+            (other, _) => Err(unexpected("Option", &other)),
+        }
+    }
+}
+
+impl<T: IntoValue, E: IntoValue> IntoValue for Result<T, E> {
+    fn into_value(self) -> Value {
+        Value::Result(match self {
+            Ok(value) => Ok(Some(Box::new(value.into_value()))),
+            Err(error) => Err(Some(Box::new(error.into_value()))),
+        })
+    }
+
+    fn get_type() -> AnalysedType {
+        AnalysedType::Result {
+            ok: Some(Box::new(T::get_type())),
+            error: Some(Box::new(E::get_type())),
+        }
+    }
+}
+
+impl<T: FromValueAndType, E: FromValueAndType> FromValueAndType for Result<T, E> {
+    fn from_value_and_type(value: Value, typ: &AnalysedType) -> Result<Self, String> {
+        match (value, typ) {
+            (Value::Result(Ok(Some(value))), AnalysedType::Result { ok: Some(typ), .. }) => {
+                T::from_value_and_type(*value, typ).map(Ok)
+            }
+            (
+                Value::Result(Err(Some(error))),
+                AnalysedType::Result {
+                    error: Some(typ), ..
+                },
+            ) => E::from_value_and_type(*error, typ).map(Err),
+            (other, _) => Err(unexpected("Result", &other)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Box<T> {
+    fn into_value(self) -> Value {
+        (*self).into_value()
+    }
+
+    fn get_type() -> AnalysedType {
+        T::get_type()
+    }
+}
+
+impl<T: FromValueAndType> FromValueAndType for Box<T> {
+    fn from_value_and_type(value: Value, typ: &AnalysedType) -> Result<Self, String> {
+        T::from_value_and_type(value, typ).map(Box::new)
+    }
+}
+
+macro_rules! tuple {
+    ($($name:ident),*) => {
+        impl<$($name: IntoValue),*> IntoValue for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn into_value(self) -> Value {
+                let ($($name,)*) = self;
+                Value::Tuple(vec![$($name.into_value()),*])
+            }
+
+            fn get_type() -> AnalysedType {
+                AnalysedType::Tuple(vec![$($name::get_type()),*])
+            }
+        }
+
+        impl<$($name: FromValueAndType),*> FromValueAndType for ($($name,)*) {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn from_value_and_type(value: Value, typ: &AnalysedType) -> Result<Self, String> {
+                let arity = <[&str]>::len(&[$(stringify!($name)),*]);
+                match (value, typ) {
+                    (Value::Tuple(values), AnalysedType::Tuple(types))
+                        if values.len() == arity && types.len() == arity =>
+                    {
+                        let mut values = values.into_iter();
+                        let mut types = types.iter();
+                        Ok(($($name::from_value_and_type(
+                            values.next().unwrap(),
+                            types.next().unwrap(),
+                        )?,)*))
+                    }
+                    (Value::Tuple(values), _) => Err(format!(
+                        "Expected a tuple of {arity} values, got {}",
+                        values.len()
+                    )),
+                    (other, _) => Err(unexpected("Tuple", &other)),
+                }
+            }
+        }
+    };
+}
+
+tuple!();
+tuple!(A);
+tuple!(A, B);
+tuple!(A, B, C);
+tuple!(A, B, C, D);
+tuple!(A, B, C, D, E);
+tuple!(A, B, C, D, E, F);
+tuple!(A, B, C, D, E, F, G);
+tuple!(A, B, C, D, E, F, G, H);
+
+fn unexpected(expected: &str, value: &Value) -> String {
+    format!("Expected a {expected} value, got {value:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::{FromValueAndType, IntoValue};
+    use crate::infer::infer_type;
+    use crate::Value;
+    use golem_wasm_ast::analysis::AnalysedType;
+
+    fn round_trip<T: IntoValue + FromValueAndType>(value: T) -> T {
+        T::from_value_and_type(value.into_value(), &T::get_type()).unwrap()
+    }
+
+    #[test]
+    fn values_are_converted_both_ways() {
+        assert_eq!(round_trip(42u64), 42);
+        assert_eq!(round_trip("name".to_string()), "name");
+        assert_eq!(
+            round_trip(vec![Some(1i32), None, Some(-1)]),
+            vec![Some(1), None, Some(-1)]
+        );
+        assert_eq!(
+            round_trip((1u8, 'x', Ok::<f64, String>(1.5))),
+            (1, 'x', Ok(1.5))
+        );
+        assert_eq!(
+            round_trip(Err::<bool, String>("failed".to_string())),
+            Err("failed".to_string())
+        );
+        assert_eq!(
+            <(u32, Vec<String>)>::get_type(),
+            AnalysedType::Tuple(vec![
+                AnalysedType::U32,
+                AnalysedType::List(Box::new(AnalysedType::Str))
+            ])
+        );
+    }
+
+    #[test]
+    fn values_of_the_inferred_types_are_converted() {
+        let value = vec![vec![1u16, 2], vec![]].into_value();
+        let typ = infer_type(&value);
+        assert_eq!(
+            Vec::<Vec<u16>>::from_value_and_type(value, &typ),
+            Ok(vec![vec![1, 2], vec![]])
+        );
+    }
+
+    #[test]
+    fn mismatching_values_are_rejected() {
+        let typ = <(u32, String)>::get_type();
+        assert!(
+            <(u32, String)>::from_value_and_type(Value::Tuple(vec![Value::U32(1)]), &typ).is_err()
+        );
+        assert!(u32::from_value_and_type(Value::S32(1), &AnalysedType::U32).is_err());
+        assert!(Vec::<u8>::from_value_and_type(Value::U8(1), &AnalysedType::U8).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::record::canonical_value;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// An invocation performed by a caller worker through its host, identified by its sequence number among the
+/// invocations of the host
+#[derive(Debug, Clone)]
+pub struct DurableInvocation {
+    pub sequence: u64,
+    pub uri: Uri,
+    pub function_name: String,
+    pub function_params: Vec<WitValue>,
+    /// The metadata of the invocation, including the idempotency key added by the host
+    pub metadata: Vec<(String, String)>,
+}
+
+/// Hooks of the durable execution engines externalizing the side effects of the invocations, so workers can be
+/// replayed without invoking the remote targets again. The hooks get the parameters and the results with their floats
+/// canonicalized, so the same invocations are recorded with the same bytes whatever NaN payloads or signed zeros the
+/// workers produced.
+#[async_trait]
+pub trait ReplayHooks: Send + Sync {
+    /// Called before an invocation is performed. Returning the recorded result of the invocation replays it: the
+    /// result is returned to the caller without invoking the target.
+    async fn replay(&self, invocation: &DurableInvocation) -> Option<Result<WitValue, RpcError>>;
+
+    /// Called with the result of an invocation which was performed, not replayed, so it can be recorded. The results
+    /// of oneway invocations are empty tuples.
+    async fn record(&self, invocation: &DurableInvocation, result: &Result<WitValue, RpcError>);
+}
+
+/// Numbers the invocations of a caller worker, and runs them through its replay hooks. Like the idempotency keys, the
+/// sequence numbers are deterministic, so a worker replaying its invocations after a restart gets the same numbers
+/// again, as long as it resumes the sequence with [Replay::starting_at].
+pub struct Replay {
+    hooks: Arc<dyn ReplayHooks>,
+    next: AtomicU64,
+}
+
+impl Replay {
+    pub fn new(hooks: impl ReplayHooks + 'static) -> Self {
+        Self {
+            hooks: Arc::new(hooks),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts the sequence at the given number instead of zero
+    pub fn starting_at(self, sequence: u64) -> Self {
+        Self {
+            next: AtomicU64::new(sequence),
+            ..self
+        }
+    }
+
+    /// Performs the next invocation of the sequence, unless the hooks replay it, and records its result
+    pub(crate) async fn run<F: Future<Output = Result<WitValue, RpcError>>>(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+        perform: impl FnOnce(Vec<WitValue>) -> F,
+    ) -> Result<WitValue, RpcError> {
+        let invocation = DurableInvocation {
+            sequence: self.next.fetch_add(1, Ordering::Relaxed),
+            uri: uri.clone(),
+            function_name: function_name.to_string(),
+            function_params: function_params
+                .iter()
+                .cloned()
+                .map(canonical_value)
+                .collect(),
+            metadata: metadata.to_vec(),
+        };
+        if let Some(result) = self.hooks.replay(&invocation).await {
+            return result;
+        }
+        let result = perform(function_params).await;
+        let recorded = result.clone().map(canonical_value);
+        self.hooks.record(&invocation, &recorded).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::durable::{DurableInvocation, Replay, ReplayHooks};
+    use crate::idempotency::{idempotency_key, IdempotencyKeys};
+    use crate::transport::{RpcTransport, WasmRpcHost};
+    use crate::{RpcError, Uri, Value, WitValue, WorkerUri};
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Returns the number of invocations it received
+    #[derive(Default)]
+    struct Counter {
+        invoked: AtomicU64,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Counter {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            let invoked = self.invoked.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Value::Tuple(vec![Value::U64(invoked)]).into())
+        }
+    }
+
+    struct Entry {
+        idempotency_key: Option<String>,
+        function_params: Vec<WitValue>,
+        result: Result<WitValue, RpcError>,
+    }
+
+    /// Keeps the results of the invocations by their sequence numbers, with the idempotency keys they had
+    #[derive(Clone, Default)]
+    struct Journal {
+        results: Arc<Mutex<HashMap<u64, Entry>>>,
+    }
+
+    #[async_trait]
+    impl ReplayHooks for Journal {
+        async fn replay(
+            &self,
+            invocation: &DurableInvocation,
+        ) -> Option<Result<WitValue, RpcError>> {
+            let results = self.results.lock().unwrap();
+            results
+                .get(&invocation.sequence)
+                .map(|entry| entry.result.clone())
+        }
+
+        async fn record(
+            &self,
+            invocation: &DurableInvocation,
+            result: &Result<WitValue, RpcError>,
+        ) {
+            let idempotency_key = idempotency_key(&invocation.metadata).map(|key| key.to_string());
+            self.results.lock().unwrap().insert(
+                invocation.sequence,
+                Entry {
+                    idempotency_key,
+                    function_params: invocation.function_params.clone(),
+                    result: result.clone(),
+                },
+            );
+        }
+    }
+
+    fn counted(result: Result<WitValue, RpcError>) -> Value {
+        Value::from(result.unwrap())
+    }
+
+    #[tokio::test]
+    async fn recorded_invocations_are_replayed_without_invoking_the_target() {
+        let caller = WorkerUri::new("caller", "worker-1");
+        let target = Uri::worker("counter", "worker-1");
+        let journal = Journal::default();
+        let counter = Arc::new(Counter::default());
+        let host = |starting_at| {
+            WasmRpcHost::builder(counter.clone())
+                .idempotency_keys(IdempotencyKeys::new(&caller).starting_at(starting_at))
+                .replay(Replay::new(journal.clone()).starting_at(starting_at))
+                .build()
+        };
+
+        let original = host(0);
+        for expected in 1..=2 {
+            let result = original.invoke_and_await(&target, "add", vec![]).await;
+            assert_eq!(counted(result), Value::Tuple(vec![Value::U64(expected)]));
+        }
+        assert_eq!(
+            journal.results.lock().unwrap()[&1]
+                .idempotency_key
+                .as_deref(),
+            Some("caller/worker-1/1")
+        );
+
+        // Replaying the worker returns the recorded results, and invokes the target for the new invocations only
+        let replayed = host(0);
+        for expected in 1..=3 {
+            let result = replayed.invoke_and_await(&target, "add", vec![]).await;
+            assert_eq!(counted(result), Value::Tuple(vec![Value::U64(expected)]));
+        }
+        assert_eq!(counter.invoked.load(Ordering::SeqCst), 3);
+
+        let resumed = host(1);
+        let result = resumed.invoke_and_await(&target, "add", vec![]).await;
+        assert_eq!(counted(result), Value::Tuple(vec![Value::U64(2)]));
+        assert_eq!(counter.invoked.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn oneway_invocations_are_numbered_and_replayed() {
+        let target = Uri::worker("counter", "worker-1");
+        let journal = Journal::default();
+        let counter = Arc::new(Counter::default());
+        let host = || {
+            WasmRpcHost::builder(counter.clone())
+                .replay(Replay::new(journal.clone()))
+                .build()
+        };
+
+        host().invoke(&target, "add", vec![]).await.unwrap();
+        let recorded = journal.results.lock().unwrap()[&0].result.clone();
+        assert_eq!(counted(recorded), Value::Tuple(vec![]));
+
+        let replayed = host();
+        replayed.invoke(&target, "add", vec![]).await.unwrap();
+        let result = replayed.invoke_and_await(&target, "get", vec![]).await;
+        assert_eq!(counted(result), Value::Tuple(vec![Value::U64(2)]));
+        assert_eq!(counter.invoked.load(Ordering::SeqCst), 2);
+    }
+
+    /// Returns its parameters
+    struct Echo;
+
+    #[async_trait]
+    impl RpcTransport for Echo {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            Ok(Value::Tuple(function_params.into_iter().map(Value::from).collect()).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn floats_are_recorded_canonicalized() {
+        let target = Uri::worker("echo", "worker-1");
+        let journal = Journal::default();
+        let host = WasmRpcHost::builder(Echo)
+            .replay(Replay::new(journal.clone()))
+            .build();
+
+        let nan = f32::from_bits(f32::NAN.to_bits() | 1);
+        let params = vec![Value::F32(nan).into(), Value::F64(-0.0).into()];
+        let result = host.invoke_and_await(&target, "echo", params).await;
+        // The caller still gets the values as they are
+        let Value::Tuple(echoed) = counted(result) else {
+            panic!("Unexpected result");
+        };
+        assert!(matches!(echoed[0], Value::F32(value) if value.to_bits() == nan.to_bits()));
+        assert!(matches!(echoed[1], Value::F64(value) if value.is_sign_negative()));
+
+        let is_canonical = |values: &[Value]| {
+            matches!(values, [Value::F32(nan), Value::F64(zero)]
+                if nan.to_bits() == f32::NAN.to_bits() && zero.to_bits() == 0)
+        };
+        let results = journal.results.lock().unwrap();
+        let params = results[&0]
+            .function_params
+            .iter()
+            .cloned()
+            .map(Value::from)
+            .collect::<Vec<_>>();
+        assert!(is_canonical(&params));
+        let Value::Tuple(recorded) = counted(results[&0].result.clone()) else {
+            panic!("Unexpected result");
+        };
+        assert!(is_canonical(&recorded));
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::codec::list_bytes;
+use crate::transport::RpcTransport;
+use crate::{NodeBuilder, RpcError, Uri, WitValue, WitValueBuilderExtensions, WitValueExtractor};
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// A 256 bit XChaCha20-Poly1305 key, with an id sent along with the payloads encrypted by it so the receiver can
+/// find it after the keys are rotated
+#[derive(Clone, PartialEq, Eq)]
+pub struct PayloadKey {
+    pub id: String,
+    bytes: [u8; 32],
+}
+
+impl PayloadKey {
+    pub fn new(id: impl Into<String>, bytes: [u8; 32]) -> Self {
+        Self {
+            id: id.into(),
+            bytes,
+        }
+    }
+}
+
+impl Debug for PayloadKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PayloadKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Provides the keys encrypting the payloads, like from a key management service
+pub trait KeyProvider: Send + Sync {
+    /// The key encrypting the payloads exchanged with a worker
+    fn encryption_key(&self, uri: &Uri) -> Result<PayloadKey, RpcError>;
+
+    /// A key by its id, to decrypt the payloads encrypted by it
+    fn decryption_key(&self, id: &str) -> Result<PayloadKey, RpcError>;
+}
+
+/// A single key used for every worker
+impl KeyProvider for PayloadKey {
+    fn encryption_key(&self, _uri: &Uri) -> Result<PayloadKey, RpcError> {
+        Ok(self.clone())
+    }
+
+    fn decryption_key(&self, id: &str) -> Result<PayloadKey, RpcError> {
+        if id == self.id {
+            Ok(self.clone())
+        } else {
+            Err(RpcError::ProtocolError(format!(
+                "Unknown payload encryption key: {id}"
+            )))
+        }
+    }
+}
+
+/// Encrypts and decrypts the payloads of the invocations. The parameters and the results are encoded with bincode
+/// and sealed in a single `tuple<string, list<u8>, list<u8>>` value of the key id, the nonce and the ciphertext.
+///
+/// The target worker and the function name are authenticated with each payload, so a payload can not be replayed to
+/// another worker or function, or a result passed off as the parameters. The receiving side of the transport opens
+/// the parameters and seals the results with the same keys.
+#[derive(Clone)]
+pub struct PayloadEncryption {
+    keys: Arc<dyn KeyProvider>,
+}
+
+impl PayloadEncryption {
+    pub fn new(keys: impl KeyProvider + 'static) -> Self {
+        Self {
+            keys: Arc::new(keys),
+        }
+    }
+
+    pub fn seal_params(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: &[WitValue],
+    ) -> Result<WitValue, RpcError> {
+        let plaintext = bincode::encode_to_vec(function_params, bincode::config::standard())
+            .map_err(|err| {
+                RpcError::ProtocolError(format!("Failed to encode the parameters: {err}"))
+            })?;
+        self.seal(uri, function_name, PayloadKind::Params, &plaintext)
+    }
+
+    pub fn open_params(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        sealed: WitValue,
+    ) -> Result<Vec<WitValue>, RpcError> {
+        let plaintext = self.open(uri, function_name, PayloadKind::Params, sealed)?;
+        decode(&plaintext)
+    }
+
+    pub fn seal_result(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        result: &WitValue,
+    ) -> Result<WitValue, RpcError> {
+        let plaintext =
+            bincode::encode_to_vec(result, bincode::config::standard()).map_err(|err| {
+                RpcError::ProtocolError(format!("Failed to encode the result: {err}"))
+            })?;
+        self.seal(uri, function_name, PayloadKind::Result, &plaintext)
+    }
+
+    pub fn open_result(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        sealed: WitValue,
+    ) -> Result<WitValue, RpcError> {
+        let plaintext = self.open(uri, function_name, PayloadKind::Result, sealed)?;
+        decode(&plaintext)
+    }
+
+    fn seal(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        kind: PayloadKind,
+        plaintext: &[u8],
+    ) -> Result<WitValue, RpcError> {
+        let key = self.keys.encryption_key(uri)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key.bytes));
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = associated_data(uri, function_name, kind);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| RpcError::ProtocolError("Failed to encrypt the payload".to_string()))?;
+        Ok(WitValue::builder()
+            .tuple()
+            .item()
+            .string(&key.id)
+            .item()
+            .bytes(&nonce)
+            .item()
+            .bytes(&ciphertext)
+            .finish())
+    }
+
+    fn open(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        kind: PayloadKind,
+        sealed: WitValue,
+    ) -> Result<Vec<u8>, RpcError> {
+        let invalid = || RpcError::ProtocolError("Invalid encrypted payload".to_string());
+        if sealed.tuple_element(3).is_some() {
+            return Err(invalid());
+        }
+        let key_id = sealed
+            .tuple_element(0)
+            .and_then(|key_id| key_id.string())
+            .ok_or_else(invalid)?;
+        let nonce = sealed
+            .tuple_element(1)
+            .and_then(list_bytes)
+            .ok_or_else(invalid)?;
+        let ciphertext = sealed
+            .tuple_element(2)
+            .and_then(list_bytes)
+            .ok_or_else(invalid)?;
+        if nonce.len() != 24 {
+            return Err(invalid());
+        }
+
+        let key = self.keys.decryption_key(key_id)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key.bytes));
+        let aad = associated_data(uri, function_name, kind);
+        cipher
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| RpcError::ProtocolError("Failed to decrypt the payload".to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PayloadKind {
+    Params,
+    Result,
+}
+
+fn associated_data(uri: &Uri, function_name: &str, kind: PayloadKind) -> Vec<u8> {
+    let kind = match kind {
+        PayloadKind::Params => "params",
+        PayloadKind::Result => "result",
+    };
+    format!("{kind}\0{}\0{function_name}", uri.value).into_bytes()
+}
+
+fn decode<T: bincode::Decode>(bytes: &[u8]) -> Result<T, RpcError> {
+    bincode::decode_from_slice(bytes, bincode::config::standard())
+        .map(|(value, _)| value)
+        .map_err(|err| RpcError::ProtocolError(format!("Failed to decode the payload: {err}")))
+}
+
+/// Encrypts the parameters of the invocations before they reach the transport, and decrypts their results, for
+/// deployments where the transport between the workers is not trusted
+pub struct EncryptingTransport<T> {
+    inner: T,
+    encryption: PayloadEncryption,
+}
+
+impl<T: RpcTransport> EncryptingTransport<T> {
+    pub fn new(inner: T, encryption: PayloadEncryption) -> Self {
+        Self { inner, encryption }
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for EncryptingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let sealed = self
+            .encryption
+            .seal_params(uri, function_name, &function_params)?;
+        let result = self
+            .inner
+            .invoke_and_await_with_metadata(uri, function_name, vec![sealed], metadata)
+            .await?;
+        self.encryption.open_result(uri, function_name, result)
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        let sealed = self
+            .encryption
+            .seal_params(uri, function_name, &function_params)?;
+        self.inner
+            .invoke_with_metadata(uri, function_name, vec![sealed], metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::list_bytes;
+    use crate::encrypt::{EncryptingTransport, PayloadEncryption, PayloadKey};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue, WitValueExtractor};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Opens the parameters like the receiving side, and returns the length of the string parameter
+    struct Receiver {
+        encryption: PayloadEncryption,
+        received: Mutex<Vec<WitValue>>,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Receiver {
+        async fn invoke_and_await(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            let sealed = function_params.into_iter().next().unwrap();
+            self.received.lock().unwrap().push(sealed.clone());
+            let params = self.encryption.open_params(uri, function_name, sealed)?;
+            let length = match Value::from(params[0].clone()) {
+                Value::String(value) => value.len() as u32,
+                _ => panic!("Unexpected parameter"),
+            };
+            self.encryption
+                .seal_result(uri, function_name, &Value::U32(length).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn payloads_are_encrypted_end_to_end() {
+        let key = PayloadKey::new("key-1", [7; 32]);
+        let transport = EncryptingTransport::new(
+            Receiver {
+                encryption: PayloadEncryption::new(key.clone()),
+                received: Mutex::new(Vec::new()),
+            },
+            PayloadEncryption::new(key),
+        );
+        let uri = Uri::worker("component", "worker-1");
+
+        let result = transport
+            .invoke_and_await(
+                &uri,
+                "rpc:text/api.{length}",
+                vec![Value::String("secret".to_string()).into()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::U32(6));
+
+        let sealed = transport.inner.received.lock().unwrap()[0].clone();
+        assert_eq!(sealed.tuple_element(0).unwrap().string(), Some("key-1"));
+        let ciphertext = list_bytes(sealed.tuple_element(2).unwrap()).unwrap();
+        assert!(!ciphertext.windows(6).any(|window| window == b"secret"));
+
+        // Bound to the target worker and function
+        let encryption = &transport.inner.encryption;
+        let other = Uri::worker("component", "worker-2");
+        assert!(encryption
+            .open_params(&other, "rpc:text/api.{length}", sealed.clone())
+            .is_err());
+        assert!(encryption
+            .open_result(&uri, "rpc:text/api.{length}", sealed.clone())
+            .is_err());
+        assert!(PayloadEncryption::new(PayloadKey::new("key-2", [7; 32]))
+            .open_params(&uri, "rpc:text/api.{length}", sealed)
+            .is_err());
+    }
+}
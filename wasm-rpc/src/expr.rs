@@ -0,0 +1,797 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Value;
+use golem_wasm_ast::analysis::AnalysedType;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A predicate over values, comparing the parts selected by paths with literals or with each other, and combining
+/// the comparisons with `&&`, `||`, `!` and parentheses, like `result.status == "failed" && retries > 3`.
+///
+/// A path starts with the name of a value of the [Scope], followed by record fields, flags, variant cases and the
+/// `ok` and `err` cases of results selected with `.name`, and by tuple and list elements selected with `.0` or `[0]`.
+/// Options are unwrapped on the way. The literals are numbers, double quoted strings, `true` and `false`; the enum
+/// and variant values are compared with the names of their cases. The numbers of any kind are compared by value.
+///
+/// A path reaching into a `none`, into an other case of a variant or result, or past the end of a list selects
+/// nothing: it is only equal to an other path selecting nothing, and the orderings involving it are false.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr(Node);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Or(Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Operand, Comparison, Operand),
+    Is(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Path(Path),
+    Bool(bool),
+    Number(Value),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Path {
+    name: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Name(String),
+    Index(usize),
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for segment in &self.segments {
+            match segment {
+                Segment::Name(name) => write!(f, ".{name}")?,
+                Segment::Index(idx) => write!(f, "[{idx}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The named values, with their types, which the paths of the expressions start from
+#[derive(Debug, Clone, Default)]
+pub struct Scope<'a> {
+    bindings: Vec<(String, &'a Value, &'a AnalysedType)>,
+}
+
+impl<'a> Scope<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a named value, hiding any value added before with the same name
+    pub fn with_binding(
+        mut self,
+        name: impl Into<String>,
+        value: &'a Value,
+        typ: &'a AnalysedType,
+    ) -> Self {
+        self.bindings.push((name.into(), value, typ));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<(&'a Value, &'a AnalysedType)> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(binding, _, _)| binding == name)
+            .map(|(_, value, typ)| (*value, *typ))
+    }
+}
+
+/// Describes why an expression could not be parsed or evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    /// The expression is not well formed, at the given byte offset
+    Parse { position: usize, message: String },
+    /// A path starts with a name not in the scope
+    UnknownName(String),
+    /// A path selects a part which the type of the value does not have
+    InvalidPath(String),
+    /// Values of different kinds are compared, or a value which is not a boolean is used as a condition
+    TypeMismatch(String),
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::Parse { position, message } => {
+                write!(f, "invalid expression at {position}: {message}")
+            }
+            ExprError::UnknownName(name) => write!(f, "unknown name {name}"),
+            ExprError::InvalidPath(message) => write!(f, "invalid path: {message}"),
+            ExprError::TypeMismatch(message) => write!(f, "type mismatch: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr, ExprError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            end: source.len(),
+        };
+        let node = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(Expr(node)),
+            Some((position, token)) => Err(ExprError::Parse {
+                position: *position,
+                message: format!("unexpected {token}"),
+            }),
+        }
+    }
+
+    /// Evaluates the expression over the values of the scope. The comparisons not needed for the result, like the
+    /// right side of `&&` when the left side is false, are not evaluated.
+    pub fn evaluate(&self, scope: &Scope) -> Result<bool, ExprError> {
+        evaluate(&self.0, scope)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Expr::parse(s)
+    }
+}
+
+fn evaluate(node: &Node, scope: &Scope) -> Result<bool, ExprError> {
+    match node {
+        Node::Or(left, right) => Ok(evaluate(left, scope)? || evaluate(right, scope)?),
+        Node::And(left, right) => Ok(evaluate(left, scope)? && evaluate(right, scope)?),
+        Node::Not(node) => Ok(!evaluate(node, scope)?),
+        Node::Compare(left, comparison, right) => {
+            let left = scalar(left, scope)?;
+            let right = scalar(right, scope)?;
+            compare(&left, *comparison, &right)
+        }
+        Node::Is(operand) => match scalar(operand, scope)? {
+            Scalar::Bool(value) => Ok(value),
+            Scalar::Nothing => Ok(false),
+            other => Err(ExprError::TypeMismatch(format!(
+                "{} is used as a condition",
+                other.kind()
+            ))),
+        },
+    }
+}
+
+/// The comparable values the operands evaluate to
+enum Scalar<'a> {
+    Bool(bool),
+    Number(Cow<'a, Value>),
+    Text(Cow<'a, str>),
+    Nothing,
+}
+
+impl Scalar<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Scalar::Bool(_) => "a boolean",
+            Scalar::Number(_) => "a number",
+            Scalar::Text(_) => "a string",
+            Scalar::Nothing => "nothing",
+        }
+    }
+}
+
+fn compare(left: &Scalar, comparison: Comparison, right: &Scalar) -> Result<bool, ExprError> {
+    let ordering = match (left, right) {
+        (Scalar::Nothing, _) | (_, Scalar::Nothing) => {
+            let both = matches!((left, right), (Scalar::Nothing, Scalar::Nothing));
+            return Ok(match comparison {
+                Comparison::Eq => both,
+                Comparison::Ne => !both,
+                _ => false,
+            });
+        }
+        (Scalar::Bool(left), Scalar::Bool(right))
+            if matches!(comparison, Comparison::Eq | Comparison::Ne) =>
+        {
+            Some(left.cmp(right))
+        }
+        (Scalar::Number(left), Scalar::Number(right)) => compare_numbers(left, right),
+        (Scalar::Text(left), Scalar::Text(right)) => Some(left.cmp(right)),
+        _ => {
+            return Err(ExprError::TypeMismatch(format!(
+                "{} is compared with {}",
+                left.kind(),
+                right.kind()
+            )))
+        }
+    };
+    Ok(match ordering {
+        Some(ordering) => match comparison {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+        },
+        // NaN is not ordered, and is not equal to any number
+        None => comparison == Comparison::Ne,
+    })
+}
+
+/// Compares the integers exactly, and the other numbers as floats
+fn compare_numbers(left: &Value, right: &Value) -> Option<Ordering> {
+    if let (Some(left), Some(right)) = (left.as_i64(), right.as_i64()) {
+        Some(left.cmp(&right))
+    } else if let (Some(left), Some(right)) = (left.as_u64(), right.as_u64()) {
+        Some(left.cmp(&right))
+    } else {
+        left.as_f64_lossy()?.partial_cmp(&right.as_f64_lossy()?)
+    }
+}
+
+fn scalar<'a>(operand: &'a Operand, scope: &Scope<'a>) -> Result<Scalar<'a>, ExprError> {
+    match operand {
+        Operand::Bool(value) => Ok(Scalar::Bool(*value)),
+        Operand::Number(value) => Ok(Scalar::Number(Cow::Borrowed(value))),
+        Operand::String(value) => Ok(Scalar::Text(Cow::Borrowed(value))),
+        Operand::Path(path) => match resolve(path, scope)? {
+            Selected::Value(value, typ) => value_scalar(path, value, typ),
+            Selected::Flag(value) => Ok(Scalar::Bool(value)),
+            Selected::Nothing => Ok(Scalar::Nothing),
+        },
+    }
+}
+
+fn value_scalar<'a>(
+    path: &Path,
+    value: &'a Value,
+    typ: &'a AnalysedType,
+) -> Result<Scalar<'a>, ExprError> {
+    match (value, typ) {
+        (Value::Bool(value), _) => Ok(Scalar::Bool(*value)),
+        (Value::String(value), _) => Ok(Scalar::Text(Cow::Borrowed(value))),
+        (Value::Char(value), _) => Ok(Scalar::Text(Cow::Owned(value.to_string()))),
+        (Value::Enum(case_idx), AnalysedType::Enum(cases)) => case_name(path, cases, *case_idx),
+        (Value::Variant { case_idx, .. }, AnalysedType::Variant(cases)) => {
+            let names = cases.iter().map(|(name, _)| name).collect::<Vec<_>>();
+            case_name(path, &names, *case_idx)
+        }
+        (Value::Option(None), _) => Ok(Scalar::Nothing),
+        (Value::Option(Some(value)), AnalysedType::Option(typ)) => value_scalar(path, value, typ),
+        (value, _) if value.as_f64_lossy().is_some() => Ok(Scalar::Number(Cow::Borrowed(value))),
+        _ => Err(ExprError::TypeMismatch(format!(
+            "{path} is not a boolean, number, string, enum or variant"
+        ))),
+    }
+}
+
+fn case_name(
+    path: &Path,
+    cases: &[impl AsRef<str>],
+    case_idx: u32,
+) -> Result<Scalar<'static>, ExprError> {
+    cases
+        .get(case_idx as usize)
+        .map(|name| Scalar::Text(Cow::Owned(name.as_ref().to_string())))
+        .ok_or_else(|| ExprError::InvalidPath(format!("{path} has no case {case_idx}")))
+}
+
+/// The part of a value selected by a path
+enum Selected<'a> {
+    Value(&'a Value, &'a AnalysedType),
+    Flag(bool),
+    Nothing,
+}
+
+fn resolve<'a>(path: &Path, scope: &Scope<'a>) -> Result<Selected<'a>, ExprError> {
+    let (mut value, mut typ) = scope
+        .get(&path.name)
+        .ok_or_else(|| ExprError::UnknownName(path.name.clone()))?;
+    let invalid = |message: &str| Err(ExprError::InvalidPath(format!("{path}: {message}")));
+    let mut segments = path.segments.iter().peekable();
+    while let Some(segment) = segments.next() {
+        // Options are unwrapped before selecting their parts
+        while let (Value::Option(inner), AnalysedType::Option(inner_typ)) = (value, typ) {
+            match inner {
+                Some(inner) => (value, typ) = (inner, inner_typ),
+                None => return Ok(Selected::Nothing),
+            }
+        }
+        let selected = match (segment, value, typ) {
+            (Segment::Name(name), Value::Record(fields), AnalysedType::Record(field_types)) => {
+                match field_types.iter().position(|(field, _)| field == name) {
+                    Some(idx) => fields.get(idx).map(|field| (field, &field_types[idx].1)),
+                    None => return invalid(&format!("no field {name}")),
+                }
+            }
+            (Segment::Name(name), Value::Flags(flags), AnalysedType::Flags(names)) => {
+                let Some(idx) = names.iter().position(|flag| flag == name) else {
+                    return invalid(&format!("no flag {name}"));
+                };
+                if segments.peek().is_some() {
+                    return invalid(&format!("the flag {name} has no parts"));
+                }
+                return Ok(Selected::Flag(flags.get(idx).copied().unwrap_or(false)));
+            }
+            (
+                Segment::Name(name),
+                Value::Variant {
+                    case_idx,
+                    case_value,
+                },
+                AnalysedType::Variant(cases),
+            ) => match cases.iter().position(|(case, _)| case == name) {
+                Some(idx) if idx == *case_idx as usize => {
+                    case_value.as_deref().zip(cases[idx].1.as_ref())
+                }
+                Some(_) => None,
+                None => return invalid(&format!("no case {name}")),
+            },
+            (Segment::Name(name), Value::Result(result), AnalysedType::Result { ok, error }) => {
+                match (name.as_str(), result) {
+                    ("ok", Ok(value)) => value.as_deref().zip(ok.as_deref()),
+                    ("err", Err(value)) => value.as_deref().zip(error.as_deref()),
+                    ("ok", Err(_)) | ("err", Ok(_)) => None,
+                    _ => return invalid(&format!("a result has no case {name}")),
+                }
+            }
+            (Segment::Index(idx), Value::Tuple(items), AnalysedType::Tuple(types)) => {
+                match (items.get(*idx), types.get(*idx)) {
+                    (Some(item), Some(typ)) => Some((item, typ)),
+                    _ => return invalid(&format!("no element {idx}")),
+                }
+            }
+            (Segment::Index(idx), Value::List(items), AnalysedType::List(typ)) => {
+                items.get(*idx).map(|item| (item, typ.as_ref()))
+            }
+            (Segment::Name(name), _, _) => return invalid(&format!("no part named {name}")),
+            (Segment::Index(idx), _, _) => return invalid(&format!("no element {idx}")),
+        };
+        match selected {
+            Some(selected) => (value, typ) = selected,
+            None => return Ok(Selected::Nothing),
+        }
+    }
+    Ok(Selected::Value(value, typ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(Value),
+    String(String),
+    Comparison(Comparison),
+    And,
+    Or,
+    Not,
+    Dot,
+    OpenBracket,
+    CloseBracket,
+    OpenParen,
+    CloseParen,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "{name}"),
+            Token::Number(_) => write!(f, "number"),
+            Token::String(value) => write!(f, "{value:?}"),
+            Token::Comparison(comparison) => write!(
+                f,
+                "{}",
+                match comparison {
+                    Comparison::Eq => "==",
+                    Comparison::Ne => "!=",
+                    Comparison::Lt => "<",
+                    Comparison::Le => "<=",
+                    Comparison::Gt => ">",
+                    Comparison::Ge => ">=",
+                }
+            ),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Dot => write!(f, "."),
+            Token::OpenBracket => write!(f, "["),
+            Token::CloseBracket => write!(f, "]"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens: Vec<(usize, Token)> = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let error = |message: String| Err(ExprError::Parse { position, message });
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '.' => Token::Dot,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Comparison(Comparison::Eq),
+            '!' if next_is('=') => Token::Comparison(Comparison::Ne),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Comparison(Comparison::Le),
+            '<' => Token::Comparison(Comparison::Lt),
+            '>' if next_is('=') => Token::Comparison(Comparison::Ge),
+            '>' => Token::Comparison(Comparison::Gt),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, c @ ('"' | '\\'))) => value.push(c),
+                            _ => return error("invalid escape in string".to_string()),
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => return error("unterminated string".to_string()),
+                    }
+                }
+                Token::String(value)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                // The indices after a dot, like in `pair.0.1`, have no fractional part
+                let after_dot = matches!(tokens.last(), Some((_, Token::Dot)));
+                let mut end = position + c.len_utf8();
+                let mut fraction = false;
+                while let Some((idx, c)) = chars.peek().copied() {
+                    let digit_follows = source[idx + 1..].starts_with(|c: char| c.is_ascii_digit());
+                    if c.is_ascii_digit() {
+                        end = idx + 1;
+                    } else if c == '.' && !fraction && !after_dot && digit_follows {
+                        fraction = true;
+                        end = idx + 1;
+                    } else {
+                        break;
+                    }
+                    chars.next();
+                }
+                let text = &source[position..end];
+                let number = if fraction {
+                    text.parse().ok().map(Value::F64)
+                } else {
+                    text.parse()
+                        .map(Value::S64)
+                        .or_else(|_| text.parse().map(Value::U64))
+                        .ok()
+                };
+                match number {
+                    Some(number) => Token::Number(number),
+                    None => return error(format!("invalid number {text}")),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = position + c.len_utf8();
+                while let Some((idx, c)) =
+                    chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '-')
+                {
+                    end = idx + c.len_utf8();
+                }
+                Token::Name(source[position..end].to_string())
+            }
+            c => return error(format!("unexpected {c:?}")),
+        };
+        tokens.push((position, token));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of the tokens, `||` binding looser than `&&`, which binds looser than `!` and the
+/// comparisons
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.and()?;
+        while self.next_if(&Token::Or) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.not()?;
+        while self.next_if(&Token::And) {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, ExprError> {
+        if self.next_if(&Token::Not) {
+            Ok(Node::Not(Box::new(self.not()?)))
+        } else if self.next_if(&Token::OpenParen) {
+            let node = self.or()?;
+            self.expect(&Token::CloseParen)?;
+            Ok(node)
+        } else {
+            let left = self.operand()?;
+            match self.peek() {
+                Some(Token::Comparison(comparison)) => {
+                    let comparison = *comparison;
+                    self.position += 1;
+                    Ok(Node::Compare(left, comparison, self.operand()?))
+                }
+                _ => Ok(Node::Is(left)),
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, ExprError> {
+        let position = self.offset();
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Operand::Number(value)),
+            Some(Token::String(value)) => Ok(Operand::String(value)),
+            Some(Token::Name(name)) if name == "true" => Ok(Operand::Bool(true)),
+            Some(Token::Name(name)) if name == "false" => Ok(Operand::Bool(false)),
+            Some(Token::Name(name)) => {
+                let mut segments = Vec::new();
+                loop {
+                    if self.next_if(&Token::Dot) {
+                        let position = self.offset();
+                        match self.next() {
+                            Some(Token::Name(name)) => segments.push(Segment::Name(name)),
+                            Some(Token::Number(Value::S64(idx))) if idx >= 0 => {
+                                segments.push(Segment::Index(idx as usize))
+                            }
+                            _ => return self.error(position, "expected a name or an index"),
+                        }
+                    } else if self.next_if(&Token::OpenBracket) {
+                        let position = self.offset();
+                        match self.next() {
+                            Some(Token::Number(Value::S64(idx))) if idx >= 0 => {
+                                segments.push(Segment::Index(idx as usize))
+                            }
+                            _ => return self.error(position, "expected an index"),
+                        }
+                        self.expect(&Token::CloseBracket)?;
+                    } else {
+                        return Ok(Operand::Path(Path { name, segments }));
+                    }
+                }
+            }
+            _ => self.error(position, "expected a path or a literal"),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(_, token)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        let matches = self.peek() == Some(expected);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), ExprError> {
+        let position = self.offset();
+        if self.next_if(expected) {
+            Ok(())
+        } else {
+            self.error(position, &format!("expected {expected}"))
+        }
+    }
+
+    /// The byte offset of the next token, or the end of the source
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    fn error<T>(&self, position: usize, message: &str) -> Result<T, ExprError> {
+        Err(ExprError::Parse {
+            position,
+            message: message.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::{Expr, ExprError, Scope};
+    use crate::Value;
+    use golem_wasm_ast::analysis::AnalysedType;
+
+    fn job_type() -> AnalysedType {
+        AnalysedType::Record(vec![
+            (
+                "status".to_string(),
+                AnalysedType::Enum(vec!["running".to_string(), "failed".to_string()]),
+            ),
+            (
+                "error".to_string(),
+                AnalysedType::Option(Box::new(AnalysedType::Str)),
+            ),
+            (
+                "timings".to_string(),
+                AnalysedType::List(Box::new(AnalysedType::F64)),
+            ),
+            (
+                "access".to_string(),
+                AnalysedType::Flags(vec!["read".to_string(), "write".to_string()]),
+            ),
+            (
+                "outcome".to_string(),
+                AnalysedType::Variant(vec![
+                    ("pending".to_string(), None),
+                    (
+                        "done".to_string(),
+                        Some(AnalysedType::Tuple(vec![
+                            AnalysedType::U64,
+                            AnalysedType::Chr,
+                        ])),
+                    ),
+                ]),
+            ),
+        ])
+    }
+
+    fn job(status: u32, error: Option<&str>) -> Value {
+        Value::Record(vec![
+            Value::Enum(status),
+            Value::Option(error.map(|error| Box::new(Value::String(error.to_string())))),
+            Value::List(vec![Value::F64(0.5), Value::F64(2.0)]),
+            Value::Flags(vec![true, false]),
+            Value::Variant {
+                case_idx: 1,
+                case_value: Some(Box::new(Value::Tuple(vec![
+                    Value::U64(u64::MAX),
+                    Value::Char('x'),
+                ]))),
+            },
+        ])
+    }
+
+    fn evaluate(source: &str, result: &Value) -> Result<bool, ExprError> {
+        let typ = job_type();
+        let retries = Value::U8(4);
+        let scope = Scope::new()
+            .with_binding("result", result, &typ)
+            .with_binding("retries", &retries, &AnalysedType::U8);
+        Expr::parse(source)?.evaluate(&scope)
+    }
+
+    #[test]
+    fn expressions_select_and_compare_values() {
+        let failed = job(1, Some("timeout"));
+        let running = job(0, None);
+        let source = r#"result.status == "failed" && retries > 3"#;
+        assert_eq!(evaluate(source, &failed), Ok(true));
+        assert_eq!(evaluate(source, &running), Ok(false));
+        assert_eq!(evaluate(r#"result.error == "timeout""#, &failed), Ok(true));
+        assert_eq!(evaluate(r#"result.error != "timeout""#, &running), Ok(true));
+        assert_eq!(evaluate(r#"result.error < "z""#, &running), Ok(false));
+        assert_eq!(
+            evaluate("result.timings[1] >= 2 && result.timings.0 < 1", &failed),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate("result.timings[2] == result.timings[3]", &failed),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate("result.access.read && !result.access.write", &failed),
+            Ok(true)
+        );
+        assert_eq!(evaluate(r#"result.outcome == "done""#, &failed), Ok(true));
+        assert_eq!(
+            evaluate("result.outcome.done.0 > 18446744073709551614", &failed),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate(r#"result.outcome.done.1 == "x""#, &failed),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate("retries == 4.0 && retries != -4", &failed),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate("(retries < 3 || retries > 5) || false", &failed),
+            Ok(false)
+        );
+        assert!(evaluate("retries < 3 || (result.nothing == 1)", &failed).is_err());
+        assert_eq!(
+            evaluate("retries > 3 || result.nothing == 1", &failed),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        let failed = job(1, None);
+        assert!(matches!(
+            evaluate("retries >", &failed),
+            Err(ExprError::Parse { position: 9, .. })
+        ));
+        assert!(matches!(
+            evaluate("retries > 3)", &failed),
+            Err(ExprError::Parse { position: 11, .. })
+        ));
+        assert!(matches!(
+            evaluate(r#"result.status == "failed"#, &failed),
+            Err(ExprError::Parse { position: 17, .. })
+        ));
+        assert_eq!(
+            evaluate("attempts > 3", &failed),
+            Err(ExprError::UnknownName("attempts".to_string()))
+        );
+        assert!(matches!(
+            evaluate("result.state == 1", &failed),
+            Err(ExprError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            evaluate(r#"retries == "4""#, &failed),
+            Err(ExprError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            evaluate("result.timings", &failed),
+            Err(ExprError::TypeMismatch(_))
+        ));
+    }
+}
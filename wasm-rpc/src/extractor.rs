@@ -1,4 +1,8 @@
-use crate::{Uri, WitNode, WitValue};
+use crate::node;
+use crate::{CheckedNodeIndex, NodeIndex, Uri, WitNode, WitValue};
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
 
 pub trait WitValueExtractor<'a> {
     fn u8(&'a self) -> Option<u8>;
@@ -14,12 +18,14 @@ pub trait WitValueExtractor<'a> {
     fn char(&'a self) -> Option<char>;
     fn bool(&'a self) -> Option<bool>;
     fn string(&'a self) -> Option<&'a str>;
+    fn bytes(&'a self) -> Option<&'a [u8]>;
     fn field(&'a self, field_idx: usize) -> Option<WitNodePointer<'a>>;
     fn variant(&'a self) -> Option<(u32, Option<WitNodePointer<'a>>)>;
     fn enum_value(&'a self) -> Option<u32>;
     fn flags(&'a self) -> Option<&'a [bool]>;
     fn tuple_element(&'a self, element_idx: usize) -> Option<WitNodePointer<'a>>;
     fn list_elements<R>(&'a self, f: impl Fn(WitNodePointer<'a>) -> R) -> Option<Vec<R>>;
+    fn list_iter(&'a self) -> Option<ListElements<'a>>;
     fn option(&'a self) -> Option<Option<WitNodePointer<'a>>>;
     fn result(&'a self) -> Option<Result<Option<WitNodePointer<'a>>, Option<WitNodePointer<'a>>>>;
 
@@ -28,111 +34,224 @@ pub trait WitValueExtractor<'a> {
 
 impl<'a> WitValueExtractor<'a> for WitValue {
     fn u8(&self) -> Option<u8> {
-        WitNodePointer::new(self, 0).u8()
+        WitNodePointer::root(self)?.u8()
     }
 
     fn u16(&self) -> Option<u16> {
-        WitNodePointer::new(self, 0).u16()
+        WitNodePointer::root(self)?.u16()
     }
 
     fn u32(&self) -> Option<u32> {
-        WitNodePointer::new(self, 0).u32()
+        WitNodePointer::root(self)?.u32()
     }
 
     fn u64(&self) -> Option<u64> {
-        WitNodePointer::new(self, 0).u64()
+        WitNodePointer::root(self)?.u64()
     }
 
     fn s8(&self) -> Option<i8> {
-        WitNodePointer::new(self, 0).s8()
+        WitNodePointer::root(self)?.s8()
     }
 
     fn s16(&self) -> Option<i16> {
-        WitNodePointer::new(self, 0).s16()
+        WitNodePointer::root(self)?.s16()
     }
 
     fn s32(&self) -> Option<i32> {
-        WitNodePointer::new(self, 0).s32()
+        WitNodePointer::root(self)?.s32()
     }
 
     fn s64(&self) -> Option<i64> {
-        WitNodePointer::new(self, 0).s64()
+        WitNodePointer::root(self)?.s64()
     }
 
     fn f32(&self) -> Option<f32> {
-        WitNodePointer::new(self, 0).f32()
+        WitNodePointer::root(self)?.f32()
     }
 
     fn f64(&self) -> Option<f64> {
-        WitNodePointer::new(self, 0).f64()
+        WitNodePointer::root(self)?.f64()
     }
 
     fn char(&self) -> Option<char> {
-        WitNodePointer::new(self, 0).char()
+        WitNodePointer::root(self)?.char()
     }
 
     fn bool(&self) -> Option<bool> {
-        WitNodePointer::new(self, 0).bool()
+        WitNodePointer::root(self)?.bool()
     }
 
     fn string(&'a self) -> Option<&'a str> {
-        WitNodePointer::<'a>::new(self, 0).string()
+        WitNodePointer::<'a>::root(self)?.string()
+    }
+
+    fn bytes(&'a self) -> Option<&'a [u8]> {
+        WitNodePointer::<'a>::root(self)?.bytes()
     }
 
     fn field(&'a self, field_idx: usize) -> Option<WitNodePointer<'a>> {
-        WitNodePointer::new(self, 0).field(field_idx)
+        WitNodePointer::root(self)?.field(field_idx)
     }
 
     fn variant(&'a self) -> Option<(u32, Option<WitNodePointer<'a>>)> {
-        WitNodePointer::new(self, 0).variant()
+        WitNodePointer::root(self)?.variant()
     }
 
     fn enum_value(&'a self) -> Option<u32> {
-        WitNodePointer::new(self, 0).enum_value()
+        WitNodePointer::root(self)?.enum_value()
     }
 
     fn flags(&'a self) -> Option<&'a [bool]> {
-        WitNodePointer::new(self, 0).flags()
+        WitNodePointer::root(self)?.flags()
     }
 
     fn tuple_element(&'a self, element_idx: usize) -> Option<WitNodePointer<'a>> {
-        WitNodePointer::new(self, 0).tuple_element(element_idx)
+        WitNodePointer::root(self)?.tuple_element(element_idx)
     }
 
     fn list_elements<R>(&'a self, f: impl Fn(WitNodePointer<'a>) -> R) -> Option<Vec<R>> {
-        WitNodePointer::new(self, 0).list_elements(f)
+        WitNodePointer::root(self)?.list_elements(f)
+    }
+
+    fn list_iter(&'a self) -> Option<ListElements<'a>> {
+        WitNodePointer::root(self)?.list_iter()
     }
 
     fn option(&'a self) -> Option<Option<WitNodePointer<'a>>> {
-        WitNodePointer::new(self, 0).option()
+        WitNodePointer::root(self)?.option()
     }
 
     fn result(&'a self) -> Option<Result<Option<WitNodePointer<'a>>, Option<WitNodePointer<'a>>>> {
-        WitNodePointer::new(self, 0).result()
+        WitNodePointer::root(self)?.result()
     }
 
     fn handle(&'a self) -> Option<(Uri, u64)> {
-        WitNodePointer::new(self, 0).handle()
+        WitNodePointer::root(self)?.handle()
+    }
+}
+
+/// Fast paths reading a single primitive directly from its node, without building a [Value](crate::Value) tree, for
+/// the common case of a function returning a string or a number
+impl WitValue {
+    /// The primitive at the root, or the only element of a tuple at the root like the results of a function
+    fn single(&self) -> Option<WitNodePointer<'_>> {
+        let root = WitNodePointer::root(self)?;
+        match root.node()? {
+            WitNode::TupleValue(indices) if indices.len() == 1 => root.child(indices[0]),
+            _ => Some(root),
+        }
+    }
+
+    pub fn as_single_u8(&self) -> Option<u8> {
+        self.single()?.u8()
+    }
+
+    pub fn as_single_u16(&self) -> Option<u16> {
+        self.single()?.u16()
+    }
+
+    pub fn as_single_u32(&self) -> Option<u32> {
+        self.single()?.u32()
+    }
+
+    pub fn as_single_u64(&self) -> Option<u64> {
+        self.single()?.u64()
+    }
+
+    pub fn as_single_s8(&self) -> Option<i8> {
+        self.single()?.s8()
+    }
+
+    pub fn as_single_s16(&self) -> Option<i16> {
+        self.single()?.s16()
+    }
+
+    pub fn as_single_s32(&self) -> Option<i32> {
+        self.single()?.s32()
+    }
+
+    pub fn as_single_s64(&self) -> Option<i64> {
+        self.single()?.s64()
+    }
+
+    pub fn as_single_f32(&self) -> Option<f32> {
+        self.single()?.f32()
+    }
+
+    pub fn as_single_f64(&self) -> Option<f64> {
+        self.single()?.f64()
+    }
+
+    pub fn as_single_char(&self) -> Option<char> {
+        self.single()?.char()
+    }
+
+    pub fn as_single_bool(&self) -> Option<bool> {
+        self.single()?.bool()
+    }
+
+    pub fn as_single_string(&self) -> Option<&str> {
+        self.single()?.string()
     }
 }
 
+/// A node of a WitValue. The nodes referring to children out of range, as the ones of malformed values received from
+/// untrusted sources can, are extracted as `None` instead of panicking.
+#[derive(Clone)]
 pub struct WitNodePointer<'a> {
     value: &'a WitValue,
-    idx: usize,
+    idx: CheckedNodeIndex,
+    /// An element of the list of bytes stored in the node
+    byte: Option<u8>,
 }
 
 impl<'a> WitNodePointer<'a> {
-    fn new(value: &'a WitValue, idx: usize) -> Self {
-        assert!(idx < value.nodes.len());
-        Self { value, idx }
+    pub(crate) fn root(value: &'a WitValue) -> Option<Self> {
+        let idx = CheckedNodeIndex::root(&value.nodes).ok()?;
+        Some(Self {
+            value,
+            idx,
+            byte: None,
+        })
+    }
+
+    fn child(&self, idx: NodeIndex) -> Option<Self> {
+        let idx = CheckedNodeIndex::new(idx, &self.value.nodes).ok()?;
+        Some(Self {
+            value: self.value,
+            idx,
+            byte: None,
+        })
+    }
+
+    fn byte(&self, byte: u8) -> Self {
+        Self {
+            value: self.value,
+            idx: self.idx,
+            byte: Some(byte),
+        }
     }
 
-    fn node(&self) -> &'a WitNode {
-        &self.value.nodes[self.idx]
+    fn optional_child(&self, idx: Option<NodeIndex>) -> Option<Option<Self>> {
+        match idx {
+            Some(idx) => self.child(idx).map(Some),
+            None => Some(None),
+        }
+    }
+
+    /// The node, or `None` for the elements of a list of bytes which have no node of their own
+    fn node(&self) -> Option<&'a WitNode> {
+        match self.byte {
+            Some(_) => None,
+            None => Some(&self.value.nodes[self.idx.position()]),
+        }
     }
 
     pub fn u8(&self) -> Option<u8> {
-        if let WitNode::PrimU8(value) = self.node() {
+        if self.byte.is_some() {
+            return self.byte;
+        }
+        if let WitNode::PrimU8(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -140,7 +259,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn u16(&self) -> Option<u16> {
-        if let WitNode::PrimU16(value) = self.node() {
+        if let WitNode::PrimU16(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -148,7 +267,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn u32(&self) -> Option<u32> {
-        if let WitNode::PrimU32(value) = self.node() {
+        if let WitNode::PrimU32(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -156,7 +275,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn u64(&self) -> Option<u64> {
-        if let WitNode::PrimU64(value) = self.node() {
+        if let WitNode::PrimU64(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -164,7 +283,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn s8(&self) -> Option<i8> {
-        if let WitNode::PrimS8(value) = self.node() {
+        if let WitNode::PrimS8(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -172,7 +291,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn s16(&self) -> Option<i16> {
-        if let WitNode::PrimS16(value) = self.node() {
+        if let WitNode::PrimS16(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -180,7 +299,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn s32(&self) -> Option<i32> {
-        if let WitNode::PrimS32(value) = self.node() {
+        if let WitNode::PrimS32(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -188,7 +307,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn s64(&self) -> Option<i64> {
-        if let WitNode::PrimS64(value) = self.node() {
+        if let WitNode::PrimS64(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -196,7 +315,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn f32(&self) -> Option<f32> {
-        if let WitNode::PrimFloat32(value) = self.node() {
+        if let WitNode::PrimFloat32(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -204,7 +323,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn f64(&self) -> Option<f64> {
-        if let WitNode::PrimFloat64(value) = self.node() {
+        if let WitNode::PrimFloat64(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -212,7 +331,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn char(&self) -> Option<char> {
-        if let WitNode::PrimChar(value) = self.node() {
+        if let WitNode::PrimChar(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -220,7 +339,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn bool(&self) -> Option<bool> {
-        if let WitNode::PrimBool(value) = self.node() {
+        if let WitNode::PrimBool(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -228,7 +347,15 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn string(&self) -> Option<&'a str> {
-        if let WitNode::PrimString(value) = self.node() {
+        if let WitNode::PrimString(value) = self.node()? {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    pub fn bytes(&self) -> Option<&'a [u8]> {
+        if let WitNode::PrimBytes(value) = self.node()? {
             Some(value)
         } else {
             None
@@ -236,26 +363,23 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn field(&self, field_idx: usize) -> Option<WitNodePointer<'a>> {
-        if let WitNode::RecordValue(fields) = self.node() {
-            fields
-                .get(field_idx)
-                .map(|idx| WitNodePointer::new(self.value, *idx as usize))
+        if let WitNode::RecordValue(fields) = self.node()? {
+            fields.get(field_idx).and_then(|idx| self.child(*idx))
         } else {
             None
         }
     }
 
     pub fn variant(&self) -> Option<(u32, Option<WitNodePointer<'a>>)> {
-        if let WitNode::VariantValue((case, value)) = self.node() {
-            let value = value.map(|idx| WitNodePointer::new(self.value, idx as usize));
-            Some((*case, value))
+        if let WitNode::VariantValue((case, value)) = self.node()? {
+            Some((*case, self.optional_child(*value)?))
         } else {
             None
         }
     }
 
     pub fn enum_value(&self) -> Option<u32> {
-        if let WitNode::EnumValue(value) = self.node() {
+        if let WitNode::EnumValue(value) = self.node()? {
             Some(*value)
         } else {
             None
@@ -263,7 +387,7 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn flags(&self) -> Option<&'a [bool]> {
-        if let WitNode::FlagsValue(value) = self.node() {
+        if let WitNode::FlagsValue(value) = self.node()? {
             Some(value)
         } else {
             None
@@ -271,56 +395,127 @@ impl<'a> WitNodePointer<'a> {
     }
 
     pub fn tuple_element(&self, element_idx: usize) -> Option<WitNodePointer<'a>> {
-        if let WitNode::TupleValue(elements) = self.node() {
-            elements
-                .get(element_idx)
-                .map(|idx| WitNodePointer::new(self.value, *idx as usize))
+        if let WitNode::TupleValue(elements) = self.node()? {
+            elements.get(element_idx).and_then(|idx| self.child(*idx))
         } else {
             None
         }
     }
 
     pub fn list_elements<R>(&self, f: impl Fn(WitNodePointer<'a>) -> R) -> Option<Vec<R>> {
-        if let WitNode::ListValue(elements) = self.node() {
-            Some(
-                elements
-                    .iter()
-                    .map(|idx| f(WitNodePointer::new(self.value, *idx as usize)))
-                    .collect(),
-            )
-        } else {
-            None
-        }
+        Some(self.list_iter()?.map(f).collect())
+    }
+
+    /// Iterates the elements of a list without collecting them, or `None` if it is not a list or any of its elements
+    /// is out of range. The elements of a list of bytes are read from its single node.
+    pub fn list_iter(&self) -> Option<ListElements<'a>> {
+        let elements = match self.node()? {
+            WitNode::ListValue(indices) => {
+                for idx in indices {
+                    CheckedNodeIndex::new(*idx, &self.value.nodes).ok()?;
+                }
+                Elements::Nodes(indices.iter())
+            }
+            WitNode::PrimBytes(bytes) => Elements::Bytes(bytes.iter()),
+            _ => return None,
+        };
+        Some(ListElements {
+            list: self.clone(),
+            elements,
+        })
     }
 
     pub fn option(&self) -> Option<Option<WitNodePointer<'a>>> {
-        if let WitNode::OptionValue(value) = self.node() {
-            Some(value.map(|idx| WitNodePointer::new(self.value, idx as usize)))
+        if let WitNode::OptionValue(value) = self.node()? {
+            self.optional_child(*value)
         } else {
             None
         }
     }
 
     pub fn result(&self) -> Option<Result<Option<WitNodePointer<'a>>, Option<WitNodePointer<'a>>>> {
-        if let WitNode::ResultValue(value) = self.node() {
-            Some(match value {
-                Ok(idx) => Ok(idx.map(|idx| WitNodePointer::new(self.value, idx as usize))),
-                Err(idx) => Err(idx.map(|idx| WitNodePointer::new(self.value, idx as usize))),
-            })
+        if let WitNode::ResultValue(value) = self.node()? {
+            match value {
+                Ok(idx) => self.optional_child(*idx).map(Ok),
+                Err(idx) => self.optional_child(*idx).map(Err),
+            }
         } else {
             None
         }
     }
 
     pub fn handle(&self) -> Option<(Uri, u64)> {
-        if let WitNode::Handle((uri, idx)) = self.node() {
+        if let WitNode::Handle((uri, idx)) = self.node()? {
             Some((uri.clone(), *idx))
         } else {
             None
         }
     }
+
+    /// Copies the subtree rooted at this node into a standalone WitValue, renumbering the indices of its nodes, so
+    /// a part of a large value can be forwarded without converting it to a [Value](crate::Value). The nodes shared
+    /// within the subtree, like the interned strings, stay shared.
+    pub fn to_wit_value(&self) -> Option<WitValue> {
+        if let Some(byte) = self.byte {
+            return Some(WitValue {
+                nodes: vec![WitNode::PrimU8(byte)],
+            });
+        }
+        let mut nodes = vec![self.node()?.clone()];
+        let mut renumbered = BTreeMap::from([(self.idx, 0)]);
+        let mut next = 0;
+        while let Some(node) = nodes.get_mut(next) {
+            let mut node = core::mem::replace(node, WitNode::PrimBool(false));
+            for child in node::children_mut(&mut node) {
+                let idx = CheckedNodeIndex::new(*child, &self.value.nodes).ok()?;
+                *child = match renumbered.get(&idx) {
+                    Some(renumbered) => *renumbered,
+                    None => {
+                        let new_idx = CheckedNodeIndex::next(nodes.len()).ok()?.index();
+                        nodes.push(idx.node(&self.value.nodes).ok()?.clone());
+                        renumbered.insert(idx, new_idx);
+                        new_idx
+                    }
+                };
+            }
+            nodes[next] = node;
+            next += 1;
+        }
+        Some(WitValue { nodes })
+    }
+}
+
+/// The elements of a list, pointed to one at a time
+pub struct ListElements<'a> {
+    list: WitNodePointer<'a>,
+    elements: Elements<'a>,
+}
+
+enum Elements<'a> {
+    Nodes(core::slice::Iter<'a, NodeIndex>),
+    Bytes(core::slice::Iter<'a, u8>),
+}
+
+impl<'a> Iterator for ListElements<'a> {
+    type Item = WitNodePointer<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.elements {
+            Elements::Nodes(indices) => self.list.child(*indices.next()?),
+            Elements::Bytes(bytes) => Some(self.list.byte(*bytes.next()?)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.elements {
+            Elements::Nodes(indices) => indices.size_hint(),
+            Elements::Bytes(bytes) => bytes.size_hint(),
+        }
+    }
 }
 
+impl ExactSizeIterator for ListElements<'_> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -471,6 +666,155 @@ mod tests {
         assert!(value.result().unwrap().err().unwrap().is_none());
     }
 
+    #[test]
+    fn single_primitives() {
+        assert_eq!(WitValue::builder().u64(42).as_single_u64(), Some(42));
+        assert_eq!(WitValue::builder().u64(42).as_single_u32(), None);
+
+        let result = WitValue::builder().tuple().item().string("hello").finish();
+        assert_eq!(result.as_single_string(), Some("hello"));
+
+        let results = WitValue::builder()
+            .tuple()
+            .item()
+            .string("hello")
+            .item()
+            .string("world")
+            .finish();
+        assert_eq!(results.as_single_string(), None);
+
+        let record = WitValue::builder().record().item().bool(true).finish();
+        assert_eq!(record.as_single_bool(), None);
+
+        let empty = WitValue { nodes: Vec::new() };
+        assert_eq!(empty.as_single_s32(), None);
+    }
+
+    #[test]
+    fn malformed_children() {
+        let with_child = |node: WitNode| WitValue {
+            nodes: vec![node, WitNode::PrimU8(1)],
+        };
+        let record = with_child(WitNode::RecordValue(vec![1, -1, 7]));
+        assert_eq!(record.field(0).and_then(|field| field.u8()), Some(1));
+        assert!(record.field(1).is_none());
+        assert!(record.field(2).is_none());
+
+        let list = with_child(WitNode::ListValue(vec![1, 8]));
+        assert_eq!(list.list_elements(|element| element.u8()), None);
+        assert!(with_child(WitNode::OptionValue(Some(-2)))
+            .option()
+            .is_none());
+        assert!(with_child(WitNode::ResultValue(Err(Some(9))))
+            .result()
+            .is_none());
+        assert!(with_child(WitNode::VariantValue((0, Some(2))))
+            .variant()
+            .is_none());
+        assert!(WitValue { nodes: Vec::new() }.u8().is_none());
+    }
+
+    #[test]
+    fn subtrees() {
+        let value = WitValue::builder()
+            .record()
+            .item()
+            .u8(1)
+            .item()
+            .list()
+            .item()
+            .option_some()
+            .s32(-1)
+            .finish()
+            .item()
+            .option_none()
+            .finish()
+            .finish();
+        let field = value.field(1).unwrap().to_wit_value().unwrap();
+        assert_eq!(
+            Value::from(field),
+            Value::List(vec![
+                Value::Option(Some(Box::new(Value::S32(-1)))),
+                Value::Option(None),
+            ])
+        );
+        let field = value.field(0).unwrap().to_wit_value().unwrap();
+        assert_eq!(Value::from(field), Value::U8(1));
+
+        let interned = WitValue::builder()
+            .intern_strings()
+            .tuple()
+            .item()
+            .list_fn(&["a", "b", "a"], |s, b| b.string(s))
+            .finish();
+        let list = interned.tuple_element(0).unwrap().to_wit_value().unwrap();
+        assert_eq!(list.nodes.len(), 3);
+        assert_eq!(
+            Value::from(list),
+            Value::List(vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string()),
+                Value::String("a".to_string()),
+            ])
+        );
+
+        let malformed = WitValue {
+            nodes: vec![WitNode::TupleValue(vec![1]), WitNode::OptionValue(Some(5))],
+        };
+        assert!(malformed.tuple_element(0).unwrap().to_wit_value().is_none());
+    }
+
+    #[test]
+    fn bytes() {
+        let value = WitValue::builder().bytes(&[1, 2, 3]);
+        assert_eq!(value.bytes(), Some([1, 2, 3].as_slice()));
+        assert_eq!(
+            value.list_elements(|element| element.u8()),
+            Some(vec![Some(1), Some(2), Some(3)])
+        );
+        assert_eq!(
+            value.list_elements(|element| element.s8()),
+            Some(vec![None, None, None])
+        );
+        let element = value
+            .list_elements(|element| element.to_wit_value())
+            .unwrap();
+        assert_eq!(
+            element[1].as_ref().and_then(|element| element.u8()),
+            Some(2)
+        );
+
+        let value = WitValue::builder().list_fn(&[1u8, 2], |byte, builder| builder.u8(*byte));
+        assert_eq!(value.bytes(), None);
+    }
+
+    #[test]
+    fn list_iter() {
+        let value = WitValue::builder().list_fn(&[1u32, 2, 3], |n, b| b.u32(*n));
+        let elements = value.list_iter().unwrap();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(
+            elements.map(|element| element.u32()).collect::<Vec<_>>(),
+            vec![Some(1), Some(2), Some(3)]
+        );
+
+        let bytes = WitValue::builder().bytes(&[7, 8]);
+        assert_eq!(
+            bytes
+                .list_iter()
+                .unwrap()
+                .map(|element| element.u8())
+                .collect::<Vec<_>>(),
+            vec![Some(7), Some(8)]
+        );
+
+        let malformed = WitValue {
+            nodes: vec![WitNode::ListValue(vec![0, 3])],
+        };
+        assert!(malformed.list_iter().is_none());
+        assert!(WitValue::builder().u8(1).list_iter().is_none());
+    }
+
     #[test]
     fn handle() {
         let value = WitValue::builder().handle(
@@ -0,0 +1,394 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The state of the connection to a target worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Healthy,
+    /// The connection failed the health checks and is being replaced
+    Reconnecting,
+    /// The connection failed the health checks and could not be replaced. It is reconnected again by the next health
+    /// check or invocation.
+    Unhealthy,
+}
+
+type StateChangeCallback = Arc<dyn Fn(&Uri, ConnectionState) + Send + Sync>;
+
+/// Configures the periodic health checks of the connections to the target workers
+#[derive(Clone)]
+pub struct HealthChecks {
+    interval: Duration,
+    failure_threshold: u32,
+    idle_timeout: Duration,
+    on_state_change: Option<StateChangeCallback>,
+}
+
+impl HealthChecks {
+    /// Pings the connections at the given interval. By default a connection is reconnected after 3 failed pings, and
+    /// it is no longer checked if it was not used for an invocation in 10 minutes.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            failure_threshold: 3,
+            idle_timeout: Duration::from_secs(600),
+            on_state_change: None,
+        }
+    }
+
+    /// The number of consecutive failed pings after which the connection is reconnected, at least one
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// The time after the last invocation of a worker when its connection is no longer checked
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Called with the new state whenever the state of a connection changes
+    pub fn on_state_change(
+        mut self,
+        callback: impl Fn(&Uri, ConnectionState) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state_change = Some(Arc::new(callback));
+        self
+    }
+}
+
+struct Connection {
+    uri: Uri,
+    state: ConnectionState,
+    failed_pings: u32,
+    last_used: Instant,
+}
+
+/// Tracks the connections to the workers invoked through the wrapped transport, pinging them with [Self::check] and
+/// reconnecting the failing ones. Invocations of workers with unhealthy connections try to reconnect first.
+pub struct HealthCheckingTransport<T> {
+    inner: T,
+    config: HealthChecks,
+    connections: Mutex<HashMap<String, Connection>>,
+}
+
+impl<T: RpcTransport> HealthCheckingTransport<T> {
+    pub fn new(inner: T, config: HealthChecks) -> Self {
+        Self {
+            inner,
+            config,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The state of the connection to the worker, if it is tracked
+    pub fn state(&self, uri: &Uri) -> Option<ConnectionState> {
+        self.connections
+            .lock()
+            .unwrap()
+            .get(&uri.value)
+            .map(|connection| connection.state)
+    }
+
+    /// Pings every tracked connection once, and reconnects the ones reaching the failure threshold. Connections idle
+    /// for longer than the idle timeout are no longer tracked.
+    pub async fn check(&self) {
+        let uris = {
+            let mut connections = self.connections.lock().unwrap();
+            connections
+                .retain(|_, connection| connection.last_used.elapsed() < self.config.idle_timeout);
+            connections
+                .values()
+                .map(|connection| connection.uri.clone())
+                .collect::<Vec<_>>()
+        };
+        for uri in uris {
+            match self.inner.ping(&uri).await {
+                Ok(()) => self.update(&uri, |connection| {
+                    connection.failed_pings = 0;
+                    ConnectionState::Healthy
+                }),
+                Err(_) => {
+                    let mut reconnect = false;
+                    self.update(&uri, |connection| {
+                        connection.failed_pings += 1;
+                        reconnect = connection.failed_pings >= self.config.failure_threshold;
+                        connection.state
+                    });
+                    if reconnect {
+                        self.reconnect(&uri).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&self, uri: &Uri) {
+        self.update(uri, |_| ConnectionState::Reconnecting);
+        let result = self.inner.reconnect(uri).await;
+        self.update(uri, |connection| match result {
+            Ok(()) => {
+                connection.failed_pings = 0;
+                ConnectionState::Healthy
+            }
+            Err(_) => ConnectionState::Unhealthy,
+        });
+    }
+
+    /// Updates the state of a tracked connection, and notifies the callback if it changed
+    fn update(&self, uri: &Uri, f: impl FnOnce(&mut Connection) -> ConnectionState) {
+        let changed = {
+            let mut connections = self.connections.lock().unwrap();
+            match connections.get_mut(&uri.value) {
+                Some(connection) => {
+                    let state = f(connection);
+                    let changed = connection.state != state;
+                    connection.state = state;
+                    changed.then_some(state)
+                }
+                None => None,
+            }
+        };
+        // The callback is called without holding the lock, so it can query the states
+        if let (Some(state), Some(callback)) = (changed, &self.config.on_state_change) {
+            callback(uri, state);
+        }
+    }
+
+    /// Starts tracking the connection to the worker, and returns its state
+    fn touch(&self, uri: &Uri) -> ConnectionState {
+        let mut connections = self.connections.lock().unwrap();
+        let connection = connections
+            .entry(uri.value.clone())
+            .or_insert_with(|| Connection {
+                uri: uri.clone(),
+                state: ConnectionState::Healthy,
+                failed_pings: 0,
+                last_used: Instant::now(),
+            });
+        connection.last_used = Instant::now();
+        connection.state
+    }
+}
+
+impl<T: RpcTransport + 'static> HealthCheckingTransport<T> {
+    /// Runs [Self::check] at the configured interval on the current tokio runtime, until the transport is dropped
+    pub fn spawn_checks(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let transport = Arc::downgrade(self);
+        let interval = self.config.interval;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match transport.upgrade() {
+                    Some(transport) => transport.check().await,
+                    None => break,
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for HealthCheckingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        if self.touch(uri) == ConnectionState::Unhealthy {
+            self.reconnect(uri).await;
+        }
+        self.inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        if self.touch(uri) == ConnectionState::Unhealthy {
+            self.reconnect(uri).await;
+        }
+        self.inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::health::{ConnectionState, HealthCheckingTransport, HealthChecks};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// A connection which can be broken, and repaired by reconnecting if `repairable`
+    #[derive(Default)]
+    struct Flaky {
+        broken: AtomicBool,
+        repairable: AtomicBool,
+        reconnects: AtomicU32,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Flaky {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            if self.broken.load(Ordering::SeqCst) {
+                Err(RpcError::ProtocolError("broken".to_string()))
+            } else {
+                Ok(Value::Tuple(vec![]).into())
+            }
+        }
+
+        async fn ping(&self, _uri: &Uri) -> Result<(), RpcError> {
+            if self.broken.load(Ordering::SeqCst) {
+                Err(RpcError::ProtocolError("broken".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+
+        async fn reconnect(&self, _uri: &Uri) -> Result<(), RpcError> {
+            self.reconnects.fetch_add(1, Ordering::SeqCst);
+            if self.repairable.load(Ordering::SeqCst) {
+                self.broken.store(false, Ordering::SeqCst);
+                Ok(())
+            } else {
+                Err(RpcError::ProtocolError("unreachable".to_string()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_connections_are_reconnected() {
+        let flaky = Arc::new(Flaky::default());
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded_changes = changes.clone();
+        let transport = HealthCheckingTransport::new(
+            flaky.clone(),
+            HealthChecks::new(Duration::from_secs(1))
+                .with_failure_threshold(2)
+                .on_state_change(move |uri, state| {
+                    recorded_changes
+                        .lock()
+                        .unwrap()
+                        .push((uri.value.clone(), state))
+                }),
+        );
+        let uri = Uri::worker("component", "worker-1");
+        assert_eq!(transport.state(&uri), None);
+        transport
+            .invoke_and_await(&uri, "run", vec![])
+            .await
+            .unwrap();
+        assert_eq!(transport.state(&uri), Some(ConnectionState::Healthy));
+
+        flaky.broken.store(true, Ordering::SeqCst);
+        transport.check().await;
+        assert_eq!(flaky.reconnects.load(Ordering::SeqCst), 0);
+        transport.check().await;
+        assert_eq!(flaky.reconnects.load(Ordering::SeqCst), 1);
+        assert_eq!(transport.state(&uri), Some(ConnectionState::Unhealthy));
+
+        // The next invocation reconnects before calling the worker
+        flaky.repairable.store(true, Ordering::SeqCst);
+        transport
+            .invoke_and_await(&uri, "run", vec![])
+            .await
+            .unwrap();
+        assert_eq!(flaky.reconnects.load(Ordering::SeqCst), 2);
+        assert_eq!(transport.state(&uri), Some(ConnectionState::Healthy));
+
+        let worker = "worker://component/worker-1".to_string();
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![
+                (worker.clone(), ConnectionState::Reconnecting),
+                (worker.clone(), ConnectionState::Unhealthy),
+                (worker.clone(), ConnectionState::Reconnecting),
+                (worker, ConnectionState::Healthy),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_not_checked() {
+        let flaky = Arc::new(Flaky::default());
+        let transport = HealthCheckingTransport::new(
+            flaky.clone(),
+            HealthChecks::new(Duration::from_secs(1))
+                .with_failure_threshold(1)
+                .with_idle_timeout(Duration::ZERO),
+        );
+        let uri = Uri::worker("component", "worker-1");
+        transport
+            .invoke_and_await(&uri, "run", vec![])
+            .await
+            .unwrap();
+
+        flaky.broken.store(true, Ordering::SeqCst);
+        transport.check().await;
+        assert_eq!(flaky.reconnects.load(Ordering::SeqCst), 0);
+        assert_eq!(transport.state(&uri), None);
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::metrics::RpcMetrics;
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+/// Configures the hedging of the invocations of idempotent functions
+#[derive(Debug, Clone)]
+pub struct Hedging {
+    delay: Duration,
+    idempotent_functions: HashSet<String>,
+}
+
+impl Hedging {
+    /// Sends a second attempt of the invocations not completed within the delay
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            idempotent_functions: HashSet::new(),
+        }
+    }
+
+    /// Marks a function as idempotent, by its remote name like `rpc:counter/api.{get}`. Only the invocations of
+    /// idempotent functions are hedged, as the worker may execute both attempts.
+    pub fn idempotent(mut self, function_name: impl Into<String>) -> Self {
+        self.idempotent_functions.insert(function_name.into());
+        self
+    }
+}
+
+/// Hedges the invocations of idempotent functions: if the first attempt does not complete within the delay, a second
+/// one is sent, and the result of the one completing first successfully is returned. The other attempt is cancelled.
+pub struct HedgingTransport<T> {
+    inner: T,
+    config: Hedging,
+    metrics: Option<Arc<dyn RpcMetrics>>,
+}
+
+impl<T: RpcTransport> HedgingTransport<T> {
+    pub fn new(inner: T, config: Hedging) -> Self {
+        Self {
+            inner,
+            config,
+            metrics: None,
+        }
+    }
+
+    /// Reports the hedged invocations
+    pub fn with_metrics(mut self, metrics: Arc<dyn RpcMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for HedgingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        if !self.config.idempotent_functions.contains(function_name) {
+            return self
+                .inner
+                .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+                .await;
+        }
+
+        let mut first = pin!(self.inner.invoke_and_await_with_metadata(
+            uri,
+            function_name,
+            function_params.clone(),
+            metadata
+        ));
+        let mut delay = pin!(tokio::time::sleep(self.config.delay));
+        let completed = poll_fn(|cx| match first.as_mut().poll(cx) {
+            Poll::Ready(result) => Poll::Ready(Some(result)),
+            Poll::Pending => delay.as_mut().poll(cx).map(|_| None),
+        })
+        .await;
+        if let Some(result) = completed {
+            return result;
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.hedged(uri, function_name);
+        }
+        let mut second = pin!(self.inner.invoke_and_await_with_metadata(
+            uri,
+            function_name,
+            function_params,
+            metadata
+        ));
+        // Returns the first success, or the error of the second attempt if both fail. Dropping the future of the
+        // attempt still running cancels it.
+        let mut first_error = None;
+        let mut second_error = None;
+        poll_fn(|cx| {
+            if first_error.is_none() {
+                match first.as_mut().poll(cx) {
+                    Poll::Ready(Ok(value)) => return Poll::Ready(Ok(value)),
+                    Poll::Ready(Err(err)) => first_error = Some(err),
+                    Poll::Pending => {}
+                }
+            }
+            if second_error.is_none() {
+                match second.as_mut().poll(cx) {
+                    Poll::Ready(Ok(value)) => return Poll::Ready(Ok(value)),
+                    Poll::Ready(Err(err)) => second_error = Some(err),
+                    Poll::Pending => {}
+                }
+            }
+            match (&first_error, &second_error) {
+                (Some(_), Some(err)) => Poll::Ready(Err(err.clone())),
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        self.inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hedge::{Hedging, HedgingTransport};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// The first call is slow, the later ones are fast, and each returns its number
+    #[derive(Default)]
+    struct SlowFirst {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl RpcTransport for SlowFirst {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call == 0 {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            Ok(Value::U32(call).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_idempotent_invocations_are_hedged() {
+        let transport = HedgingTransport::new(
+            SlowFirst::default(),
+            Hedging::new(Duration::from_millis(20)).idempotent("rpc:counter/api.{get}"),
+        );
+        let uri = Uri::worker("component", "worker-1");
+
+        let start = Instant::now();
+        let result = transport
+            .invoke_and_await(&uri, "rpc:counter/api.{get}", vec![])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::U32(1));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(transport.inner.calls.load(Ordering::SeqCst), 2);
+
+        let result = transport
+            .invoke_and_await(&uri, "rpc:counter/api.{add}", vec![])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::U32(2));
+        assert_eq!(transport.inner.calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::WorkerUri;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The name of the metadata entry carrying the idempotency key of an invocation
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Generates the idempotency keys of the invocations made by a caller worker, from its id and the sequence number of
+/// the invocation. The keys are deterministic, so a caller replaying its invocations after a restart generates the
+/// same keys again, as long as it resumes the sequence with [IdempotencyKeys::starting_at].
+#[derive(Debug)]
+pub struct IdempotencyKeys {
+    caller: String,
+    next: AtomicU64,
+}
+
+impl IdempotencyKeys {
+    /// Generates the keys of the invocations of the given caller worker
+    pub fn new(caller: &WorkerUri) -> Self {
+        Self {
+            caller: format!("{}/{}", caller.component_id, caller.worker_name),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts the sequence at the given number instead of zero
+    pub fn starting_at(self, sequence: u64) -> Self {
+        Self {
+            next: AtomicU64::new(sequence),
+            ..self
+        }
+    }
+
+    /// The key of the next invocation, like `<component-id>/<worker-name>/<sequence>`
+    pub fn next_key(&self) -> String {
+        let sequence = self.next.fetch_add(1, Ordering::Relaxed);
+        format!("{}/{sequence}", self.caller)
+    }
+
+    /// Adds the key of the next invocation to the metadata, unless it already has one
+    pub fn attach(&self, metadata: &mut Vec<(String, String)>) {
+        if idempotency_key(metadata).is_none() {
+            metadata.push((IDEMPOTENCY_KEY.to_string(), self.next_key()));
+        }
+    }
+}
+
+/// The idempotency key of an invocation, given its metadata
+pub fn idempotency_key(metadata: &[(String, String)]) -> Option<&str> {
+    metadata
+        .iter()
+        .find(|(name, _)| name == IDEMPOTENCY_KEY)
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idempotency::{idempotency_key, IdempotencyKeys, IDEMPOTENCY_KEY};
+    use crate::WorkerUri;
+
+    #[test]
+    fn keys_are_derived_from_the_caller_and_the_sequence() {
+        let caller = WorkerUri::new("component", "worker-1");
+        let keys = IdempotencyKeys::new(&caller);
+        assert_eq!(keys.next_key(), "component/worker-1/0");
+        assert_eq!(keys.next_key(), "component/worker-1/1");
+
+        let resumed = IdempotencyKeys::new(&caller).starting_at(1);
+        let mut metadata = vec![("tenant".to_string(), "acme".to_string())];
+        resumed.attach(&mut metadata);
+        assert_eq!(idempotency_key(&metadata), Some("component/worker-1/1"));
+
+        let mut metadata = vec![(IDEMPOTENCY_KEY.to_string(), "given".to_string())];
+        resumed.attach(&mut metadata);
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(idempotency_key(&metadata), Some("given"));
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Value;
+use golem_wasm_ast::analysis::{AnalysedResourceId, AnalysedResourceMode, AnalysedType};
+
+/// Infers a best-effort type of a value. The values do not carry the names of their record fields, variant cases,
+/// enum cases and flags, so they are named by their position: `field0`, `case0` and `flag0`. The variants and enums
+/// get as many cases as needed for the case of the value.
+///
+/// The types of the parts of a value which are not present, like the elements of an empty list or the payload of a
+/// none option, are unknown, and inferred as `tuple<>`. The elements of a list are unified: an unknown type is replaced
+/// by the known one, the integers of the same signedness are widened, `f32` is widened to `f64`, the variants and enums
+/// get the cases of all the elements, and otherwise the type of the first element is kept. Handles are inferred as owned
+/// resources with the id 0.
+pub fn infer_type(value: &Value) -> AnalysedType {
+    match value {
+        Value::Bool(_) => AnalysedType::Bool,
+        Value::U8(_) => AnalysedType::U8,
+        Value::U16(_) => AnalysedType::U16,
+        Value::U32(_) => AnalysedType::U32,
+        Value::U64(_) => AnalysedType::U64,
+        Value::S8(_) => AnalysedType::S8,
+        Value::S16(_) => AnalysedType::S16,
+        Value::S32(_) => AnalysedType::S32,
+        Value::S64(_) => AnalysedType::S64,
+        Value::F32(_) => AnalysedType::F32,
+        Value::F64(_) => AnalysedType::F64,
+        Value::Char(_) => AnalysedType::Chr,
+        Value::String(_) => AnalysedType::Str,
+        Value::List(values) => AnalysedType::List(Box::new(
+            values
+                .iter()
+                .map(infer_type)
+                .reduce(unify)
+                .unwrap_or_else(unknown),
+        )),
+        Value::Tuple(values) => AnalysedType::Tuple(values.iter().map(infer_type).collect()),
+        Value::Record(values) => AnalysedType::Record(
+            values
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| (format!("field{idx}"), infer_type(value)))
+                .collect(),
+        ),
+        Value::Variant {
+            case_idx,
+            case_value,
+        } => AnalysedType::Variant(
+            (0..=*case_idx)
+                .map(|idx| {
+                    let typ = if idx == *case_idx {
+                        case_value.as_deref().map(infer_type)
+                    } else {
+                        None
+                    };
+                    (format!("case{idx}"), typ)
+                })
+                .collect(),
+        ),
+        Value::Enum(case_idx) => AnalysedType::Enum(case_names(*case_idx as usize + 1)),
+        Value::Flags(flags) => {
+            AnalysedType::Flags((0..flags.len()).map(|idx| format!("flag{idx}")).collect())
+        }
+        Value::Option(value) => AnalysedType::Option(Box::new(
+            value.as_deref().map(infer_type).unwrap_or_else(unknown),
+        )),
+        Value::Result(result) => {
+            let (ok, error) = match result {
+                Ok(value) => (Some(infer_optional(value)), None),
+                Err(value) => (None, Some(infer_optional(value))),
+            };
+            AnalysedType::Result {
+                ok: ok.flatten(),
+                error: error.flatten(),
+            }
+        }
+        Value::Handle { .. } => AnalysedType::Resource {
+            id: AnalysedResourceId { value: 0 },
+            resource_mode: AnalysedResourceMode::Owned,
+        },
+    }
+}
+
+fn infer_optional(value: &Option<Box<Value>>) -> Option<Box<AnalysedType>> {
+    value.as_deref().map(|value| Box::new(infer_type(value)))
+}
+
+fn unknown() -> AnalysedType {
+    AnalysedType::Tuple(Vec::new())
+}
+
+fn is_unknown(typ: &AnalysedType) -> bool {
+    matches!(typ, AnalysedType::Tuple(types) if types.is_empty())
+}
+
+fn case_names(count: usize) -> Vec<String> {
+    (0..count).map(|idx| format!("case{idx}")).collect()
+}
+
+/// The rank of an integer type among the ones of the same signedness
+fn integer_rank(typ: &AnalysedType) -> Option<(bool, u8)> {
+    match typ {
+        AnalysedType::U8 => Some((false, 0)),
+        AnalysedType::U16 => Some((false, 1)),
+        AnalysedType::U32 => Some((false, 2)),
+        AnalysedType::U64 => Some((false, 3)),
+        AnalysedType::S8 => Some((true, 0)),
+        AnalysedType::S16 => Some((true, 1)),
+        AnalysedType::S32 => Some((true, 2)),
+        AnalysedType::S64 => Some((true, 3)),
+        _ => None,
+    }
+}
+
+/// The type of a list having elements of both types
+fn unify(first: AnalysedType, second: AnalysedType) -> AnalysedType {
+    if is_unknown(&first) {
+        return second;
+    }
+    if is_unknown(&second) || first == second {
+        return first;
+    }
+    match (first, second) {
+        (AnalysedType::F32, AnalysedType::F64) => AnalysedType::F64,
+        (AnalysedType::List(first), AnalysedType::List(second)) => {
+            AnalysedType::List(Box::new(unify(*first, *second)))
+        }
+        (AnalysedType::Option(first), AnalysedType::Option(second)) => {
+            AnalysedType::Option(Box::new(unify(*first, *second)))
+        }
+        (AnalysedType::Tuple(first), AnalysedType::Tuple(second))
+            if first.len() == second.len() =>
+        {
+            AnalysedType::Tuple(
+                first
+                    .into_iter()
+                    .zip(second)
+                    .map(|(a, b)| unify(a, b))
+                    .collect(),
+            )
+        }
+        (AnalysedType::Record(first), AnalysedType::Record(second))
+            if first.len() == second.len() =>
+        {
+            AnalysedType::Record(
+                first
+                    .into_iter()
+                    .zip(second)
+                    .map(|((name, a), (_, b))| (name, unify(a, b)))
+                    .collect(),
+            )
+        }
+        (AnalysedType::Variant(first), AnalysedType::Variant(second)) => {
+            let (mut longer, shorter) = if first.len() >= second.len() {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            for (idx, (_, typ)) in shorter.into_iter().enumerate() {
+                longer[idx].1 = match (longer[idx].1.take(), typ) {
+                    (Some(a), Some(b)) => Some(unify(a, b)),
+                    (a, b) => a.or(b),
+                };
+            }
+            AnalysedType::Variant(longer)
+        }
+        (AnalysedType::Enum(first), AnalysedType::Enum(second)) => {
+            AnalysedType::Enum(case_names(first.len().max(second.len())))
+        }
+        (
+            AnalysedType::Result {
+                ok: first_ok,
+                error: first_error,
+            },
+            AnalysedType::Result {
+                ok: second_ok,
+                error: second_error,
+            },
+        ) => AnalysedType::Result {
+            ok: unify_optional(first_ok, second_ok),
+            error: unify_optional(first_error, second_error),
+        },
+        (first, second) => match (integer_rank(&first), integer_rank(&second)) {
+            (Some((first_signed, first_rank)), Some((second_signed, second_rank)))
+                if first_signed == second_signed && second_rank > first_rank =>
+            {
+                second
+            }
+            _ => first,
+        },
+    }
+}
+
+fn unify_optional(
+    first: Option<Box<AnalysedType>>,
+    second: Option<Box<AnalysedType>>,
+) -> Option<Box<AnalysedType>> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(Box::new(unify(*first, *second))),
+        (first, second) => first.or(second),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::infer::infer_type;
+    use crate::Value;
+    use golem_wasm_ast::analysis::AnalysedType;
+
+    #[test]
+    fn primitives_and_records() {
+        let value = Value::Record(vec![
+            Value::String("counter".to_string()),
+            Value::U64(1),
+            Value::Option(None),
+        ]);
+        assert_eq!(
+            infer_type(&value),
+            AnalysedType::Record(vec![
+                ("field0".to_string(), AnalysedType::Str),
+                ("field1".to_string(), AnalysedType::U64),
+                (
+                    "field2".to_string(),
+                    AnalysedType::Option(Box::new(AnalysedType::Tuple(vec![])))
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn list_elements_are_unified() {
+        let value = Value::List(vec![
+            Value::Tuple(vec![Value::U8(1), Value::List(vec![]), Value::Option(None)]),
+            Value::Tuple(vec![
+                Value::U32(2),
+                Value::List(vec![Value::F32(1.0), Value::F64(2.0)]),
+                Value::Option(Some(Box::new(Value::Char('a')))),
+            ]),
+        ]);
+        assert_eq!(
+            infer_type(&value),
+            AnalysedType::List(Box::new(AnalysedType::Tuple(vec![
+                AnalysedType::U32,
+                AnalysedType::List(Box::new(AnalysedType::F64)),
+                AnalysedType::Option(Box::new(AnalysedType::Chr)),
+            ])))
+        );
+
+        // Conflicting types keep the first one
+        let value = Value::List(vec![
+            Value::S8(1),
+            Value::U64(2),
+            Value::String("3".to_string()),
+        ]);
+        assert_eq!(
+            infer_type(&value),
+            AnalysedType::List(Box::new(AnalysedType::S8))
+        );
+        assert_eq!(
+            infer_type(&Value::List(vec![])),
+            AnalysedType::List(Box::new(AnalysedType::Tuple(vec![])))
+        );
+    }
+
+    #[test]
+    fn variants_get_the_cases_of_all_elements() {
+        let value = Value::List(vec![
+            Value::Variant {
+                case_idx: 2,
+                case_value: Some(Box::new(Value::Bool(true))),
+            },
+            Value::Variant {
+                case_idx: 0,
+                case_value: Some(Box::new(Value::S16(1))),
+            },
+        ]);
+        assert_eq!(
+            infer_type(&value),
+            AnalysedType::List(Box::new(AnalysedType::Variant(vec![
+                ("case0".to_string(), Some(AnalysedType::S16)),
+                ("case1".to_string(), None),
+                ("case2".to_string(), Some(AnalysedType::Bool)),
+            ])))
+        );
+
+        let value = Value::List(vec![
+            Value::Result(Ok(Some(Box::new(Value::Enum(1))))),
+            Value::Result(Err(Some(Box::new(Value::String("error".to_string()))))),
+            Value::Result(Ok(Some(Box::new(Value::Enum(3))))),
+        ]);
+        assert_eq!(
+            infer_type(&value),
+            AnalysedType::List(Box::new(AnalysedType::Result {
+                ok: Some(Box::new(AnalysedType::Enum(vec![
+                    "case0".to_string(),
+                    "case1".to_string(),
+                    "case2".to_string(),
+                    "case3".to_string(),
+                ]))),
+                error: Some(Box::new(AnalysedType::Str)),
+            }))
+        );
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::idempotency::idempotency_key;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::Arc;
+
+/// An invocation of an exported function received by the host of the target worker, before it is dispatched to the
+/// worker
+#[derive(Debug, Clone)]
+pub struct IncomingCall {
+    /// The target worker
+    pub uri: Uri,
+    pub function_name: String,
+    pub function_params: Vec<WitValue>,
+    /// The metadata passed by the caller with the invocation, like the tracing context, auth tokens or tenancy
+    pub metadata: Vec<(String, String)>,
+}
+
+impl IncomingCall {
+    pub fn new(uri: Uri, function_name: impl Into<String>, function_params: Vec<WitValue>) -> Self {
+        Self {
+            uri,
+            function_name: function_name.into(),
+            function_params,
+            metadata: Vec::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// The value of the metadata entry with the given name, if any
+    pub fn metadata(&self, name: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The idempotency key of the invocation, shared by its retried attempts, if the caller passed one
+    pub fn idempotency_key(&self) -> Option<&str> {
+        idempotency_key(&self.metadata)
+    }
+}
+
+/// Observes and guards the incoming invocations handled by the host, like for authorization, quotas and audit
+/// logging. Every method does nothing by default.
+#[async_trait]
+pub trait Interceptor: Send + Sync {
+    /// Called before the invocation is dispatched. Failing rejects the invocation: it is not dispatched, and the
+    /// error is returned to the caller.
+    async fn before(&self, _call: &IncomingCall) -> Result<(), RpcError> {
+        Ok(())
+    }
+
+    /// Called after the invocation completed successfully
+    async fn after(&self, _call: &IncomingCall, _result: &WitValue) {}
+
+    /// Called after the invocation failed, or was rejected by a later interceptor
+    async fn on_error(&self, _call: &IncomingCall, _error: &RpcError) {}
+}
+
+/// The chain of interceptors of the incoming invocations, the receiving side counterpart of the layers of
+/// [crate::WasmRpcHostBuilder]. Embedders run the dispatch of each incoming invocation, decoding its parameters,
+/// calling the function and encoding its results, through [Interceptors::dispatch].
+///
+/// The `before` hooks run in the order the interceptors were added, and the `after` and `on_error` hooks in the
+/// reverse order, so the first interceptor is the outermost one. The interceptors whose `before` hook did not run, or
+/// failed, are not notified of the outcome.
+#[derive(Clone, Default)]
+pub struct Interceptors {
+    chain: Vec<Arc<dyn Interceptor>>,
+}
+
+impl Interceptors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.chain.push(Arc::new(interceptor));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
+    /// Runs the dispatch of an incoming invocation through the interceptors, returning its result, or the error of
+    /// the interceptor rejecting it
+    pub async fn dispatch<F, Fut>(
+        &self,
+        call: &IncomingCall,
+        dispatch: F,
+    ) -> Result<WitValue, RpcError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<WitValue, RpcError>>,
+    {
+        let mut entered = 0;
+        let mut rejection = None;
+        for interceptor in &self.chain {
+            match interceptor.before(call).await {
+                Ok(()) => entered += 1,
+                Err(err) => {
+                    rejection = Some(err);
+                    break;
+                }
+            }
+        }
+
+        let result = match rejection {
+            Some(err) => Err(err),
+            None => dispatch().await,
+        };
+
+        for interceptor in self.chain[..entered].iter().rev() {
+            match &result {
+                Ok(value) => interceptor.after(call, value).await,
+                Err(err) => interceptor.on_error(call, err).await,
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intercept::{IncomingCall, Interceptor, Interceptors};
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the hooks called, prefixed by its name
+    struct Audit {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Interceptor for Audit {
+        async fn before(&self, call: &IncomingCall) -> Result<(), RpcError> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} before {}", self.name, call.function_name));
+            Ok(())
+        }
+
+        async fn after(&self, _call: &IncomingCall, result: &WitValue) {
+            let result = Value::from(result.clone());
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} after {result:?}", self.name));
+        }
+
+        async fn on_error(&self, _call: &IncomingCall, error: &RpcError) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} error {error}", self.name));
+        }
+    }
+
+    /// Rejects the invocations of the admin interface
+    struct Auth;
+
+    #[async_trait]
+    impl Interceptor for Auth {
+        async fn before(&self, call: &IncomingCall) -> Result<(), RpcError> {
+            if call.function_name.starts_with("rpc:admin/") {
+                Err(RpcError::Denied("admin only".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptors_wrap_the_dispatch() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let audit = |name| Audit {
+            name,
+            log: log.clone(),
+        };
+        let interceptors = Interceptors::new()
+            .with(audit("outer"))
+            .with(Auth)
+            .with(audit("inner"));
+        let uri = Uri::worker("component", "worker-1");
+
+        let call = IncomingCall::new(uri.clone(), "rpc:counter/api.{get}", vec![]);
+        let result = interceptors
+            .dispatch(&call, || async { Ok(Value::U64(1).into()) })
+            .await;
+        assert!(result.is_ok());
+
+        let call = IncomingCall::new(uri.clone(), "rpc:counter/api.{add}", vec![]);
+        let result = interceptors
+            .dispatch(&call, || async {
+                Err(RpcError::RemoteInternalError("trapped".to_string()))
+            })
+            .await;
+        assert!(matches!(result, Err(RpcError::RemoteInternalError(_))));
+
+        let call = IncomingCall::new(uri, "rpc:admin/api.{reset}", vec![]);
+        let result = interceptors
+            .dispatch(&call, || async {
+                panic!("rejected invocations are not dispatched")
+            })
+            .await;
+        assert!(matches!(result, Err(RpcError::Denied(_))));
+
+        let log = log.lock().unwrap().clone();
+        let error = |message: &str| RpcError::RemoteInternalError(message.to_string());
+        assert_eq!(
+            log,
+            vec![
+                "outer before rpc:counter/api.{get}".to_string(),
+                "inner before rpc:counter/api.{get}".to_string(),
+                "inner after U64(1)".to_string(),
+                "outer after U64(1)".to_string(),
+                "outer before rpc:counter/api.{add}".to_string(),
+                "inner before rpc:counter/api.{add}".to_string(),
+                format!("inner error {}", error("trapped")),
+                format!("outer error {}", error("trapped")),
+                "outer before rpc:admin/api.{reset}".to_string(),
+                format!("outer error {}", RpcError::Denied("admin only".to_string())),
+            ]
+        );
+    }
+}
@@ -18,7 +18,7 @@ use serde_json::{Number, Value as JsonValue};
 use std::collections::HashMap;
 use std::str::FromStr;
 
-use crate::{Uri, Value};
+use crate::{unicode, Uri, Value};
 
 pub fn function_parameters(
     value: &JsonValue,
@@ -293,17 +293,18 @@ fn bigdecimal(value: &JsonValue) -> Result<BigDecimal, Vec<String>> {
 
 fn get_char(json: &JsonValue) -> Result<char, Vec<String>> {
     if let Some(num_u64) = json.as_u64() {
-        if num_u64 > u32::MAX as u64 {
-            Err(vec![format!(
+        let Ok(code_point) = u32::try_from(num_u64) else {
+            return Err(vec![format!(
                 "The value {} is too large to be converted to a char",
                 num_u64
-            )])
-        } else {
-            char::from_u32(num_u64 as u32).ok_or(vec![format!(
-                "The value {} is not a valid unicode character",
+            )]);
+        };
+        unicode::decode_char(code_point).map_err(|err| {
+            vec![format!(
+                "The value {} is not a valid unicode character: {err}",
                 num_u64
-            )])
-        }
+            )]
+        })
     } else {
         let type_description = type_description(json);
 
@@ -917,6 +918,13 @@ mod tests {
             prop_assert_eq!(result, Ok(Value::Char(value)));
         }
 
+        #[test]
+        fn test_invalid_char_param(value in prop_oneof![0xD800u64..=0xDFFF, 0x110000u64..=u64::MAX]) {
+            let json = JsonValue::Number(Number::from(value));
+            let result = validate_function_parameter(&json, &AnalysedType::Chr);
+            prop_assert!(result.is_err());
+        }
+
         #[test]
         fn test_string_param(value: String) {
             let json = JsonValue::String(value.clone());
@@ -12,11 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[allow(unused)]
-#[rustfmt::skip]
-#[cfg(not(feature = "host"))]
-#[cfg(feature = "stub")]
-mod bindings;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 /// Implements bincode encoders and decoders for WitValue instances
 #[cfg(feature = "bincode")]
@@ -24,34 +22,166 @@ pub mod bincode;
 /// A builder interface for WitValue instances
 mod builder;
 
+/// Caching the results of pure functions in the host transport
+#[cfg(feature = "transport")]
+pub mod cache;
+
+/// Fault injection into the invocations of a transport, for testing the callers
+#[cfg(feature = "transport")]
+pub mod chaos;
+
+/// Per worker circuit breakers of the host transport
+#[cfg(feature = "transport")]
+pub mod circuit;
+
+/// Payload codecs negotiated per connection by the host transport
+#[cfg(feature = "transport")]
+pub mod codec;
+
+/// Conversion of Rust types to and from values
+#[cfg(feature = "typeinfo")]
+pub mod convert;
+
+/// Replay hooks of the host for durable execution engines
+#[cfg(feature = "transport")]
+pub mod durable;
+
+/// End-to-end encryption of the payloads of the host transport
+#[cfg(feature = "encryption")]
+pub mod encrypt;
+
+/// Predicates over the contents of values, for routing, alerting and test assertions on the payloads
+#[cfg(feature = "typeinfo")]
+pub mod expr;
+
 /// Extension methods for extracting values from WitValue instances
 mod extractor;
 
+/// Health checks and reconnection of the connections of the host transport
+#[cfg(feature = "transport")]
+pub mod health;
+
+/// Hedging of the slow invocations of idempotent functions in the host transport
+#[cfg(feature = "transport")]
+pub mod hedge;
+
+/// Idempotency keys of the invocations, for de-duplicating the retried ones on the target side
+#[cfg(feature = "transport")]
+pub mod idempotency;
+
+/// Best-effort inference of the types of values
+#[cfg(feature = "typeinfo")]
+pub mod infer;
+
+/// Interceptors of the incoming invocations handled by the host of the target workers
+#[cfg(feature = "transport")]
+pub mod intercept;
+
 /// Conversion to and from JSON, in the presence of golem-wasm-ast generated type information
 #[cfg(feature = "json")]
 pub mod json;
 
+/// Concurrency and rate limits of the host transport
+#[cfg(feature = "transport")]
+pub mod limit;
+
+/// Loading and caching the exported function signatures of components
+#[cfg(feature = "metadata")]
+pub mod metadata;
+
+/// The metrics hook of the host transport
+#[cfg(feature = "transport")]
+pub mod metrics;
+
+/// Checked indices of the nodes of WitValue instances
+mod node;
+
+/// Offloading the huge values of the host transport to a blob store
+#[cfg(feature = "transport")]
+pub mod offload;
+
+/// The queue of the oneway invocations of the host transport
+#[cfg(feature = "transport")]
+pub mod oneway;
+
+/// Readable multi-line printing of values with their types
+#[cfg(feature = "typeinfo")]
+pub mod pretty;
+
 /// Protobuf-defined value types and conversion to them
 #[cfg(feature = "protobuf")]
 pub mod protobuf;
 
-/// Serde instances for WitValue
+/// Recording the invocations of the host, and replaying them without calling the workers
+#[cfg(feature = "transport")]
+pub mod record;
+
+/// Dispatching the incoming invocations to the component instances of the host, by their target workers
+#[cfg(feature = "transport")]
+pub mod router;
+
+/// Redaction of secrets from values before they are logged or recorded
+#[cfg(feature = "typeinfo")]
+pub mod redact;
+
+/// Generating arbitrary values of WIT types, for testing the invocations of functions
+#[cfg(all(feature = "arbitrary", feature = "typeinfo"))]
+pub mod sample;
+
+/// Selecting the target workers of the calls, like sharding by a key
+pub mod selector;
+
+/// Serde instances for WitValue and RpcError
 #[cfg(feature = "serde")]
 pub mod serde;
 
+/// Snapshot assertions of values, for readable tests of RPC responses
+#[cfg(feature = "typeinfo")]
+pub mod snapshot;
+
+/// Conversion to and from the WAVE text format, in the presence of golem-wasm-ast generated type information
 #[cfg(feature = "text")]
 mod text;
 
+/// The host side implementation of the `wasm-rpc` resource, on top of a pluggable transport
+#[cfg(feature = "transport")]
+pub mod transport;
+
+/// The types of the `golem:rpc/types` interface, shared by the guest bindings and the hosts not using wasmtime
+#[cfg(not(feature = "host"))]
+mod types;
+
+/// Validation of the chars and strings decoded from untrusted sources
+pub mod unicode;
+
+/// Parsing, validating and constructing worker URIs
+pub mod uri;
+
 #[cfg(feature = "wasmtime")]
 pub mod wasmtime;
 
 use crate::builder::WitValueBuilder;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 pub use builder::{NodeBuilder, WitValueBuilderExtensions};
-pub use extractor::{WitNodePointer, WitValueExtractor};
+pub use extractor::{ListElements, WitNodePointer, WitValueExtractor};
+pub use node::CheckedNodeIndex;
+
+#[cfg(feature = "text")]
+pub use text::{parse_value, print_value};
+
+#[cfg(feature = "transport")]
+pub use router::{RpcHandler, RpcRouter};
+pub use selector::{HashSharding, WorkerSelector};
+#[cfg(feature = "transport")]
+pub use transport::{RpcTransport, WasmRpcHost, WasmRpcHostBuilder};
+pub use uri::{UriError, WorkerUri};
 
 #[cfg(not(feature = "host"))]
-#[cfg(feature = "stub")]
-pub use bindings::golem::rpc::types::{NodeIndex, RpcError, Uri, WasmRpc, WitNode, WitValue};
+pub use types::{NodeIndex, RpcError, Uri, WitNode, WitValue};
 
 #[cfg(feature = "host")]
 use ::wasmtime::component::bindgen;
@@ -60,7 +190,7 @@ use ::wasmtime::component::bindgen;
 bindgen!({
     path: "wit",
     interfaces: "
-      import golem:rpc/types@0.1.0;
+      import golem:rpc/types@0.2.0;
     ",
     tracing: false,
     async: true,
@@ -72,6 +202,21 @@ bindgen!({
 #[cfg(feature = "host")]
 pub use golem::rpc::types::{Host, HostWasmRpc, NodeIndex, RpcError, Uri, WitNode, WitValue};
 
+/// The bindings of the `rpc-server` world, for calling the `golem:rpc/server` interface exported by the components
+/// serving RPC invocations
+#[cfg(feature = "host")]
+pub mod server {
+    ::wasmtime::component::bindgen!({
+        path: "wit",
+        world: "rpc-server",
+        tracing: false,
+        async: true,
+        with: {
+            "golem:rpc/types": crate::golem::rpc::types,
+        }
+    });
+}
+
 #[cfg(feature = "host")]
 pub struct WasmRpcEntry {
     pub payload: Box<dyn std::any::Any + Send + Sync>,
@@ -148,6 +293,16 @@ fn build_wit_value(value: Value, builder: &mut WitValueBuilder) -> NodeIndex {
         Value::F64(value) => builder.add_f64(value),
         Value::Char(value) => builder.add_char(value),
         Value::String(value) => builder.add_string(&value),
+        Value::List(values) if builder.compacts_bytes() && is_bytes(&values) => {
+            let bytes: Vec<u8> = values
+                .into_iter()
+                .filter_map(|value| match value {
+                    Value::U8(value) => Some(value),
+                    _ => None,
+                })
+                .collect();
+            builder.add_bytes(&bytes)
+        }
         Value::List(values) => {
             let list_idx = builder.add_list();
             let mut items = Vec::new();
@@ -223,25 +378,216 @@ fn build_wit_value(value: Value, builder: &mut WitValueBuilder) -> NodeIndex {
     }
 }
 
+/// The maximum nesting depth of the values converted by [Value::try_from_wit_value]
+pub const MAX_VALUE_DEPTH: usize = 512;
+
+impl Value {
+    /// Converts a WitValue received from an untrusted source, failing instead of panicking or overflowing the stack if
+    /// it is not a well formed tree: if it has no nodes, refers to a node out of range or to the same node more than
+    /// once, or is nested deeper than [MAX_VALUE_DEPTH]. Only the string nodes can be referred to more than once, as
+    /// they are when the strings are interned.
+    pub fn try_from_wit_value(value: WitValue) -> Result<Value, String> {
+        validate_tree(&value.nodes)?;
+        build_tree(
+            CheckedNodeIndex::root(&value.nodes)?.node(&value.nodes)?,
+            &value.nodes,
+        )
+    }
+
+    /// Converts to a WitValue storing each distinct string once, like the ones built with
+    /// `WitValue::builder().intern_strings()`
+    pub fn into_interned_wit_value(self) -> WitValue {
+        let mut builder = WitValueBuilder::new().intern_strings();
+        build_wit_value(self, &mut builder);
+        builder.build()
+    }
+
+    /// Converts to a WitValue storing every non-empty list of bytes as a single `prim-bytes` node, like the ones built
+    /// with `WitValue::builder().compact_bytes()`
+    pub fn into_compact_wit_value(self) -> WitValue {
+        let mut builder = WitValueBuilder::new().compact_bytes();
+        build_wit_value(self, &mut builder);
+        builder.build()
+    }
+
+    /// Replaces every NaN by the canonical quiet NaN and every -0.0 by 0.0, so values equal as numbers have the same
+    /// encoding, as needed for payloads that must be byte for byte identical when replayed
+    pub fn canonicalize_floats(&mut self) {
+        match self {
+            Value::F32(value) => *value = canonical_f32(*value),
+            Value::F64(value) => *value = canonical_f64(*value),
+            Value::List(values) | Value::Tuple(values) | Value::Record(values) => {
+                values.iter_mut().for_each(Value::canonicalize_floats)
+            }
+            Value::Variant {
+                case_value: Some(value),
+                ..
+            }
+            | Value::Option(Some(value))
+            | Value::Result(Ok(Some(value)))
+            | Value::Result(Err(Some(value))) => value.canonicalize_floats(),
+            _ => {}
+        }
+    }
+
+    /// The number held by any integer or float node, converted with `as`: the integers above `i64::MAX` wrap around,
+    /// and the floats are truncated towards zero, saturating at the bounds of `i64`, with NaN becoming 0. `None` if
+    /// the value is not a number.
+    pub fn as_i64_lossy(&self) -> Option<i64> {
+        match self {
+            Value::F32(value) => Some(*value as i64),
+            Value::F64(value) => Some(*value as i64),
+            _ => self.integer().map(|value| value as i64),
+        }
+    }
+
+    /// The number held by any integer or float node, rounded to the nearest `f64` for the 64 bit integers too large
+    /// to be represented exactly. `None` if the value is not a number.
+    pub fn as_f64_lossy(&self) -> Option<f64> {
+        match self {
+            Value::F32(value) => Some(*value as f64),
+            Value::F64(value) => Some(*value),
+            _ => self.integer().map(|value| value as f64),
+        }
+    }
+
+    /// The number held by any integer or float node, if it is an `i64` exactly: the floats must be integral, and
+    /// every number in the range of `i64`
+    pub fn as_i64(&self) -> Option<i64> {
+        self.exact_integer()
+            .and_then(|value| i64::try_from(value).ok())
+    }
+
+    /// The number held by any integer or float node, if it is a `u64` exactly: the floats must be integral, and
+    /// every number in the range of `u64`
+    pub fn as_u64(&self) -> Option<u64> {
+        self.exact_integer()
+            .and_then(|value| u64::try_from(value).ok())
+    }
+
+    /// The number held by any integer or float node, if it is an `f64` exactly: the 64 bit integers too large to be
+    /// represented exactly are rejected
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::F32(value) => Some(*value as f64),
+            Value::F64(value) => Some(*value),
+            _ => self.integer().and_then(|value| {
+                let float = value as f64;
+                (float as i128 == value).then_some(float)
+            }),
+        }
+    }
+
+    /// The value of the integer nodes, widened without loss
+    fn integer(&self) -> Option<i128> {
+        match self {
+            Value::U8(value) => Some(i128::from(*value)),
+            Value::U16(value) => Some(i128::from(*value)),
+            Value::U32(value) => Some(i128::from(*value)),
+            Value::U64(value) => Some(i128::from(*value)),
+            Value::S8(value) => Some(i128::from(*value)),
+            Value::S16(value) => Some(i128::from(*value)),
+            Value::S32(value) => Some(i128::from(*value)),
+            Value::S64(value) => Some(i128::from(*value)),
+            _ => None,
+        }
+    }
+
+    /// The value of the integer nodes, and of the float nodes holding an integral number
+    fn exact_integer(&self) -> Option<i128> {
+        let float = match self {
+            Value::F32(value) => *value as f64,
+            Value::F64(value) => *value,
+            _ => return self.integer(),
+        };
+        let in_range = float >= i128::MIN as f64 && float < i128::MAX as f64;
+        (in_range && (float as i128) as f64 == float).then_some(float as i128)
+    }
+}
+
+impl WitValue {
+    /// Canonicalizes the floats like [Value::canonicalize_floats], in place
+    pub fn canonicalize_floats(&mut self) {
+        for node in &mut self.nodes {
+            match node {
+                WitNode::PrimFloat32(value) => *value = canonical_f32(*value),
+                WitNode::PrimFloat64(value) => *value = canonical_f64(*value),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn canonical_f32(value: f32) -> f32 {
+    if value.is_nan() {
+        f32::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+fn canonical_f64(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+/// Converts a well formed WitValue, like the ones built from a [Value], panicking if it is not one. Use
+/// [Value::try_from_wit_value] for the WitValues received from untrusted sources.
 impl From<WitValue> for Value {
     fn from(value: WitValue) -> Self {
-        assert!(!value.nodes.is_empty());
-        build_tree(&value.nodes[0], &value.nodes)
+        CheckedNodeIndex::root(&value.nodes)
+            .and_then(|root| build_tree(root.node(&value.nodes)?, &value.nodes))
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+}
+
+/// Lists of bytes are stored as a single [WitNode::PrimBytes] node when compacting them. The empty lists are not, as
+/// their element type is not known.
+fn is_bytes(values: &[Value]) -> bool {
+    !values.is_empty() && values.iter().all(|value| matches!(value, Value::U8(_)))
+}
+
+/// Checks that the nodes form a tree rooted at the first node, without recursion
+fn validate_tree(nodes: &[WitNode]) -> Result<(), String> {
+    let mut visited = vec![false; nodes.len()];
+    let mut pending = vec![(CheckedNodeIndex::root(nodes)?.index(), 1)];
+    while let Some((index, depth)) = pending.pop() {
+        let checked = CheckedNodeIndex::new(index, nodes)?;
+        let node = checked.node(nodes)?;
+        if core::mem::replace(&mut visited[checked.position()], true)
+            && !matches!(node, WitNode::PrimString(_))
+        {
+            return Err(format!("WitValue refers to node {index} more than once"));
+        }
+        if depth > MAX_VALUE_DEPTH {
+            return Err(format!(
+                "WitValue is nested deeper than {MAX_VALUE_DEPTH} levels"
+            ));
+        }
+        pending.extend(node::children(node).iter().map(|child| (*child, depth + 1)));
     }
+    Ok(())
 }
 
-fn build_tree(node: &WitNode, nodes: &[WitNode]) -> Value {
-    match node {
+fn build_tree(node: &WitNode, nodes: &[WitNode]) -> Result<Value, String> {
+    let value = match node {
         WitNode::RecordValue(field_indices) => {
             let mut fields = Vec::new();
             for index in field_indices {
-                let value = build_tree(&nodes[*index as usize], nodes);
+                let value = build_tree(node::child(nodes, *index)?, nodes)?;
                 fields.push(value);
             }
             Value::Record(fields)
         }
         WitNode::VariantValue((case_idx, Some(inner_idx))) => {
-            let value = build_tree(&nodes[*inner_idx as usize], nodes);
+            let value = build_tree(node::child(nodes, *inner_idx)?, nodes)?;
             Value::Variant {
                 case_idx: *case_idx,
                 case_value: Some(Box::new(value)),
@@ -256,7 +602,7 @@ fn build_tree(node: &WitNode, nodes: &[WitNode]) -> Value {
         WitNode::TupleValue(indices) => {
             let mut values = Vec::new();
             for index in indices {
-                let value = build_tree(&nodes[*index as usize], nodes);
+                let value = build_tree(node::child(nodes, *index)?, nodes)?;
                 values.push(value);
             }
             Value::Tuple(values)
@@ -264,23 +610,23 @@ fn build_tree(node: &WitNode, nodes: &[WitNode]) -> Value {
         WitNode::ListValue(indices) => {
             let mut values = Vec::new();
             for index in indices {
-                let value = build_tree(&nodes[*index as usize], nodes);
+                let value = build_tree(node::child(nodes, *index)?, nodes)?;
                 values.push(value);
             }
             Value::List(values)
         }
         WitNode::OptionValue(Some(index)) => {
-            let value = build_tree(&nodes[*index as usize], nodes);
+            let value = build_tree(node::child(nodes, *index)?, nodes)?;
             Value::Option(Some(Box::new(value)))
         }
         WitNode::OptionValue(None) => Value::Option(None),
         WitNode::ResultValue(Ok(Some(index))) => {
-            let value = build_tree(&nodes[*index as usize], nodes);
+            let value = build_tree(node::child(nodes, *index)?, nodes)?;
             Value::Result(Ok(Some(Box::new(value))))
         }
         WitNode::ResultValue(Ok(None)) => Value::Result(Ok(None)),
         WitNode::ResultValue(Err(Some(index))) => {
-            let value = build_tree(&nodes[*index as usize], nodes);
+            let value = build_tree(node::child(nodes, *index)?, nodes)?;
             Value::Result(Err(Some(Box::new(value))))
         }
         WitNode::ResultValue(Err(None)) => Value::Result(Err(None)),
@@ -301,7 +647,9 @@ fn build_tree(node: &WitNode, nodes: &[WitNode]) -> Value {
             uri: uri.clone(),
             resource_id: *value,
         },
-    }
+        WitNode::PrimBytes(value) => Value::List(value.iter().copied().map(Value::U8).collect()),
+    };
+    Ok(value)
 }
 
 #[cfg(feature = "arbitrary")]
@@ -319,7 +667,7 @@ pub const WASM_RPC_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg(test)]
 mod tests {
-    use crate::{Value, WitValue};
+    use crate::{Value, WitNode, WitValue, MAX_VALUE_DEPTH};
     use proptest::prelude::*;
     use proptest_arbitrary_interop::arb_sized;
 
@@ -338,4 +686,135 @@ mod tests {
             prop_assert_eq!(value, round_trip_value);
         }
     }
+
+    #[test]
+    fn numbers_of_any_kind_are_read() {
+        assert_eq!(Value::U8(7).as_i64_lossy(), Some(7));
+        assert_eq!(Value::U64(u64::MAX).as_i64_lossy(), Some(-1));
+        assert_eq!(Value::F64(-2.9).as_i64_lossy(), Some(-2));
+        assert_eq!(Value::F32(f32::NAN).as_i64_lossy(), Some(0));
+        assert_eq!(Value::S16(-3).as_f64_lossy(), Some(-3.0));
+        assert_eq!(Value::U64(u64::MAX).as_f64_lossy(), Some(u64::MAX as f64));
+        assert_eq!(Value::String("1".to_string()).as_i64_lossy(), None);
+        assert_eq!(Value::Bool(true).as_f64_lossy(), None);
+
+        assert_eq!(Value::S32(-5).as_i64(), Some(-5));
+        assert_eq!(Value::F64(42.0).as_i64(), Some(42));
+        assert_eq!(Value::F64(42.5).as_i64(), None);
+        assert_eq!(Value::F64(f64::NAN).as_i64(), None);
+        assert_eq!(Value::F64(9.3e18).as_i64(), None);
+        assert_eq!(Value::F64(9.3e18).as_u64(), Some(9_300_000_000_000_000_000));
+        assert_eq!(Value::U64(u64::MAX).as_i64(), None);
+        assert_eq!(Value::U64(u64::MAX).as_u64(), Some(u64::MAX));
+        assert_eq!(Value::S8(-1).as_u64(), None);
+        assert_eq!(Value::F32(-0.0).as_u64(), Some(0));
+        assert_eq!(Value::F64(f64::INFINITY).as_u64(), None);
+
+        assert_eq!(Value::F32(0.5).as_f64(), Some(0.5));
+        assert_eq!(Value::S64(1 << 53).as_f64(), Some(9_007_199_254_740_992.0));
+        assert_eq!(Value::S64((1 << 53) + 1).as_f64(), None);
+        assert_eq!(Value::S64(i64::MAX).as_f64(), None);
+        assert_eq!(Value::Char('1').as_f64(), None);
+    }
+
+    #[test]
+    fn malformed_wit_values_are_rejected() {
+        let cases = [
+            vec![],
+            vec![WitNode::ListValue(vec![1, 2])],
+            vec![WitNode::ListValue(vec![-1])],
+            vec![WitNode::OptionValue(Some(0))],
+            vec![WitNode::TupleValue(vec![1, 1]), WitNode::PrimBool(true)],
+            (0..=MAX_VALUE_DEPTH as i32)
+                .map(|index| WitNode::OptionValue(Some(index + 1)))
+                .chain([WitNode::PrimU8(1)])
+                .collect(),
+        ];
+        for nodes in cases {
+            assert!(Value::try_from_wit_value(WitValue { nodes }).is_err());
+        }
+
+        let value = Value::Tuple(vec![Value::U8(1), Value::Option(None)]);
+        assert_eq!(Value::try_from_wit_value(value.clone().into()), Ok(value));
+    }
+
+    #[test]
+    fn interned_strings_are_stored_once() {
+        let status = |status: &str| {
+            Value::Record(vec![
+                Value::String(status.to_string()),
+                Value::String("shared".to_string()),
+            ])
+        };
+        let value = Value::List(vec![status("active"), status("inactive"), status("active")]);
+
+        let interned = value.clone().into_interned_wit_value();
+        let strings = interned
+            .nodes
+            .iter()
+            .filter(|node| matches!(node, WitNode::PrimString(_)))
+            .count();
+        assert_eq!(strings, 3);
+        assert!(interned.nodes.len() < WitValue::from(value.clone()).nodes.len());
+        assert_eq!(Value::try_from_wit_value(interned), Ok(value));
+    }
+
+    #[test]
+    fn canonicalized_floats_have_a_single_encoding() {
+        let float_bits = |value: &Value| -> Vec<u64> {
+            let Value::Record(fields) = value else {
+                panic!("Unexpected value");
+            };
+            fields
+                .iter()
+                .map(|field| match field {
+                    Value::F32(value) => value.to_bits() as u64,
+                    Value::F64(value) => value.to_bits(),
+                    Value::Option(Some(value)) => match **value {
+                        Value::F64(value) => value.to_bits(),
+                        _ => panic!("Unexpected value"),
+                    },
+                    _ => panic!("Unexpected value"),
+                })
+                .collect()
+        };
+
+        let mut first = Value::Record(vec![
+            Value::F32(-0.0),
+            Value::F64(f64::from_bits(f64::NAN.to_bits() | 0x1234)),
+            Value::Option(Some(Box::new(Value::F64(-f64::NAN)))),
+            Value::F64(-1.5),
+        ]);
+        let mut second = Value::Record(vec![
+            Value::F32(0.0),
+            Value::F64(f64::NAN),
+            Value::Option(Some(Box::new(Value::F64(f64::NAN)))),
+            Value::F64(-1.5),
+        ]);
+        assert_ne!(float_bits(&first), float_bits(&second));
+
+        first.canonicalize_floats();
+        second.canonicalize_floats();
+        assert_eq!(float_bits(&first), float_bits(&second));
+        assert_eq!(
+            float_bits(&first),
+            vec![
+                0,
+                f64::NAN.to_bits(),
+                f64::NAN.to_bits(),
+                (-1.5f64).to_bits()
+            ]
+        );
+
+        let mut first = WitValue::from(Value::Tuple(vec![
+            Value::F32(f32::from_bits(0xffc0_0001)),
+            Value::F64(-0.0),
+        ]));
+        first.canonicalize_floats();
+        assert!(matches!(
+            first.nodes[1..],
+            [WitNode::PrimFloat32(a), WitNode::PrimFloat64(b)]
+                if a.to_bits() == f32::NAN.to_bits() && b.to_bits() == 0
+        ));
+    }
 }
@@ -0,0 +1,382 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::metrics::RpcMetrics;
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Configures the limits of the invocations of each target worker
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    max_concurrency: Option<usize>,
+    rate: Option<Rate>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rate {
+    per_second: f64,
+    burst: u32,
+}
+
+impl Limits {
+    /// No limits, until they are configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The maximum number of concurrent invocations of a worker, at least one
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency.max(1));
+        self
+    }
+
+    /// The number of invocations of a worker started per second on average, allowing bursts of up to `burst`
+    /// invocations, at least one. A rate which is not a positive number, like 0, removes the rate limit.
+    pub fn with_rate(mut self, per_second: f64, burst: u32) -> Self {
+        self.rate = (per_second > 0.0 && !per_second.is_nan()).then_some(Rate {
+            per_second,
+            burst: burst.max(1),
+        });
+        self
+    }
+}
+
+struct TokenBucket {
+    /// Can be negative, when invocations are waiting for the tokens reserved by them
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// The tokens available at the given time
+    fn available(&self, now: Instant, rate: Rate) -> f64 {
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * rate.per_second;
+        (self.tokens + refill).min(rate.burst as f64)
+    }
+
+    /// Takes a token, and returns how long the invocation has to wait for it
+    fn take(&mut self, rate: Rate) -> Duration {
+        let now = Instant::now();
+        self.tokens = self.available(now, rate);
+        self.refilled_at = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::try_from_secs_f64(-self.tokens / rate.per_second).unwrap_or(Duration::MAX)
+        }
+    }
+}
+
+struct Target {
+    permits: Option<Arc<Semaphore>>,
+    bucket: TokenBucket,
+}
+
+impl Target {
+    /// Whether the target is in the same state as a new one, so it can be dropped: no invocation holds or waits for
+    /// its permits, and its bucket is full
+    fn is_idle(&self, now: Instant, rate: Option<Rate>) -> bool {
+        let permits_free = match &self.permits {
+            Some(permits) => Arc::strong_count(permits) == 1,
+            None => true,
+        };
+        let bucket_full = match rate {
+            Some(rate) => self.bucket.available(now, rate) >= rate.burst as f64,
+            None => true,
+        };
+        permits_free && bucket_full
+    }
+}
+
+/// The targets of the invocations, dropping the idle ones whenever their number doubles
+struct Targets {
+    by_uri: HashMap<String, Target>,
+    prune_at: usize,
+}
+
+const MIN_PRUNED_TARGETS: usize = 64;
+
+/// Limits the concurrency and the rate of the invocations of each target worker, so a misbehaving caller can not
+/// overload them. The invocations exceeding the limits wait until they can be started.
+pub struct LimitingTransport<T> {
+    inner: T,
+    config: Limits,
+    metrics: Option<Arc<dyn RpcMetrics>>,
+    targets: Mutex<Targets>,
+}
+
+impl<T: RpcTransport> LimitingTransport<T> {
+    pub fn new(inner: T, config: Limits) -> Self {
+        Self {
+            inner,
+            config,
+            metrics: None,
+            targets: Mutex::new(Targets {
+                by_uri: HashMap::new(),
+                prune_at: MIN_PRUNED_TARGETS,
+            }),
+        }
+    }
+
+    /// Reports the invocations delayed by the limits
+    pub fn with_metrics(mut self, metrics: Arc<dyn RpcMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Waits until the invocation is allowed by the rate limit and a concurrency permit of the target is available, and
+    /// returns the permit held until the invocation completes
+    async fn admit(&self, uri: &Uri) -> Result<Option<OwnedSemaphorePermit>, RpcError> {
+        let (permits, wait) = self.reserve(uri);
+        let mut throttled = !wait.is_zero();
+        if throttled {
+            tokio::time::sleep(wait).await;
+        }
+        let permit = match permits {
+            Some(permits) => {
+                throttled |= permits.available_permits() == 0;
+                Some(permits.acquire_owned().await.map_err(|_| {
+                    RpcError::ProtocolError("The concurrency limit is closed".to_string())
+                })?)
+            }
+            None => None,
+        };
+        if throttled {
+            if let Some(metrics) = &self.metrics {
+                metrics.throttled(uri);
+            }
+        }
+        Ok(permit)
+    }
+
+    /// Takes a token of the rate limit, and returns the concurrency permits of the target with the time to wait
+    fn reserve(&self, uri: &Uri) -> (Option<Arc<Semaphore>>, Duration) {
+        let mut targets = self.targets.lock().unwrap();
+        if targets.by_uri.len() >= targets.prune_at {
+            let now = Instant::now();
+            let rate = self.config.rate;
+            targets
+                .by_uri
+                .retain(|_, target| !target.is_idle(now, rate));
+            targets.prune_at = (targets.by_uri.len() * 2).max(MIN_PRUNED_TARGETS);
+        }
+        let target = targets
+            .by_uri
+            .entry(uri.value.clone())
+            .or_insert_with(|| Target {
+                permits: self
+                    .config
+                    .max_concurrency
+                    .map(|max| Arc::new(Semaphore::new(max))),
+                bucket: TokenBucket {
+                    tokens: self
+                        .config
+                        .rate
+                        .map(|rate| rate.burst as f64)
+                        .unwrap_or_default(),
+                    refilled_at: Instant::now(),
+                },
+            });
+        let wait = match self.config.rate {
+            Some(rate) => target.bucket.take(rate),
+            None => Duration::ZERO,
+        };
+        (target.permits.clone(), wait)
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for LimitingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let _permit = self.admit(uri).await?;
+        self.inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    /// The oneway invocations hold their permit until they are delivered
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        let _permit = self.admit(uri).await?;
+        self.inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limit::{LimitingTransport, Limits, MIN_PRUNED_TARGETS};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Takes the given time per invocation, and tracks the highest number of concurrent invocations
+    struct Worker {
+        delay: Duration,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl Worker {
+        fn new(delay: Duration) -> Self {
+            Self {
+                delay,
+                running: AtomicUsize::new(0),
+                max_running: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RpcTransport for Worker {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(Value::Tuple(vec![]).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrency_is_limited_per_worker() {
+        let transport = LimitingTransport::new(
+            Worker::new(Duration::from_millis(20)),
+            Limits::new().with_max_concurrency(2),
+        );
+        let worker1 = Uri::worker("component", "worker-1");
+        let worker2 = Uri::worker("component", "worker-2");
+        let invoke = |uri| transport.invoke_and_await(uri, "run", vec![]);
+        let results = tokio::join!(
+            invoke(&worker1),
+            invoke(&worker1),
+            invoke(&worker1),
+            invoke(&worker2),
+            invoke(&worker2)
+        );
+        assert!(results.0.is_ok() && results.2.is_ok() && results.4.is_ok());
+        // Two invocations of each worker at a time
+        assert_eq!(transport.inner.max_running.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn rate_is_limited_per_worker() {
+        let transport = LimitingTransport::new(
+            Worker::new(Duration::ZERO),
+            Limits::new().with_rate(50.0, 2),
+        );
+        let worker1 = Uri::worker("component", "worker-1");
+        let worker2 = Uri::worker("component", "worker-2");
+        let invoke = |uri| transport.invoke_and_await(uri, "run", vec![]);
+
+        let start = Instant::now();
+        for uri in [&worker1, &worker1, &worker2, &worker2] {
+            invoke(uri).await.unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(20));
+
+        // The bursts are used up, so the next invocations start every 20ms
+        let start = Instant::now();
+        for _ in 0..3 {
+            invoke(&worker1).await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(55));
+    }
+
+    #[tokio::test]
+    async fn invalid_rates_do_not_limit() {
+        for per_second in [0.0, -1.0, f64::NAN] {
+            let transport = LimitingTransport::new(
+                Worker::new(Duration::ZERO),
+                Limits::new().with_rate(per_second, 1),
+            );
+            let uri = Uri::worker("component", "worker-1");
+            for _ in 0..3 {
+                transport
+                    .invoke_and_await(&uri, "run", vec![])
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_targets_are_dropped() {
+        let transport = LimitingTransport::new(
+            Worker::new(Duration::ZERO),
+            Limits::new().with_max_concurrency(1),
+        );
+        for idx in 0..1000 {
+            let uri = Uri::worker("component", format!("worker-{idx}"));
+            transport
+                .invoke_and_await(&uri, "run", vec![])
+                .await
+                .unwrap();
+        }
+        assert!(transport.targets.lock().unwrap().by_uri.len() <= MIN_PRUNED_TARGETS);
+    }
+}
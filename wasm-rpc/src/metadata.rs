@@ -0,0 +1,282 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use golem_wasm_ast::analysis::{
+    AnalysedExport, AnalysedFunction, AnalysedType, AnalysisContext, AnalysisFailure,
+};
+use golem_wasm_ast::component::Component;
+use golem_wasm_ast::IgnoreAllButMetadata;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The interfaces and functions exported by a component, with their types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentExports {
+    exports: Vec<AnalysedExport>,
+}
+
+impl ComponentExports {
+    pub fn new(exports: Vec<AnalysedExport>) -> Self {
+        Self { exports }
+    }
+
+    /// Analyses the exports of a component from its WASM bytes, without caching them
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let component = Component::<IgnoreAllButMetadata>::from_bytes(bytes)
+            .map_err(|err| format!("Failed to parse the component: {err}"))?;
+        let exports = AnalysisContext::new(component)
+            .get_top_level_exports()
+            .map_err(|err| match err {
+                AnalysisFailure::Failed(msg) => format!("Failed to analyse the component: {msg}"),
+            })?;
+        Ok(Self::new(exports))
+    }
+
+    pub fn exports(&self) -> &[AnalysedExport] {
+        &self.exports
+    }
+
+    /// Finds a function by its name, like `rpc:counter/api.{add}` for the functions of exported interfaces, or
+    /// `{add}` for the ones exported directly by the component
+    pub fn function(&self, name: &str) -> Option<&AnalysedFunction> {
+        match name.split_once(".{") {
+            Some((interface, function)) => {
+                self.interface_function(Some(interface), function.strip_suffix('}')?)
+            }
+            None => {
+                self.interface_function(None, name.trim_start_matches('{').trim_end_matches('}'))
+            }
+        }
+    }
+
+    /// Finds a function of an exported interface, or exported directly by the component if the interface is `None`
+    pub fn interface_function(
+        &self,
+        interface: Option<&str>,
+        function: &str,
+    ) -> Option<&AnalysedFunction> {
+        self.exports
+            .iter()
+            .find_map(|export| match (export, interface) {
+                (AnalysedExport::Instance(instance), Some(interface))
+                    if instance.name == interface =>
+                {
+                    instance.funcs.iter().find(|f| f.name == function)
+                }
+                (AnalysedExport::Function(f), None) if f.name == function => Some(f),
+                _ => None,
+            })
+    }
+}
+
+/// Whether a type expected by a caller, like a stub, is the same as the one of a component. Resources are identified by
+/// the component analysing them, so any resources of the same mode match.
+pub fn types_match(expected: &AnalysedType, actual: &AnalysedType) -> bool {
+    match (expected, actual) {
+        (
+            AnalysedType::Resource {
+                resource_mode: expected_mode,
+                ..
+            },
+            AnalysedType::Resource {
+                resource_mode: actual_mode,
+                ..
+            },
+        ) => expected_mode == actual_mode,
+        (AnalysedType::List(expected), AnalysedType::List(actual))
+        | (AnalysedType::Option(expected), AnalysedType::Option(actual)) => {
+            types_match(expected, actual)
+        }
+        (AnalysedType::Tuple(expected), AnalysedType::Tuple(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().zip(actual).all(|(e, a)| types_match(e, a))
+        }
+        (AnalysedType::Record(expected), AnalysedType::Record(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual)
+                    .all(|((en, et), (an, at))| en == an && types_match(et, at))
+        }
+        (AnalysedType::Variant(expected), AnalysedType::Variant(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().zip(actual).all(|((en, et), (an, at))| {
+                    en == an && optional_types_match(et.as_ref(), at.as_ref())
+                })
+        }
+        (
+            AnalysedType::Result {
+                ok: expected_ok,
+                error: expected_error,
+            },
+            AnalysedType::Result {
+                ok: actual_ok,
+                error: actual_error,
+            },
+        ) => {
+            optional_types_match(expected_ok.as_deref(), actual_ok.as_deref())
+                && optional_types_match(expected_error.as_deref(), actual_error.as_deref())
+        }
+        (expected, actual) => expected == actual,
+    }
+}
+
+fn optional_types_match(expected: Option<&AnalysedType>, actual: Option<&AnalysedType>) -> bool {
+    match (expected, actual) {
+        (Some(expected), Some(actual)) => types_match(expected, actual),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Caches the exports of the components by the SHA-256 hash of their bytes, so each component is analysed once
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    entries: Mutex<HashMap<[u8; 32], Arc<ComponentExports>>>,
+}
+
+impl MetadataCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The exports of a component, analysed on the first request for the same bytes. Failures are not cached.
+    pub fn load(&self, bytes: &[u8]) -> Result<Arc<ComponentExports>, String> {
+        let hash: [u8; 32] = Sha256::digest(bytes).into();
+        if let Some(exports) = self.entries.lock().unwrap().get(&hash) {
+            return Ok(exports.clone());
+        }
+        // Analysing happens outside the lock, so concurrent loads of different components do not wait for each other
+        let exports = Arc::new(ComponentExports::from_bytes(bytes)?);
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .entry(hash)
+            .or_insert(exports)
+            .clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear()
+    }
+}
+
+/// The exports of a component, cached in a cache shared by the whole process
+pub fn load(bytes: &[u8]) -> Result<Arc<ComponentExports>, String> {
+    static CACHE: OnceLock<MetadataCache> = OnceLock::new();
+    CACHE.get_or_init(MetadataCache::new).load(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::{types_match, ComponentExports, MetadataCache};
+    use golem_wasm_ast::analysis::{
+        AnalysedExport, AnalysedFunction, AnalysedFunctionParameter, AnalysedInstance,
+        AnalysedResourceId, AnalysedResourceMode, AnalysedType,
+    };
+
+    fn function(name: &str) -> AnalysedFunction {
+        AnalysedFunction {
+            name: name.to_string(),
+            params: vec![AnalysedFunctionParameter {
+                name: "value".to_string(),
+                typ: AnalysedType::U64,
+            }],
+            results: vec![],
+        }
+    }
+
+    #[test]
+    fn functions_are_found_by_their_qualified_names() {
+        let exports = ComponentExports::new(vec![
+            AnalysedExport::Instance(AnalysedInstance {
+                name: "rpc:counter/api".to_string(),
+                funcs: vec![function("add"), function("[method]counter.add")],
+            }),
+            AnalysedExport::Function(function("run")),
+        ]);
+
+        let name = |function: &str| exports.function(function).map(|f| f.name.clone());
+        assert_eq!(name("rpc:counter/api.{add}"), Some("add".to_string()));
+        assert_eq!(
+            name("rpc:counter/api.{[method]counter.add}"),
+            Some("[method]counter.add".to_string())
+        );
+        assert_eq!(name("{run}"), Some("run".to_string()));
+        assert_eq!(name("run"), Some("run".to_string()));
+        assert_eq!(name("rpc:counter/api.{run}"), None);
+        assert_eq!(name("rpc:other/api.{add}"), None);
+        assert_eq!(name("add"), None);
+        assert_eq!(
+            exports
+                .interface_function(Some("rpc:counter/api"), "add")
+                .map(|f| f.name.as_str()),
+            Some("add")
+        );
+    }
+
+    #[test]
+    fn failures_are_not_cached() {
+        let cache = MetadataCache::new();
+        assert!(cache.load(b"not a component").is_err());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn resources_match_regardless_of_their_ids() {
+        let resource = |id, resource_mode| AnalysedType::Resource {
+            id: AnalysedResourceId { value: id },
+            resource_mode,
+        };
+        let expected = AnalysedType::Tuple(vec![
+            resource(0, AnalysedResourceMode::Owned),
+            AnalysedType::Option(Box::new(resource(0, AnalysedResourceMode::Borrowed))),
+        ]);
+        let actual = AnalysedType::Tuple(vec![
+            resource(3, AnalysedResourceMode::Owned),
+            AnalysedType::Option(Box::new(resource(7, AnalysedResourceMode::Borrowed))),
+        ]);
+        assert!(types_match(&expected, &actual));
+
+        let borrowed = AnalysedType::Tuple(vec![
+            resource(3, AnalysedResourceMode::Borrowed),
+            AnalysedType::Option(Box::new(resource(7, AnalysedResourceMode::Borrowed))),
+        ]);
+        assert!(!types_match(&expected, &borrowed));
+        assert!(!types_match(
+            &AnalysedType::Record(vec![("x".to_string(), AnalysedType::S32)]),
+            &AnalysedType::Record(vec![("y".to_string(), AnalysedType::S32)])
+        ));
+        assert!(!types_match(
+            &AnalysedType::Result {
+                ok: Some(Box::new(AnalysedType::U8)),
+                error: None
+            },
+            &AnalysedType::Result {
+                ok: Some(Box::new(AnalysedType::U8)),
+                error: Some(Box::new(AnalysedType::Str))
+            }
+        ));
+    }
+}
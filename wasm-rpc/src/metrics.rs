@@ -0,0 +1,41 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::circuit::CircuitState;
+use crate::Uri;
+
+/// Receives the metrics of the layers of a [crate::WasmRpcHost], to be exported to the monitoring system of the
+/// embedder. Every method does nothing by default.
+pub trait RpcMetrics: Send + Sync {
+    /// The circuit breaker of the target worker changed its state
+    fn circuit_state_changed(&self, _uri: &Uri, _state: CircuitState) {}
+
+    /// An invocation was rejected without calling the worker, because its circuit breaker is open
+    fn circuit_rejected(&self, _uri: &Uri) {}
+
+    /// A second attempt of a slow invocation of an idempotent function was sent
+    fn hedged(&self, _uri: &Uri, _function_name: &str) {}
+
+    /// An invocation had to wait because of the concurrency or rate limit of the target worker
+    fn throttled(&self, _uri: &Uri) {}
+
+    /// The number of oneway invocations waiting in the queue changed
+    fn oneway_queue_depth(&self, _depth: usize) {}
+
+    /// A oneway invocation was dropped from the full queue without being delivered
+    fn oneway_dropped(&self, _uri: &Uri, _function_name: &str) {}
+
+    /// The result of an invocation of a cached function was looked up in the cache
+    fn cache_lookup(&self, _uri: &Uri, _function_name: &str, _hit: bool) {}
+}
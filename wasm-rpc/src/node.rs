@@ -0,0 +1,133 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{NodeIndex, WitNode};
+use alloc::format;
+use alloc::string::String;
+
+/// A [NodeIndex] checked to refer to one of the nodes of a WitValue. The nodes refer to their children by WIT `s32`
+/// indices, which can be negative or out of range in values received from untrusted sources, so they are only used to
+/// look up nodes after being checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CheckedNodeIndex(usize);
+
+impl CheckedNodeIndex {
+    /// Checks that the index refers to one of the nodes
+    pub fn new(index: NodeIndex, nodes: &[WitNode]) -> Result<Self, String> {
+        match usize::try_from(index) {
+            Ok(position) if position < nodes.len() => Ok(CheckedNodeIndex(position)),
+            _ => Err(format!("WitValue refers to a node out of range: {index}")),
+        }
+    }
+
+    /// The root of the tree, the first node
+    pub fn root(nodes: &[WitNode]) -> Result<Self, String> {
+        if nodes.is_empty() {
+            Err("WitValue has no nodes".into())
+        } else {
+            Ok(CheckedNodeIndex(0))
+        }
+    }
+
+    /// The index of a node added at the given position, failing if a [NodeIndex] cannot refer to it
+    pub(crate) fn next(position: usize) -> Result<Self, String> {
+        if NodeIndex::try_from(position).is_ok() {
+            Ok(CheckedNodeIndex(position))
+        } else {
+            Err(format!(
+                "WitValue has more nodes than a node index can refer to: {position}"
+            ))
+        }
+    }
+
+    /// The position of the node in the nodes
+    pub fn position(self) -> usize {
+        self.0
+    }
+
+    /// The index referring to the node from its parent
+    pub fn index(self) -> NodeIndex {
+        NodeIndex::try_from(self.0).expect("checked node indices fit a NodeIndex")
+    }
+
+    /// The node it refers to, failing if the nodes are not the ones it was checked against and have fewer nodes
+    pub fn node(self, nodes: &[WitNode]) -> Result<&WitNode, String> {
+        nodes
+            .get(self.0)
+            .ok_or_else(|| format!("WitValue refers to a node out of range: {}", self.0))
+    }
+}
+
+/// Resolves one of the child indices of a node to the child node
+pub(crate) fn child(nodes: &[WitNode], index: NodeIndex) -> Result<&WitNode, String> {
+    CheckedNodeIndex::new(index, nodes)?.node(nodes)
+}
+
+/// The indices of the children of a node, in order
+pub(crate) fn children(node: &WitNode) -> &[NodeIndex] {
+    match node {
+        WitNode::RecordValue(indices)
+        | WitNode::TupleValue(indices)
+        | WitNode::ListValue(indices) => indices,
+        WitNode::VariantValue((_, Some(index)))
+        | WitNode::OptionValue(Some(index))
+        | WitNode::ResultValue(Ok(Some(index)))
+        | WitNode::ResultValue(Err(Some(index))) => core::slice::from_ref(index),
+        _ => &[],
+    }
+}
+
+/// The indices of the children of a node, to renumber them
+pub(crate) fn children_mut(node: &mut WitNode) -> &mut [NodeIndex] {
+    match node {
+        WitNode::RecordValue(indices)
+        | WitNode::TupleValue(indices)
+        | WitNode::ListValue(indices) => indices,
+        WitNode::VariantValue((_, Some(index)))
+        | WitNode::OptionValue(Some(index))
+        | WitNode::ResultValue(Ok(Some(index)))
+        | WitNode::ResultValue(Err(Some(index))) => core::slice::from_mut(index),
+        _ => &mut [],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::{child, CheckedNodeIndex};
+    use crate::{NodeIndex, WitNode};
+
+    #[test]
+    fn indices_are_checked_against_the_nodes() {
+        let nodes = vec![WitNode::ListValue(vec![1, -1, 2]), WitNode::PrimU8(1)];
+        assert_eq!(
+            CheckedNodeIndex::new(1, &nodes).map(CheckedNodeIndex::position),
+            Ok(1)
+        );
+        assert!(CheckedNodeIndex::new(-1, &nodes).is_err());
+        assert!(CheckedNodeIndex::new(2, &nodes).is_err());
+        assert!(CheckedNodeIndex::new(NodeIndex::MIN, &nodes).is_err());
+        assert!(CheckedNodeIndex::root(&[]).is_err());
+        assert!(matches!(child(&nodes, 1), Ok(WitNode::PrimU8(1))));
+        assert!(child(&nodes, -1).is_err());
+    }
+
+    #[test]
+    fn positions_beyond_the_node_indices_are_rejected() {
+        assert_eq!(
+            CheckedNodeIndex::next(7).map(CheckedNodeIndex::index),
+            Ok(7)
+        );
+        assert!(CheckedNodeIndex::next(NodeIndex::MAX as usize + 1).is_err());
+    }
+}
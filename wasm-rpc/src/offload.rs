@@ -0,0 +1,359 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, Value, WitValue};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The first field of the record replacing an offloaded value, telling it apart from the other values
+pub const BLOB_REF_MARKER: &str = "golem:rpc/blob-ref";
+
+/// Stores the encoded values too large to be sent through the transport
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores a payload by its SHA-256 hash, and returns its location in the store
+    async fn put(&self, hash: &str, payload: Vec<u8>) -> Result<String, RpcError>;
+
+    async fn get(&self, location: &str) -> Result<Vec<u8>, RpcError>;
+}
+
+/// A [BlobStore] keeping the payloads in memory, located by their hashes. Identical payloads are stored once.
+#[derive(Default)]
+pub struct InMemoryBlobStore {
+    blobs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl BlobStore for InMemoryBlobStore {
+    async fn put(&self, hash: &str, payload: Vec<u8>) -> Result<String, RpcError> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .entry(hash.to_string())
+            .or_insert(payload);
+        Ok(hash.to_string())
+    }
+
+    async fn get(&self, location: &str) -> Result<Vec<u8>, RpcError> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .get(location)
+            .cloned()
+            .ok_or_else(|| RpcError::ProtocolError(format!("Blob not found: {location}")))
+    }
+}
+
+/// A reference to an offloaded value, sent through the transport in its place as a
+/// `record { marker: string, hash: string, location: string, size: u64 }`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobRef {
+    /// The SHA-256 hash of the encoded value, in hexadecimal
+    pub hash: String,
+    pub location: String,
+    /// The size of the encoded value in bytes
+    pub size: u64,
+}
+
+impl BlobRef {
+    /// Recognizes the references among the values
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Record(fields) => match fields.as_slice() {
+                [Value::String(marker), Value::String(hash), Value::String(location), Value::U64(size)]
+                    if marker == BLOB_REF_MARKER =>
+                {
+                    Some(Self {
+                        hash: hash.clone(),
+                        location: location.clone(),
+                        size: *size,
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<BlobRef> for Value {
+    fn from(value: BlobRef) -> Self {
+        Value::Record(vec![
+            Value::String(BLOB_REF_MARKER.to_string()),
+            Value::String(value.hash),
+            Value::String(value.location),
+            Value::U64(value.size),
+        ])
+    }
+}
+
+/// Replaces the values larger than a threshold with references to a [BlobStore], and resolves the references back to
+/// the values. Both sides of the transport use the same store: the sending side offloads the parameters and resolves
+/// the results, the receiving side resolves the parameters and offloads the results.
+#[derive(Clone)]
+pub struct BlobOffloading {
+    store: Arc<dyn BlobStore>,
+    threshold: usize,
+}
+
+impl BlobOffloading {
+    /// Offloads the values whose bincode encoding is larger than `threshold` bytes
+    pub fn new(store: impl BlobStore + 'static, threshold: usize) -> Self {
+        Self {
+            store: Arc::new(store),
+            threshold,
+        }
+    }
+
+    pub async fn offload(&self, value: WitValue) -> Result<WitValue, RpcError> {
+        let payload = bincode::encode_to_vec(&value, bincode::config::standard())
+            .map_err(|err| RpcError::ProtocolError(format!("Failed to encode the value: {err}")))?;
+        if payload.len() <= self.threshold {
+            return Ok(value);
+        }
+        let hash = format!("{:x}", Sha256::digest(&payload));
+        let size = payload.len() as u64;
+        let location = self.store.put(&hash, payload).await?;
+        Ok(Value::from(BlobRef {
+            hash,
+            location,
+            size,
+        })
+        .into())
+    }
+
+    /// Resolves a reference to the offloaded value, checking its hash. Other values are returned as they are.
+    pub async fn resolve(&self, value: WitValue) -> Result<WitValue, RpcError> {
+        let value = Value::try_from_wit_value(value).map_err(RpcError::ProtocolError)?;
+        let Some(blob_ref) = BlobRef::from_value(&value) else {
+            return Ok(value.into());
+        };
+        let payload = self.store.get(&blob_ref.location).await?;
+        if format!("{:x}", Sha256::digest(&payload)) != blob_ref.hash {
+            return Err(RpcError::ProtocolError(format!(
+                "The blob at {} does not match its hash {}",
+                blob_ref.location, blob_ref.hash
+            )));
+        }
+        bincode::decode_from_slice(&payload, bincode::config::standard())
+            .map(|(value, _)| value)
+            .map_err(|err| RpcError::ProtocolError(format!("Failed to decode the blob: {err}")))
+    }
+
+    pub async fn offload_all(&self, values: Vec<WitValue>) -> Result<Vec<WitValue>, RpcError> {
+        let mut offloaded = Vec::with_capacity(values.len());
+        for value in values {
+            offloaded.push(self.offload(value).await?);
+        }
+        Ok(offloaded)
+    }
+
+    pub async fn resolve_all(&self, values: Vec<WitValue>) -> Result<Vec<WitValue>, RpcError> {
+        let mut resolved = Vec::with_capacity(values.len());
+        for value in values {
+            resolved.push(self.resolve(value).await?);
+        }
+        Ok(resolved)
+    }
+}
+
+/// Sends the huge parameters through a [BlobStore] instead of the transport, and resolves the offloaded results
+pub struct OffloadingTransport<T> {
+    inner: T,
+    offloading: BlobOffloading,
+}
+
+impl<T: RpcTransport> OffloadingTransport<T> {
+    pub fn new(inner: T, offloading: BlobOffloading) -> Self {
+        Self { inner, offloading }
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for OffloadingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let function_params = self.offloading.offload_all(function_params).await?;
+        let result = self
+            .inner
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await?;
+        self.offloading.resolve(result).await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        let function_params = self.offloading.offload_all(function_params).await?;
+        self.inner
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offload::{
+        BlobOffloading, BlobRef, BlobStore, InMemoryBlobStore, OffloadingTransport,
+    };
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    /// Shares a store between the sides of the transport
+    #[derive(Clone, Default)]
+    struct SharedStore(Arc<InMemoryBlobStore>);
+
+    #[async_trait]
+    impl BlobStore for SharedStore {
+        async fn put(&self, hash: &str, payload: Vec<u8>) -> Result<String, RpcError> {
+            self.0.put(hash, payload).await
+        }
+
+        async fn get(&self, location: &str) -> Result<Vec<u8>, RpcError> {
+            self.0.get(location).await
+        }
+    }
+
+    /// Resolves the parameters like the receiving side, and returns the string parameter repeated
+    struct Receiver {
+        offloading: BlobOffloading,
+        received: Mutex<Vec<Value>>,
+    }
+
+    #[async_trait]
+    impl RpcTransport for Receiver {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.received
+                .lock()
+                .unwrap()
+                .extend(function_params.iter().cloned().map(Value::from));
+            let params = self.offloading.resolve_all(function_params).await?;
+            let Value::String(text) = Value::from(params[0].clone()) else {
+                panic!("Unexpected parameter");
+            };
+            self.offloading
+                .offload(Value::String(text.repeat(2)).into())
+                .await
+        }
+    }
+
+    fn text(length: usize) -> WitValue {
+        Value::String("x".repeat(length)).into()
+    }
+
+    #[tokio::test]
+    async fn large_values_are_offloaded_to_the_blob_store() {
+        let store = SharedStore::default();
+        let transport = OffloadingTransport::new(
+            Receiver {
+                offloading: BlobOffloading::new(store.clone(), 1024),
+                received: Mutex::new(Vec::new()),
+            },
+            BlobOffloading::new(store.clone(), 1024),
+        );
+        let uri = Uri::worker("component", "worker-1");
+
+        let result = transport
+            .invoke_and_await(&uri, "rpc:text/api.{double}", vec![text(100)])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::from(text(200)));
+
+        let result = transport
+            .invoke_and_await(&uri, "rpc:text/api.{double}", vec![text(100_000)])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::from(text(200_000)));
+
+        let received = transport.inner.received.lock().unwrap().clone();
+        assert!(BlobRef::from_value(&received[0]).is_none());
+        let blob_ref = BlobRef::from_value(&received[1]).unwrap();
+        assert!(blob_ref.size > 100_000);
+        assert_eq!(blob_ref.hash.len(), 64);
+        // The parameter and the result
+        assert_eq!(store.0.blobs.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn blobs_not_matching_their_hashes_are_rejected() {
+        let store = SharedStore::default();
+        let offloading = BlobOffloading::new(store.clone(), 0);
+        let offloaded = offloading.offload(text(10)).await.unwrap();
+        let blob_ref = BlobRef::from_value(&Value::from(offloaded.clone())).unwrap();
+        store
+            .0
+            .blobs
+            .lock()
+            .unwrap()
+            .insert(blob_ref.location, vec![1, 2, 3]);
+
+        assert!(matches!(
+            offloading.resolve(offloaded).await,
+            Err(RpcError::ProtocolError(_))
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::metrics::RpcMetrics;
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, WitValue};
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// What happens to a oneway invocation when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The caller waits until there is space in the queue
+    Block,
+    /// The oldest pending invocation is dropped to make space
+    DropOldest,
+    /// The invocation fails with a protocol error
+    Error,
+}
+
+/// Configures the queue of the oneway invocations
+#[derive(Debug, Clone)]
+pub struct OnewayQueueConfig {
+    capacity: usize,
+    overflow: OverflowPolicy,
+    workers: usize,
+}
+
+impl OnewayQueueConfig {
+    /// A queue of the given capacity, at least one, blocking the callers when full and delivering the invocations one
+    /// by one, in order
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            overflow: OverflowPolicy::Block,
+            workers: 1,
+        }
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// The number of invocations delivered concurrently, at least one. With more than one the invocations may reach
+    /// the workers out of order.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+}
+
+struct PendingInvocation {
+    uri: Uri,
+    function_name: String,
+    function_params: Vec<WitValue>,
+    metadata: Vec<(String, String)>,
+}
+
+struct QueueState {
+    config: OnewayQueueConfig,
+    metrics: Option<Arc<dyn RpcMetrics>>,
+    pending: Mutex<VecDeque<PendingInvocation>>,
+    closed: AtomicBool,
+    /// Notified when an invocation is pushed, or the queue is closed
+    available: Notify,
+    /// Notified when an invocation is taken from the queue
+    space: Notify,
+}
+
+impl QueueState {
+    fn report_depth(&self, depth: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.oneway_queue_depth(depth);
+        }
+    }
+
+    fn pop(&self) -> Option<PendingInvocation> {
+        let (invocation, depth) = {
+            let mut pending = self.pending.lock().unwrap();
+            (pending.pop_front()?, pending.len())
+        };
+        self.report_depth(depth);
+        self.space.notify_one();
+        Some(invocation)
+    }
+}
+
+/// A bounded queue of fire-and-forget invocations, delivered in the background through a transport. The failures of
+/// the delivered invocations are ignored, as nobody waits for their results.
+///
+/// Dropping the queue stops accepting new invocations, but the pending ones are still delivered.
+pub struct OnewayQueue {
+    state: Arc<QueueState>,
+}
+
+impl OnewayQueue {
+    /// Starts the delivery of the queued invocations on the current tokio runtime
+    pub fn start(
+        transport: Arc<dyn RpcTransport>,
+        config: OnewayQueueConfig,
+        metrics: Option<Arc<dyn RpcMetrics>>,
+    ) -> Self {
+        let state = Arc::new(QueueState {
+            config,
+            metrics,
+            pending: Mutex::new(VecDeque::new()),
+            closed: AtomicBool::new(false),
+            available: Notify::new(),
+            space: Notify::new(),
+        });
+        for _ in 0..state.config.workers {
+            tokio::spawn(deliver(state.clone(), transport.clone()));
+        }
+        Self { state }
+    }
+
+    /// The number of invocations waiting to be delivered
+    pub fn depth(&self) -> usize {
+        self.state.pending.lock().unwrap().len()
+    }
+
+    /// Queues an invocation, applying the overflow policy if the queue is full
+    pub async fn push(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.push_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    /// Queues an invocation with its metadata, delivered along with it, like its idempotency key
+    pub async fn push_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        let mut invocation = Some(PendingInvocation {
+            uri: uri.clone(),
+            function_name: function_name.to_string(),
+            function_params,
+            metadata: metadata.to_vec(),
+        });
+        loop {
+            // Registered before checking the queue, so the space made in between is not missed
+            let mut space = pin!(self.state.space.notified());
+            space.as_mut().enable();
+            let (depth, dropped) = {
+                let mut pending = self.state.pending.lock().unwrap();
+                if pending.len() < self.state.config.capacity {
+                    pending.extend(invocation.take());
+                    (pending.len(), None)
+                } else {
+                    match self.state.config.overflow {
+                        OverflowPolicy::Block => (pending.len(), None),
+                        OverflowPolicy::DropOldest => {
+                            let dropped = pending.pop_front();
+                            pending.extend(invocation.take());
+                            (pending.len(), dropped)
+                        }
+                        OverflowPolicy::Error => {
+                            return Err(RpcError::ProtocolError(format!(
+                                "The oneway queue is full with {} pending invocations",
+                                pending.len()
+                            )));
+                        }
+                    }
+                }
+            };
+            if let (Some(dropped), Some(metrics)) = (dropped, &self.state.metrics) {
+                metrics.oneway_dropped(&dropped.uri, &dropped.function_name);
+            }
+            if invocation.is_none() {
+                self.state.report_depth(depth);
+                self.state.available.notify_one();
+                return Ok(());
+            }
+            space.await;
+        }
+    }
+}
+
+impl Drop for OnewayQueue {
+    fn drop(&mut self) {
+        self.state.closed.store(true, Ordering::SeqCst);
+        self.state.available.notify_waiters();
+    }
+}
+
+async fn deliver(state: Arc<QueueState>, transport: Arc<dyn RpcTransport>) {
+    loop {
+        // Registered before checking the queue, so the invocations pushed in between are not missed
+        let mut available = pin!(state.available.notified());
+        available.as_mut().enable();
+        match state.pop() {
+            Some(invocation) => {
+                let _ = transport
+                    .invoke_with_metadata(
+                        &invocation.uri,
+                        &invocation.function_name,
+                        invocation.function_params,
+                        &invocation.metadata,
+                    )
+                    .await;
+            }
+            None if state.closed.load(Ordering::SeqCst) => break,
+            None => available.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::RpcMetrics;
+    use crate::oneway::{OnewayQueue, OnewayQueueConfig, OverflowPolicy};
+    use crate::transport::RpcTransport;
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::Semaphore;
+
+    /// Delivers an invocation for each permit added to the gate, and records the delivered function names
+    struct Gated {
+        gate: Semaphore,
+        started: Mutex<Vec<String>>,
+    }
+
+    impl Default for Gated {
+        fn default() -> Self {
+            Self {
+                gate: Semaphore::new(0),
+                started: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RpcTransport for Gated {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.started.lock().unwrap().push(function_name.to_string());
+            self.gate.acquire().await.unwrap().forget();
+            Ok(Value::Tuple(vec![]).into())
+        }
+    }
+
+    #[derive(Default)]
+    struct Metrics {
+        dropped: Mutex<Vec<String>>,
+    }
+
+    impl RpcMetrics for Metrics {
+        fn oneway_dropped(&self, _uri: &Uri, function_name: &str) {
+            self.dropped.lock().unwrap().push(function_name.to_string());
+        }
+    }
+
+    async fn started(transport: &Gated, count: usize) -> Vec<String> {
+        while transport.started.lock().unwrap().len() < count {
+            tokio::task::yield_now().await;
+        }
+        transport.started.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn full_queues_apply_the_overflow_policy() {
+        let uri = Uri::worker("component", "worker-1");
+        for overflow in [OverflowPolicy::DropOldest, OverflowPolicy::Error] {
+            let transport = Arc::new(Gated::default());
+            let metrics = Arc::new(Metrics::default());
+            let queue = OnewayQueue::start(
+                transport.clone(),
+                OnewayQueueConfig::new(1).with_overflow(overflow),
+                Some(metrics.clone()),
+            );
+
+            queue.push(&uri, "a", vec![]).await.unwrap();
+            assert_eq!(started(&transport, 1).await, vec!["a"]);
+            queue.push(&uri, "b", vec![]).await.unwrap();
+            assert_eq!(queue.depth(), 1);
+            let result = queue.push(&uri, "c", vec![]).await;
+
+            transport.gate.add_permits(2);
+            let delivered = started(&transport, 2).await;
+            match overflow {
+                OverflowPolicy::DropOldest => {
+                    assert!(result.is_ok());
+                    assert_eq!(delivered, vec!["a", "c"]);
+                    assert_eq!(*metrics.dropped.lock().unwrap(), vec!["b"]);
+                }
+                _ => {
+                    assert!(matches!(result, Err(RpcError::ProtocolError(_))));
+                    assert_eq!(delivered, vec!["a", "b"]);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn blocked_callers_wait_for_space() {
+        let uri = Uri::worker("component", "worker-1");
+        let transport = Arc::new(Gated::default());
+        let queue = OnewayQueue::start(transport.clone(), OnewayQueueConfig::new(1), None);
+
+        queue.push(&uri, "a", vec![]).await.unwrap();
+        started(&transport, 1).await;
+        queue.push(&uri, "b", vec![]).await.unwrap();
+        let (pushed, _) = tokio::join!(queue.push(&uri, "c", vec![]), async {
+            transport.gate.add_permits(3);
+        });
+        pushed.unwrap();
+        assert_eq!(started(&transport, 3).await, vec!["a", "b", "c"]);
+    }
+}
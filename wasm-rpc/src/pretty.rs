@@ -0,0 +1,326 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::infer::infer_type;
+use crate::Value;
+use golem_wasm_ast::analysis::{AnalysedResourceMode, AnalysedType};
+use std::fmt::{Debug, Write};
+
+const INDENT: &str = "    ";
+
+/// Prints a value of the given type in a readable, multi-line form of the WAVE text format, with one record field,
+/// list or tuple element per line, and the names of the record fields, enum and variant cases and flags taken from
+/// the type. The parts of the value not matching the type are printed with the names of their inferred types, like
+/// `field0`. The result is stable, so it can be compared in snapshot tests.
+pub fn pretty_print(value: &Value, typ: &AnalysedType) -> String {
+    let mut out = String::new();
+    print(&mut out, value, typ, 0);
+    out
+}
+
+/// Renders a type like it is written in WIT, with the records, variants, enums and flags written out structurally
+pub fn print_type(typ: &AnalysedType) -> String {
+    let list = |types: &mut dyn Iterator<Item = String>| types.collect::<Vec<_>>().join(", ");
+    let optional = |typ: &Option<Box<AnalysedType>>| {
+        typ.as_ref()
+            .map(|typ| print_type(typ))
+            .unwrap_or_else(|| "_".to_string())
+    };
+    match typ {
+        AnalysedType::Bool => "bool".to_string(),
+        AnalysedType::S8 => "s8".to_string(),
+        AnalysedType::U8 => "u8".to_string(),
+        AnalysedType::S16 => "s16".to_string(),
+        AnalysedType::U16 => "u16".to_string(),
+        AnalysedType::S32 => "s32".to_string(),
+        AnalysedType::U32 => "u32".to_string(),
+        AnalysedType::S64 => "s64".to_string(),
+        AnalysedType::U64 => "u64".to_string(),
+        AnalysedType::F32 => "float32".to_string(),
+        AnalysedType::F64 => "float64".to_string(),
+        AnalysedType::Chr => "char".to_string(),
+        AnalysedType::Str => "string".to_string(),
+        AnalysedType::List(typ) => format!("list<{}>", print_type(typ)),
+        AnalysedType::Option(typ) => format!("option<{}>", print_type(typ)),
+        AnalysedType::Tuple(types) => format!("tuple<{}>", list(&mut types.iter().map(print_type))),
+        AnalysedType::Result { ok, error } => {
+            format!("result<{}, {}>", optional(ok), optional(error))
+        }
+        AnalysedType::Record(fields) => format!(
+            "record {{ {} }}",
+            list(
+                &mut fields
+                    .iter()
+                    .map(|(name, typ)| format!("{name}: {}", print_type(typ)))
+            )
+        ),
+        AnalysedType::Variant(cases) => format!(
+            "variant {{ {} }}",
+            list(&mut cases.iter().map(|(name, typ)| match typ {
+                Some(typ) => format!("{name}({})", print_type(typ)),
+                None => name.clone(),
+            }))
+        ),
+        AnalysedType::Enum(cases) => format!("enum {{ {} }}", cases.join(", ")),
+        AnalysedType::Flags(flags) => format!("flags {{ {} }}", flags.join(", ")),
+        AnalysedType::Resource { id, resource_mode } => match resource_mode {
+            AnalysedResourceMode::Owned => format!("own<resource-{}>", id.value),
+            AnalysedResourceMode::Borrowed => format!("borrow<resource-{}>", id.value),
+        },
+    }
+}
+
+fn print(out: &mut String, value: &Value, typ: &AnalysedType, depth: usize) {
+    match (value, typ) {
+        (Value::Bool(value), AnalysedType::Bool) => write!(out, "{value}"),
+        (Value::U8(value), AnalysedType::U8) => write!(out, "{value}"),
+        (Value::U16(value), AnalysedType::U16) => write!(out, "{value}"),
+        (Value::U32(value), AnalysedType::U32) => write!(out, "{value}"),
+        (Value::U64(value), AnalysedType::U64) => write!(out, "{value}"),
+        (Value::S8(value), AnalysedType::S8) => write!(out, "{value}"),
+        (Value::S16(value), AnalysedType::S16) => write!(out, "{value}"),
+        (Value::S32(value), AnalysedType::S32) => write!(out, "{value}"),
+        (Value::S64(value), AnalysedType::S64) => write!(out, "{value}"),
+        (Value::F32(value), AnalysedType::F32) => write!(out, "{}", float(*value)),
+        (Value::F64(value), AnalysedType::F64) => write!(out, "{}", float(*value)),
+        (Value::Char(value), AnalysedType::Chr) => write!(out, "{value:?}"),
+        (Value::String(value), AnalysedType::Str) => write!(out, "{value:?}"),
+        (Value::List(items), AnalysedType::List(typ)) => {
+            print_items(
+                out,
+                "[",
+                "]",
+                items.iter().map(|item| (None, item, &**typ)),
+                depth,
+            );
+            Ok(())
+        }
+        (Value::Tuple(items), AnalysedType::Tuple(types)) if items.len() == types.len() => {
+            let items = items.iter().zip(types).map(|(item, typ)| (None, item, typ));
+            print_items(out, "(", ")", items, depth);
+            Ok(())
+        }
+        (Value::Record(fields), AnalysedType::Record(types)) if fields.len() == types.len() => {
+            let fields = fields
+                .iter()
+                .zip(types)
+                .map(|(field, (name, typ))| (Some(name.as_str()), field, typ));
+            print_items(out, "{", "}", fields, depth);
+            Ok(())
+        }
+        (Value::Enum(case_idx), AnalysedType::Enum(cases))
+            if (*case_idx as usize) < cases.len() =>
+        {
+            write!(out, "{}", cases[*case_idx as usize])
+        }
+        (
+            Value::Variant {
+                case_idx,
+                case_value,
+            },
+            AnalysedType::Variant(cases),
+        ) if matches!(
+            (case_value, cases.get(*case_idx as usize)),
+            (None, Some((_, None))) | (Some(_), Some((_, Some(_))))
+        ) =>
+        {
+            let (name, typ) = &cases[*case_idx as usize];
+            print_case(out, name, case_value.as_deref().zip(typ.as_ref()), depth);
+            Ok(())
+        }
+        (Value::Flags(flags), AnalysedType::Flags(names)) if flags.len() == names.len() => {
+            let set = names
+                .iter()
+                .zip(flags)
+                .filter(|(_, set)| **set)
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            write!(out, "{{{}}}", set.join(", "))
+        }
+        (Value::Option(None), AnalysedType::Option(_)) => write!(out, "none"),
+        (Value::Option(Some(value)), AnalysedType::Option(typ)) => {
+            print_case(out, "some", Some((value, typ)), depth);
+            Ok(())
+        }
+        (Value::Result(result), AnalysedType::Result { ok, error })
+            if matches!(
+                (result, ok, error),
+                (Ok(None), None, _)
+                    | (Ok(Some(_)), Some(_), _)
+                    | (Err(None), _, None)
+                    | (Err(Some(_)), _, Some(_))
+            ) =>
+        {
+            let (name, value, typ) = match result {
+                Ok(value) => ("ok", value, ok),
+                Err(value) => ("err", value, error),
+            };
+            print_case(out, name, value.as_deref().zip(typ.as_deref()), depth);
+            Ok(())
+        }
+        (Value::Handle { uri, resource_id }, AnalysedType::Resource { .. }) => {
+            write!(out, "handle({:?}, {resource_id})", uri.value)
+        }
+        (value, typ) => {
+            let inferred = infer_type(value);
+            if inferred == *typ {
+                // Not reached, as the values always match their inferred types
+                write!(out, "{value:?}")
+            } else {
+                print(out, value, &inferred, depth);
+                Ok(())
+            }
+        }
+    }
+    .expect("writing to a String does not fail")
+}
+
+/// Prints a list, tuple or record, one item per line, or with nothing between the delimiters if it is empty
+fn print_items<'a>(
+    out: &mut String,
+    open: &str,
+    close: &str,
+    items: impl Iterator<Item = (Option<&'a str>, &'a Value, &'a AnalysedType)>,
+    depth: usize,
+) {
+    out.push_str(open);
+    let mut empty = true;
+    for (name, item, typ) in items {
+        empty = false;
+        out.push('\n');
+        out.push_str(&INDENT.repeat(depth + 1));
+        if let Some(name) = name {
+            out.push_str(name);
+            out.push_str(": ");
+        }
+        print(out, item, typ, depth + 1);
+        out.push(',');
+    }
+    if !empty {
+        out.push('\n');
+        out.push_str(&INDENT.repeat(depth));
+    }
+    out.push_str(close);
+}
+
+fn print_case(
+    out: &mut String,
+    name: &str,
+    payload: Option<(&Value, &AnalysedType)>,
+    depth: usize,
+) {
+    out.push_str(name);
+    if let Some((value, typ)) = payload {
+        out.push('(');
+        print(out, value, typ, depth);
+        out.push(')');
+    }
+}
+
+/// Floats with a fractional part or exponent, and the special values spelled like in WAVE
+fn float<F: Into<f64> + Debug + Copy>(value: F) -> String {
+    let float: f64 = value.into();
+    if float.is_nan() {
+        "nan".to_string()
+    } else if float.is_infinite() {
+        if float > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pretty::{pretty_print, print_type};
+    use crate::Value;
+    use golem_wasm_ast::analysis::AnalysedType;
+
+    #[test]
+    fn values_are_printed_with_their_types() {
+        let typ = AnalysedType::Record(vec![
+            (
+                "status".to_string(),
+                AnalysedType::Enum(vec!["running".to_string(), "failed".to_string()]),
+            ),
+            (
+                "error".to_string(),
+                AnalysedType::Option(Box::new(AnalysedType::Str)),
+            ),
+            (
+                "timings".to_string(),
+                AnalysedType::List(Box::new(AnalysedType::F32)),
+            ),
+            (
+                "access".to_string(),
+                AnalysedType::Flags(vec!["read".to_string(), "write".to_string()]),
+            ),
+            (
+                "outcome".to_string(),
+                AnalysedType::Result {
+                    ok: Some(Box::new(AnalysedType::Tuple(vec![
+                        AnalysedType::U64,
+                        AnalysedType::Chr,
+                    ]))),
+                    error: None,
+                },
+            ),
+            (
+                "tags".to_string(),
+                AnalysedType::List(Box::new(AnalysedType::Str)),
+            ),
+        ]);
+        let value = Value::Record(vec![
+            Value::Enum(1),
+            Value::Option(Some(Box::new(Value::String("time\nout".to_string())))),
+            Value::List(vec![Value::F32(0.1), Value::F32(f32::NEG_INFINITY)]),
+            Value::Flags(vec![true, false]),
+            Value::Result(Ok(Some(Box::new(Value::Tuple(vec![
+                Value::U64(7),
+                Value::Char('x'),
+            ]))))),
+            Value::List(vec![]),
+        ]);
+        assert_eq!(
+            pretty_print(&value, &typ),
+            r#"{
+    status: failed,
+    error: some("time\nout"),
+    timings: [
+        0.1,
+        -inf,
+    ],
+    access: {read},
+    outcome: ok((
+        7,
+        'x',
+    )),
+    tags: [],
+}"#
+        );
+        assert_eq!(
+            print_type(&typ),
+            "record { status: enum { running, failed }, error: option<string>, timings: list<float32>, \
+             access: flags { read, write }, outcome: result<tuple<u64, char>, _>, tags: list<string> }"
+        );
+    }
+
+    #[test]
+    fn values_not_matching_their_types_are_printed_with_the_inferred_types() {
+        let value = Value::Record(vec![Value::U8(1), Value::Enum(2)]);
+        assert_eq!(
+            pretty_print(&value, &AnalysedType::Str),
+            "{\n    field0: 1,\n    field1: case2,\n}"
+        );
+    }
+}
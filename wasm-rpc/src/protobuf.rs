@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::Value;
+use crate::{unicode, Value};
 use golem_wasm_ast::analysis::AnalysedFunctionParameter;
+use prost::bytes::Buf;
+use prost::encoding::{self, DecodeContext, WireType};
+use prost::DecodeError;
 include!(concat!(env!("OUT_DIR"), "/wasm.rpc.rs"));
 
 // Conversion from WIT WitValue to Protobuf WitValue
@@ -117,6 +120,9 @@ impl From<super::WitNode> for WitNode {
                     value,
                 })),
             },
+            super::WitNode::PrimBytes(value) => WitNode {
+                value: Some(wit_node::Value::Bytes(WitPrimBytesNode { value })),
+            },
         }
     }
 }
@@ -202,10 +208,9 @@ impl TryFrom<WitNode> for super::WitNode {
             Some(wit_node::Value::F64(WitPrimF64Node { value })) => {
                 Ok(super::WitNode::PrimFloat64(value))
             }
-            Some(wit_node::Value::Char(WitPrimCharNode { value })) => Ok(super::WitNode::PrimChar(
-                char::from_u32(value)
-                    .ok_or("Protobuf WitPrimCharNode has invalid value".to_string())?,
-            )),
+            Some(wit_node::Value::Char(WitPrimCharNode { value })) => {
+                decode_char(value).map(super::WitNode::PrimChar)
+            }
             Some(wit_node::Value::Bool(WitPrimBoolNode { value })) => {
                 Ok(super::WitNode::PrimBool(value))
             }
@@ -215,8 +220,325 @@ impl TryFrom<WitNode> for super::WitNode {
             Some(wit_node::Value::Handle(WitHandleNode { uri, value })) => {
                 Ok(super::WitNode::Handle((super::Uri { value: uri }, value)))
             }
+            Some(wit_node::Value::Bytes(WitPrimBytesNode { value })) => {
+                Ok(super::WitNode::PrimBytes(value))
+            }
+        }
+    }
+}
+
+// Encoding WIT WitValues directly to protobuf WitValue messages, and decoding them, without the intermediate messages
+
+/// Encodes a WitValue as a protobuf `WitValue` message, appending it to the buffer. The encoding is the same as of the
+/// converted message, but without allocating it, so reusing the buffer between the calls makes encoding allocation
+/// free.
+pub fn encode_into(value: &super::WitValue, buf: &mut Vec<u8>) {
+    buf.reserve(encoded_len(value));
+    for node in &value.nodes {
+        encoding::encode_key(1, WireType::LengthDelimited, buf);
+        encoding::encode_varint(node_encoded_len(node) as u64, buf);
+        let (tag, fields) = node_fields(node);
+        encoding::encode_key(tag, WireType::LengthDelimited, buf);
+        encoding::encode_varint(fields_encoded_len(&fields) as u64, buf);
+        for (field_tag, field) in (1..).zip(&fields) {
+            field.encode(field_tag, buf);
+        }
+    }
+}
+
+/// The length of the protobuf encoding of a WitValue
+pub fn encoded_len(value: &super::WitValue) -> usize {
+    value
+        .nodes
+        .iter()
+        .map(|node| {
+            let len = node_encoded_len(node);
+            encoding::key_len(1) + encoding::encoded_len_varint(len as u64) + len
+        })
+        .sum()
+}
+
+/// Decodes a protobuf `WitValue` message directly to a WitValue, without decoding the intermediate messages. The
+/// buffer can be a `bytes::Bytes` received from the network, or a slice.
+pub fn decode(mut buf: impl Buf) -> Result<super::WitValue, String> {
+    let mut nodes = Vec::new();
+    while buf.has_remaining() {
+        let (tag, wire_type) = encoding::decode_key(&mut buf).map_err(|err| err.to_string())?;
+        if tag == 1 {
+            encoding::check_wire_type(WireType::LengthDelimited, wire_type)
+                .map_err(|err| err.to_string())?;
+            nodes.push(decode_node(&mut buf)?);
+        } else {
+            encoding::skip_field(wire_type, tag, &mut buf, DecodeContext::default())
+                .map_err(|err| err.to_string())?;
         }
     }
+    Ok(super::WitValue { nodes })
+}
+
+/// A field of the protobuf message of a node
+enum Field<'a> {
+    Absent,
+    Int32(i32),
+    OptionalInt32(Option<i32>),
+    UInt32(u32),
+    UInt64(u64),
+    SInt32(i32),
+    SInt64(i64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+    String(&'a String),
+    Bytes(&'a Vec<u8>),
+    PackedInt32(&'a [i32]),
+    PackedBool(&'a [bool]),
+}
+
+impl Field<'_> {
+    /// Like the generated messages, the proto3 fields with default values are not encoded
+    fn encoded_len(&self, tag: u32) -> usize {
+        match self {
+            Field::Int32(value) if *value != 0 => encoding::int32::encoded_len(tag, value),
+            Field::OptionalInt32(Some(value)) => encoding::int32::encoded_len(tag, value),
+            Field::UInt32(value) if *value != 0 => encoding::uint32::encoded_len(tag, value),
+            Field::UInt64(value) if *value != 0 => encoding::uint64::encoded_len(tag, value),
+            Field::SInt32(value) if *value != 0 => encoding::sint32::encoded_len(tag, value),
+            Field::SInt64(value) if *value != 0 => encoding::sint64::encoded_len(tag, value),
+            Field::Float(value) if *value != 0.0 => encoding::float::encoded_len(tag, value),
+            Field::Double(value) if *value != 0.0 => encoding::double::encoded_len(tag, value),
+            Field::Bool(value) if *value => encoding::bool::encoded_len(tag, value),
+            Field::String(value) if !value.is_empty() => encoding::string::encoded_len(tag, value),
+            Field::Bytes(value) if !value.is_empty() => encoding::bytes::encoded_len(tag, *value),
+            Field::PackedInt32(values) => encoding::int32::encoded_len_packed(tag, values),
+            Field::PackedBool(values) => encoding::bool::encoded_len_packed(tag, values),
+            _ => 0,
+        }
+    }
+
+    fn encode(&self, tag: u32, buf: &mut Vec<u8>) {
+        match self {
+            Field::Int32(value) if *value != 0 => encoding::int32::encode(tag, value, buf),
+            Field::OptionalInt32(Some(value)) => encoding::int32::encode(tag, value, buf),
+            Field::UInt32(value) if *value != 0 => encoding::uint32::encode(tag, value, buf),
+            Field::UInt64(value) if *value != 0 => encoding::uint64::encode(tag, value, buf),
+            Field::SInt32(value) if *value != 0 => encoding::sint32::encode(tag, value, buf),
+            Field::SInt64(value) if *value != 0 => encoding::sint64::encode(tag, value, buf),
+            Field::Float(value) if *value != 0.0 => encoding::float::encode(tag, value, buf),
+            Field::Double(value) if *value != 0.0 => encoding::double::encode(tag, value, buf),
+            Field::Bool(value) if *value => encoding::bool::encode(tag, value, buf),
+            Field::String(value) if !value.is_empty() => encoding::string::encode(tag, value, buf),
+            Field::Bytes(value) if !value.is_empty() => encoding::bytes::encode(tag, *value, buf),
+            Field::PackedInt32(values) => encoding::int32::encode_packed(tag, values, buf),
+            Field::PackedBool(values) => encoding::bool::encode_packed(tag, values, buf),
+            _ => {}
+        }
+    }
+}
+
+/// The tag of the node in the `WitNode` oneof, and the fields of its message, tagged from 1
+fn node_fields(node: &super::WitNode) -> (u32, [Field<'_>; 2]) {
+    match node {
+        super::WitNode::RecordValue(fields) => (1, [Field::PackedInt32(fields), Field::Absent]),
+        super::WitNode::VariantValue((case_index, case_value)) => (
+            2,
+            [
+                Field::UInt32(*case_index),
+                Field::OptionalInt32(*case_value),
+            ],
+        ),
+        super::WitNode::EnumValue(value) => (3, [Field::UInt32(*value), Field::Absent]),
+        super::WitNode::FlagsValue(flags) => (4, [Field::PackedBool(flags), Field::Absent]),
+        super::WitNode::TupleValue(values) => (5, [Field::PackedInt32(values), Field::Absent]),
+        super::WitNode::ListValue(values) => (6, [Field::PackedInt32(values), Field::Absent]),
+        super::WitNode::OptionValue(value) => (7, [Field::OptionalInt32(*value), Field::Absent]),
+        super::WitNode::ResultValue(Ok(value)) => {
+            (8, [Field::Int32(0), Field::OptionalInt32(*value)])
+        }
+        super::WitNode::ResultValue(Err(value)) => {
+            (8, [Field::Int32(1), Field::OptionalInt32(*value)])
+        }
+        super::WitNode::PrimU8(value) => (9, [Field::UInt32(*value as u32), Field::Absent]),
+        super::WitNode::PrimU16(value) => (10, [Field::UInt32(*value as u32), Field::Absent]),
+        super::WitNode::PrimU32(value) => (11, [Field::UInt32(*value), Field::Absent]),
+        super::WitNode::PrimU64(value) => (12, [Field::UInt64(*value), Field::Absent]),
+        super::WitNode::PrimS8(value) => (13, [Field::SInt32(*value as i32), Field::Absent]),
+        super::WitNode::PrimS16(value) => (14, [Field::SInt32(*value as i32), Field::Absent]),
+        super::WitNode::PrimS32(value) => (15, [Field::SInt32(*value), Field::Absent]),
+        super::WitNode::PrimS64(value) => (16, [Field::SInt64(*value), Field::Absent]),
+        super::WitNode::PrimFloat32(value) => (17, [Field::Float(*value), Field::Absent]),
+        super::WitNode::PrimFloat64(value) => (18, [Field::Double(*value), Field::Absent]),
+        super::WitNode::PrimChar(value) => (19, [Field::UInt32(*value as u32), Field::Absent]),
+        super::WitNode::PrimBool(value) => (20, [Field::Bool(*value), Field::Absent]),
+        super::WitNode::PrimString(value) => (21, [Field::String(value), Field::Absent]),
+        super::WitNode::Handle((uri, value)) => {
+            (22, [Field::String(&uri.value), Field::UInt64(*value)])
+        }
+        super::WitNode::PrimBytes(value) => (23, [Field::Bytes(value), Field::Absent]),
+    }
+}
+
+fn fields_encoded_len(fields: &[Field<'_>; 2]) -> usize {
+    (1..)
+        .zip(fields)
+        .map(|(tag, field)| field.encoded_len(tag))
+        .sum()
+}
+
+/// The length of the `WitNode` message of a node
+fn node_encoded_len(node: &super::WitNode) -> usize {
+    let (tag, fields) = node_fields(node);
+    let len = fields_encoded_len(&fields);
+    encoding::key_len(tag) + encoding::encoded_len_varint(len as u64) + len
+}
+
+/// Decodes the fields of a length delimited message, skipping the unknown ones
+fn merge_message<B: Buf>(
+    buf: &mut B,
+    mut merge_field: impl FnMut(u32, WireType, &mut B) -> Result<(), String>,
+) -> Result<(), String> {
+    let len = encoding::decode_varint(buf).map_err(|err| err.to_string())?;
+    let remaining = buf.remaining();
+    if len > remaining as u64 {
+        return Err("Protobuf message is truncated".to_string());
+    }
+    let end = remaining - len as usize;
+    while buf.remaining() > end {
+        let (tag, wire_type) = encoding::decode_key(buf).map_err(|err| err.to_string())?;
+        merge_field(tag, wire_type, buf)?;
+    }
+    if buf.remaining() == end {
+        Ok(())
+    } else {
+        Err("Protobuf message has an invalid length".to_string())
+    }
+}
+
+/// Decodes a message of a single field tagged 1
+fn merge_single<B: Buf, T: Default>(
+    buf: &mut B,
+    merge: impl Fn(WireType, &mut T, &mut B, DecodeContext) -> Result<(), DecodeError>,
+) -> Result<T, String> {
+    let mut value = T::default();
+    merge_message(buf, |tag, wire_type, buf| {
+        match tag {
+            1 => merge(wire_type, &mut value, buf, DecodeContext::default()),
+            _ => encoding::skip_field(wire_type, tag, buf, DecodeContext::default()),
+        }
+        .map_err(|err| err.to_string())
+    })?;
+    Ok(value)
+}
+
+/// Decodes a message of an `uint32` or `int32` field tagged 1, and an optional `int32` field tagged 2
+fn merge_pair<B: Buf, T: Default>(
+    buf: &mut B,
+    merge: impl Fn(WireType, &mut T, &mut B, DecodeContext) -> Result<(), DecodeError>,
+) -> Result<(T, Option<i32>), String> {
+    let mut first = T::default();
+    let mut second = None;
+    merge_message(buf, |tag, wire_type, buf| {
+        match tag {
+            1 => merge(wire_type, &mut first, buf, DecodeContext::default()),
+            2 => encoding::int32::merge(
+                wire_type,
+                second.get_or_insert(0),
+                buf,
+                DecodeContext::default(),
+            ),
+            _ => encoding::skip_field(wire_type, tag, buf, DecodeContext::default()),
+        }
+        .map_err(|err| err.to_string())
+    })?;
+    Ok((first, second))
+}
+
+/// Validates the code point of a char node
+fn decode_char(code_point: impl Into<i64>) -> Result<char, String> {
+    unicode::decode_char(code_point)
+        .map_err(|err| format!("Protobuf WitPrimCharNode has invalid value: {err}"))
+}
+
+/// Decodes a `WitNode` message
+fn decode_node(buf: &mut impl Buf) -> Result<super::WitNode, String> {
+    let mut node = None;
+    merge_message(buf, |tag, wire_type, buf| {
+        if (1..=23).contains(&tag) {
+            encoding::check_wire_type(WireType::LengthDelimited, wire_type)
+                .map_err(|err| err.to_string())?;
+            node = Some(decode_node_value(tag, buf)?);
+            Ok(())
+        } else {
+            encoding::skip_field(wire_type, tag, buf, DecodeContext::default())
+                .map_err(|err| err.to_string())
+        }
+    })?;
+    node.ok_or("Protobuf WitNode has no value".to_string())
+}
+
+fn decode_node_value<B: Buf>(tag: u32, buf: &mut B) -> Result<super::WitNode, String> {
+    match tag {
+        1 => merge_single(buf, encoding::int32::merge_repeated).map(super::WitNode::RecordValue),
+        2 => merge_pair(buf, encoding::uint32::merge).map(super::WitNode::VariantValue),
+        3 => merge_single(buf, encoding::uint32::merge).map(super::WitNode::EnumValue),
+        4 => merge_single(buf, encoding::bool::merge_repeated).map(super::WitNode::FlagsValue),
+        5 => merge_single(buf, encoding::int32::merge_repeated).map(super::WitNode::TupleValue),
+        6 => merge_single(buf, encoding::int32::merge_repeated).map(super::WitNode::ListValue),
+        7 => merge_single(buf, |wire_type, value: &mut Option<i32>, buf, ctx| {
+            encoding::int32::merge(wire_type, value.get_or_insert(0), buf, ctx)
+        })
+        .map(super::WitNode::OptionValue),
+        8 => match merge_pair(buf, encoding::int32::merge)? {
+            (0, value) => Ok(super::WitNode::ResultValue(Ok(value))),
+            (1, value) => Ok(super::WitNode::ResultValue(Err(value))),
+            _ => Err("Protobuf WitResultNode has invalid discriminant".to_string()),
+        },
+        9 => merge_single(buf, encoding::uint32::merge)
+            .map(|value| super::WitNode::PrimU8(value as u8)),
+        10 => merge_single(buf, encoding::uint32::merge)
+            .map(|value| super::WitNode::PrimU16(value as u16)),
+        11 => merge_single(buf, encoding::uint32::merge).map(super::WitNode::PrimU32),
+        12 => merge_single(buf, encoding::uint64::merge).map(super::WitNode::PrimU64),
+        13 => merge_single(buf, encoding::sint32::merge)
+            .map(|value| super::WitNode::PrimS8(value as i8)),
+        14 => merge_single(buf, encoding::sint32::merge)
+            .map(|value| super::WitNode::PrimS16(value as i16)),
+        15 => merge_single(buf, encoding::sint32::merge).map(super::WitNode::PrimS32),
+        16 => merge_single(buf, encoding::sint64::merge).map(super::WitNode::PrimS64),
+        17 => merge_single(buf, encoding::float::merge).map(super::WitNode::PrimFloat32),
+        18 => merge_single(buf, encoding::double::merge).map(super::WitNode::PrimFloat64),
+        19 => {
+            let value = merge_single(buf, encoding::uint32::merge)?;
+            decode_char(value).map(super::WitNode::PrimChar)
+        }
+        20 => merge_single(buf, encoding::bool::merge).map(super::WitNode::PrimBool),
+        21 => {
+            let value = merge_single(buf, encoding::bytes::merge)?;
+            unicode::decode_string(value)
+                .map(super::WitNode::PrimString)
+                .map_err(|err| format!("Protobuf WitPrimStringNode has invalid value: {err}"))
+        }
+        22 => {
+            let mut uri = String::new();
+            let mut value = 0;
+            merge_message(buf, |tag, wire_type, buf| {
+                match tag {
+                    1 => {
+                        encoding::string::merge(wire_type, &mut uri, buf, DecodeContext::default())
+                    }
+                    2 => encoding::uint64::merge(
+                        wire_type,
+                        &mut value,
+                        buf,
+                        DecodeContext::default(),
+                    ),
+                    _ => encoding::skip_field(wire_type, tag, buf, DecodeContext::default()),
+                }
+                .map_err(|err| err.to_string())
+            })?;
+            Ok(super::WitNode::Handle((super::Uri { value: uri }, value)))
+        }
+        _ => merge_single(buf, encoding::bytes::merge).map(super::WitNode::PrimBytes),
+    }
 }
 
 // Conversion from WitValue to protobuf Val
@@ -346,6 +668,10 @@ impl From<Value> for Val {
     }
 }
 
+/// The maximum number of flags accepted when converting a protobuf [Val] to a [Value], bounding the allocation made
+/// for the `count` of a `ValFlags` message
+pub const MAX_FLAGS_COUNT: usize = 1 << 16;
+
 // Conversion from protobuf Val to WitValue
 impl TryFrom<Val> for super::WitValue {
     type Error = String;
@@ -373,10 +699,7 @@ impl TryFrom<Val> for Value {
             Some(val::Val::S64(value)) => Ok(Value::S64(value)),
             Some(val::Val::F32(value)) => Ok(Value::F32(value)),
             Some(val::Val::F64(value)) => Ok(Value::F64(value)),
-            Some(val::Val::Char(value)) => Ok(Value::Char(
-                char::from_u32(value as u32)
-                    .ok_or("Protobuf WitPrimCharNode has invalid value".to_string())?,
-            )),
+            Some(val::Val::Char(value)) => decode_char(value).map(Value::Char),
             Some(val::Val::String(value)) => Ok(Value::String(value)),
             Some(val::Val::List(ValList { values })) => Ok(Value::List(
                 values
@@ -411,9 +734,20 @@ impl TryFrom<Val> for Value {
             }
             Some(val::Val::Enum(ValEnum { discriminant })) => Ok(Value::Enum(discriminant as u32)),
             Some(val::Val::Flags(ValFlags { count, value })) => {
-                let mut flags = vec![false; count as usize];
+                let count = usize::try_from(count)
+                    .ok()
+                    .filter(|count| *count <= MAX_FLAGS_COUNT)
+                    .ok_or_else(|| format!("Protobuf ValFlags has invalid count {count}"))?;
+                let mut flags = vec![false; count];
                 for i in value {
-                    flags[i as usize] = true;
+                    match usize::try_from(i).ok().and_then(|i| flags.get_mut(i)) {
+                        Some(flag) => *flag = true,
+                        None => {
+                            return Err(format!(
+                                "Protobuf ValFlags has index {i} out of range for {count} flags"
+                            ))
+                        }
+                    }
                 }
                 Ok(Value::Flags(flags))
             }
@@ -469,10 +803,15 @@ pub fn function_parameters(
 
 #[cfg(test)]
 mod tests {
-    use super::{Val, WitValue};
+    use super::{
+        decode, encode_into, val, wit_node, Val, ValFlags, WitNode, WitPrimBytesNode,
+        WitPrimCharNode, WitValue,
+    };
     use crate::Value;
     use proptest::prelude::*;
     use proptest_arbitrary_interop::arb_sized;
+    use prost::bytes::Bytes;
+    use prost::Message;
 
     const CASES: u32 = 10000;
     const SIZE: usize = 4096;
@@ -500,5 +839,56 @@ mod tests {
             let round_trip_value: Value = round_trip_wit_value.into();
             prop_assert_eq!(value, round_trip_value);
         }
+
+        #[test]
+        fn round_trip_encode_into(value in arb_sized::<Value>(SIZE).prop_filter("Value must be equal to itself", |v| v.eq(v))) {
+            let wit_value: crate::WitValue = value.clone().into();
+            let mut buf = Vec::new();
+            encode_into(&wit_value, &mut buf);
+
+            let protobuf_wit_value: WitValue = wit_value.into();
+            prop_assert_eq!(&buf, &protobuf_wit_value.encode_to_vec());
+            let round_trip_wit_value = decode(Bytes::from(buf)).unwrap();
+            let round_trip_value: Value = round_trip_wit_value.into();
+            prop_assert_eq!(value, round_trip_value);
+        }
+    }
+    #[test]
+    fn invalid_chars_and_strings_are_rejected() {
+        let node = |value| WitValue {
+            nodes: vec![WitNode { value: Some(value) }],
+        };
+        let surrogate = node(wit_node::Value::Char(WitPrimCharNode { value: 0xD800 }));
+        assert!(crate::WitValue::try_from(surrogate.clone()).is_err());
+        assert!(decode(Bytes::from(surrogate.encode_to_vec())).is_err());
+        let negative = Val {
+            val: Some(val::Val::Char(-1)),
+        };
+        assert!(Value::try_from(negative).is_err());
+
+        // A string node holding invalid UTF-8 is encoded like a bytes node, with the tag of the string node
+        let bytes = node(wit_node::Value::Bytes(WitPrimBytesNode {
+            value: vec![0xC3, 0x28],
+        }));
+        let mut encoded = bytes.encode_to_vec();
+        assert_eq!(encoded[2..4], [0xBA, 0x01]);
+        encoded[2] = 0xAA;
+        assert!(decode(Bytes::from(encoded.clone())).is_err());
+        assert!(WitValue::decode(Bytes::from(encoded)).is_err());
+    }
+
+    #[test]
+    fn invalid_flags_are_rejected() {
+        let flags = |count, value| Val {
+            val: Some(val::Val::Flags(ValFlags { count, value })),
+        };
+        assert!(Value::try_from(flags(-1, vec![])).is_err());
+        assert!(Value::try_from(flags(i32::MAX, vec![])).is_err());
+        assert!(Value::try_from(flags(2, vec![2])).is_err());
+        assert!(Value::try_from(flags(2, vec![-1])).is_err());
+        assert_eq!(
+            Value::try_from(flags(3, vec![0, 2])),
+            Ok(Value::Flags(vec![true, false, true]))
+        );
     }
 }
@@ -0,0 +1,526 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::redact::Redactor;
+use crate::transport::RpcTransport;
+use crate::{RpcError, Uri, Value, WitValue};
+use async_trait::async_trait;
+use golem_wasm_ast::analysis::{AnalysedFunction, AnalysedType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// An invocation performed through the host, as written to the recordings, one JSON document per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedInvocation {
+    /// The position of the invocation among all the recorded ones, starting from 0
+    pub sequence: u64,
+    pub uri: String,
+    pub function_name: String,
+    pub function_params: Vec<WitValue>,
+    pub result: Result<WitValue, RpcError>,
+    /// Whether the invocation did not wait for the results of the function, in which case the result is an empty
+    /// tuple once it was delivered
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oneway: bool,
+    /// The start of the invocation, in milliseconds since the UNIX epoch
+    pub started_at: u64,
+    pub duration: Duration,
+}
+
+/// Writes the invocations flowing through a [crate::WasmRpcHost] to a recording
+pub struct InvocationRecorder {
+    writer: Mutex<Box<dyn Write + Send>>,
+    sequence: AtomicU64,
+    redactor: Redactor,
+    signatures: HashMap<String, AnalysedFunction>,
+}
+
+impl InvocationRecorder {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            sequence: AtomicU64::new(0),
+            redactor: Redactor::new(),
+            signatures: HashMap::new(),
+        }
+    }
+
+    /// Redacts the parameters and results before recording them. The signatures of the functions, by the names they
+    /// are invoked with, provide the types for the rules depending on them.
+    pub fn with_redactor(
+        mut self,
+        redactor: Redactor,
+        signatures: HashMap<String, AnalysedFunction>,
+    ) -> Self {
+        self.redactor = redactor;
+        self.signatures = signatures;
+        self
+    }
+
+    /// Records to a new file, replacing the existing one
+    pub fn to_file(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    fn next_sequence(&self) -> u64 {
+        self.sequence.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn redact(&self, mut invocation: RecordedInvocation) -> RecordedInvocation {
+        if self.redactor.is_empty() {
+            return invocation;
+        }
+        let function_name = invocation.function_name.clone();
+        let signature = self.signatures.get(&function_name);
+        invocation.function_params = invocation
+            .function_params
+            .into_iter()
+            .enumerate()
+            .map(|(idx, param)| {
+                let typ = signature
+                    .and_then(|signature| signature.params.get(idx))
+                    .map(|param| &param.typ);
+                self.redactor.redact_wit_value(&function_name, param, typ)
+            })
+            .collect();
+        // The results are returned as a tuple
+        let result_type = signature.map(|signature| {
+            AnalysedType::Tuple(
+                signature
+                    .results
+                    .iter()
+                    .map(|result| result.typ.clone())
+                    .collect(),
+            )
+        });
+        invocation.result = invocation.result.map(|result| {
+            self.redactor
+                .redact_wit_value(&function_name, result, result_type.as_ref())
+        });
+        invocation
+    }
+
+    /// Canonicalizes the floats of the parameters and results, so replaying the same invocations records the same
+    /// bytes whatever NaN payloads or signed zeros the workers produced
+    fn canonicalize(mut invocation: RecordedInvocation) -> RecordedInvocation {
+        invocation
+            .function_params
+            .iter_mut()
+            .for_each(WitValue::canonicalize_floats);
+        if let Ok(result) = &mut invocation.result {
+            result.canonicalize_floats();
+        }
+        invocation
+    }
+
+    fn write(&self, invocation: RecordedInvocation) -> std::io::Result<()> {
+        let invocation = self.redact(Self::canonicalize(invocation));
+        let mut line = serde_json::to_vec(&invocation)?;
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(&line)?;
+        writer.flush()
+    }
+}
+
+/// Records the invocations performed through the wrapped transport. Failing to write the recording does not fail the
+/// invocations.
+pub struct RecordingTransport<T> {
+    inner: T,
+    recorder: InvocationRecorder,
+}
+
+impl<T: RpcTransport> RecordingTransport<T> {
+    pub fn new(inner: T, recorder: InvocationRecorder) -> Self {
+        Self { inner, recorder }
+    }
+
+    async fn record<F: Future<Output = Result<WitValue, RpcError>>>(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        oneway: bool,
+        perform: impl FnOnce(Vec<WitValue>) -> F,
+    ) -> Result<WitValue, RpcError> {
+        let sequence = self.recorder.next_sequence();
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let start = Instant::now();
+        let result = perform(function_params.clone()).await;
+
+        let invocation = RecordedInvocation {
+            sequence,
+            uri: uri.value.clone(),
+            function_name: function_name.to_string(),
+            function_params,
+            result: result.clone(),
+            oneway,
+            started_at,
+            duration: start.elapsed(),
+        };
+        // The recording is a debugging aid, it must not change the outcome of the invocation
+        let _ = self.recorder.write(invocation);
+        result
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport> RpcTransport for RecordingTransport<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        self.record(uri, function_name, function_params, false, |params| {
+            self.inner
+                .invoke_and_await_with_metadata(uri, function_name, params, metadata)
+        })
+        .await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        self.record(
+            uri,
+            function_name,
+            function_params,
+            true,
+            |params| async move {
+                self.inner
+                    .invoke_with_metadata(uri, function_name, params, metadata)
+                    .await
+                    .map(|()| Value::Tuple(vec![]).into())
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.inner.reconnect(uri).await
+    }
+}
+
+/// A mock transport answering the invocations from a recording, without calling any worker. Each recorded
+/// invocation is replayed once, for the first invocation of the same function on the same worker with equal
+/// parameters, after canonicalizing their floats, so the interleaving of the calls to different workers may differ from the recorded one.
+pub struct ReplayTransport {
+    remaining: Mutex<Vec<RecordedInvocation>>,
+}
+
+impl ReplayTransport {
+    pub fn new(invocations: Vec<RecordedInvocation>) -> Self {
+        let mut invocations = invocations;
+        invocations.sort_by_key(|invocation| invocation.sequence);
+        Self {
+            remaining: Mutex::new(invocations),
+        }
+    }
+
+    /// Reads a recording written by [InvocationRecorder]
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let mut invocations = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                invocations.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self::new(invocations))
+    }
+
+    /// The recorded invocations which have not been replayed yet
+    pub fn remaining(&self) -> Vec<RecordedInvocation> {
+        self.remaining.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl RpcTransport for ReplayTransport {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        let params: Vec<Vec<u8>> = function_params
+            .into_iter()
+            .map(canonical_encoding)
+            .collect();
+        let mut remaining = self.remaining.lock().unwrap();
+        let idx = remaining.iter().position(|invocation| {
+            invocation.uri == uri.value
+                && invocation.function_name == function_name
+                && invocation.function_params.len() == params.len()
+                && invocation
+                    .function_params
+                    .iter()
+                    .zip(&params)
+                    .all(|(recorded, param)| canonical_encoding(recorded.clone()) == *param)
+        });
+        match idx {
+            Some(idx) => remaining.remove(idx).result,
+            None => Err(RpcError::ProtocolError(format!(
+                "No recorded invocation of {function_name} on {} with the same parameters",
+                uri.value
+            ))),
+        }
+    }
+}
+
+/// The encoding of a value with its floats canonicalized, comparing NaNs as equal unlike [Value]
+fn canonical_encoding(value: WitValue) -> Vec<u8> {
+    bincode::encode_to_vec(canonical_value(value), bincode::config::standard()).unwrap_or_default()
+}
+
+/// The value with its floats canonicalized and its nodes rebuilt, so the same values have the same encoding.
+/// Malformed values keep their nodes as they are.
+pub(crate) fn canonical_value(mut value: WitValue) -> WitValue {
+    match Value::try_from_wit_value(value.clone()) {
+        Ok(mut value) => {
+            value.canonicalize_floats();
+            WitValue::from(value)
+        }
+        Err(_) => {
+            value.canonicalize_floats();
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::{InvocationRecorder, RecordedInvocation, ReplayTransport};
+    use crate::transport::{RpcTransport, WasmRpcHost};
+    use crate::{RpcError, Uri, Value, WitValue};
+    use async_trait::async_trait;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Doubles its parameter, and fails for negative ones
+    struct Doubler;
+
+    #[async_trait]
+    impl RpcTransport for Doubler {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            match Value::from(function_params[0].clone()) {
+                Value::S32(value) if value >= 0 => Ok(Value::S32(value * 2).into()),
+                _ => Err(RpcError::RemoteInternalError("negative".to_string())),
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn uri(value: &str) -> Uri {
+        Uri {
+            value: value.to_string(),
+        }
+    }
+
+    fn param(value: i32) -> Vec<WitValue> {
+        vec![Value::S32(value).into()]
+    }
+
+    #[tokio::test]
+    async fn recorded_invocations_are_replayed() {
+        let buffer = SharedBuffer::default();
+        let host = WasmRpcHost::builder(Doubler)
+            .record(InvocationRecorder::new(buffer.clone()))
+            .build();
+        let worker1 = uri("worker://component/worker-1");
+        let worker2 = uri("worker://component/worker-2");
+        host.invoke_and_await(&worker1, "double", param(1))
+            .await
+            .unwrap();
+        host.invoke_and_await(&worker2, "double", param(-1))
+            .await
+            .unwrap_err();
+        host.invoke_and_await(&worker1, "double", param(2))
+            .await
+            .unwrap();
+
+        let recording = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let invocations = recording
+            .lines()
+            .map(|line| serde_json::from_str::<RecordedInvocation>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            invocations
+                .iter()
+                .map(|invocation| invocation.sequence)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        let replay = WasmRpcHost::builder(ReplayTransport::new(invocations)).build();
+        let result = replay
+            .invoke_and_await(&worker1, "double", param(2))
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::S32(4));
+        let error = replay
+            .invoke_and_await(&worker2, "double", param(-1))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RpcError::RemoteInternalError(msg) if msg == "negative"));
+        let result = replay
+            .invoke_and_await(&worker1, "double", param(1))
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::S32(2));
+
+        let error = replay
+            .invoke_and_await(&worker1, "double", param(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RpcError::ProtocolError(_)));
+
+        let malformed = WitValue {
+            nodes: vec![crate::WitNode::ListValue(vec![1])],
+        };
+        let error = replay
+            .invoke_and_await(&worker1, "double", vec![malformed])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RpcError::ProtocolError(_)));
+    }
+
+    /// Returns its parameter
+    struct Echo;
+
+    #[async_trait]
+    impl RpcTransport for Echo {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            Ok(function_params[0].clone())
+        }
+    }
+
+    async fn record_floats(nan: f64, zero: f32) -> Vec<RecordedInvocation> {
+        let buffer = SharedBuffer::default();
+        let host = WasmRpcHost::builder(Echo)
+            .record(InvocationRecorder::new(buffer.clone()))
+            .build();
+        let param = Value::Tuple(vec![Value::F64(nan), Value::F32(zero)]);
+        host.invoke_and_await(
+            &uri("worker://component/worker-1"),
+            "echo",
+            vec![param.into()],
+        )
+        .await
+        .unwrap();
+        let recording = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        recording
+            .lines()
+            .map(|line| serde_json::from_str::<RecordedInvocation>(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn recorded_floats_are_canonical() {
+        let payloads = |invocations: &[RecordedInvocation]| {
+            invocations
+                .iter()
+                .map(|invocation| {
+                    serde_json::to_string(&(&invocation.function_params, &invocation.result))
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = record_floats(f64::NAN, 0.0).await;
+        let second = record_floats(f64::from_bits(f64::NAN.to_bits() | 0xbeef), -0.0).await;
+        assert_eq!(payloads(&first), payloads(&second));
+
+        let replay = WasmRpcHost::builder(ReplayTransport::new(first)).build();
+        let param = Value::Tuple(vec![Value::F64(-f64::NAN), Value::F32(-0.0)]);
+        let result = replay
+            .invoke_and_await(
+                &uri("worker://component/worker-1"),
+                "echo",
+                vec![param.into()],
+            )
+            .await
+            .unwrap();
+        let Value::Tuple(fields) = Value::from(result) else {
+            panic!("Unexpected result");
+        };
+        assert!(matches!(
+            fields[..],
+            [Value::F64(nan), Value::F32(zero)]
+                if nan.to_bits() == f64::NAN.to_bits() && zero.to_bits() == 0
+        ));
+    }
+}
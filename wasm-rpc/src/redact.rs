@@ -0,0 +1,300 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Value, WitValue};
+use golem_wasm_ast::analysis::AnalysedType;
+
+/// The text replacing the redacted strings
+pub const REDACTED: &str = "<redacted>";
+
+/// Selects the parts of the values to be redacted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedactionRule {
+    /// Every record field with the given name, in any function. Finding the fields requires the type of the value.
+    FieldNamed(String),
+    /// Strings longer than the given number of characters, in the functions of the given interface, like
+    /// `rpc:counter/api`, or in all functions
+    LongStrings {
+        max_length: usize,
+        interface: Option<String>,
+    },
+}
+
+impl RedactionRule {
+    fn applies_to(&self, function_name: &str) -> bool {
+        match self {
+            RedactionRule::FieldNamed(_) => true,
+            RedactionRule::LongStrings { interface, .. } => match interface {
+                Some(interface) => function_name
+                    .strip_prefix(interface.as_str())
+                    .is_some_and(|rest| rest.starts_with('/') || rest.starts_with('.')),
+                None => true,
+            },
+        }
+    }
+}
+
+/// Redacts secrets from the parameters and results of invocations before they are logged or recorded. The
+/// redacted values keep their structure, so they remain valid values of their types: strings are replaced by
+/// [REDACTED], numbers by zero, lists are emptied and options are set to none.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: Vec<RedactionRule>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, rule: RedactionRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Redacts a value passed to or returned by a function. Without the type of the value only the rules not
+    /// depending on it are applied.
+    pub fn redact(&self, function_name: &str, value: Value, typ: Option<&AnalysedType>) -> Value {
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(function_name))
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            value
+        } else {
+            redact_value(&rules, value, typ)
+        }
+    }
+
+    pub fn redact_wit_value(
+        &self,
+        function_name: &str,
+        value: WitValue,
+        typ: Option<&AnalysedType>,
+    ) -> WitValue {
+        if self.is_empty() {
+            value
+        } else {
+            self.redact(function_name, value.into(), typ).into()
+        }
+    }
+}
+
+fn redact_value(rules: &[&RedactionRule], value: Value, typ: Option<&AnalysedType>) -> Value {
+    let redact_box =
+        |value: Box<Value>, typ: Option<&AnalysedType>| Box::new(redact_value(rules, *value, typ));
+    match value {
+        Value::String(string) => {
+            let too_long = rules.iter().any(|rule| match rule {
+                RedactionRule::LongStrings { max_length, .. } => {
+                    string.chars().count() > *max_length
+                }
+                _ => false,
+            });
+            if too_long {
+                Value::String(REDACTED.to_string())
+            } else {
+                Value::String(string)
+            }
+        }
+        Value::Record(fields) => {
+            let field_types = match typ {
+                Some(AnalysedType::Record(field_types)) => Some(field_types),
+                _ => None,
+            };
+            Value::Record(
+                fields
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, field)| {
+                        match field_types.and_then(|field_types| field_types.get(idx)) {
+                            Some((name, _)) if is_redacted_field(rules, name) => mask(field),
+                            Some((_, typ)) => redact_value(rules, field, Some(typ)),
+                            None => redact_value(rules, field, None),
+                        }
+                    })
+                    .collect(),
+            )
+        }
+        Value::List(items) => {
+            let typ = match typ {
+                Some(AnalysedType::List(typ)) => Some(typ.as_ref()),
+                _ => None,
+            };
+            Value::List(
+                items
+                    .into_iter()
+                    .map(|item| redact_value(rules, item, typ))
+                    .collect(),
+            )
+        }
+        Value::Tuple(items) => {
+            let types = match typ {
+                Some(AnalysedType::Tuple(types)) => Some(types),
+                _ => None,
+            };
+            Value::Tuple(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, item)| {
+                        redact_value(rules, item, types.and_then(|types| types.get(idx)))
+                    })
+                    .collect(),
+            )
+        }
+        Value::Option(value) => {
+            let typ = match typ {
+                Some(AnalysedType::Option(typ)) => Some(typ.as_ref()),
+                _ => None,
+            };
+            Value::Option(value.map(|value| redact_box(value, typ)))
+        }
+        Value::Result(result) => {
+            let (ok, err) = match typ {
+                Some(AnalysedType::Result { ok, error }) => (ok.as_deref(), error.as_deref()),
+                _ => (None, None),
+            };
+            Value::Result(match result {
+                Ok(value) => Ok(value.map(|value| redact_box(value, ok))),
+                Err(value) => Err(value.map(|value| redact_box(value, err))),
+            })
+        }
+        Value::Variant {
+            case_idx,
+            case_value,
+        } => {
+            let typ = match typ {
+                Some(AnalysedType::Variant(cases)) => cases
+                    .get(case_idx as usize)
+                    .and_then(|(_, typ)| typ.as_ref()),
+                _ => None,
+            };
+            Value::Variant {
+                case_idx,
+                case_value: case_value.map(|value| redact_box(value, typ)),
+            }
+        }
+        value => value,
+    }
+}
+
+fn is_redacted_field(rules: &[&RedactionRule], name: &str) -> bool {
+    rules
+        .iter()
+        .any(|rule| matches!(rule, RedactionRule::FieldNamed(field) if field == name))
+}
+
+/// Replaces the contents of a value, keeping its structure
+fn mask(value: Value) -> Value {
+    let mask_box = |value: Box<Value>| Box::new(mask(*value));
+    match value {
+        Value::Bool(_) => Value::Bool(false),
+        Value::U8(_) => Value::U8(0),
+        Value::U16(_) => Value::U16(0),
+        Value::U32(_) => Value::U32(0),
+        Value::U64(_) => Value::U64(0),
+        Value::S8(_) => Value::S8(0),
+        Value::S16(_) => Value::S16(0),
+        Value::S32(_) => Value::S32(0),
+        Value::S64(_) => Value::S64(0),
+        Value::F32(_) => Value::F32(0.0),
+        Value::F64(_) => Value::F64(0.0),
+        Value::Char(_) => Value::Char('*'),
+        Value::String(_) => Value::String(REDACTED.to_string()),
+        Value::List(_) => Value::List(vec![]),
+        Value::Tuple(items) => Value::Tuple(items.into_iter().map(mask).collect()),
+        Value::Record(fields) => Value::Record(fields.into_iter().map(mask).collect()),
+        Value::Variant {
+            case_idx,
+            case_value,
+        } => Value::Variant {
+            case_idx,
+            case_value: case_value.map(mask_box),
+        },
+        Value::Enum(_) => Value::Enum(0),
+        Value::Flags(flags) => Value::Flags(vec![false; flags.len()]),
+        Value::Option(_) => Value::Option(None),
+        Value::Result(result) => Value::Result(match result {
+            Ok(value) => Ok(value.map(mask_box)),
+            Err(value) => Err(value.map(mask_box)),
+        }),
+        handle @ Value::Handle { .. } => handle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redact::{RedactionRule, Redactor, REDACTED};
+    use crate::Value;
+    use golem_wasm_ast::analysis::AnalysedType;
+
+    fn credentials_type() -> AnalysedType {
+        AnalysedType::Record(vec![
+            ("user".to_string(), AnalysedType::Str),
+            (
+                "password".to_string(),
+                AnalysedType::Option(Box::new(AnalysedType::Str)),
+            ),
+            ("note".to_string(), AnalysedType::Str),
+        ])
+    }
+
+    fn credentials(note: &str) -> Value {
+        Value::Record(vec![
+            Value::String("alice".to_string()),
+            Value::Option(Some(Box::new(Value::String("secret".to_string())))),
+            Value::String(note.to_string()),
+        ])
+    }
+
+    #[test]
+    fn fields_and_long_strings_are_redacted() {
+        let redactor = Redactor::new()
+            .with_rule(RedactionRule::FieldNamed("password".to_string()))
+            .with_rule(RedactionRule::LongStrings {
+                max_length: 8,
+                interface: Some("rpc:auth/api".to_string()),
+            });
+        let typ = AnalysedType::List(Box::new(credentials_type()));
+        let value = Value::List(vec![credentials("a long note")]);
+
+        assert_eq!(
+            redactor.redact("rpc:auth/api.{login}", value.clone(), Some(&typ)),
+            Value::List(vec![Value::Record(vec![
+                Value::String("alice".to_string()),
+                Value::Option(None),
+                Value::String(REDACTED.to_string()),
+            ])])
+        );
+        assert_eq!(
+            redactor.redact("rpc:auth/api-v2.{login}", value.clone(), Some(&typ)),
+            Value::List(vec![Value::Record(vec![
+                Value::String("alice".to_string()),
+                Value::Option(None),
+                Value::String("a long note".to_string()),
+            ])])
+        );
+        // Without the type the field names are unknown
+        assert_eq!(
+            redactor.redact("rpc:auth/api.{login}", credentials("short"), None),
+            credentials("short")
+        );
+    }
+}
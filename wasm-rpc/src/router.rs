@@ -0,0 +1,382 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::codec::{Codecs, CONTENT_CODEC, NEGOTIATE_FUNCTION};
+use crate::intercept::{IncomingCall, Interceptors};
+use crate::transport::RpcTransport;
+use crate::uri::{UriError, WorkerUri};
+use crate::{RpcError, Uri, WitValue};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+#[cfg(feature = "host")]
+pub use instance::ComponentInstance;
+
+/// Handles the invocations routed to a component instance
+#[async_trait]
+pub trait RpcHandler: Send + Sync {
+    async fn handle(&self, call: &IncomingCall) -> Result<WitValue, RpcError>;
+}
+
+#[async_trait]
+impl<H: RpcHandler + ?Sized> RpcHandler for Arc<H> {
+    async fn handle(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+        (**self).handle(call).await
+    }
+}
+
+/// The server side of the host: dispatches the incoming invocations to the handlers registered for their target
+/// workers, through the interceptors of the router.
+///
+/// A handler is registered either for a single worker, or for all the workers of a component which have no handler
+/// of their own. The router is also an [RpcTransport], so a [crate::WasmRpcHost] built on it delivers the invocations
+/// of the `wasm-rpc` resources to the component instances of the same host, without a network in between.
+#[derive(Clone, Default)]
+pub struct RpcRouter {
+    routes: Arc<RwLock<Routes>>,
+    interceptors: Interceptors,
+    codecs: Codecs,
+}
+
+#[derive(Default)]
+struct Routes {
+    workers: HashMap<WorkerUri, Arc<dyn RpcHandler>>,
+    components: HashMap<String, Arc<dyn RpcHandler>>,
+}
+
+impl RpcRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the dispatch of every incoming invocation through the interceptors
+    pub fn interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Restricts the payload codecs negotiated with the callers, which are all the available ones by default
+    pub fn codecs(mut self, codecs: Codecs) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    /// Registers the handler of a worker, replacing its previous handler. The function of the URI, if any, is
+    /// ignored.
+    pub fn register(&self, uri: &Uri, handler: impl RpcHandler + 'static) -> Result<(), UriError> {
+        let worker = uri.parse_worker()?.worker();
+        self.routes
+            .write()
+            .unwrap()
+            .workers
+            .insert(worker, Arc::new(handler));
+        Ok(())
+    }
+
+    /// Registers the handler of the workers of a component which have no handler of their own, like a component
+    /// instantiating its workers on demand
+    pub fn register_component(
+        &self,
+        component_id: impl Into<String>,
+        handler: impl RpcHandler + 'static,
+    ) {
+        self.routes
+            .write()
+            .unwrap()
+            .components
+            .insert(component_id.into(), Arc::new(handler));
+    }
+
+    /// Removes the handler of a worker, returning whether it had one
+    pub fn unregister(&self, uri: &Uri) -> bool {
+        match uri.parse_worker() {
+            Ok(worker) => self
+                .routes
+                .write()
+                .unwrap()
+                .workers
+                .remove(&worker.worker())
+                .is_some(),
+            Err(_) => false,
+        }
+    }
+
+    /// The handler of the target worker of an invocation
+    pub fn route(&self, uri: &Uri) -> Option<Arc<dyn RpcHandler>> {
+        let worker = uri.parse_worker().ok()?;
+        let routes = self.routes.read().unwrap();
+        routes
+            .workers
+            .get(&worker.worker())
+            .or_else(|| routes.components.get(&worker.component_id))
+            .cloned()
+    }
+
+    /// Dispatches an incoming invocation to the handler of its target worker through the interceptors. Invocations
+    /// of workers without a handler fail with [RpcError::NotFound], after the interceptors had the chance to reject
+    /// them.
+    ///
+    /// The codec negotiations of the callers are answered by the router itself, and the encoded payloads are decoded
+    /// before the interceptors see them.
+    pub async fn dispatch(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+        if call.function_name == NEGOTIATE_FUNCTION {
+            return Ok(self.codecs.answer(&call.metadata));
+        }
+        match self.codecs.content_codec(&call.metadata)? {
+            Some(codec) => {
+                let decoded = IncomingCall {
+                    function_params: codec.decode_params(call.function_params.clone())?,
+                    metadata: call
+                        .metadata
+                        .iter()
+                        .filter(|(name, _)| name != CONTENT_CODEC)
+                        .cloned()
+                        .collect(),
+                    ..call.clone()
+                };
+                let result = self.dispatch_decoded(&decoded).await?;
+                codec.encode_result(&result)
+            }
+            None => self.dispatch_decoded(call).await,
+        }
+    }
+
+    async fn dispatch_decoded(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+        let handler = self.route(&call.uri);
+        self.interceptors
+            .dispatch(call, || async {
+                match handler {
+                    Some(handler) => handler.handle(call).await,
+                    None => Err(RpcError::NotFound(format!(
+                        "No component instance is registered for {}",
+                        call.uri
+                    ))),
+                }
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl RpcTransport for RpcRouter {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.dispatch(&IncomingCall::new(
+            uri.clone(),
+            function_name,
+            function_params,
+        ))
+        .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        let call = IncomingCall::new(uri.clone(), function_name, function_params)
+            .with_metadata(metadata.to_vec());
+        self.dispatch(&call).await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await
+            .map(|_| ())
+    }
+}
+
+#[cfg(feature = "host")]
+mod instance {
+    use crate::intercept::IncomingCall;
+    use crate::router::RpcHandler;
+    use crate::server::RpcServer;
+    use crate::{RpcError, WitValue};
+    use async_trait::async_trait;
+    use tokio::sync::Mutex;
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::Store;
+
+    /// A component instance exporting the `golem:rpc/server` interface of the `rpc-server` world. Like a worker, it
+    /// handles the invocations routed to it one at a time.
+    pub struct ComponentInstance<T: Send + 'static> {
+        state: Mutex<(Store<T>, RpcServer)>,
+    }
+
+    impl<T: Send + 'static> ComponentInstance<T> {
+        /// Instantiates the component in its own store
+        pub async fn instantiate(
+            mut store: Store<T>,
+            component: &Component,
+            linker: &Linker<T>,
+        ) -> wasmtime::Result<Self> {
+            let (server, _) = RpcServer::instantiate_async(&mut store, component, linker).await?;
+            Ok(Self {
+                state: Mutex::new((store, server)),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl<T: Send + 'static> RpcHandler for ComponentInstance<T> {
+        async fn handle(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+            let mut state = self.state.lock().await;
+            let (store, server) = &mut *state;
+            server
+                .golem_rpc_server()
+                .call_handle(
+                    &mut *store,
+                    &call.uri,
+                    &call.function_name,
+                    &call.function_params,
+                    &call.metadata,
+                )
+                .await
+                .map_err(|err| RpcError::RemoteInternalError(format!("{err:#}")))?
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intercept::{IncomingCall, Interceptor, Interceptors};
+    use crate::router::{RpcHandler, RpcRouter};
+    use crate::{RpcError, Uri, Value, WasmRpcHost, WitValue};
+    use async_trait::async_trait;
+
+    /// Returns its name, the target worker, the function and the metadata of the invocation
+    struct Echo(&'static str);
+
+    #[async_trait]
+    impl RpcHandler for Echo {
+        async fn handle(&self, call: &IncomingCall) -> Result<WitValue, RpcError> {
+            let metadata = call
+                .metadata
+                .iter()
+                .map(|(name, value)| Value::String(format!("{name}={value}")))
+                .collect();
+            Ok(Value::Tuple(vec![
+                Value::String(self.0.to_string()),
+                Value::String(call.uri.to_string()),
+                Value::String(call.function_name.clone()),
+                Value::List(metadata),
+            ])
+            .into())
+        }
+    }
+
+    fn echoed(handler: &str, uri: &Uri, function_name: &str, metadata: &[&str]) -> Value {
+        Value::Tuple(vec![
+            Value::String(handler.to_string()),
+            Value::String(uri.to_string()),
+            Value::String(function_name.to_string()),
+            Value::List(
+                metadata
+                    .iter()
+                    .map(|entry| Value::String(entry.to_string()))
+                    .collect(),
+            ),
+        ])
+    }
+
+    /// Rejects every invocation of the given worker
+    struct Deny(&'static str);
+
+    #[async_trait]
+    impl Interceptor for Deny {
+        async fn before(&self, call: &IncomingCall) -> Result<(), RpcError> {
+            if call.uri.parse_worker().unwrap().worker_name == self.0 {
+                Err(RpcError::Denied(self.0.to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn invocations_are_routed_by_their_target_worker() {
+        let router = RpcRouter::new().interceptors(Interceptors::new().with(Deny("blocked")));
+        router
+            .register(&Uri::worker("counter", "worker-1"), Echo("worker-1"))
+            .unwrap();
+        router.register_component("counter", Echo("counter"));
+        let call = |uri: &Uri| IncomingCall::new(uri.clone(), "rpc:counter/api.{get}", vec![]);
+
+        let uri = Uri::worker("counter", "worker-1");
+        let result = router.dispatch(&call(&uri)).await.unwrap();
+        assert_eq!(
+            Value::from(result),
+            echoed("worker-1", &uri, "rpc:counter/api.{get}", &[])
+        );
+
+        let uri = Uri::worker("counter", "worker-2");
+        let result = router.dispatch(&call(&uri)).await.unwrap();
+        assert_eq!(
+            Value::from(result),
+            echoed("counter", &uri, "rpc:counter/api.{get}", &[])
+        );
+
+        let result = router
+            .dispatch(&call(&Uri::worker("counter", "blocked")))
+            .await;
+        assert!(matches!(result, Err(RpcError::Denied(_))));
+
+        let result = router
+            .dispatch(&call(&Uri::worker("other", "worker-1")))
+            .await;
+        assert!(matches!(result, Err(RpcError::NotFound(_))));
+
+        assert!(router.unregister(&Uri::worker("counter", "worker-1")));
+        assert!(!router.unregister(&Uri::worker("counter", "worker-1")));
+        let uri = Uri::worker("counter", "worker-1");
+        let result = router.dispatch(&call(&uri)).await.unwrap();
+        assert_eq!(
+            Value::from(result),
+            echoed("counter", &uri, "rpc:counter/api.{get}", &[])
+        );
+    }
+
+    #[tokio::test]
+    async fn hosts_on_a_router_call_the_local_instances() {
+        let router = RpcRouter::new();
+        router
+            .register(&Uri::worker("counter", "worker-1"), Echo("worker-1"))
+            .unwrap();
+        let host = WasmRpcHost::builder(router).build();
+        let uri = Uri::worker("counter", "worker-1");
+        let metadata = vec![("tenant".to_string(), "acme".to_string())];
+
+        let result = host
+            .invoke_and_await_with_metadata(&uri, "rpc:counter/api.{add}", vec![], &metadata)
+            .await
+            .unwrap();
+        assert_eq!(
+            Value::from(result),
+            echoed("worker-1", &uri, "rpc:counter/api.{add}", &["tenant=acme"])
+        );
+    }
+}
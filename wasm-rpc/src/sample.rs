@@ -0,0 +1,198 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Value;
+use arbitrary::{Error, Unstructured};
+use golem_wasm_ast::analysis::AnalysedType;
+
+/// The maximum number of elements of the generated lists
+const MAX_LIST_LENGTH: u32 = 4;
+
+/// Generates an arbitrary value of a WIT type, like the parameters of a function invoked by a test. Resource handles
+/// cannot be made up, so generating a value holding one fails with [Error::IncorrectFormat]; they have to be taken from
+/// the results of other invocations instead.
+pub fn arbitrary_value(typ: &AnalysedType, u: &mut Unstructured) -> arbitrary::Result<Value> {
+    Ok(match typ {
+        AnalysedType::Bool => Value::Bool(u.arbitrary()?),
+        AnalysedType::U8 => Value::U8(u.arbitrary()?),
+        AnalysedType::U16 => Value::U16(u.arbitrary()?),
+        AnalysedType::U32 => Value::U32(u.arbitrary()?),
+        AnalysedType::U64 => Value::U64(u.arbitrary()?),
+        AnalysedType::S8 => Value::S8(u.arbitrary()?),
+        AnalysedType::S16 => Value::S16(u.arbitrary()?),
+        AnalysedType::S32 => Value::S32(u.arbitrary()?),
+        AnalysedType::S64 => Value::S64(u.arbitrary()?),
+        AnalysedType::F32 => Value::F32(u.arbitrary()?),
+        AnalysedType::F64 => Value::F64(u.arbitrary()?),
+        AnalysedType::Chr => Value::Char(u.arbitrary()?),
+        AnalysedType::Str => Value::String(u.arbitrary()?),
+        AnalysedType::List(element) => {
+            let len = u.int_in_range(0..=MAX_LIST_LENGTH)?;
+            Value::List(
+                (0..len)
+                    .map(|_| arbitrary_value(element, u))
+                    .collect::<arbitrary::Result<_>>()?,
+            )
+        }
+        AnalysedType::Tuple(types) => Value::Tuple(arbitrary_values(types.iter(), u)?),
+        AnalysedType::Record(fields) => {
+            Value::Record(arbitrary_values(fields.iter().map(|(_, typ)| typ), u)?)
+        }
+        AnalysedType::Variant(cases) => {
+            let case_idx = u.choose_index(cases.len())?;
+            Value::Variant {
+                case_idx: case_idx as u32,
+                case_value: arbitrary_optional(cases[case_idx].1.as_ref(), u)?,
+            }
+        }
+        AnalysedType::Enum(cases) => Value::Enum(u.choose_index(cases.len())? as u32),
+        AnalysedType::Flags(flags) => Value::Flags(
+            flags
+                .iter()
+                .map(|_| u.arbitrary())
+                .collect::<arbitrary::Result<_>>()?,
+        ),
+        AnalysedType::Option(inner) => Value::Option(if u.arbitrary()? {
+            Some(Box::new(arbitrary_value(inner, u)?))
+        } else {
+            None
+        }),
+        AnalysedType::Result { ok, error } => Value::Result(if u.arbitrary()? {
+            Ok(arbitrary_optional(ok.as_deref(), u)?)
+        } else {
+            Err(arbitrary_optional(error.as_deref(), u)?)
+        }),
+        AnalysedType::Resource { .. } => return Err(Error::IncorrectFormat),
+    })
+}
+
+fn arbitrary_values<'a>(
+    types: impl Iterator<Item = &'a AnalysedType>,
+    u: &mut Unstructured,
+) -> arbitrary::Result<Vec<Value>> {
+    types.map(|typ| arbitrary_value(typ, u)).collect()
+}
+
+fn arbitrary_optional(
+    typ: Option<&AnalysedType>,
+    u: &mut Unstructured,
+) -> arbitrary::Result<Option<Box<Value>>> {
+    typ.map(|typ| arbitrary_value(typ, u).map(Box::new))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::arbitrary_value;
+    use crate::Value;
+    use arbitrary::Unstructured;
+    use golem_wasm_ast::analysis::{AnalysedResourceId, AnalysedResourceMode, AnalysedType};
+
+    fn conforms(value: &Value, typ: &AnalysedType) -> bool {
+        match (value, typ) {
+            (Value::List(values), AnalysedType::List(element)) => {
+                values.iter().all(|value| conforms(value, element))
+            }
+            (Value::Tuple(values), AnalysedType::Tuple(types)) => {
+                values.len() == types.len() && values.iter().zip(types).all(|(v, t)| conforms(v, t))
+            }
+            (Value::Record(values), AnalysedType::Record(fields)) => {
+                values.len() == fields.len()
+                    && values.iter().zip(fields).all(|(v, (_, t))| conforms(v, t))
+            }
+            (
+                Value::Variant {
+                    case_idx,
+                    case_value,
+                },
+                AnalysedType::Variant(cases),
+            ) => match (cases.get(*case_idx as usize), case_value) {
+                (Some((_, Some(typ))), Some(value)) => conforms(value, typ),
+                (Some((_, None)), None) => true,
+                _ => false,
+            },
+            (Value::Enum(case_idx), AnalysedType::Enum(cases)) => {
+                (*case_idx as usize) < cases.len()
+            }
+            (Value::Flags(values), AnalysedType::Flags(flags)) => values.len() == flags.len(),
+            (Value::Option(value), AnalysedType::Option(inner)) => {
+                value.as_deref().is_none_or(|value| conforms(value, inner))
+            }
+            (Value::Result(result), AnalysedType::Result { ok, error }) => {
+                let (value, typ) = match result {
+                    Ok(value) => (value, ok),
+                    Err(value) => (value, error),
+                };
+                match (value, typ) {
+                    (Some(value), Some(typ)) => conforms(value, typ),
+                    (None, None) => true,
+                    _ => false,
+                }
+            }
+            (Value::String(_), AnalysedType::Str) | (Value::U16(_), AnalysedType::U16) => true,
+            (Value::Bool(_), AnalysedType::Bool) | (Value::F64(_), AnalysedType::F64) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn values_conform_to_their_types() {
+        let typ = AnalysedType::List(Box::new(AnalysedType::Record(vec![
+            ("name".to_string(), AnalysedType::Str),
+            (
+                "tags".to_string(),
+                AnalysedType::Tuple(vec![AnalysedType::U16, AnalysedType::Bool]),
+            ),
+            (
+                "status".to_string(),
+                AnalysedType::Variant(vec![
+                    ("active".to_string(), Some(AnalysedType::F64)),
+                    ("inactive".to_string(), None),
+                ]),
+            ),
+            (
+                "level".to_string(),
+                AnalysedType::Enum(vec!["low".to_string(), "high".to_string()]),
+            ),
+            (
+                "flags".to_string(),
+                AnalysedType::Flags(vec!["read".to_string(), "write".to_string()]),
+            ),
+            (
+                "outcome".to_string(),
+                AnalysedType::Result {
+                    ok: Some(Box::new(AnalysedType::Option(Box::new(AnalysedType::Str)))),
+                    error: None,
+                },
+            ),
+        ])));
+
+        let bytes: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&bytes);
+        for _ in 0..16 {
+            let value = arbitrary_value(&typ, &mut u).unwrap();
+            assert!(conforms(&value, &typ), "{value:?} does not conform");
+        }
+    }
+
+    #[test]
+    fn resources_are_not_generated() {
+        let typ = AnalysedType::Option(Box::new(AnalysedType::Resource {
+            id: AnalysedResourceId { value: 0 },
+            resource_mode: AnalysedResourceMode::Owned,
+        }));
+        let bytes = [1u8; 16];
+        assert!(arbitrary_value(&typ, &mut Unstructured::new(&bytes)).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Uri;
+use alloc::format;
+use alloc::string::String;
+
+/// Chooses the name of the worker handling the calls related to a key, like the id of a user. The selected name can
+/// be passed to the `for-worker` constructor of the generated stubs.
+pub trait WorkerSelector {
+    fn select(&self, key: &str) -> String;
+
+    /// The URI of the selected worker of the given component
+    fn select_uri(&self, component_id: &str, key: &str) -> Uri {
+        Uri::worker(component_id, self.select(key))
+    }
+}
+
+impl<F: Fn(&str) -> String> WorkerSelector for F {
+    fn select(&self, key: &str) -> String {
+        self(key)
+    }
+}
+
+/// Spreads the keys across a fixed number of workers, named `<prefix>-<shard>`. The shards are chosen by a hash of the
+/// key which is the same on every platform and in every version, so the callers agree on the worker of each key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashSharding {
+    prefix: String,
+    shards: u32,
+}
+
+impl HashSharding {
+    /// Shards across `shards` workers, at least one
+    pub fn new(prefix: impl Into<String>, shards: u32) -> Self {
+        Self {
+            prefix: prefix.into(),
+            shards: shards.max(1),
+        }
+    }
+
+    /// The index of the shard of the key, from 0 to `shards - 1`
+    pub fn shard(&self, key: &str) -> u32 {
+        (fnv1a(key.as_bytes()) % self.shards as u64) as u32
+    }
+}
+
+impl WorkerSelector for HashSharding {
+    fn select(&self, key: &str) -> String {
+        format!("{}-{}", self.prefix, self.shard(key))
+    }
+}
+
+/// The 64 bit FNV-1a hash, which unlike the hasher of the standard library is stable
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::selector::{HashSharding, WorkerSelector};
+    use crate::Uri;
+    use std::collections::HashSet;
+
+    #[test]
+    fn keys_are_sharded_stably() {
+        let sharding = HashSharding::new("counter", 4);
+        assert_eq!(sharding.select("alice"), sharding.select("alice"));
+        assert_eq!(sharding.select("alice"), "counter-3");
+        assert_eq!(
+            sharding.select_uri("4b1c", "alice"),
+            Uri::worker("4b1c", "counter-3")
+        );
+
+        let shards = (0..100)
+            .map(|idx| sharding.shard(&format!("user-{idx}")))
+            .collect::<HashSet<_>>();
+        assert_eq!(shards, HashSet::from([0, 1, 2, 3]));
+
+        assert_eq!(HashSharding::new("single", 0).select("alice"), "single-0");
+    }
+
+    #[test]
+    fn functions_select_workers() {
+        let selector = |key: &str| format!("tenant-{}", key.split('/').next().unwrap());
+        assert_eq!(selector.select("acme/alice"), "tenant-acme");
+    }
+}
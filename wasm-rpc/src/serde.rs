@@ -1,4 +1,4 @@
-use crate::WitValue;
+use crate::{RpcError, WitValue};
 use serde::{Deserialize, Deserializer, Serialize};
 
 impl<'de> Deserialize<'de> for WitValue {
@@ -23,3 +23,44 @@ impl Serialize for WitValue {
         binary.serialize(serializer)
     }
 }
+
+/// The serialized form of RpcError, which is generated by the bindings
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind", content = "message")]
+enum SerializedRpcError {
+    ProtocolError(String),
+    Denied(String),
+    NotFound(String),
+    RemoteInternalError(String),
+}
+
+impl<'de> Deserialize<'de> for RpcError {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match SerializedRpcError::deserialize(deserializer)? {
+            SerializedRpcError::ProtocolError(msg) => RpcError::ProtocolError(msg),
+            SerializedRpcError::Denied(msg) => RpcError::Denied(msg),
+            SerializedRpcError::NotFound(msg) => RpcError::NotFound(msg),
+            SerializedRpcError::RemoteInternalError(msg) => RpcError::RemoteInternalError(msg),
+        })
+    }
+}
+
+impl Serialize for RpcError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let serialized = match self {
+            RpcError::ProtocolError(msg) => SerializedRpcError::ProtocolError(msg.clone()),
+            RpcError::Denied(msg) => SerializedRpcError::Denied(msg.clone()),
+            RpcError::NotFound(msg) => SerializedRpcError::NotFound(msg.clone()),
+            RpcError::RemoteInternalError(msg) => {
+                SerializedRpcError::RemoteInternalError(msg.clone())
+            }
+        };
+        serialized.serialize(serializer)
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::pretty::{pretty_print, print_type};
+use crate::Value;
+use golem_wasm_ast::analysis::AnalysedType;
+use std::path::{Path, PathBuf};
+
+/// The environment variable which, set to `1`, makes the snapshot assertions accept the new snapshots
+pub const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+/// Asserts that a value of a type, printed with [pretty_print](crate::pretty::pretty_print), matches the snapshot
+/// stored in the `snapshots` directory of the crate, named after the module and the test function, or the given
+/// name when a test has several snapshots:
+///
+/// ```ignore
+/// assert_value_snapshot!(value, typ);
+/// assert_value_snapshot!("after-update", value, typ);
+/// ```
+///
+/// When the snapshot is missing or different, the new one is written next to it with the `.snap.new` extension and
+/// the assertion fails. Running the tests with `UPDATE_SNAPSHOTS=1` accepts the new snapshots instead.
+#[macro_export]
+macro_rules! assert_value_snapshot {
+    ($value:expr, $typ:expr $(,)?) => {{
+        fn f() {}
+        fn type_name_of<T>(_: T) -> &'static str {
+            ::std::any::type_name::<T>()
+        }
+        $crate::assert_value_snapshot!(
+            $crate::snapshot::function_name(type_name_of(f)),
+            $value,
+            $typ
+        )
+    }};
+    ($name:expr, $value:expr, $typ:expr $(,)?) => {
+        $crate::snapshot::assert_snapshot(
+            env!("CARGO_MANIFEST_DIR"),
+            module_path!(),
+            $name,
+            &$value,
+            &$typ,
+        )
+    };
+}
+
+/// The name of the function containing the item of the given type name, like the test function calling the macro
+#[doc(hidden)]
+pub fn function_name(type_name: &str) -> &str {
+    type_name
+        .rsplit("::")
+        .find(|segment| *segment != "f" && *segment != "{{closure}}")
+        .unwrap_or(type_name)
+}
+
+/// The implementation of [assert_value_snapshot], panicking if the snapshot does not match
+#[doc(hidden)]
+pub fn assert_snapshot(
+    manifest_dir: &str,
+    module_path: &str,
+    name: &str,
+    value: &Value,
+    typ: &AnalysedType,
+) {
+    let path = snapshot_path(Path::new(manifest_dir), module_path, name);
+    let update = std::env::var(UPDATE_SNAPSHOTS).is_ok_and(|update| update == "1");
+    if let Err(message) = check_snapshot(&path, &render(value, typ), update) {
+        panic!("{message}");
+    }
+}
+
+/// The path of a snapshot, like `snapshots/pretty__tests__records.snap`, without the name of the crate
+fn snapshot_path(manifest_dir: &Path, module_path: &str, name: &str) -> PathBuf {
+    let module = module_path
+        .split("::")
+        .skip(1)
+        .chain([name])
+        .collect::<Vec<_>>()
+        .join("__");
+    manifest_dir
+        .join("snapshots")
+        .join(format!("{module}.snap"))
+}
+
+fn render(value: &Value, typ: &AnalysedType) -> String {
+    format!(
+        "---\ntype: {}\n---\n{}\n",
+        print_type(typ),
+        pretty_print(value, typ)
+    )
+}
+
+/// Compares the snapshot to the stored one, replacing the stored one if `update` is set, or else writing it with
+/// the `.snap.new` extension for review if it is different
+fn check_snapshot(path: &Path, snapshot: &str, update: bool) -> Result<(), String> {
+    let stored = std::fs::read_to_string(path)
+        .ok()
+        .map(|stored| stored.replace("\r\n", "\n"));
+    let new_path = path.with_extension("snap.new");
+    if stored.as_deref() == Some(snapshot) {
+        let _ = std::fs::remove_file(&new_path);
+        return Ok(());
+    }
+
+    let (target, accepted) = if update {
+        (path, true)
+    } else {
+        (new_path.as_path(), false)
+    };
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = target.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(target, snapshot)
+    };
+    write().map_err(|err| format!("Failed to write the snapshot {}: {err}", target.display()))?;
+    if accepted {
+        let _ = std::fs::remove_file(&new_path);
+        return Ok(());
+    }
+
+    match stored {
+        Some(stored) => Err(format!(
+            "The snapshot {} does not match, the new one is written to {}. Run with {UPDATE_SNAPSHOTS}=1 to accept it.\n\nStored:\n{stored}\nNew:\n{snapshot}",
+            path.display(),
+            new_path.display()
+        )),
+        None => Err(format!(
+            "The snapshot {} is missing, the new one is written to {}. Run with {UPDATE_SNAPSHOTS}=1 to accept it.\n\nNew:\n{snapshot}",
+            path.display(),
+            new_path.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::{check_snapshot, function_name, snapshot_path};
+    use crate::Value;
+    use golem_wasm_ast::analysis::AnalysedType;
+    use std::path::Path;
+
+    #[test]
+    fn snapshots_are_compared_and_updated() {
+        let dir = std::env::temp_dir().join(format!("wasm-rpc-snapshots-{}", std::process::id()));
+        let path = dir.join("values.snap");
+        let new_path = dir.join("values.snap.new");
+
+        assert!(check_snapshot(&path, "1\n", false).is_err());
+        assert_eq!(std::fs::read_to_string(&new_path).unwrap(), "1\n");
+        assert!(!path.exists());
+
+        check_snapshot(&path, "1\n", true).unwrap();
+        assert!(!new_path.exists());
+        check_snapshot(&path, "1\n", false).unwrap();
+
+        let err = check_snapshot(&path, "2\n", false).unwrap_err();
+        assert!(err.contains("does not match"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n");
+        assert_eq!(std::fs::read_to_string(&new_path).unwrap(), "2\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_are_named_after_the_tests() {
+        assert_eq!(
+            function_name("golem_wasm_rpc::snapshot::tests::records::f"),
+            "records"
+        );
+        assert_eq!(function_name("app::tests::calls::{{closure}}::f"), "calls");
+        assert_eq!(
+            snapshot_path(
+                Path::new("/app"),
+                "golem_wasm_rpc::snapshot::tests",
+                "records"
+            ),
+            Path::new("/app/snapshots/snapshot__tests__records.snap")
+        );
+    }
+
+    #[test]
+    fn readable_snapshot() {
+        let typ = AnalysedType::Variant(vec![
+            ("pending".to_string(), None),
+            (
+                "done".to_string(),
+                Some(AnalysedType::Record(vec![
+                    ("count".to_string(), AnalysedType::U32),
+                    (
+                        "names".to_string(),
+                        AnalysedType::List(Box::new(AnalysedType::Str)),
+                    ),
+                ])),
+            ),
+        ]);
+        let value = Value::Variant {
+            case_idx: 1,
+            case_value: Some(Box::new(Value::Record(vec![
+                Value::U32(2),
+                Value::List(vec![
+                    Value::String("a".to_string()),
+                    Value::String("b".to_string()),
+                ]),
+            ]))),
+        };
+        assert_value_snapshot!(value, typ);
+        assert_value_snapshot!(
+            "pending",
+            Value::Variant {
+                case_idx: 0,
+                case_value: None
+            },
+            typ
+        );
+    }
+}
@@ -21,7 +21,6 @@ pub struct TypedValue {
 }
 
 impl TypedValue {
-    #[allow(dead_code)]
     pub fn new(value: Value, typ: AnalysedType) -> Self {
         Self { value, typ }
     }
@@ -623,6 +622,25 @@ impl WasmType for AnalysedType {
     }
 }
 
+/// Parses a value of the given type from its WAVE text representation, like `{name: "x", count: 1}`
+pub fn parse_value(
+    typ: &golem_wasm_ast::analysis::AnalysedType,
+    text: &str,
+) -> Result<Value, String> {
+    let typed_value: TypedValue =
+        wasm_wave::from_str(&AnalysedType(typ.clone()), text).map_err(|err| err.to_string())?;
+    Ok(typed_value.value)
+}
+
+/// Prints a value of the given type in the WAVE text format
+pub fn print_value(
+    value: Value,
+    typ: &golem_wasm_ast::analysis::AnalysedType,
+) -> Result<String, String> {
+    wasm_wave::to_string(&TypedValue::new(value, AnalysedType(typ.clone())))
+        .map_err(|err| err.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::text::TypedValue;
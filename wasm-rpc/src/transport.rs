@@ -0,0 +1,916 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cache::{Caching, CachingTransport, RpcCache};
+use crate::circuit::{CircuitBreakers, CircuitBreakingTransport};
+use crate::codec::{CodecTransport, Codecs};
+use crate::convert::{FromValueAndType, IntoValue};
+use crate::durable::Replay;
+#[cfg(feature = "encryption")]
+use crate::encrypt::{EncryptingTransport, KeyProvider, PayloadEncryption};
+use crate::health::{HealthCheckingTransport, HealthChecks};
+use crate::hedge::{Hedging, HedgingTransport};
+use crate::idempotency::IdempotencyKeys;
+use crate::infer::infer_type;
+use crate::limit::{LimitingTransport, Limits};
+use crate::metrics::RpcMetrics;
+use crate::offload::{BlobOffloading, BlobStore, OffloadingTransport};
+use crate::oneway::{OnewayQueue, OnewayQueueConfig};
+use crate::record::{InvocationRecorder, RecordingTransport};
+use crate::{RpcError, Uri, Value, WitValue};
+use async_trait::async_trait;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Delivers the invocations of the `wasm-rpc` resources to the target workers
+#[async_trait]
+pub trait RpcTransport: Send + Sync {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError>;
+
+    /// Invokes a function passing the metadata of the invocation, like the tracing context, auth tokens or tenancy,
+    /// as name and value pairs. By default the metadata is dropped, the transports able to carry it override this.
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        _metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await(uri, function_name, function_params)
+            .await
+    }
+
+    /// Invokes a function without waiting for its results. By default the results are awaited and ignored.
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_and_await(uri, function_name, function_params)
+            .await
+            .map(|_| ())
+    }
+
+    /// Invokes a function without waiting for its results, passing the metadata of the invocation. By default the
+    /// metadata is dropped, the transports able to carry it override this.
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        _metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        self.invoke(uri, function_name, function_params).await
+    }
+
+    /// Checks whether the connection to the target worker is alive. Transports without connections are always
+    /// healthy.
+    async fn ping(&self, _uri: &Uri) -> Result<(), RpcError> {
+        Ok(())
+    }
+
+    /// Replaces the connection to the target worker with a new one
+    async fn reconnect(&self, _uri: &Uri) -> Result<(), RpcError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: RpcTransport + ?Sized> RpcTransport for Arc<T> {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        (**self)
+            .invoke_and_await(uri, function_name, function_params)
+            .await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        (**self)
+            .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        (**self).invoke(uri, function_name, function_params).await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        (**self)
+            .invoke_with_metadata(uri, function_name, function_params, metadata)
+            .await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        (**self).ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        (**self).reconnect(uri).await
+    }
+}
+
+/// The host side implementation of the `wasm-rpc` resource, performing the invocations through a transport and the
+/// layers configured on top of it with [WasmRpcHostBuilder].
+///
+/// Embedders keep the target `Uri` of each `wasm-rpc` resource in the payload of its `WasmRpcEntry`, and forward the
+/// invocations of `HostWasmRpc` to [WasmRpcHost::invoke_and_await] and [WasmRpcHost::invoke_and_await_with_metadata].
+///
+/// Invocations targeting an invalid worker URI fail with a protocol error without reaching the transport.
+#[derive(Clone)]
+pub struct WasmRpcHost {
+    transport: Arc<dyn RpcTransport>,
+    oneway: Option<Arc<OnewayQueue>>,
+    idempotency_keys: Option<Arc<IdempotencyKeys>>,
+    replay: Option<Arc<Replay>>,
+}
+
+impl WasmRpcHost {
+    pub fn builder(transport: impl RpcTransport + 'static) -> WasmRpcHostBuilder {
+        WasmRpcHostBuilder {
+            transport: Arc::new(transport),
+            metrics: None,
+            oneway: None,
+            idempotency_keys: None,
+            replay: None,
+        }
+    }
+
+    pub async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    /// Invokes a function passing the metadata of the invocation through the layers to the transport. With idempotency
+    /// keys configured, a key is added to the metadata unless the caller passed one.
+    pub async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        uri.parse_worker()
+            .map_err(|err| RpcError::ProtocolError(format!("Invalid worker URI: {err}")))?;
+        let mut metadata = Cow::Borrowed(metadata);
+        if let Some(keys) = &self.idempotency_keys {
+            keys.attach(metadata.to_mut());
+        }
+        let perform = |function_params| {
+            self.transport.invoke_and_await_with_metadata(
+                uri,
+                function_name,
+                function_params,
+                &metadata,
+            )
+        };
+        match &self.replay {
+            Some(replay) => {
+                replay
+                    .run(uri, function_name, function_params, &metadata, perform)
+                    .await
+            }
+            None => perform(function_params).await,
+        }
+    }
+
+    /// Invokes a function with typed parameters and results. The parameters are given as a tuple, like `(a, b)`, or
+    /// `(a,)` for a single one, and the results are converted to a tuple the same way. The host does not know the
+    /// signatures of the functions, so the results are converted by their inferred type.
+    pub async fn invoke_typed<P: IntoValue, R: FromValueAndType>(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        params: P,
+    ) -> Result<R, RpcError> {
+        let function_params = match params.into_value() {
+            Value::Tuple(values) => values.into_iter().map(WitValue::from).collect(),
+            value => vec![value.into()],
+        };
+        let result = self
+            .invoke_and_await(uri, function_name, function_params)
+            .await?;
+        let result = Value::try_from_wit_value(result).map_err(|err| {
+            RpcError::ProtocolError(format!("Invalid results of {function_name}: {err}"))
+        })?;
+        let typ = infer_type(&result);
+        R::from_value_and_type(result, &typ).map_err(|err| {
+            RpcError::ProtocolError(format!(
+                "Failed to convert the results of {function_name}: {err}"
+            ))
+        })
+    }
+
+    /// Invokes a function without waiting for its results. With a oneway queue configured, the invocation is queued
+    /// and delivered in the background.
+    pub async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        self.invoke_with_metadata(uri, function_name, function_params, &[])
+            .await
+    }
+
+    /// Invokes a function without waiting for its results, passing the metadata of the invocation through the layers
+    /// to the transport. With idempotency keys configured, a key is added to the metadata unless the caller passed one,
+    /// so the target side can de-duplicate the oneway invocations delivered more than once.
+    pub async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        uri.parse_worker()
+            .map_err(|err| RpcError::ProtocolError(format!("Invalid worker URI: {err}")))?;
+        let mut metadata = Cow::Borrowed(metadata);
+        if let Some(keys) = &self.idempotency_keys {
+            keys.attach(metadata.to_mut());
+        }
+        let metadata = &metadata;
+        let perform = |function_params| async move {
+            match &self.oneway {
+                Some(queue) => {
+                    queue
+                        .push_with_metadata(uri, function_name, function_params, metadata)
+                        .await
+                }
+                None => {
+                    self.transport
+                        .invoke_with_metadata(uri, function_name, function_params, metadata)
+                        .await
+                }
+            }
+        };
+        match &self.replay {
+            Some(replay) => replay
+                .run(
+                    uri,
+                    function_name,
+                    function_params,
+                    metadata,
+                    |function_params| async move {
+                        perform(function_params)
+                            .await
+                            .map(|()| Value::Tuple(Vec::new()).into())
+                    },
+                )
+                .await
+                .map(|_| ()),
+            None => perform(function_params).await,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for WasmRpcHost {
+    async fn invoke_and_await(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<WitValue, RpcError> {
+        WasmRpcHost::invoke_and_await(self, uri, function_name, function_params).await
+    }
+
+    async fn invoke_and_await_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<WitValue, RpcError> {
+        WasmRpcHost::invoke_and_await_with_metadata(
+            self,
+            uri,
+            function_name,
+            function_params,
+            metadata,
+        )
+        .await
+    }
+
+    async fn invoke(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+    ) -> Result<(), RpcError> {
+        WasmRpcHost::invoke(self, uri, function_name, function_params).await
+    }
+
+    async fn invoke_with_metadata(
+        &self,
+        uri: &Uri,
+        function_name: &str,
+        function_params: Vec<WitValue>,
+        metadata: &[(String, String)],
+    ) -> Result<(), RpcError> {
+        WasmRpcHost::invoke_with_metadata(self, uri, function_name, function_params, metadata).await
+    }
+
+    async fn ping(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.transport.ping(uri).await
+    }
+
+    async fn reconnect(&self, uri: &Uri) -> Result<(), RpcError> {
+        self.transport.reconnect(uri).await
+    }
+}
+
+/// Configures the layers of a [WasmRpcHost]. Each layer wraps the ones configured before it, so the first one is the
+/// closest to the transport.
+///
+/// [WasmRpcHostBuilder::codecs] and [WasmRpcHostBuilder::encryption] change the payloads, so they are to be configured
+/// before the other layers, which then see the plain values. When both are used the codecs come first, closest to the
+/// transport: the receiving [crate::RpcRouter] answers the negotiations and decodes the payloads itself, before its
+/// handlers open them. Configured the other way around, the codecs are never negotiated.
+pub struct WasmRpcHostBuilder {
+    transport: Arc<dyn RpcTransport>,
+    metrics: Option<Arc<dyn RpcMetrics>>,
+    oneway: Option<OnewayQueueConfig>,
+    idempotency_keys: Option<IdempotencyKeys>,
+    replay: Option<Replay>,
+}
+
+impl WasmRpcHostBuilder {
+    /// Reports the metrics of the layers configured after this call
+    pub fn metrics(mut self, metrics: impl RpcMetrics + 'static) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Negotiates the codec of the payloads with each target worker, and passes the parameters and the results of the
+    /// invocations encoded with it
+    pub fn codecs(mut self, codecs: Codecs) -> Self {
+        self.transport = Arc::new(CodecTransport::new(self.transport, codecs));
+        self
+    }
+
+    /// Encrypts the parameters and the results of the invocations with the keys of the provider
+    #[cfg(feature = "encryption")]
+    pub fn encryption(mut self, keys: impl KeyProvider + 'static) -> Self {
+        self.transport = Arc::new(EncryptingTransport::new(
+            self.transport,
+            PayloadEncryption::new(keys),
+        ));
+        self
+    }
+
+    /// Sends the values whose encoding is larger than `threshold` bytes through the blob store, passing only references
+    /// to them through the transport
+    pub fn blob_offloading(mut self, store: impl BlobStore + 'static, threshold: usize) -> Self {
+        self.transport = Arc::new(OffloadingTransport::new(
+            self.transport,
+            BlobOffloading::new(store, threshold),
+        ));
+        self
+    }
+
+    /// Records every invocation with its parameters, result and timing
+    pub fn record(mut self, recorder: InvocationRecorder) -> Self {
+        self.transport = Arc::new(RecordingTransport::new(self.transport, recorder));
+        self
+    }
+
+    /// Pings the connections to the recently invoked workers periodically, and reconnects the ones failing. The checks
+    /// run on the current tokio runtime until the host is dropped, so this must be called within a runtime.
+    pub fn health_checks(mut self, health_checks: HealthChecks) -> Self {
+        let transport = Arc::new(HealthCheckingTransport::new(self.transport, health_checks));
+        transport.spawn_checks();
+        self.transport = transport;
+        self
+    }
+
+    /// Rejects the invocations of the workers failing repeatedly, until they are successfully probed again
+    pub fn circuit_breaker(mut self, circuit_breakers: CircuitBreakers) -> Self {
+        let mut transport = CircuitBreakingTransport::new(self.transport, circuit_breakers);
+        if let Some(metrics) = &self.metrics {
+            transport = transport.with_metrics(metrics.clone());
+        }
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Sends a second attempt of the invocations of idempotent functions not completed within the delay
+    pub fn hedging(mut self, hedging: Hedging) -> Self {
+        let mut transport = HedgingTransport::new(self.transport, hedging);
+        if let Some(metrics) = &self.metrics {
+            transport = transport.with_metrics(metrics.clone());
+        }
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Limits the concurrency and the rate of the invocations of each target worker
+    pub fn limits(mut self, limits: Limits) -> Self {
+        let mut transport = LimitingTransport::new(self.transport, limits);
+        if let Some(metrics) = &self.metrics {
+            transport = transport.with_metrics(metrics.clone());
+        }
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Answers the repeated identical invocations of the configured functions from the cache
+    pub fn cache(mut self, cache: impl RpcCache + 'static, caching: Caching) -> Self {
+        let mut transport = CachingTransport::new(self.transport, Arc::new(cache), caching);
+        if let Some(metrics) = &self.metrics {
+            transport = transport.with_metrics(metrics.clone());
+        }
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Queues the oneway invocations, delivering them in the background through all the layers. The queue is started
+    /// by [Self::build] on the current tokio runtime, so it must be called within a runtime.
+    pub fn oneway_queue(mut self, config: OnewayQueueConfig) -> Self {
+        self.oneway = Some(config);
+        self
+    }
+
+    /// Passes an idempotency key with every invocation, awaited or oneway, generated from the id of the caller worker and the
+    /// sequence number of the invocation. The key is generated once per invocation of the host, so the attempts of the
+    /// layers, like hedging, share it, and the target side can de-duplicate them.
+    pub fn idempotency_keys(mut self, keys: IdempotencyKeys) -> Self {
+        self.idempotency_keys = Some(keys);
+        self
+    }
+
+    /// Runs the invocations through the replay hooks of a durable execution engine, which can supply their recorded
+    /// results instead of invoking the targets. Configured outside of all the other layers, so the replayed
+    /// invocations do not reach them.
+    pub fn replay(mut self, replay: Replay) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    pub fn build(self) -> WasmRpcHost {
+        let oneway = self.oneway.map(|config| {
+            Arc::new(OnewayQueue::start(
+                self.transport.clone(),
+                config,
+                self.metrics.clone(),
+            ))
+        });
+        WasmRpcHost {
+            transport: self.transport,
+            oneway,
+            idempotency_keys: self.idempotency_keys.map(Arc::new),
+            replay: self.replay.map(Arc::new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{Caching, InMemoryCache};
+    use crate::circuit::CircuitBreakers;
+    use crate::health::HealthChecks;
+    use crate::hedge::Hedging;
+    use crate::idempotency::{IdempotencyKeys, IDEMPOTENCY_KEY};
+    use crate::limit::Limits;
+    use crate::oneway::OnewayQueueConfig;
+    use crate::record::{InvocationRecorder, RecordedInvocation};
+    use crate::transport::{RpcTransport, WasmRpcHost};
+    use crate::{RpcError, Uri, Value, WitValue, WorkerUri};
+    use async_trait::async_trait;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Returns the sum and the count of the numbers of its list parameter, if its string parameter is not empty
+    struct Summer;
+
+    #[async_trait]
+    impl RpcTransport for Summer {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            let params: Vec<Value> = function_params.into_iter().map(Value::from).collect();
+            let result = match params.as_slice() {
+                [Value::String(name), Value::List(values)] if !name.is_empty() => {
+                    let sum = values
+                        .iter()
+                        .map(|value| match value {
+                            Value::U32(value) => *value as u64,
+                            _ => 0,
+                        })
+                        .sum();
+                    Ok(Some(Box::new(Value::Tuple(vec![
+                        Value::U64(sum),
+                        Value::U32(values.len() as u32),
+                    ]))))
+                }
+                _ => Err(Some(Box::new(Value::String("invalid".to_string())))),
+            };
+            Ok(Value::Tuple(vec![Value::Result(result)]).into())
+        }
+    }
+
+    /// Returns the value of the given metadata entry of the invocation
+    struct MetadataEntry(&'static str);
+
+    #[async_trait]
+    impl RpcTransport for MetadataEntry {
+        async fn invoke_and_await(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+                .await
+        }
+
+        async fn invoke_and_await_with_metadata(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+            metadata: &[(String, String)],
+        ) -> Result<WitValue, RpcError> {
+            let value = metadata
+                .iter()
+                .find(|(name, _)| name == self.0)
+                .map(|(_, value)| Box::new(Value::String(value.clone())));
+            Ok(Value::Option(value).into())
+        }
+    }
+
+    /// Opens the parameters and seals the length of the string parameter, like an encrypting receiver
+    #[cfg(feature = "encryption")]
+    struct Opener(crate::encrypt::PayloadEncryption);
+
+    #[cfg(feature = "encryption")]
+    #[async_trait]
+    impl crate::RpcHandler for Opener {
+        async fn handle(
+            &self,
+            call: &crate::intercept::IncomingCall,
+        ) -> Result<WitValue, RpcError> {
+            let [sealed] = call.function_params.as_slice() else {
+                return Err(RpcError::ProtocolError("Unexpected parameters".to_string()));
+            };
+            let params = self
+                .0
+                .open_params(&call.uri, &call.function_name, sealed.clone())?;
+            let Some(Value::String(text)) = params.into_iter().next().map(Value::from) else {
+                return Err(RpcError::ProtocolError("Unexpected parameters".to_string()));
+            };
+            let length = Value::U32(text.len() as u32).into();
+            self.0.seal_result(&call.uri, &call.function_name, &length)
+        }
+    }
+
+    /// Forwards to a router, keeping the metadata of the invocations it received
+    #[cfg(feature = "encryption")]
+    struct Received {
+        router: crate::RpcRouter,
+        metadata: Mutex<Vec<Vec<(String, String)>>>,
+    }
+
+    #[cfg(feature = "encryption")]
+    #[async_trait]
+    impl RpcTransport for Received {
+        async fn invoke_and_await(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.invoke_and_await_with_metadata(uri, function_name, function_params, &[])
+                .await
+        }
+
+        async fn invoke_and_await_with_metadata(
+            &self,
+            uri: &Uri,
+            function_name: &str,
+            function_params: Vec<WitValue>,
+            metadata: &[(String, String)],
+        ) -> Result<WitValue, RpcError> {
+            self.metadata.lock().unwrap().push(metadata.to_vec());
+            self.router
+                .invoke_and_await_with_metadata(uri, function_name, function_params, metadata)
+                .await
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn encrypted_payloads_are_encoded_with_the_codecs_configured_before() {
+        use crate::codec::{Codecs, CONTENT_CODEC};
+        use crate::encrypt::{PayloadEncryption, PayloadKey};
+
+        let key = PayloadKey::new("key-1", [7; 32]);
+        let router = crate::RpcRouter::new();
+        router.register_component("text", Opener(PayloadEncryption::new(key.clone())));
+        let received = Arc::new(Received {
+            router,
+            metadata: Mutex::new(Vec::new()),
+        });
+        let host = WasmRpcHost::builder(received.clone())
+            .codecs(Codecs::new())
+            .encryption(key)
+            .build();
+
+        let result = host
+            .invoke_and_await(
+                &Uri::worker("text", "worker-1"),
+                "rpc:text/api.{length}",
+                vec![Value::String("secret".to_string()).into()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::U32(6));
+        let metadata = received.metadata.lock().unwrap();
+        assert!(metadata
+            .last()
+            .unwrap()
+            .iter()
+            .any(|(name, _)| name == CONTENT_CODEC));
+    }
+
+    #[tokio::test]
+    async fn metadata_is_passed_through_the_layers() {
+        let host = WasmRpcHost::builder(MetadataEntry("tenant"))
+            .limits(Limits::new())
+            .circuit_breaker(CircuitBreakers::new())
+            .hedging(Hedging::new(Duration::from_secs(1)).idempotent("rpc:tenant/api.{get}"))
+            .build();
+        let uri = Uri::worker("component", "worker-1");
+        let metadata = vec![
+            ("traceparent".to_string(), "00-abc-def-01".to_string()),
+            ("tenant".to_string(), "acme".to_string()),
+        ];
+
+        let result = host
+            .invoke_and_await_with_metadata(&uri, "rpc:tenant/api.{get}", vec![], &metadata)
+            .await
+            .unwrap();
+        assert_eq!(
+            Value::from(result),
+            Value::Option(Some(Box::new(Value::String("acme".to_string()))))
+        );
+
+        let result = host
+            .invoke_and_await(&uri, "rpc:tenant/api.{get}", vec![])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), Value::Option(None));
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_are_passed_with_every_invocation() {
+        let caller = WorkerUri::new("caller", "worker-1");
+        let host = WasmRpcHost::builder(MetadataEntry(IDEMPOTENCY_KEY))
+            .idempotency_keys(IdempotencyKeys::new(&caller))
+            .build();
+        let uri = Uri::worker("component", "worker-1");
+        let key = |value: &str| Value::Option(Some(Box::new(Value::String(value.to_string()))));
+
+        let result = host
+            .invoke_and_await(&uri, "rpc:counter/api.{add}", vec![])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), key("caller/worker-1/0"));
+
+        let metadata = vec![(IDEMPOTENCY_KEY.to_string(), "retried".to_string())];
+        let result = host
+            .invoke_and_await_with_metadata(&uri, "rpc:counter/api.{add}", vec![], &metadata)
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), key("retried"));
+
+        let result = host
+            .invoke_and_await(&uri, "rpc:counter/api.{add}", vec![])
+            .await
+            .unwrap();
+        assert_eq!(Value::from(result), key("caller/worker-1/1"));
+    }
+
+    /// Lists the idempotency keys of the oneway invocations it received
+    #[derive(Default)]
+    struct OnewayKeys(Mutex<Vec<Option<String>>>);
+
+    #[async_trait]
+    impl RpcTransport for OnewayKeys {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            Ok(Value::Tuple(vec![]).into())
+        }
+
+        async fn invoke_with_metadata(
+            &self,
+            _uri: &Uri,
+            _function_name: &str,
+            _function_params: Vec<WitValue>,
+            metadata: &[(String, String)],
+        ) -> Result<(), RpcError> {
+            let key = metadata
+                .iter()
+                .find(|(name, _)| name == IDEMPOTENCY_KEY)
+                .map(|(_, value)| value.clone());
+            self.0.lock().unwrap().push(key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_are_passed_with_queued_oneway_invocations() {
+        let keys = Arc::new(OnewayKeys::default());
+        let caller = WorkerUri::new("caller", "worker-1");
+        let host = WasmRpcHost::builder(keys.clone())
+            .oneway_queue(OnewayQueueConfig::new(10))
+            .idempotency_keys(IdempotencyKeys::new(&caller))
+            .build();
+        let uri = Uri::worker("component", "worker-1");
+
+        host.invoke(&uri, "rpc:counter/api.{add}", vec![])
+            .await
+            .unwrap();
+        let metadata = vec![(IDEMPOTENCY_KEY.to_string(), "retried".to_string())];
+        host.invoke_with_metadata(&uri, "rpc:counter/api.{add}", vec![], &metadata)
+            .await
+            .unwrap();
+        host.invoke(&uri, "rpc:counter/api.{add}", vec![])
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while keys.0.lock().unwrap().len() < 3 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            *keys.0.lock().unwrap(),
+            vec![
+                Some("caller/worker-1/0".to_string()),
+                Some("retried".to_string()),
+                Some("caller/worker-1/1".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn typed_invocations() {
+        let host = WasmRpcHost::builder(Summer).build();
+        let uri = Uri::worker("component", "worker-1");
+
+        let (result,): (Result<(u64, u32), String>,) = host
+            .invoke_typed(&uri, "rpc:math/api.{sum}", ("numbers", vec![1u32, 2, 3]))
+            .await
+            .unwrap();
+        assert_eq!(result, Ok((6, 3)));
+
+        let (result,): (Result<(u64, u32), String>,) = host
+            .invoke_typed(&uri, "rpc:math/api.{sum}", ("", Vec::<u32>::new()))
+            .await
+            .unwrap();
+        assert_eq!(result, Err("invalid".to_string()));
+
+        let error = host
+            .invoke_typed::<_, (String,)>(&uri, "rpc:math/api.{sum}", ("numbers", vec![1u32]))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RpcError::ProtocolError(_)));
+    }
+
+    /// Lists the invocations it received, by whether they were oneway
+    #[derive(Default)]
+    struct Deliveries(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl RpcTransport for Deliveries {
+        async fn invoke_and_await(
+            &self,
+            _uri: &Uri,
+            function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<WitValue, RpcError> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("await {function_name}"));
+            Ok(Value::Tuple(vec![]).into())
+        }
+
+        async fn invoke(
+            &self,
+            _uri: &Uri,
+            function_name: &str,
+            _function_params: Vec<WitValue>,
+        ) -> Result<(), RpcError> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("oneway {function_name}"));
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn oneway_invocations_pass_through_every_layer() {
+        let deliveries = Arc::new(Deliveries::default());
+        let buffer = SharedBuffer::default();
+        let function_name = "rpc:counter/api.{add}";
+        let host = WasmRpcHost::builder(deliveries.clone())
+            .record(InvocationRecorder::new(buffer.clone()))
+            .health_checks(HealthChecks::new(Duration::from_secs(60)))
+            .circuit_breaker(CircuitBreakers::new())
+            .hedging(Hedging::new(Duration::from_millis(1)).idempotent(function_name))
+            .limits(Limits::new().with_max_concurrency(1))
+            .cache(
+                InMemoryCache::new(10),
+                Caching::new().function(function_name, Duration::from_secs(60)),
+            )
+            .build();
+        let uri = Uri::worker("counter", "worker-1");
+
+        for _ in 0..2 {
+            host.invoke(&uri, function_name, vec![Value::U64(1).into()])
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            *deliveries.0.lock().unwrap(),
+            vec![format!("oneway {function_name}"); 2]
+        );
+
+        let recorded = buffer.0.lock().unwrap().clone();
+        let recorded: Vec<RecordedInvocation> = String::from_utf8(recorded)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(recorded.len(), 2);
+        assert!(recorded.iter().all(|invocation| invocation.oneway));
+    }
+}
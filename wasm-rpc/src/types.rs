@@ -0,0 +1,73 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+pub type NodeIndex = i32;
+
+#[derive(Debug, Clone)]
+pub struct Uri {
+    pub value: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum WitNode {
+    RecordValue(Vec<NodeIndex>),
+    VariantValue((u32, Option<NodeIndex>)),
+    EnumValue(u32),
+    FlagsValue(Vec<bool>),
+    TupleValue(Vec<NodeIndex>),
+    ListValue(Vec<NodeIndex>),
+    OptionValue(Option<NodeIndex>),
+    ResultValue(Result<Option<NodeIndex>, Option<NodeIndex>>),
+    PrimU8(u8),
+    PrimU16(u16),
+    PrimU32(u32),
+    PrimU64(u64),
+    PrimS8(i8),
+    PrimS16(i16),
+    PrimS32(i32),
+    PrimS64(i64),
+    PrimFloat32(f32),
+    PrimFloat64(f64),
+    PrimChar(char),
+    PrimBool(bool),
+    PrimString(String),
+    Handle((Uri, u64)),
+    PrimBytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct WitValue {
+    pub nodes: Vec<WitNode>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RpcError {
+    ProtocolError(String),
+    Denied(String),
+    NotFound(String),
+    RemoteInternalError(String),
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RpcError {}
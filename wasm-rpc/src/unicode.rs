@@ -0,0 +1,88 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+/// A char or a string decoded from an untrusted source which is not valid Unicode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidUnicode {
+    /// A code point which is not a Unicode scalar value: negative, a surrogate, or beyond U+10FFFF
+    Char(i64),
+    /// Bytes which are not valid UTF-8 from the given position
+    String { valid_up_to: usize },
+}
+
+impl Display for InvalidUnicode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidUnicode::Char(code_point) => {
+                write!(f, "{code_point} is not a valid Unicode scalar value")
+            }
+            InvalidUnicode::String { valid_up_to } => {
+                write!(f, "String is not valid UTF-8 from byte {valid_up_to}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidUnicode {}
+
+/// Converts a code point of any integer type to a char, failing instead of truncating it
+pub fn decode_char(code_point: impl Into<i64>) -> Result<char, InvalidUnicode> {
+    let code_point = code_point.into();
+    u32::try_from(code_point)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or(InvalidUnicode::Char(code_point))
+}
+
+/// Converts the bytes of a string, failing if they are not valid UTF-8
+pub fn decode_string(bytes: Vec<u8>) -> Result<String, InvalidUnicode> {
+    String::from_utf8(bytes).map_err(|err| InvalidUnicode::String {
+        valid_up_to: err.utf8_error().valid_up_to(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::unicode::{decode_char, decode_string, InvalidUnicode};
+
+    #[test]
+    fn chars_are_unicode_scalar_values() {
+        assert_eq!(decode_char(0x61u32), Ok('a'));
+        assert_eq!(decode_char(0x10FFFFi32), Ok('\u{10FFFF}'));
+        assert_eq!(decode_char(0xD800u32), Err(InvalidUnicode::Char(0xD800)));
+        assert_eq!(
+            decode_char(0x110000u32),
+            Err(InvalidUnicode::Char(0x110000))
+        );
+        assert_eq!(decode_char(-1i32), Err(InvalidUnicode::Char(-1)));
+        assert_eq!(
+            decode_char(u32::MAX),
+            Err(InvalidUnicode::Char(u32::MAX.into()))
+        );
+    }
+
+    #[test]
+    fn strings_are_utf8() {
+        assert_eq!(decode_string(b"abc".to_vec()), Ok("abc".to_string()));
+        assert_eq!(
+            decode_string(vec![b'a', 0xC3, 0x28]),
+            Err(InvalidUnicode::String { valid_up_to: 1 })
+        );
+    }
+}
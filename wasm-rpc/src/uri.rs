@@ -0,0 +1,240 @@
+// Copyright 2024 Golem Cloud
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Uri;
+use alloc::string::{String, ToString};
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+
+const WORKER_SCHEME: &str = "worker://";
+
+/// The parsed form of a worker URI: `worker://<component-id>/<worker-name>[/<function>]`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WorkerUri {
+    pub component_id: String,
+    pub worker_name: String,
+    /// The function of the worker the URI points to, like `rpc:counter/api.{add}`
+    pub function: Option<String>,
+}
+
+impl WorkerUri {
+    pub fn new(component_id: impl Into<String>, worker_name: impl Into<String>) -> Self {
+        Self {
+            component_id: component_id.into(),
+            worker_name: worker_name.into(),
+            function: None,
+        }
+    }
+
+    pub fn with_function(mut self, function: impl Into<String>) -> Self {
+        self.function = Some(function.into());
+        self
+    }
+
+    /// The URI of the worker itself, without the function
+    pub fn worker(&self) -> WorkerUri {
+        WorkerUri::new(&self.component_id, &self.worker_name)
+    }
+
+    fn validate(&self) -> Result<(), UriError> {
+        if self.component_id.is_empty() {
+            return Err(UriError::MissingComponentId);
+        }
+        if self.worker_name.is_empty() {
+            return Err(UriError::MissingWorkerName);
+        }
+        let invalid_char = |c: char| c.is_whitespace() || c.is_control();
+        if let Some(c) = self.component_id.chars().find(|c| invalid_char(*c)) {
+            return Err(UriError::InvalidCharacter(c));
+        }
+        if let Some(c) = self.worker_name.chars().find(|c| invalid_char(*c)) {
+            return Err(UriError::InvalidCharacter(c));
+        }
+        if self.function.as_ref().is_some_and(|f| f.is_empty()) {
+            return Err(UriError::MissingFunction);
+        }
+        Ok(())
+    }
+}
+
+impl Display for WorkerUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{WORKER_SCHEME}{}/{}",
+            self.component_id, self.worker_name
+        )?;
+        if let Some(function) = &self.function {
+            write!(f, "/{function}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for WorkerUri {
+    type Err = UriError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = s
+            .strip_prefix(WORKER_SCHEME)
+            .ok_or_else(|| UriError::InvalidScheme(s.to_string()))?;
+        // Worker names cannot contain slashes, but the names of the functions can
+        let mut segments = path.splitn(3, '/');
+        let uri = WorkerUri {
+            component_id: segments.next().unwrap_or_default().to_string(),
+            worker_name: segments.next().unwrap_or_default().to_string(),
+            function: segments.next().map(|function| function.to_string()),
+        };
+        uri.validate()?;
+        Ok(uri)
+    }
+}
+
+/// Describes why a string is not a valid worker URI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UriError {
+    InvalidScheme(String),
+    MissingComponentId,
+    MissingWorkerName,
+    MissingFunction,
+    InvalidCharacter(char),
+}
+
+impl Display for UriError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            UriError::InvalidScheme(uri) => write!(
+                f,
+                "{uri} is not a worker URI, expected {WORKER_SCHEME}<component-id>/<worker-name>"
+            ),
+            UriError::MissingComponentId => write!(f, "the component id is missing"),
+            UriError::MissingWorkerName => write!(f, "the worker name is missing"),
+            UriError::MissingFunction => write!(f, "the function name is empty"),
+            UriError::InvalidCharacter(c) => write!(f, "invalid character {c:?}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UriError {}
+
+impl Uri {
+    /// The URI of a worker, like `worker://<component-id>/<worker-name>`
+    pub fn worker(component_id: impl Into<String>, worker_name: impl Into<String>) -> Uri {
+        WorkerUri::new(component_id, worker_name).into()
+    }
+
+    pub fn parse_worker(&self) -> Result<WorkerUri, UriError> {
+        self.value.parse()
+    }
+}
+
+impl From<WorkerUri> for Uri {
+    fn from(uri: WorkerUri) -> Self {
+        Uri {
+            value: uri.to_string(),
+        }
+    }
+}
+
+impl TryFrom<&Uri> for WorkerUri {
+    type Error = UriError;
+
+    fn try_from(uri: &Uri) -> Result<Self, Self::Error> {
+        uri.parse_worker()
+    }
+}
+
+impl Display for Uri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl FromStr for Uri {
+    type Err = UriError;
+
+    /// Parses a worker URI, keeping it in its original form
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<WorkerUri>()?;
+        Ok(Uri {
+            value: s.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::uri::{UriError, WorkerUri};
+    use crate::Uri;
+
+    #[test]
+    fn worker_uris_are_parsed_and_printed() {
+        let uri: WorkerUri = "worker://4b1c/counter-1".parse().unwrap();
+        assert_eq!(uri, WorkerUri::new("4b1c", "counter-1"));
+        assert_eq!(uri.to_string(), "worker://4b1c/counter-1");
+
+        let uri: WorkerUri = "worker://4b1c/counter-1/rpc:counter/api.{add}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            uri,
+            WorkerUri::new("4b1c", "counter-1").with_function("rpc:counter/api.{add}")
+        );
+        assert_eq!(
+            uri.to_string(),
+            "worker://4b1c/counter-1/rpc:counter/api.{add}"
+        );
+        assert_eq!(uri.worker().to_string(), "worker://4b1c/counter-1");
+
+        assert_eq!(
+            Uri::worker("4b1c", "counter-1"),
+            Uri {
+                value: "worker://4b1c/counter-1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_worker_uris_are_rejected() {
+        let error = |uri: &str| uri.parse::<WorkerUri>().unwrap_err();
+        assert_eq!(
+            error("http://4b1c/counter-1"),
+            UriError::InvalidScheme("http://4b1c/counter-1".to_string())
+        );
+        assert_eq!(error("worker:///counter-1"), UriError::MissingComponentId);
+        assert_eq!(error("worker://4b1c"), UriError::MissingWorkerName);
+        assert_eq!(error("worker://4b1c/"), UriError::MissingWorkerName);
+        assert_eq!(error("worker://4b1c/counter-1/"), UriError::MissingFunction);
+        assert_eq!(
+            error("worker://4b1c/counter 1"),
+            UriError::InvalidCharacter(' ')
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn uri_errors_are_std_errors_with_the_std_feature() {
+        fn parse(uri: &str) -> Result<WorkerUri, Box<dyn std::error::Error>> {
+            Ok(uri.parse::<WorkerUri>()?)
+        }
+
+        let error = parse("worker://4b1c").unwrap_err();
+        assert_eq!(error.to_string(), "the worker name is missing");
+        assert_eq!(
+            error.downcast_ref::<UriError>(),
+            Some(&UriError::MissingWorkerName)
+        );
+    }
+}
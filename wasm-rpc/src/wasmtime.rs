@@ -13,8 +13,11 @@
 // limitations under the License.
 
 use crate::{Uri, Value};
+use wasmparser::types::{ComponentAnyTypeId, ComponentEntityType, Types};
+use wasmparser::{Parser, Payload, Validator, WasmFeatures};
 use wasmtime::component::{
-    types, Enum, Flags, List, OptionVal, Record, ResourceAny, ResultVal, Tuple, Type, Val, Variant,
+    types, Component, Enum, Flags, LinkerInstance, List, OptionVal, Record, ResourceAny,
+    ResourceType, ResultVal, Tuple, Type, Val, Variant,
 };
 
 pub enum EncodingError {
@@ -479,3 +482,150 @@ pub struct WasmFlags {
     count: u32,
     value: Box<[u32]>,
 }
+
+/// Defines every import of a component in the linker, so it can be instantiated without the hosts of its
+/// imports. The imported functions trap when called and the imported resources are defined as stateless
+/// host resources. Definitions added afterwards with shadowing allowed replace the trapping instances.
+///
+/// The component bytes are needed because wasmtime does not expose the imports of a component.
+pub fn define_imports_as_traps<T>(
+    linker: &mut wasmtime::component::Linker<T>,
+    component: &Component,
+    component_bytes: &[u8],
+) -> wasmtime::Result<()> {
+    let types = Validator::new_with_features(WasmFeatures {
+        component_model: true,
+        ..WasmFeatures::default()
+    })
+    .validate_all(component_bytes)?;
+
+    let mut root = linker.root();
+    for name in import_names(component_bytes)? {
+        if let Some(ty) = types.component_entity_type_of_import(&name) {
+            define_trap(&mut root, component, &types, &name, &name, ty)?;
+        }
+    }
+    Ok(())
+}
+
+fn import_names(component_bytes: &[u8]) -> wasmtime::Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(component_bytes) {
+        match payload? {
+            Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => depth += 1,
+            Payload::End(_) => depth -= 1,
+            Payload::ComponentImportSection(imports) if depth == 0 => {
+                for import in imports {
+                    names.push(import?.name.0.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+fn define_trap<T>(
+    linker: &mut LinkerInstance<T>,
+    component: &Component,
+    types: &Types,
+    path: &str,
+    name: &str,
+    ty: ComponentEntityType,
+) -> wasmtime::Result<()> {
+    match ty {
+        ComponentEntityType::Func(_) => {
+            let path = path.to_string();
+            linker.func_new(component, name, move |_, _, _| {
+                Err(wasmtime::Error::msg(format!("{path} is not available")))
+            })
+        }
+        ComponentEntityType::Instance(id) => {
+            let mut instance = linker.instance(name)?;
+            for (export, ty) in &types[id].exports {
+                let path = format!("{path}.{{{export}}}");
+                define_trap(&mut instance, component, types, &path, export, *ty)?;
+            }
+            Ok(())
+        }
+        ComponentEntityType::Type {
+            referenced: ComponentAnyTypeId::Resource(_),
+            ..
+        } => linker
+            .resource(name, ResourceType::host::<()>(), |_, _| Ok(()))
+            .map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wasmtime::define_imports_as_traps;
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::{Config, Engine, Store};
+
+    const COMPONENT: &str = r#"
+        (component
+          (import "test:host/api" (instance $api
+            (export "res" (type (sub resource)))
+            (export "ping" (func))
+          ))
+          (core func $ping (canon lower (func $api "ping")))
+          (core module $m
+            (import "host" "ping" (func $ping))
+            (func (export "run") call $ping))
+          (core instance $i (instantiate $m (with "host" (instance (export "ping" (func $ping))))))
+          (func (export "run") (canon lift (core func $i "run")))
+        )
+    "#;
+
+    fn load() -> (Engine, Component, Vec<u8>) {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        let engine = Engine::new(&config).unwrap();
+        let bytes = wat::parse_str(COMPONENT).unwrap();
+        let component = Component::new(&engine, &bytes).unwrap();
+        (engine, component, bytes)
+    }
+
+    #[test]
+    fn imported_functions_trap_when_called() {
+        let (engine, component, bytes) = load();
+        let mut linker = Linker::new(&engine);
+        define_imports_as_traps(&mut linker, &component, &bytes).unwrap();
+
+        let mut store = Store::new(&engine, ());
+        let instance = linker.instantiate(&mut store, &component).unwrap();
+        let run = instance
+            .get_typed_func::<(), ()>(&mut store, "run")
+            .unwrap();
+        let error = run.call(&mut store, ()).unwrap_err();
+
+        assert!(format!("{error:?}").contains("test:host/api.{ping} is not available"));
+    }
+
+    #[test]
+    fn traps_can_be_shadowed_by_real_definitions() {
+        let (engine, component, bytes) = load();
+        let mut linker = Linker::new(&engine);
+        define_imports_as_traps(&mut linker, &component, &bytes).unwrap();
+        linker.allow_shadowing(true);
+        let mut api = linker.instance("test:host/api").unwrap();
+        api.resource(
+            "res",
+            wasmtime::component::ResourceType::host::<()>(),
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        api.func_wrap("ping", |_, ()| Ok(())).unwrap();
+
+        let mut store = Store::new(&engine, ());
+        let instance = linker.instantiate(&mut store, &component).unwrap();
+        let run = instance
+            .get_typed_func::<(), ()>(&mut store, "run")
+            .unwrap();
+
+        assert!(run.call(&mut store, ()).is_ok());
+    }
+}